@@ -0,0 +1,66 @@
+//! A full backtest of one symbol over one year of 1m candles. The candles are a seeded
+//! random walk on the fixture's price step, written to a temporary directory, so the
+//! benchmark needs no downloaded data. Run with `cargo bench --bench backtest`.
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use passivbot_rs::backtest;
+use passivbot_rs::config;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+const START_MS: u64 = 1_672_531_200_000; // 2023-01-01
+const N_CANDLES: u64 = 365 * 24 * 60;
+
+fn write_candles(path: &Path) {
+    let mut rng = StdRng::seed_from_u64(42);
+    let mut csv = String::from("timestamp,open,high,low,close,volume\n");
+    let mut close: f64 = 100.0;
+    for i in 0..N_CANDLES {
+        let open = close;
+        close = (open * (1.0 + rng.gen_range(-0.002..0.002))).max(1.0);
+        let high = open.max(close) * (1.0 + rng.gen_range(0.0..0.001));
+        let low = open.min(close) * (1.0 - rng.gen_range(0.0..0.001));
+        let _ = writeln!(
+            csv,
+            "{},{:.2},{:.2},{:.2},{:.2},{:.3}",
+            START_MS + i * 60_000,
+            open,
+            high,
+            low,
+            close,
+            rng.gen_range(10.0..100.0)
+        );
+    }
+    fs::write(path, csv).unwrap();
+}
+
+fn bench_one_year_backtest(c: &mut Criterion) {
+    let data_dir = std::env::temp_dir().join(format!("passivbot_bench_{}", std::process::id()));
+    fs::create_dir_all(&data_dir).unwrap();
+    write_candles(&data_dir.join("BTCUSDT_1m.csv"));
+
+    let mut config = config::load_config("tests/fixtures/backtest_config.hjson").unwrap();
+    config.backtest.base_dir = data_dir.to_string_lossy().into_owned();
+    config.backtest.start_date = "2023-01-01".to_string();
+    config.backtest.end_date = "2024-01-01".to_string();
+    config.backtest.symbols = HashMap::from([("bybit".to_string(), vec!["BTCUSDT".to_string()])]);
+
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let mut group = c.benchmark_group("backtest");
+    group.sample_size(10);
+    group.measurement_time(Duration::from_secs(60));
+    group.bench_function("1 symbol, 1 year of 1m candles", |b| {
+        b.iter(|| runtime.block_on(backtest::run_single(&config)).unwrap())
+    });
+    group.finish();
+
+    fs::remove_dir_all(&data_dir).unwrap();
+}
+
+criterion_group!(benches, bench_one_year_backtest);
+criterion_main!(benches);
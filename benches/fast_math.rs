@@ -0,0 +1,43 @@
+//! Arithmetic kernels of the backtest hot path, see `grid::fast_math`. Compare runs with
+//! `cargo bench --bench fast_math` and `cargo bench --bench fast_math --features fast-math`;
+//! only the step rounding differs between them.
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use passivbot_rs::backtest::CandleIndicators;
+use passivbot_rs::config;
+use passivbot_rs::grid::utils::{round_, round_dn, round_up};
+
+fn bench_step_rounding(c: &mut Criterion) {
+    let values: Vec<f64> = (0..10_000).map(|i| 100.0 + i as f64 * 0.0137).collect();
+    c.bench_function("round to price and qty steps", |b| {
+        b.iter(|| {
+            let mut sum = 0.0;
+            for &value in &values {
+                sum += round_dn(black_box(value), 0.01)
+                    + round_up(black_box(value) * 0.001, 0.001)
+                    + round_(black_box(value), 0.5);
+            }
+            sum
+        })
+    });
+}
+
+fn bench_candle_indicators(c: &mut Criterion) {
+    let config = config::load_config("tests/fixtures/backtest_config.hjson").unwrap();
+    let bot_params = config.bot.long;
+    let closes: Vec<f64> = (0..100_000)
+        .map(|i| 100.0 + (i as f64 * 0.01).sin() * 5.0)
+        .collect();
+    c.bench_function("update EMA bands and ATR over 100k candles", |b| {
+        b.iter(|| {
+            let mut indicators = CandleIndicators::default();
+            for &close in &closes {
+                indicators.update(close * 1.001, close * 0.999, close, &bot_params);
+            }
+            black_box(indicators.ema_bands())
+        })
+    });
+}
+
+criterion_group!(benches, bench_step_rounding, bench_candle_indicators);
+criterion_main!(benches);
@@ -0,0 +1,233 @@
+//! Order grid functions evaluated for every symbol on every backtest step. Run with
+//! `cargo bench --bench grid`; criterion reports the change against the previous run.
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use passivbot_rs::config;
+use passivbot_rs::grid::closes::{calc_closes_long, calc_closes_short};
+use passivbot_rs::grid::entries::{self, calc_entries_long, calc_entries_short};
+use passivbot_rs::grid::utils::{
+    find_close_qty_long_bringing_wallet_exposure_to_target,
+    find_close_qty_short_bringing_wallet_exposure_to_target,
+    find_entry_qty_bringing_wallet_exposure_to_target,
+};
+use passivbot_rs::types::{
+    BotSideConfig, EMABands, ExchangeParams, OrderBook, Position, StateParams, TrailingPriceBundle,
+};
+
+fn exchange_params() -> ExchangeParams {
+    ExchangeParams {
+        qty_step: 0.001,
+        price_step: 0.01,
+        min_qty: 0.001,
+        min_cost: 5.0,
+        c_mult: 1.0,
+        inverse: false,
+    }
+}
+
+fn state_params() -> StateParams {
+    StateParams {
+        balance: 1000.0,
+        order_book: OrderBook {
+            bids: vec![[99.99, 1.0]],
+            asks: vec![[100.01, 1.0]],
+        },
+        ema_bands: EMABands {
+            upper: 101.0,
+            lower: 99.0,
+        },
+        atr: 0.5,
+        pnl_cumsum_max: 0.0,
+        pnl_cumsum_last: 0.0,
+    }
+}
+
+/// The fixture's bot params with trailing disabled, so the grid paths are measured.
+fn grid_params(bot_params: &BotSideConfig) -> BotSideConfig {
+    BotSideConfig {
+        entry_trailing_threshold_pct: 0.0,
+        entry_trailing_retracement_pct: 0.0,
+        close_trailing_threshold_pct: 0.0,
+        close_trailing_retracement_pct: 0.0,
+        ..bot_params.clone()
+    }
+}
+
+fn bench_entries(c: &mut Criterion) {
+    let config = config::load_config("tests/fixtures/backtest_config.hjson").unwrap();
+    let (exchange_params, state_params) = (exchange_params(), state_params());
+    let trailing = TrailingPriceBundle::default();
+    let long = grid_params(&config.bot.long);
+    let short = grid_params(&config.bot.short);
+    // a position a few re-entries deep, so the whole grid is computed
+    let long_position = Position {
+        size: 1.5,
+        price: 102.0,
+    };
+    let short_position = Position {
+        size: -1.5,
+        price: 98.0,
+    };
+
+    c.bench_function("calc_entries_long", |b| {
+        b.iter(|| {
+            calc_entries_long(
+                black_box(&exchange_params),
+                black_box(&state_params),
+                &long,
+                black_box(&long_position),
+                &trailing,
+            )
+        })
+    });
+    c.bench_function("calc_entries_short", |b| {
+        b.iter(|| {
+            calc_entries_short(
+                black_box(&exchange_params),
+                black_box(&state_params),
+                &short,
+                black_box(&short_position),
+                &trailing,
+            )
+        })
+    });
+    c.bench_function("calc_entries_long initial entry", |b| {
+        b.iter(|| {
+            calc_entries_long(
+                black_box(&exchange_params),
+                black_box(&state_params),
+                &long,
+                black_box(&Position::default()),
+                &trailing,
+            )
+        })
+    });
+}
+
+fn bench_closes(c: &mut Criterion) {
+    let config = config::load_config("tests/fixtures/backtest_config.hjson").unwrap();
+    let (exchange_params, state_params) = (exchange_params(), state_params());
+    let trailing = TrailingPriceBundle::default();
+    let long = grid_params(&config.bot.long);
+    let short = grid_params(&config.bot.short);
+    let long_position = Position {
+        size: 5.0,
+        price: 99.5,
+    };
+    let short_position = Position {
+        size: -5.0,
+        price: 100.5,
+    };
+
+    c.bench_function("calc_closes_long", |b| {
+        b.iter(|| {
+            calc_closes_long(
+                black_box(&exchange_params),
+                black_box(&state_params),
+                &long,
+                black_box(&long_position),
+                &trailing,
+            )
+        })
+    });
+    c.bench_function("calc_closes_short", |b| {
+        b.iter(|| {
+            calc_closes_short(
+                black_box(&exchange_params),
+                black_box(&state_params),
+                &short,
+                black_box(&short_position),
+                &trailing,
+            )
+        })
+    });
+}
+
+fn bench_wallet_exposure_solvers(c: &mut Criterion) {
+    let config = config::load_config("tests/fixtures/backtest_config.hjson").unwrap();
+    let (exchange_params, state_params) = (exchange_params(), state_params());
+    let position = Position {
+        size: 2.0,
+        price: 100.0,
+    };
+
+    c.bench_function("entry qty to wallet exposure target", |b| {
+        b.iter(|| {
+            find_entry_qty_bringing_wallet_exposure_to_target(
+                black_box(1000.0),
+                black_box(2.0),
+                100.0,
+                0.5,
+                black_box(98.0),
+                false,
+                &exchange_params,
+            )
+        })
+    });
+    c.bench_function("entry qty to wallet exposure limit", |b| {
+        b.iter(|| {
+            entries::find_entry_qty_bringing_wallet_exposure_to_target(
+                &exchange_params,
+                &config.bot.long,
+                black_box(&state_params),
+                black_box(&position),
+                black_box(98.0),
+            )
+        })
+    });
+    c.bench_function("long close qty to wallet exposure target", |b| {
+        b.iter(|| {
+            find_close_qty_long_bringing_wallet_exposure_to_target(
+                black_box(1000.0),
+                black_box(5.0),
+                100.0,
+                0.2,
+                black_box(101.0),
+                false,
+                &exchange_params,
+            )
+        })
+    });
+    c.bench_function("short close qty to wallet exposure target", |b| {
+        b.iter(|| {
+            find_close_qty_short_bringing_wallet_exposure_to_target(
+                black_box(1000.0),
+                black_box(-5.0),
+                100.0,
+                0.2,
+                black_box(99.0),
+                false,
+                &exchange_params,
+            )
+        })
+    });
+    // a whole position of a few qty steps, where interpolation used to oscillate
+    let coarse = ExchangeParams {
+        qty_step: 1.0,
+        ..exchange_params.clone()
+    };
+    c.bench_function(
+        "long close qty to wallet exposure target, coarse qty step",
+        |b| {
+            b.iter(|| {
+                find_close_qty_long_bringing_wallet_exposure_to_target(
+                    black_box(1000.0),
+                    black_box(5.0),
+                    100.0,
+                    0.2,
+                    black_box(101.0),
+                    false,
+                    &coarse,
+                )
+            })
+        },
+    );
+}
+
+criterion_group!(
+    benches,
+    bench_entries,
+    bench_closes,
+    bench_wallet_exposure_solvers
+);
+criterion_main!(benches);
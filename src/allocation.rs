@@ -0,0 +1,108 @@
+use crate::exchange::SendSyncError;
+use crate::paths::Paths;
+use crate::types::{AllocationGroup, LiveConfig};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use tracing::warn;
+
+/// Total position value one user's bot reports to its allocation group, stored as
+/// `<state_dir>/groups/<group>/<user>.json` so bots of other users on the same host can
+/// read it.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+pub struct UserExposure {
+    pub user: String,
+    /// Combined position value in quote currency.
+    pub exposure: f64,
+    pub updated_at_ms: i64,
+}
+
+/// Returns the allocation group the running user belongs to, if any.
+pub fn find_group(live_config: &LiveConfig) -> Option<&AllocationGroup> {
+    live_config
+        .allocation_groups
+        .iter()
+        .find(|group| group.users.contains(&live_config.user))
+}
+
+/// Writes the user's current exposure for the rest of the group to see.
+pub fn publish_exposure(
+    paths: &Paths, group: &AllocationGroup, exposure: &UserExposure,
+) -> Result<(), SendSyncError> {
+    let Some(dir) = paths.group_dir(&group.name) else {
+        return Ok(());
+    };
+    fs::create_dir_all(&dir)?;
+    // write then rename, so readers never see a partial file
+    let tmp_path = dir.join(format!(".{}.json.tmp", exposure.user));
+    fs::write(&tmp_path, serde_json::to_string(exposure)?)?;
+    fs::rename(&tmp_path, dir.join(format!("{}.json", exposure.user)))?;
+    Ok(())
+}
+
+/// Sums the exposures reported by the group's users. Reports older than
+/// `max_report_age_seconds` are ignored, as their bot is presumed stopped.
+pub fn load_group_exposure(paths: &Paths, group: &AllocationGroup, now_ms: i64) -> f64 {
+    let Some(dir) = paths.group_dir(&group.name) else {
+        return 0.0;
+    };
+    let Ok(entries) = fs::read_dir(&dir) else {
+        return 0.0;
+    };
+    let max_age_ms = (group.max_report_age_seconds * 1000.0) as i64;
+    entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.path().extension().is_some_and(|ext| ext == "json"))
+        .filter_map(|entry| {
+            let path = entry.path();
+            let content = fs::read_to_string(&path).ok()?;
+            match serde_json::from_str::<UserExposure>(&content) {
+                Ok(exposure) => Some(exposure),
+                Err(e) => {
+                    warn!(
+                        "Ignoring unreadable exposure file {}: {}",
+                        path.display(),
+                        e
+                    );
+                    None
+                }
+            }
+        })
+        .filter(|exposure| group.users.contains(&exposure.user))
+        .filter(|exposure| now_ms - exposure.updated_at_ms <= max_age_ms)
+        .map(|exposure| exposure.exposure)
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_group_exposure() {
+        let state_dir =
+            std::env::temp_dir().join(format!("allocation_test_{}", std::process::id()));
+        let paths = Paths::new(state_dir.to_str().unwrap(), "a");
+        let group = AllocationGroup {
+            name: "main".to_string(),
+            users: vec!["a".to_string(), "b".to_string(), "c".to_string()],
+            max_exposure: 1000.0,
+            max_report_age_seconds: 60.0,
+        };
+        let report = |user: &str, exposure: f64, updated_at_ms: i64| UserExposure {
+            user: user.to_string(),
+            exposure,
+            updated_at_ms,
+        };
+        publish_exposure(&paths, &group, &report("a", 300.0, 100_000)).unwrap();
+        publish_exposure(&paths, &group, &report("b", 200.0, 90_000)).unwrap();
+        // stale report from a stopped bot
+        publish_exposure(&paths, &group, &report("c", 400.0, 10_000)).unwrap();
+        assert_eq!(load_group_exposure(&paths, &group, 100_000), 500.0);
+
+        publish_exposure(&paths, &group, &report("a", 350.0, 100_000)).unwrap();
+        assert_eq!(load_group_exposure(&paths, &group, 100_000), 550.0);
+        assert_eq!(load_group_exposure(&Paths::default(), &group, 100_000), 0.0);
+
+        fs::remove_dir_all(&state_dir).unwrap();
+    }
+}
@@ -1,6 +1,15 @@
-use crate::types::Analysis;
+use crate::types::{Analysis, PeriodAnalysis, PeriodMetrics};
+use chrono::{DateTime, Datelike};
 use statrs::statistics::Statistics;
 
+const MS_PER_DAY: f64 = 24.0 * 60.0 * 60.0 * 1000.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Period {
+    Month,
+    Quarter,
+}
+
 pub fn calculate_metrics(equity_curve: &[f64]) -> Analysis {
     let mut analysis = Analysis::default();
     if equity_curve.len() < 2 {
@@ -18,8 +27,117 @@ pub fn calculate_metrics(equity_curve: &[f64]) -> Analysis {
     analysis
 }
 
+/// Buckets an equity curve with timestamps (ms) by calendar month and quarter.
+pub fn calculate_period_analysis(timestamps: &[i64], equity_curve: &[f64]) -> PeriodAnalysis {
+    let monthly = calculate_period_metrics(timestamps, equity_curve, Period::Month);
+    let quarterly = calculate_period_metrics(timestamps, equity_curve, Period::Quarter);
+    PeriodAnalysis {
+        win_months_ratio: calculate_win_ratio(&monthly),
+        win_quarters_ratio: calculate_win_ratio(&quarterly),
+        monthly,
+        quarterly,
+    }
+}
+
+fn period_key(timestamp: i64, period: Period) -> String {
+    let Some(date) = DateTime::from_timestamp_millis(timestamp) else {
+        return String::new();
+    };
+    match period {
+        Period::Month => date.format("%Y-%m").to_string(),
+        Period::Quarter => format!("{}-Q{}", date.year(), (date.month() - 1) / 3 + 1),
+    }
+}
+
+/// Calculates metrics for each run of consecutive points falling into the same period.
+///
+/// Each period starts from the last point of the one before, so period gains chain up to
+/// the total gain. Backtests simulate symbols one after another, so the same calendar
+/// period can appear once per symbol; a new run starts wherever time goes backwards.
+pub fn calculate_period_metrics(
+    timestamps: &[i64], equity_curve: &[f64], period: Period,
+) -> Vec<PeriodMetrics> {
+    let n = timestamps.len().min(equity_curve.len());
+    let mut periods = Vec::new();
+    let mut i = 0;
+    while i < n {
+        let key = period_key(timestamps[i], period);
+        let mut j = i;
+        while j + 1 < n
+            && timestamps[j + 1] >= timestamps[j]
+            && period_key(timestamps[j + 1], period) == key
+        {
+            j += 1;
+        }
+        let start = if i > 0 && timestamps[i - 1] <= timestamps[i] {
+            i - 1
+        } else {
+            i
+        };
+        let equity = &equity_curve[start..=j];
+        let (start_equity, end_equity) = (equity[0], equity[equity.len() - 1]);
+        let n_days = (timestamps[j] - timestamps[start]) as f64 / MS_PER_DAY;
+        let valid = start_equity > 0.0 && end_equity > 0.0;
+        periods.push(PeriodMetrics {
+            period: key,
+            start_equity,
+            end_equity,
+            gain: if valid {
+                end_equity / start_equity - 1.0
+            } else {
+                0.0
+            },
+            adg: if valid && n_days > 0.0 {
+                (end_equity / start_equity).powf(1.0 / n_days) - 1.0
+            } else {
+                0.0
+            },
+            drawdown_worst: calculate_max_drawdown(equity),
+        });
+        i = j + 1;
+    }
+    periods
+}
+
+fn calculate_win_ratio(periods: &[PeriodMetrics]) -> f64 {
+    if periods.is_empty() {
+        return 0.0;
+    }
+    periods.iter().filter(|p| p.gain > 0.0).count() as f64 / periods.len() as f64
+}
+
+/// Formats the monthly and quarterly tables with their win ratios.
+pub fn format_period_analysis(periods: &PeriodAnalysis) -> String {
+    format!(
+        "Monthly performance (win months ratio {:.2}):\n{}\nQuarterly performance (win quarters ratio {:.2}):\n{}",
+        periods.win_months_ratio,
+        format_period_table(&periods.monthly),
+        periods.win_quarters_ratio,
+        format_period_table(&periods.quarterly)
+    )
+}
+
+/// Formats period metrics as a plain text table with percentages.
+pub fn format_period_table(periods: &[PeriodMetrics]) -> String {
+    let mut table = format!(
+        "{:<10} {:>12} {:>9} {:>9} {:>9}\n",
+        "period", "end equity", "gain %", "adg %", "max dd %"
+    );
+    for p in periods {
+        table.push_str(&format!(
+            "{:<10} {:>12.2} {:>9.2} {:>9.3} {:>9.2}\n",
+            p.period,
+            p.end_equity,
+            p.gain * 100.0,
+            p.adg * 100.0,
+            p.drawdown_worst * 100.0
+        ));
+    }
+    table
+}
+
 /// Calculates the periodic returns from an equity curve.
-fn calculate_returns(equity_curve: &[f64]) -> Vec<f64> {
+pub fn calculate_returns(equity_curve: &[f64]) -> Vec<f64> {
     equity_curve
         .windows(2)
         .map(|w| (w[1] - w[0]) / w[0])
@@ -28,7 +146,7 @@ fn calculate_returns(equity_curve: &[f64]) -> Vec<f64> {
 
 /// Calculates the Sharpe ratio from a slice of returns.
 /// Assumes a risk-free rate of 0.
-fn calculate_sharpe_ratio(returns: &[f64]) -> f64 {
+pub fn calculate_sharpe_ratio(returns: &[f64]) -> f64 {
     if returns.is_empty() {
         return 0.0;
     }
@@ -109,3 +227,35 @@ fn calculate_max_drawdown(equity_curve: &[f64]) -> f64 {
     }
     max_drawdown
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_period_metrics() {
+        let day = MS_PER_DAY as i64;
+        // 2023-01-30 .. 2023-02-02, then a second symbol restarting in January
+        let jan_30 = 1_675_036_800_000;
+        let timestamps = [
+            jan_30,
+            jan_30 + day,
+            jan_30 + 2 * day,
+            jan_30 + 3 * day,
+            jan_30,
+        ];
+        let equity = [100.0, 110.0, 99.0, 121.0, 110.0];
+        let monthly = calculate_period_metrics(&timestamps, &equity, Period::Month);
+        let keys: Vec<&str> = monthly.iter().map(|p| p.period.as_str()).collect();
+        assert_eq!(keys, vec!["2023-01", "2023-02", "2023-01"]);
+        assert!((monthly[0].gain - 0.1).abs() < 1e-12);
+        assert!((monthly[1].gain - 0.1).abs() < 1e-12);
+        assert!((monthly[1].drawdown_worst - 0.1).abs() < 1e-12);
+        assert!((monthly[1].adg - 1.1f64.sqrt() + 1.0).abs() < 1e-12);
+        assert_eq!(monthly[2].gain, 0.0);
+
+        let analysis = calculate_period_analysis(&timestamps, &equity);
+        assert_eq!(analysis.quarterly[0].period, "2023-Q1");
+        assert!((analysis.win_months_ratio - 2.0 / 3.0).abs() < 1e-12);
+    }
+}
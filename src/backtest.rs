@@ -1,18 +1,163 @@
 use crate::analysis;
+use crate::chart::{ChartExport, SymbolChart};
 use crate::types::{
-    Analysis, BotConfig, Market, Ticker, StateParams, GridOrder, TrailingPriceBundle, Order,
-    OrderBook, EMABands,
+    Analysis, PeriodAnalysis, BotConfig, BotSideConfig, Market, Ticker, StateParams, GridOrder,
+    TrailingPriceBundle, Order, EMABands, ExchangeParams, OrderJitterConfig, SlippageModel,
 };
-use crate::grid::{entries, closes, utils};
+use crate::grid::{entries, closes, fast_math, jitter, utils};
+use crate::exchange::market_cache::MarketInfo;
+use crate::exchange::simulated::SimulatedExchange;
 use crate::exchange::{Exchange, SendSyncError};
 use crate::data;
-use std::collections::HashMap;
+use crate::execution_stats::{self, ExecutionTracker, OrderTypeStats};
+use crate::results::RunDir;
+use crate::risk_governor::{self, RiskGovernor};
+use serde::Serialize;
+use crate::schedule;
+use crate::universe;
+use crate::state::SymbolState;
+use std::collections::{BTreeMap, HashMap};
 use chrono::{DateTime, Utc};
+use clap::Parser;
 use tracing::{info, warn};
 
+/// Command line overrides scoping a backtest or optimization run.
+#[derive(Parser, Debug, Clone)]
+pub struct BacktestArgs {
+    /// Comma separated symbols to evaluate, e.g. BTCUSDT,ETHUSDT
+    #[clap(long, value_delimiter = ',')]
+    pub symbols: Vec<String>,
+
+    /// Start date, e.g. 2023-01-01
+    #[clap(long)]
+    pub start: Option<String>,
+
+    /// End date, e.g. 2024-01-01
+    #[clap(long)]
+    pub end: Option<String>,
+
+    /// Candle resolution to step through, e.g. 1s; defaults to backtest.resolution
+    #[clap(long)]
+    pub resolution: Option<String>,
+
+    /// Write chart.json to the run directory; same as backtest.export_chart
+    #[clap(long)]
+    pub chart: bool,
+}
+
+impl BacktestArgs {
+    pub fn apply(&self, config: &mut BotConfig) {
+        if !self.symbols.is_empty() {
+            let exchange = config
+                .backtest
+                .symbols
+                .keys()
+                .min()
+                .cloned()
+                .unwrap_or_else(|| config.live.exchange.clone());
+            config.backtest.symbols = HashMap::from([(exchange, self.symbols.clone())]);
+        }
+        if let Some(start) = &self.start {
+            config.backtest.start_date = start.clone();
+        }
+        if let Some(end) = &self.end {
+            config.backtest.end_date = end.clone();
+        }
+        if let Some(resolution) = &self.resolution {
+            config.backtest.resolution = resolution.clone();
+        }
+        if self.chart {
+            config.backtest.export_chart = true;
+        }
+    }
+}
+
+#[derive(Serialize)]
 pub struct BacktestResult {
     pub final_balance: f64,
     pub analysis: Analysis,
+    pub periods: PeriodAnalysis,
+    /// First and last candle timestamp (ms) actually used per symbol.
+    pub data_ranges: HashMap<String, (u64, u64)>,
+    /// Funding settled on all positions, negative when more was received than paid; 0
+    /// unless `backtest.include_funding` is set.
+    pub funding_paid: f64,
+    /// Fill rate, time to fill and adverse move of the orders placed, by order type.
+    pub execution: BTreeMap<String, OrderTypeStats>,
+}
+
+/// EMA bands, ATR and trailing prices derived from the candles seen so far.
+#[derive(Debug, Clone, Default)]
+pub struct CandleIndicators {
+    n_closes: usize,
+    ema0: f64,
+    ema1: f64,
+    atr: f64,
+    prev_close: f64,
+    /// Spans of ema0, ema1 and the ATR, with their smoothing factors computed once.
+    spans: [f64; 3],
+    alphas: [f64; 3],
+    pub trailing_price_bundle: TrailingPriceBundle,
+}
+
+impl CandleIndicators {
+    pub fn update(&mut self, high: f64, low: f64, close_price: f64, bot_params: &BotSideConfig) {
+        let spans = [
+            bot_params.ema_span_0,
+            bot_params.ema_span_1,
+            bot_params.entry_trailing_atr_span,
+        ];
+        if self.n_closes == 0 || spans != self.spans {
+            self.spans = spans;
+            self.alphas = spans.map(fast_math::ema_alpha);
+        }
+        if self.n_closes == 0 {
+            self.ema0 = close_price;
+            self.ema1 = close_price;
+            self.atr = high - low;
+        } else {
+            self.ema0 = fast_math::ema_step(self.ema0, close_price, self.alphas[0]);
+            self.ema1 = fast_math::ema_step(self.ema1, close_price, self.alphas[1]);
+            let true_range = (high - low)
+                .max((high - self.prev_close).abs())
+                .max((low - self.prev_close).abs());
+            self.atr = fast_math::ema_step(self.atr, true_range, self.alphas[2]);
+        }
+        self.prev_close = close_price;
+        self.n_closes += 1;
+
+        let bundle = &mut self.trailing_price_bundle;
+        bundle.min_since_open = f64::min(bundle.min_since_open, close_price);
+        bundle.max_since_open = f64::max(bundle.max_since_open, close_price);
+        if bundle.min_since_open < bundle.max_since_open {
+            bundle.max_since_min = f64::max(bundle.max_since_min, close_price);
+        }
+        if bundle.max_since_open > bundle.min_since_open {
+            bundle.min_since_max = f64::min(bundle.min_since_max, close_price);
+        }
+    }
+
+    pub fn atr(&self) -> f64 {
+        self.atr
+    }
+
+    pub fn ema_bands(&self) -> EMABands {
+        EMABands {
+            upper: f64::max(self.ema0, self.ema1),
+            lower: f64::min(self.ema0, self.ema1),
+        }
+    }
+}
+
+/// Bot params with the indicator spans, given in minutes, converted to steps of `step_ms`,
+/// so indicators cover the same time at any backtest resolution.
+pub fn scale_indicator_spans(bot_params: &BotSideConfig, step_ms: u64) -> BotSideConfig {
+    let steps_per_minute = 60_000.0 / step_ms as f64;
+    let mut scaled = bot_params.clone();
+    scaled.ema_span_0 *= steps_per_minute;
+    scaled.ema_span_1 *= steps_per_minute;
+    scaled.entry_trailing_atr_span *= steps_per_minute;
+    scaled
 }
 
 pub async fn run_single(config: &BotConfig) -> Result<BacktestResult, SendSyncError> {
@@ -21,247 +166,742 @@ pub async fn run_single(config: &BotConfig) -> Result<BacktestResult, SendSyncEr
     Ok(result)
 }
 
+/// One backtested symbol: its own simulated book and position, trading on the wallet
+/// shared by all symbols, see `Backtester::balance`.
+pub struct SymbolAccount {
+    pub exchange_name: String,
+    pub symbol: String,
+    pub simulated: SimulatedExchange,
+    /// Indicators over the long and the short side's spans.
+    long_indicators: CandleIndicators,
+    short_indicators: CandleIndicators,
+    symbol_state: SymbolState,
+    chart: Option<SymbolChart>,
+    execution: ExecutionTracker,
+    /// Fills already passed to `execution`.
+    n_fills_tracked: usize,
+    last_close: f64,
+    /// Funding settlements as (time in ms, rate), and the index of the next one due.
+    funding_rates: Vec<(u64, f64)>,
+    next_funding: usize,
+}
+
+/// What every symbol trades on at one step.
+struct StepContext<'a> {
+    timestamp: u64,
+    balance: f64,
+    /// Realized PnL of the shared wallet at its peak and now, for the unstuck allowance.
+    pnl_cumsum_max: f64,
+    pnl_cumsum_last: f64,
+    long: &'a BotSideConfig,
+    short: &'a BotSideConfig,
+    entries_paused: bool,
+    order_jitter: &'a OrderJitterConfig,
+}
+
+impl SymbolAccount {
+    fn new(
+        exchange_name: String, symbol: String, config: &BotConfig, exchange_params: ExchangeParams,
+        record_chart: bool,
+    ) -> Self {
+        let simulated = SimulatedExchange::new(config.backtest.starting_balance);
+        simulated.set_maker_fee(config.backtest.maker_fee);
+        simulated.set_taker_fee(config.backtest.taker_fee);
+        simulated.set_taker_slippage(config.backtest.taker_slippage);
+        // validated by `run`
+        simulated.set_slippage_model(
+            SlippageModel::from_config(&config.backtest.slippage).unwrap_or(SlippageModel::None),
+            config.backtest.slippage.max_bps / 10_000.0,
+        );
+        simulated.set_market_unstuck_closes(config.backtest.market_unstuck_closes);
+        simulated.set_exchange_params(exchange_params);
+        SymbolAccount {
+            chart: record_chart.then(|| SymbolChart::new(&symbol)),
+            exchange_name,
+            symbol,
+            simulated,
+            long_indicators: CandleIndicators::default(),
+            short_indicators: CandleIndicators::default(),
+            symbol_state: SymbolState::default(),
+            execution: ExecutionTracker::default(),
+            n_fills_tracked: 0,
+            last_close: 0.0,
+            funding_rates: Vec::new(),
+            next_funding: 0,
+        }
+    }
+
+    /// Settles the funding due by the candle's time on the position held into it, then
+    /// fills the resting orders crossed by the candle and updates the indicators.
+    fn update(
+        &mut self, timestamp: u64, high: f64, low: f64, close_price: f64, volume: f64,
+        indicator_params: (&BotSideConfig, &BotSideConfig),
+    ) {
+        self.simulated.set_time_ms(timestamp as i64);
+        while let Some(&(funding_ms, rate)) = self.funding_rates.get(self.next_funding) {
+            if funding_ms > timestamp {
+                break;
+            }
+            self.simulated.settle_funding(&self.symbol, rate);
+            self.next_funding += 1;
+        }
+        self.simulated.update_candle(high, low, close_price, volume);
+        let fills = self.simulated.fills_since(self.n_fills_tracked);
+        self.n_fills_tracked += fills.len();
+        self.execution.record_fills(&fills);
+        if let Some(chart) = &mut self.chart {
+            chart.push_candle(timestamp, high, low, close_price);
+        }
+        self.long_indicators
+            .update(high, low, close_price, indicator_params.0);
+        self.short_indicators
+            .update(high, low, close_price, indicator_params.1);
+        self.last_close = close_price;
+    }
+
+    async fn unrealized_pnl(&self) -> Result<f64, SendSyncError> {
+        let position = self.simulated.fetch_positions(&self.symbol).await?.net();
+        let exchange_params = self.simulated.fetch_exchange_params(&self.symbol).await?;
+        Ok(risk_governor::calc_unrealized_pnl(
+            &position,
+            self.last_close,
+            &exchange_params,
+        ))
+    }
+
+    /// Computes the symbol's grid on the shared balance and places it.
+    async fn trade(&mut self, step: &StepContext<'_>) -> Result<(), SendSyncError> {
+        let symbol = self.symbol.clone();
+        let order_book = self.simulated.fetch_order_book(&symbol).await?;
+        let positions = self.simulated.fetch_positions(&symbol).await?;
+        let position_costs = self.simulated.fetch_position_costs(&symbol).await?;
+        self.symbol_state
+            .update_position(positions.net().size, step.timestamp as i64);
+        let position_age_days = self.symbol_state.position_age_days(step.timestamp as i64);
+        let exchange_params = self.simulated.fetch_exchange_params(&symbol).await?;
+
+        // each side on its own EMA bands and ATR
+        let long_state = StateParams {
+            balance: step.balance,
+            order_book: order_book.clone(),
+            ema_bands: self.long_indicators.ema_bands(),
+            atr: self.long_indicators.atr(),
+            pnl_cumsum_max: step.pnl_cumsum_max,
+            pnl_cumsum_last: step.pnl_cumsum_last,
+        };
+        let short_state = StateParams {
+            balance: step.balance,
+            order_book,
+            ema_bands: self.short_indicators.ema_bands(),
+            atr: self.short_indicators.atr(),
+            pnl_cumsum_max: step.pnl_cumsum_max,
+            pnl_cumsum_last: step.pnl_cumsum_last,
+        };
+        let trailing_price_bundle = &self.long_indicators.trailing_price_bundle;
+        let (long_cfg, short_cfg) = (step.long, step.short);
+
+        let mut entry_orders_long = Vec::new();
+        let mut close_orders_long = Vec::new();
+        if long_cfg.enabled {
+            let position = positions.long;
+            if !step.entries_paused {
+                entry_orders_long = entries::calc_entries_long(
+                    &exchange_params,
+                    &long_state,
+                    long_cfg,
+                    &position,
+                    trailing_price_bundle,
+                );
+            }
+            close_orders_long = closes::calc_closes_long(
+                &exchange_params,
+                &long_state,
+                long_cfg,
+                &position,
+                trailing_price_bundle,
+            );
+            if long_cfg.close_floor_at_break_even && position.size > 0.0 {
+                closes::floor_nearest_close_at_break_even(
+                    &mut close_orders_long,
+                    utils::calc_break_even_price_long(
+                        position.price,
+                        position.size,
+                        position_costs.total(),
+                        exchange_params.inverse,
+                        exchange_params.c_mult,
+                    ),
+                    exchange_params.price_step,
+                );
+            }
+            close_orders_long.extend(closes::calc_aged_unstuck_close_long(
+                &exchange_params,
+                &long_state,
+                long_cfg,
+                &position,
+                position_age_days,
+                &close_orders_long,
+            ));
+        }
+
+        let mut entry_orders_short = Vec::new();
+        let mut close_orders_short = Vec::new();
+        if short_cfg.enabled {
+            let position = positions.short;
+            if !step.entries_paused {
+                entry_orders_short = entries::calc_entries_short(
+                    &exchange_params,
+                    &short_state,
+                    short_cfg,
+                    &position,
+                    trailing_price_bundle,
+                );
+            }
+            close_orders_short = closes::calc_closes_short(
+                &exchange_params,
+                &short_state,
+                short_cfg,
+                &position,
+                trailing_price_bundle,
+            );
+            if short_cfg.close_floor_at_break_even && position.size < 0.0 {
+                closes::floor_nearest_close_at_break_even(
+                    &mut close_orders_short,
+                    utils::calc_break_even_price_short(
+                        position.price,
+                        position.size,
+                        position_costs.total(),
+                        exchange_params.inverse,
+                        exchange_params.c_mult,
+                    ),
+                    exchange_params.price_step,
+                );
+            }
+            close_orders_short.extend(closes::calc_aged_unstuck_close_short(
+                &exchange_params,
+                &short_state,
+                short_cfg,
+                &position,
+                position_age_days,
+                &close_orders_short,
+            ));
+        }
+
+        for orders in [
+            &mut entry_orders_long,
+            &mut entry_orders_short,
+            &mut close_orders_long,
+            &mut close_orders_short,
+        ] {
+            if step.order_jitter.in_backtests {
+                jitter::apply_order_jitter(
+                    orders,
+                    &long_state.order_book,
+                    &exchange_params,
+                    step.order_jitter,
+                    step.order_jitter.seed,
+                );
+            }
+        }
+
+        // closes first, matching the live order queue's priority
+        self.place_grid_orders(close_orders_long).await?;
+        self.place_grid_orders(close_orders_short).await?;
+        self.place_grid_orders(entry_orders_long).await?;
+        self.place_grid_orders(entry_orders_short).await?;
+        self.execution.end_step();
+        Ok(())
+    }
+
+    async fn place_grid_orders(
+        &mut self, grid_orders: Vec<GridOrder>,
+    ) -> Result<(), SendSyncError> {
+        for grid_order in grid_orders {
+            let order = Order {
+                id: "".to_string(), // Will be set by the exchange
+                symbol: self.symbol.clone(),
+                side: if grid_order.qty > 0.0 {
+                    "Buy".to_string()
+                } else {
+                    "Sell".to_string()
+                },
+                position_side: if grid_order.qty > 0.0 {
+                    "Long".to_string()
+                } else {
+                    "Short".to_string()
+                },
+                qty: grid_order.qty.abs(),
+                price: grid_order.price,
+                reduce_only: grid_order.order_type.is_close(),
+                custom_id: grid_order.order_type.to_string(),
+                client_order_id: String::new(),
+                time_in_force: "GTC".to_string(),
+            };
+            self.execution.record_placement(
+                grid_order.order_type,
+                grid_order.qty > 0.0,
+                grid_order.price,
+                self.simulated.now_ms(),
+                self.last_close,
+            );
+            self.simulated.place_order(&order).await?;
+        }
+        Ok(())
+    }
+}
+
 pub struct Backtester {
     pub config: BotConfig,
-    pub exchange: Box<dyn Exchange>,
+    /// The backtested symbols, in the order they are stepped through at every step.
+    /// Filled by `run`.
+    pub accounts: Vec<SymbolAccount>,
     pub markets: HashMap<String, Market>,
     pub tickers: HashMap<String, Ticker>,
     pub now: DateTime<Utc>,
+    /// Candles and fills recorded for `chart.json`; None skips recording.
+    pub chart: Option<ChartExport>,
+    /// Steps and minimums of (exchange, symbol), from the market cache; symbols without
+    /// an entry use the simulated exchange's defaults.
+    pub exchange_params: HashMap<(String, String), ExchangeParams>,
 }
 
 impl Backtester {
     pub fn new(config: BotConfig) -> Self {
-        let starting_balance = config.backtest.starting_balance;
         Backtester {
             config,
-            exchange: Box::new(crate::exchange::simulated::SimulatedExchange::new(
-                starting_balance,
-            )),
+            accounts: Vec::new(),
             markets: HashMap::new(),
             tickers: HashMap::new(),
             now: Utc::now(),
+            chart: None,
+            exchange_params: HashMap::new(),
         }
     }
 
-    pub async fn start(&mut self) -> Result<(), SendSyncError> {
+    /// The shared wallet: the starting balance plus the realized PnL and fees of every
+    /// symbol, as on an exchange account trading several coins.
+    pub fn balance(&self) -> f64 {
+        let starting_balance = self.config.backtest.starting_balance;
+        starting_balance
+            + self
+                .accounts
+                .iter()
+                .map(|account| account.simulated.balance() - starting_balance)
+                .sum::<f64>()
+    }
+
+    pub async fn start(&mut self) -> Result<BacktestResult, SendSyncError> {
         info!("Starting backtest...");
+        universe::resolve_backtest_symbols(
+            &mut self.config.backtest,
+            self.config.live.minimum_coin_age_days,
+        )?;
+        let run_dir = RunDir::create("backtest", &self.config)?;
+        self.chart = self.config.backtest.export_chart.then(ChartExport::default);
+        // steps rarely change, so cached params of any age beat the defaults
+        for (exchange_name, symbols) in &self.config.backtest.symbols {
+            let cache = MarketInfo::load(&MarketInfo::path(exchange_name));
+            for symbol in symbols {
+                match cache.exchange_params.get(symbol) {
+                    Some(cached) => {
+                        self.exchange_params.insert(
+                            (exchange_name.clone(), symbol.clone()),
+                            cached.value.clone(),
+                        );
+                    }
+                    None => info!(
+                        "No cached exchange params for {} on {}, using default steps",
+                        symbol, exchange_name
+                    ),
+                }
+            }
+        }
         let result = self.run().await?;
         info!("Backtest finished. Final balance: {}", result.final_balance);
+        if self.config.backtest.include_funding {
+            info!("Funding paid: {}", result.funding_paid);
+        }
+        let fills: Vec<_> = self
+            .accounts
+            .iter()
+            .flat_map(|account| account.simulated.snapshot().fills)
+            .collect();
+        info!(
+            "Fills: {}, of which taker: {}",
+            fills.len(),
+            fills.iter().filter(|f| f.taker).count()
+        );
         info!("Performance Analysis:\n{:#?}", result.analysis);
-        Ok(())
+        info!("{}", analysis::format_period_analysis(&result.periods));
+        info!(
+            "{}",
+            execution_stats::format_execution_stats(&result.execution)
+        );
+        run_dir.write_json("result.json", &result)?;
+        if let Some(chart) = &self.chart {
+            run_dir.write_compact_json("chart.json", chart)?;
+        }
+        Ok(result)
     }
 
-    async fn run(&mut self) -> Result<BacktestResult, SendSyncError> {
+    pub(crate) async fn run(&mut self) -> Result<BacktestResult, SendSyncError> {
         info!("Backtester is running...");
+        self.config.backtest.validate()?;
+        universe::resolve_backtest_symbols(
+            &mut self.config.backtest,
+            self.config.live.minimum_coin_age_days,
+        )?;
         let mut equity_curve = Vec::new();
-        let mut all_hlcvs = HashMap::new();
+        let mut equity_timestamps = Vec::new();
+        let mut risk_governor = RiskGovernor::default();
 
-        for (exchange_name, symbols) in &self.config.backtest.symbols {
-            for symbol in symbols {
-                info!(
-                    "Preparing data for symbol: {} on exchange: {}",
-                    symbol, exchange_name
-                );
-                let hlcvs = match data::prepare_hlcvs(
-                    &self.config,
-                    &self.config.live,
+        let step_ms = self.config.backtest.step_ms();
+        let long_indicator_params = scale_indicator_spans(&self.config.bot.long, step_ms);
+        let short_indicator_params = scale_indicator_spans(&self.config.bot.short, step_ms);
+        if step_ms != 60_000 {
+            info!(
+                "Stepping through {} candles, recording equity once per minute",
+                self.config.backtest.resolution
+            );
+        }
+        if self.config.live.pair_mode.enabled {
+            warn!("live.pair_mode is not simulated: backtests net both sides into one position");
+        }
+        if !matches!(self.config.live.book_price_mode.as_str(), "" | "grid") {
+            warn!(
+                "live.book_price_mode is not simulated: backtests have no spread to price against"
+            );
+        }
+        // sorted, so symbols trade in the same order at every step and in every run
+        let mut symbols: Vec<(String, String)> = self
+            .config
+            .backtest
+            .symbols
+            .iter()
+            .flat_map(|(exchange_name, symbols)| {
+                symbols
+                    .iter()
+                    .map(move |symbol| (exchange_name.clone(), symbol.clone()))
+            })
+            .collect();
+        symbols.sort();
+        let mut matrix = data::HlcvMatrix::load(
+            &self.config,
+            &symbols
+                .iter()
+                .map(|(_, symbol)| symbol.clone())
+                .collect::<Vec<_>>(),
+        )?;
+        let mut data_ranges = HashMap::new();
+        for ((_, symbol), range) in symbols.iter().zip(&matrix.ranges) {
+            match range {
+                Some(range) => data_ranges.insert(symbol.clone(), *range),
+                None => return Err(format!("No HLCV data found for symbol: {}", symbol).into()),
+            };
+        }
+        info!(
+            "Backtesting {} symbols over {} steps: {:?}",
+            symbols.len(),
+            matrix.n_steps(),
+            symbols
+        );
+
+        let default_exchange_params = SimulatedExchange::new(0.0).snapshot().exchange_params;
+        let record_chart = self.chart.is_some();
+        self.accounts = symbols
+            .into_iter()
+            .map(|(exchange_name, symbol)| {
+                let exchange_params = self
+                    .exchange_params
+                    .get(&(exchange_name.clone(), symbol.clone()))
+                    .cloned()
+                    .unwrap_or_else(|| default_exchange_params.clone());
+                SymbolAccount::new(
+                    exchange_name,
                     symbol,
-                    Some(&self.config.backtest.start_date),
-                    Some(&self.config.backtest.end_date),
+                    &self.config,
+                    exchange_params,
+                    record_chart,
                 )
-                .await
-                {
-                    Ok(hlcvs) => hlcvs,
-                    Err(e) => return Err(e),
-                };
-                all_hlcvs.insert(symbol.clone(), hlcvs);
+            })
+            .collect();
+        if self.config.backtest.include_funding {
+            for account in &mut self.accounts {
+                account.funding_rates = data::load_funding_rates(&self.config, &account.symbol)
+                    .map_err(|e| {
+                        format!(
+                            "No funding rates for {} at {} ({}); run the downloader with \
+                            backtest.include_funding set",
+                            account.symbol,
+                            data::funding_path(&self.config, &account.symbol),
+                            e
+                        )
+                    })?;
             }
         }
+        let order_jitter = self.config.live.order_jitter.clone();
+        let entry_schedule = schedule::EntrySchedule::from_config(&self.config.live)?;
 
-        // This is a simplified main loop. A real backtest would need to handle time synchronization
-        // across different symbols' data. For now, we process one symbol fully, then the next.
-        let symbols_to_backtest = self.config.backtest.symbols.clone();
-        for (exchange_name, symbols) in &symbols_to_backtest {
-            for symbol in symbols {
-                info!(
-                    "Backtesting symbol: {} on exchange: {}",
-                    symbol, exchange_name
-                );
-                let hlcvs = match all_hlcvs.get(symbol) {
-                    Some(hlcvs) => hlcvs,
-                    None => {
-                        warn!("No HLCV data found for symbol: {}", symbol);
-                        continue;
-                    }
-                };
-
-                let mut ema0 = 0.0;
-                let mut ema1 = 0.0;
-                let mut trailing_price_bundle = TrailingPriceBundle::default();
-
-                for i in 0..hlcvs.nrows() {
-                    let row = hlcvs.row(i);
-                    let close_price = row[4];
-
-                    let current_balance = match self.exchange.fetch_balance().await {
-                        Ok(balance) => balance,
-                        Err(e) => return Err(e),
-                    };
-                    equity_curve.push(current_balance);
-
-                    if i == 0 {
-                        ema0 = close_price;
-                        ema1 = close_price;
-                    } else {
-                        ema0 = utils::calc_ema(ema0, close_price, self.config.bot.long.ema_span_0);
-                        ema1 = utils::calc_ema(ema1, close_price, self.config.bot.long.ema_span_1);
-                    }
-
-                    let order_book = OrderBook {
-                        bids: vec![[close_price, 0.0]],
-                        asks: vec![[close_price, 0.0]],
-                    };
-
-                    let balance = match self.exchange.fetch_balance().await {
-                        Ok(balance) => balance,
-                        Err(e) => return Err(e),
-                    };
-                    let position = match self.exchange.fetch_position(symbol).await {
-                        Ok(position) => position,
-                        Err(e) => return Err(e),
-                    };
-                    let exchange_params = match self.exchange.fetch_exchange_params(symbol).await {
-                        Ok(params) => params,
-                        Err(e) => return Err(e),
-                    };
-
-                    let state_params = StateParams {
-                        balance,
-                        order_book,
-                        ema_bands: EMABands {
-                            upper: f64::max(ema0, ema1),
-                            lower: f64::min(ema0, ema1),
-                        },
-                    };
-
-                    trailing_price_bundle.min_since_open =
-                        f64::min(trailing_price_bundle.min_since_open, close_price);
-                    trailing_price_bundle.max_since_open =
-                        f64::max(trailing_price_bundle.max_since_open, close_price);
-                    if trailing_price_bundle.min_since_open < trailing_price_bundle.max_since_open {
-                        trailing_price_bundle.max_since_min =
-                            f64::max(trailing_price_bundle.max_since_min, close_price);
-                    }
-                    if trailing_price_bundle.max_since_open > trailing_price_bundle.min_since_open {
-                        trailing_price_bundle.min_since_max =
-                            f64::min(trailing_price_bundle.min_since_max, close_price);
-                    }
-
-                    let (
-                        entry_orders_long,
-                        entry_orders_short,
-                        close_orders_long,
-                        close_orders_short,
-                    ) = {
-                        let long_cfg = self.config.bot.long.clone();
-                        let short_cfg = self.config.bot.short.clone();
-
-                        let entry_orders_long = entries::calc_entries_long(
-                            &exchange_params,
-                            &state_params,
-                            &long_cfg,
-                            &position,
-                            &trailing_price_bundle,
-                        );
-
-                        let entry_orders_short = entries::calc_entries_short(
-                            &exchange_params,
-                            &state_params,
-                            &short_cfg,
-                            &position,
-                            &trailing_price_bundle,
+        let starting_balance = self.config.backtest.starting_balance;
+        let mut balance_peak = starting_balance;
+        let mut active = Vec::with_capacity(self.accounts.len());
+        while let Some(window) = matrix.next_window()? {
+            for i in 0..window.n_steps() {
+                let timestamp = window.timestamp(i);
+                active.clear();
+                for (j, account) in self.accounts.iter_mut().enumerate() {
+                    if let Some((high, low, close_price)) = window.candle(i, j) {
+                        let volume = window.volume(i, j);
+                        account.update(
+                            timestamp,
+                            high,
+                            low,
+                            close_price,
+                            volume,
+                            (&long_indicator_params, &short_indicator_params),
                         );
+                        active.push(j);
+                    }
+                }
+                if active.is_empty() {
+                    continue;
+                }
 
-                        let close_orders_long = closes::calc_closes_long(
-                            &exchange_params,
-                            &state_params,
-                            &long_cfg,
-                            &position,
-                            &trailing_price_bundle,
-                        );
+                let balance = self.balance();
+                balance_peak = balance_peak.max(balance);
+                // one point per minute keeps metrics comparable across resolutions
+                let minute_start = timestamp as i64 - timestamp as i64 % 60_000;
+                if equity_timestamps.last() != Some(&minute_start) {
+                    equity_curve.push(balance);
+                    equity_timestamps.push(minute_start);
+                }
 
-                        let close_orders_short = closes::calc_closes_short(
-                            &exchange_params,
-                            &state_params,
-                            &short_cfg,
-                            &position,
-                            &trailing_price_bundle,
-                        );
-                        (
-                            entry_orders_long,
-                            entry_orders_short,
-                            close_orders_long,
-                            close_orders_short,
-                        )
-                    };
+                let mut unrealized_pnl = 0.0;
+                for account in &self.accounts {
+                    unrealized_pnl += account.unrealized_pnl().await?;
+                }
+                let exposure_scale =
+                    risk_governor.update(balance + unrealized_pnl, &self.config.live.risk_governor);
+                let long_cfg =
+                    risk_governor::scale_exposure_limit(&self.config.bot.long, exposure_scale);
+                let short_cfg =
+                    risk_governor::scale_exposure_limit(&self.config.bot.short, exposure_scale);
+                let step = StepContext {
+                    timestamp,
+                    balance,
+                    pnl_cumsum_max: balance_peak - starting_balance,
+                    pnl_cumsum_last: balance - starting_balance,
+                    long: &long_cfg,
+                    short: &short_cfg,
+                    entries_paused: DateTime::from_timestamp_millis(timestamp as i64)
+                        .is_some_and(|now| entry_schedule.entries_paused(now)),
+                    order_jitter: &order_jitter,
+                };
+                for &j in &active {
+                    self.accounts[j].trade(&step).await?;
+                }
+            }
+        }
 
-                    if let Err(e) = self.place_grid_orders(symbol, entry_orders_long).await {
-                        return Err(e);
-                    }
-                    if let Err(e) = self.place_grid_orders(symbol, entry_orders_short).await {
-                        return Err(e);
-                    }
-                    if let Err(e) = self.place_grid_orders(symbol, close_orders_long).await {
-                        return Err(e);
-                    }
-                    if let Err(e) = self.place_grid_orders(symbol, close_orders_short).await {
-                        return Err(e);
-                    }
+        if let Some(chart) = &mut self.chart {
+            for account in &mut self.accounts {
+                if let Some(mut symbol_chart) = account.chart.take() {
+                    symbol_chart.add_fills(&account.simulated.snapshot().fills, 0);
+                    chart.symbols.push(symbol_chart);
                 }
             }
         }
-        let final_balance = match self.exchange.fetch_balance().await {
-            Ok(balance) => balance,
-            Err(e) => return Err(e),
-        };
+        let final_balance = self.balance();
+        let funding_paid = self
+            .accounts
+            .iter()
+            .flat_map(|account| account.simulated.snapshot().funding_payments)
+            .map(|payment| payment.amount)
+            .sum();
         let analysis = analysis::calculate_metrics(&equity_curve);
+        let periods = analysis::calculate_period_analysis(&equity_timestamps, &equity_curve);
 
         Ok(BacktestResult {
             final_balance,
             analysis,
+            periods,
+            data_ranges,
+            funding_paid,
+            execution: execution_stats::summarize(
+                self.accounts.iter().map(|account| &account.execution),
+            ),
         })
     }
 }
 
-impl Backtester {
-    async fn place_grid_orders(
-        &mut self, symbol: &str, grid_orders: Vec<GridOrder>,
-    ) -> Result<(), SendSyncError> {
-        for grid_order in grid_orders {
-            let order = Order {
-                id: "".to_string(), // Will be set by the exchange
-                symbol: symbol.to_string(),
-                side: if grid_order.qty > 0.0 {
-                    "Buy".to_string()
-                } else {
-                    "Sell".to_string()
-                },
-                position_side: if grid_order.qty > 0.0 {
-                    "Long".to_string()
-                } else {
-                    "Short".to_string()
-                },
-                qty: grid_order.qty.abs(),
-                price: grid_order.price,
-                reduce_only: false, // This will be determined by other logic later
-                custom_id: grid_order.order_type.to_string(),
-                time_in_force: "GTC".to_string(),
-            };
-            match self.exchange.place_order(&order).await {
-                Ok(_) => (),
-                Err(e) => return Err(e),
-            };
-        }
-        Ok(())
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config;
+
+    /// Runs a backtest over the bundled fixture and compares the results bit for bit.
+    /// Any change to the grid math or float handling that alters the outcome must
+    /// update these values deliberately.
+    #[tokio::test]
+    #[cfg_attr(feature = "fast-math", ignore = "expects exact decimal rounding")]
+    async fn test_backtest_golden_results() {
+        let mut config = config::load_config("tests/fixtures/backtest_config.hjson").unwrap();
+        // fees, slippage and funding all move the balance; closing on the grid rather than
+        // trailing lets positions grow stuck, so unstuck closes fill as market orders and
+        // both wallet exposure solvers crop orders
+        config.backtest.maker_fee = 0.0002;
+        config.backtest.taker_fee = 0.00055;
+        config.backtest.taker_slippage = 0.0001;
+        config.backtest.slippage.model = "range".to_string();
+        config.backtest.slippage.range_fraction = 0.1;
+        config.backtest.market_unstuck_closes = true;
+        config.backtest.include_funding = true;
+        config.bot.long.close_trailing_threshold_pct = 0.0;
+        config.bot.long.unstuck_threshold = 0.3;
+        let mut backtester = Backtester::new(config);
+        let result = backtester.run().await.unwrap();
+
+        assert_eq!(result.final_balance.to_bits(), 0x408d7644d4d32f4e);
+        assert_eq!(result.analysis.drawdown_worst.to_bits(), 0x3fb0fc7f2d59aeae);
+        assert_eq!(result.analysis.sharpe_ratio.to_bits(), 0xc002be9ffaff8fe7);
+        assert_eq!(result.analysis.sortino_ratio.to_bits(), 0x3fae557795d1807a);
+        assert_eq!(result.funding_paid.to_bits(), 0x3fe0a4f347937013);
+
+        let fills = backtester.accounts[0].simulated.snapshot().fills;
+        assert_eq!(fills.len(), 78);
+        let fill = |i: usize| (fills[i].custom_id.as_str(), fills[i].qty, fills[i].price);
+        assert_eq!(fill(0), ("entry_initial_normal_long", 0.501, 99.9));
+        assert_eq!(fill(21), ("entry_grid_cropped_long", 4.97, 99.73));
+        // slipped below its price when placed
+        assert_eq!(fill(22), ("unstuck_close_long", 2.912, 97.905203));
+        let takers: Vec<_> = fills.iter().filter(|fill| fill.taker).collect();
+        assert_eq!(takers.len(), 18);
+        assert!(takers
+            .iter()
+            .all(|fill| fill.custom_id == "unstuck_close_long" && fill.price < fill.order_price));
+        let qty: f64 = fills.iter().map(|fill| fill.qty).sum();
+        let notional: f64 = fills.iter().map(|fill| fill.qty * fill.price).sum();
+        let fees: f64 = fills.iter().map(|fill| fill.fee).sum();
+        assert_eq!(qty.to_bits(), 0x4058c50e56041897);
+        assert_eq!(notional.to_bits(), 0x40c2e204efb89f9a);
+        assert_eq!(fees.to_bits(), 0x40068f1896a99b7a);
+    }
+
+    #[test]
+    fn test_indicator_spans_scale_with_resolution() {
+        let config = config::load_config("tests/fixtures/backtest_config.hjson").unwrap();
+        let bot_params = &config.bot.long;
+        let per_second = scale_indicator_spans(bot_params, 1_000);
+        assert_eq!(per_second.ema_span_0, bot_params.ema_span_0 * 60.0);
+        assert_eq!(
+            per_second.entry_trailing_atr_span,
+            bot_params.entry_trailing_atr_span * 60.0
+        );
+        assert_eq!(
+            scale_indicator_spans(bot_params, 60_000).ema_span_1,
+            bot_params.ema_span_1
+        );
+
+        let mut backtest = config.backtest.clone();
+        backtest.resolution = "5s".to_string();
+        assert_eq!(backtest.step_ms(), 5_000);
+        assert!(backtest.validate().is_ok());
+        backtest.resolution = "7s".to_string();
+        assert!(backtest.validate().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_symbols_share_one_wallet() {
+        let data_dir = std::env::temp_dir().join(format!("backtest_wallet_{}", std::process::id()));
+        std::fs::create_dir_all(&data_dir).unwrap();
+        let candles = std::fs::read_to_string("tests/fixtures/BTCUSDT_1m.csv").unwrap();
+        std::fs::write(data_dir.join("BTCUSDT_1m.csv"), &candles).unwrap();
+        // listed halfway through the other symbol's data
+        let lines: Vec<&str> = candles.lines().collect();
+        let late_listing = [&lines[..1], &lines[lines.len() / 2..]].concat().join("\n");
+        std::fs::write(data_dir.join("ETHUSDT_1m.csv"), late_listing).unwrap();
+
+        let mut config = config::load_config("tests/fixtures/backtest_config.hjson").unwrap();
+        config.backtest.base_dir = data_dir.to_string_lossy().into_owned();
+        config.backtest.symbols = HashMap::from([(
+            "bybit".to_string(),
+            vec!["ETHUSDT".to_string(), "BTCUSDT".to_string()],
+        )]);
+        let mut backtester = Backtester::new(config.clone());
+        let result = backtester.run().await.unwrap();
+        std::fs::remove_dir_all(&data_dir).unwrap();
+
+        let symbols: Vec<&str> = backtester
+            .accounts
+            .iter()
+            .map(|account| account.symbol.as_str())
+            .collect();
+        assert_eq!(symbols, ["BTCUSDT", "ETHUSDT"]);
+        let (btc_start, btc_end) = result.data_ranges["BTCUSDT"];
+        let (eth_start, eth_end) = result.data_ranges["ETHUSDT"];
+        assert!(eth_start > btc_start);
+        assert_eq!(eth_end, btc_end);
+        let eth_fills = backtester.accounts[1].simulated.snapshot().fills;
+        assert!(!eth_fills.is_empty());
+        assert!(eth_fills
+            .iter()
+            .all(|fill| fill.timestamp_ms >= eth_start as i64));
+
+        // both symbols size on, and settle into, the one wallet
+        let starting_balance = config.backtest.starting_balance;
+        let pnl: Vec<f64> = backtester
+            .accounts
+            .iter()
+            .map(|account| account.simulated.balance() - starting_balance)
+            .collect();
+        assert!(pnl.iter().all(|pnl| *pnl != 0.0));
+        assert!((result.final_balance - (starting_balance + pnl[0] + pnl[1])).abs() < 1e-9);
+        let mut btc_only =
+            Backtester::new(config::load_config("tests/fixtures/backtest_config.hjson").unwrap());
+        let btc_only = btc_only.run().await.unwrap();
+        assert_ne!(
+            btc_only.final_balance,
+            starting_balance + pnl[0],
+            "BTCUSDT should size on the balance ETHUSDT's fills changed"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_funding_settles_when_included() {
+        let data_dir =
+            std::env::temp_dir().join(format!("backtest_funding_{}", std::process::id()));
+        std::fs::create_dir_all(&data_dir).unwrap();
+        std::fs::copy(
+            "tests/fixtures/BTCUSDT_1m.csv",
+            data_dir.join("BTCUSDT_1m.csv"),
+        )
+        .unwrap();
+        let mut config = config::load_config("tests/fixtures/backtest_config.hjson").unwrap();
+        config.backtest.base_dir = data_dir.to_string_lossy().into_owned();
+        config.backtest.include_funding = true;
+        let missing = Backtester::new(config.clone()).run().await;
+        assert!(missing.is_err_and(|e| e.to_string().contains("No funding rates for BTCUSDT")));
+
+        // hourly settlements over the fixture's four hours
+        let funding: String = (0..5)
+            .map(|hour| format!("{},0.001\n", 1672531200000u64 + hour * 3_600_000))
+            .collect();
+        std::fs::write(
+            data_dir.join("BTCUSDT_funding.csv"),
+            format!("timestamp,funding_rate\n{}", funding),
+        )
+        .unwrap();
+        let mut backtester = Backtester::new(config);
+        let result = backtester.run().await.unwrap();
+        std::fs::remove_dir_all(&data_dir).unwrap();
+        let payments = backtester.accounts[0].simulated.snapshot().funding_payments;
+        assert!(!payments.is_empty());
+        assert!(payments
+            .iter()
+            .all(|payment| payment.timestamp_ms % 3_600_000 == 0));
+        assert!(result.funding_paid != 0.0);
+        assert_eq!(
+            result.funding_paid,
+            payments.iter().map(|payment| payment.amount).sum::<f64>()
+        );
     }
 }
@@ -1,46 +1,203 @@
-use crate::types::BotConfig;
+use crate::types::{default_max_report_age_seconds, AllocationGroup, BotConfig};
+use crate::capital;
+use crate::control::{self, StatusBoard};
+use crate::exchange::intent_log::IntentLog;
+use crate::exchange::quarantine::SymbolQuarantine;
+use crate::exchange::queue::{BatchLimits, OrderQueue};
+use crate::exchange::ws::MarketStream;
 use crate::exchange::{Exchange, SendSyncError};
 use crate::manager::Manager;
+use crate::events::{BotEvent, EventBus, LogNotifier};
 use crate::forager::Forager;
+use crate::heartbeat::Heartbeat;
+use crate::notifications::MessageTemplates;
+use crate::journal::{self, JournalRecorder};
+use crate::paths::Paths;
+use crate::performance::PerformanceTracker;
+use crate::restarts::{self, RestartLog};
+use crate::venue::{self, Venue, VenueAccount};
 use std::collections::HashMap;
-use tracing::info;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tracing::{info, warn};
+use std::time::Duration;
 use tokio::task;
 
 pub struct Passivbot {
     pub config: BotConfig,
     pub exchange: Box<dyn Exchange>,
+    /// Accounts coins may be traded on when `live.venues` lists more than `live.user`.
+    pub venues: Vec<VenueAccount>,
+    /// Streams market data of `exchange` when `live.websocket` is enabled.
+    pub market_stream: Option<MarketStream>,
+    /// Restarts in the last day, when they put the bot in safe mode.
+    safe_mode_restarts: Option<usize>,
 }
 
 impl Passivbot {
     pub fn new(config: BotConfig, exchange: Box<dyn Exchange>) -> Self {
-        Passivbot { config, exchange }
+        Passivbot {
+            config,
+            exchange,
+            venues: Vec::new(),
+            market_stream: None,
+            safe_mode_restarts: None,
+        }
+    }
+
+    pub fn with_venues(mut self, venues: Vec<VenueAccount>) -> Self {
+        self.venues = venues;
+        self
+    }
+
+    pub fn with_market_stream(mut self, market_stream: MarketStream) -> Self {
+        self.market_stream = Some(market_stream);
+        self
     }
 
     pub async fn start(&mut self) -> Result<(), SendSyncError> {
         info!("Starting bot...");
+        let paths = Paths::from_config(&self.config.live);
+        paths.migrate_legacy_layout();
+        for venue in &self.venues {
+            paths.for_user(&venue.user).migrate_legacy_layout();
+        }
+        if let Some(path) = paths.restarts() {
+            let mut restart_log = RestartLog::load(&path);
+            let n_restarts = restart_log.record_start(chrono::Utc::now().timestamp_millis());
+            if let Err(e) = restart_log.save(&path) {
+                warn!("Failed to save restart log {}: {}", path.display(), e);
+            }
+            let max_n_restarts = self.config.live.max_n_restarts_per_day;
+            if restarts::exceeds_limit(n_restarts, max_n_restarts) {
+                warn!(
+                    "{} restarts in the last day, more than live.max_n_restarts_per_day ({}): starting in safe mode, gracefully stopping all symbols",
+                    n_restarts, max_n_restarts
+                );
+                restarts::enter_safe_mode(&mut self.config);
+                self.safe_mode_restarts = Some(n_restarts);
+            }
+        }
         self.run().await?;
         Ok(())
     }
 
+    /// Creates the event bus of the trading loop and subscribes notifications, the fill
+    /// journal, the performance tracker and the status board to it, then announces safe
+    /// mode on it. Fails on invalid notification templates.
+    fn spawn_subscribers(&self, status_board: &StatusBoard) -> Result<EventBus, SendSyncError> {
+        let templates = MessageTemplates::load(&self.config.live.notifications)?;
+        let events = EventBus::default();
+        events.subscribe(LogNotifier::new(templates));
+        events.subscribe(status_board.clone());
+        let journal_path = Paths::from_config(&self.config.live).journal();
+        if let Some(path) = &journal_path {
+            events.subscribe(JournalRecorder::new(path.clone(), status_board.clone()));
+        }
+        events.subscribe(PerformanceTracker::new(
+            self.config.live.performance_alerts.clone(),
+            journal_path,
+            status_board.clone(),
+        ));
+        if let Some(n_restarts) = self.safe_mode_restarts {
+            events.publish(BotEvent::SafeMode { n_restarts });
+        }
+        Ok(events)
+    }
+
     pub async fn run(&mut self) -> Result<(), SendSyncError> {
         info!("Bot is running...");
+        if self.venues.len() > 1 {
+            return self.run_venues().await;
+        }
 
-        let manager = Manager::new("".into(), self.config.clone(), self.exchange.clone_box());
+        let quarantine = SymbolQuarantine::new(
+            self.config.live.quarantine_error_threshold,
+            Duration::from_secs_f64(self.config.live.quarantine_minutes * 60.0),
+        );
+        let intent_log = IntentLog::open(Paths::from_config(&self.config.live).intent_log());
+        let status_board = StatusBoard::default().with_read_only(self.config.live.read_only);
+        let events = self.spawn_subscribers(&status_board)?;
+        let heartbeat = Heartbeat::new(
+            &Paths::from_config(&self.config.live),
+            &self.config.live.user,
+        );
+        let order_queue = OrderQueue::spawn(
+            self.exchange.clone_box(),
+            quarantine,
+            intent_log,
+            events.clone(),
+            BatchLimits::from_config(&self.config.live),
+        );
+        if self.config.live.control_port != 0 {
+            let listener =
+                tokio::net::TcpListener::bind(("127.0.0.1", self.config.live.control_port)).await?;
+            task::spawn(control::serve(
+                listener,
+                self.config.live.user.clone(),
+                status_board.clone(),
+            ));
+        }
+        let interval_minutes = self.config.live.equity_snapshot_interval_minutes;
+        if interval_minutes > 0.0 && Paths::from_config(&self.config.live).journal().is_some() {
+            task::spawn(journal::take_equity_snapshots(
+                self.exchange.clone_box(),
+                status_board.clone(),
+                events.clone(),
+                Duration::from_secs_f64(interval_minutes * 60.0),
+            ));
+        }
+        let manager = Manager::new(
+            "".into(),
+            self.config.clone(),
+            self.exchange.clone_box(),
+            order_queue.clone(),
+            status_board.clone(),
+        );
         let forager = Forager::new(manager.clone()).await;
 
         let mut handles = HashMap::new();
+        // flags of the managers, set while their symbol is wound down, and since when
+        let mut winding_down: HashMap<String, Arc<AtomicBool>> = HashMap::new();
+        let mut winding_since_ms: HashMap<String, i64> = HashMap::new();
+        let mut symbols_to_trade = forager.run().await;
+        capital::preflight(&mut self.config, self.exchange.as_ref(), &symbols_to_trade).await?;
 
         loop {
-            let symbols_to_trade = forager.run().await;
+            if let Some(stream) = &self.market_stream {
+                // symbols being wound down keep their stream until their managers stop
+                let mut streamed = symbols_to_trade.clone();
+                streamed.extend(
+                    handles
+                        .keys()
+                        .filter(|s| !symbols_to_trade.contains(s))
+                        .cloned(),
+                );
+                stream.set_symbols(&streamed);
+            }
 
             // Start managers for new symbols
             for symbol in &symbols_to_trade {
+                if winding_since_ms.remove(symbol).is_some() {
+                    info!("[{}] Back in the symbol list, trading normally", symbol);
+                    winding_down[symbol].store(false, Ordering::Relaxed);
+                }
                 if !handles.contains_key(symbol) {
+                    let flag = Arc::new(AtomicBool::new(false));
+                    winding_down.insert(symbol.clone(), flag.clone());
                     let mut manager = Manager::new(
                         symbol.clone(),
                         self.config.clone(),
                         self.exchange.clone_box(),
-                    );
+                        order_queue.clone(),
+                        status_board.clone(),
+                    )
+                    .with_events(events.clone())
+                    .with_heartbeat(heartbeat.clone())
+                    .with_winding_down(flag);
+                    if let Some(stream) = &self.market_stream {
+                        manager = manager.with_market_feed(stream.feed());
+                    }
                     let handle = task::spawn(async move {
                         manager.run().await;
                     });
@@ -48,7 +205,8 @@ impl Passivbot {
                 }
             }
 
-            // Stop managers for symbols that are no longer in the list
+            // Wind down managers of symbols that are no longer in the list: they stop
+            // opening positions and are stopped once flat without orders
             let symbols_to_stop: Vec<String> = handles
                 .keys()
                 .filter(|s| !symbols_to_trade.contains(s))
@@ -56,9 +214,208 @@ impl Passivbot {
                 .collect();
 
             for symbol in symbols_to_stop {
+                let now_ms = chrono::Utc::now().timestamp_millis();
+                let since_ms = *winding_since_ms.entry(symbol.clone()).or_insert_with(|| {
+                    info!("[{}] Dropped from the symbol list, winding down", symbol);
+                    winding_down[&symbol].store(true, Ordering::Relaxed);
+                    now_ms
+                });
+                let wound_down = status_board.get("", &symbol).is_none_or(|status| {
+                    status.updated_at_ms > since_ms
+                        && status.position_size == 0.0
+                        && status.nearest_entry_price.is_none()
+                        && status.nearest_close_price.is_none()
+                });
+                if !wound_down {
+                    continue;
+                }
+                info!("[{}] Wound down, stopping manager", symbol);
                 if let Some(handle) = handles.remove(&symbol) {
                     handle.abort();
                 }
+                winding_down.remove(&symbol);
+                winding_since_ms.remove(&symbol);
+                status_board.remove("", &symbol);
+                if let Some(heartbeat) = &heartbeat {
+                    heartbeat.remove_symbol(&symbol);
+                }
+            }
+
+            tokio::time::sleep(tokio::time::Duration::from_secs(60)).await;
+            symbols_to_trade = forager.run().await;
+        }
+    }
+
+    /// Like `run`, but every coin is traded on the venue the forager picks for it, with one
+    /// order queue per venue. Positions on all venues share `live.venues.max_exposure`.
+    async fn run_venues(&mut self) -> Result<(), SendSyncError> {
+        let live = &self.config.live;
+        if live.venues.max_exposure > 0.0 {
+            // managers of all venues report to one status board, so a group of the bot's
+            // own user caps their combined exposure
+            self.config.live.allocation_groups.insert(
+                0,
+                AllocationGroup {
+                    name: format!("{}-venues", live.user),
+                    users: vec![live.user.clone()],
+                    max_exposure: live.venues.max_exposure,
+                    max_report_age_seconds: default_max_report_age_seconds(),
+                },
+            );
+        }
+        let status_board = StatusBoard::default().with_read_only(self.config.live.read_only);
+        let events = self.spawn_subscribers(&status_board)?;
+        let live = &self.config.live;
+        let paths = Paths::from_config(live);
+        let heartbeat = Heartbeat::new(&paths, &live.user);
+        let quarantine_duration = Duration::from_secs_f64(live.quarantine_minutes * 60.0);
+        let venues: Vec<Venue> = self
+            .venues
+            .iter()
+            .map(|account| Venue {
+                account: account.clone(),
+                order_queue: OrderQueue::spawn(
+                    account.exchange.clone_box(),
+                    SymbolQuarantine::new(live.quarantine_error_threshold, quarantine_duration),
+                    IntentLog::open(paths.for_user(&account.user).intent_log()),
+                    events.clone(),
+                    BatchLimits::from_config(live),
+                ),
+            })
+            .collect();
+        if live.control_port != 0 {
+            let listener = tokio::net::TcpListener::bind(("127.0.0.1", live.control_port)).await?;
+            task::spawn(control::serve(
+                listener,
+                live.user.clone(),
+                status_board.clone(),
+            ));
+        }
+        let manager = Manager::new(
+            "".into(),
+            self.config.clone(),
+            self.exchange.clone_box(),
+            venues[0].order_queue.clone(),
+            status_board.clone(),
+        );
+        let forager = Forager::new(manager).await;
+
+        // by (user, symbol), as a coin may trade on one venue while wound down on another
+        let mut handles: HashMap<(String, String), task::JoinHandle<()>> = HashMap::new();
+        let mut winding_down: HashMap<(String, String), Arc<AtomicBool>> = HashMap::new();
+        let mut winding_since_ms: HashMap<(String, String), i64> = HashMap::new();
+
+        loop {
+            let held: HashMap<String, String> = handles
+                .keys()
+                .filter(|(user, symbol)| {
+                    status_board
+                        .get(user, symbol)
+                        .is_some_and(|status| status.position_size != 0.0)
+                })
+                .filter_map(|(user, symbol)| {
+                    let venue = venues.iter().find(|v| &v.account.user == user)?;
+                    let coin = venue::coin_of(symbol, venue.account.exchange.quote());
+                    Some((coin, user.clone()))
+                })
+                .collect();
+            let to_trade = forager.run_venues(&venues, &held).await;
+            // the stream follows the exchange of `live.user` only, including symbols being
+            // wound down there
+            if let Some(stream) = &self.market_stream {
+                let streamed: Vec<String> = to_trade
+                    .iter()
+                    .chain(handles.keys().filter(|key| !to_trade.contains(key)))
+                    .filter(|(user, _)| user == &self.config.live.user)
+                    .map(|(_, symbol)| symbol.clone())
+                    .collect();
+                stream.set_symbols(&streamed);
+            }
+
+            // Managers of coins dropped or moved to another venue wind down as in `run`,
+            // cancelling their entries on the old venue, and stop once flat without orders.
+            // Held coins stay on their venue, so a moved coin only has orders to cancel.
+            let to_stop: Vec<(String, String)> = handles
+                .keys()
+                .filter(|key| !to_trade.contains(key))
+                .cloned()
+                .collect();
+            for key in to_stop {
+                let (user, symbol) = &key;
+                let now_ms = chrono::Utc::now().timestamp_millis();
+                let since_ms = *winding_since_ms.entry(key.clone()).or_insert_with(|| {
+                    info!(
+                        "[{}] Dropped from the venue of user {}, winding down",
+                        symbol, user
+                    );
+                    winding_down[&key].store(true, Ordering::Relaxed);
+                    now_ms
+                });
+                let wound_down = status_board.get(user, symbol).is_none_or(|status| {
+                    status.updated_at_ms > since_ms
+                        && status.position_size == 0.0
+                        && status.nearest_entry_price.is_none()
+                        && status.nearest_close_price.is_none()
+                });
+                if !wound_down {
+                    continue;
+                }
+                info!(
+                    "[{}] Wound down on the venue of user {}, stopping manager",
+                    symbol, user
+                );
+                if let Some(handle) = handles.remove(&key) {
+                    handle.abort();
+                }
+                winding_down.remove(&key);
+                winding_since_ms.remove(&key);
+                status_board.remove(user, symbol);
+                if let Some(heartbeat) = &heartbeat {
+                    // the coin may have moved to another venue
+                    if !handles.keys().any(|(_, other)| other == symbol) {
+                        heartbeat.remove_symbol(symbol);
+                    }
+                }
+            }
+
+            for (user, symbol) in &to_trade {
+                let key = (user.clone(), symbol.clone());
+                if winding_since_ms.remove(&key).is_some() {
+                    info!(
+                        "[{}] Back on the venue of user {}, trading normally",
+                        symbol, user
+                    );
+                    winding_down[&key].store(false, Ordering::Relaxed);
+                }
+                if handles.contains_key(&key) {
+                    continue;
+                }
+                let Some(venue) = venues.iter().find(|v| &v.account.user == user) else {
+                    continue;
+                };
+                info!("[{}] Trading on the venue of user {}", symbol, user);
+                let flag = Arc::new(AtomicBool::new(false));
+                winding_down.insert(key.clone(), flag.clone());
+                let mut manager = Manager::new(
+                    symbol.clone(),
+                    self.config.clone(),
+                    venue.account.exchange.clone_box(),
+                    venue.order_queue.clone(),
+                    status_board.clone(),
+                )
+                .with_events(events.clone())
+                .with_heartbeat(heartbeat.clone())
+                .with_winding_down(flag)
+                .with_venue(user);
+                if let Some(stream) = &self.market_stream {
+                    if user == &self.config.live.user {
+                        manager = manager.with_market_feed(stream.feed());
+                    }
+                }
+                let handle = task::spawn(async move {
+                    manager.run().await;
+                });
+                handles.insert(key, handle);
             }
 
             tokio::time::sleep(tokio::time::Duration::from_secs(60)).await;
@@ -0,0 +1,317 @@
+//! Measures the fees and slippage the account actually paid from its recent fills, so
+//! backtests can be run on measured rather than assumed costs.
+
+use crate::exchange::{Exchange, SendSyncError};
+use crate::types::TradeFill;
+use chrono::Utc;
+use clap::Parser;
+use serde::Serialize;
+use std::fmt::Write;
+use std::fs;
+use std::path::Path;
+use tracing::info;
+
+const MS_PER_DAY: f64 = 24.0 * 60.0 * 60.0 * 1000.0;
+
+#[derive(Parser, Debug, Clone)]
+pub struct CalibrateArgs {
+    /// User/account name defined in api-keys.json
+    #[clap(long)]
+    pub user: String,
+
+    /// Number of days of fills to analyze
+    #[clap(long, default_value_t = 30.0)]
+    pub days: f64,
+
+    /// Symbols to analyze, defaults to live.approved_coins
+    #[clap(long, value_delimiter = ',')]
+    pub symbols: Vec<String>,
+
+    /// Prints the calibration without writing it to config.hjson
+    #[clap(long)]
+    pub dry_run: bool,
+}
+
+/// Costs measured over a set of fills. Fees are fractions of the filled value; slippage
+/// is the notional-weighted move of taker fills away from their order price, positive
+/// when against the order.
+#[derive(Serialize, Debug, Clone, PartialEq, Default)]
+pub struct FeeCalibration {
+    pub n_fills: usize,
+    pub n_maker: usize,
+    pub n_taker: usize,
+    /// Fills the exchange did not report as maker or taker; left out of the fees.
+    pub n_unclassified: usize,
+    /// Share of classified fills that were makers.
+    pub maker_ratio: f64,
+    /// None without fills of the kind.
+    pub maker_fee: Option<f64>,
+    pub taker_fee: Option<f64>,
+    pub taker_slippage: Option<f64>,
+    /// Taker fills with a known order price.
+    pub n_slippage_samples: usize,
+}
+
+impl FeeCalibration {
+    /// The `backtest` keys to set, rounded to a readable precision.
+    pub fn backtest_values(&self) -> Vec<(&'static str, f64)> {
+        [
+            ("maker_fee", self.maker_fee),
+            ("taker_fee", self.taker_fee),
+            ("taker_slippage", self.taker_slippage),
+        ]
+        .into_iter()
+        .filter_map(|(key, value)| Some((key, (value? * 1e8).round() / 1e8)))
+        .collect()
+    }
+}
+
+pub fn calibrate(fills: &[TradeFill]) -> FeeCalibration {
+    let mut calibration = FeeCalibration {
+        n_fills: fills.len(),
+        ..Default::default()
+    };
+    // (fees, notional) of maker and taker fills
+    let mut maker = (0.0, 0.0);
+    let mut taker = (0.0, 0.0);
+    // (notional-weighted slippage, notional)
+    let mut slippage = (0.0, 0.0);
+    for fill in fills {
+        let notional = fill.qty * fill.price;
+        match fill.maker {
+            Some(true) => {
+                calibration.n_maker += 1;
+                maker.0 += fill.fee;
+                maker.1 += notional;
+            }
+            Some(false) => {
+                calibration.n_taker += 1;
+                taker.0 += fill.fee;
+                taker.1 += notional;
+                if fill.order_price > 0.0 {
+                    let direction = if fill.side == "Buy" { 1.0 } else { -1.0 };
+                    let move_pct = (fill.price - fill.order_price) / fill.order_price * direction;
+                    slippage.0 += move_pct * notional;
+                    slippage.1 += notional;
+                    calibration.n_slippage_samples += 1;
+                }
+            }
+            None => calibration.n_unclassified += 1,
+        }
+    }
+    let n_classified = calibration.n_maker + calibration.n_taker;
+    if n_classified > 0 {
+        calibration.maker_ratio = calibration.n_maker as f64 / n_classified as f64;
+    }
+    let ratio =
+        |(numerator, denominator): (f64, f64)| (denominator > 0.0).then(|| numerator / denominator);
+    calibration.maker_fee = ratio(maker);
+    calibration.taker_fee = ratio(taker);
+    calibration.taker_slippage = ratio(slippage);
+    calibration
+}
+
+/// Fetches the last `days` of fills of `symbols` and measures them.
+pub async fn calibrate_from_history(
+    exchange: &dyn Exchange, symbols: &[String], days: f64,
+) -> Result<FeeCalibration, SendSyncError> {
+    if symbols.is_empty() {
+        return Err("No symbols to calibrate on; pass --symbols or set live.approved_coins".into());
+    }
+    let end_ms = Utc::now().timestamp_millis();
+    let start_ms = end_ms - (days * MS_PER_DAY) as i64;
+    let mut fills = Vec::new();
+    for symbol in symbols {
+        let symbol_fills = exchange
+            .fetch_fill_history(symbol, start_ms, end_ms)
+            .await?;
+        info!("Fetched {} fills for {}", symbol_fills.len(), symbol);
+        fills.extend(symbol_fills);
+    }
+    if fills.is_empty() {
+        return Err(format!("No fills in the last {} days to calibrate on", days).into());
+    }
+    Ok(calibrate(&fills))
+}
+
+/// Sets `values` in the top-level `backtest` block of an hjson or JSON config, replacing
+/// keys already there and appending the others, and leaves the rest of the text as is.
+pub fn set_backtest_values(config: &str, values: &[(&str, f64)]) -> Result<String, String> {
+    let mut lines: Vec<String> = config.lines().map(str::to_string).collect();
+    let is_key = |line: &str, key: &str| {
+        let line = line.trim_start();
+        let rest = line
+            .strip_prefix(key)
+            .or_else(|| line.strip_prefix(&format!("\"{}\"", key)));
+        rest.is_some_and(|rest| rest.trim_start().starts_with(':'))
+    };
+    let start = lines
+        .iter()
+        .position(|line| is_key(line, "backtest") && line.trim_end().ends_with('{'))
+        .ok_or("config has no backtest block")?;
+    // lines directly inside the block, and its closing line
+    let mut entries = Vec::new();
+    let mut depth = 1;
+    let mut end = None;
+    for (i, line) in lines.iter().enumerate().skip(start + 1) {
+        if depth == 1 && !line.trim().is_empty() && !line.trim_start().starts_with('}') {
+            entries.push(i);
+        }
+        depth += line.matches('{').count() as i32 - line.matches('}').count() as i32;
+        if depth <= 0 {
+            end = Some(i);
+            break;
+        }
+    }
+    let end = end.ok_or("backtest block is not closed")?;
+    let quoted = entries
+        .first()
+        .is_some_and(|&i| lines[i].trim_start().starts_with('"'));
+    // JSON needs commas, hjson takes them or not
+    let uses_commas = quoted || entries.iter().any(|&i| lines[i].trim_end().ends_with(','));
+    let indent = entries.first().map_or_else(
+        || {
+            format!(
+                "{}    ",
+                &lines[end][..lines[end].len() - lines[end].trim_start().len()]
+            )
+        },
+        |&i| lines[i][..lines[i].len() - lines[i].trim_start().len()].to_string(),
+    );
+    let entry = |key: &str, value: f64, comma: bool| {
+        let key = if quoted {
+            format!("\"{}\"", key)
+        } else {
+            key.to_string()
+        };
+        format!(
+            "{}{}: {}{}",
+            indent,
+            key,
+            value,
+            if comma { "," } else { "" }
+        )
+    };
+
+    let mut appended = Vec::new();
+    for &(key, value) in values {
+        match entries.iter().find(|&&i| is_key(&lines[i], key)) {
+            Some(&i) => {
+                let comma = lines[i].trim_end().ends_with(',');
+                lines[i] = entry(key, value, comma);
+            }
+            None => appended.push((key, value)),
+        }
+    }
+    if !appended.is_empty() {
+        if uses_commas {
+            if let Some(&last) = entries.last() {
+                if !lines[last].trim_end().ends_with(',') {
+                    lines[last].push(',');
+                }
+            }
+        }
+        let n_appended = appended.len();
+        let new_lines = appended
+            .into_iter()
+            .enumerate()
+            .map(|(i, (key, value))| entry(key, value, uses_commas && i + 1 < n_appended));
+        lines.splice(end..end, new_lines);
+    }
+    let mut text = lines.join("\n");
+    if config.ends_with('\n') {
+        text.push('\n');
+    }
+    Ok(text)
+}
+
+/// Writes the measured costs into the `backtest` block of the config at `path`.
+pub fn write_to_config(path: &Path, calibration: &FeeCalibration) -> Result<(), SendSyncError> {
+    let values = calibration.backtest_values();
+    if values.is_empty() {
+        return Err("No maker or taker fills to calibrate on".into());
+    }
+    let config = fs::read_to_string(path)?;
+    let updated = set_backtest_values(&config, &values)
+        .map_err(|e| format!("Failed to update {}: {}", path.display(), e))?;
+    fs::write(path, updated)?;
+    Ok(())
+}
+
+pub fn format_calibration(calibration: &FeeCalibration) -> String {
+    let fmt_pct = |value: Option<f64>| match value {
+        Some(value) => format!("{:.4}%", value * 100.0),
+        None => "n/a".to_string(),
+    };
+    let mut out = format!(
+        "fills: {}  maker: {}  taker: {}  unclassified: {}  maker ratio: {:.1}%\n",
+        calibration.n_fills,
+        calibration.n_maker,
+        calibration.n_taker,
+        calibration.n_unclassified,
+        calibration.maker_ratio * 100.0
+    );
+    let _ = writeln!(
+        out,
+        "maker fee: {}  taker fee: {}  taker slippage: {} ({} fills with an order price)",
+        fmt_pct(calibration.maker_fee),
+        fmt_pct(calibration.taker_fee),
+        fmt_pct(calibration.taker_slippage),
+        calibration.n_slippage_samples
+    );
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_calibrate_and_write_backtest_block() {
+        let fill =
+            |side: &str, price: f64, order_price: f64, fee: f64, maker: Option<bool>| TradeFill {
+                side: side.to_string(),
+                qty: 1.0,
+                price,
+                order_price,
+                fee,
+                maker,
+                ..Default::default()
+            };
+        let calibration = calibrate(&[
+            fill("Buy", 100.0, 100.0, 0.02, Some(true)),
+            fill("Sell", 100.0, 100.0, -0.01, Some(true)),
+            // a buy 0.1% above and a sell 0.3% below their order prices
+            fill("Buy", 100.1, 100.0, 0.05005, Some(false)),
+            fill("Sell", 99.7, 100.0, 0.04985, Some(false)),
+            fill("Buy", 100.0, 0.0, 0.05, None),
+        ]);
+        assert_eq!(
+            (
+                calibration.n_maker,
+                calibration.n_taker,
+                calibration.n_unclassified
+            ),
+            (2, 2, 1)
+        );
+        assert_eq!(calibration.maker_ratio, 0.5);
+        assert!((calibration.maker_fee.unwrap() - 0.00005).abs() < 1e-12);
+        assert!((calibration.taker_fee.unwrap() - 0.0005).abs() < 1e-12);
+        let slippage = (0.001 * 100.1 + 0.003 * 99.7) / (100.1 + 99.7);
+        assert!((calibration.taker_slippage.unwrap() - slippage).abs() < 1e-12);
+
+        let config = "{\n  live: {\n    leverage: 10\n  }\n  backtest: {\n    exchanges: {\n      binance: {}\n    }\n    maker_fee: 0.0002\n  }\n}\n";
+        let values = [("maker_fee", 0.00005), ("taker_fee", 0.0005)];
+        let updated = set_backtest_values(config, &values).unwrap();
+        assert_eq!(
+            updated,
+            "{\n  live: {\n    leverage: 10\n  }\n  backtest: {\n    exchanges: {\n      binance: {}\n    }\n    maker_fee: 0.00005\n    taker_fee: 0.0005\n  }\n}\n"
+        );
+        let json = "{\n  \"backtest\": {\n    \"starting_balance\": 1000\n  }\n}";
+        assert_eq!(
+            set_backtest_values(json, &values).unwrap(),
+            "{\n  \"backtest\": {\n    \"starting_balance\": 1000,\n    \"maker_fee\": 0.00005,\n    \"taker_fee\": 0.0005\n  }\n}"
+        );
+        assert!(set_backtest_values("{\n  live: {}\n}", &values).is_err());
+    }
+}
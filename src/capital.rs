@@ -0,0 +1,186 @@
+//! Pre-flight check that the account can fund the configured universe. Below a certain
+//! balance, initial entries sized from the exposure limits fall under the exchange's
+//! minimum order cost and are rounded up to it, so positions end up larger than the
+//! config intends.
+
+use crate::constants::{LONG, SHORT};
+use crate::exchange::{Exchange, SendSyncError};
+use crate::grid::entries::calc_min_entry_qty;
+use crate::grid::utils::qty_to_cost;
+use crate::types::{BotConfig, BotSideConfig};
+use tracing::{info, warn};
+
+/// The balance one side needs: `n_positions` full grids of minimum-size entries within
+/// `total_wallet_exposure_limit`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CapitalRequirement {
+    pub pside: usize,
+    pub n_positions: usize,
+    /// The symbol with the costliest minimum entry among those to trade.
+    pub symbol: String,
+    pub min_entry_cost: f64,
+    /// Entries a full position holds at the initial entry size.
+    pub grid_depth: f64,
+    pub total_wallet_exposure_limit: f64,
+}
+
+impl CapitalRequirement {
+    pub fn required_balance(&self) -> f64 {
+        self.n_positions as f64 * self.min_entry_cost * self.grid_depth
+            / self.total_wallet_exposure_limit
+    }
+
+    /// The most positions `balance` can fund.
+    pub fn affordable_positions(&self, balance: f64) -> usize {
+        (balance * self.total_wallet_exposure_limit / (self.min_entry_cost * self.grid_depth))
+            .floor()
+            .max(0.0) as usize
+    }
+
+    pub fn explain(&self, balance: f64, quote: &str) -> String {
+        format!(
+            "balance {:.2} {quote} is below the {:.2} {quote} the {} side needs: {} positions × \
+            {:.2} {quote} minimum entry ({}) × {:.1} grid depth (1 / entry_initial_qty_pct) / \
+            total_wallet_exposure_limit {}",
+            balance,
+            self.required_balance(),
+            if self.pside == LONG { "long" } else { "short" },
+            self.n_positions,
+            self.min_entry_cost,
+            self.symbol,
+            self.grid_depth,
+            self.total_wallet_exposure_limit,
+        )
+    }
+}
+
+/// Initial entries a full position holds.
+pub fn grid_depth(bot_params: &BotSideConfig) -> f64 {
+    if bot_params.entry_initial_qty_pct > 0.0 {
+        1.0 / bot_params.entry_initial_qty_pct
+    } else {
+        1.0
+    }
+}
+
+/// Requirements of the enabled sides, given each symbol's minimum entry cost.
+pub fn requirements(
+    config: &BotConfig, min_entry_costs: &[(String, f64)],
+) -> Vec<CapitalRequirement> {
+    let Some((symbol, min_entry_cost)) = min_entry_costs.iter().max_by(|a, b| a.1.total_cmp(&b.1))
+    else {
+        return Vec::new();
+    };
+    [(LONG, &config.bot.long), (SHORT, &config.bot.short)]
+        .into_iter()
+        .filter(|(_, side)| {
+            side.enabled && side.n_positions >= 1.0 && side.total_wallet_exposure_limit > 0.0
+        })
+        .map(|(pside, side)| CapitalRequirement {
+            pside,
+            n_positions: side.n_positions.round() as usize,
+            symbol: symbol.clone(),
+            min_entry_cost: *min_entry_cost,
+            grid_depth: grid_depth(side),
+            total_wallet_exposure_limit: side.total_wallet_exposure_limit,
+        })
+        .collect()
+}
+
+/// The cost of the smallest order the exchange accepts for each of `symbols`, at the
+/// current price.
+pub async fn min_entry_costs(
+    exchange: &dyn Exchange, symbols: &[String],
+) -> Result<Vec<(String, f64)>, SendSyncError> {
+    let mut costs = Vec::with_capacity(symbols.len());
+    for symbol in symbols {
+        let exchange_params = exchange.fetch_exchange_params(symbol).await?;
+        let price = exchange.fetch_ticker(symbol).await?;
+        let cost = qty_to_cost(
+            calc_min_entry_qty(price, &exchange_params),
+            price,
+            exchange_params.inverse,
+            exchange_params.c_mult,
+        );
+        costs.push((symbol.clone(), cost));
+    }
+    Ok(costs)
+}
+
+/// Checks the balance against the requirements of `symbols` before trading starts. With
+/// `live.capital_check` "refuse" an underfunded account is an error; with "reduce" each
+/// side's `n_positions` is lowered to what the balance can fund; "off" skips the check.
+pub async fn preflight(
+    config: &mut BotConfig, exchange: &dyn Exchange, symbols: &[String],
+) -> Result<(), SendSyncError> {
+    let mode = config.live.capital_check.clone();
+    if !matches!(mode.as_str(), "refuse" | "reduce" | "off") {
+        return Err(format!(
+            "Unknown live.capital_check {:?}, expected \"refuse\", \"reduce\" or \"off\"",
+            mode
+        )
+        .into());
+    }
+    if mode == "off" || symbols.is_empty() {
+        return Ok(());
+    }
+    let balance = exchange.fetch_balance().await?;
+    let costs = min_entry_costs(exchange, symbols).await?;
+    for requirement in requirements(config, &costs) {
+        let required_balance = requirement.required_balance();
+        if balance >= required_balance {
+            info!(
+                "Capital check passed: balance {:.2} covers the {:.2} {} positions need",
+                balance, required_balance, requirement.n_positions
+            );
+            continue;
+        }
+        let explanation = requirement.explain(balance, exchange.quote());
+        let affordable = requirement.affordable_positions(balance);
+        if mode == "refuse" || affordable == 0 {
+            let remedy = if affordable == 0 {
+                "fund the account or trade symbols with smaller minimum orders".to_string()
+            } else {
+                format!(
+                    "fund the account, set n_positions to {} or less, or set \
+                    live.capital_check to \"reduce\"",
+                    affordable
+                )
+            };
+            return Err(format!("Refusing to start: {}; {}", explanation, remedy).into());
+        }
+        warn!("{}; reducing n_positions to {}", explanation, affordable);
+        let side = if requirement.pside == LONG {
+            &mut config.bot.long
+        } else {
+            &mut config.bot.short
+        };
+        side.n_positions = affordable as f64;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config;
+
+    #[test]
+    fn test_required_balance_and_reduction() {
+        let mut config = config::load_config("tests/fixtures/backtest_config.hjson").unwrap();
+        config.bot.short.enabled = false;
+        // 10 positions, 20 entries deep at entry_initial_qty_pct 0.05, exposure limit 1
+        let costs = [("BTCUSDT".to_string(), 5.0), ("ETHUSDT".to_string(), 2.0)];
+        let requirements = requirements(&config, &costs);
+        assert_eq!(requirements.len(), 1);
+        let requirement = &requirements[0];
+        assert_eq!(requirement.symbol, "BTCUSDT");
+        assert!((requirement.required_balance() - 1000.0).abs() < 1e-6);
+        assert_eq!(requirement.affordable_positions(1000.0 + 1e-6), 10);
+        assert_eq!(requirement.affordable_positions(450.0), 4);
+        assert_eq!(requirement.affordable_positions(99.0), 0);
+        assert!(requirement
+            .explain(450.0, "USDT")
+            .contains("10 positions × 5.00 USDT minimum entry (BTCUSDT)"));
+    }
+}
@@ -0,0 +1,151 @@
+use crate::exchange::simulated::Fill;
+use crate::types::OrderType;
+use serde::Serialize;
+
+/// A candle in lightweight-charts' `CandlestickData` format, with `time` in seconds.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct ChartCandle {
+    pub time: i64,
+    pub open: f64,
+    pub high: f64,
+    pub low: f64,
+    pub close: f64,
+}
+
+/// A fill in lightweight-charts' `SeriesMarker` format. `kind`, `qty` and `price` are not
+/// read by the chart; the viewer uses them to filter markers and show fill details.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct ChartMarker {
+    pub time: i64,
+    pub position: &'static str,
+    pub color: &'static str,
+    pub shape: &'static str,
+    pub text: String,
+    /// "entry", "close" or "unstuck".
+    pub kind: &'static str,
+    pub qty: f64,
+    pub price: f64,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct SymbolChart {
+    pub symbol: String,
+    pub candles: Vec<ChartCandle>,
+    pub markers: Vec<ChartMarker>,
+}
+
+/// Candles and fill markers of a backtest, written as `chart.json` and rendered by
+/// `tools/fills_chart.html`.
+#[derive(Serialize, Debug, Clone, Default, PartialEq)]
+pub struct ChartExport {
+    pub symbols: Vec<SymbolChart>,
+}
+
+impl SymbolChart {
+    pub fn new(symbol: &str) -> Self {
+        Self {
+            symbol: symbol.to_string(),
+            candles: Vec::new(),
+            markers: Vec::new(),
+        }
+    }
+
+    /// Appends a candle. HLCV data has no open, so the previous close stands in for it.
+    pub fn push_candle(&mut self, timestamp_ms: u64, high: f64, low: f64, close: f64) {
+        let open = self.candles.last().map_or(close, |c| c.close);
+        self.candles.push(ChartCandle {
+            time: (timestamp_ms / 1000) as i64,
+            open,
+            high,
+            low,
+            close,
+        });
+    }
+
+    /// Adds a marker per fill. A fill's `step` counts price updates of the simulated
+    /// exchange, so `first_step` is its value when this symbol's first candle was pushed.
+    pub fn add_fills(&mut self, fills: &[Fill], first_step: u64) {
+        for fill in fills {
+            let Some(candle) = fill
+                .step
+                .checked_sub(first_step)
+                .and_then(|i| self.candles.get(i as usize))
+            else {
+                continue;
+            };
+            let order_type = OrderType::from_str(&fill.custom_id).unwrap_or(OrderType::Empty);
+            let kind = match order_type {
+                OrderType::CloseUnstuckLong
+                | OrderType::CloseUnstuckShort
+                | OrderType::EntryUnstuckLong
+                | OrderType::EntryUnstuckShort => "unstuck",
+                t if t.is_close() => "close",
+                _ => "entry",
+            };
+            let (position, shape) = if fill.side == "Buy" {
+                ("belowBar", "arrowUp")
+            } else {
+                ("aboveBar", "arrowDown")
+            };
+            self.markers.push(ChartMarker {
+                time: candle.time,
+                position,
+                color: match kind {
+                    "unstuck" => "#ff9800",
+                    "close" => "#2962ff",
+                    _ => "#26a69a",
+                },
+                shape: if kind == "unstuck" { "circle" } else { shape },
+                text: fill.custom_id.clone(),
+                kind,
+                qty: fill.qty,
+                price: fill.price,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fill(step: u64, side: &str, custom_id: &str) -> Fill {
+        Fill {
+            step,
+            timestamp_ms: 0,
+            side: side.to_string(),
+            qty: 0.1,
+            price: 100.0,
+            order_price: 100.0,
+            order_id: String::new(),
+            custom_id: custom_id.to_string(),
+            fee: 0.0,
+            taker: false,
+        }
+    }
+
+    #[test]
+    fn test_symbol_chart() {
+        let mut chart = SymbolChart::new("BTCUSDT");
+        chart.push_candle(60_000, 101.0, 99.0, 100.0);
+        chart.push_candle(120_000, 102.0, 98.0, 101.0);
+        assert_eq!(chart.candles[0].open, 100.0);
+        assert_eq!(chart.candles[1].open, 100.0);
+        assert_eq!(chart.candles[1].time, 120);
+
+        chart.add_fills(
+            &[
+                fill(4, "Buy", "entry_grid_normal_long"),
+                fill(5, "Buy", "entry_initial_normal_long"),
+                fill(6, "Sell", "close_grid_long"),
+                fill(6, "Sell", "unstuck_close_long"),
+            ],
+            5,
+        );
+        let kinds: Vec<&str> = chart.markers.iter().map(|m| m.kind).collect();
+        assert_eq!(kinds, vec!["entry", "close", "unstuck"]);
+        assert_eq!(chart.markers[0].time, 60);
+        assert_eq!(chart.markers[1].position, "aboveBar");
+        assert_eq!(chart.markers[2].shape, "circle");
+    }
+}
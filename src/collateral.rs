@@ -0,0 +1,46 @@
+//! Margin available on accounts holding several collateral assets. Exchanges count
+//! non-quote collateral at a discount, so sizing on its full value would over-size
+//! positions; `live.cross_collateral` sizes on `usable_collateral` instead.
+
+use crate::types::CollateralAsset;
+
+/// The margin value of `assets` in quote currency: each asset's value less its haircut.
+/// Borrowed assets count in full against it.
+pub fn usable_collateral(assets: &[CollateralAsset]) -> f64 {
+    assets
+        .iter()
+        .map(|asset| {
+            if asset.value < 0.0 {
+                asset.value
+            } else {
+                asset.value * (1.0 - asset.haircut.clamp(0.0, 1.0))
+            }
+        })
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_haircuts_and_borrows() {
+        let asset = |asset: &str, value: f64, haircut: f64| CollateralAsset {
+            asset: asset.to_string(),
+            quantity: 0.0,
+            value,
+            haircut,
+        };
+        let assets = [
+            asset("USDT", 1000.0, 0.0),
+            asset("BTC", 2000.0, 0.05),
+            asset("ETH", 500.0, 0.1),
+            // excluded from margin entirely
+            asset("DOGE", 300.0, 1.0),
+            asset("USDC", -200.0, 0.0),
+        ];
+        let usable = usable_collateral(&assets);
+        assert!((usable - (1000.0 + 1900.0 + 450.0 - 200.0)).abs() < 1e-9);
+        assert_eq!(usable_collateral(&[]), 0.0);
+    }
+}
@@ -18,8 +18,53 @@ pub struct UserConfig {
     pub wallet_address: String,
     #[serde(default)]
     pub private_key: String,
+    /// Hyperliquid: `wallet_address` is a vault traded on behalf of by the signing key.
     #[serde(default)]
     pub is_vault: bool,
+    /// Hyperliquid: address of the agent wallet `private_key` belongs to, when orders are
+    /// signed by a delegated agent instead of the master key.
+    #[serde(default)]
+    pub agent_address: String,
+    /// Hyperliquid: expiry of the agent approval (ms), 0 if it never expires.
+    #[serde(default)]
+    pub agent_valid_until_ms: i64,
+    /// Hyperliquid: master key used only to renew the agent approval before it expires.
+    #[serde(default)]
+    pub master_private_key: String,
+    /// Maker fee of the account's fee tier, used to compare venues.
+    #[serde(default = "default_maker_fee")]
+    pub maker_fee: f64,
+    /// Quote/settlement currency of the account's markets, e.g. USDT or USDC.
+    #[serde(default = "default_quote")]
+    pub quote: String,
+}
+
+impl UserConfig {
+    /// A config without credentials, for public market data of `exchange`.
+    pub fn public(exchange: &str) -> Self {
+        serde_json::from_value(serde_json::json!({ "exchange": exchange }))
+            .expect("all fields but exchange have defaults")
+    }
+
+    /// Checks that the exchange can trade markets settled in `quote`: Gate.io and Bitget
+    /// only load USDT-settled contracts.
+    pub fn validate(&self) -> Result<(), String> {
+        if matches!(self.exchange.as_str(), "gateio" | "bitget") && self.quote != "USDT" {
+            return Err(format!(
+                "{} only trades USDT-settled contracts, got quote \"{}\"",
+                self.exchange, self.quote
+            ));
+        }
+        Ok(())
+    }
+}
+
+fn default_maker_fee() -> f64 {
+    0.0002
+}
+
+fn default_quote() -> String {
+    "USDT".to_string()
 }
 
 pub fn load_api_keys() -> Result<HashMap<String, UserConfig>, SendSyncError> {
@@ -33,5 +78,27 @@ pub fn load_config<P: AsRef<Path>>(path: P) -> Result<BotConfig, SendSyncError>
     let content = fs::read_to_string(path).map_err(|e| Box::new(e) as SendSyncError)?;
     let config: BotConfig =
         serde_hjson::from_str(&content).map_err(|e| Box::new(e) as SendSyncError)?;
+    config.live.validate()?;
     Ok(config)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quote_must_be_tradable_on_the_exchange() {
+        let user = |exchange: &str, quote: &str| -> UserConfig {
+            serde_json::from_value(serde_json::json!({ "exchange": exchange, "quote": quote }))
+                .unwrap()
+        };
+        for exchange in ["bybit", "binance", "okx"] {
+            assert!(user(exchange, "USDC").validate().is_ok());
+        }
+        for exchange in ["gateio", "bitget"] {
+            assert!(UserConfig::public(exchange).validate().is_ok());
+            let error = user(exchange, "USDC").validate().unwrap_err();
+            assert!(error.contains("USDT-settled"), "{}", error);
+        }
+    }
+}
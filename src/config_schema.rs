@@ -0,0 +1,293 @@
+use crate::types::BotConfig;
+use clap::Subcommand;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fmt::Write;
+
+/// The config types are documented where they are declared; the reference is read from
+/// their source so it never falls behind the structs themselves.
+const TYPES_SOURCE: &str = include_str!("types.rs");
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum ConfigCommand {
+    /// Prints a reference of every config parameter with its type, default and range
+    Schema,
+}
+
+/// One leaf parameter of the config, such as `live.leverage`.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct ParamDoc {
+    /// Dotted path; `[]` stands for list items and `<key>` for map keys.
+    pub path: String,
+    #[serde(rename = "type")]
+    pub ty: String,
+    /// `None` for parameters that must be given.
+    pub default: Option<String>,
+    /// Search bounds from `optimizer.long`/`optimizer.short`, for bot side parameters.
+    pub range: Option<(f64, f64)>,
+    pub doc: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum FieldDefault {
+    Required,
+    /// `#[serde(default)]`: the type's default.
+    TypeDefault,
+    /// `#[serde(default = "<fn>")]`.
+    Fn(String),
+}
+
+#[derive(Debug, Clone)]
+struct FieldDef {
+    name: String,
+    ty: String,
+    doc: String,
+    default: FieldDefault,
+}
+
+/// Fields of every `pub struct` in `source`, by struct name.
+fn parse_structs(source: &str) -> HashMap<String, Vec<FieldDef>> {
+    let mut structs = HashMap::new();
+    let mut current: Option<(String, Vec<FieldDef>)> = None;
+    let mut doc: Vec<&str> = Vec::new();
+    let mut default = FieldDefault::Required;
+    let mut rename = None;
+    for line in source.lines() {
+        let trimmed = line.trim();
+        if let Some(text) = trimmed.strip_prefix("///") {
+            doc.push(text.trim());
+            continue;
+        }
+        if let Some(attr) = trimmed.strip_prefix("#[serde(") {
+            if let Some(name) = attr_value(attr, "default") {
+                default = FieldDefault::Fn(name);
+            } else if attr.split([',', ')']).any(|part| part.trim() == "default") {
+                default = FieldDefault::TypeDefault;
+            }
+            if let Some(name) = attr_value(attr, "rename") {
+                rename = Some(name);
+            }
+            continue;
+        }
+        if trimmed.starts_with("#[") {
+            continue;
+        }
+        if let Some(name) = line
+            .strip_prefix("pub struct ")
+            .and_then(|rest| rest.strip_suffix(" {"))
+        {
+            current = Some((name.to_string(), Vec::new()));
+        } else if line == "}" {
+            if let Some((name, fields)) = current.take() {
+                structs.insert(name, fields);
+            }
+        } else if let (Some((_, fields)), Some((name, ty))) = (
+            current.as_mut(),
+            trimmed.strip_prefix("pub ").and_then(|f| f.split_once(':')),
+        ) {
+            fields.push(FieldDef {
+                name: rename.take().unwrap_or_else(|| name.to_string()),
+                ty: ty.trim().trim_end_matches(',').to_string(),
+                doc: doc.join(" "),
+                default: std::mem::replace(&mut default, FieldDefault::Required),
+            });
+        }
+        doc.clear();
+        default = FieldDefault::Required;
+        rename = None;
+    }
+    structs
+}
+
+/// The quoted value of `key = "value"` in a serde attribute.
+fn attr_value(attr: &str, key: &str) -> Option<String> {
+    attr.split(',').find_map(|part| {
+        let (k, v) = part.split_once('=')?;
+        (k.trim() == key).then(|| {
+            v.trim()
+                .trim_end_matches(")]")
+                .trim_matches('"')
+                .to_string()
+        })
+    })
+}
+
+/// Return values of the `default_*` functions in `source` that are a single expression.
+fn parse_default_fns(source: &str) -> HashMap<String, String> {
+    let mut defaults = HashMap::new();
+    let mut lines = source.lines();
+    while let Some(line) = lines.next() {
+        let Some(name) = line
+            .strip_prefix("fn ")
+            .or_else(|| line.strip_prefix("pub fn "))
+            .and_then(|rest| rest.split_once("()"))
+            .map(|(name, _)| name)
+            .filter(|name| name.starts_with("default_"))
+        else {
+            continue;
+        };
+        let body: Vec<&str> = lines
+            .by_ref()
+            .take_while(|line| *line != "}")
+            .map(str::trim)
+            .collect();
+        if let [value] = body.as_slice() {
+            defaults.insert(
+                name.to_string(),
+                value.trim_end_matches(".to_string()").to_string(),
+            );
+        }
+    }
+    defaults
+}
+
+/// The default of a `#[serde(default)]` field of a type without a `Default` impl of its own.
+fn type_default(ty: &str) -> String {
+    match ty {
+        "bool" => "false",
+        "String" => "\"\"",
+        "f64" | "f32" => "0.0",
+        _ if ty.starts_with("Vec<") => "[]",
+        _ if ty.starts_with("HashMap<") => "{}",
+        _ if ty.starts_with("Option<") => "null",
+        _ => "0",
+    }
+    .to_string()
+}
+
+/// The struct a field's type holds, if any, with the path suffix that leads into it.
+fn nested_struct<'a>(
+    ty: &'a str, structs: &HashMap<String, Vec<FieldDef>>,
+) -> Option<(&'a str, &'static str)> {
+    let (inner, suffix) = if let Some(inner) = ty.strip_prefix("Vec<") {
+        (inner.strip_suffix('>')?, "[]")
+    } else if let Some(inner) = ty.strip_prefix("HashMap<String, ") {
+        (inner.strip_suffix('>')?, ".<key>")
+    } else {
+        (ty, "")
+    };
+    structs.contains_key(inner).then_some((inner, suffix))
+}
+
+fn collect_params(
+    struct_name: &str, prefix: &str, structs: &HashMap<String, Vec<FieldDef>>,
+    default_fns: &HashMap<String, String>, out: &mut Vec<ParamDoc>,
+) {
+    for field in structs.get(struct_name).into_iter().flatten() {
+        let path = if prefix.is_empty() {
+            field.name.clone()
+        } else {
+            format!("{}.{}", prefix, field.name)
+        };
+        if let Some((nested, suffix)) = nested_struct(&field.ty, structs) {
+            collect_params(nested, &(path + suffix), structs, default_fns, out);
+            continue;
+        }
+        let default = match &field.default {
+            FieldDefault::Required => None,
+            FieldDefault::TypeDefault => Some(type_default(&field.ty)),
+            FieldDefault::Fn(name) => Some(default_fns.get(name).cloned().unwrap_or(name.clone())),
+        };
+        out.push(ParamDoc {
+            path,
+            ty: field.ty.clone(),
+            default,
+            range: None,
+            doc: field.doc.clone(),
+        });
+    }
+}
+
+/// Every parameter of `BotConfig`, in declaration order. Bot side parameters carry the
+/// optimizer bounds of `config`, if it has any for them.
+pub fn build_schema(config: &BotConfig) -> Vec<ParamDoc> {
+    let structs = parse_structs(TYPES_SOURCE);
+    let default_fns = parse_default_fns(TYPES_SOURCE);
+    let mut params = Vec::new();
+    collect_params("BotConfig", "", &structs, &default_fns, &mut params);
+    for param in &mut params {
+        let bounds = match param.path.split_once('.') {
+            Some(("bot", rest)) => match rest.split_once('.') {
+                Some(("long", name)) => config.optimizer.long.get(name),
+                Some(("short", name)) => config.optimizer.short.get(name),
+                _ => None,
+            },
+            _ => None,
+        };
+        param.range = bounds.map(|range| (range.start, range.end));
+    }
+    params
+}
+
+/// Formats the parameters as a markdown table.
+pub fn format_schema(params: &[ParamDoc]) -> String {
+    let mut out = String::from("| parameter | type | default | range | description |\n");
+    out.push_str("|---|---|---|---|---|\n");
+    for param in params {
+        let _ = writeln!(
+            out,
+            "| `{}` | `{}` | {} | {} | {} |",
+            param.path,
+            param.ty,
+            param
+                .default
+                .as_ref()
+                .map_or("required".to_string(), |d| format!("`{}`", d)),
+            param
+                .range
+                .map_or(String::new(), |(start, end)| format!("{} - {}", start, end)),
+            param.doc.replace('|', "\\|")
+        );
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config;
+    use crate::types::OptimizeInRange;
+
+    #[test]
+    fn test_build_schema() {
+        let mut config = config::load_config("tests/fixtures/backtest_config.hjson").unwrap();
+        config.optimizer.long.insert(
+            "ema_span_0".to_string(),
+            OptimizeInRange {
+                start: 200.0,
+                end: 1440.0,
+            },
+        );
+        let params = build_schema(&config);
+        let param = |path: &str| params.iter().find(|p| p.path == path).unwrap().clone();
+
+        let threshold = param("live.close_refresh_threshold");
+        assert_eq!(threshold.ty, "f64");
+        assert_eq!(threshold.default.as_deref(), Some("0.002"));
+        assert!(threshold.doc.starts_with("Relative move of the mid price"));
+        assert_eq!(param("live.leverage").default, None);
+        assert_eq!(
+            param("live.book_price_mode").default.as_deref(),
+            Some("\"grid\"")
+        );
+        assert_eq!(param("live.approved_coins").default.as_deref(), Some("[]"));
+        // nested structs are listed by their fields
+        assert_eq!(
+            param("live.risk_governor.max_drawdown").default.as_deref(),
+            Some("0.2")
+        );
+        assert_eq!(
+            param("live.allocation_groups[].max_report_age_seconds")
+                .default
+                .as_deref(),
+            Some("300.0")
+        );
+        assert!(params
+            .iter()
+            .any(|p| p.path == "optimizer.long.<key>.start"));
+        assert!(params.iter().all(|p| p.path != "live.risk_governor"));
+        assert_eq!(param("bot.short.enabled").default.as_deref(), Some("true"));
+        assert_eq!(param("bot.long.ema_span_0").range, Some((200.0, 1440.0)));
+        assert_eq!(param("bot.short.ema_span_0").range, None);
+    }
+}
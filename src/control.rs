@@ -0,0 +1,419 @@
+use crate::events::{BotEvent, EventSubscriber};
+use crate::exchange::SendSyncError;
+use crate::journal::{self, RealizedPnl};
+use crate::margin::{self, AccountMargin, SymbolMargin};
+use crate::order_diff::OrderDiff;
+use crate::performance::RollingMetrics;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write as _;
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tracing::{info, warn};
+
+/// Snapshot of one symbol's manager, published after every cycle.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct SymbolStatus {
+    pub symbol: String,
+    /// User whose account the symbol trades on with `live.venues`, where a symbol may be
+    /// traded on several; empty otherwise.
+    #[serde(default)]
+    pub venue: String,
+    pub mode_long: String,
+    pub mode_short: String,
+    pub position_size: f64,
+    pub position_price: f64,
+    pub wallet_exposure: f64,
+    pub nearest_entry_price: Option<f64>,
+    pub nearest_close_price: Option<f64>,
+    pub unstuck_allowance_long: f64,
+    pub unstuck_allowance_short: f64,
+    #[serde(default)]
+    pub unrealized_pnl: f64,
+    /// Funding paid by the position since it was opened.
+    #[serde(default)]
+    pub funding_paid: f64,
+    /// Resting orders kept and cancelled and orders created by the last reconciliation.
+    #[serde(default)]
+    pub last_order_diff: OrderDiff,
+    /// Order requests of the account's queue not yet completed by the exchange, as of the
+    /// symbol's last placement.
+    #[serde(default)]
+    pub order_queue_depth: usize,
+    /// Account balance and the symbol's leverage, as of the symbol's last cycle.
+    #[serde(default)]
+    pub balance: f64,
+    #[serde(default)]
+    pub leverage: f64,
+    #[serde(default)]
+    pub margin: SymbolMargin,
+    pub updated_at_ms: i64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct BotStatus {
+    pub user: String,
+    pub symbols: Vec<SymbolStatus>,
+    /// Events published since the bot started, by kind.
+    #[serde(default)]
+    pub event_counts: BTreeMap<String, u64>,
+    #[serde(default)]
+    pub last_error: Option<String>,
+    /// Rolling ADG and Sharpe from the fill journal, per window.
+    #[serde(default)]
+    pub performance: Vec<RollingMetrics>,
+    /// Account-wide margin usage, at the balance of the most recent symbol update.
+    #[serde(default)]
+    pub margin: AccountMargin,
+    /// Whether the bot only publishes the orders it would place.
+    #[serde(default)]
+    pub read_only: bool,
+    /// Realized PnL from the fill journal, when the bot keeps one.
+    #[serde(default)]
+    pub realized_pnl: Option<RealizedPnl>,
+}
+
+/// Latest status of every running manager, shared between the managers and the
+/// control API.
+#[derive(Clone, Default)]
+pub struct StatusBoard {
+    /// By venue and symbol.
+    symbols: Arc<Mutex<HashMap<(String, String), SymbolStatus>>>,
+    event_counts: Arc<Mutex<BTreeMap<String, u64>>>,
+    last_error: Arc<Mutex<Option<String>>>,
+    performance: Arc<Mutex<Vec<RollingMetrics>>>,
+    realized_pnl: Arc<Mutex<Option<RealizedPnl>>>,
+    read_only: bool,
+}
+
+impl StatusBoard {
+    pub fn with_read_only(mut self, read_only: bool) -> Self {
+        self.read_only = read_only;
+        self
+    }
+
+    pub fn update(&self, status: SymbolStatus) {
+        let mut symbols = self.symbols.lock().unwrap();
+        symbols.insert((status.venue.clone(), status.symbol.clone()), status);
+    }
+
+    pub fn set_performance(&self, performance: Vec<RollingMetrics>) {
+        *self.performance.lock().unwrap() = performance;
+    }
+
+    pub fn set_realized_pnl(&self, realized_pnl: RealizedPnl) {
+        *self.realized_pnl.lock().unwrap() = Some(realized_pnl);
+    }
+
+    /// The journal's realized PnL; `None` without a journal.
+    pub fn realized_pnl(&self) -> Option<RealizedPnl> {
+        self.realized_pnl.lock().unwrap().clone()
+    }
+
+    pub fn get(&self, venue: &str, symbol: &str) -> Option<SymbolStatus> {
+        let key = (venue.to_string(), symbol.to_string());
+        self.symbols.lock().unwrap().get(&key).cloned()
+    }
+
+    pub fn remove(&self, venue: &str, symbol: &str) {
+        let key = (venue.to_string(), symbol.to_string());
+        self.symbols.lock().unwrap().remove(&key);
+    }
+
+    /// Combined position value of all symbols except `symbol` on `venue`.
+    pub fn position_value_excluding(&self, venue: &str, symbol: &str) -> f64 {
+        self.symbols
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|s| s.venue != venue || s.symbol != symbol)
+            .map(|s| (s.position_size * s.position_price).abs())
+            .sum()
+    }
+
+    /// Combined unrealized PnL of all symbols except `symbol` on `venue`.
+    pub fn unrealized_pnl_excluding(&self, venue: &str, symbol: &str) -> f64 {
+        self.symbols
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|s| s.venue != venue || s.symbol != symbol)
+            .map(|s| s.unrealized_pnl)
+            .sum()
+    }
+
+    pub fn snapshot(&self, user: &str) -> BotStatus {
+        let mut symbols: Vec<SymbolStatus> =
+            self.symbols.lock().unwrap().values().cloned().collect();
+        symbols.sort_by(|a, b| (&a.symbol, &a.venue).cmp(&(&b.symbol, &b.venue)));
+        let balance = symbols
+            .iter()
+            .max_by_key(|s| s.updated_at_ms)
+            .map_or(0.0, |s| s.balance);
+        BotStatus {
+            margin: margin::account_margin(balance, &symbols),
+            user: user.to_string(),
+            symbols,
+            event_counts: self.event_counts.lock().unwrap().clone(),
+            last_error: self.last_error.lock().unwrap().clone(),
+            performance: self.performance.lock().unwrap().clone(),
+            read_only: self.read_only,
+            realized_pnl: self.realized_pnl(),
+        }
+    }
+}
+
+impl EventSubscriber for StatusBoard {
+    fn on_event(&mut self, event: &BotEvent) {
+        *self
+            .event_counts
+            .lock()
+            .unwrap()
+            .entry(event.kind().to_string())
+            .or_default() += 1;
+        if let BotEvent::Error { symbol, message } = event {
+            *self.last_error.lock().unwrap() = Some(format!("[{}] {}", symbol, message));
+        }
+    }
+}
+
+/// Serves the control API on `listener`: `GET /status` returns the `BotStatus` as JSON.
+pub async fn serve(listener: TcpListener, user: String, board: StatusBoard) {
+    if let Ok(addr) = listener.local_addr() {
+        info!("Control API listening on {}", addr);
+    }
+    loop {
+        match listener.accept().await {
+            Ok((stream, _)) => {
+                let status = board.snapshot(&user);
+                tokio::spawn(async move {
+                    if let Err(e) = handle_connection(stream, &status).await {
+                        warn!("Control API connection error: {}", e);
+                    }
+                });
+            }
+            Err(e) => warn!("Control API accept error: {}", e),
+        }
+    }
+}
+
+async fn handle_connection(mut stream: TcpStream, status: &BotStatus) -> Result<(), SendSyncError> {
+    let mut buf = vec![0u8; 4096];
+    let n = stream.read(&mut buf).await?;
+    let request = String::from_utf8_lossy(&buf[..n]);
+    let request_line = request.lines().next().unwrap_or_default();
+
+    let (code, body) = if request_line.starts_with("GET /status ") {
+        ("200 OK", serde_json::to_string(status)?)
+    } else {
+        ("404 Not Found", "{}".to_string())
+    };
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        code,
+        body.len(),
+        body
+    );
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await?;
+    Ok(())
+}
+
+pub async fn fetch_status(port: u16) -> Result<BotStatus, SendSyncError> {
+    let url = format!("http://127.0.0.1:{}/status", port);
+    let status = reqwest::get(&url).await?.json::<BotStatus>().await?;
+    Ok(status)
+}
+
+/// Renders a status summary for operators, one line per symbol.
+pub fn format_status(status: &BotStatus) -> String {
+    let fmt_price = |price: Option<f64>| price.map_or("-".to_string(), |p| p.to_string());
+    let mut out = format!(
+        "user: {}{}\n",
+        status.user,
+        if status.read_only { " (read-only)" } else { "" }
+    );
+    let _ = writeln!(
+        out,
+        "{:<14} {:<16} {:<16} {:>12} {:>12} {:>8} {:>20} {:>12} {:>12} {:>20} {:>16} {:>8}",
+        "symbol",
+        "mode long",
+        "mode short",
+        "pos size",
+        "pos price",
+        "WE",
+        "uPnL (funding)",
+        "next entry",
+        "next close",
+        "unstuck budget l/s",
+        "orders k/c/n",
+        "queued"
+    );
+    for s in &status.symbols {
+        let symbol = if s.venue.is_empty() {
+            s.symbol.clone()
+        } else {
+            format!("{}@{}", s.symbol, s.venue)
+        };
+        let _ = writeln!(
+            out,
+            "{:<14} {:<16} {:<16} {:>12} {:>12} {:>8.4} {:>20} {:>12} {:>12} {:>20} {:>16} {:>8}",
+            symbol,
+            s.mode_long,
+            s.mode_short,
+            s.position_size,
+            s.position_price,
+            s.wallet_exposure,
+            // funding is already settled into the balance; shown net to judge the position
+            format!(
+                "{:.2} ({:.2})",
+                s.unrealized_pnl - s.funding_paid,
+                -s.funding_paid
+            ),
+            fmt_price(s.nearest_entry_price),
+            fmt_price(s.nearest_close_price),
+            format!(
+                "{:.2}/{:.2}",
+                s.unstuck_allowance_long, s.unstuck_allowance_short
+            ),
+            format!(
+                "{}/{}/{}",
+                s.last_order_diff.kept.len(),
+                s.last_order_diff.to_cancel.len(),
+                s.last_order_diff.to_create.len()
+            ),
+            s.order_queue_depth
+        );
+    }
+    if status.symbols.is_empty() {
+        out.push_str("no active symbols\n");
+    } else {
+        let m = &status.margin;
+        let share = |margin: f64| {
+            if m.equity > 0.0 {
+                margin / m.equity * 100.0
+            } else {
+                0.0
+            }
+        };
+        let symbol_margins: Vec<String> = status
+            .symbols
+            .iter()
+            .filter(|s| s.margin != SymbolMargin::default())
+            .map(|s| {
+                format!(
+                    "{} {:.1}%/{:.1}%",
+                    s.symbol,
+                    share(s.margin.initial_margin + s.margin.order_margin),
+                    share(s.margin.maintenance_margin)
+                )
+            })
+            .collect();
+        let _ = writeln!(
+            out,
+            "margin: initial {:.1}%, maintenance {:.1}% of equity {:.2}; headroom {:.2} \
+            ({:.2} of position value){}",
+            m.initial_margin_usage * 100.0,
+            m.maintenance_margin_usage * 100.0,
+            m.equity,
+            m.headroom,
+            m.headroom_position_value,
+            if symbol_margins.is_empty() {
+                String::new()
+            } else {
+                format!(
+                    "; initial/maintenance by symbol: {}",
+                    symbol_margins.join(", ")
+                )
+            }
+        );
+    }
+    if !status.event_counts.is_empty() {
+        let counts: Vec<String> = status
+            .event_counts
+            .iter()
+            .map(|(kind, count)| format!("{} {}", kind, count))
+            .collect();
+        let _ = writeln!(out, "events: {}", counts.join(", "));
+    }
+    for p in &status.performance {
+        let _ = writeln!(
+            out,
+            "{}d: adg {:.3}%, sharpe {:.2}, {} fills{}",
+            p.window_days,
+            p.adg * 100.0,
+            p.sharpe,
+            p.n_fills,
+            if p.degraded.is_empty() {
+                String::new()
+            } else {
+                format!(" (below baseline: {})", p.degraded.join(", "))
+            }
+        );
+    }
+    if let Some(pnl) = &status.realized_pnl {
+        let _ = writeln!(
+            out,
+            "realized pnl: {:.2} ({:.2} at peak), by day:",
+            pnl.pnl_cumsum_last, pnl.pnl_cumsum_max
+        );
+        out.push_str(&journal::format_daily_pnls(&pnl.daily));
+    }
+    if let Some(error) = &status.last_error {
+        let _ = writeln!(out, "last error: {}", error);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_status_roundtrip() {
+        let board = StatusBoard::default();
+        board.update(SymbolStatus {
+            symbol: "BTCUSDT".to_string(),
+            mode_long: "Normal".to_string(),
+            mode_short: "TakeProfitOnly".to_string(),
+            position_size: 0.5,
+            position_price: 100.0,
+            nearest_close_price: Some(101.0),
+            order_queue_depth: 3,
+            ..Default::default()
+        });
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(serve(listener, "test_user".to_string(), board.clone()));
+
+        let status = fetch_status(port).await.unwrap();
+        assert_eq!(status.user, "test_user");
+        assert_eq!(status.symbols.len(), 1);
+        assert_eq!(status.symbols[0].nearest_close_price, Some(101.0));
+        assert_eq!(status.symbols[0].order_queue_depth, 3);
+
+        let summary = format_status(&status);
+        assert!(summary.contains("BTCUSDT"));
+        assert!(summary.contains("TakeProfitOnly"));
+
+        // the same symbol on another venue is a separate entry
+        board.update(SymbolStatus {
+            symbol: "BTCUSDT".to_string(),
+            venue: "other_user".to_string(),
+            position_size: -1.0,
+            position_price: 100.0,
+            ..Default::default()
+        });
+        assert_eq!(
+            board.get("other_user", "BTCUSDT").unwrap().position_size,
+            -1.0
+        );
+        assert_eq!(board.position_value_excluding("", "BTCUSDT"), 100.0);
+        assert!(format_status(&board.snapshot("test_user")).contains("BTCUSDT@other_user"));
+
+        board.remove("", "BTCUSDT");
+        board.remove("other_user", "BTCUSDT");
+        assert!(fetch_status(port).await.unwrap().symbols.is_empty());
+    }
+}
@@ -1,73 +1,582 @@
 use crate::types::{BotConfig, LiveConfig};
-use ndarray::Array2;
+use ndarray::{Array2, Array3};
+use std::collections::VecDeque;
 use tracing::info;
 use csv;
 use crate::exchange::SendSyncError;
-use chrono::{NaiveDateTime, Utc};
+use chrono::{DateTime, NaiveDate, Utc};
+use std::fs::File;
 
-pub async fn prepare_hlcvs(
-    _config: &BotConfig, _exchange_config: &LiveConfig, symbol: &str, start_date: Option<&str>,
-    end_date: Option<&str>,
-) -> Result<Array2<f64>, SendSyncError> {
-    info!("Preparing HLCV data for {} from local file...", symbol);
+/// Parses a date into a millisecond timestamp. Accepts `%Y-%m-%d` (midnight UTC), `now`
+/// and `now-<timeframe>` such as `now-90d` or `now-12h`.
+pub fn parse_date_ms(date: &str) -> Option<u64> {
+    parse_date_ms_at(date, Utc::now().timestamp_millis() as u64)
+}
 
-    let start_ts = start_date
-        .and_then(|s| NaiveDateTime::parse_from_str(s, "%Y-%m-%d").ok())
-        .map(|dt| dt.and_local_timezone(Utc).unwrap().timestamp_millis() as u64);
+/// Like [`parse_date_ms`], with `now` given as a millisecond timestamp.
+pub fn parse_date_ms_at(date: &str, now_ms: u64) -> Option<u64> {
+    let date = date.trim();
+    if date == "now" {
+        return Some(now_ms);
+    }
+    if let Some(offset) = date.strip_prefix("now-") {
+        return now_ms.checked_sub(parse_timeframe_ms(offset)?);
+    }
+    NaiveDate::parse_from_str(date, "%Y-%m-%d")
+        .ok()
+        .and_then(|d| d.and_hms_opt(0, 0, 0))
+        .map(|dt| dt.and_utc().timestamp_millis() as u64)
+}
 
-    let end_ts = end_date
-        .and_then(|s| NaiveDateTime::parse_from_str(s, "%Y-%m-%d").ok())
-        .map(|dt| dt.and_local_timezone(Utc).unwrap().timestamp_millis() as u64);
+/// Parses a date like [`parse_date_ms`] and returns the UTC day it falls on.
+pub fn parse_date_day(date: &str) -> Option<NaiveDate> {
+    DateTime::from_timestamp_millis(parse_date_ms(date)? as i64).map(|dt| dt.date_naive())
+}
 
-    let file_path = format!("data/{}_1m.csv", symbol);
-    let mut rdr = csv::Reader::from_path(file_path).map_err(|e| Box::new(e) as SendSyncError)?;
+/// Candle timestamps (ms) alongside their HLCV rows.
+pub type HlcvChunk = (Vec<u64>, Array2<f64>);
 
-    let mut hlcvs = Vec::new();
-    for result in rdr.records() {
-        let record = result.map_err(|e| Box::new(e) as SendSyncError)?;
-        let timestamp: u64 = record[0]
-            .parse()
+/// Reads HLCVs at `backtest.resolution` from a local CSV file in chunks of `chunk_size` rows,
+/// so only one window of candles per symbol needs to be held in memory.
+/// A chunk size of 0 reads the whole file in one chunk.
+pub struct HlcvReader {
+    records: csv::StringRecordsIntoIter<File>,
+    start_ts: Option<u64>,
+    end_ts: Option<u64>,
+    chunk_size: usize,
+}
+
+impl HlcvReader {
+    pub fn open(
+        config: &BotConfig, symbol: &str, start_date: Option<&str>, end_date: Option<&str>,
+        chunk_size: usize,
+    ) -> Result<Self, SendSyncError> {
+        let start_ts = start_date.and_then(parse_date_ms);
+        let end_ts = end_date.and_then(parse_date_ms);
+
+        let file_path = format!(
+            "{}/{}_{}.csv",
+            config.backtest.data_dir(),
+            symbol,
+            config.backtest.resolution
+        );
+        let rdr = csv::Reader::from_path(file_path).map_err(|e| Box::new(e) as SendSyncError)?;
+
+        let chunk_size = match chunk_size {
+            0 => usize::MAX,
+            n => n,
+        };
+
+        Ok(Self {
+            records: rdr.into_records(),
+            start_ts,
+            end_ts,
+            chunk_size,
+        })
+    }
+
+    /// Returns the next chunk of timestamps and HLCV rows, or `None` once the file is exhausted.
+    pub fn next_chunk(&mut self) -> Result<Option<HlcvChunk>, SendSyncError> {
+        let mut timestamps = Vec::new();
+        let mut hlcvs = Vec::new();
+        while timestamps.len() < self.chunk_size {
+            let record = match self.records.next() {
+                Some(result) => result.map_err(|e| Box::new(e) as SendSyncError)?,
+                None => break,
+            };
+            let timestamp: u64 = record[0]
+                .parse()
+                .map_err(|e| Box::new(e) as SendSyncError)?;
+
+            if let Some(start) = self.start_ts {
+                if timestamp < start {
+                    continue;
+                }
+            }
+            if let Some(end) = self.end_ts {
+                if timestamp > end {
+                    continue;
+                }
+            }
+
+            timestamps.push(timestamp);
+            hlcvs.push([
+                record[2]
+                    .parse()
+                    .map_err(|e| Box::new(e) as SendSyncError)?, // high
+                record[3]
+                    .parse()
+                    .map_err(|e| Box::new(e) as SendSyncError)?, // low
+                record[4]
+                    .parse()
+                    .map_err(|e| Box::new(e) as SendSyncError)?, // close
+                record[5]
+                    .parse()
+                    .map_err(|e| Box::new(e) as SendSyncError)?, // volume
+                record[4]
+                    .parse()
+                    .map_err(|e| Box::new(e) as SendSyncError)?, // close (again, for the 5th column)
+            ]);
+        }
+
+        if timestamps.is_empty() {
+            return Ok(None);
+        }
+
+        let hlcvs = Array2::from_shape_vec((hlcvs.len(), 5), hlcvs.into_iter().flatten().collect())
             .map_err(|e| Box::new(e) as SendSyncError)?;
 
-        if let Some(start) = start_ts {
-            if timestamp < start {
-                continue;
+        Ok(Some((timestamps, hlcvs)))
+    }
+}
+
+/// Candles of several symbols aligned on one time axis, so a backtest can step through
+/// all of them at once. Steps are handed out in windows of `backtest.candle_chunk_size`
+/// rows, so only one window per symbol is held in memory. Candle files must be sorted by
+/// time, as the downloader writes them.
+pub struct HlcvMatrix {
+    pub start_ms: u64,
+    pub step_ms: u64,
+    /// First and last candle timestamp of each symbol, or `None` if it has no data.
+    pub ranges: Vec<Option<(u64, u64)>>,
+    n_steps: usize,
+    window_steps: usize,
+    next_step: usize,
+    readers: Vec<HlcvReader>,
+    /// Rows read from each symbol's file that fall after the current window.
+    pending: Vec<VecDeque<(u64, [f64; 4])>>,
+}
+
+impl HlcvMatrix {
+    /// Scans the candles of `symbols` between `backtest.start_date` and `end_date` for
+    /// their time ranges, then opens them again to be stepped through by `next_window`.
+    /// A `backtest.candle_chunk_size` of 0 steps through the whole range in one window.
+    pub fn load(config: &BotConfig, symbols: &[String]) -> Result<Self, SendSyncError> {
+        let step_ms = config.backtest.step_ms();
+        let chunk_size = config.backtest.candle_chunk_size;
+        let open = |symbol: &String| {
+            HlcvReader::open(
+                config,
+                symbol,
+                Some(&config.backtest.start_date),
+                Some(&config.backtest.end_date),
+                chunk_size,
+            )
+        };
+        let mut ranges = Vec::with_capacity(symbols.len());
+        for symbol in symbols {
+            let mut reader = open(symbol)?;
+            let mut range: Option<(u64, u64)> = None;
+            while let Some((timestamps, _)) = reader.next_chunk()? {
+                for timestamp in timestamps {
+                    range = Some(match range {
+                        Some((first, last)) => (first.min(timestamp), last.max(timestamp)),
+                        None => (timestamp, timestamp),
+                    });
+                }
             }
+            ranges.push(range);
         }
-        if let Some(end) = end_ts {
-            if timestamp > end {
-                continue;
+        let start_ms = ranges.iter().flatten().map(|r| r.0).min().unwrap_or(0);
+        let end_ms = ranges.iter().flatten().map(|r| r.1).max().unwrap_or(0);
+        let n_steps = if ranges.iter().any(Option::is_some) {
+            ((end_ms - start_ms) / step_ms) as usize + 1
+        } else {
+            0
+        };
+        let readers = symbols.iter().map(open).collect::<Result<Vec<_>, _>>()?;
+        Ok(Self {
+            start_ms,
+            step_ms,
+            ranges,
+            n_steps,
+            window_steps: match chunk_size {
+                0 => n_steps.max(1),
+                n => n,
+            },
+            next_step: 0,
+            pending: vec![VecDeque::new(); readers.len()],
+            readers,
+        })
+    }
+
+    pub fn n_steps(&self) -> usize {
+        self.n_steps
+    }
+
+    /// Reads the next window of steps, or returns `None` once all steps were handed out.
+    pub fn next_window(&mut self) -> Result<Option<HlcvWindow>, SendSyncError> {
+        if self.next_step >= self.n_steps {
+            return Ok(None);
+        }
+        let n_steps = self.window_steps.min(self.n_steps - self.next_step);
+        let start_ms = self.start_ms + self.next_step as u64 * self.step_ms;
+        let end_ms = start_ms + n_steps as u64 * self.step_ms;
+        let mut hlcvs = Array3::from_elem((n_steps, self.readers.len(), 4), f64::NAN);
+        for (j, (reader, pending)) in self.readers.iter_mut().zip(&mut self.pending).enumerate() {
+            loop {
+                while let Some(&(timestamp, row)) = pending.front() {
+                    if timestamp >= end_ms {
+                        break;
+                    }
+                    pending.pop_front();
+                    // only out of order rows fall before the window
+                    if timestamp < start_ms {
+                        continue;
+                    }
+                    let i = ((timestamp - start_ms) / self.step_ms) as usize;
+                    for (k, value) in row.into_iter().enumerate() {
+                        hlcvs[[i, j, k]] = value;
+                    }
+                }
+                if !pending.is_empty() {
+                    break;
+                }
+                match reader.next_chunk()? {
+                    Some((timestamps, rows)) => pending.extend(
+                        timestamps
+                            .into_iter()
+                            .zip(rows.outer_iter())
+                            .map(|(timestamp, row)| (timestamp, [row[0], row[1], row[2], row[3]])),
+                    ),
+                    None => break,
+                }
             }
         }
+        self.next_step += n_steps;
+        Ok(Some(HlcvWindow {
+            start_ms,
+            step_ms: self.step_ms,
+            hlcvs,
+        }))
+    }
+}
 
-        hlcvs.push([
-            record[2]
-                .parse()
-                .map_err(|e| Box::new(e) as SendSyncError)?, // high
-            record[3]
-                .parse()
-                .map_err(|e| Box::new(e) as SendSyncError)?, // low
-            record[4]
-                .parse()
-                .map_err(|e| Box::new(e) as SendSyncError)?, // close
-            record[5]
-                .parse()
-                .map_err(|e| Box::new(e) as SendSyncError)?, // volume
-            record[4]
-                .parse()
-                .map_err(|e| Box::new(e) as SendSyncError)?, // close (again, for the 5th column)
-        ]);
+/// A run of consecutive steps of an `HlcvMatrix`. Row `i` holds the candles at
+/// `timestamp(i)`; symbols without a candle at that time, before their listing or in gaps
+/// of their data, hold NaN.
+pub struct HlcvWindow {
+    pub start_ms: u64,
+    pub step_ms: u64,
+    /// Indexed by (step, symbol, [high, low, close, volume]).
+    pub hlcvs: Array3<f64>,
+}
+
+impl HlcvWindow {
+    pub fn n_steps(&self) -> usize {
+        self.hlcvs.shape()[0]
+    }
+
+    pub fn timestamp(&self, step: usize) -> u64 {
+        self.start_ms + step as u64 * self.step_ms
+    }
+
+    /// High, low and close of `symbol` (its index) at `step`, if it has a candle there.
+    pub fn candle(&self, step: usize, symbol: usize) -> Option<(f64, f64, f64)> {
+        let close = self.hlcvs[[step, symbol, 2]];
+        (!close.is_nan()).then(|| {
+            (
+                self.hlcvs[[step, symbol, 0]],
+                self.hlcvs[[step, symbol, 1]],
+                close,
+            )
+        })
+    }
+
+    /// Base volume of `symbol` at `step`; 0 without a candle.
+    pub fn volume(&self, step: usize, symbol: usize) -> f64 {
+        let volume = self.hlcvs[[step, symbol, 3]];
+        if volume.is_nan() {
+            0.0
+        } else {
+            volume
+        }
     }
+}
+
+/// Path of `symbol`'s funding rates, as `timestamp,funding_rate` rows.
+pub fn funding_path(config: &BotConfig, symbol: &str) -> String {
+    format!("{}/{}_funding.csv", config.backtest.data_dir(), symbol)
+}
+
+/// Reads `symbol`'s historical funding rates, as (settlement time in ms, rate) sorted by
+/// time.
+pub fn load_funding_rates(
+    config: &BotConfig, symbol: &str,
+) -> Result<Vec<(u64, f64)>, SendSyncError> {
+    let mut rdr = csv::Reader::from_path(funding_path(config, symbol))
+        .map_err(|e| Box::new(e) as SendSyncError)?;
+    let mut rates = Vec::new();
+    for record in rdr.records() {
+        let record = record.map_err(|e| Box::new(e) as SendSyncError)?;
+        let timestamp: u64 = record[0]
+            .parse()
+            .map_err(|e| Box::new(e) as SendSyncError)?;
+        let rate: f64 = record[1]
+            .parse()
+            .map_err(|e| Box::new(e) as SendSyncError)?;
+        rates.push((timestamp, rate));
+    }
+    rates.sort_by_key(|(timestamp, _)| *timestamp);
+    Ok(rates)
+}
+
+pub async fn prepare_hlcvs(
+    config: &BotConfig, _exchange_config: &LiveConfig, symbol: &str, start_date: Option<&str>,
+    end_date: Option<&str>,
+) -> Result<HlcvChunk, SendSyncError> {
+    info!("Preparing HLCV data for {} from local file...", symbol);
+
+    let mut reader = HlcvReader::open(config, symbol, start_date, end_date, 0)?;
 
-    if hlcvs.is_empty() {
-        return Err(Box::new(std::io::Error::new(
+    match reader.next_chunk()? {
+        Some(chunk) => Ok(chunk),
+        None => Err(Box::new(std::io::Error::new(
             std::io::ErrorKind::NotFound,
             "No data found for the specified date range",
-        )));
+        ))),
     }
+}
 
-    let hlcvs = Array2::from_shape_vec((hlcvs.len(), 5), hlcvs.into_iter().flatten().collect())
-        .map_err(|e| Box::new(e) as SendSyncError)?;
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Candle {
+    pub timestamp: u64,
+    pub open: f64,
+    pub high: f64,
+    pub low: f64,
+    pub close: f64,
+    pub volume: f64,
+}
+
+/// Parses a timeframe such as `1s`, `15m`, `1h` or `1d` into milliseconds.
+pub fn parse_timeframe_ms(timeframe: &str) -> Option<u64> {
+    let (split, unit) = timeframe.char_indices().last()?;
+    let value: u64 = timeframe[..split].parse().ok()?;
+    let unit_ms = match unit {
+        's' => 1_000,
+        'm' => 60_000,
+        'h' => 3_600_000,
+        'd' => 86_400_000,
+        _ => return None,
+    };
+    Some(value * unit_ms).filter(|&ms| ms > 0)
+}
+
+/// Aggregates 1m candles into `timeframe_ms` buckets aligned to multiples of the
+/// timeframe since the Unix epoch (UTC, so unaffected by DST). Open is the first
+/// open, close the last close, high/low the extremes and volume the sum of the bucket.
+/// Candles must be sorted by timestamp; gaps simply produce fewer or partial buckets.
+pub fn resample_candles(candles: &[Candle], timeframe_ms: u64) -> Vec<Candle> {
+    let mut resampled: Vec<Candle> = Vec::new();
+    for candle in candles {
+        let bucket = candle.timestamp - candle.timestamp % timeframe_ms;
+        match resampled.last_mut() {
+            Some(last) if last.timestamp == bucket => {
+                last.high = last.high.max(candle.high);
+                last.low = last.low.min(candle.low);
+                last.close = candle.close;
+                last.volume += candle.volume;
+            }
+            _ => resampled.push(Candle {
+                timestamp: bucket,
+                ..*candle
+            }),
+        }
+    }
+    resampled
+}
+
+/// Same as `resample_candles` for the backtester's HLCV layout
+/// (high, low, close, volume, close).
+pub fn resample_hlcvs(timestamps: &[u64], hlcvs: &Array2<f64>, timeframe_ms: u64) -> HlcvChunk {
+    let candles: Vec<Candle> = timestamps
+        .iter()
+        .zip(hlcvs.outer_iter())
+        .map(|(&timestamp, row)| Candle {
+            timestamp,
+            open: row[2],
+            high: row[0],
+            low: row[1],
+            close: row[2],
+            volume: row[3],
+        })
+        .collect();
+    let resampled = resample_candles(&candles, timeframe_ms);
+    let timestamps = resampled.iter().map(|c| c.timestamp).collect();
+    let rows: Vec<f64> = resampled
+        .iter()
+        .flat_map(|c| [c.high, c.low, c.close, c.volume, c.close])
+        .collect();
+    let hlcvs = Array2::from_shape_vec((resampled.len(), 5), rows)
+        .expect("resampled rows always have 5 columns");
+    (timestamps, hlcvs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn minute_candles(start: u64, n: usize) -> Vec<Candle> {
+        (0..n)
+            .map(|i| {
+                let price = 100.0 + i as f64;
+                Candle {
+                    timestamp: start + i as u64 * 60_000,
+                    open: price,
+                    high: price + 0.5,
+                    low: price - 0.5,
+                    close: price + 0.25,
+                    volume: 1.0,
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_hlcv_chunks_join_into_a_single_read() {
+        let config = crate::config::load_config("tests/fixtures/backtest_config.hjson").unwrap();
+        let read = |chunk_size: usize| {
+            let mut reader = HlcvReader::open(&config, "BTCUSDT", None, None, chunk_size).unwrap();
+            let mut chunks = Vec::new();
+            while let Some(chunk) = reader.next_chunk().unwrap() {
+                chunks.push(chunk);
+            }
+            chunks
+        };
+        let single = read(0);
+        assert_eq!(single.len(), 1);
+        let (timestamps, hlcvs) = &single[0];
+        assert_eq!((timestamps.len(), hlcvs.nrows()), (240, 240));
+
+        // one row per chunk, a partial last chunk of 240 % 7, exactly one full chunk and
+        // a chunk larger than the file
+        for chunk_size in [1, 7, 240, 1000] {
+            let chunks = read(chunk_size);
+            assert_eq!(
+                chunks.len(),
+                240_usize.div_ceil(chunk_size),
+                "{}",
+                chunk_size
+            );
+            assert!(chunks[..chunks.len() - 1]
+                .iter()
+                .all(|(timestamps, _)| timestamps.len() == chunk_size));
+            let joined_timestamps: Vec<u64> = chunks
+                .iter()
+                .flat_map(|(timestamps, _)| timestamps.iter().copied())
+                .collect();
+            let joined_rows: Vec<f64> = chunks
+                .iter()
+                .flat_map(|(_, hlcvs)| hlcvs.iter().copied())
+                .collect();
+            assert_eq!(&joined_timestamps, timestamps, "{}", chunk_size);
+            assert_eq!(
+                joined_rows,
+                hlcvs.iter().copied().collect::<Vec<f64>>(),
+                "{}",
+                chunk_size
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_relative_dates() {
+        let now_ms = parse_date_ms("2024-03-10").unwrap() + 5 * 3_600_000;
+        assert_eq!(parse_date_ms_at("now", now_ms), Some(now_ms));
+        assert_eq!(
+            parse_date_ms_at("now-90d", now_ms),
+            Some(now_ms - 90 * 86_400_000)
+        );
+        assert_eq!(
+            parse_date_ms_at(" now-12h ", now_ms),
+            Some(now_ms - 12 * 3_600_000)
+        );
+        assert_eq!(
+            parse_date_ms_at("2024-01-01", now_ms),
+            parse_date_ms("2024-01-01")
+        );
+        assert_eq!(parse_date_ms_at("now-90", now_ms), None);
+        assert_eq!(parse_date_ms_at("now+1d", now_ms), None);
+        assert_eq!(parse_date_ms_at("01/01/2024", now_ms), None);
+    }
 
-    Ok(hlcvs)
+    #[test]
+    fn test_parse_timeframe_ms() {
+        assert_eq!(parse_timeframe_ms("5m"), Some(300_000));
+        assert_eq!(parse_timeframe_ms("1h"), Some(3_600_000));
+        assert_eq!(parse_timeframe_ms("1d"), Some(86_400_000));
+        assert_eq!(parse_timeframe_ms("1s"), Some(1_000));
+        assert_eq!(parse_timeframe_ms("0m"), None);
+        assert_eq!(parse_timeframe_ms("h"), None);
+        assert_eq!(parse_timeframe_ms("1µ"), None);
+        assert_eq!(parse_timeframe_ms(""), None);
+    }
+
+    #[test]
+    fn test_resample_ohlcv_semantics_and_alignment() {
+        // 2023-01-01 00:03 UTC, not aligned to 5m
+        let start = parse_date_ms("2023-01-01").unwrap() + 3 * 60_000;
+        let candles = minute_candles(start, 7);
+        let resampled = resample_candles(&candles, 300_000);
+
+        assert_eq!(resampled.len(), 2);
+        assert_eq!(resampled[0].timestamp, start - 3 * 60_000);
+        assert_eq!(resampled[0].open, 100.0);
+        assert_eq!(resampled[0].close, 101.25);
+        assert_eq!(resampled[0].volume, 2.0);
+        assert_eq!(resampled[1].timestamp, start + 2 * 60_000);
+        assert_eq!(resampled[1].open, 102.0);
+        assert_eq!(resampled[1].high, 106.5);
+        assert_eq!(resampled[1].low, 101.5);
+        assert_eq!(resampled[1].close, 106.25);
+        assert_eq!(resampled[1].volume, 5.0);
+    }
+
+    #[test]
+    fn test_resample_across_day_and_dst_boundaries() {
+        // US and EU DST transitions happen around these dates; UTC buckets must not shift.
+        for date in ["2023-03-12", "2023-03-26", "2023-10-29"] {
+            let midnight = parse_date_ms(date).unwrap();
+            let candles = minute_candles(midnight - 90 * 60_000, 180);
+
+            let hourly = resample_candles(&candles, 3_600_000);
+            assert_eq!(hourly.len(), 4);
+            assert!(hourly.iter().all(|c| c.timestamp % 3_600_000 == 0));
+            assert_eq!(hourly[2].timestamp, midnight);
+            assert_eq!(hourly[2].volume, 60.0);
+
+            let daily = resample_candles(&candles, 86_400_000);
+            assert_eq!(daily.len(), 2);
+            assert_eq!(daily[1].timestamp, midnight);
+            assert_eq!(daily[0].volume + daily[1].volume, 180.0);
+        }
+    }
+
+    #[test]
+    fn test_matrix_windows_match_whole_range() {
+        let mut config =
+            crate::config::load_config("tests/fixtures/backtest_config.hjson").unwrap();
+        let symbols = vec!["BTCUSDT".to_string()];
+        let collect = |config: &BotConfig| {
+            let mut matrix = HlcvMatrix::load(config, &symbols).unwrap();
+            let mut rows = Vec::new();
+            while let Some(window) = matrix.next_window().unwrap() {
+                for i in 0..window.n_steps() {
+                    rows.push((
+                        window.timestamp(i),
+                        window.candle(i, 0),
+                        window.volume(i, 0),
+                    ));
+                }
+            }
+            (matrix.n_steps(), rows)
+        };
+        config.backtest.candle_chunk_size = 0;
+        let (n_steps, whole) = collect(&config);
+        config.backtest.candle_chunk_size = 7;
+        let (_, windowed) = collect(&config);
+
+        assert_eq!(whole.len(), n_steps);
+        assert_eq!(windowed, whole);
+    }
 }
@@ -1,11 +1,17 @@
+use crate::data;
+use crate::exchange::Exchange;
+use crate::listings::ListingRegistry;
+use crate::universe::{self, MarketSnapshot};
 use crate::types::{BotConfig, ExchangeConfig};
 use anyhow::{anyhow, Result};
 use bytes::Bytes;
-use chrono::{NaiveDate, Utc};
-use csv::ReaderBuilder;
-use futures::future::join_all;
+use chrono::{Days, NaiveDate, Utc};
+use csv::{ReaderBuilder, WriterBuilder};
+use futures::stream::{self, StreamExt};
 use ndarray::Array2;
-use ndarray_npy::WriteNpyExt;
+use ndarray_npy::{ReadNpyExt, WriteNpyExt};
+use serde::Deserialize;
+use std::collections::HashMap;
 use std::fs::{self, File};
 use std::io::{Cursor, Read};
 use std::path::{Path, PathBuf};
@@ -13,19 +19,88 @@ use tokio::time::{sleep, Duration};
 use tracing::{error, info, warn};
 use zip::ZipArchive;
 
+/// One file of 1m candles of a symbol: a month, or a day of a month without a monthly file.
+#[derive(Debug, Clone, PartialEq)]
+struct Chunk {
+    url: String,
+    path: PathBuf,
+}
+
+/// Funding settlements fetched per request.
+const BINANCE_FUNDING_LIMIT: usize = 1000;
+const BYBIT_FUNDING_LIMIT: usize = 200;
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BinanceFundingRate {
+    funding_time: u64,
+    funding_rate: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BybitFundingResponse {
+    ret_code: i32,
+    ret_msg: String,
+    result: BybitFundingResult,
+}
+
+#[derive(Deserialize)]
+struct BybitFundingResult {
+    list: Vec<BybitFundingRate>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BybitFundingRate {
+    funding_rate: String,
+    funding_rate_timestamp: String,
+}
+
 pub struct Downloader {
     pub config: BotConfig,
+    /// Exchanges whose market snapshots are refreshed to resolve symbol presets.
+    pub market_sources: HashMap<String, Box<dyn Exchange>>,
 }
 
 impl Downloader {
     pub fn new(config: BotConfig) -> Self {
-        Downloader { config }
+        Downloader {
+            config,
+            market_sources: HashMap::new(),
+        }
+    }
+
+    pub fn with_market_source(mut self, exchange_name: &str, exchange: Box<dyn Exchange>) -> Self {
+        self.market_sources
+            .insert(exchange_name.to_string(), exchange);
+        self
     }
 
     pub async fn start(&self) -> Result<()> {
         info!("Starting downloader...");
 
-        let backtest_config = &self.config.backtest;
+        for (exchange_name, exchange) in &self.market_sources {
+            info!("Refreshing market snapshot of {}", exchange_name);
+            let snapshot = MarketSnapshot::fetch(exchange.as_ref())
+                .await
+                .map_err(|e| anyhow!("Failed to fetch markets of {}: {}", exchange_name, e))?;
+            snapshot.save(exchange_name).map_err(|e| anyhow!(e))?;
+            let path = ListingRegistry::path(exchange_name);
+            let mut listings = ListingRegistry::load(&path);
+            for m in &snapshot.symbols {
+                listings.record_listing(&m.symbol, m.created_at_ms);
+            }
+            listings.save(&path).map_err(|e| anyhow!(e))?;
+        }
+        let mut backtest_config = self.config.backtest.clone();
+        backtest_config.validate().map_err(|e| anyhow!(e))?;
+        universe::resolve_backtest_symbols(
+            &mut backtest_config,
+            self.config.live.minimum_coin_age_days,
+        )
+        .map_err(|e| anyhow!(e))?;
+        let backtest_config = &backtest_config;
 
         for (exchange_name, exchange_config) in &backtest_config.exchanges {
             info!("Downloading data for exchange: {}", exchange_name);
@@ -55,10 +130,22 @@ impl Downloader {
                     )
                     .await?
                 }
-                _ => warn!(
-                    "Exchange '{}' is not supported by the downloader.",
-                    exchange_name
-                ),
+                _ => {
+                    warn!(
+                        "Exchange '{}' is not supported by the downloader.",
+                        exchange_name
+                    );
+                    continue;
+                }
+            }
+            if backtest_config.include_funding {
+                self.download_funding_rates(
+                    exchange_name,
+                    symbols,
+                    &backtest_config.start_date,
+                    &backtest_config.end_date,
+                )
+                .await?;
             }
         }
 
@@ -67,86 +154,130 @@ impl Downloader {
     }
 
     async fn download_binance_data(
-        &self, symbols: &Vec<String>, exchange_config: &ExchangeConfig, start_date_str: &str,
+        &self, symbols: &[String], exchange_config: &ExchangeConfig, start_date_str: &str,
         end_date_str: &str,
     ) -> Result<()> {
-        let start_date = NaiveDate::parse_from_str(start_date_str, "%Y-%m-%d")?;
-        let end_date = NaiveDate::parse_from_str(end_date_str, "%Y-%m-%d")?;
+        let start_date = data::parse_date_day(start_date_str)
+            .ok_or_else(|| anyhow!("Invalid start date: {}", start_date_str))?;
+        // today's candles are incomplete, so stop at the latest complete day
+        let latest_complete_day = Utc::now().date_naive() - Days::new(1);
+        let end_date = if end_date_str.is_empty() {
+            latest_complete_day
+        } else {
+            data::parse_date_day(end_date_str)
+                .ok_or_else(|| anyhow!("Invalid end date: {}", end_date_str))?
+                .min(latest_complete_day)
+        };
         let market_type = if exchange_config.spot {
             "spot"
         } else {
             "futures/um"
         };
+        let listings_path = ListingRegistry::path("binance");
+        let mut listings = ListingRegistry::load(&listings_path);
+        let start_ms = data::parse_date_ms(start_date_str).unwrap_or_default() as i64;
 
-        for symbol in symbols {
-            info!("Downloading Binance data for {}", symbol);
-            let dir_path = PathBuf::from(format!(
-                "historical_data/ohlcvs_{}/{}/",
-                if exchange_config.spot {
-                    "spot"
-                } else {
-                    "futures"
-                },
-                symbol
-            ));
-            fs::create_dir_all(&dir_path)?;
-
-            let (months, days) = self.get_date_ranges(start_date, end_date);
-
-            // Download monthly data
-            let mut tasks = vec![];
-            for month in months {
-                let month_path = dir_path.join(format!("{}.npy", month));
-                if !month_path.exists() {
-                    let url = format!(
-                        "https://data.binance.vision/data/{}/monthly/klines/{}/1m/{}-1m-{}.zip",
-                        market_type, symbol, symbol, month
-                    );
-                    tasks.push(self.download_and_process_zip(url, month_path.clone()));
-                }
-            }
-            join_all(tasks).await;
-
-            // Download daily data
-            let mut tasks = vec![];
-            for day in days {
-                let day_path = dir_path.join(format!("{}.npy", day));
-                let month_of_day = &day[0..7];
-                let month_path = dir_path.join(format!("{}.npy", month_of_day));
-                if !day_path.exists() && !month_path.exists() {
-                    let url = format!(
-                        "https://data.binance.vision/data/{}/daily/klines/{}/1m/{}-1m-{}.zip",
-                        market_type, symbol, symbol, day
-                    );
-                    tasks.push(self.download_and_process_zip(url, day_path.clone()));
+        let dir_paths: Vec<(&String, PathBuf)> = symbols
+            .iter()
+            .map(|symbol| {
+                let dir_path = PathBuf::from(format!(
+                    "historical_data/ohlcvs_{}/{}/",
+                    if exchange_config.spot {
+                        "spot"
+                    } else {
+                        "futures"
+                    },
+                    symbol
+                ));
+                fs::create_dir_all(&dir_path)?;
+                Ok((symbol, dir_path))
+            })
+            .collect::<Result<_>>()?;
+        let (months, days) = self.get_date_ranges(start_date, end_date);
+
+        // months first, so days are only fetched for months Binance has not published yet
+        for (period, names) in [("monthly", &months), ("daily", &days)] {
+            let chunks: Vec<Chunk> = dir_paths
+                .iter()
+                .flat_map(|(symbol, dir_path)| {
+                    missing_chunks(dir_path, names)
+                        .into_iter()
+                        .map(move |path| {
+                            let name = path.file_stem().unwrap_or_default().to_string_lossy();
+                            Chunk {
+                                url: format!(
+                                "https://data.binance.vision/data/{}/{}/klines/{}/1m/{}-1m-{}.zip",
+                                market_type, period, symbol, symbol, name
+                            ),
+                                path,
+                            }
+                        })
+                })
+                .collect();
+            self.download_chunks(period, chunks).await;
+        }
+
+        for (symbol, dir_path) in &dir_paths {
+            self.cleanup_daily_files(dir_path)?;
+            // data beginning after the start date begins at the listing
+            if let Some(first_ms) = first_candle_ms(dir_path) {
+                if first_ms > start_ms && listings.record_candle(symbol, first_ms) {
+                    info!("{} has data since {}", symbol, first_ms);
                 }
             }
-            join_all(tasks).await;
-
-            self.cleanup_daily_files(&dir_path)?;
         }
+        listings.save(&listings_path).map_err(|e| anyhow!(e))?;
         Ok(())
     }
 
-    async fn download_and_process_zip(&self, url: String, npy_path: PathBuf) -> Result<()> {
-        info!("Fetching {}", url);
-        match reqwest::get(&url).await {
-            Ok(response) => {
-                if response.status().is_success() {
-                    let zip_bytes = response.bytes().await?;
-                    self.process_zip_data(zip_bytes, npy_path)?;
-                } else {
-                    warn!("Failed to download {}: Status {}", url, response.status());
+    /// Downloads `chunks`, `backtest.download_concurrency` at a time. Chunks that fail are
+    /// left missing, so the next run fetches them and only them.
+    async fn download_chunks(&self, period: &str, chunks: Vec<Chunk>) {
+        if chunks.is_empty() {
+            return;
+        }
+        let n_chunks = chunks.len();
+        info!("Downloading {} {} files", n_chunks, period);
+        let concurrency = self.config.backtest.download_concurrency.max(1);
+        let n_failed = stream::iter(chunks)
+            .map(|chunk| async move {
+                let result = self.download_and_process_zip(&chunk.url, &chunk.path).await;
+                if let Err(e) = &result {
+                    error!("Error downloading {}: {}", chunk.url, e);
                 }
-            }
-            Err(e) => error!("Error downloading {}: {}", url, e),
+                // Rate limit
+                sleep(Duration::from_millis(500)).await;
+                result.is_err()
+            })
+            .buffer_unordered(concurrency)
+            .filter(|failed| futures::future::ready(*failed))
+            .count()
+            .await;
+        if n_failed > 0 {
+            warn!(
+                "{} of {} {} files failed; run download again to fetch them",
+                n_failed, n_chunks, period
+            );
         }
-        // Rate limit
-        sleep(Duration::from_millis(500)).await;
-        Ok(())
     }
 
-    fn process_zip_data(&self, zip_bytes: Bytes, npy_path: PathBuf) -> Result<()> {
+    /// Downloads a zipped CSV of candles into `npy_path`. A file Binance does not have, such
+    /// as a day before the listing, is skipped.
+    async fn download_and_process_zip(&self, url: &str, npy_path: &Path) -> Result<()> {
+        info!("Fetching {}", url);
+        let response = reqwest::get(url).await?;
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            warn!("Failed to download {}: Status {}", url, response.status());
+            return Ok(());
+        }
+        let zip_bytes = response.error_for_status()?.bytes().await?;
+        self.process_zip_data(zip_bytes, npy_path)
+    }
+
+    /// Converts the zip's CSV into `npy_path`. The file is written under a temporary name
+    /// and renamed when complete, so an interrupted download never leaves a file that looks
+    /// downloaded.
+    fn process_zip_data(&self, zip_bytes: Bytes, npy_path: &Path) -> Result<()> {
         let cursor = Cursor::new(zip_bytes);
         let mut archive = ZipArchive::new(cursor)?;
 
@@ -181,8 +312,9 @@ impl Downloader {
         let array =
             Array2::from_shape_vec((records.len(), 6), records.into_iter().flatten().collect())?;
 
-        let file = File::create(&npy_path)?;
-        array.write_npy(file)?;
+        let part_path = npy_path.with_extension("npy.part");
+        array.write_npy(File::create(&part_path)?)?;
+        fs::rename(&part_path, npy_path)?;
 
         info!("Saved data to {}", npy_path.display());
         Ok(())
@@ -233,6 +365,48 @@ impl Downloader {
         Ok(())
     }
 
+    /// Fetches the funding settlements of `symbols` in the date range and writes them to
+    /// `data::funding_path`, replacing earlier downloads. Empty dates fetch from the
+    /// listing and up to now.
+    async fn download_funding_rates(
+        &self, exchange_name: &str, symbols: &[String], start_date_str: &str, end_date_str: &str,
+    ) -> Result<()> {
+        let start_ms = if start_date_str.is_empty() {
+            0
+        } else {
+            data::parse_date_ms(start_date_str)
+                .ok_or_else(|| anyhow!("Invalid start date: {}", start_date_str))?
+        };
+        let end_ms = if end_date_str.is_empty() {
+            Utc::now().timestamp_millis() as u64
+        } else {
+            data::parse_date_ms(end_date_str)
+                .ok_or_else(|| anyhow!("Invalid end date: {}", end_date_str))?
+        };
+        fs::create_dir_all(self.config.backtest.data_dir())?;
+
+        for symbol in symbols {
+            info!("Downloading {} funding rates for {}", exchange_name, symbol);
+            let rates = match exchange_name {
+                "binance" => fetch_binance_funding_rates(symbol, start_ms, end_ms).await?,
+                "bybit" => fetch_bybit_funding_rates(symbol, start_ms, end_ms).await?,
+                _ => return Ok(()),
+            };
+            if rates.is_empty() {
+                warn!("No funding rates found for {}", symbol);
+            }
+            let path = data::funding_path(&self.config, symbol);
+            let mut writer = WriterBuilder::new().from_path(&path)?;
+            writer.write_record(["timestamp", "funding_rate"])?;
+            for (timestamp, rate) in &rates {
+                writer.write_record([timestamp.to_string(), rate.to_string()])?;
+            }
+            writer.flush()?;
+            info!("Saved {} funding rates to {}", rates.len(), path);
+        }
+        Ok(())
+    }
+
     async fn download_bybit_data(
         &self, symbols: &Vec<String>, _start_date_str: &str, _end_date_str: &str,
     ) -> Result<()> {
@@ -255,3 +429,124 @@ impl Downloader {
         Ok(())
     }
 }
+
+/// Paths of the files named `<name>.npy` for `names` (months or days) that are not under
+/// `dir_path` yet. A day is also present when its month is.
+fn missing_chunks(dir_path: &Path, names: &[String]) -> Vec<PathBuf> {
+    names
+        .iter()
+        .filter(|name| {
+            !dir_path.join(format!("{}.npy", name)).exists()
+                && !dir_path.join(format!("{}.npy", &name[0..7])).exists()
+        })
+        .map(|name| dir_path.join(format!("{}.npy", name)))
+        .collect()
+}
+
+/// Open time of the earliest candle in the monthly and daily `.npy` files of `dir_path`.
+fn first_candle_ms(dir_path: &Path) -> Option<i64> {
+    let mut paths: Vec<PathBuf> = fs::read_dir(dir_path)
+        .ok()?
+        .filter_map(Result::ok)
+        .map(|e| e.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "npy"))
+        .collect();
+    // YYYY-MM and YYYY-MM-DD stems sort chronologically
+    paths.sort();
+    let array = Array2::<f64>::read_npy(File::open(paths.first()?).ok()?).ok()?;
+    array.column(0).iter().next().map(|&ms| ms as i64)
+}
+
+/// Funding settlements of a Binance USDⓈ-M perpetual, oldest first.
+async fn fetch_binance_funding_rates(
+    symbol: &str, start_ms: u64, end_ms: u64,
+) -> Result<Vec<(u64, f64)>> {
+    let mut rates = Vec::new();
+    let mut start_ms = start_ms;
+    while start_ms <= end_ms {
+        let url = format!(
+            "https://fapi.binance.com/fapi/v1/fundingRate?symbol={}&startTime={}&endTime={}&limit={}",
+            symbol, start_ms, end_ms, BINANCE_FUNDING_LIMIT
+        );
+        let page: Vec<BinanceFundingRate> =
+            reqwest::get(&url).await?.error_for_status()?.json().await?;
+        for rate in &page {
+            rates.push((rate.funding_time, rate.funding_rate.parse()?));
+        }
+        match page.last() {
+            Some(last) if page.len() == BINANCE_FUNDING_LIMIT => start_ms = last.funding_time + 1,
+            _ => break,
+        }
+        sleep(Duration::from_millis(500)).await;
+    }
+    Ok(rates)
+}
+
+/// Funding settlements of a Bybit linear perpetual, oldest first. Bybit pages from the
+/// newest settlement backwards.
+async fn fetch_bybit_funding_rates(
+    symbol: &str, start_ms: u64, end_ms: u64,
+) -> Result<Vec<(u64, f64)>> {
+    let mut rates = Vec::new();
+    let mut end_ms = end_ms;
+    while start_ms <= end_ms {
+        let url = format!(
+            "https://api.bybit.com/v5/market/funding/history?category=linear&symbol={}&startTime={}&endTime={}&limit={}",
+            symbol, start_ms, end_ms, BYBIT_FUNDING_LIMIT
+        );
+        let response: BybitFundingResponse =
+            reqwest::get(&url).await?.error_for_status()?.json().await?;
+        if response.ret_code != 0 {
+            return Err(anyhow!(
+                "Failed to fetch funding rates of {}: {}",
+                symbol,
+                response.ret_msg
+            ));
+        }
+        let page = response.result.list;
+        for rate in &page {
+            rates.push((
+                rate.funding_rate_timestamp.parse()?,
+                rate.funding_rate.parse()?,
+            ));
+        }
+        match rates.iter().map(|(timestamp, _)| *timestamp).min() {
+            Some(oldest) if page.len() == BYBIT_FUNDING_LIMIT && oldest > start_ms => {
+                end_ms = oldest - 1
+            }
+            _ => break,
+        }
+        sleep(Duration::from_millis(500)).await;
+    }
+    rates.sort_by_key(|(timestamp, _)| *timestamp);
+    rates.dedup_by_key(|(timestamp, _)| *timestamp);
+    Ok(rates)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_missing_chunks_skip_downloaded_files() {
+        let dir_path = std::env::temp_dir().join(format!("downloader_test_{}", std::process::id()));
+        fs::create_dir_all(&dir_path).unwrap();
+        for name in ["2024-01.npy", "2024-03-01.npy", "2024-03-02.npy.part"] {
+            fs::write(dir_path.join(name), b"").unwrap();
+        }
+        let names = |names: &[&str]| names.iter().map(|n| n.to_string()).collect::<Vec<_>>();
+        assert_eq!(
+            missing_chunks(&dir_path, &names(&["2024-01", "2024-02"])),
+            vec![dir_path.join("2024-02.npy")]
+        );
+        // days of a downloaded month are present, an interrupted download is not
+        assert_eq!(
+            missing_chunks(
+                &dir_path,
+                &names(&["2024-01-31", "2024-03-01", "2024-03-02"])
+            ),
+            vec![dir_path.join("2024-03-02.npy")]
+        );
+        fs::remove_dir_all(&dir_path).unwrap();
+    }
+}
@@ -0,0 +1,171 @@
+use crate::journal::EquitySnapshot;
+use crate::notifications::{self, MessageTemplates};
+use crate::types::{Order, Position, TradeFill, TradingMode};
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::task::JoinHandle;
+use tracing::{info, warn};
+
+/// Events past this many unread by a subscriber are dropped for it.
+const EVENT_BUS_CAPACITY: usize = 1024;
+
+/// Something that happened in the trading loop, published for subscribers.
+#[derive(Debug, Clone)]
+pub enum BotEvent {
+    /// The exchange accepted an order.
+    OrderPlaced {
+        order: Order,
+    },
+    /// Orders a read-only bot would have cancelled and placed.
+    OrdersIntended {
+        symbol: String,
+        to_cancel: Vec<Order>,
+        to_create: Vec<Order>,
+    },
+    OrderFilled {
+        fill: TradeFill,
+    },
+    PositionChanged {
+        symbol: String,
+        position: Position,
+    },
+    BalanceUpdated {
+        balance: f64,
+    },
+    ModeChanged {
+        symbol: String,
+        long: TradingMode,
+        short: TradingMode,
+    },
+    Error {
+        symbol: String,
+        message: String,
+    },
+    /// The account's balance, equity and exposure, taken every
+    /// `live.equity_snapshot_interval_minutes`.
+    EquitySnapshot {
+        snapshot: EquitySnapshot,
+    },
+    /// The bot started in safe mode after more than `live.max_n_restarts_per_day`
+    /// restarts in the last day.
+    SafeMode {
+        n_restarts: usize,
+    },
+}
+
+impl BotEvent {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            BotEvent::OrderPlaced { .. } => "OrderPlaced",
+            BotEvent::OrdersIntended { .. } => "OrdersIntended",
+            BotEvent::OrderFilled { .. } => "OrderFilled",
+            BotEvent::PositionChanged { .. } => "PositionChanged",
+            BotEvent::BalanceUpdated { .. } => "BalanceUpdated",
+            BotEvent::ModeChanged { .. } => "ModeChanged",
+            BotEvent::Error { .. } => "Error",
+            BotEvent::EquitySnapshot { .. } => "EquitySnapshot",
+            BotEvent::SafeMode { .. } => "SafeMode",
+        }
+    }
+}
+
+/// Receives every event published on an `EventBus` it is subscribed to.
+pub trait EventSubscriber: Send + 'static {
+    fn on_event(&mut self, event: &BotEvent);
+}
+
+/// Fans events out to subscribers, each running in its own task so a slow subscriber
+/// never holds up the trading loop. Publishing without subscribers is a no-op.
+#[derive(Clone)]
+pub struct EventBus {
+    sender: broadcast::Sender<BotEvent>,
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        let (sender, _) = broadcast::channel(EVENT_BUS_CAPACITY);
+        Self { sender }
+    }
+}
+
+impl EventBus {
+    pub fn publish(&self, event: BotEvent) {
+        // an error only means nobody is subscribed
+        let _ = self.sender.send(event);
+    }
+
+    /// Feeds events published from now on to `subscriber` until the bus is dropped.
+    pub fn subscribe(&self, mut subscriber: impl EventSubscriber) -> JoinHandle<()> {
+        let mut receiver = self.sender.subscribe();
+        tokio::spawn(async move {
+            loop {
+                match receiver.recv().await {
+                    Ok(event) => subscriber.on_event(&event),
+                    Err(RecvError::Lagged(n)) => warn!("Event subscriber missed {} events", n),
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        })
+    }
+}
+
+/// Logs the notification messages of events, worded by `MessageTemplates`; by default
+/// fills, intended orders and mode changes. Errors are logged where they occur.
+pub struct LogNotifier {
+    templates: MessageTemplates,
+}
+
+impl LogNotifier {
+    pub fn new(templates: MessageTemplates) -> Self {
+        Self { templates }
+    }
+}
+
+impl EventSubscriber for LogNotifier {
+    fn on_event(&mut self, event: &BotEvent) {
+        for message in notifications::messages(event) {
+            if let Some(text) = self.templates.render(&message) {
+                info!("{}", text);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    struct Recorder(Arc<Mutex<Vec<&'static str>>>);
+
+    impl EventSubscriber for Recorder {
+        fn on_event(&mut self, event: &BotEvent) {
+            self.0.lock().unwrap().push(event.kind());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_subscribers_receive_events() {
+        let bus = EventBus::default();
+        bus.publish(BotEvent::BalanceUpdated { balance: 1.0 });
+
+        let (first, second) = (Arc::default(), Arc::default());
+        let handles = [
+            bus.subscribe(Recorder(Arc::clone(&first))),
+            bus.subscribe(Recorder(Arc::clone(&second))),
+        ];
+        bus.publish(BotEvent::BalanceUpdated { balance: 2.0 });
+        bus.publish(BotEvent::Error {
+            symbol: "BTCUSDT".to_string(),
+            message: "boom".to_string(),
+        });
+        // subscribers drain what was published and stop once the bus is gone
+        drop(bus);
+        for handle in handles {
+            handle.await.unwrap();
+        }
+
+        let expected = vec!["BalanceUpdated", "Error"];
+        assert_eq!(*first.lock().unwrap(), expected);
+        assert_eq!(*second.lock().unwrap(), expected);
+    }
+}
@@ -5,11 +5,24 @@ use std::collections::HashMap;
 use chrono::Utc;
 use hmac::{Hmac, Mac};
 use sha2::Sha256;
-use crate::types::{ExchangeParams, LiveConfig, Market, Ticker, Order, Position, OrderBook};
-use super::{Exchange, SendSyncError};
+use crate::types::{
+    ExchangeParams, LiveConfig, Market, Ticker, Order, Position, OrderBook, TradeFill,
+    FundingPayment, CancelRequest, PositionPair, CollateralAsset, PositionPnl, MarginMode,
+    ApiKeyInfo,
+};
+use crate::collateral::usable_collateral;
+use super::rate_limit::{RateLimiter, SendRateLimited};
+use super::{parse_candle_row, timeframe_ms, Exchange, ExchangeError};
+use crate::data::Candle;
 use tracing::{info, error, warn};
 
 const BINANCE_API_URL: &str = "https://fapi.binance.com";
+/// Account-wide endpoints, such as the API key's restrictions, are on the spot API.
+const BINANCE_SPOT_API_URL: &str = "https://api.binance.com";
+/// Most orders one batchOrders request may place.
+const MAX_BATCH_PLACE: usize = 5;
+/// Most orders, all of one symbol, one batchOrders request may cancel.
+const MAX_BATCH_CANCEL: usize = 10;
 
 #[derive(Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
@@ -18,6 +31,7 @@ struct BinanceMarket {
     status: String,
     contract_type: String,
     onboard_date: i64,
+    margin_asset: String,
     filters: Vec<HashMap<String, serde_json::Value>>,
 }
 
@@ -54,12 +68,73 @@ struct BinanceBalance {
     balance: String,
 }
 
+/// Price index of a collateral asset in multi-assets mode.
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct BinanceAssetIndex {
+    /// The asset followed by "USD", e.g. "BTCUSD".
+    symbol: String,
+    index: String,
+    /// Discount applied to the asset's value when it counts as margin.
+    bid_buffer: String,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct BinanceApiRestrictions {
+    ip_restrict: bool,
+    enable_futures: bool,
+    enable_withdrawals: bool,
+    /// When the trade permission of keys without an IP whitelist expires.
+    #[serde(default)]
+    trading_authority_expiration_time: Option<i64>,
+}
+
 #[derive(Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 struct BinancePosition {
     symbol: String,
     position_amt: String,
     entry_price: String,
+    /// "BOTH" in one-way mode; "LONG" or "SHORT" per leg in hedge mode.
+    #[serde(default)]
+    position_side: String,
+    /// Maximum notional allowed at the current leverage bracket.
+    #[serde(default)]
+    max_notional_value: String,
+    #[serde(default)]
+    mark_price: String,
+    #[serde(default)]
+    un_realized_profit: String,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct BinanceUserTrade {
+    id: u64,
+    order_id: u64,
+    symbol: String,
+    side: String,
+    position_side: String,
+    price: String,
+    qty: String,
+    commission: String,
+    commission_asset: String,
+    #[serde(default)]
+    realized_pnl: String,
+    time: i64,
+    #[serde(default)]
+    maker: Option<bool>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct BinanceIncome {
+    symbol: String,
+    /// Negative when funding was paid.
+    income: String,
+    asset: String,
+    time: i64,
 }
 
 #[derive(serde::Serialize, Debug)]
@@ -72,29 +147,203 @@ struct BinanceOrderRequest {
     quantity: String,
     price: String,
     time_in_force: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    new_client_order_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    position_side: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct BinanceOpenOrder {
+    order_id: u64,
+    client_order_id: String,
+    symbol: String,
+    side: String,
+    position_side: String,
+    price: String,
+    orig_qty: String,
+    executed_qty: String,
+    reduce_only: bool,
+    time_in_force: String,
 }
 
 pub struct Binance {
     client: reqwest::Client,
+    rate_limiter: RateLimiter,
     api_key: String,
     api_secret: String,
+    quote: String,
+    /// Routes orders to the long or short leg, for `live.pair_mode`.
+    hedge_mode: bool,
+    /// Reports usable collateral as the balance, for `live.cross_collateral`.
+    cross_collateral: bool,
 }
 
 impl Binance {
-    pub fn new(_live_config: &LiveConfig, user_config: &UserConfig) -> Self {
+    pub fn new(live_config: &LiveConfig, user_config: &UserConfig) -> Self {
         Binance {
             client: reqwest::Client::new(),
+            rate_limiter: RateLimiter::shared("binance", &live_config.rate_limit),
             api_key: user_config.key.clone(),
             api_secret: user_config.secret.clone(),
+            quote: user_config.quote.clone(),
+            hedge_mode: live_config.pair_mode.enabled,
+            cross_collateral: live_config.cross_collateral,
         }
     }
 
+    /// The leg an order goes to in hedge mode; one-way accounts take none.
+    fn position_side(&self, order: &Order) -> Option<String> {
+        self.hedge_mode.then(|| order.position_side.to_uppercase())
+    }
+
+    async fn fetch_position_risk(
+        &self, symbol: &str,
+    ) -> Result<Option<BinancePosition>, ExchangeError> {
+        Ok(self.fetch_position_risks(symbol).await?.into_iter().next())
+    }
+
+    /// One entry in one-way mode, one per leg in hedge mode.
+    async fn fetch_position_risks(
+        &self, symbol: &str,
+    ) -> Result<Vec<BinancePosition>, ExchangeError> {
+        let timestamp = Utc::now().timestamp_millis();
+        let params = format!("symbol={}&timestamp={}", symbol, timestamp);
+        let signature = self.sign_request(&params);
+        let url = format!(
+            "{}/fapi/v2/positionRisk?{}&signature={}",
+            BINANCE_API_URL, params, signature
+        );
+
+        let response = self
+            .client
+            .get(&url)
+            .header("X-MBX-APIKEY", &self.api_key)
+            .send_limited(&self.rate_limiter)
+            .await?
+            .text()
+            .await?;
+
+        let positions: Vec<BinancePosition> = serde_json::from_str(&response)?;
+        Ok(positions
+            .into_iter()
+            .filter(|p| p.symbol == symbol)
+            .collect())
+    }
+
+    /// Sends a signed batchOrders request with `params` and returns the outcome of each of
+    /// its `n` orders. Rejected orders come back as `{"code": .., "msg": ..}` in their slot.
+    async fn send_batch(
+        &self, method: reqwest::Method, params: String, n: usize,
+    ) -> Result<Vec<Result<(), ExchangeError>>, ExchangeError> {
+        let params = format!("{}&timestamp={}", params, Utc::now().timestamp_millis());
+        let signature = self.sign_request(&params);
+        let url = format!(
+            "{}/fapi/v1/batchOrders?{}&signature={}",
+            BINANCE_API_URL, params, signature
+        );
+        let response = self
+            .client
+            .request(method, &url)
+            .header("X-MBX-APIKEY", &self.api_key)
+            .send_limited(&self.rate_limiter)
+            .await?
+            .text()
+            .await?;
+        // a rejected request as a whole is a single error object
+        let items: Vec<serde_json::Value> =
+            serde_json::from_str(&response).map_err(|_| response.clone())?;
+        if items.len() != n {
+            return Err(format!(
+                "Batch response has {} results for {} orders",
+                items.len(),
+                n
+            )
+            .into());
+        }
+        Ok(items
+            .into_iter()
+            .map(|item| match (item.get("code"), item.get("orderId")) {
+                (Some(_), None) => Err(item.to_string().into()),
+                _ => Ok(()),
+            })
+            .collect())
+    }
+
+    async fn fetch_balances(&self) -> Result<Vec<BinanceBalance>, ExchangeError> {
+        let timestamp = Utc::now().timestamp_millis();
+        let params = format!("timestamp={}", timestamp);
+        let signature = self.sign_request(&params);
+        let url = format!(
+            "{}/fapi/v2/balance?{}&signature={}",
+            BINANCE_API_URL, params, signature
+        );
+
+        let response = self
+            .client
+            .get(&url)
+            .header("X-MBX-APIKEY", &self.api_key)
+            .send_limited(&self.rate_limiter)
+            .await?
+            .text()
+            .await?;
+
+        Ok(serde_json::from_str(&response)?)
+    }
+
     fn sign_request(&self, params: &str) -> String {
         type HmacSha256 = Hmac<Sha256>;
         let mut mac = HmacSha256::new_from_slice(self.api_secret.as_bytes()).unwrap();
         mac.update(params.as_bytes());
         hex::encode(mac.finalize().into_bytes())
     }
+
+    /// Posts `params` to `path`, succeeding unless the response carries an error code
+    /// other than any of `unchanged`, the codes for a setting that is already in place.
+    async fn post_setting(
+        &self, path: &str, params: &str, unchanged: &[i64],
+    ) -> Result<(), ExchangeError> {
+        let params = format!("{}&timestamp={}", params, Utc::now().timestamp_millis());
+        let signature = self.sign_request(&params);
+        let url = format!(
+            "{}{}?{}&signature={}",
+            BINANCE_API_URL, path, params, signature
+        );
+        let response = self
+            .client
+            .post(&url)
+            .header("X-MBX-APIKEY", &self.api_key)
+            .send_limited(&self.rate_limiter)
+            .await?
+            .text()
+            .await?;
+        let parsed: serde_json::Value = serde_json::from_str(&response)?;
+        match parsed["code"].as_i64() {
+            None | Some(200) => Ok(()),
+            Some(code) if unchanged.contains(&code) => Ok(()),
+            Some(_) => Err(ExchangeError::classify(response)),
+        }
+    }
+}
+
+/// The legs of a symbol's position risks. A one-way account has a single "BOTH" entry,
+/// also while flat.
+fn position_pair(risks: Vec<BinancePosition>) -> Result<PositionPair, ExchangeError> {
+    let mut positions = PositionPair::default();
+    for position in risks {
+        // amounts are signed, negative for the short leg and a one-way short
+        let leg = Position {
+            size: position.position_amt.parse()?,
+            price: position.entry_price.parse()?,
+        };
+        match position.position_side.as_str() {
+            "LONG" => positions.long = leg,
+            "SHORT" => positions.short = leg,
+            _ => positions = PositionPair::from_net(leg),
+        }
+    }
+    Ok(positions)
 }
 
 #[async_trait]
@@ -102,15 +351,29 @@ impl Exchange for Binance {
     fn clone_box(&self) -> Box<dyn Exchange> {
         Box::new(Binance {
             client: self.client.clone(),
+            rate_limiter: self.rate_limiter.clone(),
             api_key: self.api_key.clone(),
             api_secret: self.api_secret.clone(),
+            quote: self.quote.clone(),
+            hedge_mode: self.hedge_mode,
+            cross_collateral: self.cross_collateral,
         })
     }
 
-    async fn load_markets(&self) -> Result<HashMap<String, Market>, SendSyncError> {
+    fn quote(&self) -> &str {
+        &self.quote
+    }
+
+    async fn load_markets(&self) -> Result<HashMap<String, Market>, ExchangeError> {
         info!("Loading markets from Binance");
         let url = format!("{}/fapi/v1/exchangeInfo", BINANCE_API_URL);
-        let response = self.client.get(&url).send().await?.text().await?;
+        let response = self
+            .client
+            .get(&url)
+            .send_limited(&self.rate_limiter)
+            .await?
+            .text()
+            .await?;
         let binance_response: BinanceExchangeInfo = serde_json::from_str(&response)?;
 
         let markets = binance_response
@@ -125,6 +388,7 @@ impl Exchange for Binance {
                         swap: m.contract_type == "PERPETUAL",
                         linear: true, // Binance futures are linear
                         created_at: m.onboard_date,
+                        settle: m.margin_asset,
                     },
                 )
             })
@@ -134,7 +398,7 @@ impl Exchange for Binance {
 
     async fn fetch_tickers(
         &self, symbols: &[String],
-    ) -> Result<HashMap<String, Ticker>, SendSyncError> {
+    ) -> Result<HashMap<String, Ticker>, ExchangeError> {
         info!("Fetching tickers from Binance");
         let url = if symbols.is_empty() {
             format!("{}/fapi/v1/ticker/24hr", BINANCE_API_URL)
@@ -144,7 +408,13 @@ impl Exchange for Binance {
             format!("{}/fapi/v1/ticker/24hr", BINANCE_API_URL)
         };
 
-        let response = self.client.get(&url).send().await?.text().await?;
+        let response = self
+            .client
+            .get(&url)
+            .send_limited(&self.rate_limiter)
+            .await?
+            .text()
+            .await?;
         let binance_tickers: Vec<BinanceTicker> = serde_json::from_str(&response)?;
 
         let tickers = binance_tickers
@@ -165,6 +435,7 @@ impl Exchange for Binance {
                             ask,
                             last,
                             quote_volume,
+                            funding_rate: 0.0, // not provided by the 24h ticker
                         },
                     )),
                     _ => {
@@ -178,27 +449,81 @@ impl Exchange for Binance {
         Ok(tickers)
     }
 
-    async fn fetch_ticker(&self, symbol: &str) -> Result<f64, SendSyncError> {
+    async fn fetch_ticker(&self, symbol: &str) -> Result<f64, ExchangeError> {
         info!("Fetching ticker for symbol: {}", symbol);
         let tickers = self.fetch_tickers(&[symbol.to_string()]).await?;
         if let Some(ticker) = tickers.get(symbol) {
             Ok(ticker.last)
         } else {
             error!("Ticker data not found for symbol: {}", symbol);
-            Err(Box::new(std::io::Error::new(
-                std::io::ErrorKind::Other,
+            Err(ExchangeError::classify(
                 "Ticker data not found in Binance response",
-            )))
+            ))
+        }
+    }
+
+    async fn fetch_last_close(&self, symbol: &str) -> Result<Option<f64>, ExchangeError> {
+        let url = format!(
+            "{}/fapi/v1/klines?symbol={}&interval=1m&limit=1",
+            BINANCE_API_URL, symbol
+        );
+        let response = self
+            .client
+            .get(&url)
+            .send_limited(&self.rate_limiter)
+            .await?
+            .text()
+            .await?;
+        // [open time, open, high, low, close, ...]
+        let klines: Vec<Vec<serde_json::Value>> = serde_json::from_str(&response)?;
+        match klines
+            .first()
+            .and_then(|k| k.get(4))
+            .and_then(|c| c.as_str())
+        {
+            Some(close) => Ok(Some(close.parse()?)),
+            None => Err(format!("No kline data for {}", symbol).into()),
+        }
+    }
+
+    async fn fetch_ohlcv(
+        &self, symbol: &str, timeframe: &str, since_ms: Option<i64>, limit: usize,
+    ) -> Result<Vec<Candle>, ExchangeError> {
+        timeframe_ms(timeframe)?;
+        let mut url = format!(
+            "{}/fapi/v1/klines?symbol={}&interval={}&limit={}",
+            BINANCE_API_URL,
+            symbol,
+            timeframe,
+            limit.min(1500)
+        );
+        if let Some(since_ms) = since_ms {
+            url.push_str(&format!("&startTime={}", since_ms));
         }
+        let response = self
+            .client
+            .get(&url)
+            .send_limited(&self.rate_limiter)
+            .await?
+            .text()
+            .await?;
+        let klines: Vec<Vec<serde_json::Value>> = serde_json::from_str(&response)?;
+        klines.iter().map(|row| parse_candle_row(row)).collect()
     }
 
-    async fn fetch_order_book(&self, symbol: &str) -> Result<OrderBook, SendSyncError> {
+    async fn fetch_order_book(&self, symbol: &str) -> Result<OrderBook, ExchangeError> {
         info!("Fetching order book for symbol: {}", symbol);
         let url = format!(
             "{}/fapi/v1/depth?symbol={}&limit=100",
             BINANCE_API_URL, symbol
         );
-        let response = self.client.get(&url).send().await?.text().await?;
+        let response = self
+            .client
+            .get(&url)
+            .send_limited(&self.rate_limiter)
+            .await?
+            .text()
+            .await?;
         let order_book_result: BinanceOrderBookResult = serde_json::from_str(&response)?;
 
         let bids = order_book_result
@@ -228,35 +553,63 @@ impl Exchange for Binance {
         Ok(OrderBook { bids, asks })
     }
 
-    async fn fetch_balance(&self) -> Result<f64, SendSyncError> {
+    async fn fetch_balance(&self) -> Result<f64, ExchangeError> {
+        if self.cross_collateral {
+            return Ok(usable_collateral(&self.fetch_collateral().await?));
+        }
         info!("Fetching balance from Binance");
-        let timestamp = Utc::now().timestamp_millis();
-        let params = format!("timestamp={}", timestamp);
-        let signature = self.sign_request(&params);
-        let url = format!(
-            "{}/fapi/v2/balance?{}&signature={}",
-            BINANCE_API_URL, params, signature
-        );
+        let binance_balances = self.fetch_balances().await?;
+
+        if let Some(quote_balance) = binance_balances.iter().find(|b| b.asset == self.quote) {
+            Ok(quote_balance.balance.parse()?)
+        } else {
+            Ok(0.0)
+        }
+    }
 
+    async fn fetch_collateral(&self) -> Result<Vec<CollateralAsset>, ExchangeError> {
+        info!("Fetching collateral from Binance");
+        let binance_balances = self.fetch_balances().await?;
+        let url = format!("{}/fapi/v1/assetIndex", BINANCE_API_URL);
         let response = self
             .client
             .get(&url)
-            .header("X-MBX-APIKEY", &self.api_key)
-            .send()
+            .send_limited(&self.rate_limiter)
             .await?
             .text()
             .await?;
+        let asset_indexes: Vec<BinanceAssetIndex> = serde_json::from_str(&response)?;
+        let asset_indexes: HashMap<&str, &BinanceAssetIndex> = asset_indexes
+            .iter()
+            .filter_map(|index| Some((index.symbol.strip_suffix("USD")?, index)))
+            .collect();
 
-        let binance_balances: Vec<BinanceBalance> = serde_json::from_str(&response)?;
-
-        if let Some(usdt_balance) = binance_balances.iter().find(|b| b.asset == "USDT") {
-            Ok(usdt_balance.balance.parse()?)
-        } else {
-            Ok(0.0)
-        }
+        binance_balances
+            .iter()
+            .map(|balance| {
+                let quantity: f64 = balance.balance.parse()?;
+                let (value, haircut) = if balance.asset == self.quote {
+                    (quantity, 0.0)
+                } else if let Some(index) = asset_indexes.get(balance.asset.as_str()) {
+                    (
+                        quantity * index.index.parse::<f64>()?,
+                        index.bid_buffer.parse()?,
+                    )
+                } else {
+                    // assets without an index are not margin in multi-assets mode
+                    (0.0, 1.0)
+                };
+                Ok(CollateralAsset {
+                    asset: balance.asset.clone(),
+                    quantity,
+                    value,
+                    haircut,
+                })
+            })
+            .collect()
     }
 
-    async fn place_order(&mut self, order: &Order) -> Result<(), SendSyncError> {
+    async fn place_order(&mut self, order: &Order) -> Result<(), ExchangeError> {
         info!("Placing order on Binance: {:?}", order);
         let order_request = BinanceOrderRequest {
             symbol: order.symbol.clone(),
@@ -265,6 +618,8 @@ impl Exchange for Binance {
             quantity: order.qty.to_string(),
             price: order.price.to_string(),
             time_in_force: order.time_in_force.clone(),
+            new_client_order_id: order.client_order_id.clone(),
+            position_side: self.position_side(order),
         };
 
         let mut params = serde_urlencoded::to_string(&order_request)?;
@@ -281,7 +636,7 @@ impl Exchange for Binance {
             .client
             .post(&url)
             .header("X-MBX-APIKEY", &self.api_key)
-            .send()
+            .send_limited(&self.rate_limiter)
             .await?
             .text()
             .await?;
@@ -289,20 +644,16 @@ impl Exchange for Binance {
         // TODO: better error handling
         if response.contains("code") {
             error!("Failed to place order: {}", response);
-            return Err(Box::new(std::io::Error::new(
-                std::io::ErrorKind::Other,
-                response,
-            )));
+            return Err(ExchangeError::classify(response));
         }
 
         Ok(())
     }
 
-    async fn cancel_order(&mut self, order_id: &str) -> Result<(), SendSyncError> {
-        info!("Canceling order on Binance: {}", order_id);
+    async fn cancel_order(&mut self, symbol: &str, order_id: &str) -> Result<(), ExchangeError> {
+        info!("[{}] Canceling order on Binance: {}", symbol, order_id);
 
-        // aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa
-        let mut params = format!("symbol=BTCUSDT&orderId={}", order_id); // TODO: get symbol from order
+        let mut params = format!("symbol={}&orderId={}", symbol, order_id);
         let timestamp = Utc::now().timestamp_millis();
         params.push_str(&format!("&timestamp={}", timestamp));
 
@@ -316,7 +667,7 @@ impl Exchange for Binance {
             .client
             .delete(&url)
             .header("X-MBX-APIKEY", &self.api_key)
-            .send()
+            .send_limited(&self.rate_limiter)
             .await?
             .text()
             .await?;
@@ -324,52 +675,174 @@ impl Exchange for Binance {
         // TODO: better error handling
         if response.contains("code") {
             error!("Failed to cancel order: {}", response);
-            return Err(Box::new(std::io::Error::new(
-                std::io::ErrorKind::Other,
-                response,
-            )));
+            return Err(ExchangeError::classify(response));
         }
 
         Ok(())
     }
 
-    async fn fetch_position(&self, symbol: &str) -> Result<Position, SendSyncError> {
-        info!("Fetching position for symbol: {}", symbol);
+    async fn place_orders(&mut self, orders: &[Order]) -> Vec<Result<(), ExchangeError>> {
+        let mut results = Vec::with_capacity(orders.len());
+        for chunk in orders.chunks(MAX_BATCH_PLACE) {
+            info!("Placing {} orders on Binance in a batch", chunk.len());
+            let requests: Vec<BinanceOrderRequest> = chunk
+                .iter()
+                .map(|order| BinanceOrderRequest {
+                    symbol: order.symbol.clone(),
+                    side: order.side.clone(),
+                    order_type: "LIMIT".to_string(),
+                    quantity: order.qty.to_string(),
+                    price: order.price.to_string(),
+                    time_in_force: order.time_in_force.clone(),
+                    new_client_order_id: order.client_order_id.clone(),
+                    position_side: self.position_side(order),
+                })
+                .collect();
+            let chunk_results = match serde_json::to_string(&requests) {
+                Ok(batch) => {
+                    let params =
+                        serde_urlencoded::to_string([("batchOrders", batch)]).unwrap_or_default();
+                    self.send_batch(reqwest::Method::POST, params, chunk.len())
+                        .await
+                }
+                Err(e) => Err(e.into()),
+            };
+            match chunk_results {
+                Ok(chunk_results) => results.extend(chunk_results),
+                Err(e) => {
+                    error!("Failed to place batch of orders: {}", e);
+                    results.extend(chunk.iter().map(|_| Err(e.clone())));
+                }
+            }
+        }
+        results
+    }
+
+    async fn cancel_orders(&mut self, cancels: &[CancelRequest]) -> Vec<Result<(), ExchangeError>> {
+        // a batch cancels orders of one symbol; results are put back in request order
+        let mut results: Vec<Result<(), ExchangeError>> = Vec::with_capacity(cancels.len());
+        results.resize_with(cancels.len(), || Ok(()));
+        let mut by_symbol: Vec<(&str, Vec<usize>)> = Vec::new();
+        for (i, cancel) in cancels.iter().enumerate() {
+            match by_symbol
+                .iter_mut()
+                .find(|(symbol, _)| *symbol == cancel.symbol)
+            {
+                Some((_, indices)) => indices.push(i),
+                None => by_symbol.push((&cancel.symbol, vec![i])),
+            }
+        }
+        for (symbol, indices) in by_symbol {
+            for chunk in indices.chunks(MAX_BATCH_CANCEL) {
+                info!(
+                    "Canceling {} orders of {} on Binance in a batch",
+                    chunk.len(),
+                    symbol
+                );
+                let order_ids: Vec<&str> = chunk
+                    .iter()
+                    .map(|&i| cancels[i].order_id.as_str())
+                    .collect();
+                let params = serde_urlencoded::to_string([
+                    ("symbol", symbol.to_string()),
+                    ("orderIdList", format!("[{}]", order_ids.join(","))),
+                ])
+                .unwrap_or_default();
+                match self
+                    .send_batch(reqwest::Method::DELETE, params, chunk.len())
+                    .await
+                {
+                    Ok(chunk_results) => {
+                        for (&i, result) in chunk.iter().zip(chunk_results) {
+                            results[i] = result;
+                        }
+                    }
+                    Err(e) => {
+                        error!("Failed to cancel batch of orders: {}", e);
+                        for &i in chunk {
+                            results[i] = Err(e.clone());
+                        }
+                    }
+                }
+            }
+        }
+        results
+    }
+
+    async fn fetch_open_orders(&self, symbol: &str) -> Result<Vec<Order>, ExchangeError> {
         let timestamp = Utc::now().timestamp_millis();
         let params = format!("symbol={}&timestamp={}", symbol, timestamp);
         let signature = self.sign_request(&params);
         let url = format!(
-            "{}/fapi/v2/positionRisk?{}&signature={}",
+            "{}/fapi/v1/openOrders?{}&signature={}",
             BINANCE_API_URL, params, signature
         );
-
         let response = self
             .client
             .get(&url)
             .header("X-MBX-APIKEY", &self.api_key)
-            .send()
+            .send_limited(&self.rate_limiter)
             .await?
             .text()
             .await?;
-
-        let positions: Vec<BinancePosition> = serde_json::from_str(&response)?;
-
-        if let Some(position) = positions.iter().find(|p| p.symbol == symbol) {
-            Ok(Position {
-                size: position.position_amt.parse()?,
-                price: position.entry_price.parse()?,
-            })
-        } else {
-            Ok(Position {
-                size: 0.0,
-                price: 0.0,
+        let open_orders: Vec<BinanceOpenOrder> = serde_json::from_str(&response)?;
+        open_orders
+            .into_iter()
+            .map(|open_order| {
+                let position_side = match open_order.position_side.as_str() {
+                    "LONG" => "Long",
+                    "SHORT" => "Short",
+                    _ => "",
+                };
+                Ok(Order {
+                    id: open_order.order_id.to_string(),
+                    symbol: open_order.symbol,
+                    side: if open_order.side == "BUY" {
+                        "Buy".to_string()
+                    } else {
+                        "Sell".to_string()
+                    },
+                    position_side: position_side.to_string(),
+                    qty: open_order.orig_qty.parse::<f64>()?
+                        - open_order.executed_qty.parse::<f64>()?,
+                    price: open_order.price.parse()?,
+                    reduce_only: open_order.reduce_only,
+                    custom_id: String::new(),
+                    client_order_id: open_order.client_order_id,
+                    time_in_force: open_order.time_in_force,
+                })
             })
+            .collect()
+    }
+
+    async fn fetch_positions(&self, symbol: &str) -> Result<PositionPair, ExchangeError> {
+        info!("Fetching positions for symbol: {}", symbol);
+        position_pair(self.fetch_position_risks(symbol).await?)
+    }
+    async fn fetch_position_pnl(&self, symbol: &str) -> Result<Option<PositionPnl>, ExchangeError> {
+        let Some(position) = self.fetch_position_risk(symbol).await? else {
+            return Ok(None);
+        };
+        let size: f64 = position.position_amt.parse()?;
+        if size == 0.0 || position.un_realized_profit.is_empty() {
+            return Ok(None);
         }
+        Ok(Some(PositionPnl {
+            size,
+            mark_price: position.mark_price.parse()?,
+            unrealized_pnl: position.un_realized_profit.parse()?,
+        }))
     }
-    async fn fetch_exchange_params(&self, symbol: &str) -> Result<ExchangeParams, SendSyncError> {
+    async fn fetch_exchange_params(&self, symbol: &str) -> Result<ExchangeParams, ExchangeError> {
         info!("Fetching exchange params for symbol: {}", symbol);
         let url = format!("{}/fapi/v1/exchangeInfo", BINANCE_API_URL);
-        let response = self.client.get(&url).send().await?.text().await?;
+        let response = self
+            .client
+            .get(&url)
+            .send_limited(&self.rate_limiter)
+            .await?
+            .text()
+            .await?;
         let exchange_info: BinanceExchangeInfo = serde_json::from_str(&response)?;
 
         if let Some(market) = exchange_info
@@ -423,10 +896,277 @@ impl Exchange for Binance {
                 inverse: false, // Binance futures are not inverse
             })
         } else {
-            Err(Box::new(std::io::Error::new(
-                std::io::ErrorKind::Other,
-                format!("Could not find market info for {}", symbol),
+            Err(ExchangeError::classify(format!(
+                "Could not find market info for {}",
+                symbol
             )))
         }
     }
+
+    fn is_precision_error(&self, error: &str) -> bool {
+        // -1111: precision over the maximum; -1013: price or lot size filter failure;
+        // -4164: notional below the minimum
+        ["-1111", "-1013", "-4164"]
+            .iter()
+            .any(|code| error.contains(code))
+    }
+
+    fn is_risk_limit_error(&self, error: &str) -> bool {
+        // -2027: exceeded the maximum allowable position at current leverage
+        error.contains("-2027")
+    }
+
+    fn has_fill_history(&self) -> bool {
+        true
+    }
+
+    async fn fetch_fill_history(
+        &self, symbol: &str, start_ms: i64, end_ms: i64,
+    ) -> Result<Vec<TradeFill>, ExchangeError> {
+        // userTrades spans at most 7 days and 1000 trades per query
+        const WINDOW_MS: i64 = 7 * 24 * 60 * 60 * 1000;
+        const LIMIT: usize = 1000;
+        let mut fills: Vec<TradeFill> = Vec::new();
+        let mut window_start = start_ms;
+        while window_start < end_ms {
+            let window_end = (window_start + WINDOW_MS).min(end_ms);
+            let timestamp = Utc::now().timestamp_millis();
+            let params = format!(
+                "symbol={}&startTime={}&endTime={}&limit={}&timestamp={}",
+                symbol, window_start, window_end, LIMIT, timestamp
+            );
+            let signature = self.sign_request(&params);
+            let url = format!(
+                "{}/fapi/v1/userTrades?{}&signature={}",
+                BINANCE_API_URL, params, signature
+            );
+            let response = self
+                .client
+                .get(&url)
+                .header("X-MBX-APIKEY", &self.api_key)
+                .send_limited(&self.rate_limiter)
+                .await?
+                .text()
+                .await?;
+            let trades: Vec<BinanceUserTrade> = serde_json::from_str(&response)?;
+            let n_trades = trades.len();
+            let last_time = trades.last().map(|t| t.time);
+            for trade in trades {
+                if fills.iter().any(|f| f.id == trade.id.to_string()) {
+                    continue;
+                }
+                let side = if trade.side == "BUY" { "Buy" } else { "Sell" };
+                let position_side = match trade.position_side.as_str() {
+                    "LONG" => "Long",
+                    "SHORT" => "Short",
+                    _ => "",
+                };
+                // commissions paid in other assets (e.g. BNB) cannot be valued here
+                let fee = if trade.commission_asset == self.quote {
+                    trade.commission.parse()?
+                } else {
+                    0.0
+                };
+                fills.push(TradeFill {
+                    id: trade.id.to_string(),
+                    order_id: trade.order_id.to_string(),
+                    symbol: trade.symbol,
+                    timestamp_ms: trade.time,
+                    side: side.to_string(),
+                    position_side: position_side.to_string(),
+                    qty: trade.qty.parse()?,
+                    price: trade.price.parse()?,
+                    fee,
+                    realized_pnl: trade.realized_pnl.parse().ok(),
+                    maker: trade.maker,
+                    // the trade list does not carry the order's price
+                    order_price: 0.0,
+                });
+            }
+            // a full page may have more trades in the window; resume from the last one
+            window_start = match last_time {
+                Some(t) if n_trades == LIMIT && t > window_start => t,
+                _ => window_end,
+            };
+        }
+        fills.sort_by_key(|f| f.timestamp_ms);
+        Ok(fills)
+    }
+
+    async fn fetch_funding_payments(
+        &self, symbol: &str, start_ms: i64, end_ms: i64,
+    ) -> Result<Vec<FundingPayment>, ExchangeError> {
+        const LIMIT: usize = 1000;
+        let mut payments: Vec<FundingPayment> = Vec::new();
+        let mut window_start = start_ms;
+        while window_start < end_ms {
+            let timestamp = Utc::now().timestamp_millis();
+            let params = format!(
+                "symbol={}&incomeType=FUNDING_FEE&startTime={}&endTime={}&limit={}&timestamp={}",
+                symbol, window_start, end_ms, LIMIT, timestamp
+            );
+            let signature = self.sign_request(&params);
+            let url = format!(
+                "{}/fapi/v1/income?{}&signature={}",
+                BINANCE_API_URL, params, signature
+            );
+            let response = self
+                .client
+                .get(&url)
+                .header("X-MBX-APIKEY", &self.api_key)
+                .send_limited(&self.rate_limiter)
+                .await?
+                .text()
+                .await?;
+            let incomes: Vec<BinanceIncome> = serde_json::from_str(&response)?;
+            let n_incomes = incomes.len();
+            let last_time = incomes.last().map(|i| i.time);
+            for income in incomes {
+                if income.asset != self.quote || income.time < window_start {
+                    continue;
+                }
+                payments.push(FundingPayment {
+                    symbol: income.symbol,
+                    timestamp_ms: income.time,
+                    amount: -income.income.parse::<f64>()?,
+                });
+            }
+            // a full page may have more payments; resume after the last one
+            window_start = match last_time {
+                Some(t) if n_incomes == LIMIT => t + 1,
+                _ => end_ms,
+            };
+        }
+        Ok(payments)
+    }
+
+    // Binance only raises the allowed notional by lowering leverage, which changes the
+    // margin posted, so the limit is left to the user and the grid is capped instead.
+    async fn fetch_risk_limit(&self, symbol: &str) -> Result<Option<f64>, ExchangeError> {
+        Ok(self
+            .fetch_position_risk(symbol)
+            .await?
+            .and_then(|p| p.max_notional_value.parse().ok()))
+    }
+
+    async fn set_leverage(&mut self, symbol: &str, leverage: f64) -> Result<(), ExchangeError> {
+        // whole multiples only
+        let leverage = leverage.round().max(1.0);
+        info!("Setting leverage for {} to {}", symbol, leverage);
+        let params = format!("symbol={}&leverage={}", symbol, leverage);
+        self.post_setting("/fapi/v1/leverage", &params, &[]).await
+    }
+
+    async fn set_margin_mode(
+        &mut self, symbol: &str, mode: MarginMode,
+    ) -> Result<(), ExchangeError> {
+        info!("Setting {:?} margin for {}", mode, symbol);
+        let margin_type = match mode {
+            MarginMode::Cross => "CROSSED",
+            MarginMode::Isolated => "ISOLATED",
+        };
+        let params = format!("symbol={}&marginType={}", symbol, margin_type);
+        // -4046: no need to change margin type
+        self.post_setting("/fapi/v1/marginType", &params, &[-4046])
+            .await
+    }
+
+    async fn fetch_api_key_info(&self) -> Result<Option<ApiKeyInfo>, ExchangeError> {
+        let params = format!("timestamp={}", Utc::now().timestamp_millis());
+        let signature = self.sign_request(&params);
+        let url = format!(
+            "{}/sapi/v1/account/apiRestrictions?{}&signature={}",
+            BINANCE_SPOT_API_URL, params, signature
+        );
+        let response = self
+            .client
+            .get(&url)
+            .header("X-MBX-APIKEY", &self.api_key)
+            .send_limited(&self.rate_limiter)
+            .await?
+            .text()
+            .await?;
+        let parsed: serde_json::Value = serde_json::from_str(&response)?;
+        if parsed.get("code").is_some() {
+            return Err(ExchangeError::classify(response));
+        }
+        let restrictions: BinanceApiRestrictions = serde_json::from_value(parsed)?;
+        Ok(Some(ApiKeyInfo {
+            can_trade: restrictions.enable_futures,
+            can_withdraw: restrictions.enable_withdrawals,
+            ip_restricted: restrictions.ip_restrict,
+            expires_at_ms: restrictions
+                .trading_authority_expiration_time
+                .filter(|&t| t > 0),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_failed_batch_transport_stays_a_network_error() {
+        let live_config = crate::config::load_config("config.hjson").unwrap().live;
+        let user_config: UserConfig =
+            serde_json::from_value(serde_json::json!({ "exchange": "binance" })).unwrap();
+        let mut binance = Binance::new(&live_config, &user_config);
+        // the proxy never resolves, so every request fails before reaching the exchange
+        binance.client = reqwest::Client::builder()
+            .proxy(reqwest::Proxy::all("http://proxy.invalid:1").unwrap())
+            .build()
+            .unwrap();
+        let orders = [99.0, 98.0].map(|price| Order {
+            symbol: "BTCUSDT".to_string(),
+            side: "Buy".to_string(),
+            qty: 0.001,
+            price,
+            ..Default::default()
+        });
+        let cancels = ["1", "2"].map(|order_id| CancelRequest {
+            symbol: "BTCUSDT".to_string(),
+            order_id: order_id.to_string(),
+        });
+
+        // the error text holds the signed URL, which would read as an auth failure
+        for result in binance.place_orders(&orders).await {
+            assert!(matches!(result, Err(ExchangeError::Network(_))));
+        }
+        for result in binance.cancel_orders(&cancels).await {
+            assert!(matches!(result, Err(ExchangeError::Network(_))));
+        }
+    }
+
+    #[test]
+    fn test_position_pair_reports_the_mode_of_a_flat_account() {
+        let parse =
+            |response: &str| position_pair(serde_json::from_str(response).unwrap()).unwrap();
+        let flat = parse(
+            r#"[{"symbol":"BTCUSDT","positionAmt":"0.000","entryPrice":"0.0",
+            "markPrice":"64012.10000000","unRealizedProfit":"0.00000000",
+            "liquidationPrice":"0","leverage":"20","maxNotionalValue":"25000000",
+            "marginType":"cross","isolatedMargin":"0.00000000","isAutoAddMargin":"false",
+            "positionSide":"BOTH","notional":"0","isolatedWallet":"0","updateTime":0}]"#,
+        );
+        assert!(flat.one_way);
+        assert_eq!((flat.long.size, flat.short.size), (0.0, 0.0));
+
+        let short = parse(
+            r#"[{"symbol":"BTCUSDT","positionAmt":"-0.015","entryPrice":"64100.5",
+            "markPrice":"64012.1","unRealizedProfit":"1.32","positionSide":"BOTH"}]"#,
+        );
+        assert!(short.one_way);
+        assert_eq!((short.short.size, short.short.price), (-0.015, 64100.5));
+
+        let hedge = parse(
+            r#"[{"symbol":"BTCUSDT","positionAmt":"0.020","entryPrice":"63000.0",
+            "positionSide":"LONG"},
+            {"symbol":"BTCUSDT","positionAmt":"0.000","entryPrice":"0.0",
+            "positionSide":"SHORT"}]"#,
+        );
+        assert!(!hedge.one_way);
+        assert_eq!((hedge.long.size, hedge.long.price), (0.02, 63000.0));
+        assert_eq!(hedge.short.size, 0.0);
+    }
 }
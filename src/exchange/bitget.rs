@@ -5,8 +5,13 @@ use std::collections::HashMap;
 use chrono::Utc;
 use hmac::{Hmac, Mac};
 use sha2::Sha256;
-use crate::types::{ExchangeParams, LiveConfig, Market, Ticker, Order, Position, OrderBook};
-use super::{Exchange, SendSyncError};
+use crate::types::{
+    ExchangeParams, LiveConfig, MarginMode, Market, Ticker, Order, Position, PositionPair,
+    OrderBook,
+};
+use super::rate_limit::{RateLimiter, SendRateLimited};
+use super::{parse_candle_row, timeframe_ms, Exchange, ExchangeError};
+use crate::data::Candle;
 use tracing::{info, error};
 
 const BITGET_API_URL: &str = "https://api.bitget.com";
@@ -39,22 +44,28 @@ struct BitgetTicker {
     best_ask_price: String,
     last_price: String,
     quote_volume: String,
+    #[serde(default)]
+    funding_rate: String,
 }
 
 pub struct Bitget {
     client: reqwest::Client,
+    rate_limiter: RateLimiter,
     api_key: String,
     api_secret: String,
     passphrase: String,
+    quote: String,
 }
 
 impl Bitget {
-    pub fn new(_live_config: &LiveConfig, user_config: &UserConfig) -> Self {
+    pub fn new(live_config: &LiveConfig, user_config: &UserConfig) -> Self {
         Bitget {
             client: reqwest::Client::new(),
+            rate_limiter: RateLimiter::shared("bitget", &live_config.rate_limit),
             api_key: user_config.key.clone(),
             api_secret: user_config.secret.clone(),
             passphrase: user_config.passphrase.clone(),
+            quote: user_config.quote.clone(),
         }
     }
 
@@ -69,6 +80,57 @@ impl Bitget {
         };
         (timestamp, signature)
     }
+
+    /// Posts a setting of `symbol` to `request_path`; setting the current value again
+    /// succeeds.
+    async fn post_setting(
+        &self, request_path: &str, symbol: &str, fields: &[(&str, String)],
+    ) -> Result<(), ExchangeError> {
+        let mut request = std::collections::HashMap::new();
+        request.insert("symbol", symbol.to_string());
+        request.insert("marginCoin", self.quote.clone());
+        request.extend(fields.iter().cloned());
+        let payload = serde_json::to_string(&request)?;
+        let (timestamp, signature) = self.sign_request("POST", request_path, &payload);
+        let response = self
+            .client
+            .post(format!("{}{}", BITGET_API_URL, request_path))
+            .header("ACCESS-KEY", &self.api_key)
+            .header("ACCESS-SIGN", &signature)
+            .header("ACCESS-TIMESTAMP", &timestamp)
+            .header("ACCESS-PASSPHRASE", &self.passphrase)
+            .header("Content-Type", "application/json")
+            .body(payload)
+            .send_limited(&self.rate_limiter)
+            .await?
+            .text()
+            .await?;
+        let bitget_response: BitgetResponse<serde_json::Value> = serde_json::from_str(&response)?;
+        if bitget_response.code != "0" {
+            return Err(ExchangeError::classify(bitget_response.msg));
+        }
+        Ok(())
+    }
+}
+
+/// The legs of a symbol's single position entries, each saying whether the account holds
+/// positions one-way ("single_hold") or hedged ("double_hold"), also while flat.
+fn position_pair(data: &[serde_json::Value]) -> Result<PositionPair, ExchangeError> {
+    let mut positions = PositionPair::default();
+    for position_data in data {
+        // one entry per side, with an unsigned size
+        let size: f64 = position_data["total"].as_str().unwrap_or("0").parse()?;
+        let price: f64 = position_data["averageOpenPrice"]
+            .as_str()
+            .unwrap_or("0")
+            .parse()?;
+        match position_data["holdSide"].as_str() {
+            Some("short") => positions.short = Position { size: -size, price },
+            _ => positions.long = Position { size, price },
+        }
+        positions.one_way |= position_data["holdMode"].as_str() == Some("single_hold");
+    }
+    Ok(positions)
 }
 
 #[async_trait]
@@ -76,27 +138,36 @@ impl Exchange for Bitget {
     fn clone_box(&self) -> Box<dyn Exchange> {
         Box::new(Bitget {
             client: self.client.clone(),
+            rate_limiter: self.rate_limiter.clone(),
             api_key: self.api_key.clone(),
             api_secret: self.api_secret.clone(),
             passphrase: self.passphrase.clone(),
+            quote: self.quote.clone(),
         })
     }
 
-    async fn load_markets(&self) -> Result<HashMap<String, Market>, SendSyncError> {
+    fn quote(&self) -> &str {
+        &self.quote
+    }
+
+    async fn load_markets(&self) -> Result<HashMap<String, Market>, ExchangeError> {
         info!("Loading markets from Bitget");
         let url = format!(
             "{}/api/mix/v1/market/contracts?productType=umcbl",
             BITGET_API_URL
         );
-        let response = self.client.get(&url).send().await?.text().await?;
+        let response = self
+            .client
+            .get(&url)
+            .send_limited(&self.rate_limiter)
+            .await?
+            .text()
+            .await?;
         let bitget_response: BitgetResponse<Vec<BitgetMarket>> = serde_json::from_str(&response)?;
 
         if bitget_response.code != "0" {
             error!("Failed to load markets: {}", bitget_response.msg);
-            return Err(Box::new(std::io::Error::new(
-                std::io::ErrorKind::Other,
-                bitget_response.msg,
-            )));
+            return Err(ExchangeError::classify(bitget_response.msg));
         }
 
         let markets = bitget_response
@@ -111,6 +182,7 @@ impl Exchange for Bitget {
                         swap: true,
                         linear: true,
                         created_at: 0, // Bitget does not provide creation date
+                        settle: m.quote_coin,
                     },
                 )
             })
@@ -121,21 +193,24 @@ impl Exchange for Bitget {
 
     async fn fetch_tickers(
         &self, symbols: &[String],
-    ) -> Result<HashMap<String, Ticker>, SendSyncError> {
+    ) -> Result<HashMap<String, Ticker>, ExchangeError> {
         info!("Fetching tickers from Bitget");
         let url = format!(
             "{}/api/mix/v1/market/tickers?productType=umcbl",
             BITGET_API_URL
         );
-        let response = self.client.get(&url).send().await?.text().await?;
+        let response = self
+            .client
+            .get(&url)
+            .send_limited(&self.rate_limiter)
+            .await?
+            .text()
+            .await?;
         let bitget_response: BitgetResponse<Vec<BitgetTicker>> = serde_json::from_str(&response)?;
 
         if bitget_response.code != "0" {
             error!("Failed to fetch tickers: {}", bitget_response.msg);
-            return Err(Box::new(std::io::Error::new(
-                std::io::ErrorKind::Other,
-                bitget_response.msg,
-            )));
+            return Err(ExchangeError::classify(bitget_response.msg));
         }
 
         let tickers = bitget_response
@@ -151,6 +226,7 @@ impl Exchange for Bitget {
                         ask: t.best_ask_price.parse().unwrap_or(0.0),
                         last: t.last_price.parse().unwrap_or(0.0),
                         quote_volume: t.quote_volume.parse().unwrap_or(0.0),
+                        funding_rate: t.funding_rate.parse().unwrap_or(0.0),
                     },
                 )
             })
@@ -159,35 +235,37 @@ impl Exchange for Bitget {
         Ok(tickers)
     }
 
-    async fn fetch_ticker(&self, symbol: &str) -> Result<f64, SendSyncError> {
+    async fn fetch_ticker(&self, symbol: &str) -> Result<f64, ExchangeError> {
         info!("Fetching ticker for symbol: {}", symbol);
         let tickers = self.fetch_tickers(&[symbol.to_string()]).await?;
         if let Some(ticker) = tickers.get(symbol) {
             Ok(ticker.last)
         } else {
             error!("Ticker data not found for symbol: {}", symbol);
-            Err(Box::new(std::io::Error::new(
-                std::io::ErrorKind::Other,
+            Err(ExchangeError::classify(
                 "Ticker data not found in Bitget response",
-            )))
+            ))
         }
     }
 
-    async fn fetch_order_book(&self, symbol: &str) -> Result<OrderBook, SendSyncError> {
+    async fn fetch_order_book(&self, symbol: &str) -> Result<OrderBook, ExchangeError> {
         info!("Fetching order book for symbol: {}", symbol);
         let url = format!(
             "{}/api/mix/v1/market/depth?symbol={}&limit=100",
             BITGET_API_URL, symbol
         );
-        let response = self.client.get(&url).send().await?.text().await?;
+        let response = self
+            .client
+            .get(&url)
+            .send_limited(&self.rate_limiter)
+            .await?
+            .text()
+            .await?;
         let bitget_response: BitgetResponse<serde_json::Value> = serde_json::from_str(&response)?;
 
         if bitget_response.code != "0" {
             error!("Failed to fetch order book: {}", bitget_response.msg);
-            return Err(Box::new(std::io::Error::new(
-                std::io::ErrorKind::Other,
-                bitget_response.msg,
-            )));
+            return Err(ExchangeError::classify(bitget_response.msg));
         }
 
         let asks: Vec<[f64; 2]> = serde_json::from_value(bitget_response.data["asks"].clone())?;
@@ -196,7 +274,37 @@ impl Exchange for Bitget {
         Ok(OrderBook { bids, asks })
     }
 
-    async fn fetch_balance(&self) -> Result<f64, SendSyncError> {
+    async fn fetch_ohlcv(
+        &self, symbol: &str, timeframe: &str, since_ms: Option<i64>, limit: usize,
+    ) -> Result<Vec<Candle>, ExchangeError> {
+        let limit = limit.min(1000);
+        let span_ms = timeframe_ms(timeframe)? * limit as i64;
+        let now_ms = Utc::now().timestamp_millis();
+        let start_ms = since_ms.unwrap_or(now_ms - span_ms);
+        // hours and days are upper case, e.g. 1H and 1D
+        let granularity = timeframe.replace('h', "H").replace('d', "D");
+        let url = format!(
+            "{}/api/mix/v1/market/candles?symbol={}&granularity={}&startTime={}&endTime={}&limit={}",
+            BITGET_API_URL,
+            symbol,
+            granularity,
+            start_ms,
+            (start_ms + span_ms).min(now_ms),
+            limit
+        );
+        let response = self
+            .client
+            .get(&url)
+            .send_limited(&self.rate_limiter)
+            .await?
+            .text()
+            .await?;
+        // [ts, open, high, low, close, base volume, quote volume], oldest first
+        let klines: Vec<Vec<serde_json::Value>> = serde_json::from_str(&response)?;
+        klines.iter().map(|row| parse_candle_row(row)).collect()
+    }
+
+    async fn fetch_balance(&self) -> Result<f64, ExchangeError> {
         info!("Fetching balance from Bitget");
         let request_path = "/api/mix/v1/account/account";
         let params = "symbol=USDT_UMCBL";
@@ -211,7 +319,7 @@ impl Exchange for Bitget {
             .header("ACCESS-TIMESTAMP", &timestamp)
             .header("ACCESS-PASSPHRASE", &self.passphrase)
             .header("Content-Type", "application/json")
-            .send()
+            .send_limited(&self.rate_limiter)
             .await?
             .text()
             .await?;
@@ -220,10 +328,7 @@ impl Exchange for Bitget {
 
         if bitget_response.code != "0" {
             error!("Failed to fetch balance: {}", bitget_response.msg);
-            return Err(Box::new(std::io::Error::new(
-                std::io::ErrorKind::Other,
-                bitget_response.msg,
-            )));
+            return Err(ExchangeError::classify(bitget_response.msg));
         }
 
         let balance: f64 = bitget_response.data["usdtEquity"]
@@ -233,18 +338,21 @@ impl Exchange for Bitget {
         Ok(balance)
     }
 
-    async fn place_order(&mut self, order: &Order) -> Result<(), SendSyncError> {
+    async fn place_order(&mut self, order: &Order) -> Result<(), ExchangeError> {
         info!("Placing order: {:?}", order);
         let request_path = "/api/mix/v1/order/placeOrder";
 
         let mut order_request = std::collections::HashMap::new();
         order_request.insert("symbol", order.symbol.clone());
-        order_request.insert("marginCoin", "USDT".to_string());
+        order_request.insert("marginCoin", self.quote.clone());
         order_request.insert("size", order.qty.to_string());
         order_request.insert("price", order.price.to_string());
         order_request.insert("side", format!("{}_{}", order.side, order.position_side));
         order_request.insert("orderType", "limit".to_string());
         order_request.insert("timeInForceValue", order.time_in_force.clone());
+        if !order.client_order_id.is_empty() {
+            order_request.insert("clientOid", order.client_order_id.clone());
+        }
 
         let payload = serde_json::to_string(&order_request)?;
         let (timestamp, signature) = self.sign_request("POST", request_path, &payload);
@@ -258,7 +366,7 @@ impl Exchange for Bitget {
             .header("ACCESS-PASSPHRASE", &self.passphrase)
             .header("Content-Type", "application/json")
             .body(payload)
-            .send()
+            .send_limited(&self.rate_limiter)
             .await?
             .text()
             .await?;
@@ -270,21 +378,18 @@ impl Exchange for Bitget {
                 "Failed to place order: {}. Response: {}",
                 bitget_response.msg, response
             );
-            return Err(Box::new(std::io::Error::new(
-                std::io::ErrorKind::Other,
-                bitget_response.msg,
-            )));
+            return Err(ExchangeError::classify(bitget_response.msg));
         }
 
         Ok(())
     }
 
-    async fn cancel_order(&mut self, order_id: &str) -> Result<(), SendSyncError> {
-        info!("Canceling order: {}", order_id);
+    async fn cancel_order(&mut self, symbol: &str, order_id: &str) -> Result<(), ExchangeError> {
+        info!("[{}] Canceling order: {}", symbol, order_id);
         let request_path = "/api/mix/v1/order/cancelOrder";
 
         let mut order_request = std::collections::HashMap::new();
-        order_request.insert("symbol", "BTCUSDT_UMCBL".to_string()); // TODO: get from order
+        order_request.insert("symbol", symbol.to_string());
         order_request.insert("marginCoin", "USDT".to_string());
         order_request.insert("orderId", order_id.to_string());
 
@@ -300,7 +405,7 @@ impl Exchange for Bitget {
             .header("ACCESS-PASSPHRASE", &self.passphrase)
             .header("Content-Type", "application/json")
             .body(payload)
-            .send()
+            .send_limited(&self.rate_limiter)
             .await?
             .text()
             .await?;
@@ -312,19 +417,92 @@ impl Exchange for Bitget {
                 "Failed to cancel order: {}. Response: {}",
                 bitget_response.msg, response
             );
-            return Err(Box::new(std::io::Error::new(
-                std::io::ErrorKind::Other,
-                bitget_response.msg,
-            )));
+            return Err(ExchangeError::classify(bitget_response.msg));
         }
 
         Ok(())
     }
 
-    async fn fetch_position(&self, symbol: &str) -> Result<Position, SendSyncError> {
-        info!("Fetching position for symbol: {}", symbol);
+    async fn fetch_open_orders(&self, symbol: &str) -> Result<Vec<Order>, ExchangeError> {
+        let request_path = "/api/mix/v1/order/current";
+        let params = format!("symbol={}", symbol);
+        let url = format!("{}{}?{}", BITGET_API_URL, request_path, params);
+        let (timestamp, signature) =
+            self.sign_request("GET", &format!("{}?{}", request_path, params), "");
+
+        let response = self
+            .client
+            .get(&url)
+            .header("ACCESS-KEY", &self.api_key)
+            .header("ACCESS-SIGN", &signature)
+            .header("ACCESS-TIMESTAMP", &timestamp)
+            .header("ACCESS-PASSPHRASE", &self.passphrase)
+            .header("Content-Type", "application/json")
+            .send_limited(&self.rate_limiter)
+            .await?
+            .text()
+            .await?;
+
+        let bitget_response: BitgetResponse<Option<Vec<serde_json::Value>>> =
+            serde_json::from_str(&response)?;
+
+        if bitget_response.code != "0" {
+            error!("Failed to fetch open orders: {}", bitget_response.msg);
+            return Err(bitget_response.msg.into());
+        }
+
+        // amounts come as numbers, ids as strings
+        let number = |value: &serde_json::Value| -> Result<f64, ExchangeError> {
+            match value {
+                serde_json::Value::String(text) => Ok(text.parse()?),
+                value => value
+                    .as_f64()
+                    .ok_or_else(|| format!("Not a number: {}", value).into()),
+            }
+        };
+        let mut orders = Vec::new();
+        for open_order in bitget_response.data.unwrap_or_default() {
+            // sides are open_long, close_long, open_short and close_short
+            let (side, position_side, reduce_only) = match open_order["side"].as_str() {
+                Some("open_long") => ("Buy", "Long", false),
+                Some("close_long") => ("Sell", "Long", true),
+                Some("open_short") => ("Sell", "Short", false),
+                Some("close_short") => ("Buy", "Short", true),
+                other => return Err(format!("Unknown order side: {:?}", other).into()),
+            };
+            let filled = match open_order.get("filledQty") {
+                Some(filled) if !filled.is_null() => number(filled)?,
+                _ => 0.0,
+            };
+            orders.push(Order {
+                id: open_order["orderId"]
+                    .as_str()
+                    .unwrap_or_default()
+                    .to_string(),
+                symbol: symbol.to_string(),
+                side: side.to_string(),
+                position_side: position_side.to_string(),
+                qty: number(&open_order["size"])? - filled,
+                price: number(&open_order["price"])?,
+                reduce_only,
+                custom_id: String::new(),
+                client_order_id: open_order["clientOid"]
+                    .as_str()
+                    .unwrap_or_default()
+                    .to_string(),
+                time_in_force: open_order["timeInForce"]
+                    .as_str()
+                    .unwrap_or_default()
+                    .to_string(),
+            });
+        }
+        Ok(orders)
+    }
+
+    async fn fetch_positions(&self, symbol: &str) -> Result<PositionPair, ExchangeError> {
+        info!("Fetching positions for symbol: {}", symbol);
         let request_path = "/api/mix/v1/position/singlePosition";
-        let params = format!("symbol={}&marginCoin=USDT", symbol);
+        let params = format!("symbol={}&marginCoin={}", symbol, self.quote);
         let url = format!("{}{}?{}", BITGET_API_URL, request_path, params);
         let (timestamp, signature) = self.sign_request("GET", request_path, "");
 
@@ -336,7 +514,7 @@ impl Exchange for Bitget {
             .header("ACCESS-TIMESTAMP", &timestamp)
             .header("ACCESS-PASSPHRASE", &self.passphrase)
             .header("Content-Type", "application/json")
-            .send()
+            .send_limited(&self.rate_limiter)
             .await?
             .text()
             .await?;
@@ -346,41 +524,29 @@ impl Exchange for Bitget {
 
         if bitget_response.code != "0" {
             error!("Failed to fetch position: {}", bitget_response.msg);
-            return Err(Box::new(std::io::Error::new(
-                std::io::ErrorKind::Other,
-                bitget_response.msg,
-            )));
+            return Err(ExchangeError::classify(bitget_response.msg));
         }
 
-        if let Some(position_data) = bitget_response.data.get(0) {
-            let size: f64 = position_data["total"].as_str().unwrap_or("0").parse()?;
-            let price: f64 = position_data["averageOpenPrice"]
-                .as_str()
-                .unwrap_or("0")
-                .parse()?;
-            Ok(Position { size, price })
-        } else {
-            Ok(Position {
-                size: 0.0,
-                price: 0.0,
-            })
-        }
+        position_pair(&bitget_response.data)
     }
-    async fn fetch_exchange_params(&self, symbol: &str) -> Result<ExchangeParams, SendSyncError> {
+    async fn fetch_exchange_params(&self, symbol: &str) -> Result<ExchangeParams, ExchangeError> {
         info!("Fetching exchange params for symbol: {}", symbol);
         let url = format!(
             "{}/api/mix/v1/market/contracts?productType=umcbl",
             BITGET_API_URL
         );
-        let response = self.client.get(&url).send().await?.text().await?;
+        let response = self
+            .client
+            .get(&url)
+            .send_limited(&self.rate_limiter)
+            .await?
+            .text()
+            .await?;
         let bitget_response: BitgetResponse<Vec<BitgetMarket>> = serde_json::from_str(&response)?;
 
         if bitget_response.code != "0" {
             error!("Failed to fetch exchange params: {}", bitget_response.msg);
-            return Err(Box::new(std::io::Error::new(
-                std::io::ErrorKind::Other,
-                bitget_response.msg,
-            )));
+            return Err(ExchangeError::classify(bitget_response.msg));
         }
 
         if let Some(market) = bitget_response
@@ -397,10 +563,73 @@ impl Exchange for Bitget {
                 inverse: false, // Bitget futures are not inverse
             })
         } else {
-            Err(Box::new(std::io::Error::new(
-                std::io::ErrorKind::Other,
-                format!("Could not find market info for {}", symbol),
+            Err(ExchangeError::classify(format!(
+                "Could not find market info for {}",
+                symbol
             )))
         }
     }
+
+    async fn set_leverage(&mut self, symbol: &str, leverage: f64) -> Result<(), ExchangeError> {
+        info!("Setting leverage for {} to {}", symbol, leverage);
+        // isolated positions take a leverage per side, cross ones ignore the side
+        for hold_side in ["long", "short"] {
+            let fields = [
+                ("leverage", leverage.to_string()),
+                ("holdSide", hold_side.to_string()),
+            ];
+            self.post_setting("/api/mix/v1/account/setLeverage", symbol, &fields)
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn set_margin_mode(
+        &mut self, symbol: &str, mode: MarginMode,
+    ) -> Result<(), ExchangeError> {
+        info!("Setting {:?} margin for {}", mode, symbol);
+        let margin_mode = match mode {
+            MarginMode::Cross => "crossed",
+            MarginMode::Isolated => "fixed",
+        };
+        let fields = [("marginMode", margin_mode.to_string())];
+        self.post_setting("/api/mix/v1/account/setMarginMode", symbol, &fields)
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_position_pair_reports_the_mode_of_a_flat_account() {
+        let parse = |response: &str| {
+            let response: BitgetResponse<Vec<serde_json::Value>> =
+                serde_json::from_str(response).unwrap();
+            position_pair(&response.data).unwrap()
+        };
+        let flat = parse(
+            r#"{"code":"00000","msg":"success","requestTime":1718000000000,"data":[
+            {"marginCoin":"USDT","symbol":"BTCUSDT_UMCBL","holdSide":"long","openDelegateCount":"0",
+            "margin":"0","available":"0","locked":"0","total":"0","leverage":20,
+            "achievedProfits":"0","averageOpenPrice":"0","marginMode":"crossed",
+            "holdMode":"single_hold","unrealizedPL":"0","marketPrice":"64012.1"},
+            {"marginCoin":"USDT","symbol":"BTCUSDT_UMCBL","holdSide":"short","total":"0",
+            "averageOpenPrice":"0","holdMode":"single_hold"}]}"#,
+        );
+        assert!(flat.one_way);
+        assert_eq!((flat.long.size, flat.short.size), (0.0, 0.0));
+
+        let hedge = parse(
+            r#"{"code":"00000","msg":"success","requestTime":1718000000000,"data":[
+            {"symbol":"BTCUSDT_UMCBL","holdSide":"long","total":"0.02",
+            "averageOpenPrice":"63000","holdMode":"double_hold"},
+            {"symbol":"BTCUSDT_UMCBL","holdSide":"short","total":"0.01",
+            "averageOpenPrice":"65000","holdMode":"double_hold"}]}"#,
+        );
+        assert!(!hedge.one_way);
+        assert_eq!((hedge.long.size, hedge.long.price), (0.02, 63000.0));
+        assert_eq!((hedge.short.size, hedge.short.price), (-0.01, 65000.0));
+    }
 }
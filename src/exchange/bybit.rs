@@ -5,11 +5,23 @@ use std::collections::HashMap;
 use chrono::Utc;
 use hmac::{Hmac, Mac};
 use sha2::Sha256;
-use crate::types::{ExchangeParams, LiveConfig, Market, Ticker, Order, Position, OrderBook};
-use super::{Exchange, SendSyncError};
+use crate::types::{
+    ExchangeParams, LiveConfig, Market, Ticker, Order, Position, OrderBook, TradeFill,
+    FundingPayment, CancelRequest, PositionPair, CollateralAsset, PositionPnl, MarginMode,
+    ApiKeyInfo,
+};
+use crate::collateral::usable_collateral;
+use super::rate_limit::{RateLimiter, SendRateLimited};
+use super::{parse_candle_row, timeframe_ms, Exchange, ExchangeError};
+use crate::data::Candle;
 use tracing::{info, error, warn};
 
 const BYBIT_API_URL: &str = "https://api.bybit.com";
+/// Most orders a linear batch create or cancel request may carry.
+const MAX_BATCH_SIZE: usize = 20;
+/// retCodes of orders rejected by the risk limit tier: 110016, qty exceeds the risk limit;
+/// 110090, the position would exceed the max position value of the tier.
+const RISK_LIMIT_RET_CODES: [u32; 2] = [110016, 110090];
 
 #[derive(Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
@@ -18,6 +30,7 @@ struct BybitMarket {
     status: String,
     contract_type: String,
     quote_coin: String,
+    settle_coin: String,
     #[serde(rename = "createdTime")]
     created_at: String,
     #[serde(rename = "lotSizeFilter")]
@@ -54,6 +67,8 @@ struct BybitTicker {
     last_price: String,
     #[serde(rename = "volume24h")]
     volume_24h: String,
+    #[serde(default)]
+    funding_rate: String,
 }
 
 #[derive(Deserialize, Debug)]
@@ -73,6 +88,13 @@ struct BybitResponse<T> {
 #[derive(Deserialize, Debug)]
 struct BybitOrderBookEntry(String, String);
 
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct BybitKlineResult {
+    /// [start, open, high, low, close, volume, turnover], newest first
+    list: Vec<Vec<String>>,
+}
+
 #[derive(Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 struct BybitOrderBookResult {
@@ -89,13 +111,56 @@ struct BybitBalanceResult {
 #[derive(Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 struct BybitBalance {
-    total_wallet_balance: String,
+    coin: Vec<BybitCoinBalance>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct BybitCoinBalance {
+    coin: String,
+    wallet_balance: String,
+    /// Empty for coins without a USD price.
+    #[serde(default)]
+    usd_value: String,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct BybitCollateralInfoResult {
+    list: Vec<BybitCollateralInfo>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct BybitCollateralInfo {
+    currency: String,
+    collateral_ratio: String,
+    /// Whether the coin can be margin at all, and whether the account enabled it.
+    margin_collateral: bool,
+    collateral_switch: bool,
+}
+
+/// The API key's own settings: `read_only` is 1 for keys without any write permission.
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct BybitApiKeyInfo {
+    read_only: i64,
+    #[serde(default)]
+    permissions: HashMap<String, Vec<String>>,
+    /// Whitelisted IP addresses; "*" for keys usable from anywhere.
+    #[serde(default)]
+    ips: Vec<String>,
+    /// RFC 3339 time the key expires, empty for keys that do not.
+    #[serde(default)]
+    expired_at: String,
 }
 
 #[derive(Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 struct BybitPositionResult {
     list: Vec<BybitPosition>,
+    #[serde(default)]
+    next_page_cursor: String,
 }
 
 #[derive(Deserialize, Debug)]
@@ -105,6 +170,144 @@ struct BybitPosition {
     side: String,
     size: String,
     avg_price: String,
+    /// 0 in one-way mode; 1 for the long and 2 for the short leg in hedge mode.
+    #[serde(default)]
+    position_idx: i32,
+    #[serde(default)]
+    risk_limit_value: String,
+    #[serde(default)]
+    mark_price: String,
+    #[serde(default)]
+    unrealised_pnl: String,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct BybitRiskLimitResult {
+    list: Vec<BybitRiskLimitTier>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct BybitOpenOrderResult {
+    list: Vec<BybitOpenOrder>,
+    #[serde(default)]
+    next_page_cursor: String,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct BybitOpenOrder {
+    order_id: String,
+    order_link_id: String,
+    symbol: String,
+    side: String,
+    price: String,
+    qty: String,
+    /// Qty not yet filled.
+    leaves_qty: String,
+    reduce_only: bool,
+    position_idx: i32,
+    time_in_force: String,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct BybitClosedPnlResult {
+    list: Vec<BybitClosedPnl>,
+    #[serde(default)]
+    next_page_cursor: String,
+}
+
+/// One order that closed (part of) a position.
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct BybitClosedPnl {
+    order_id: String,
+    avg_entry_price: String,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct BybitExecutionResult {
+    list: Vec<BybitExecution>,
+    #[serde(default)]
+    next_page_cursor: String,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct BybitExecution {
+    exec_id: String,
+    order_id: String,
+    symbol: String,
+    side: String,
+    exec_type: String,
+    exec_qty: String,
+    exec_price: String,
+    exec_fee: String,
+    exec_time: String,
+    #[serde(default)]
+    closed_size: String,
+    #[serde(default)]
+    is_maker: Option<bool>,
+    #[serde(default)]
+    order_price: String,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct BybitTransactionLogResult {
+    list: Vec<BybitTransactionLog>,
+    #[serde(default)]
+    next_page_cursor: String,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct BybitTransactionLog {
+    symbol: String,
+    transaction_time: String,
+    /// Positive when funding was paid, negative when received.
+    funding: String,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct BybitRiskLimitTier {
+    id: i64,
+    risk_limit_value: String,
+}
+
+#[derive(serde::Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct BybitSetRiskLimitRequest {
+    category: String,
+    symbol: String,
+    risk_id: i64,
+}
+
+#[derive(serde::Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct BybitSetLeverageRequest {
+    category: String,
+    symbol: String,
+    buy_leverage: String,
+    sell_leverage: String,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct BybitAccountInfo {
+    /// "REGULAR_MARGIN", "ISOLATED_MARGIN" or "PORTFOLIO_MARGIN".
+    margin_mode: String,
+}
+
+#[derive(serde::Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct BybitSetMarginModeRequest {
+    /// "REGULAR_MARGIN" for cross, "ISOLATED_MARGIN" for isolated.
+    set_margin_mode: String,
 }
 
 #[derive(serde::Serialize, Debug)]
@@ -117,6 +320,10 @@ struct BybitOrderRequest {
     qty: String,
     price: Option<String>,
     time_in_force: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    order_link_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    position_idx: Option<i32>,
 }
 
 #[derive(serde::Serialize, Debug)]
@@ -127,21 +334,65 @@ struct BybitCancelOrderRequest {
     order_id: String,
 }
 
+#[derive(serde::Serialize, Debug)]
+struct BybitBatchRequest<T> {
+    category: String,
+    request: Vec<T>,
+}
+
+#[derive(Deserialize, Debug)]
+struct BybitBatchItemResult {
+    code: i32,
+    msg: String,
+}
+
+#[derive(Deserialize, Debug, Default)]
+struct BybitBatchExtInfo {
+    #[serde(default)]
+    list: Vec<BybitBatchItemResult>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct BybitBatchResponse {
+    ret_code: i32,
+    ret_msg: String,
+    /// One result per request, in order.
+    #[serde(default)]
+    ret_ext_info: BybitBatchExtInfo,
+}
+
 pub struct Bybit {
     client: reqwest::Client,
+    rate_limiter: RateLimiter,
     api_key: String,
     api_secret: String,
+    quote: String,
+    /// Routes orders to the long or short leg, for `live.pair_mode`.
+    hedge_mode: bool,
+    /// Reports usable collateral as the balance, for `live.cross_collateral`.
+    cross_collateral: bool,
 }
 
 impl Bybit {
-    pub fn new(_live_config: &LiveConfig, user_config: &UserConfig) -> Self {
+    pub fn new(live_config: &LiveConfig, user_config: &UserConfig) -> Self {
         Bybit {
             client: reqwest::Client::new(),
+            rate_limiter: RateLimiter::shared("bybit", &live_config.rate_limit),
             api_key: user_config.key.clone(),
             api_secret: user_config.secret.clone(),
+            quote: user_config.quote.clone(),
+            hedge_mode: live_config.pair_mode.enabled,
+            cross_collateral: live_config.cross_collateral,
         }
     }
 
+    /// The leg an order goes to in hedge mode; one-way accounts take none.
+    fn position_idx(&self, order: &Order) -> Option<i32> {
+        self.hedge_mode
+            .then(|| if order.position_side == "Long" { 1 } else { 2 })
+    }
+
     fn sign_request(&self, params: &str) -> (String, String) {
         let timestamp = Utc::now().timestamp_millis().to_string();
         let to_sign = format!("{}{}{}", timestamp, self.api_key, params);
@@ -154,6 +405,180 @@ impl Bybit {
         (timestamp, signature)
     }
 
+    async fn fetch_position_info(
+        &self, symbol: &str,
+    ) -> Result<Option<BybitPosition>, ExchangeError> {
+        Ok(self.fetch_position_list(symbol).await?.into_iter().next())
+    }
+
+    /// One entry in one-way mode, one per leg in hedge mode.
+    async fn fetch_position_list(&self, symbol: &str) -> Result<Vec<BybitPosition>, ExchangeError> {
+        self.query_position_list(&format!("category=linear&symbol={}", symbol))
+            .await
+    }
+
+    /// All pages of the position list matching `params`.
+    async fn query_position_list(&self, params: &str) -> Result<Vec<BybitPosition>, ExchangeError> {
+        let mut positions = Vec::new();
+        let mut cursor = String::new();
+        loop {
+            let mut page_params = format!("{}&limit=200", params);
+            if !cursor.is_empty() {
+                page_params.push_str(&format!("&cursor={}", cursor));
+            }
+            let (timestamp, signature) = self.sign_request(&page_params);
+            let url = format!("{}/v5/position/list?{}", BYBIT_API_URL, page_params);
+
+            let response = self
+                .client
+                .get(&url)
+                .header("X-BAPI-API-KEY", &self.api_key)
+                .header("X-BAPI-TIMESTAMP", timestamp)
+                .header("X-BAPI-SIGN", signature)
+                .send_limited(&self.rate_limiter)
+                .await?
+                .text()
+                .await?;
+
+            let bybit_response: BybitResponse<BybitPositionResult> =
+                serde_json::from_str(&response)?;
+
+            if bybit_response.ret_code != 0 {
+                error!("Failed to fetch position: {}", bybit_response.ret_msg);
+                return Err(bybit_response.ret_msg.into());
+            }
+            positions.extend(bybit_response.result.list);
+            cursor = bybit_response.result.next_page_cursor;
+            if cursor.is_empty() {
+                break;
+            }
+        }
+        Ok(positions)
+    }
+
+    /// Average entry price of each order of `symbol` that closed a position between
+    /// `start_ms` and `end_ms`, by order id.
+    async fn fetch_closed_entry_prices(
+        &self, symbol: &str, start_ms: i64, end_ms: i64,
+    ) -> Result<HashMap<String, f64>, ExchangeError> {
+        // the closed PnL list spans at most 7 days per query
+        const WINDOW_MS: i64 = 7 * 24 * 60 * 60 * 1000;
+        let mut entry_prices = HashMap::new();
+        let mut window_start = start_ms;
+        while window_start < end_ms {
+            let window_end = (window_start + WINDOW_MS).min(end_ms);
+            let mut cursor = String::new();
+            loop {
+                let mut params = format!(
+                    "category=linear&symbol={}&startTime={}&endTime={}&limit=100",
+                    symbol, window_start, window_end
+                );
+                if !cursor.is_empty() {
+                    params.push_str(&format!("&cursor={}", cursor));
+                }
+                let (timestamp, signature) = self.sign_request(&params);
+                let url = format!("{}/v5/position/closed-pnl?{}", BYBIT_API_URL, params);
+                let response = self
+                    .client
+                    .get(&url)
+                    .header("X-BAPI-API-KEY", &self.api_key)
+                    .header("X-BAPI-TIMESTAMP", timestamp)
+                    .header("X-BAPI-SIGN", signature)
+                    .send_limited(&self.rate_limiter)
+                    .await?
+                    .text()
+                    .await?;
+                let bybit_response: BybitResponse<BybitClosedPnlResult> =
+                    serde_json::from_str(&response)?;
+                if bybit_response.ret_code != 0 {
+                    error!("Failed to fetch closed PnL: {}", bybit_response.ret_msg);
+                    return Err(bybit_response.ret_msg.into());
+                }
+                for closed in bybit_response.result.list {
+                    entry_prices.insert(closed.order_id, closed.avg_entry_price.parse()?);
+                }
+                cursor = bybit_response.result.next_page_cursor;
+                if cursor.is_empty() {
+                    break;
+                }
+            }
+            window_start = window_end;
+        }
+        Ok(entry_prices)
+    }
+
+    /// The account's margin mode; unified accounts have one for all their positions.
+    async fn fetch_account_margin_mode(&self) -> Result<String, ExchangeError> {
+        let (timestamp, signature) = self.sign_request("");
+        let url = format!("{}/v5/account/info", BYBIT_API_URL);
+        let response = self
+            .client
+            .get(&url)
+            .header("X-BAPI-API-KEY", &self.api_key)
+            .header("X-BAPI-TIMESTAMP", timestamp)
+            .header("X-BAPI-SIGN", signature)
+            .send_limited(&self.rate_limiter)
+            .await?
+            .text()
+            .await?;
+        let account_info: BybitResponse<BybitAccountInfo> = serde_json::from_str(&response)?;
+        if account_info.ret_code != 0 {
+            error!("Failed to fetch account info: {}", account_info.ret_msg);
+            return Err(account_info.ret_msg.into());
+        }
+        Ok(account_info.result.margin_mode)
+    }
+
+    /// Sends one batch request to `path` and returns the outcome of each of its `n` items.
+    async fn post_batch<T: serde::Serialize>(
+        &self, path: &str, items: Vec<T>,
+    ) -> Result<Vec<Result<(), ExchangeError>>, ExchangeError> {
+        let n = items.len();
+        let request = BybitBatchRequest {
+            category: "linear".to_string(),
+            request: items,
+        };
+        let payload = serde_json::to_string(&request)?;
+        let (timestamp, recv_window, signature) = self.sign_post_request(&payload);
+        let response = self
+            .client
+            .post(format!("{}{}", BYBIT_API_URL, path))
+            .header("X-BAPI-API-KEY", &self.api_key)
+            .header("X-BAPI-TIMESTAMP", timestamp)
+            .header("X-BAPI-RECV-WINDOW", recv_window)
+            .header("X-BAPI-SIGN", signature)
+            .header("Content-Type", "application/json")
+            .body(payload)
+            .send_limited(&self.rate_limiter)
+            .await?
+            .text()
+            .await?;
+        let bybit_response: BybitBatchResponse = serde_json::from_str(&response)?;
+        if bybit_response.ret_code != 0 {
+            return Err(format!(
+                "{} (retCode {})",
+                bybit_response.ret_msg, bybit_response.ret_code
+            )
+            .into());
+        }
+        let results = bybit_response.ret_ext_info.list;
+        if results.len() != n {
+            return Err(format!(
+                "Batch response has {} results for {} orders",
+                results.len(),
+                n
+            )
+            .into());
+        }
+        Ok(results
+            .into_iter()
+            .map(|item| match item.code {
+                0 => Ok(()),
+                code => Err(format!("{} (retCode {})", item.msg, code).into()),
+            })
+            .collect())
+    }
+
     fn sign_post_request(&self, payload: &str) -> (String, String, String) {
         let timestamp = Utc::now().timestamp_millis().to_string();
         let recv_window = "5000";
@@ -166,6 +591,87 @@ impl Bybit {
         };
         (timestamp, recv_window.to_string(), signature)
     }
+
+    /// Sends `request` to `path`, succeeding on retCode 0 and on any of `unchanged`, the
+    /// codes for a setting that is already in place.
+    async fn post_setting<T: serde::Serialize>(
+        &self, path: &str, request: &T, unchanged: &[i32],
+    ) -> Result<(), ExchangeError> {
+        let payload = serde_json::to_string(request)?;
+        let (timestamp, recv_window, signature) = self.sign_post_request(&payload);
+        let response = self
+            .client
+            .post(format!("{}{}", BYBIT_API_URL, path))
+            .header("X-BAPI-API-KEY", &self.api_key)
+            .header("X-BAPI-TIMESTAMP", timestamp)
+            .header("X-BAPI-RECV-WINDOW", recv_window)
+            .header("X-BAPI-SIGN", signature)
+            .header("Content-Type", "application/json")
+            .body(payload)
+            .send_limited(&self.rate_limiter)
+            .await?
+            .text()
+            .await?;
+        let bybit_response: BybitResponse<serde_json::Value> = serde_json::from_str(&response)?;
+        match bybit_response.ret_code {
+            0 => Ok(()),
+            code if unchanged.contains(&code) => Ok(()),
+            code => Err(format!("{} (retCode {})", bybit_response.ret_msg, code).into()),
+        }
+    }
+}
+
+/// Refuses to switch the account's margin mode from `current` to `target` away from
+/// portfolio margin, or while any of `positions`, across all symbols, is open.
+fn check_margin_mode_switch(
+    current: &str, target: &str, positions: &[BybitPosition],
+) -> Result<(), ExchangeError> {
+    if current == "PORTFOLIO_MARGIN" {
+        return Err(format!(
+            "Not switching the account from portfolio margin to {}; leave live.margin_mode empty to keep it",
+            target
+        )
+        .into());
+    }
+    let open_positions: Vec<&str> = positions
+        .iter()
+        .filter(|p| p.size.parse::<f64>().unwrap_or(0.0) != 0.0)
+        .map(|p| p.symbol.as_str())
+        .collect();
+    if !open_positions.is_empty() {
+        return Err(format!(
+            "Not switching the whole account from {} to {} while positions are open ({})",
+            current,
+            target,
+            open_positions.join(", ")
+        )
+        .into());
+    }
+    Ok(())
+}
+
+/// The legs of a symbol's position list. A one-way account lists a single entry with
+/// index 0, also while flat.
+fn position_pair(list: Vec<BybitPosition>) -> Result<PositionPair, ExchangeError> {
+    let mut positions = PositionPair::default();
+    for position in list {
+        // sizes are unsigned, the side tells a short from a long
+        let size: f64 = position.size.parse()?;
+        let price: f64 = if position.avg_price.is_empty() {
+            0.0
+        } else {
+            position.avg_price.parse()?
+        };
+        match position.position_idx {
+            1 => positions.long = Position { size, price },
+            2 => positions.short = Position { size: -size, price },
+            _ => {
+                let size = if position.side == "Sell" { -size } else { size };
+                positions = PositionPair::from_net(Position { size, price });
+            }
+        }
+    }
+    Ok(positions)
 }
 
 #[async_trait]
@@ -173,18 +679,32 @@ impl Exchange for Bybit {
     fn clone_box(&self) -> Box<dyn Exchange> {
         Box::new(Bybit {
             client: self.client.clone(),
+            rate_limiter: self.rate_limiter.clone(),
             api_key: self.api_key.clone(),
             api_secret: self.api_secret.clone(),
+            quote: self.quote.clone(),
+            hedge_mode: self.hedge_mode,
+            cross_collateral: self.cross_collateral,
         })
     }
 
-    async fn load_markets(&self) -> Result<HashMap<String, Market>, SendSyncError> {
+    fn quote(&self) -> &str {
+        &self.quote
+    }
+
+    async fn load_markets(&self) -> Result<HashMap<String, Market>, ExchangeError> {
         info!("Loading markets");
         let url = format!(
             "{}/v5/market/instruments-info?category=linear",
             BYBIT_API_URL
         );
-        let response = self.client.get(&url).send().await?.text().await?;
+        let response = self
+            .client
+            .get(&url)
+            .send_limited(&self.rate_limiter)
+            .await?
+            .text()
+            .await?;
         let bybit_response: BybitResponse<BybitMarketResult> = serde_json::from_str(&response)?;
 
         if bybit_response.ret_code != 0 {
@@ -205,6 +725,7 @@ impl Exchange for Bybit {
                         swap: m.contract_type == "LinearPerpetual",
                         linear: m.contract_type == "LinearPerpetual",
                         created_at,
+                        settle: m.settle_coin,
                     },
                 )),
                 Err(_) => {
@@ -219,14 +740,20 @@ impl Exchange for Bybit {
 
     async fn fetch_tickers(
         &self, symbols: &[String],
-    ) -> Result<HashMap<String, Ticker>, SendSyncError> {
+    ) -> Result<HashMap<String, Ticker>, ExchangeError> {
         info!("Fetching tickers for symbols: {:?}", symbols);
         let url = format!(
             "{}/v5/market/tickers?category=linear&symbol={}",
             BYBIT_API_URL,
             symbols.join(",")
         );
-        let response = self.client.get(&url).send().await?.text().await?;
+        let response = self
+            .client
+            .get(&url)
+            .send_limited(&self.rate_limiter)
+            .await?
+            .text()
+            .await?;
         let bybit_response: BybitResponse<BybitTickerResult> = serde_json::from_str(&response)?;
 
         if bybit_response.ret_code != 0 {
@@ -253,6 +780,7 @@ impl Exchange for Bybit {
                             ask,
                             last,
                             quote_volume,
+                            funding_rate: t.funding_rate.parse().unwrap_or(0.0),
                         },
                     )),
                     _ => {
@@ -266,7 +794,7 @@ impl Exchange for Bybit {
         Ok(tickers)
     }
 
-    async fn fetch_ticker(&self, symbol: &str) -> Result<f64, SendSyncError> {
+    async fn fetch_ticker(&self, symbol: &str) -> Result<f64, ExchangeError> {
         info!("Fetching ticker for symbol: {}", symbol);
         let tickers = self.fetch_tickers(&[symbol.to_string()]).await?;
         if let Some(ticker) = tickers.get(symbol) {
@@ -277,13 +805,84 @@ impl Exchange for Bybit {
         }
     }
 
-    async fn fetch_order_book(&self, symbol: &str) -> Result<OrderBook, SendSyncError> {
+    async fn fetch_last_close(&self, symbol: &str) -> Result<Option<f64>, ExchangeError> {
+        let url = format!(
+            "{}/v5/market/kline?category=linear&symbol={}&interval=1&limit=1",
+            BYBIT_API_URL, symbol
+        );
+        let response = self
+            .client
+            .get(&url)
+            .send_limited(&self.rate_limiter)
+            .await?
+            .text()
+            .await?;
+        let bybit_response: BybitResponse<BybitKlineResult> = serde_json::from_str(&response)?;
+        if bybit_response.ret_code != 0 {
+            error!("Failed to fetch klines: {}", bybit_response.ret_msg);
+            return Err(bybit_response.ret_msg.into());
+        }
+        match bybit_response.result.list.first().and_then(|k| k.get(4)) {
+            Some(close) => Ok(Some(close.parse()?)),
+            None => Err(format!("No kline data for {}", symbol).into()),
+        }
+    }
+
+    async fn fetch_ohlcv(
+        &self, symbol: &str, timeframe: &str, since_ms: Option<i64>, limit: usize,
+    ) -> Result<Vec<Candle>, ExchangeError> {
+        let interval = match timeframe_ms(timeframe)? / 60_000 {
+            1440 => "D".to_string(),
+            10080 => "W".to_string(),
+            minutes => minutes.to_string(),
+        };
+        let mut url = format!(
+            "{}/v5/market/kline?category=linear&symbol={}&interval={}&limit={}",
+            BYBIT_API_URL,
+            symbol,
+            interval,
+            limit.min(1000)
+        );
+        if let Some(since_ms) = since_ms {
+            url.push_str(&format!("&start={}", since_ms));
+        }
+        let response = self
+            .client
+            .get(&url)
+            .send_limited(&self.rate_limiter)
+            .await?
+            .text()
+            .await?;
+        let bybit_response: BybitResponse<BybitKlineResult> = serde_json::from_str(&response)?;
+        if bybit_response.ret_code != 0 {
+            error!("Failed to fetch klines: {}", bybit_response.ret_msg);
+            return Err(bybit_response.ret_msg.into());
+        }
+        bybit_response
+            .result
+            .list
+            .iter()
+            .rev()
+            .map(|row| {
+                let row: Vec<serde_json::Value> = row.iter().map(|f| f.as_str().into()).collect();
+                parse_candle_row(&row)
+            })
+            .collect()
+    }
+
+    async fn fetch_order_book(&self, symbol: &str) -> Result<OrderBook, ExchangeError> {
         info!("Fetching order book for symbol: {}", symbol);
         let url = format!(
             "{}/v5/market/orderbook?category=linear&symbol={}",
             BYBIT_API_URL, symbol
         );
-        let response = self.client.get(&url).send().await?.text().await?;
+        let response = self
+            .client
+            .get(&url)
+            .send_limited(&self.rate_limiter)
+            .await?
+            .text()
+            .await?;
         let bybit_response: BybitResponse<BybitOrderBookResult> = serde_json::from_str(&response)?;
 
         if bybit_response.ret_code != 0 {
@@ -319,10 +918,16 @@ impl Exchange for Bybit {
         Ok(OrderBook { bids, asks })
     }
 
-    async fn fetch_balance(&self) -> Result<f64, SendSyncError> {
+    async fn fetch_balance(&self) -> Result<f64, ExchangeError> {
+        if self.cross_collateral {
+            return Ok(usable_collateral(&self.fetch_collateral().await?));
+        }
         info!("Fetching balance");
         let recv_window = 5000;
-        let params = format!("accountType=UNIFIED&recvWindow={}", recv_window);
+        let params = format!(
+            "accountType=UNIFIED&coin={}&recvWindow={}",
+            self.quote, recv_window
+        );
         let (timestamp, signature) = self.sign_request(&params);
         let url = format!("{}/v5/account/wallet-balance?{}", BYBIT_API_URL, params);
 
@@ -332,7 +937,7 @@ impl Exchange for Bybit {
             .header("X-BAPI-API-KEY", &self.api_key)
             .header("X-BAPI-TIMESTAMP", timestamp)
             .header("X-BAPI-SIGN", signature)
-            .send()
+            .send_limited(&self.rate_limiter)
             .await?
             .text()
             .await?;
@@ -344,15 +949,137 @@ impl Exchange for Bybit {
             return Err(bybit_response.ret_msg.into());
         }
 
-        if let Some(balance) = bybit_response.result.list.get(0) {
-            Ok(balance.total_wallet_balance.parse()?)
+        let coin_balance = bybit_response
+            .result
+            .list
+            .iter()
+            .flat_map(|account| &account.coin)
+            .find(|c| c.coin == self.quote);
+        if let Some(balance) = coin_balance {
+            Ok(balance.wallet_balance.parse()?)
         } else {
             error!("Balance data not found in Bybit response");
             Err("Balance data not found in Bybit response".into())
         }
     }
 
-    async fn place_order(&mut self, order: &Order) -> Result<(), SendSyncError> {
+    async fn fetch_api_key_info(&self) -> Result<Option<ApiKeyInfo>, ExchangeError> {
+        let (timestamp, signature) = self.sign_request("");
+        let url = format!("{}/v5/user/query-api", BYBIT_API_URL);
+        let response = self
+            .client
+            .get(&url)
+            .header("X-BAPI-API-KEY", &self.api_key)
+            .header("X-BAPI-TIMESTAMP", timestamp)
+            .header("X-BAPI-SIGN", signature)
+            .send_limited(&self.rate_limiter)
+            .await?
+            .text()
+            .await?;
+        let key_info: BybitResponse<BybitApiKeyInfo> = serde_json::from_str(&response)?;
+        if key_info.ret_code != 0 {
+            error!("Failed to fetch API key info: {}", key_info.ret_msg);
+            return Err(key_info.ret_msg.into());
+        }
+        let key_info = key_info.result;
+        let permission = |group: &str| key_info.permissions.get(group).cloned().unwrap_or_default();
+        Ok(Some(ApiKeyInfo {
+            can_trade: key_info.read_only == 0 && !permission("ContractTrade").is_empty(),
+            can_withdraw: permission("Wallet").iter().any(|p| p == "Withdraw"),
+            ip_restricted: !key_info.ips.is_empty() && !key_info.ips.iter().any(|ip| ip == "*"),
+            expires_at_ms: chrono::DateTime::parse_from_rfc3339(&key_info.expired_at)
+                .ok()
+                .map(|t| t.timestamp_millis()),
+        }))
+    }
+
+    async fn fetch_collateral(&self) -> Result<Vec<CollateralAsset>, ExchangeError> {
+        info!("Fetching collateral");
+        let params = "accountType=UNIFIED";
+        let (timestamp, signature) = self.sign_request(params);
+        let url = format!("{}/v5/account/wallet-balance?{}", BYBIT_API_URL, params);
+        let response = self
+            .client
+            .get(&url)
+            .header("X-BAPI-API-KEY", &self.api_key)
+            .header("X-BAPI-TIMESTAMP", timestamp)
+            .header("X-BAPI-SIGN", signature)
+            .send_limited(&self.rate_limiter)
+            .await?
+            .text()
+            .await?;
+        let balances: BybitResponse<BybitBalanceResult> = serde_json::from_str(&response)?;
+        if balances.ret_code != 0 {
+            error!("Failed to fetch wallet balance: {}", balances.ret_msg);
+            return Err(balances.ret_msg.into());
+        }
+
+        let (timestamp, signature) = self.sign_request("");
+        let url = format!("{}/v5/account/collateral-info", BYBIT_API_URL);
+        let response = self
+            .client
+            .get(&url)
+            .header("X-BAPI-API-KEY", &self.api_key)
+            .header("X-BAPI-TIMESTAMP", timestamp)
+            .header("X-BAPI-SIGN", signature)
+            .send_limited(&self.rate_limiter)
+            .await?
+            .text()
+            .await?;
+        let collateral_info: BybitResponse<BybitCollateralInfoResult> =
+            serde_json::from_str(&response)?;
+        if collateral_info.ret_code != 0 {
+            error!(
+                "Failed to fetch collateral info: {}",
+                collateral_info.ret_msg
+            );
+            return Err(collateral_info.ret_msg.into());
+        }
+        let haircuts: HashMap<String, f64> = collateral_info
+            .result
+            .list
+            .into_iter()
+            .map(|info| {
+                let ratio = if info.margin_collateral && info.collateral_switch {
+                    info.collateral_ratio.parse().unwrap_or(0.0)
+                } else {
+                    0.0
+                };
+                (info.currency, 1.0 - ratio)
+            })
+            .collect();
+
+        balances
+            .result
+            .list
+            .iter()
+            .flat_map(|account| &account.coin)
+            .map(|coin| {
+                let quantity: f64 = coin.wallet_balance.parse()?;
+                let value = if coin.coin == self.quote {
+                    quantity
+                } else if coin.usd_value.is_empty() {
+                    0.0
+                } else {
+                    coin.usd_value.parse()?
+                };
+                // the quote coin is margin at face value; unlisted coins are not margin
+                let haircut = if coin.coin == self.quote {
+                    0.0
+                } else {
+                    haircuts.get(&coin.coin).copied().unwrap_or(1.0)
+                };
+                Ok(CollateralAsset {
+                    asset: coin.coin.clone(),
+                    quantity,
+                    value,
+                    haircut,
+                })
+            })
+            .collect()
+    }
+
+    async fn place_order(&mut self, order: &Order) -> Result<(), ExchangeError> {
         info!("Placing order: {:?}", order);
         let order_request = BybitOrderRequest {
             category: "linear".to_string(),
@@ -362,6 +1089,8 @@ impl Exchange for Bybit {
             qty: order.qty.to_string(),
             price: Some(order.price.to_string()),
             time_in_force: order.time_in_force.clone(),
+            order_link_id: order.client_order_id.clone(),
+            position_idx: self.position_idx(order),
         };
 
         let payload = serde_json::to_string(&order_request)?;
@@ -376,7 +1105,7 @@ impl Exchange for Bybit {
             .header("X-BAPI-SIGN", signature)
             .header("Content-Type", "application/json")
             .body(payload)
-            .send()
+            .send_limited(&self.rate_limiter)
             .await?
             .text()
             .await?;
@@ -388,17 +1117,21 @@ impl Exchange for Bybit {
                 "Failed to place order: {}. Response: {}",
                 bybit_response.ret_msg, response
             );
-            return Err(bybit_response.ret_msg.into());
+            return Err(format!(
+                "{} (retCode {})",
+                bybit_response.ret_msg, bybit_response.ret_code
+            )
+            .into());
         }
 
         Ok(())
     }
 
-    async fn cancel_order(&mut self, order_id: &str) -> Result<(), SendSyncError> {
-        info!("Canceling order: {}", order_id);
+    async fn cancel_order(&mut self, symbol: &str, order_id: &str) -> Result<(), ExchangeError> {
+        info!("[{}] Canceling order: {}", symbol, order_id);
         let cancel_request = BybitCancelOrderRequest {
             category: "linear".to_string(),
-            symbol: "BTCUSDT".to_string(), // TODO: Get from order
+            symbol: symbol.to_string(),
             order_id: order_id.to_string(),
         };
 
@@ -414,7 +1147,7 @@ impl Exchange for Bybit {
             .header("X-BAPI-SIGN", signature)
             .header("Content-Type", "application/json")
             .body(payload)
-            .send()
+            .send_limited(&self.rate_limiter)
             .await?
             .text()
             .await?;
@@ -429,49 +1162,142 @@ impl Exchange for Bybit {
         Ok(())
     }
 
-    async fn fetch_position(&self, symbol: &str) -> Result<Position, SendSyncError> {
-        info!("Fetching position for symbol: {}", symbol);
-        let params = format!("category=linear&symbol={}", symbol);
-        let (timestamp, signature) = self.sign_request(&params);
-        let url = format!("{}/v5/position/list?{}", BYBIT_API_URL, params);
-
-        let response = self
-            .client
-            .get(&url)
-            .header("X-BAPI-API-KEY", &self.api_key)
-            .header("X-BAPI-TIMESTAMP", timestamp)
-            .header("X-BAPI-SIGN", signature)
-            .send()
-            .await?
-            .text()
-            .await?;
+    async fn place_orders(&mut self, orders: &[Order]) -> Vec<Result<(), ExchangeError>> {
+        let mut results = Vec::with_capacity(orders.len());
+        for chunk in orders.chunks(MAX_BATCH_SIZE) {
+            info!("Placing {} orders in a batch", chunk.len());
+            let items = chunk
+                .iter()
+                .map(|order| BybitOrderRequest {
+                    category: "linear".to_string(),
+                    symbol: order.symbol.clone(),
+                    side: order.side.clone(),
+                    order_type: "Limit".to_string(),
+                    qty: order.qty.to_string(),
+                    price: Some(order.price.to_string()),
+                    time_in_force: order.time_in_force.clone(),
+                    order_link_id: order.client_order_id.clone(),
+                    position_idx: self.position_idx(order),
+                })
+                .collect();
+            match self.post_batch("/v5/order/create-batch", items).await {
+                Ok(chunk_results) => results.extend(chunk_results),
+                Err(e) => {
+                    error!("Failed to place batch of orders: {}", e);
+                    results.extend(chunk.iter().map(|_| Err(e.clone())));
+                }
+            }
+        }
+        results
+    }
 
-        let bybit_response: BybitResponse<BybitPositionResult> = serde_json::from_str(&response)?;
+    async fn cancel_orders(&mut self, cancels: &[CancelRequest]) -> Vec<Result<(), ExchangeError>> {
+        let mut results = Vec::with_capacity(cancels.len());
+        for chunk in cancels.chunks(MAX_BATCH_SIZE) {
+            info!("Canceling {} orders in a batch", chunk.len());
+            let items = chunk
+                .iter()
+                .map(|cancel| BybitCancelOrderRequest {
+                    category: "linear".to_string(),
+                    symbol: cancel.symbol.clone(),
+                    order_id: cancel.order_id.clone(),
+                })
+                .collect();
+            match self.post_batch("/v5/order/cancel-batch", items).await {
+                Ok(chunk_results) => results.extend(chunk_results),
+                Err(e) => {
+                    error!("Failed to cancel batch of orders: {}", e);
+                    results.extend(chunk.iter().map(|_| Err(e.clone())));
+                }
+            }
+        }
+        results
+    }
 
-        if bybit_response.ret_code != 0 {
-            error!("Failed to fetch position: {}", bybit_response.ret_msg);
-            return Err(bybit_response.ret_msg.into());
+    async fn fetch_open_orders(&self, symbol: &str) -> Result<Vec<Order>, ExchangeError> {
+        let mut orders = Vec::new();
+        let mut cursor = String::new();
+        loop {
+            let mut params = format!("category=linear&symbol={}&limit=50", symbol);
+            if !cursor.is_empty() {
+                params.push_str(&format!("&cursor={}", cursor));
+            }
+            let (timestamp, signature) = self.sign_request(&params);
+            let url = format!("{}/v5/order/realtime?{}", BYBIT_API_URL, params);
+            let response = self
+                .client
+                .get(&url)
+                .header("X-BAPI-API-KEY", &self.api_key)
+                .header("X-BAPI-TIMESTAMP", timestamp)
+                .header("X-BAPI-SIGN", signature)
+                .send_limited(&self.rate_limiter)
+                .await?
+                .text()
+                .await?;
+            let bybit_response: BybitResponse<BybitOpenOrderResult> =
+                serde_json::from_str(&response)?;
+            if bybit_response.ret_code != 0 {
+                error!("Failed to fetch open orders: {}", bybit_response.ret_msg);
+                return Err(bybit_response.ret_msg.into());
+            }
+            for open_order in bybit_response.result.list {
+                let position_side = match open_order.position_idx {
+                    1 => "Long",
+                    2 => "Short",
+                    _ => "",
+                };
+                orders.push(Order {
+                    id: open_order.order_id,
+                    symbol: open_order.symbol,
+                    side: open_order.side,
+                    position_side: position_side.to_string(),
+                    qty: open_order.leaves_qty.parse()?,
+                    price: open_order.price.parse()?,
+                    reduce_only: open_order.reduce_only,
+                    custom_id: String::new(),
+                    client_order_id: open_order.order_link_id,
+                    time_in_force: open_order.time_in_force,
+                });
+            }
+            cursor = bybit_response.result.next_page_cursor;
+            if cursor.is_empty() {
+                break;
+            }
         }
+        Ok(orders)
+    }
 
-        if let Some(position) = bybit_response.result.list.get(0) {
-            Ok(Position {
-                size: position.size.parse()?,
-                price: position.avg_price.parse()?,
-            })
-        } else {
-            Ok(Position {
-                size: 0.0,
-                price: 0.0,
-            })
+    async fn fetch_positions(&self, symbol: &str) -> Result<PositionPair, ExchangeError> {
+        info!("Fetching positions for symbol: {}", symbol);
+        position_pair(self.fetch_position_list(symbol).await?)
+    }
+    async fn fetch_position_pnl(&self, symbol: &str) -> Result<Option<PositionPnl>, ExchangeError> {
+        let Some(position) = self.fetch_position_info(symbol).await? else {
+            return Ok(None);
+        };
+        let size: f64 = position.size.parse()?;
+        if size == 0.0 || position.unrealised_pnl.is_empty() {
+            return Ok(None);
         }
+        Ok(Some(PositionPnl {
+            size: if position.side == "Sell" { -size } else { size },
+            mark_price: position.mark_price.parse()?,
+            unrealized_pnl: position.unrealised_pnl.parse()?,
+        }))
     }
-    async fn fetch_exchange_params(&self, symbol: &str) -> Result<ExchangeParams, SendSyncError> {
+    async fn fetch_exchange_params(&self, symbol: &str) -> Result<ExchangeParams, ExchangeError> {
         info!("Fetching exchange params for symbol: {}", symbol);
         let url = format!(
             "{}/v5/market/instruments-info?category=linear&symbol={}",
             BYBIT_API_URL, symbol
         );
-        let response = self.client.get(&url).send().await?.text().await?;
+        let response = self
+            .client
+            .get(&url)
+            .send_limited(&self.rate_limiter)
+            .await?
+            .text()
+            .await?;
         let bybit_response: BybitResponse<BybitMarketResult> = serde_json::from_str(&response)?;
 
         if bybit_response.ret_code != 0 {
@@ -495,4 +1321,423 @@ impl Exchange for Bybit {
             Err(format!("Could not find market info for {}", symbol).into())
         }
     }
+
+    fn is_precision_error(&self, error: &str) -> bool {
+        // 110094: order value below the minimum; 10001 covers invalid qty and price
+        let error = error.to_lowercase();
+        error.contains("retcode 110094")
+            || error.contains("qty invalid")
+            || error.contains("price invalid")
+            || error.contains("too many decimals")
+    }
+
+    fn is_risk_limit_error(&self, error: &str) -> bool {
+        RISK_LIMIT_RET_CODES
+            .iter()
+            .any(|code| error.contains(&format!("(retCode {})", code)))
+    }
+
+    fn has_fill_history(&self) -> bool {
+        true
+    }
+
+    async fn fetch_fill_history(
+        &self, symbol: &str, start_ms: i64, end_ms: i64,
+    ) -> Result<Vec<TradeFill>, ExchangeError> {
+        // the execution list spans at most 7 days per query
+        const WINDOW_MS: i64 = 7 * 24 * 60 * 60 * 1000;
+        let entry_prices = self
+            .fetch_closed_entry_prices(symbol, start_ms, end_ms)
+            .await?;
+        let mut fills = Vec::new();
+        let mut window_start = start_ms;
+        while window_start < end_ms {
+            let window_end = (window_start + WINDOW_MS).min(end_ms);
+            let mut cursor = String::new();
+            loop {
+                let mut params = format!(
+                    "category=linear&symbol={}&startTime={}&endTime={}&limit=100",
+                    symbol, window_start, window_end
+                );
+                if !cursor.is_empty() {
+                    params.push_str(&format!("&cursor={}", cursor));
+                }
+                let (timestamp, signature) = self.sign_request(&params);
+                let url = format!("{}/v5/execution/list?{}", BYBIT_API_URL, params);
+                let response = self
+                    .client
+                    .get(&url)
+                    .header("X-BAPI-API-KEY", &self.api_key)
+                    .header("X-BAPI-TIMESTAMP", timestamp)
+                    .header("X-BAPI-SIGN", signature)
+                    .send_limited(&self.rate_limiter)
+                    .await?
+                    .text()
+                    .await?;
+                let bybit_response: BybitResponse<BybitExecutionResult> =
+                    serde_json::from_str(&response)?;
+                if bybit_response.ret_code != 0 {
+                    error!("Failed to fetch executions: {}", bybit_response.ret_msg);
+                    return Err(bybit_response.ret_msg.into());
+                }
+                for execution in bybit_response.result.list {
+                    if execution.exec_type != "Trade" {
+                        continue;
+                    }
+                    // executions do not carry the position side; a fill that closed
+                    // something belongs to the opposite side
+                    let closed_size = execution.closed_size.parse::<f64>().unwrap_or(0.0);
+                    let closing = closed_size > 0.0;
+                    let position_side = match (execution.side.as_str(), closing) {
+                        ("Buy", false) | ("Sell", true) => "Long",
+                        _ => "Short",
+                    };
+                    let exec_price: f64 = execution.exec_price.parse()?;
+                    // realized against the entry price the exchange closed at
+                    let realized_pnl = match entry_prices.get(&execution.order_id) {
+                        Some(entry_price) if closing => {
+                            let direction = if position_side == "Long" { 1.0 } else { -1.0 };
+                            Some(closed_size * (exec_price - entry_price) * direction)
+                        }
+                        _ if closing => None,
+                        _ => Some(0.0),
+                    };
+                    fills.push(TradeFill {
+                        id: execution.exec_id,
+                        order_id: execution.order_id,
+                        symbol: execution.symbol,
+                        timestamp_ms: execution.exec_time.parse()?,
+                        side: execution.side,
+                        position_side: position_side.to_string(),
+                        qty: execution.exec_qty.parse()?,
+                        price: exec_price,
+                        fee: execution.exec_fee.parse()?,
+                        realized_pnl,
+                        maker: execution.is_maker,
+                        order_price: execution.order_price.parse().unwrap_or(0.0),
+                    });
+                }
+                cursor = bybit_response.result.next_page_cursor;
+                if cursor.is_empty() {
+                    break;
+                }
+            }
+            window_start = window_end;
+        }
+        fills.sort_by_key(|f| f.timestamp_ms);
+        Ok(fills)
+    }
+
+    async fn fetch_funding_payments(
+        &self, symbol: &str, start_ms: i64, end_ms: i64,
+    ) -> Result<Vec<FundingPayment>, ExchangeError> {
+        // the transaction log spans at most 7 days per query
+        const WINDOW_MS: i64 = 7 * 24 * 60 * 60 * 1000;
+        let mut payments = Vec::new();
+        let mut window_start = start_ms;
+        while window_start < end_ms {
+            let window_end = (window_start + WINDOW_MS).min(end_ms);
+            let mut cursor = String::new();
+            loop {
+                let mut params = format!(
+                    "accountType=UNIFIED&category=linear&currency={}&type=SETTLEMENT&startTime={}&endTime={}&limit=50",
+                    self.quote, window_start, window_end
+                );
+                if !cursor.is_empty() {
+                    params.push_str(&format!("&cursor={}", cursor));
+                }
+                let (timestamp, signature) = self.sign_request(&params);
+                let url = format!("{}/v5/account/transaction-log?{}", BYBIT_API_URL, params);
+                let response = self
+                    .client
+                    .get(&url)
+                    .header("X-BAPI-API-KEY", &self.api_key)
+                    .header("X-BAPI-TIMESTAMP", timestamp)
+                    .header("X-BAPI-SIGN", signature)
+                    .send_limited(&self.rate_limiter)
+                    .await?
+                    .text()
+                    .await?;
+                let bybit_response: BybitResponse<BybitTransactionLogResult> =
+                    serde_json::from_str(&response)?;
+                if bybit_response.ret_code != 0 {
+                    error!(
+                        "Failed to fetch transaction log: {}",
+                        bybit_response.ret_msg
+                    );
+                    return Err(bybit_response.ret_msg.into());
+                }
+                for log in bybit_response.result.list {
+                    if log.symbol != symbol {
+                        continue;
+                    }
+                    payments.push(FundingPayment {
+                        symbol: log.symbol,
+                        timestamp_ms: log.transaction_time.parse()?,
+                        amount: log.funding.parse().unwrap_or(0.0),
+                    });
+                }
+                cursor = bybit_response.result.next_page_cursor;
+                if cursor.is_empty() {
+                    break;
+                }
+            }
+            window_start = window_end;
+        }
+        payments.sort_by_key(|p| p.timestamp_ms);
+        Ok(payments)
+    }
+
+    async fn fetch_risk_limit(&self, symbol: &str) -> Result<Option<f64>, ExchangeError> {
+        Ok(self
+            .fetch_position_info(symbol)
+            .await?
+            .and_then(|p| p.risk_limit_value.parse().ok()))
+    }
+
+    async fn raise_risk_limit(&mut self, symbol: &str) -> Result<Option<f64>, ExchangeError> {
+        let Some(current) = self.fetch_risk_limit(symbol).await? else {
+            return Ok(None);
+        };
+        let url = format!(
+            "{}/v5/market/risk-limit?category=linear&symbol={}",
+            BYBIT_API_URL, symbol
+        );
+        let response = self
+            .client
+            .get(&url)
+            .send_limited(&self.rate_limiter)
+            .await?
+            .text()
+            .await?;
+        let bybit_response: BybitResponse<BybitRiskLimitResult> = serde_json::from_str(&response)?;
+        if bybit_response.ret_code != 0 {
+            return Err(bybit_response.ret_msg.into());
+        }
+        let next_tier = bybit_response
+            .result
+            .list
+            .iter()
+            .filter_map(|tier| Some((tier.id, tier.risk_limit_value.parse::<f64>().ok()?)))
+            .filter(|(_, value)| *value > current)
+            .min_by(|a, b| a.1.total_cmp(&b.1));
+        let Some((risk_id, max_position_value)) = next_tier else {
+            return Ok(None);
+        };
+
+        info!(
+            "Raising risk limit for {} to tier {} (max position value {})",
+            symbol, risk_id, max_position_value
+        );
+        let request = BybitSetRiskLimitRequest {
+            category: "linear".to_string(),
+            symbol: symbol.to_string(),
+            risk_id,
+        };
+        let payload = serde_json::to_string(&request)?;
+        let (timestamp, recv_window, signature) = self.sign_post_request(&payload);
+        let response = self
+            .client
+            .post(format!("{}/v5/position/set-risk-limit", BYBIT_API_URL))
+            .header("X-BAPI-API-KEY", &self.api_key)
+            .header("X-BAPI-TIMESTAMP", timestamp)
+            .header("X-BAPI-RECV-WINDOW", recv_window)
+            .header("X-BAPI-SIGN", signature)
+            .header("Content-Type", "application/json")
+            .body(payload)
+            .send_limited(&self.rate_limiter)
+            .await?
+            .text()
+            .await?;
+        let bybit_response: BybitResponse<serde_json::Value> = serde_json::from_str(&response)?;
+        if bybit_response.ret_code != 0 {
+            return Err(bybit_response.ret_msg.into());
+        }
+        Ok(Some(max_position_value))
+    }
+
+    async fn set_leverage(&mut self, symbol: &str, leverage: f64) -> Result<(), ExchangeError> {
+        info!("Setting leverage for {} to {}", symbol, leverage);
+        let request = BybitSetLeverageRequest {
+            category: "linear".to_string(),
+            symbol: symbol.to_string(),
+            buy_leverage: leverage.to_string(),
+            sell_leverage: leverage.to_string(),
+        };
+        // 110043: leverage not modified
+        self.post_setting("/v5/position/set-leverage", &request, &[110043])
+            .await
+    }
+
+    async fn set_margin_mode(
+        &mut self, symbol: &str, mode: MarginMode,
+    ) -> Result<(), ExchangeError> {
+        // unified accounts set the margin mode for the whole account, so it is only
+        // changed while no position of any symbol depends on the current one
+        let target = match mode {
+            MarginMode::Cross => "REGULAR_MARGIN",
+            MarginMode::Isolated => "ISOLATED_MARGIN",
+        };
+        let current = self.fetch_account_margin_mode().await?;
+        if current == target {
+            return Ok(());
+        }
+        let positions = self
+            .query_position_list(&format!("category=linear&settleCoin={}", self.quote))
+            .await?;
+        check_margin_mode_switch(&current, target, &positions)?;
+        info!(
+            "Setting {:?} margin for the account, starting with {}",
+            mode, symbol
+        );
+        let request = BybitSetMarginModeRequest {
+            set_margin_mode: target.to_string(),
+        };
+        // 110026: margin mode not modified
+        self.post_setting("/v5/account/set-margin-mode", &request, &[110026])
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_risk_limit_errors_match_ret_codes() {
+        let live_config = crate::config::load_config("config.hjson").unwrap().live;
+        let user_config: UserConfig =
+            serde_json::from_value(serde_json::json!({ "exchange": "bybit" })).unwrap();
+        let bybit = Bybit::new(&live_config, &user_config);
+
+        assert!(bybit.is_risk_limit_error(
+            "Order failed: position may exceed the max limit allowed (retCode 110090)"
+        ));
+        assert!(bybit.is_risk_limit_error("Qty exceeds the risk limit (retCode 110016)"));
+        // other rejections merely mentioning the risk limit
+        assert!(!bybit.is_risk_limit_error("risk limit not modified (retCode 110075)"));
+        assert!(!bybit.is_risk_limit_error("Cannot set leverage due to risk limit level"));
+        assert!(!bybit.is_risk_limit_error("unknown error (retCode 1100900)"));
+    }
+
+    #[tokio::test]
+    async fn test_failed_batch_transport_does_not_quarantine() {
+        use crate::events::EventBus;
+        use crate::exchange::intent_log::IntentLog;
+        use crate::exchange::quarantine::SymbolQuarantine;
+        use crate::exchange::queue::{BatchLimits, OrderQueue};
+
+        let live_config = crate::config::load_config("config.hjson").unwrap().live;
+        let user_config: UserConfig =
+            serde_json::from_value(serde_json::json!({ "exchange": "bybit" })).unwrap();
+        let mut bybit = Bybit::new(&live_config, &user_config);
+        // the proxy never resolves, so every request fails before reaching the exchange
+        bybit.client = reqwest::Client::builder()
+            .proxy(reqwest::Proxy::all("http://proxy.invalid:1").unwrap())
+            .build()
+            .unwrap();
+        let entry = |price: f64| Order {
+            symbol: "BTCUSDT".to_string(),
+            side: "Buy".to_string(),
+            qty: 0.001,
+            price,
+            ..Default::default()
+        };
+        let orders = [entry(100.0), entry(99.0), entry(98.0)];
+        let cancels = [CancelRequest {
+            symbol: "BTCUSDT".to_string(),
+            order_id: "1".to_string(),
+        }];
+        for result in bybit.place_orders(&orders).await {
+            assert!(matches!(result, Err(ExchangeError::Network(_))));
+        }
+        for result in bybit.cancel_orders(&cancels).await {
+            assert!(matches!(result, Err(ExchangeError::Network(_))));
+        }
+
+        let quarantine = SymbolQuarantine::new(3, std::time::Duration::from_secs(3600));
+        let queue = OrderQueue::spawn(
+            bybit.clone_box(),
+            quarantine.clone(),
+            IntentLog::open(None),
+            EventBus::default(),
+            BatchLimits::default(),
+        );
+        for order in orders {
+            queue.place(order).unwrap();
+        }
+        while queue.depth() > 0 {
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        assert!(!quarantine.is_quarantined("BTCUSDT"));
+    }
+
+    #[test]
+    fn test_position_pair_reports_the_mode_of_a_flat_account() {
+        let parse = |response: &str| {
+            let response: BybitResponse<BybitPositionResult> =
+                serde_json::from_str(response).unwrap();
+            position_pair(response.result.list).unwrap()
+        };
+        // a flat one-way account still lists its position at index 0
+        let flat = parse(
+            r#"{"retCode":0,"retMsg":"OK","result":{"list":[{"positionIdx":0,"riskId":1,
+            "riskLimitValue":"2000000","symbol":"BTCUSDT","side":"","size":"0","avgPrice":"",
+            "positionValue":"","markPrice":"64012.1","unrealisedPnl":""}],
+            "nextPageCursor":"","category":"linear"},"time":1718000000000}"#,
+        );
+        assert!(flat.one_way);
+        assert_eq!((flat.long.size, flat.short.size), (0.0, 0.0));
+
+        let short = parse(
+            r#"{"retCode":0,"retMsg":"OK","result":{"list":[{"positionIdx":0,
+            "symbol":"BTCUSDT","side":"Sell","size":"0.015","avgPrice":"64100.5",
+            "markPrice":"64012.1","unrealisedPnl":"1.32"}],"nextPageCursor":""},
+            "time":1718000000000}"#,
+        );
+        assert!(short.one_way);
+        assert_eq!((short.short.size, short.short.price), (-0.015, 64100.5));
+
+        let hedge = parse(
+            r#"{"retCode":0,"retMsg":"OK","result":{"list":[
+            {"positionIdx":1,"symbol":"BTCUSDT","side":"Buy","size":"0.02","avgPrice":"63000"},
+            {"positionIdx":2,"symbol":"BTCUSDT","side":"","size":"0","avgPrice":""}],
+            "nextPageCursor":""},"time":1718000000000}"#,
+        );
+        assert!(!hedge.one_way);
+        assert_eq!((hedge.long.size, hedge.long.price), (0.02, 63000.0));
+        assert_eq!(hedge.short.size, 0.0);
+    }
+
+    #[test]
+    fn test_margin_mode_switch_refused_with_a_position_on_any_page() {
+        // the settle coin's positions, split over two pages by the cursor
+        let pages = [
+            r#"{"retCode":0,"retMsg":"OK","result":{"list":[
+            {"positionIdx":0,"symbol":"ADAUSDT","side":"","size":"0","avgPrice":""},
+            {"positionIdx":0,"symbol":"BTCUSDT","side":"","size":"0","avgPrice":""}],
+            "nextPageCursor":"BTCUSDT%3A0%2CBTCUSDT%3A0","category":"linear"},
+            "time":1718000000000}"#,
+            r#"{"retCode":0,"retMsg":"OK","result":{"list":[
+            {"positionIdx":0,"symbol":"XRPUSDT","side":"Buy","size":"120","avgPrice":"0.49"}],
+            "nextPageCursor":"","category":"linear"},"time":1718000000000}"#,
+        ];
+        let mut positions = Vec::new();
+        for page in pages {
+            let page: BybitResponse<BybitPositionResult> = serde_json::from_str(page).unwrap();
+            positions.extend(page.result.list);
+        }
+
+        assert!(
+            check_margin_mode_switch("REGULAR_MARGIN", "ISOLATED_MARGIN", &positions[..2]).is_ok()
+        );
+        let refusal = check_margin_mode_switch("REGULAR_MARGIN", "ISOLATED_MARGIN", &positions)
+            .unwrap_err()
+            .to_string();
+        assert!(refusal.contains("(XRPUSDT)"), "{}", refusal);
+        let refusal = check_margin_mode_switch("PORTFOLIO_MARGIN", "REGULAR_MARGIN", &[])
+            .unwrap_err()
+            .to_string();
+        assert!(refusal.contains("portfolio margin"), "{}", refusal);
+    }
 }
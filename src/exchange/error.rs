@@ -0,0 +1,252 @@
+//! Errors of exchange requests, classified so callers can tell failures worth retrying
+//! from rejections that will fail again.
+
+use std::fmt;
+use super::SendSyncError;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExchangeError {
+    /// The request timed out or the connection failed; it may or may not have arrived.
+    Network(String),
+    /// The exchange refused the request for exceeding its rate limits.
+    RateLimited(String),
+    /// The exchange rejected the order itself: its price, qty, type or state.
+    InvalidOrder(String),
+    InsufficientBalance(String),
+    /// Missing, invalid or expired API keys, a bad signature or missing permissions.
+    AuthFailed(String),
+    /// The exchange is down for maintenance or overloaded.
+    ExchangeMaintenance(String),
+    /// Anything else, such as a response that could not be parsed.
+    Other(String),
+}
+
+type Category = fn(String) -> ExchangeError;
+
+/// Lower-case message fragments identifying each category, checked in this order. Bare
+/// numeric codes are left out, as they also match prices and quantities.
+const CATEGORIES: &[(Category, &[&str])] = &[
+    (
+        ExchangeError::RateLimited,
+        &[
+            "too many requests",
+            "rate limit",
+            "too many visits",
+            "-1003",
+        ],
+    ),
+    (
+        ExchangeError::AuthFailed,
+        &[
+            "api key",
+            "api-key",
+            "apikey",
+            "signature",
+            "unauthorized",
+            "permission",
+            "-2014",
+            "-2015",
+            "-1022",
+        ],
+    ),
+    (
+        ExchangeError::ExchangeMaintenance,
+        &[
+            "maintenance",
+            "service unavailable",
+            "system busy",
+            "server busy",
+        ],
+    ),
+    (
+        ExchangeError::Network,
+        &["timed out", "timeout", "connection", "bad gateway"],
+    ),
+    (
+        ExchangeError::InsufficientBalance,
+        &["insufficient", "not enough", "-2019"],
+    ),
+    (
+        ExchangeError::InvalidOrder,
+        &[
+            "order",
+            "precision",
+            "not a multiple",
+            "below min_",
+            "qty",
+            "quantity",
+            "price",
+            "notional",
+            "-1111",
+            "-1013",
+            "-4164",
+        ],
+    ),
+];
+
+impl ExchangeError {
+    /// Classifies an error message from its text, e.g. an exchange's `retMsg`.
+    pub fn classify(message: impl Into<String>) -> Self {
+        let message = message.into();
+        let lower = message.to_lowercase();
+        for (category, fragments) in CATEGORIES {
+            if fragments.iter().any(|fragment| lower.contains(fragment)) {
+                return category(message);
+            }
+        }
+        ExchangeError::Other(message)
+    }
+
+    /// Whether the same request may succeed when sent again after a pause.
+    pub fn is_retryable(&self) -> bool {
+        matches!(
+            self,
+            ExchangeError::Network(_)
+                | ExchangeError::RateLimited(_)
+                | ExchangeError::ExchangeMaintenance(_)
+        )
+    }
+
+    pub fn message(&self) -> &str {
+        match self {
+            ExchangeError::Network(message)
+            | ExchangeError::RateLimited(message)
+            | ExchangeError::InvalidOrder(message)
+            | ExchangeError::InsufficientBalance(message)
+            | ExchangeError::AuthFailed(message)
+            | ExchangeError::ExchangeMaintenance(message)
+            | ExchangeError::Other(message) => message,
+        }
+    }
+}
+
+/// Only the message, so errors read as the exchange reported them.
+impl fmt::Display for ExchangeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.message())
+    }
+}
+
+impl std::error::Error for ExchangeError {}
+
+impl From<String> for ExchangeError {
+    fn from(message: String) -> Self {
+        ExchangeError::classify(message)
+    }
+}
+
+impl From<&str> for ExchangeError {
+    fn from(message: &str) -> Self {
+        ExchangeError::classify(message)
+    }
+}
+
+impl From<reqwest::Error> for ExchangeError {
+    fn from(e: reqwest::Error) -> Self {
+        let status = e.status().map(|status| status.as_u16());
+        if e.is_timeout() || e.is_connect() || e.is_request() || e.is_body() {
+            ExchangeError::Network(e.to_string())
+        } else if matches!(status, Some(429 | 418)) {
+            ExchangeError::RateLimited(e.to_string())
+        } else if matches!(status, Some(401 | 403)) {
+            ExchangeError::AuthFailed(e.to_string())
+        } else if matches!(status, Some(502..=504)) {
+            ExchangeError::ExchangeMaintenance(e.to_string())
+        } else if e.is_decode() {
+            ExchangeError::Other(e.to_string())
+        } else {
+            ExchangeError::classify(e.to_string())
+        }
+    }
+}
+
+/// Errors of helpers shared with the rest of the bot keep their category if they have one.
+impl From<SendSyncError> for ExchangeError {
+    fn from(e: SendSyncError) -> Self {
+        match e.downcast::<ExchangeError>() {
+            Ok(e) => *e,
+            Err(e) => match e.downcast::<reqwest::Error>() {
+                Ok(e) => (*e).into(),
+                Err(e) => ExchangeError::classify(e.to_string()),
+            },
+        }
+    }
+}
+
+/// Errors that never come from the exchange's answer, such as unparseable responses.
+macro_rules! other_from {
+    ($($error:ty),*) => {
+        $(impl From<$error> for ExchangeError {
+            fn from(e: $error) -> Self {
+                ExchangeError::Other(e.to_string())
+            }
+        })*
+    };
+}
+
+other_from!(
+    serde_json::Error,
+    std::num::ParseFloatError,
+    std::num::ParseIntError,
+    serde_urlencoded::ser::Error,
+    std::io::Error,
+    hmac::digest::InvalidLength,
+    reqwest::header::InvalidHeaderValue
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classify_exchange_messages() {
+        let cases = [
+            ("Too many visits!", ExchangeError::RateLimited as Category),
+            (
+                "Invalid API-key, IP, or permissions for action.",
+                ExchangeError::AuthFailed,
+            ),
+            (
+                "error sign! origin_string[...]: invalid signature",
+                ExchangeError::AuthFailed,
+            ),
+            (
+                "System is under maintenance",
+                ExchangeError::ExchangeMaintenance,
+            ),
+            (
+                "place_order: request timed out (injected)",
+                ExchangeError::Network,
+            ),
+            (
+                "ab not enough for new order",
+                ExchangeError::InsufficientBalance,
+            ),
+            (
+                "Margin is insufficient.",
+                ExchangeError::InsufficientBalance,
+            ),
+            ("Qty invalid", ExchangeError::InvalidOrder),
+            (
+                "price 99.05 not a multiple of 0.1",
+                ExchangeError::InvalidOrder,
+            ),
+            ("missing field `result`", ExchangeError::Other),
+        ];
+        for (message, category) in cases {
+            assert_eq!(
+                ExchangeError::classify(message),
+                category(message.to_string())
+            );
+        }
+        assert!(ExchangeError::classify("place_order: 429 Too Many Requests").is_retryable());
+        assert!(!ExchangeError::classify("Qty invalid").is_retryable());
+        // boxed and unboxed again, as errors pass through helpers shared with the bot
+        let boxed: SendSyncError = ExchangeError::Network("reset".to_string()).into();
+        assert_eq!(
+            ExchangeError::from(boxed),
+            ExchangeError::Network("reset".to_string())
+        );
+        assert_eq!(ExchangeError::RateLimited("x".to_string()).to_string(), "x");
+    }
+}
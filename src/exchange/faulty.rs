@@ -0,0 +1,439 @@
+use async_trait::async_trait;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use crate::types::{
+    ExchangeParams, FaultInjectionConfig, Market, Order, OrderBook, PositionPair, PositionCosts,
+    PositionPnl, Ticker, TradeFill, FundingPayment, CollateralAsset, MarginMode, ApiKeyInfo,
+};
+use super::{Exchange, ExchangeError};
+use crate::data::Candle;
+use tracing::debug;
+
+#[derive(Default)]
+struct LastResponses {
+    order_books: HashMap<String, OrderBook>,
+    positions: HashMap<String, PositionPair>,
+    tickers: HashMap<String, f64>,
+    balance: Option<f64>,
+}
+
+/// Wraps any exchange and randomly injects failures, for resilience testing in
+/// simulation and integration tests.
+///
+/// Every request may time out (after `timeout_seconds`) or be rate limited. Reads may
+/// also return the previous response again (stale data) or a truncated one (one-sided
+/// order books, missing markets/tickers). A timed out order placement or cancellation
+/// is still forwarded, so callers see an error for a request that actually went through.
+#[derive(Clone)]
+pub struct FaultInjectingExchange {
+    inner: Box<dyn Exchange>,
+    config: FaultInjectionConfig,
+    rng: Arc<Mutex<StdRng>>,
+    last: Arc<Mutex<LastResponses>>,
+}
+
+enum Fault {
+    Timeout,
+    RateLimit,
+    Stale,
+    Partial,
+}
+
+impl FaultInjectingExchange {
+    pub fn new(inner: Box<dyn Exchange>, config: FaultInjectionConfig) -> Self {
+        Self {
+            inner,
+            rng: Arc::new(Mutex::new(StdRng::seed_from_u64(config.seed))),
+            config,
+            last: Arc::new(Mutex::new(LastResponses::default())),
+        }
+    }
+
+    fn roll(&self) -> Option<Fault> {
+        let mut rng = self.rng.lock().unwrap();
+        let mut draw = |prob: f64| prob > 0.0 && rng.gen::<f64>() < prob;
+        if draw(self.config.timeout_prob) {
+            Some(Fault::Timeout)
+        } else if draw(self.config.rate_limit_prob) {
+            Some(Fault::RateLimit)
+        } else if draw(self.config.stale_prob) {
+            Some(Fault::Stale)
+        } else if draw(self.config.partial_prob) {
+            Some(Fault::Partial)
+        } else {
+            None
+        }
+    }
+
+    async fn fail(&self, fault: &Fault, request: &str) -> Option<ExchangeError> {
+        match fault {
+            Fault::Timeout => {
+                debug!("Injecting timeout into {}", request);
+                tokio::time::sleep(Duration::from_secs_f64(self.config.timeout_seconds)).await;
+                Some(ExchangeError::Network(format!(
+                    "{}: request timed out (injected)",
+                    request
+                )))
+            }
+            Fault::RateLimit => {
+                debug!("Injecting rate limit into {}", request);
+                Some(ExchangeError::RateLimited(format!(
+                    "{}: 429 Too Many Requests (injected)",
+                    request
+                )))
+            }
+            _ => None,
+        }
+    }
+
+    fn drop_half<T>(&self, map: &mut HashMap<String, T>) {
+        let mut rng = self.rng.lock().unwrap();
+        map.retain(|_, _| rng.gen::<bool>());
+    }
+}
+
+#[async_trait]
+impl Exchange for FaultInjectingExchange {
+    fn clone_box(&self) -> Box<dyn Exchange> {
+        Box::new(self.clone())
+    }
+
+    fn quote(&self) -> &str {
+        self.inner.quote()
+    }
+
+    async fn load_markets(&self) -> Result<HashMap<String, Market>, ExchangeError> {
+        let fault = self.roll();
+        if let Some(f) = &fault {
+            if let Some(e) = self.fail(f, "load_markets").await {
+                return Err(e);
+            }
+        }
+        let mut markets = self.inner.load_markets().await?;
+        if matches!(fault, Some(Fault::Partial)) {
+            self.drop_half(&mut markets);
+        }
+        Ok(markets)
+    }
+
+    async fn fetch_tickers(
+        &self, symbols: &[String],
+    ) -> Result<HashMap<String, Ticker>, ExchangeError> {
+        let fault = self.roll();
+        if let Some(f) = &fault {
+            if let Some(e) = self.fail(f, "fetch_tickers").await {
+                return Err(e);
+            }
+        }
+        let mut tickers = self.inner.fetch_tickers(symbols).await?;
+        if matches!(fault, Some(Fault::Partial)) {
+            self.drop_half(&mut tickers);
+        }
+        Ok(tickers)
+    }
+
+    async fn fetch_ticker(&self, symbol: &str) -> Result<f64, ExchangeError> {
+        let fault = self.roll();
+        if let Some(f) = &fault {
+            if let Some(e) = self.fail(f, "fetch_ticker").await {
+                return Err(e);
+            }
+        }
+        if matches!(fault, Some(Fault::Stale)) {
+            if let Some(price) = self.last.lock().unwrap().tickers.get(symbol) {
+                return Ok(*price);
+            }
+        }
+        let price = self.inner.fetch_ticker(symbol).await?;
+        self.last
+            .lock()
+            .unwrap()
+            .tickers
+            .insert(symbol.to_string(), price);
+        Ok(price)
+    }
+
+    async fn fetch_order_book(&self, symbol: &str) -> Result<OrderBook, ExchangeError> {
+        let fault = self.roll();
+        if let Some(f) = &fault {
+            if let Some(e) = self.fail(f, "fetch_order_book").await {
+                return Err(e);
+            }
+        }
+        if matches!(fault, Some(Fault::Stale)) {
+            if let Some(order_book) = self.last.lock().unwrap().order_books.get(symbol) {
+                return Ok(order_book.clone());
+            }
+        }
+        let mut order_book = self.inner.fetch_order_book(symbol).await?;
+        self.last
+            .lock()
+            .unwrap()
+            .order_books
+            .insert(symbol.to_string(), order_book.clone());
+        if matches!(fault, Some(Fault::Partial)) {
+            if self.rng.lock().unwrap().gen::<bool>() {
+                order_book.bids.clear();
+            } else {
+                order_book.asks.clear();
+            }
+        }
+        Ok(order_book)
+    }
+
+    async fn fetch_balance(&self) -> Result<f64, ExchangeError> {
+        let fault = self.roll();
+        if let Some(f) = &fault {
+            if let Some(e) = self.fail(f, "fetch_balance").await {
+                return Err(e);
+            }
+        }
+        if matches!(fault, Some(Fault::Stale)) {
+            if let Some(balance) = self.last.lock().unwrap().balance {
+                return Ok(balance);
+            }
+        }
+        let balance = self.inner.fetch_balance().await?;
+        self.last.lock().unwrap().balance = Some(balance);
+        Ok(balance)
+    }
+
+    async fn place_order(&mut self, order: &Order) -> Result<(), ExchangeError> {
+        match self.roll() {
+            Some(Fault::RateLimit) => {
+                Err(self.fail(&Fault::RateLimit, "place_order").await.unwrap())
+            }
+            Some(Fault::Timeout) => {
+                let _ = self.inner.place_order(order).await;
+                Err(self.fail(&Fault::Timeout, "place_order").await.unwrap())
+            }
+            _ => self.inner.place_order(order).await,
+        }
+    }
+
+    async fn cancel_order(&mut self, symbol: &str, order_id: &str) -> Result<(), ExchangeError> {
+        match self.roll() {
+            Some(Fault::RateLimit) => {
+                Err(self.fail(&Fault::RateLimit, "cancel_order").await.unwrap())
+            }
+            Some(Fault::Timeout) => {
+                let _ = self.inner.cancel_order(symbol, order_id).await;
+                Err(self.fail(&Fault::Timeout, "cancel_order").await.unwrap())
+            }
+            _ => self.inner.cancel_order(symbol, order_id).await,
+        }
+    }
+
+    async fn fetch_positions(&self, symbol: &str) -> Result<PositionPair, ExchangeError> {
+        let fault = self.roll();
+        if let Some(f) = &fault {
+            if let Some(e) = self.fail(f, "fetch_positions").await {
+                return Err(e);
+            }
+        }
+        if matches!(fault, Some(Fault::Stale)) {
+            if let Some(positions) = self.last.lock().unwrap().positions.get(symbol) {
+                return Ok(*positions);
+            }
+        }
+        let positions = self.inner.fetch_positions(symbol).await?;
+        self.last
+            .lock()
+            .unwrap()
+            .positions
+            .insert(symbol.to_string(), positions);
+        Ok(positions)
+    }
+
+    async fn fetch_position_costs(&self, symbol: &str) -> Result<PositionCosts, ExchangeError> {
+        self.inner.fetch_position_costs(symbol).await
+    }
+
+    async fn fetch_position_pnl(&self, symbol: &str) -> Result<Option<PositionPnl>, ExchangeError> {
+        self.inner.fetch_position_pnl(symbol).await
+    }
+
+    async fn fetch_exchange_params(&self, symbol: &str) -> Result<ExchangeParams, ExchangeError> {
+        if let Some(f) = &self.roll() {
+            if let Some(e) = self.fail(f, "fetch_exchange_params").await {
+                return Err(e);
+            }
+        }
+        self.inner.fetch_exchange_params(symbol).await
+    }
+
+    async fn fetch_api_key_info(&self) -> Result<Option<ApiKeyInfo>, ExchangeError> {
+        if let Some(f) = &self.roll() {
+            if let Some(e) = self.fail(f, "fetch_api_key_info").await {
+                return Err(e);
+            }
+        }
+        self.inner.fetch_api_key_info().await
+    }
+
+    fn now_ms(&self) -> i64 {
+        self.inner.now_ms()
+    }
+
+    async fn fetch_funding_payments(
+        &self, symbol: &str, start_ms: i64, end_ms: i64,
+    ) -> Result<Vec<FundingPayment>, ExchangeError> {
+        if let Some(f) = &self.roll() {
+            if let Some(e) = self.fail(f, "fetch_funding_payments").await {
+                return Err(e);
+            }
+        }
+        self.inner
+            .fetch_funding_payments(symbol, start_ms, end_ms)
+            .await
+    }
+
+    async fn fetch_last_close(&self, symbol: &str) -> Result<Option<f64>, ExchangeError> {
+        if let Some(f) = &self.roll() {
+            if let Some(e) = self.fail(f, "fetch_last_close").await {
+                return Err(e);
+            }
+        }
+        self.inner.fetch_last_close(symbol).await
+    }
+
+    async fn fetch_ohlcv(
+        &self, symbol: &str, timeframe: &str, since_ms: Option<i64>, limit: usize,
+    ) -> Result<Vec<Candle>, ExchangeError> {
+        if let Some(f) = &self.roll() {
+            if let Some(e) = self.fail(f, "fetch_ohlcv").await {
+                return Err(e);
+            }
+        }
+        self.inner
+            .fetch_ohlcv(symbol, timeframe, since_ms, limit)
+            .await
+    }
+
+    fn is_precision_error(&self, error: &str) -> bool {
+        self.inner.is_precision_error(error)
+    }
+
+    fn has_fill_history(&self) -> bool {
+        self.inner.has_fill_history()
+    }
+
+    fn is_risk_limit_error(&self, error: &str) -> bool {
+        self.inner.is_risk_limit_error(error)
+    }
+
+    async fn fetch_open_orders(&self, symbol: &str) -> Result<Vec<Order>, ExchangeError> {
+        if let Some(f) = &self.roll() {
+            if let Some(e) = self.fail(f, "fetch_open_orders").await {
+                return Err(e);
+            }
+        }
+        self.inner.fetch_open_orders(symbol).await
+    }
+
+    async fn fetch_fill_history(
+        &self, symbol: &str, start_ms: i64, end_ms: i64,
+    ) -> Result<Vec<TradeFill>, ExchangeError> {
+        self.inner
+            .fetch_fill_history(symbol, start_ms, end_ms)
+            .await
+    }
+
+    async fn fetch_collateral(&self) -> Result<Vec<CollateralAsset>, ExchangeError> {
+        self.inner.fetch_collateral().await
+    }
+
+    async fn fetch_risk_limit(&self, symbol: &str) -> Result<Option<f64>, ExchangeError> {
+        self.inner.fetch_risk_limit(symbol).await
+    }
+
+    async fn raise_risk_limit(&mut self, symbol: &str) -> Result<Option<f64>, ExchangeError> {
+        self.inner.raise_risk_limit(symbol).await
+    }
+
+    async fn set_leverage(&mut self, symbol: &str, leverage: f64) -> Result<(), ExchangeError> {
+        self.inner.set_leverage(symbol, leverage).await
+    }
+
+    async fn set_margin_mode(
+        &mut self, symbol: &str, mode: MarginMode,
+    ) -> Result<(), ExchangeError> {
+        self.inner.set_margin_mode(symbol, mode).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::exchange::simulated::SimulatedExchange;
+
+    fn faulty(
+        simulated: &SimulatedExchange, config: FaultInjectionConfig,
+    ) -> FaultInjectingExchange {
+        FaultInjectingExchange::new(simulated.clone_box(), config)
+    }
+
+    #[tokio::test]
+    async fn test_injected_faults() {
+        let simulated = SimulatedExchange::new(1000.0);
+        simulated.update_price(100.0, 100.0, 100.0);
+
+        let rate_limited = faulty(
+            &simulated,
+            FaultInjectionConfig {
+                rate_limit_prob: 1.0,
+                ..Default::default()
+            },
+        );
+        let err = rate_limited.fetch_balance().await.unwrap_err();
+        assert!(err.to_string().contains("429"));
+
+        // the first read has nothing cached yet and passes through
+        let stale = faulty(
+            &simulated,
+            FaultInjectionConfig {
+                stale_prob: 1.0,
+                ..Default::default()
+            },
+        );
+        assert_eq!(stale.fetch_ticker("BTCUSDT").await.unwrap(), 100.0);
+        simulated.update_price(101.0, 101.0, 101.0);
+        assert_eq!(stale.fetch_ticker("BTCUSDT").await.unwrap(), 100.0);
+
+        let partial = faulty(
+            &simulated,
+            FaultInjectionConfig {
+                partial_prob: 1.0,
+                ..Default::default()
+            },
+        );
+        let order_book = partial.fetch_order_book("BTCUSDT").await.unwrap();
+        assert!(order_book.bids.is_empty() != order_book.asks.is_empty());
+
+        // a timed out placement still reaches the exchange
+        let mut timing_out = faulty(
+            &simulated,
+            FaultInjectionConfig {
+                timeout_prob: 1.0,
+                ..Default::default()
+            },
+        );
+        let order = Order {
+            id: String::new(),
+            symbol: "BTCUSDT".to_string(),
+            side: "Buy".to_string(),
+            position_side: "Long".to_string(),
+            qty: 0.1,
+            price: 100.0,
+            reduce_only: false,
+            custom_id: String::new(),
+            client_order_id: String::new(),
+            time_in_force: String::new(),
+        };
+        assert!(timing_out.place_order(&order).await.is_err());
+        assert_eq!(simulated.snapshot().orders.len(), 1);
+    }
+}
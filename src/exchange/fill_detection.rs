@@ -0,0 +1,142 @@
+use crate::types::{Order, TradeFill};
+use std::collections::{HashMap, HashSet};
+use tracing::debug;
+
+/// How long an order that left the book may go without a matching trade before it is
+/// taken to have been cancelled.
+const FILL_CONFIRM_TIMEOUT_MS: i64 = 60 * 1000;
+
+/// Detects fills over REST alone: orders that left the book or shrank between two polls
+/// of the open orders may have filled, and stay pending until the account's trades
+/// confirm them or they time out as cancelled.
+#[derive(Debug, Default, Clone)]
+pub struct FillDetector {
+    /// Open orders by id at the last poll; `None` before the first.
+    open_orders: Option<HashMap<String, Order>>,
+    /// Ids of orders that may have filled, with when they were noticed.
+    pending: HashMap<String, i64>,
+    /// Fills already returned while orders were pending, since their window is read again.
+    seen_fills: HashSet<String>,
+}
+
+impl FillDetector {
+    /// Records the latest open orders and returns true if an order left the book or
+    /// shrank since the previous poll. The first poll only takes the snapshot.
+    pub fn update_open_orders(&mut self, open_orders: Vec<Order>, now_ms: i64) -> bool {
+        let current: HashMap<String, Order> = open_orders
+            .into_iter()
+            .map(|order| (order.id.clone(), order))
+            .collect();
+        let Some(previous) = self.open_orders.replace(current) else {
+            return false;
+        };
+        let current = self.open_orders.as_ref().unwrap();
+        let mut changed = false;
+        for (id, order) in previous {
+            let filled = match current.get(&id) {
+                Some(open) => open.qty < order.qty,
+                None => true,
+            };
+            if filled {
+                self.pending.entry(id).or_insert(now_ms);
+                changed = true;
+            }
+        }
+        changed
+    }
+
+    pub fn has_pending(&self) -> bool {
+        !self.pending.is_empty()
+    }
+
+    /// Confirms pending orders against the account's `fills` and returns the fills not
+    /// returned before. Pending orders without a fill past the timeout are dropped.
+    pub fn confirm(&mut self, fills: Vec<TradeFill>, now_ms: i64) -> Vec<TradeFill> {
+        let new_fills: Vec<TradeFill> = fills
+            .into_iter()
+            .filter(|fill| !self.seen_fills.contains(&fill.id))
+            .collect();
+        for fill in &new_fills {
+            self.pending.remove(&fill.order_id);
+        }
+        self.pending.retain(|id, noticed_ms| {
+            let expired = now_ms - *noticed_ms > FILL_CONFIRM_TIMEOUT_MS;
+            if expired {
+                debug!(
+                    "Order {} left the book without fills, taking it as cancelled",
+                    id
+                );
+            }
+            !expired
+        });
+        if self.pending.is_empty() {
+            self.seen_fills.clear();
+        } else {
+            self.seen_fills
+                .extend(new_fills.iter().map(|fill| fill.id.clone()));
+        }
+        new_fills
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn order(id: &str, qty: f64) -> Order {
+        Order {
+            id: id.to_string(),
+            symbol: "BTCUSDT".to_string(),
+            side: "Buy".to_string(),
+            position_side: "Long".to_string(),
+            qty,
+            price: 100.0,
+            reduce_only: false,
+            custom_id: String::new(),
+            client_order_id: String::new(),
+            time_in_force: String::new(),
+        }
+    }
+
+    fn fill(id: &str, order_id: &str) -> TradeFill {
+        TradeFill {
+            id: id.to_string(),
+            order_id: order_id.to_string(),
+            symbol: "BTCUSDT".to_string(),
+            timestamp_ms: 0,
+            side: "Buy".to_string(),
+            position_side: "Long".to_string(),
+            qty: 0.5,
+            price: 100.0,
+            fee: 0.0,
+            maker: None,
+            order_price: 0.0,
+            realized_pnl: None,
+        }
+    }
+
+    #[test]
+    fn test_fills_detected_from_open_orders() {
+        let mut detector = FillDetector::default();
+        assert!(!detector.update_open_orders(vec![order("a", 1.0), order("b", 1.0)], 0));
+        assert!(!detector.update_open_orders(vec![order("a", 1.0), order("b", 1.0)], 1));
+
+        // "a" partially filled, "b" left the book
+        assert!(detector.update_open_orders(vec![order("a", 0.5)], 2));
+        // the trade of "b" is not reported yet
+        let fills = detector.confirm(vec![fill("1", "a")], 3);
+        assert_eq!(fills.len(), 1);
+        assert!(detector.has_pending());
+        // re-reading the window returns only the new trade
+        let fills = detector.confirm(vec![fill("1", "a"), fill("2", "b")], 4);
+        assert_eq!(fills[0].id, "2");
+        assert!(!detector.has_pending());
+
+        // a cancelled order never trades and times out
+        assert!(detector.update_open_orders(vec![], 5));
+        assert!(detector.confirm(vec![], 6).is_empty());
+        assert!(detector.has_pending());
+        detector.confirm(vec![], 6 + FILL_CONFIRM_TIMEOUT_MS);
+        assert!(!detector.has_pending());
+    }
+}
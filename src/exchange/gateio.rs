@@ -5,8 +5,13 @@ use chrono::Utc;
 use hmac::{Hmac, Mac};
 use sha2::{Digest, Sha256};
 use crate::config::UserConfig;
-use crate::types::{ExchangeParams, LiveConfig, Market, Ticker, Order, Position, OrderBook};
-use super::{Exchange, SendSyncError};
+use crate::types::{
+    ExchangeParams, LiveConfig, MarginMode, Market, Ticker, Order, Position, PositionPair,
+    OrderBook,
+};
+use super::rate_limit::{RateLimiter, SendRateLimited};
+use super::{timeframe_ms, Exchange, ExchangeError};
+use crate::data::Candle;
 use tracing::{info, error};
 
 const GATEIO_API_URL: &str = "https://api.gateio.ws";
@@ -31,20 +36,40 @@ struct GateioTicker {
     lowest_ask: String,
     highest_bid: String,
     volume_24h_quote: String,
+    #[serde(default)]
+    funding_rate: String,
+}
+
+/// A candlestick; `t` is in seconds, `v` in contracts.
+#[derive(Deserialize, Debug)]
+struct GateioCandle {
+    t: u64,
+    #[serde(default)]
+    v: f64,
+    o: String,
+    h: String,
+    l: String,
+    c: String,
 }
 
 pub struct Gateio {
     client: reqwest::Client,
+    rate_limiter: RateLimiter,
     api_key: String,
     api_secret: String,
+    /// Gate.io sets cross margin as a leverage of 0 with a limit, so leverage is set in
+    /// the margin mode of `live.margin_mode`.
+    margin_mode: Option<MarginMode>,
 }
 
 impl Gateio {
-    pub fn new(_live_config: &LiveConfig, user_config: &UserConfig) -> Self {
+    pub fn new(live_config: &LiveConfig, user_config: &UserConfig) -> Self {
         Gateio {
             client: reqwest::Client::new(),
+            rate_limiter: RateLimiter::shared("gateio", &live_config.rate_limit),
             api_key: user_config.key.clone(),
             api_secret: user_config.secret.clone(),
+            margin_mode: MarginMode::from_str(&live_config.margin_mode),
         }
     }
 
@@ -67,6 +92,50 @@ impl Gateio {
         };
         (timestamp, signature)
     }
+
+    /// An authenticated GET of `uri`, parsed as JSON.
+    async fn fetch_signed(&self, uri: &str) -> Result<serde_json::Value, ExchangeError> {
+        let (timestamp, signature) = self.sign_request("GET", uri, "", "");
+        let response = self
+            .client
+            .get(format!("{}{}", GATEIO_API_URL, uri))
+            .header("KEY", &self.api_key)
+            .header("SIGN", &signature)
+            .header("Timestamp", &timestamp)
+            .send_limited(&self.rate_limiter)
+            .await?
+            .text()
+            .await?;
+        Ok(serde_json::from_str(&response)?)
+    }
+}
+
+/// The legs of a position response: a single position of mode "single", also while
+/// flat, or the "dual_long" and "dual_short" legs of a dual mode account.
+fn position_pair(position_response: serde_json::Value) -> Result<PositionPair, ExchangeError> {
+    let legs = match position_response.as_array() {
+        Some(legs) => legs.clone(),
+        None if position_response.get("contract").is_some() => vec![position_response],
+        None => Vec::new(),
+    };
+    let mut positions = PositionPair::default();
+    for leg in legs {
+        // sizes are signed contract counts, negative for the short leg and a single short
+        let size = match &leg["size"] {
+            serde_json::Value::String(size) => size.parse()?,
+            size => size.as_f64().unwrap_or(0.0),
+        };
+        let position = Position {
+            size,
+            price: leg["entry_price"].as_str().unwrap_or("0").parse()?,
+        };
+        match leg["mode"].as_str() {
+            Some("dual_long") => positions.long = position,
+            Some("dual_short") => positions.short = position,
+            _ => positions = PositionPair::from_net(position),
+        }
+    }
+    Ok(positions)
 }
 
 #[async_trait]
@@ -74,15 +143,23 @@ impl Exchange for Gateio {
     fn clone_box(&self) -> Box<dyn Exchange> {
         Box::new(Gateio {
             client: self.client.clone(),
+            rate_limiter: self.rate_limiter.clone(),
             api_key: self.api_key.clone(),
             api_secret: self.api_secret.clone(),
+            margin_mode: self.margin_mode,
         })
     }
 
-    async fn load_markets(&self) -> Result<HashMap<String, Market>, SendSyncError> {
+    async fn load_markets(&self) -> Result<HashMap<String, Market>, ExchangeError> {
         info!("Loading markets from Gate.io");
         let url = format!("{}/api/v4/futures/usdt/contracts", GATEIO_API_URL);
-        let response = self.client.get(&url).send().await?.text().await?;
+        let response = self
+            .client
+            .get(&url)
+            .send_limited(&self.rate_limiter)
+            .await?
+            .text()
+            .await?;
         let markets: Vec<GateioMarket> = serde_json::from_str(&response)?;
 
         let markets = markets
@@ -95,7 +172,8 @@ impl Exchange for Gateio {
                         active: m.trade_status == "trading",
                         swap: m.market_type == "futures",
                         linear: true,
-                        created_at: 0, // not provided
+                        created_at: 0,              // not provided
+                        settle: "USDT".to_string(), // the usdt settled contracts
                     },
                 )
             })
@@ -106,10 +184,16 @@ impl Exchange for Gateio {
 
     async fn fetch_tickers(
         &self, symbols: &[String],
-    ) -> Result<HashMap<String, Ticker>, SendSyncError> {
+    ) -> Result<HashMap<String, Ticker>, ExchangeError> {
         info!("Fetching tickers from Gate.io");
         let url = format!("{}/api/v4/futures/usdt/tickers", GATEIO_API_URL);
-        let response = self.client.get(&url).send().await?.text().await?;
+        let response = self
+            .client
+            .get(&url)
+            .send_limited(&self.rate_limiter)
+            .await?
+            .text()
+            .await?;
         let tickers: Vec<GateioTicker> = serde_json::from_str(&response)?;
 
         let tickers = tickers
@@ -124,6 +208,7 @@ impl Exchange for Gateio {
                         ask: t.lowest_ask.parse().unwrap_or(0.0),
                         last: t.last.parse().unwrap_or(0.0),
                         quote_volume: t.volume_24h_quote.parse().unwrap_or(0.0),
+                        funding_rate: t.funding_rate.parse().unwrap_or(0.0),
                     },
                 )
             })
@@ -132,27 +217,32 @@ impl Exchange for Gateio {
         Ok(tickers)
     }
 
-    async fn fetch_ticker(&self, symbol: &str) -> Result<f64, SendSyncError> {
+    async fn fetch_ticker(&self, symbol: &str) -> Result<f64, ExchangeError> {
         info!("Fetching ticker for symbol: {}", symbol);
         let tickers = self.fetch_tickers(&[symbol.to_string()]).await?;
         if let Some(ticker) = tickers.get(symbol) {
             Ok(ticker.last)
         } else {
             error!("Ticker data not found for symbol: {}", symbol);
-            Err(Box::new(std::io::Error::new(
-                std::io::ErrorKind::Other,
+            Err(ExchangeError::classify(
                 "Ticker data not found in Gate.io response",
-            )))
+            ))
         }
     }
 
-    async fn fetch_order_book(&self, symbol: &str) -> Result<OrderBook, SendSyncError> {
+    async fn fetch_order_book(&self, symbol: &str) -> Result<OrderBook, ExchangeError> {
         info!("Fetching order book for symbol: {}", symbol);
         let url = format!(
             "{}/api/v4/futures/usdt/order_book?contract={}",
             GATEIO_API_URL, symbol
         );
-        let response = self.client.get(&url).send().await?.text().await?;
+        let response = self
+            .client
+            .get(&url)
+            .send_limited(&self.rate_limiter)
+            .await?
+            .text()
+            .await?;
         let order_book_result: serde_json::Value = serde_json::from_str(&response)?;
 
         let asks: Vec<[f64; 2]> = serde_json::from_value(order_book_result["asks"].clone())?;
@@ -161,7 +251,48 @@ impl Exchange for Gateio {
         Ok(OrderBook { bids, asks })
     }
 
-    async fn fetch_balance(&self) -> Result<f64, SendSyncError> {
+    async fn fetch_ohlcv(
+        &self, symbol: &str, timeframe: &str, since_ms: Option<i64>, limit: usize,
+    ) -> Result<Vec<Candle>, ExchangeError> {
+        let limit = limit.min(2000);
+        let interval_ms = timeframe_ms(timeframe)?;
+        let mut url = format!(
+            "{}/api/v4/futures/usdt/candlestick?contract={}&interval={}",
+            GATEIO_API_URL, symbol, timeframe
+        );
+        // `limit` cannot be combined with `from`
+        match since_ms {
+            Some(since_ms) => {
+                let from = (since_ms + 999) / 1000;
+                let to = from + (interval_ms * (limit as i64 - 1)) / 1000;
+                url.push_str(&format!("&from={}&to={}", from, to));
+            }
+            None => url.push_str(&format!("&limit={}", limit)),
+        }
+        let response = self
+            .client
+            .get(&url)
+            .send_limited(&self.rate_limiter)
+            .await?
+            .text()
+            .await?;
+        let candles: Vec<GateioCandle> = serde_json::from_str(&response)?;
+        candles
+            .into_iter()
+            .map(|c| {
+                Ok(Candle {
+                    timestamp: c.t * 1000,
+                    open: c.o.parse()?,
+                    high: c.h.parse()?,
+                    low: c.l.parse()?,
+                    close: c.c.parse()?,
+                    volume: c.v,
+                })
+            })
+            .collect()
+    }
+
+    async fn fetch_balance(&self) -> Result<f64, ExchangeError> {
         info!("Fetching balance from Gate.io");
         let uri = "/api/v4/futures/usdt/accounts";
         let (timestamp, signature) = self.sign_request("GET", uri, "", "");
@@ -172,7 +303,7 @@ impl Exchange for Gateio {
             .header("KEY", &self.api_key)
             .header("SIGN", &signature)
             .header("Timestamp", &timestamp)
-            .send()
+            .send_limited(&self.rate_limiter)
             .await?
             .text()
             .await?;
@@ -182,7 +313,7 @@ impl Exchange for Gateio {
         Ok(balance)
     }
 
-    async fn place_order(&mut self, order: &Order) -> Result<(), SendSyncError> {
+    async fn place_order(&mut self, order: &Order) -> Result<(), ExchangeError> {
         info!("Placing order: {:?}", order);
         let uri = "/api/v4/futures/usdt/orders";
 
@@ -194,6 +325,10 @@ impl Exchange for Gateio {
         );
         order_request.insert("price", order.price.to_string());
         order_request.insert("tif", order.time_in_force.clone());
+        if !order.client_order_id.is_empty() {
+            // custom order ids must start with "t-"
+            order_request.insert("text", format!("t-{}", order.client_order_id));
+        }
 
         let payload = serde_json::to_string(&order_request)?;
         let (timestamp, signature) = self.sign_request("POST", uri, "", &payload);
@@ -206,7 +341,7 @@ impl Exchange for Gateio {
             .header("Timestamp", &timestamp)
             .header("Content-Type", "application/json")
             .body(payload)
-            .send()
+            .send_limited(&self.rate_limiter)
             .await?
             .text()
             .await?;
@@ -215,17 +350,15 @@ impl Exchange for Gateio {
 
         if order_response.get("id").is_none() {
             error!("Failed to place order: {}", response);
-            return Err(Box::new(std::io::Error::new(
-                std::io::ErrorKind::Other,
-                response,
-            )));
+            return Err(ExchangeError::classify(response));
         }
 
         Ok(())
     }
 
-    async fn cancel_order(&mut self, order_id: &str) -> Result<(), SendSyncError> {
-        info!("Canceling order: {}", order_id);
+    async fn cancel_order(&mut self, symbol: &str, order_id: &str) -> Result<(), ExchangeError> {
+        // order ids are unique across contracts, so the symbol is only logged
+        info!("[{}] Canceling order: {}", symbol, order_id);
         let uri = format!("/api/v4/futures/usdt/orders/{}", order_id);
         let (timestamp, signature) = self.sign_request("DELETE", &uri, "", "");
 
@@ -235,7 +368,7 @@ impl Exchange for Gateio {
             .header("KEY", &self.api_key)
             .header("SIGN", &signature)
             .header("Timestamp", &timestamp)
-            .send()
+            .send_limited(&self.rate_limiter)
             .await?
             .text()
             .await?;
@@ -244,54 +377,91 @@ impl Exchange for Gateio {
 
         if order_response.get("id").is_none() {
             error!("Failed to cancel order: {}", response);
-            return Err(Box::new(std::io::Error::new(
-                std::io::ErrorKind::Other,
-                response,
-            )));
+            return Err(ExchangeError::classify(response));
         }
 
         Ok(())
     }
 
-    async fn fetch_position(&self, symbol: &str) -> Result<Position, SendSyncError> {
-        info!("Fetching position for symbol: {}", symbol);
-        let uri = format!("/api/v4/futures/usdt/positions/{}", symbol);
-        let (timestamp, signature) = self.sign_request("GET", &uri, "", "");
+    async fn fetch_open_orders(&self, symbol: &str) -> Result<Vec<Order>, ExchangeError> {
+        let uri = "/api/v4/futures/usdt/orders";
+        let query_string = format!("contract={}&status=open", symbol);
+        let (timestamp, signature) = self.sign_request("GET", uri, &query_string, "");
 
         let response = self
             .client
-            .get(format!("{}{}", GATEIO_API_URL, uri))
+            .get(format!("{}{}?{}", GATEIO_API_URL, uri, query_string))
             .header("KEY", &self.api_key)
             .header("SIGN", &signature)
             .header("Timestamp", &timestamp)
-            .send()
+            .send_limited(&self.rate_limiter)
             .await?
             .text()
             .await?;
 
-        let position_response: serde_json::Value = serde_json::from_str(&response)?;
+        let open_orders: Vec<serde_json::Value> = serde_json::from_str(&response)
+            .map_err(|_| format!("Failed to fetch open orders: {}", response))?;
+
+        let mut orders = Vec::new();
+        for open_order in open_orders {
+            // sizes are signed contract counts, negative for sells
+            let size = open_order["size"].as_f64().unwrap_or(0.0);
+            let left = open_order["left"].as_f64().unwrap_or(size);
+            orders.push(Order {
+                id: open_order["id"].to_string(),
+                symbol: symbol.to_string(),
+                side: if size > 0.0 { "Buy" } else { "Sell" }.to_string(),
+                position_side: String::new(),
+                qty: left.abs(),
+                price: open_order["price"].as_str().unwrap_or("0").parse()?,
+                reduce_only: open_order["is_reduce_only"].as_bool().unwrap_or(false),
+                custom_id: String::new(),
+                client_order_id: open_order["text"]
+                    .as_str()
+                    .and_then(|text| text.strip_prefix("t-"))
+                    .unwrap_or_default()
+                    .to_string(),
+                time_in_force: open_order["tif"].as_str().unwrap_or_default().to_string(),
+            });
+        }
+        Ok(orders)
+    }
 
-        if position_response.get("contract").is_none() {
-            Ok(Position {
-                size: 0.0,
-                price: 0.0,
-            })
+    async fn fetch_positions(&self, symbol: &str) -> Result<PositionPair, ExchangeError> {
+        info!("Fetching positions for symbol: {}", symbol);
+        let position_response = self
+            .fetch_signed(&format!("/api/v4/futures/usdt/positions/{}", symbol))
+            .await?;
+        // accounts in dual mode have their two legs at a separate endpoint
+        let in_dual_mode = position_response["label"]
+            .as_str()
+            .is_some_and(|label| label.contains("DUAL"));
+        let position_response = if in_dual_mode {
+            self.fetch_signed(&format!(
+                "/api/v4/futures/usdt/dual_comp/positions/{}",
+                symbol
+            ))
+            .await?
         } else {
-            let size: f64 = position_response["size"].as_str().unwrap_or("0").parse()?;
-            let price: f64 = position_response["entry_price"]
-                .as_str()
-                .unwrap_or("0")
-                .parse()?;
-            Ok(Position { size, price })
-        }
+            position_response
+        };
+
+        position_pair(position_response)
     }
-    async fn fetch_exchange_params(&self, symbol: &str) -> Result<ExchangeParams, SendSyncError> {
+
+    async fn fetch_exchange_params(&self, symbol: &str) -> Result<ExchangeParams, ExchangeError> {
         info!("Fetching exchange params for symbol: {}", symbol);
         let url = format!(
             "{}/api/v4/futures/usdt/contracts/{}",
             GATEIO_API_URL, symbol
         );
-        let response = self.client.get(&url).send().await?.text().await?;
+        let response = self
+            .client
+            .get(&url)
+            .send_limited(&self.rate_limiter)
+            .await?
+            .text()
+            .await?;
         let market: GateioMarket = serde_json::from_str(&response)?;
 
         Ok(ExchangeParams {
@@ -303,4 +473,78 @@ impl Exchange for Gateio {
             inverse: false, // Gate.io USDT futures are linear
         })
     }
+
+    async fn set_leverage(&mut self, symbol: &str, leverage: f64) -> Result<(), ExchangeError> {
+        info!("Setting leverage for {} to {}", symbol, leverage);
+        let uri = format!("/api/v4/futures/usdt/positions/{}/leverage", symbol);
+        let cross = match self.margin_mode {
+            Some(mode) => mode == MarginMode::Cross,
+            // keep whichever mode the position has
+            None => {
+                let position = self
+                    .fetch_signed(&format!("/api/v4/futures/usdt/positions/{}", symbol))
+                    .await?;
+                position["leverage"].as_str() == Some("0")
+            }
+        };
+        let query_string = if cross {
+            format!("leverage=0&cross_leverage_limit={}", leverage)
+        } else {
+            format!("leverage={}", leverage)
+        };
+        let (timestamp, signature) = self.sign_request("POST", &uri, &query_string, "");
+        let response = self
+            .client
+            .post(format!("{}{}?{}", GATEIO_API_URL, uri, query_string))
+            .header("KEY", &self.api_key)
+            .header("SIGN", &signature)
+            .header("Timestamp", &timestamp)
+            .send_limited(&self.rate_limiter)
+            .await?
+            .text()
+            .await?;
+        // setting the current leverage again succeeds, answering with the position
+        let position: serde_json::Value = serde_json::from_str(&response)?;
+        if position.get("contract").is_none() {
+            return Err(ExchangeError::classify(response));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_position_pair_reports_the_mode_of_a_flat_account() {
+        let parse =
+            |response: &str| position_pair(serde_json::from_str(response).unwrap()).unwrap();
+        let flat = parse(
+            r#"{"user":10000,"contract":"BTC_USDT","size":0,"leverage":"0",
+            "risk_limit":"1000000","leverage_max":"100","maintenance_rate":"0.004",
+            "value":"0","margin":"0","entry_price":"0","liq_price":"0","mark_price":"64012.1",
+            "unrealised_pnl":"0","realised_pnl":"0","mode":"single","cross_leverage_limit":"20"}"#,
+        );
+        assert!(flat.one_way);
+        assert_eq!((flat.long.size, flat.short.size), (0.0, 0.0));
+
+        let short = parse(
+            r#"{"user":10000,"contract":"BTC_USDT","size":-15,"entry_price":"64100.5",
+            "mark_price":"64012.1","mode":"single"}"#,
+        );
+        assert!(short.one_way);
+        assert_eq!((short.short.size, short.short.price), (-15.0, 64100.5));
+
+        // the dual_comp endpoint lists both legs of a dual mode account
+        let dual = parse(
+            r#"[{"user":10000,"contract":"BTC_USDT","size":20,"entry_price":"63000",
+            "mode":"dual_long"},
+            {"user":10000,"contract":"BTC_USDT","size":0,"entry_price":"0",
+            "mode":"dual_short"}]"#,
+        );
+        assert!(!dual.one_way);
+        assert_eq!((dual.long.size, dual.long.price), (20.0, 63000.0));
+        assert_eq!(dual.short.size, 0.0);
+    }
 }
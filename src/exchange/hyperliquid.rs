@@ -1,12 +1,53 @@
 use async_trait::async_trait;
+use chrono::Utc;
 use serde::Deserialize;
 use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
 use crate::config::UserConfig;
-use crate::types::{ExchangeParams, LiveConfig, Market, Ticker, Order, Position, OrderBook};
-use super::{Exchange, SendSyncError};
-use tracing::{info, error};
+use crate::types::{
+    ExchangeParams, LiveConfig, MarginMode, Market, Ticker, Order, Position, PositionPair,
+    OrderBook,
+};
+use super::intent_log;
+use super::rate_limit::{RateLimiter, SendRateLimited};
+use super::{timeframe_ms, Exchange, ExchangeError};
+use crate::data::Candle;
+use tracing::{info, error, warn};
 
 const HYPERLIQUID_API_URL: &str = "https://api.hyperliquid.xyz";
+/// Agent approvals are renewed once they expire within this window.
+const AGENT_RENEWAL_WINDOW_MS: i64 = 24 * 60 * 60 * 1000;
+const AGENT_VALIDITY_MS: i64 = 30 * 24 * 60 * 60 * 1000;
+/// Chain id user-signed actions such as agent approvals are signed for (Arbitrum One).
+const SIGNATURE_CHAIN_ID: u64 = 0xa4b1;
+
+/// Rate limit weight of an `/info` request, by its type.
+fn info_weight(body: &serde_json::Value) -> f64 {
+    match body["type"].as_str() {
+        Some("l2Book" | "allMids" | "clearinghouseState" | "orderStatus") => 2.0,
+        _ => 20.0,
+    }
+}
+
+/// The 16-byte hex `cloid` of a client order id made by the intent log, holding its session
+/// and sequence number; `None` for other ids, which Hyperliquid cannot take.
+fn to_cloid(client_order_id: &str) -> Option<String> {
+    let (session, seq) = intent_log::parse_client_order_id(client_order_id)?;
+    Some(format!("0x{:016x}{:016x}", session, seq))
+}
+
+/// The client order id a `cloid` made by `to_cloid` stands for; other cloids as they are.
+fn from_cloid(cloid: &str) -> String {
+    let parsed = cloid
+        .strip_prefix("0x")
+        .filter(|hex| hex.len() == 32 && hex.is_ascii())
+        .and_then(|hex| {
+            let session = u64::from_str_radix(&hex[..16], 16).ok()?;
+            let seq = u64::from_str_radix(&hex[16..], 16).ok()?;
+            Some(intent_log::client_order_id(session, seq))
+        });
+    parsed.unwrap_or_else(|| cloid.to_string())
+}
 
 #[derive(Deserialize, Debug)]
 struct HyperliquidMarket {
@@ -22,40 +63,308 @@ struct HyperliquidUniverse {
     universe: Vec<HyperliquidMarket>,
 }
 
+/// A `candleSnapshot` candle; `t` is its open time in ms.
+#[derive(Deserialize, Debug)]
+struct HyperliquidCandle {
+    t: u64,
+    o: String,
+    h: String,
+    l: String,
+    c: String,
+    v: String,
+}
+
+/// A delegated agent wallet signing on behalf of the account.
+#[derive(Clone)]
+struct AgentWallet {
+    address: String,
+    /// Shared between clones, so a renewal is seen by every handle.
+    valid_until_ms: Arc<Mutex<i64>>,
+    /// Renews the approval when set; the bot can run without it.
+    master_private_key: String,
+}
+
+#[derive(Clone)]
 pub struct Hyperliquid {
     client: reqwest::Client,
+    rate_limiter: RateLimiter,
+    /// Account queried for balance and positions: the master wallet, or the vault.
     wallet_address: String,
+    /// Signs actions: the master key, or the agent wallet's key.
     private_key: String,
+    vault_address: Option<String>,
+    agent: Option<AgentWallet>,
+    /// Hyperliquid sets the margin mode with the leverage, so leverage is set in the mode
+    /// of `live.margin_mode`, cross unless it is "isolated".
+    is_cross: bool,
 }
 
 impl Hyperliquid {
-    pub fn new(_live_config: &LiveConfig, user_config: &UserConfig) -> Self {
+    pub fn new(live_config: &LiveConfig, user_config: &UserConfig) -> Self {
+        let or_fallback = |value: &str, fallback: &str| {
+            if value.is_empty() {
+                fallback.to_string()
+            } else {
+                value.to_string()
+            }
+        };
+        let wallet_address = or_fallback(&user_config.wallet_address, &user_config.key);
         Hyperliquid {
             client: reqwest::Client::new(),
-            wallet_address: user_config.key.clone(), // Using key for wallet_address
-            private_key: user_config.secret.clone(), // Using secret for private_key
+            rate_limiter: RateLimiter::shared("hyperliquid", &live_config.rate_limit),
+            private_key: or_fallback(&user_config.private_key, &user_config.secret),
+            vault_address: user_config.is_vault.then(|| wallet_address.clone()),
+            wallet_address,
+            agent: (!user_config.agent_address.is_empty()).then(|| AgentWallet {
+                address: user_config.agent_address.clone(),
+                valid_until_ms: Arc::new(Mutex::new(user_config.agent_valid_until_ms)),
+                master_private_key: user_config.master_private_key.clone(),
+            }),
+            is_cross: MarginMode::from_str(&live_config.margin_mode) != Some(MarginMode::Isolated),
         }
     }
 
-    fn sign_exchange_request(&self, action: serde_json::Value) -> Result<String, SendSyncError> {
-        // This is a simplified signing process. A proper implementation would use a proper library for this.
-        // For the sake of this example, we'll just serialize the action to a string.
-        let payload = serde_json::to_string(&action)?;
+    /// Wraps an action in the `/exchange` request envelope, with the vault the action
+    /// trades for, if any.
+    fn build_exchange_request(&self, action: serde_json::Value, nonce: i64) -> serde_json::Value {
+        let mut request = serde_json::json!({ "action": action, "nonce": nonce });
+        if let Some(vault_address) = &self.vault_address {
+            request["vaultAddress"] = serde_json::json!(vault_address);
+        }
+        request
+    }
+
+    async fn sign_exchange_request(
+        &self, action: serde_json::Value,
+    ) -> Result<String, ExchangeError> {
+        let now_ms = Utc::now().timestamp_millis();
+        self.ensure_agent_valid(now_ms).await?;
+        let request = self.build_exchange_request(action, now_ms);
+        // This is a simplified signing process. A proper implementation would sign the
+        // action hash with `private_key` using a proper library for this.
+        let payload = serde_json::to_string(&request)?;
         Ok(payload)
     }
+
+    /// Renews the agent approval when it is about to expire and the master key is
+    /// available. Fails once the approval has expired, as the exchange would reject
+    /// every action.
+    async fn ensure_agent_valid(&self, now_ms: i64) -> Result<(), ExchangeError> {
+        let Some(agent) = &self.agent else {
+            return Ok(());
+        };
+        let valid_until_ms = *agent.valid_until_ms.lock().unwrap();
+        if !agent_needs_renewal(valid_until_ms, now_ms) {
+            return Ok(());
+        }
+        if agent.master_private_key.is_empty() {
+            if valid_until_ms <= now_ms {
+                return Err(format!(
+                    "Hyperliquid agent wallet {} expired; approve it again from the master wallet",
+                    agent.address
+                )
+                .into());
+            }
+            warn!(
+                "Hyperliquid agent wallet {} expires in {:.1} hours and no master key is configured to renew it",
+                agent.address,
+                (valid_until_ms - now_ms) as f64 / 3_600_000.0
+            );
+            return Ok(());
+        }
+        let new_valid_until_ms = now_ms + AGENT_VALIDITY_MS;
+        let request = approve_agent_request(
+            &agent.master_private_key,
+            &agent.address,
+            new_valid_until_ms,
+            now_ms,
+        )?;
+        let payload = serde_json::to_string(&request)?;
+        let response = self
+            .client
+            .post(format!("{}/exchange", HYPERLIQUID_API_URL))
+            .header("Content-Type", "application/json")
+            .body(payload)
+            .send_limited(&self.rate_limiter)
+            .await?
+            .text()
+            .await?;
+        let response_json: serde_json::Value = serde_json::from_str(&response)?;
+        if response_json["status"] != "ok" {
+            error!("Failed to renew agent wallet approval: {}", response);
+            return if valid_until_ms <= now_ms {
+                Err(response.into())
+            } else {
+                Ok(())
+            };
+        }
+        info!(
+            "Renewed Hyperliquid agent wallet {} until {}",
+            agent.address, new_valid_until_ms
+        );
+        *agent.valid_until_ms.lock().unwrap() = new_valid_until_ms;
+        Ok(())
+    }
+
+    async fn fetch_position(&self, symbol: &str) -> Result<Position, ExchangeError> {
+        info!("Fetching position for symbol: {}", symbol);
+        let url = format!("{}/info", HYPERLIQUID_API_URL);
+        let body = serde_json::json!({ "type": "clearinghouseState", "user": self.wallet_address });
+        let response = self
+            .client
+            .post(&url)
+            .json(&body)
+            .send_weighted(&self.rate_limiter, info_weight(&body))
+            .await?
+            .text()
+            .await?;
+        let state: serde_json::Value = serde_json::from_str(&response)?;
+        position_from_state(&state, symbol)
+    }
+}
+
+fn keccak256(data: &[u8]) -> [u8; 32] {
+    use tiny_keccak::{Hasher, Keccak};
+    let mut hasher = Keccak::v256();
+    let mut hash = [0u8; 32];
+    hasher.update(data);
+    hasher.finalize(&mut hash);
+    hash
+}
+
+/// A 20-byte address left-padded to an EIP-712 word.
+fn address_word(address: &str) -> Result<[u8; 32], ExchangeError> {
+    let bytes = hex::decode(address.trim_start_matches("0x"))
+        .map_err(|e| format!("Invalid address {}: {}", address, e))?;
+    if bytes.len() != 20 {
+        return Err(format!("Invalid address {}: not 20 bytes", address).into());
+    }
+    let mut word = [0u8; 32];
+    word[12..].copy_from_slice(&bytes);
+    Ok(word)
+}
+
+fn uint_word(value: u64) -> [u8; 32] {
+    let mut word = [0u8; 32];
+    word[24..].copy_from_slice(&value.to_be_bytes());
+    word
+}
+
+/// The EIP-712 hash the master wallet signs to approve `agent_address`.
+fn approve_agent_digest(
+    agent_address: &str, agent_name: &str, nonce: u64,
+) -> Result<[u8; 32], ExchangeError> {
+    let mut domain = keccak256(
+        b"EIP712Domain(string name,string version,uint256 chainId,address verifyingContract)",
+    )
+    .to_vec();
+    domain.extend(keccak256(b"HyperliquidSignTransaction"));
+    domain.extend(keccak256(b"1"));
+    domain.extend(uint_word(SIGNATURE_CHAIN_ID));
+    domain.extend([0u8; 32]);
+
+    let mut message = keccak256(
+        b"HyperliquidTransaction:ApproveAgent(string hyperliquidChain,address agentAddress,string agentName,uint64 nonce)",
+    )
+    .to_vec();
+    message.extend(keccak256(b"Mainnet"));
+    message.extend(address_word(agent_address)?);
+    message.extend(keccak256(agent_name.as_bytes()));
+    message.extend(uint_word(nonce));
+
+    let mut encoded = vec![0x19, 0x01];
+    encoded.extend(keccak256(&domain));
+    encoded.extend(keccak256(&message));
+    Ok(keccak256(&encoded))
+}
+
+/// The `{r, s, v}` signature of `digest` by the hex `private_key`.
+fn sign_digest(private_key: &str, digest: &[u8; 32]) -> Result<serde_json::Value, ExchangeError> {
+    let key_bytes = hex::decode(private_key.trim_start_matches("0x"))
+        .map_err(|e| format!("Invalid private key: {}", e))?;
+    let signing_key = k256::ecdsa::SigningKey::from_slice(&key_bytes)
+        .map_err(|e| format!("Invalid private key: {}", e))?;
+    let (signature, recovery_id) = signing_key
+        .sign_prehash_recoverable(digest)
+        .map_err(|e| format!("Failed to sign: {}", e))?;
+    let (r, s) = signature.split_bytes();
+    Ok(serde_json::json!({
+        "r": format!("0x{}", hex::encode(r)),
+        "s": format!("0x{}", hex::encode(s)),
+        "v": 27 + recovery_id.to_byte(),
+    }))
+}
+
+/// The `/exchange` request approving `agent_address` until `valid_until_ms`, signed by the
+/// master wallet itself and never on behalf of a vault.
+fn approve_agent_request(
+    master_private_key: &str, agent_address: &str, valid_until_ms: i64, now_ms: i64,
+) -> Result<serde_json::Value, ExchangeError> {
+    let agent_name = format!("passivbot valid_until {}", valid_until_ms);
+    let digest = approve_agent_digest(agent_address, &agent_name, now_ms as u64)?;
+    let action = serde_json::json!({
+        "type": "approveAgent",
+        "hyperliquidChain": "Mainnet",
+        "signatureChainId": format!("{:#x}", SIGNATURE_CHAIN_ID),
+        "agentAddress": agent_address,
+        "agentName": agent_name,
+        "nonce": now_ms,
+    });
+    Ok(serde_json::json!({
+        "action": action,
+        "nonce": now_ms,
+        "signature": sign_digest(master_private_key, &digest)?,
+    }))
+}
+
+/// The position in `symbol` of a clearinghouse state, which lists only open positions.
+fn position_from_state(state: &serde_json::Value, symbol: &str) -> Result<Position, ExchangeError> {
+    if let Some(asset_positions) = state["assetPositions"].as_array() {
+        let coin = symbol.replace("/USDC:USDC", "");
+        for pos in asset_positions {
+            if let Some(position_info) = pos.get("position") {
+                if let Some(pos_coin) = position_info["coin"].as_str() {
+                    if pos_coin == coin {
+                        let size_str = position_info["szi"].as_str().unwrap_or("0");
+                        let price_str = position_info["entryPx"].as_str().unwrap_or("0");
+                        if price_str.is_empty() {
+                            return Ok(Position {
+                                size: 0.0,
+                                price: 0.0,
+                            });
+                        }
+                        let size = size_str.parse::<f64>()?;
+                        let price = price_str.parse::<f64>()?;
+                        return Ok(Position { size, price });
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(Position {
+        size: 0.0,
+        price: 0.0,
+    })
+}
+
+/// Whether an agent approval expiring at `valid_until_ms` (0 for never) is due for renewal.
+fn agent_needs_renewal(valid_until_ms: i64, now_ms: i64) -> bool {
+    valid_until_ms > 0 && valid_until_ms - now_ms < AGENT_RENEWAL_WINDOW_MS
 }
 
 #[async_trait]
 impl Exchange for Hyperliquid {
     fn clone_box(&self) -> Box<dyn Exchange> {
-        Box::new(Hyperliquid {
-            client: self.client.clone(),
-            wallet_address: self.wallet_address.clone(),
-            private_key: self.private_key.clone(),
-        })
+        Box::new(self.clone())
+    }
+
+    fn quote(&self) -> &str {
+        // Hyperliquid perps are all USDC-margined
+        "USDC"
     }
 
-    async fn load_markets(&self) -> Result<HashMap<String, Market>, SendSyncError> {
+    async fn load_markets(&self) -> Result<HashMap<String, Market>, ExchangeError> {
         info!("Loading markets from Hyperliquid");
         let url = format!("{}/info", HYPERLIQUID_API_URL);
         let body = serde_json::json!({ "type": "meta" });
@@ -63,7 +372,7 @@ impl Exchange for Hyperliquid {
             .client
             .post(&url)
             .json(&body)
-            .send()
+            .send_weighted(&self.rate_limiter, info_weight(&body))
             .await?
             .text()
             .await?;
@@ -81,6 +390,7 @@ impl Exchange for Hyperliquid {
                         swap: true,
                         linear: true,
                         created_at: 0, // not provided
+                        settle: "USDC".to_string(),
                     },
                 )
             })
@@ -91,7 +401,7 @@ impl Exchange for Hyperliquid {
 
     async fn fetch_tickers(
         &self, symbols: &[String],
-    ) -> Result<HashMap<String, Ticker>, SendSyncError> {
+    ) -> Result<HashMap<String, Ticker>, ExchangeError> {
         info!("Fetching tickers from Hyperliquid");
         let url = format!("{}/info", HYPERLIQUID_API_URL);
         let body = serde_json::json!({ "type": "allMids" });
@@ -99,7 +409,7 @@ impl Exchange for Hyperliquid {
             .client
             .post(&url)
             .json(&body)
-            .send()
+            .send_weighted(&self.rate_limiter, info_weight(&body))
             .await?
             .text()
             .await?;
@@ -118,6 +428,7 @@ impl Exchange for Hyperliquid {
                         ask: price,
                         last: price,
                         quote_volume: 0.0, // not provided
+                        funding_rate: 0.0,
                     },
                 )
             })
@@ -126,21 +437,20 @@ impl Exchange for Hyperliquid {
         Ok(tickers)
     }
 
-    async fn fetch_ticker(&self, symbol: &str) -> Result<f64, SendSyncError> {
+    async fn fetch_ticker(&self, symbol: &str) -> Result<f64, ExchangeError> {
         info!("Fetching ticker for symbol: {}", symbol);
         let tickers = self.fetch_tickers(&[symbol.to_string()]).await?;
         if let Some(ticker) = tickers.get(symbol) {
             Ok(ticker.last)
         } else {
             error!("Ticker data not found for symbol: {}", symbol);
-            Err(Box::new(std::io::Error::new(
-                std::io::ErrorKind::Other,
+            Err(ExchangeError::classify(
                 "Ticker data not found in Hyperliquid response",
-            )))
+            ))
         }
     }
 
-    async fn fetch_order_book(&self, symbol: &str) -> Result<OrderBook, SendSyncError> {
+    async fn fetch_order_book(&self, symbol: &str) -> Result<OrderBook, ExchangeError> {
         info!("Fetching order book for symbol: {}", symbol);
         let url = format!("{}/info", HYPERLIQUID_API_URL);
         let body =
@@ -149,7 +459,7 @@ impl Exchange for Hyperliquid {
             .client
             .post(&url)
             .json(&body)
-            .send()
+            .send_weighted(&self.rate_limiter, info_weight(&body))
             .await?
             .text()
             .await?;
@@ -181,7 +491,49 @@ impl Exchange for Hyperliquid {
         Ok(OrderBook { bids, asks })
     }
 
-    async fn fetch_balance(&self) -> Result<f64, SendSyncError> {
+    async fn fetch_ohlcv(
+        &self, symbol: &str, timeframe: &str, since_ms: Option<i64>, limit: usize,
+    ) -> Result<Vec<Candle>, ExchangeError> {
+        let span_ms = timeframe_ms(timeframe)? * limit as i64;
+        let now_ms = Utc::now().timestamp_millis();
+        let start_ms = since_ms.unwrap_or(now_ms - span_ms);
+        let url = format!("{}/info", HYPERLIQUID_API_URL);
+        let body = serde_json::json!({
+            "type": "candleSnapshot",
+            "req": {
+                "coin": symbol.replace("/USDC:USDC", ""),
+                "interval": timeframe,
+                "startTime": start_ms,
+                "endTime": (start_ms + span_ms).min(now_ms),
+            },
+        });
+        let response = self
+            .client
+            .post(&url)
+            .json(&body)
+            .send_weighted(&self.rate_limiter, info_weight(&body))
+            .await?
+            .text()
+            .await?;
+        let candles: Vec<HyperliquidCandle> = serde_json::from_str(&response)?;
+        let mut candles = candles
+            .into_iter()
+            .map(|c| {
+                Ok(Candle {
+                    timestamp: c.t,
+                    open: c.o.parse()?,
+                    high: c.h.parse()?,
+                    low: c.l.parse()?,
+                    close: c.c.parse()?,
+                    volume: c.v.parse()?,
+                })
+            })
+            .collect::<Result<Vec<_>, ExchangeError>>()?;
+        candles.truncate(limit);
+        Ok(candles)
+    }
+
+    async fn fetch_balance(&self) -> Result<f64, ExchangeError> {
         info!("Fetching balance from Hyperliquid");
         let url = format!("{}/info", HYPERLIQUID_API_URL);
         let body = serde_json::json!({ "type": "clearinghouseState", "user": self.wallet_address });
@@ -189,7 +541,7 @@ impl Exchange for Hyperliquid {
             .client
             .post(&url)
             .json(&body)
-            .send()
+            .send_weighted(&self.rate_limiter, info_weight(&body))
             .await?
             .text()
             .await?;
@@ -214,9 +566,9 @@ impl Exchange for Hyperliquid {
         Ok(account_value - unrealized_pnl)
     }
 
-    async fn place_order(&mut self, order: &Order) -> Result<(), SendSyncError> {
+    async fn place_order(&mut self, order: &Order) -> Result<(), ExchangeError> {
         info!("Placing order: {:?}", order);
-        let action = serde_json::json!({
+        let mut action = serde_json::json!({
             "type": "order",
             "orders": [
                 {
@@ -230,15 +582,18 @@ impl Exchange for Hyperliquid {
             ],
             "grouping": "na",
         });
+        if let Some(cloid) = to_cloid(&order.client_order_id) {
+            action["orders"][0]["cloid"] = serde_json::json!(cloid);
+        }
 
-        let payload = self.sign_exchange_request(action)?;
+        let payload = self.sign_exchange_request(action).await?;
 
         let response = self
             .client
             .post(format!("{}/exchange", HYPERLIQUID_API_URL))
             .header("Content-Type", "application/json")
             .body(payload)
-            .send()
+            .send_limited(&self.rate_limiter)
             .await?
             .text()
             .await?;
@@ -248,34 +603,30 @@ impl Exchange for Hyperliquid {
             Ok(())
         } else {
             error!("Failed to place order: {}", response);
-            Err(Box::new(std::io::Error::new(
-                std::io::ErrorKind::Other,
-                response,
-            )))
+            Err(ExchangeError::classify(response))
         }
     }
 
-    async fn cancel_order(&mut self, order_id: &str) -> Result<(), SendSyncError> {
-        info!("Canceling order: {}", order_id);
-        let (symbol, oid) = order_id.split_at(order_id.find(':').unwrap_or(order_id.len()));
+    async fn cancel_order(&mut self, symbol: &str, order_id: &str) -> Result<(), ExchangeError> {
+        info!("[{}] Canceling order: {}", symbol, order_id);
         let action = serde_json::json!({
             "type": "cancel",
             "cancels": [
                 {
                     "coin": symbol.replace("/USDC:USDC", ""),
-                    "oid": oid.trim_start_matches(':').parse::<u64>()?
+                    "oid": order_id.parse::<u64>()?
                 }
             ]
         });
 
-        let payload = self.sign_exchange_request(action)?;
+        let payload = self.sign_exchange_request(action).await?;
 
         let response = self
             .client
             .post(format!("{}/exchange", HYPERLIQUID_API_URL))
             .header("Content-Type", "application/json")
             .body(payload)
-            .send()
+            .send_limited(&self.rate_limiter)
             .await?
             .text()
             .await?;
@@ -285,56 +636,59 @@ impl Exchange for Hyperliquid {
             Ok(())
         } else {
             error!("Failed to cancel order: {}", response);
-            Err(Box::new(std::io::Error::new(
-                std::io::ErrorKind::Other,
-                response,
-            )))
+            Err(ExchangeError::classify(response))
         }
     }
 
-    async fn fetch_position(&self, symbol: &str) -> Result<Position, SendSyncError> {
-        info!("Fetching position for symbol: {}", symbol);
+    async fn fetch_open_orders(&self, symbol: &str) -> Result<Vec<Order>, ExchangeError> {
         let url = format!("{}/info", HYPERLIQUID_API_URL);
-        let body = serde_json::json!({ "type": "clearinghouseState", "user": self.wallet_address });
+        let body = serde_json::json!({ "type": "frontendOpenOrders", "user": self.wallet_address });
         let response = self
             .client
             .post(&url)
             .json(&body)
-            .send()
+            .send_weighted(&self.rate_limiter, info_weight(&body))
             .await?
             .text()
             .await?;
-        let state: serde_json::Value = serde_json::from_str(&response)?;
-
-        if let Some(asset_positions) = state["assetPositions"].as_array() {
-            let coin = symbol.replace("/USDC:USDC", "");
-            for pos in asset_positions {
-                if let Some(position_info) = pos.get("position") {
-                    if let Some(pos_coin) = position_info["coin"].as_str() {
-                        if pos_coin == coin {
-                            let size_str = position_info["szi"].as_str().unwrap_or("0");
-                            let price_str = position_info["entryPx"].as_str().unwrap_or("0");
-                            if price_str.is_empty() {
-                                return Ok(Position {
-                                    size: 0.0,
-                                    price: 0.0,
-                                });
-                            }
-                            let size = size_str.parse::<f64>()?;
-                            let price = price_str.parse::<f64>()?;
-                            return Ok(Position { size, price });
-                        }
-                    }
+        let open_orders: Vec<serde_json::Value> = serde_json::from_str(&response)
+            .map_err(|_| format!("Failed to fetch open orders: {}", response))?;
+
+        let coin = symbol.replace("/USDC:USDC", "");
+        let mut orders = Vec::new();
+        for open_order in open_orders
+            .iter()
+            .filter(|o| o["coin"].as_str() == Some(coin.as_str()))
+        {
+            let oid = open_order["oid"].as_u64().ok_or("Open order without oid")?;
+            orders.push(Order {
+                id: oid.to_string(),
+                symbol: symbol.to_string(),
+                // "B" for bids, "A" for asks
+                side: if open_order["side"] == "B" {
+                    "Buy"
+                } else {
+                    "Sell"
                 }
-            }
+                .to_string(),
+                position_side: String::new(),
+                qty: open_order["sz"].as_str().unwrap_or("0").parse()?,
+                price: open_order["limitPx"].as_str().unwrap_or("0").parse()?,
+                reduce_only: open_order["reduceOnly"].as_bool().unwrap_or(false),
+                custom_id: String::new(),
+                client_order_id: from_cloid(open_order["cloid"].as_str().unwrap_or_default()),
+                time_in_force: open_order["tif"].as_str().unwrap_or_default().to_string(),
+            });
         }
+        Ok(orders)
+    }
 
-        Ok(Position {
-            size: 0.0,
-            price: 0.0,
-        })
+    async fn fetch_positions(&self, symbol: &str) -> Result<PositionPair, ExchangeError> {
+        // positions are one-way only
+        Ok(PositionPair::from_net(self.fetch_position(symbol).await?))
     }
-    async fn fetch_exchange_params(&self, symbol: &str) -> Result<ExchangeParams, SendSyncError> {
+
+    async fn fetch_exchange_params(&self, symbol: &str) -> Result<ExchangeParams, ExchangeError> {
         info!("Fetching exchange params for symbol: {}", symbol);
         let url = format!("{}/info", HYPERLIQUID_API_URL);
         let body = serde_json::json!({ "type": "meta" });
@@ -342,7 +696,7 @@ impl Exchange for Hyperliquid {
             .client
             .post(&url)
             .json(&body)
-            .send()
+            .send_weighted(&self.rate_limiter, info_weight(&body))
             .await?
             .text()
             .await?;
@@ -366,9 +720,152 @@ impl Exchange for Hyperliquid {
             }
         }
 
-        Err(Box::new(std::io::Error::new(
-            std::io::ErrorKind::Other,
-            format!("Could not find market info for {}", symbol),
+        Err(ExchangeError::classify(format!(
+            "Could not find market info for {}",
+            symbol
         )))
     }
+
+    async fn set_leverage(&mut self, symbol: &str, leverage: f64) -> Result<(), ExchangeError> {
+        info!("Setting leverage for {} to {}", symbol, leverage);
+        let action = serde_json::json!({
+            "type": "updateLeverage",
+            "coin": symbol.replace("/USDC:USDC", ""),
+            "isCross": self.is_cross,
+            // whole multiples only
+            "leverage": leverage.round().max(1.0) as u32,
+        });
+
+        let payload = self.sign_exchange_request(action).await?;
+
+        let response = self
+            .client
+            .post(format!("{}/exchange", HYPERLIQUID_API_URL))
+            .header("Content-Type", "application/json")
+            .body(payload)
+            .send_limited(&self.rate_limiter)
+            .await?
+            .text()
+            .await?;
+
+        // setting the current leverage again succeeds
+        let response_json: serde_json::Value = serde_json::from_str(&response)?;
+        if response_json["status"] == "ok" {
+            Ok(())
+        } else {
+            Err(ExchangeError::classify(response))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn user_config(is_vault: bool, agent_address: &str) -> UserConfig {
+        serde_json::from_value(serde_json::json!({
+            "exchange": "hyperliquid",
+            "wallet_address": "0xvault",
+            "private_key": "0xkey",
+            "is_vault": is_vault,
+            "agent_address": agent_address,
+            "agent_valid_until_ms": 1_000_000,
+        }))
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_vault_and_agent_wallet() {
+        let live_config = crate::config::load_config("config.hjson").unwrap().live;
+        let action = serde_json::json!({ "type": "cancel" });
+
+        let vault = Hyperliquid::new(&live_config, &user_config(true, ""));
+        let request = vault.build_exchange_request(action.clone(), 1);
+        assert_eq!(request["vaultAddress"], "0xvault");
+        assert_eq!(request["action"], action);
+        let master = Hyperliquid::new(&live_config, &user_config(false, ""));
+        let request = master.build_exchange_request(action, 1);
+        assert!(request.get("vaultAddress").is_none());
+
+        assert!(!agent_needs_renewal(0, 1_000_000));
+        assert!(!agent_needs_renewal(AGENT_RENEWAL_WINDOW_MS + 10, 0));
+        assert!(agent_needs_renewal(AGENT_RENEWAL_WINDOW_MS - 10, 0));
+
+        // an expired agent without a master key cannot sign
+        let agent = Hyperliquid::new(&live_config, &user_config(false, "0xagent"));
+        assert!(agent.ensure_agent_valid(999_000).await.is_ok());
+        assert!(agent.ensure_agent_valid(1_000_000).await.is_err());
+    }
+
+    #[test]
+    fn test_cloid_round_trip() {
+        let id = intent_log::client_order_id(1_760_000_000_000, 42);
+        let cloid = to_cloid(&id).unwrap();
+        assert_eq!(cloid.len(), 2 + 32);
+        assert_eq!(from_cloid(&cloid), id);
+        assert_eq!(to_cloid("manual-order"), None);
+        assert_eq!(from_cloid("0xabc"), "0xabc");
+    }
+
+    #[test]
+    fn test_approve_agent_is_signed_by_master() {
+        use k256::ecdsa::{RecoveryId, Signature, VerifyingKey};
+        // the key 1, whose address is well known
+        let master_key = format!("0x{:064x}", 1);
+        let agent = "0x1111111111111111111111111111111111111111";
+        let request = approve_agent_request(&master_key, agent, 2_000, 1_000).unwrap();
+        assert_eq!(request["nonce"], 1_000);
+        assert_eq!(request["action"]["type"], "approveAgent");
+        assert_eq!(request["action"]["signatureChainId"], "0xa4b1");
+        assert_eq!(request["action"]["agentName"], "passivbot valid_until 2000");
+        assert!(request.get("vaultAddress").is_none());
+
+        let field = |name: &str| {
+            hex::decode(
+                request["signature"][name]
+                    .as_str()
+                    .unwrap()
+                    .trim_start_matches("0x"),
+            )
+            .unwrap()
+        };
+        let signature = Signature::from_slice(&[field("r"), field("s")].concat()).unwrap();
+        let v = request["signature"]["v"].as_u64().unwrap();
+        let recovery_id = RecoveryId::from_byte(v as u8 - 27).unwrap();
+        let digest = approve_agent_digest(agent, "passivbot valid_until 2000", 1_000).unwrap();
+        let signer = VerifyingKey::recover_from_prehash(&digest, &signature, recovery_id).unwrap();
+        let public_key = signer.to_encoded_point(false);
+        let address = hex::encode(&keccak256(&public_key.as_bytes()[1..])[12..]);
+        assert_eq!(address, "7e5f4552091a69125d5dfcb7b8c2659029395bdf");
+
+        // another agent or nonce is another message
+        assert_ne!(
+            approve_agent_digest(agent, "passivbot valid_until 2000", 1_001).unwrap(),
+            digest
+        );
+        assert!(approve_agent_request("0xnotakey", agent, 2_000, 1_000).is_err());
+    }
+
+    #[test]
+    fn test_positions_are_one_way_also_while_flat() {
+        let state: serde_json::Value = serde_json::from_str(
+            r#"{"marginSummary":{"accountValue":"1000.5","totalNtlPos":"960.2",
+            "totalRawUsd":"1960.7","totalMarginUsed":"96.0"},"crossMaintenanceMarginUsed":"9.6",
+            "withdrawable":"904.5","assetPositions":[{"type":"oneWay","position":{"coin":"ETH",
+            "szi":"-0.3","leverage":{"type":"cross","value":10},"entryPx":"3200.5",
+            "positionValue":"960.2","unrealizedPnl":"0.45","returnOnEquity":"0.004",
+            "liquidationPx":"6400.1","marginUsed":"96.0","maxLeverage":50}}],"time":1718000000000}"#,
+        )
+        .unwrap();
+        let eth = position_from_state(&state, "ETH/USDC:USDC").unwrap();
+        assert_eq!((eth.size, eth.price), (-0.3, 3200.5));
+        let pair = PositionPair::from_net(eth);
+        assert!(pair.one_way);
+        assert_eq!(pair.short.size, -0.3);
+
+        // a coin without a position is not listed
+        let flat = PositionPair::from_net(position_from_state(&state, "BTC/USDC:USDC").unwrap());
+        assert!(flat.one_way);
+        assert_eq!((flat.long.size, flat.short.size), (0.0, 0.0));
+    }
 }
@@ -0,0 +1,272 @@
+use super::queue::OrderRequest;
+use super::{Exchange, SendSyncError};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use tracing::{debug, info, warn};
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+enum IntentRecord {
+    Begin { seq: u64, request: OrderRequest },
+    Done { seq: u64 },
+}
+
+/// Write-ahead log of order requests, stored as JSON lines at `Paths::intent_log`.
+///
+/// Every request is logged before it is sent and marked done once the exchange has
+/// answered, so requests still open after a crash are known to be ambiguous: they may or
+/// may not have reached the exchange. The log is truncated whenever nothing is in flight.
+#[derive(Debug, Default)]
+pub struct IntentLog {
+    path: Option<PathBuf>,
+    /// Prefix of generated client order ids, unique per run.
+    session: u64,
+    next_seq: u64,
+    in_flight: BTreeMap<u64, OrderRequest>,
+}
+
+impl IntentLog {
+    /// Opens the log at `path`, loading the requests left in flight by the previous run.
+    /// The log is disabled without a path.
+    pub fn open(path: Option<PathBuf>) -> Self {
+        let Some(path) = path else {
+            return Self::default();
+        };
+        let mut in_flight = BTreeMap::new();
+        if let Ok(content) = fs::read_to_string(&path) {
+            for line in content.lines() {
+                // a crash can leave the last line half written
+                match serde_json::from_str::<IntentRecord>(line) {
+                    Ok(IntentRecord::Begin { seq, request }) => {
+                        in_flight.insert(seq, request);
+                    }
+                    Ok(IntentRecord::Done { seq }) => {
+                        in_flight.remove(&seq);
+                    }
+                    Err(e) => warn!(
+                        "Skipping unreadable intent record in {}: {}",
+                        path.display(),
+                        e
+                    ),
+                }
+            }
+        }
+        Self {
+            path: Some(path),
+            session: Utc::now().timestamp_millis() as u64,
+            next_seq: in_flight.keys().next_back().map_or(0, |seq| seq + 1),
+            in_flight,
+        }
+    }
+
+    /// Requests logged but never marked done, oldest first.
+    pub fn in_flight(&self) -> Vec<(u64, OrderRequest)> {
+        self.in_flight
+            .iter()
+            .map(|(seq, request)| (*seq, request.clone()))
+            .collect()
+    }
+
+    /// Durably logs a request about to be sent and returns its sequence number, or None if
+    /// the log is disabled. Orders without a client order id are given one, so they can
+    /// be found on the exchange after a crash.
+    pub fn begin(&mut self, request: &mut OrderRequest) -> Result<Option<u64>, SendSyncError> {
+        if self.path.is_none() {
+            return Ok(None);
+        }
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        if let OrderRequest::Place(order) = request {
+            if order.client_order_id.is_empty() {
+                order.client_order_id = client_order_id(self.session, seq);
+            }
+        }
+        self.append(
+            &IntentRecord::Begin {
+                seq,
+                request: request.clone(),
+            },
+            true,
+        )?;
+        self.in_flight.insert(seq, request.clone());
+        Ok(Some(seq))
+    }
+
+    /// Marks a request as answered by the exchange.
+    pub fn complete(&mut self, seq: u64) -> Result<(), SendSyncError> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        self.in_flight.remove(&seq);
+        if self.in_flight.is_empty() {
+            File::create(path)?;
+            Ok(())
+        } else {
+            self.append(&IntentRecord::Done { seq }, false)
+        }
+    }
+
+    fn append(&self, record: &IntentRecord, sync: bool) -> Result<(), SendSyncError> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+        writeln!(file, "{}", serde_json::to_string(record)?)?;
+        if sync {
+            file.sync_data()?;
+        }
+        Ok(())
+    }
+}
+
+/// Client order id of request `seq` of `session`. Letters and digits only, as OKX
+/// requires, and short enough for Binance, Bybit and Gate.io, which prefixes it with "t-".
+pub fn client_order_id(session: u64, seq: u64) -> String {
+    format!("pb{}x{}", session, seq)
+}
+
+/// The session and sequence number of an id made by `client_order_id`.
+pub fn parse_client_order_id(id: &str) -> Option<(u64, u64)> {
+    let (session, seq) = id.strip_prefix("pb")?.split_once('x')?;
+    Some((session.parse().ok()?, seq.parse().ok()?))
+}
+
+/// Resolves the requests a previous run left in flight. Cancels are sent again, since
+/// cancelling twice is harmless. Orders that did reach the exchange are cancelled, as the
+/// restarted bot does not know about them and would place them a second time. Requests
+/// that cannot be resolved stay in the log for the next start.
+pub async fn recover(exchange: &mut Box<dyn Exchange>, log: &mut IntentLog) {
+    let in_flight = log.in_flight();
+    if in_flight.is_empty() {
+        return;
+    }
+    warn!(
+        "Resolving {} order requests left in flight by the previous run",
+        in_flight.len()
+    );
+    for (seq, request) in in_flight {
+        let resolved = match &request {
+            OrderRequest::Cancel(cancel) => {
+                if let Err(e) = exchange
+                    .cancel_order(&cancel.symbol, &cancel.order_id)
+                    .await
+                {
+                    debug!("Replayed cancel of order {} failed: {}", cancel.order_id, e);
+                }
+                true
+            }
+            OrderRequest::Place(order) => match exchange.fetch_open_orders(&order.symbol).await {
+                Ok(open_orders) => match open_orders
+                    .iter()
+                    .find(|o| o.client_order_id == order.client_order_id)
+                {
+                    Some(open_order) => {
+                        info!(
+                            "[{}] Cancelling order {} placed before the restart",
+                            order.symbol, open_order.client_order_id
+                        );
+                        match exchange.cancel_order(&order.symbol, &open_order.id).await {
+                            Ok(()) => true,
+                            Err(e) => {
+                                warn!(
+                                    "[{}] Failed to cancel order {}: {}",
+                                    order.symbol, open_order.id, e
+                                );
+                                false
+                            }
+                        }
+                    }
+                    None => true,
+                },
+                Err(e) => {
+                    warn!(
+                        "[{}] Cannot resolve in-flight order {}: {}",
+                        order.symbol, order.client_order_id, e
+                    );
+                    false
+                }
+            },
+        };
+        if resolved {
+            if let Err(e) = log.complete(seq) {
+                warn!("Failed to update intent log: {}", e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::exchange::simulated::SimulatedExchange;
+    use crate::paths::Paths;
+    use crate::types::Order;
+
+    fn order(price: f64) -> Order {
+        Order {
+            id: String::new(),
+            symbol: "BTCUSDT".to_string(),
+            side: "Buy".to_string(),
+            position_side: "Long".to_string(),
+            qty: 0.1,
+            price,
+            reduce_only: false,
+            custom_id: String::new(),
+            client_order_id: String::new(),
+            time_in_force: String::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_recover_unwinds_orders_placed_before_crash() {
+        let state_dir =
+            std::env::temp_dir().join(format!("intent_log_test_{}", std::process::id()));
+        let paths = Paths::new(state_dir.to_str().unwrap(), "test_user");
+        let simulated = SimulatedExchange::new(1000.0);
+        simulated.update_price(100.0, 100.0, 100.0);
+        let mut exchange = simulated.clone_box();
+
+        let mut log = IntentLog::open(paths.intent_log());
+        // acknowledged order
+        let mut request = OrderRequest::Place(order(99.0));
+        let seq = log.begin(&mut request).unwrap().unwrap();
+        if let OrderRequest::Place(order) = &request {
+            exchange.place_order(order).await.unwrap();
+        }
+        log.complete(seq).unwrap();
+        // sent, then crashed before the acknowledgement
+        let mut request = OrderRequest::Place(order(98.0));
+        let seq = log.begin(&mut request).unwrap().unwrap();
+        if let OrderRequest::Place(order) = &request {
+            let id = &order.client_order_id;
+            assert!(id.chars().all(|c| c.is_ascii_alphanumeric()), "{}", id);
+            assert_eq!(parse_client_order_id(id).map(|(_, seq)| seq), Some(seq));
+            exchange.place_order(order).await.unwrap();
+        }
+        // crashed before sending
+        log.begin(&mut OrderRequest::Place(order(97.0))).unwrap();
+        drop(log);
+
+        let mut log = IntentLog::open(paths.intent_log());
+        assert_eq!(log.in_flight().len(), 2);
+        recover(&mut exchange, &mut log).await;
+        assert!(log.in_flight().is_empty());
+        let prices: Vec<f64> = simulated
+            .snapshot()
+            .orders
+            .iter()
+            .map(|o| o.price)
+            .collect();
+        assert_eq!(prices, vec![99.0]);
+        assert!(IntentLog::open(paths.intent_log()).in_flight().is_empty());
+
+        fs::remove_dir_all(&state_dir).unwrap();
+    }
+}
@@ -0,0 +1,171 @@
+//! Startup check of the API key: whether it may trade futures, permissions it should not
+//! have, and why the exchange rejects it, instead of auth errors surfacing mid-trading.
+//! Fetching the key's permissions is a signed request like any other, so a wrong key,
+//! secret or IP fails it.
+
+use super::{Exchange, ExchangeError, SendSyncError};
+use crate::listings::MS_PER_DAY;
+use crate::types::ApiKeyInfo;
+use chrono::Utc;
+use tracing::warn;
+
+/// Lower-case fragments of the exchanges' rejections of a key, checked in this order, with
+/// what they mean.
+const REJECTIONS: &[(&[&str], &str)] = &[
+    (
+        &[
+            "unmatched ip",
+            "ip whitelist",
+            "not included in your api key",
+        ],
+        "is bound to IP addresses other than this machine's; add this machine's public IP \
+        to the key's whitelist",
+    ),
+    (
+        &["-2015"],
+        "was refused for this machine's IP or the key's permissions; whitelist this \
+        machine's public IP and enable futures for the key",
+    ),
+    (&["expired"], "has expired; create a new key"),
+    (
+        &[
+            "api key is invalid",
+            "api key does not exist",
+            "api key doesn't exist",
+            "invalid ok-access-key",
+            "-2014",
+        ],
+        "does not exist or was deleted; check the key in api-keys.json",
+    ),
+    (
+        &["signature", "error sign", "invalid sign", "-1022"],
+        "was sent with a signature the exchange does not accept; check the secret and \
+        passphrase in api-keys.json",
+    ),
+];
+
+/// What the check found, short of a rejected key.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct KeyCheck {
+    /// Whether the key may trade futures, if the exchange reports it.
+    pub can_trade: Option<bool>,
+    /// Findings for the operator that do not stop the bot.
+    pub warnings: Vec<String>,
+}
+
+/// Checks the API key of `user` from what `fetch_api_key_info` returned at `now_ms`. Fails
+/// with the reason the exchange rejected the key; other errors leave it unchecked.
+pub fn check_api_key(
+    user: &str, info: Result<Option<ApiKeyInfo>, ExchangeError>, now_ms: i64,
+) -> Result<KeyCheck, String> {
+    let info = match info {
+        Ok(Some(info)) => info,
+        Ok(None) => return Ok(KeyCheck::default()),
+        Err(ExchangeError::AuthFailed(message)) => {
+            let lower = message.to_lowercase();
+            let reason = REJECTIONS
+                .iter()
+                .find(|(fragments, _)| fragments.iter().any(|f| lower.contains(f)))
+                .map_or(
+                    "was rejected; check the key and secret in api-keys.json, the key's \
+                    permissions and its IP whitelist",
+                    |(_, reason)| reason,
+                );
+            return Err(format!("The API key of {} {} ({})", user, reason, message));
+        }
+        Err(e) => {
+            return Ok(KeyCheck {
+                can_trade: None,
+                warnings: vec![format!("Failed to check the API key of {}: {}", user, e)],
+            })
+        }
+    };
+
+    let mut warnings = Vec::new();
+    if info.can_withdraw {
+        warnings.push(format!(
+            "The API key of {} may withdraw funds, which the bot never does; remove the \
+            permission so a leaked key cannot empty the account",
+            user
+        ));
+    }
+    if !info.ip_restricted {
+        warnings.push(format!(
+            "The API key of {} is not bound to IP addresses; whitelist this machine's so a \
+            leaked key cannot be used elsewhere",
+            user
+        ));
+    }
+    if let Some(expires_at_ms) = info.expires_at_ms {
+        let days_left = (expires_at_ms - now_ms) as f64 / MS_PER_DAY as f64;
+        if days_left < 7.0 {
+            warnings.push(format!(
+                "The API key of {} expires in {:.1} days",
+                user, days_left
+            ));
+        }
+    }
+    Ok(KeyCheck {
+        can_trade: Some(info.can_trade),
+        warnings,
+    })
+}
+
+/// Checks the API key of `user` on `exchange` and logs the warnings.
+pub async fn preflight(exchange: &dyn Exchange, user: &str) -> Result<KeyCheck, SendSyncError> {
+    let info = exchange.fetch_api_key_info().await;
+    let check = check_api_key(user, info, Utc::now().timestamp_millis())?;
+    for warning in &check.warnings {
+        warn!("{}", warning);
+    }
+    Ok(check)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rejected_keys_fail_with_the_reason() {
+        let rejected = |message: &str| {
+            check_api_key("alice", Err(ExchangeError::classify(message)), 0).unwrap_err()
+        };
+        assert!(
+            rejected("Unmatched IP, please check your API key's bound IP addresses.")
+                .contains("add this machine's public IP")
+        );
+        assert!(rejected(
+            r#"{"code":-2015,"msg":"Invalid API-key, IP, or permissions for action"}"#
+        )
+        .contains("enable futures"));
+        assert!(rejected("API key is invalid.").contains("does not exist"));
+        assert!(rejected("Invalid signature").contains("check the secret"));
+        // failures other than the key's leave it unchecked
+        let unchecked = check_api_key("alice", Err("connection reset".into()), 0).unwrap();
+        assert_eq!((unchecked.can_trade, unchecked.warnings.len()), (None, 1));
+
+        let now_ms = 100 * MS_PER_DAY;
+        let check = |info: ApiKeyInfo| check_api_key("alice", Ok(Some(info)), now_ms).unwrap();
+        let restricted = ApiKeyInfo {
+            can_trade: true,
+            ip_restricted: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            check(restricted),
+            KeyCheck {
+                can_trade: Some(true),
+                warnings: Vec::new(),
+            }
+        );
+        let risky = check(ApiKeyInfo {
+            can_trade: false,
+            can_withdraw: true,
+            ip_restricted: false,
+            expires_at_ms: Some(now_ms + 2 * MS_PER_DAY),
+        });
+        assert_eq!(risky.can_trade, Some(false));
+        assert_eq!(risky.warnings.len(), 3);
+        assert!(risky.warnings[2].contains("2.0 days"));
+    }
+}
@@ -0,0 +1,397 @@
+//! Market metadata cached on disk: `load_markets` and `fetch_exchange_params` are slow and
+//! count against rate limits, while their results rarely change.
+
+use async_trait::async_trait;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use crate::types::{
+    Market, Ticker, Order, PositionPair, PositionCosts, PositionPnl, OrderBook, ExchangeParams,
+    TradeFill, FundingPayment, CancelRequest, CollateralAsset, MarginMode, ApiKeyInfo,
+};
+use super::{Exchange, ExchangeError};
+use crate::data::Candle;
+use tracing::{debug, warn};
+
+/// Shared with the market snapshots of `universe`.
+const CACHE_DIR: &str = "historical_data/markets";
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Cached<T> {
+    pub fetched_at_ms: i64,
+    pub value: T,
+}
+
+/// The cached markets and per-symbol exchange params of one exchange.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct MarketInfo {
+    #[serde(default)]
+    pub markets: Option<Cached<HashMap<String, Market>>>,
+    #[serde(default)]
+    pub exchange_params: HashMap<String, Cached<ExchangeParams>>,
+}
+
+impl MarketInfo {
+    pub fn path(exchange_name: &str) -> PathBuf {
+        PathBuf::from(CACHE_DIR).join(format!("{}_info.json", exchange_name))
+    }
+
+    /// The cache at `path`, or an empty one if there is none or it cannot be read.
+    pub fn load(path: &Path) -> Self {
+        let Ok(content) = fs::read_to_string(path) else {
+            return Self::default();
+        };
+        serde_json::from_str(&content).unwrap_or_else(|e| {
+            warn!("Ignoring unreadable market cache {}: {}", path.display(), e);
+            Self::default()
+        })
+    }
+
+    /// Writes the cache, keeping entries other processes saved more recently.
+    pub fn save(&self, path: &Path) -> Result<(), ExchangeError> {
+        let mut merged = Self::load(path);
+        merged.merge(self.clone());
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            fs::create_dir_all(dir)?;
+        }
+        let tmp_path = path.with_extension("json.tmp");
+        fs::write(&tmp_path, serde_json::to_string_pretty(&merged)?)?;
+        fs::rename(&tmp_path, path)?;
+        Ok(())
+    }
+
+    fn merge(&mut self, other: MarketInfo) {
+        if let Some(markets) = other.markets {
+            if self
+                .markets
+                .as_ref()
+                .is_none_or(|own| own.fetched_at_ms < markets.fetched_at_ms)
+            {
+                self.markets = Some(markets);
+            }
+        }
+        for (symbol, params) in other.exchange_params {
+            match self.exchange_params.get(&symbol) {
+                Some(own) if own.fetched_at_ms >= params.fetched_at_ms => {}
+                _ => {
+                    self.exchange_params.insert(symbol, params);
+                }
+            }
+        }
+    }
+}
+
+/// Deletes the market caches of all exchanges, for `--refresh-markets`.
+pub fn clear() -> Result<(), ExchangeError> {
+    let Ok(entries) = fs::read_dir(CACHE_DIR) else {
+        return Ok(());
+    };
+    for entry in entries {
+        let path = entry?.path();
+        if path.to_string_lossy().ends_with("_info.json") {
+            fs::remove_file(&path)?;
+        }
+    }
+    Ok(())
+}
+
+/// Wraps an exchange and answers `load_markets` and `fetch_exchange_params` from the disk
+/// cache while its entries are younger than `ttl_ms`. Everything else goes to the exchange.
+/// An order rejected for its precision drops the symbol's cached params, so the retry
+/// re-quantizes with fresh ones.
+#[derive(Clone)]
+pub struct CachedMarketsExchange {
+    inner: Box<dyn Exchange>,
+    path: PathBuf,
+    ttl_ms: i64,
+    cache: Arc<Mutex<MarketInfo>>,
+}
+
+impl CachedMarketsExchange {
+    pub fn new(inner: Box<dyn Exchange>, path: PathBuf, ttl_hours: f64) -> Self {
+        Self {
+            cache: Arc::new(Mutex::new(MarketInfo::load(&path))),
+            inner,
+            path,
+            ttl_ms: (ttl_hours * 3_600_000.0) as i64,
+        }
+    }
+
+    fn is_fresh(&self, fetched_at_ms: i64) -> bool {
+        Utc::now().timestamp_millis() - fetched_at_ms < self.ttl_ms
+    }
+
+    fn save(&self) {
+        let cache = self.cache.lock().unwrap().clone();
+        if let Err(e) = cache.save(&self.path) {
+            warn!("Failed to save market cache {}: {}", self.path.display(), e);
+        }
+    }
+
+    fn invalidate_if_precision_error(&self, symbol: &str, error: &ExchangeError) {
+        if self.inner.is_precision_error(&error.to_string()) {
+            self.cache.lock().unwrap().exchange_params.remove(symbol);
+        }
+    }
+}
+
+#[async_trait]
+impl Exchange for CachedMarketsExchange {
+    fn clone_box(&self) -> Box<dyn Exchange> {
+        Box::new(self.clone())
+    }
+
+    fn quote(&self) -> &str {
+        self.inner.quote()
+    }
+
+    async fn load_markets(&self) -> Result<HashMap<String, Market>, ExchangeError> {
+        let cached = self.cache.lock().unwrap().markets.clone();
+        if let Some(cached) = cached.filter(|c| self.is_fresh(c.fetched_at_ms)) {
+            debug!("Using {} cached markets", cached.value.len());
+            return Ok(cached.value);
+        }
+        let markets = self.inner.load_markets().await?;
+        self.cache.lock().unwrap().markets = Some(Cached {
+            fetched_at_ms: Utc::now().timestamp_millis(),
+            value: markets.clone(),
+        });
+        self.save();
+        Ok(markets)
+    }
+
+    async fn fetch_tickers(
+        &self, symbols: &[String],
+    ) -> Result<HashMap<String, Ticker>, ExchangeError> {
+        self.inner.fetch_tickers(symbols).await
+    }
+
+    async fn fetch_ticker(&self, symbol: &str) -> Result<f64, ExchangeError> {
+        self.inner.fetch_ticker(symbol).await
+    }
+
+    async fn fetch_order_book(&self, symbol: &str) -> Result<OrderBook, ExchangeError> {
+        self.inner.fetch_order_book(symbol).await
+    }
+
+    async fn fetch_balance(&self) -> Result<f64, ExchangeError> {
+        self.inner.fetch_balance().await
+    }
+
+    async fn fetch_collateral(&self) -> Result<Vec<CollateralAsset>, ExchangeError> {
+        self.inner.fetch_collateral().await
+    }
+
+    async fn place_order(&mut self, order: &Order) -> Result<(), ExchangeError> {
+        let result = self.inner.place_order(order).await;
+        if let Err(e) = &result {
+            self.invalidate_if_precision_error(&order.symbol, e);
+        }
+        result
+    }
+
+    async fn cancel_order(&mut self, symbol: &str, order_id: &str) -> Result<(), ExchangeError> {
+        self.inner.cancel_order(symbol, order_id).await
+    }
+
+    async fn place_orders(&mut self, orders: &[Order]) -> Vec<Result<(), ExchangeError>> {
+        let results = self.inner.place_orders(orders).await;
+        for (order, result) in orders.iter().zip(&results) {
+            if let Err(e) = result {
+                self.invalidate_if_precision_error(&order.symbol, e);
+            }
+        }
+        results
+    }
+
+    async fn cancel_orders(&mut self, cancels: &[CancelRequest]) -> Vec<Result<(), ExchangeError>> {
+        self.inner.cancel_orders(cancels).await
+    }
+
+    async fn fetch_open_orders(&self, symbol: &str) -> Result<Vec<Order>, ExchangeError> {
+        self.inner.fetch_open_orders(symbol).await
+    }
+
+    async fn fetch_positions(&self, symbol: &str) -> Result<PositionPair, ExchangeError> {
+        self.inner.fetch_positions(symbol).await
+    }
+
+    async fn fetch_position_costs(&self, symbol: &str) -> Result<PositionCosts, ExchangeError> {
+        self.inner.fetch_position_costs(symbol).await
+    }
+
+    async fn fetch_position_pnl(&self, symbol: &str) -> Result<Option<PositionPnl>, ExchangeError> {
+        self.inner.fetch_position_pnl(symbol).await
+    }
+
+    async fn fetch_exchange_params(&self, symbol: &str) -> Result<ExchangeParams, ExchangeError> {
+        let cached = self
+            .cache
+            .lock()
+            .unwrap()
+            .exchange_params
+            .get(symbol)
+            .cloned();
+        if let Some(cached) = cached.filter(|c| self.is_fresh(c.fetched_at_ms)) {
+            return Ok(cached.value);
+        }
+        let exchange_params = self.inner.fetch_exchange_params(symbol).await?;
+        self.cache.lock().unwrap().exchange_params.insert(
+            symbol.to_string(),
+            Cached {
+                fetched_at_ms: Utc::now().timestamp_millis(),
+                value: exchange_params.clone(),
+            },
+        );
+        self.save();
+        Ok(exchange_params)
+    }
+
+    async fn fetch_fill_history(
+        &self, symbol: &str, start_ms: i64, end_ms: i64,
+    ) -> Result<Vec<TradeFill>, ExchangeError> {
+        self.inner
+            .fetch_fill_history(symbol, start_ms, end_ms)
+            .await
+    }
+
+    async fn fetch_my_trades(
+        &self, symbol: &str, since_ms: i64,
+    ) -> Result<Vec<TradeFill>, ExchangeError> {
+        self.inner.fetch_my_trades(symbol, since_ms).await
+    }
+
+    async fn fetch_funding_payments(
+        &self, symbol: &str, start_ms: i64, end_ms: i64,
+    ) -> Result<Vec<FundingPayment>, ExchangeError> {
+        self.inner
+            .fetch_funding_payments(symbol, start_ms, end_ms)
+            .await
+    }
+
+    async fn fetch_last_close(&self, symbol: &str) -> Result<Option<f64>, ExchangeError> {
+        self.inner.fetch_last_close(symbol).await
+    }
+
+    async fn fetch_ohlcv(
+        &self, symbol: &str, timeframe: &str, since_ms: Option<i64>, limit: usize,
+    ) -> Result<Vec<Candle>, ExchangeError> {
+        self.inner
+            .fetch_ohlcv(symbol, timeframe, since_ms, limit)
+            .await
+    }
+
+    async fn fetch_api_key_info(&self) -> Result<Option<ApiKeyInfo>, ExchangeError> {
+        self.inner.fetch_api_key_info().await
+    }
+
+    fn now_ms(&self) -> i64 {
+        self.inner.now_ms()
+    }
+
+    fn is_precision_error(&self, error: &str) -> bool {
+        self.inner.is_precision_error(error)
+    }
+
+    fn has_fill_history(&self) -> bool {
+        self.inner.has_fill_history()
+    }
+
+    fn is_risk_limit_error(&self, error: &str) -> bool {
+        self.inner.is_risk_limit_error(error)
+    }
+
+    async fn fetch_risk_limit(&self, symbol: &str) -> Result<Option<f64>, ExchangeError> {
+        self.inner.fetch_risk_limit(symbol).await
+    }
+
+    async fn raise_risk_limit(&mut self, symbol: &str) -> Result<Option<f64>, ExchangeError> {
+        self.inner.raise_risk_limit(symbol).await
+    }
+
+    async fn set_leverage(&mut self, symbol: &str, leverage: f64) -> Result<(), ExchangeError> {
+        self.inner.set_leverage(symbol, leverage).await
+    }
+
+    async fn set_margin_mode(
+        &mut self, symbol: &str, mode: MarginMode,
+    ) -> Result<(), ExchangeError> {
+        self.inner.set_margin_mode(symbol, mode).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::exchange::simulated::SimulatedExchange;
+
+    #[tokio::test]
+    async fn test_params_cached_until_precision_error() {
+        let path = std::env::temp_dir().join(format!("market_cache_{}.json", std::process::id()));
+        let simulated = SimulatedExchange::new(1000.0);
+        simulated.update_price(100.0, 100.0, 100.0);
+        let mut exchange = CachedMarketsExchange::new(simulated.clone_box(), path.clone(), 24.0);
+        assert_eq!(
+            exchange
+                .fetch_exchange_params("BTCUSDT")
+                .await
+                .unwrap()
+                .price_step,
+            0.01
+        );
+
+        // the exchange changes its tick size, but the cache, also on disk, still answers
+        simulated.set_exchange_params(ExchangeParams {
+            price_step: 0.1,
+            ..ExchangeParams::default()
+        });
+        assert_eq!(
+            exchange
+                .fetch_exchange_params("BTCUSDT")
+                .await
+                .unwrap()
+                .price_step,
+            0.01
+        );
+        let reopened = CachedMarketsExchange::new(simulated.clone_box(), path.clone(), 24.0);
+        assert_eq!(
+            reopened
+                .fetch_exchange_params("BTCUSDT")
+                .await
+                .unwrap()
+                .price_step,
+            0.01
+        );
+        // expired entries are refetched
+        let expired = CachedMarketsExchange::new(simulated.clone_box(), path.clone(), 0.0);
+        assert_eq!(
+            expired
+                .fetch_exchange_params("ETHUSDT")
+                .await
+                .unwrap()
+                .price_step,
+            0.1
+        );
+
+        let order = Order {
+            symbol: "BTCUSDT".to_string(),
+            side: "Buy".to_string(),
+            qty: 1.0,
+            price: 99.05,
+            ..Default::default()
+        };
+        assert!(exchange.place_order(&order).await.is_err());
+        assert_eq!(
+            exchange
+                .fetch_exchange_params("BTCUSDT")
+                .await
+                .unwrap()
+                .price_step,
+            0.1
+        );
+        fs::remove_file(&path).unwrap();
+    }
+}
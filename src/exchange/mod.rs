@@ -1,31 +1,201 @@
 pub mod binance;
 pub mod bitget;
 pub mod bybit;
+pub mod error;
+pub mod faulty;
+pub mod fill_detection;
 pub mod gateio;
 pub mod hyperliquid;
+pub mod intent_log;
+pub mod key_check;
+pub mod market_cache;
 pub mod okx;
+pub mod paper;
+pub mod pnl_check;
+pub mod precision;
+pub mod price_check;
+pub mod quarantine;
+pub mod queue;
+pub mod rate_limit;
+pub mod retry;
+pub mod risk_limit;
 pub mod simulated;
+pub mod ws;
 
 use async_trait::async_trait;
-use crate::types::{Market, Ticker, Order, Position, OrderBook, ExchangeParams};
+use crate::types::{
+    Market, Ticker, Order, PositionPair, PositionCosts, OrderBook, ExchangeParams, TradeFill,
+    FundingPayment, CancelRequest, CollateralAsset, PositionPnl, MarginMode, ApiKeyInfo,
+};
+use crate::data::{parse_timeframe_ms, Candle};
+use chrono::Utc;
 use std::collections::HashMap;
 
 pub type SendSyncError = Box<dyn std::error::Error + Send + Sync>;
+pub use error::ExchangeError;
 
 #[async_trait]
 pub trait Exchange: Send + Sync {
     fn clone_box(&self) -> Box<dyn Exchange>;
-    async fn load_markets(&self) -> Result<HashMap<String, Market>, SendSyncError>;
+    /// Quote/settlement currency that balances, `min_cost` and symbols are expressed in.
+    fn quote(&self) -> &str {
+        "USDT"
+    }
+    async fn load_markets(&self) -> Result<HashMap<String, Market>, ExchangeError>;
     async fn fetch_tickers(
         &self, symbols: &[String],
-    ) -> Result<HashMap<String, Ticker>, SendSyncError>;
-    async fn fetch_ticker(&self, symbol: &str) -> Result<f64, SendSyncError>;
-    async fn fetch_order_book(&self, symbol: &str) -> Result<OrderBook, SendSyncError>;
-    async fn fetch_balance(&self) -> Result<f64, SendSyncError>;
-    async fn place_order(&mut self, order: &Order) -> Result<(), SendSyncError>;
-    async fn cancel_order(&mut self, order_id: &str) -> Result<(), SendSyncError>;
-    async fn fetch_position(&self, symbol: &str) -> Result<Position, SendSyncError>;
-    async fn fetch_exchange_params(&self, symbol: &str) -> Result<ExchangeParams, SendSyncError>;
+    ) -> Result<HashMap<String, Ticker>, ExchangeError>;
+    async fn fetch_ticker(&self, symbol: &str) -> Result<f64, ExchangeError>;
+    async fn fetch_order_book(&self, symbol: &str) -> Result<OrderBook, ExchangeError>;
+    async fn fetch_balance(&self) -> Result<f64, ExchangeError>;
+    async fn place_order(&mut self, order: &Order) -> Result<(), ExchangeError>;
+    async fn cancel_order(&mut self, symbol: &str, order_id: &str) -> Result<(), ExchangeError>;
+    /// Places `orders` and returns the outcome of each, in the same order. Exchanges
+    /// without a batch endpoint place them one at a time.
+    async fn place_orders(&mut self, orders: &[Order]) -> Vec<Result<(), ExchangeError>> {
+        let mut results = Vec::with_capacity(orders.len());
+        for order in orders {
+            results.push(self.place_order(order).await);
+        }
+        results
+    }
+    /// Cancels `cancels` and returns the outcome of each, in the same order. Exchanges
+    /// without a batch endpoint cancel them one at a time.
+    async fn cancel_orders(&mut self, cancels: &[CancelRequest]) -> Vec<Result<(), ExchangeError>> {
+        let mut results = Vec::with_capacity(cancels.len());
+        for cancel in cancels {
+            results.push(self.cancel_order(&cancel.symbol, &cancel.order_id).await);
+        }
+        results
+    }
+    /// The account's resting orders for `symbol`, with their exchange and client order ids.
+    async fn fetch_open_orders(&self, _symbol: &str) -> Result<Vec<Order>, ExchangeError> {
+        Err("Fetching open orders is not supported for this exchange".into())
+    }
+    /// The long and short position of `symbol`: both legs on an account in hedge mode,
+    /// the single position on the leg of its sign in one-way mode.
+    async fn fetch_positions(&self, symbol: &str) -> Result<PositionPair, ExchangeError>;
+    async fn fetch_position_costs(&self, _symbol: &str) -> Result<PositionCosts, ExchangeError> {
+        Ok(PositionCosts::default())
+    }
+    async fn fetch_exchange_params(&self, symbol: &str) -> Result<ExchangeParams, ExchangeError>;
+    /// The account's fills for `symbol` between `start_ms` and `end_ms`, oldest first.
+    async fn fetch_fill_history(
+        &self, _symbol: &str, _start_ms: i64, _end_ms: i64,
+    ) -> Result<Vec<TradeFill>, ExchangeError> {
+        Err("Trade history is not supported for this exchange".into())
+    }
+    /// Whether `fetch_fill_history` is implemented, which REST fill detection relies on.
+    fn has_fill_history(&self) -> bool {
+        false
+    }
+    /// The account's fills for `symbol` since `since_ms`, oldest first. Used to confirm
+    /// fills detected by polling open orders.
+    async fn fetch_my_trades(
+        &self, symbol: &str, since_ms: i64,
+    ) -> Result<Vec<TradeFill>, ExchangeError> {
+        self.fetch_fill_history(symbol, since_ms, self.now_ms())
+            .await
+    }
+    /// Funding settlements of `symbol` between `start_ms` and `end_ms`, oldest first.
+    async fn fetch_funding_payments(
+        &self, _symbol: &str, _start_ms: i64, _end_ms: i64,
+    ) -> Result<Vec<FundingPayment>, ExchangeError> {
+        Err("Funding history is not supported for this exchange".into())
+    }
+    /// The open position's unrealized PnL as the exchange computes it, or `None` if the
+    /// exchange does not report it or there is no position.
+    async fn fetch_position_pnl(
+        &self, _symbol: &str,
+    ) -> Result<Option<PositionPnl>, ExchangeError> {
+        Ok(None)
+    }
+    /// Close of the latest 1m candle of `symbol`, or `None` if the exchange does not
+    /// provide candles.
+    async fn fetch_last_close(&self, _symbol: &str) -> Result<Option<f64>, ExchangeError> {
+        Ok(None)
+    }
+    /// Up to `limit` candles of `symbol` at `timeframe`, e.g. `1m` or `1h`, oldest first:
+    /// those opening at or after `since_ms`, or the latest ones without it. The last one
+    /// may still be open.
+    async fn fetch_ohlcv(
+        &self, _symbol: &str, _timeframe: &str, _since_ms: Option<i64>, _limit: usize,
+    ) -> Result<Vec<Candle>, ExchangeError> {
+        Err("Candles are not supported for this exchange".into())
+    }
+    /// The API key's permissions, or `None` if the exchange does not report them. Signed
+    /// like any private request, so it also fails on a wrong key or IP.
+    async fn fetch_api_key_info(&self) -> Result<Option<ApiKeyInfo>, ExchangeError> {
+        Ok(None)
+    }
+    /// Current time in ms as seen by the bot. Simulated exchanges run on their own clock.
+    fn now_ms(&self) -> i64 {
+        Utc::now().timestamp_millis()
+    }
+    /// Whether an order error means the order's price or qty does not match the symbol's
+    /// steps, or the order is below the minimum qty or notional.
+    fn is_precision_error(&self, _error: &str) -> bool {
+        false
+    }
+    /// The account's margin collateral by asset, for `live.cross_collateral`.
+    async fn fetch_collateral(&self) -> Result<Vec<CollateralAsset>, ExchangeError> {
+        Err("Collateral breakdown is not supported for this exchange".into())
+    }
+    /// Whether an order error means the order would exceed the position's risk limit.
+    fn is_risk_limit_error(&self, _error: &str) -> bool {
+        false
+    }
+    /// Maximum position value, in quote currency, allowed by the current risk limit tier,
+    /// or `None` if the exchange does not report it.
+    async fn fetch_risk_limit(&self, _symbol: &str) -> Result<Option<f64>, ExchangeError> {
+        Ok(None)
+    }
+    /// Moves the position to the next risk limit tier where the API allows it, returning
+    /// the new maximum position value, or `None` if it cannot be raised.
+    async fn raise_risk_limit(&mut self, _symbol: &str) -> Result<Option<f64>, ExchangeError> {
+        Ok(None)
+    }
+    /// Sets the leverage of `symbol`, succeeding if it is set already. Exchanges the bot
+    /// does not configure, such as simulated ones, leave it as it is.
+    async fn set_leverage(&mut self, _symbol: &str, _leverage: f64) -> Result<(), ExchangeError> {
+        Ok(())
+    }
+    /// Sets how `symbol` is margined, succeeding if it is set already. Called before
+    /// `set_leverage`, which some exchanges set per margin mode.
+    async fn set_margin_mode(
+        &mut self, _symbol: &str, _mode: MarginMode,
+    ) -> Result<(), ExchangeError> {
+        Ok(())
+    }
+}
+
+/// Length of `timeframe` in ms.
+pub(crate) fn timeframe_ms(timeframe: &str) -> Result<i64, ExchangeError> {
+    parse_timeframe_ms(timeframe)
+        .map(|ms| ms as i64)
+        .ok_or_else(|| format!("Invalid timeframe {}", timeframe).into())
+}
+
+/// Parses a kline row of [open time, open, high, low, close, volume, ...], with the
+/// fields given as numbers or as strings.
+pub(crate) fn parse_candle_row(row: &[serde_json::Value]) -> Result<Candle, ExchangeError> {
+    let field = |i: usize| -> Result<f64, ExchangeError> {
+        match row.get(i) {
+            Some(serde_json::Value::String(s)) => Ok(s.parse()?),
+            Some(value) => value
+                .as_f64()
+                .ok_or_else(|| format!("Invalid kline field {}", value).into()),
+            None => Err(format!("Kline row has {} fields", row.len()).into()),
+        }
+    };
+    Ok(Candle {
+        timestamp: field(0)? as u64,
+        open: field(1)?,
+        high: field(2)?,
+        low: field(3)?,
+        close: field(4)?,
+        volume: field(5)?,
+    })
 }
 
 impl Clone for Box<dyn Exchange> {
@@ -1,5 +1,8 @@
 use crate::config::UserConfig;
-use crate::types::{LiveConfig, Market, Ticker, Order, Position, OrderBook, ExchangeParams};
+use crate::types::{
+    LiveConfig, Market, Ticker, Order, Position, PositionPair, OrderBook, ExchangeParams,
+    MarginMode, ApiKeyInfo,
+};
 use async_trait::async_trait;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
@@ -8,7 +11,9 @@ use sha2::Sha256;
 use base64;
 use chrono::Utc;
 
-use super::{Exchange, SendSyncError};
+use super::rate_limit::{RateLimiter, SendRateLimited};
+use super::{parse_candle_row, timeframe_ms, Exchange, ExchangeError};
+use crate::data::Candle;
 
 #[derive(Deserialize, Debug)]
 struct OkxMarket {
@@ -19,6 +24,8 @@ struct OkxMarket {
     ct_type: String,
     #[serde(rename = "listTime")]
     list_time: String,
+    #[serde(rename = "settleCcy")]
+    settle_ccy: String,
     #[serde(rename = "tickSz")]
     tick_sz: String,
     #[serde(rename = "lotSz")]
@@ -63,6 +70,34 @@ struct OkxTickerResponse {
     data: Vec<OkxTicker>,
 }
 
+#[derive(Deserialize, Debug)]
+struct OkxCandlesResponse {
+    code: String,
+    #[serde(default)]
+    msg: String,
+    /// [ts, open, high, low, close, volume, ...], newest first
+    data: Vec<Vec<serde_json::Value>>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct OkxPosition {
+    /// "long" or "short" in long/short mode, "net" in net mode.
+    pos_side: String,
+    /// Signed in net mode, unsigned per side otherwise.
+    pos: String,
+    #[serde(default)]
+    avg_px: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct OkxPositionsResponse {
+    code: String,
+    #[serde(default)]
+    msg: String,
+    data: Vec<OkxPosition>,
+}
+
 #[derive(Serialize, Debug)]
 struct OkxOrderRequest<'a> {
     #[serde(rename = "instId")]
@@ -76,6 +111,53 @@ struct OkxOrderRequest<'a> {
     ord_type: &'a str,
     sz: String,
     px: String,
+    #[serde(rename = "clOrdId", skip_serializing_if = "str::is_empty")]
+    cl_ord_id: &'a str,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct OkxSetLeverageRequest<'a> {
+    inst_id: &'a str,
+    lever: String,
+    mgn_mode: &'a str,
+}
+
+/// The account's configuration, as seen through the API key making the request.
+#[derive(Deserialize, Debug)]
+struct OkxAccountConfig {
+    /// Comma-separated permissions of the key: "read_only", "trade", "withdraw".
+    #[serde(default)]
+    perm: String,
+    /// Comma-separated whitelisted IP addresses, empty for keys usable from anywhere.
+    #[serde(default)]
+    ip: String,
+    /// "net_mode" for one-way positions, "long_short_mode" for hedged ones.
+    #[serde(default, rename = "posMode")]
+    pos_mode: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct OkxAccountConfigResponse {
+    code: String,
+    #[serde(default)]
+    msg: String,
+    data: Vec<OkxAccountConfig>,
+}
+
+#[derive(Deserialize, Debug)]
+struct OkxSettingResponse {
+    code: String,
+    #[serde(default)]
+    msg: String,
+}
+
+#[derive(Serialize, Debug)]
+struct OkxCancelOrderRequest<'a> {
+    #[serde(rename = "instId")]
+    inst_id: &'a str,
+    #[serde(rename = "ordId")]
+    ord_id: &'a str,
 }
 
 #[derive(Deserialize, Debug)]
@@ -91,31 +173,68 @@ struct OkxOrderResponse {
     data: Vec<OkxOrderResponseData>,
 }
 
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct OkxOpenOrder {
+    inst_id: String,
+    ord_id: String,
+    #[serde(default)]
+    cl_ord_id: String,
+    side: String,
+    pos_side: String,
+    sz: String,
+    #[serde(default)]
+    acc_fill_sz: String,
+    px: String,
+    ord_type: String,
+    #[serde(default)]
+    reduce_only: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct OkxOpenOrdersResponse {
+    code: String,
+    #[serde(default)]
+    msg: String,
+    data: Vec<OkxOpenOrder>,
+}
+
 pub struct Okx {
     pub client: reqwest::Client,
+    rate_limiter: RateLimiter,
     user_config: UserConfig,
+    /// Margin mode of orders and leverage, "cross" or "isolated": OKX takes it per
+    /// request rather than as a setting of the symbol.
+    td_mode: &'static str,
 }
 
 impl Clone for Okx {
     fn clone(&self) -> Self {
         Self {
             client: self.client.clone(),
+            rate_limiter: self.rate_limiter.clone(),
             user_config: self.user_config.clone(),
+            td_mode: self.td_mode,
         }
     }
 }
 
 impl Okx {
-    pub fn new(_live_config: &LiveConfig, user_config: &UserConfig) -> Self {
+    pub fn new(live_config: &LiveConfig, user_config: &UserConfig) -> Self {
         Self {
             client: reqwest::Client::new(),
+            rate_limiter: RateLimiter::shared("okx", &live_config.rate_limit),
             user_config: user_config.clone(),
+            td_mode: match MarginMode::from_str(&live_config.margin_mode) {
+                Some(MarginMode::Isolated) => "isolated",
+                _ => "cross",
+            },
         }
     }
 
     fn create_auth_headers(
         &self, method: &str, request_path: &str, body: &str,
-    ) -> Result<reqwest::header::HeaderMap, SendSyncError> {
+    ) -> Result<reqwest::header::HeaderMap, ExchangeError> {
         let timestamp = Utc::now().format("%Y-%m-%dT%H:%M:%S%.3fZ").to_string();
         let message = format!("{}{}{}{}", timestamp, method, request_path, body);
 
@@ -134,6 +253,52 @@ impl Okx {
 
         Ok(headers)
     }
+
+    async fn fetch_account_config(&self) -> Result<OkxAccountConfig, ExchangeError> {
+        let request_path = "/api/v5/account/config";
+        let headers = self.create_auth_headers("GET", request_path, "")?;
+        let url = format!("https://www.okx.com{}", request_path);
+        let response = self
+            .client
+            .get(&url)
+            .headers(headers)
+            .send_limited(&self.rate_limiter)
+            .await?
+            .text()
+            .await?;
+        let parsed: OkxAccountConfigResponse = serde_json::from_str(&response)?;
+        if parsed.code != "0" {
+            return Err(parsed.msg.into());
+        }
+        parsed
+            .data
+            .into_iter()
+            .next()
+            .ok_or_else(|| "No account config data".into())
+    }
+}
+
+/// The legs of a symbol's positions. OKX lists no position while flat, so `pos_mode`
+/// of the account config tells whether an empty list is of a one-way account.
+fn position_pair(data: Vec<OkxPosition>, pos_mode: &str) -> Result<PositionPair, ExchangeError> {
+    let mut positions = PositionPair {
+        one_way: data.is_empty() && pos_mode == "net_mode",
+        ..PositionPair::default()
+    };
+    for position in data {
+        let size: f64 = position.pos.parse()?;
+        let price: f64 = if position.avg_px.is_empty() {
+            0.0
+        } else {
+            position.avg_px.parse()?
+        };
+        match position.pos_side.as_str() {
+            "long" => positions.long = Position { size, price },
+            "short" => positions.short = Position { size: -size, price },
+            _ => positions = PositionPair::from_net(Position { size, price }),
+        }
+    }
+    Ok(positions)
 }
 
 #[async_trait]
@@ -142,9 +307,19 @@ impl Exchange for Okx {
         Box::new(self.clone())
     }
 
-    async fn load_markets(&self) -> Result<HashMap<String, Market>, SendSyncError> {
+    fn quote(&self) -> &str {
+        &self.user_config.quote
+    }
+
+    async fn load_markets(&self) -> Result<HashMap<String, Market>, ExchangeError> {
         let url = "https://www.okx.com/api/v5/public/instruments?instType=SWAP";
-        let response = self.client.get(url).send().await?.text().await?;
+        let response = self
+            .client
+            .get(url)
+            .send_limited(&self.rate_limiter)
+            .await?
+            .text()
+            .await?;
         let parsed: OkxMarketsResponse = serde_json::from_str(&response)?;
 
         let mut markets = HashMap::new();
@@ -158,6 +333,7 @@ impl Exchange for Okx {
                     swap: true,
                     linear: market.ct_type == "linear",
                     created_at: market.list_time.parse::<i64>()?,
+                    settle: market.settle_ccy,
                 },
             );
         }
@@ -166,27 +342,69 @@ impl Exchange for Okx {
 
     async fn fetch_tickers(
         &self, _symbols: &[String],
-    ) -> Result<HashMap<String, Ticker>, SendSyncError> {
+    ) -> Result<HashMap<String, Ticker>, ExchangeError> {
         unimplemented!()
     }
 
-    async fn fetch_ticker(&self, symbol: &str) -> Result<f64, SendSyncError> {
+    async fn fetch_ticker(&self, symbol: &str) -> Result<f64, ExchangeError> {
         let url = format!(
             "https://www.okx.com/api/v5/market/ticker?instId={}-SWAP",
             symbol
         );
-        let response = self.client.get(&url).send().await?.text().await?;
+        let response = self
+            .client
+            .get(&url)
+            .send_limited(&self.rate_limiter)
+            .await?
+            .text()
+            .await?;
         let parsed: OkxTickerResponse = serde_json::from_str(&response)?;
 
         let ticker = parsed.data.first().ok_or("Ticker not found")?;
         Ok(ticker.last.parse::<f64>()?)
     }
 
-    async fn fetch_order_book(&self, _symbol: &str) -> Result<OrderBook, SendSyncError> {
+    async fn fetch_ohlcv(
+        &self, symbol: &str, timeframe: &str, since_ms: Option<i64>, limit: usize,
+    ) -> Result<Vec<Candle>, ExchangeError> {
+        timeframe_ms(timeframe)?;
+        // hours and days are upper case, e.g. 1H and 1D
+        let bar = timeframe.replace('h', "H").replace('d', "D");
+        let mut url = format!(
+            "https://www.okx.com/api/v5/market/candles?instId={}-SWAP&bar={}&limit={}",
+            symbol,
+            bar,
+            limit.min(300)
+        );
+        if let Some(since_ms) = since_ms {
+            // records newer than `before`
+            url.push_str(&format!("&before={}", since_ms - 1));
+        }
+        let response = self
+            .client
+            .get(&url)
+            .send_limited(&self.rate_limiter)
+            .await?
+            .text()
+            .await?;
+        let parsed: OkxCandlesResponse = serde_json::from_str(&response)?;
+        if parsed.code != "0" {
+            return Err(ExchangeError::classify(parsed.msg));
+        }
+        // newest first
+        parsed
+            .data
+            .iter()
+            .rev()
+            .map(|row| parse_candle_row(row))
+            .collect()
+    }
+
+    async fn fetch_order_book(&self, _symbol: &str) -> Result<OrderBook, ExchangeError> {
         unimplemented!()
     }
 
-    async fn fetch_balance(&self) -> Result<f64, SendSyncError> {
+    async fn fetch_balance(&self) -> Result<f64, ExchangeError> {
         let request_path = "/api/v5/account/balance";
         let headers = self.create_auth_headers("GET", request_path, "")?;
         let url = format!("https://www.okx.com{}", request_path);
@@ -195,7 +413,7 @@ impl Exchange for Okx {
             .client
             .get(&url)
             .headers(headers)
-            .send()
+            .send_limited(&self.rate_limiter)
             .await?
             .text()
             .await?;
@@ -205,24 +423,25 @@ impl Exchange for Okx {
         let usdt_balance = balance_data
             .details
             .iter()
-            .find(|d| d.ccy == "USDT")
-            .ok_or("USDT balance not found")?;
+            .find(|d| d.ccy == self.user_config.quote)
+            .ok_or(format!("{} balance not found", self.user_config.quote))?;
 
         Ok(usdt_balance.cash_bal.parse::<f64>()?)
     }
 
-    async fn place_order(&mut self, order: &Order) -> Result<(), SendSyncError> {
+    async fn place_order(&mut self, order: &Order) -> Result<(), ExchangeError> {
         let request_path = "/api/v5/trade/order";
         let inst_id = format!("{}-SWAP", order.symbol);
 
         let order_req = OkxOrderRequest {
             inst_id: &inst_id,
-            td_mode: "cross",
+            td_mode: self.td_mode,
             side: &order.side,
             pos_side: &order.position_side,
             ord_type: &order.time_in_force,
             sz: order.qty.to_string(),
             px: order.price.to_string(),
+            cl_ord_id: &order.client_order_id,
         };
 
         let body = serde_json::to_string(&order_req)?;
@@ -234,7 +453,7 @@ impl Exchange for Okx {
             .post(&url)
             .headers(headers)
             .body(body)
-            .send()
+            .send_limited(&self.rate_limiter)
             .await?
             .text()
             .await?;
@@ -252,20 +471,132 @@ impl Exchange for Okx {
         Ok(())
     }
 
-    async fn cancel_order(&mut self, _order_id: &str) -> Result<(), SendSyncError> {
-        unimplemented!()
+    async fn cancel_order(&mut self, symbol: &str, order_id: &str) -> Result<(), ExchangeError> {
+        let request_path = "/api/v5/trade/cancel-order";
+        let inst_id = format!("{}-SWAP", symbol);
+        let body = serde_json::to_string(&OkxCancelOrderRequest {
+            inst_id: &inst_id,
+            ord_id: order_id,
+        })?;
+        let headers = self.create_auth_headers("POST", request_path, &body)?;
+        let url = format!("https://www.okx.com{}", request_path);
+
+        let response = self
+            .client
+            .post(&url)
+            .headers(headers)
+            .body(body)
+            .send_limited(&self.rate_limiter)
+            .await?
+            .text()
+            .await?;
+        let parsed: OkxOrderResponse = serde_json::from_str(&response)?;
+
+        let cancel_response = parsed.data.first().ok_or("No cancel response data")?;
+        if cancel_response.s_code != "0" {
+            return Err(format!(
+                "Order cancellation failed with code {}: {}",
+                cancel_response.s_code, response
+            )
+            .into());
+        }
+
+        Ok(())
     }
 
-    async fn fetch_position(&self, _symbol: &str) -> Result<Position, SendSyncError> {
-        unimplemented!()
+    async fn fetch_open_orders(&self, symbol: &str) -> Result<Vec<Order>, ExchangeError> {
+        let request_path = format!(
+            "/api/v5/trade/orders-pending?instType=SWAP&instId={}-SWAP",
+            symbol
+        );
+        let headers = self.create_auth_headers("GET", &request_path, "")?;
+        let url = format!("https://www.okx.com{}", request_path);
+        let response = self
+            .client
+            .get(&url)
+            .headers(headers)
+            .send_limited(&self.rate_limiter)
+            .await?
+            .text()
+            .await?;
+        let parsed: OkxOpenOrdersResponse = serde_json::from_str(&response)?;
+        if parsed.code != "0" {
+            return Err(format!("Failed to fetch open orders: {}", parsed.msg).into());
+        }
+
+        let mut orders = Vec::new();
+        for open_order in parsed.data {
+            let filled = if open_order.acc_fill_sz.is_empty() {
+                0.0
+            } else {
+                open_order.acc_fill_sz.parse::<f64>()?
+            };
+            let position_side = match open_order.pos_side.as_str() {
+                "long" => "Long",
+                "short" => "Short",
+                _ => "",
+            };
+            orders.push(Order {
+                id: open_order.ord_id,
+                symbol: open_order.inst_id.replace("-SWAP", ""),
+                side: if open_order.side == "buy" {
+                    "Buy"
+                } else {
+                    "Sell"
+                }
+                .to_string(),
+                position_side: position_side.to_string(),
+                qty: open_order.sz.parse::<f64>()? - filled,
+                price: open_order.px.parse::<f64>()?,
+                reduce_only: open_order.reduce_only == "true",
+                custom_id: String::new(),
+                client_order_id: open_order.cl_ord_id,
+                time_in_force: open_order.ord_type,
+            });
+        }
+        Ok(orders)
+    }
+
+    async fn fetch_positions(&self, symbol: &str) -> Result<PositionPair, ExchangeError> {
+        let request_path = format!(
+            "/api/v5/account/positions?instType=SWAP&instId={}-SWAP",
+            symbol
+        );
+        let headers = self.create_auth_headers("GET", &request_path, "")?;
+        let url = format!("https://www.okx.com{}", request_path);
+        let response = self
+            .client
+            .get(&url)
+            .headers(headers)
+            .send_limited(&self.rate_limiter)
+            .await?
+            .text()
+            .await?;
+        let parsed: OkxPositionsResponse = serde_json::from_str(&response)?;
+        if parsed.code != "0" {
+            return Err(format!("Failed to fetch positions: {}", parsed.msg).into());
+        }
+        // a flat account lists nothing to tell its position mode by
+        let pos_mode = if parsed.data.is_empty() {
+            self.fetch_account_config().await?.pos_mode
+        } else {
+            String::new()
+        };
+        position_pair(parsed.data, &pos_mode)
     }
 
-    async fn fetch_exchange_params(&self, symbol: &str) -> Result<ExchangeParams, SendSyncError> {
+    async fn fetch_exchange_params(&self, symbol: &str) -> Result<ExchangeParams, ExchangeError> {
         let url = format!(
             "https://www.okx.com/api/v5/public/instruments?instType=SWAP&instId={}-SWAP",
             symbol
         );
-        let response = self.client.get(&url).send().await?.text().await?;
+        let response = self
+            .client
+            .get(&url)
+            .send_limited(&self.rate_limiter)
+            .await?
+            .text()
+            .await?;
         let parsed: OkxMarketsResponse = serde_json::from_str(&response)?;
 
         let market = parsed.data.first().ok_or("Market not found")?;
@@ -279,4 +610,86 @@ impl Exchange for Okx {
             inverse: market.ct_type == "inverse",
         })
     }
+
+    async fn set_leverage(&mut self, symbol: &str, leverage: f64) -> Result<(), ExchangeError> {
+        let request_path = "/api/v5/account/set-leverage";
+        let inst_id = format!("{}-SWAP", symbol);
+        let body = serde_json::to_string(&OkxSetLeverageRequest {
+            inst_id: &inst_id,
+            lever: leverage.to_string(),
+            mgn_mode: self.td_mode,
+        })?;
+        let headers = self.create_auth_headers("POST", request_path, &body)?;
+        let url = format!("https://www.okx.com{}", request_path);
+        let response = self
+            .client
+            .post(&url)
+            .headers(headers)
+            .body(body)
+            .send_limited(&self.rate_limiter)
+            .await?
+            .text()
+            .await?;
+        // setting the current leverage again succeeds
+        let parsed: OkxSettingResponse = serde_json::from_str(&response)?;
+        if parsed.code != "0" {
+            return Err(format!("Failed to set leverage: {}", parsed.msg).into());
+        }
+        Ok(())
+    }
+
+    async fn fetch_api_key_info(&self) -> Result<Option<ApiKeyInfo>, ExchangeError> {
+        let config = self.fetch_account_config().await?;
+        let permissions: Vec<&str> = config.perm.split(',').map(str::trim).collect();
+        Ok(Some(ApiKeyInfo {
+            can_trade: permissions.contains(&"trade"),
+            can_withdraw: permissions.contains(&"withdraw"),
+            ip_restricted: !config.ip.trim().is_empty(),
+            expires_at_ms: None,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(positions: &str, account_config: &str) -> PositionPair {
+        let positions: OkxPositionsResponse = serde_json::from_str(positions).unwrap();
+        let account_config: OkxAccountConfigResponse =
+            serde_json::from_str(account_config).unwrap();
+        position_pair(positions.data, &account_config.data[0].pos_mode).unwrap()
+    }
+
+    #[test]
+    fn test_position_pair_reports_the_mode_of_a_flat_account() {
+        let net_mode = r#"{"code":"0","msg":"","data":[{"uid":"44705892343619584",
+            "acctLv":"2","posMode":"net_mode","autoLoan":false,"greeksType":"PA",
+            "level":"Lv1","levelTmp":"","ctIsoMode":"automatic","mgnIsoMode":"automatic",
+            "perm":"read_only,trade","ip":""}]}"#;
+        let long_short_mode = r#"{"code":"0","msg":"","data":[{"posMode":"long_short_mode",
+            "perm":"read_only,trade","ip":""}]}"#;
+        let flat = r#"{"code":"0","msg":"","data":[]}"#;
+        assert!(parse(flat, net_mode).one_way);
+        assert!(!parse(flat, long_short_mode).one_way);
+
+        let net = parse(
+            r#"{"code":"0","msg":"","data":[{"instId":"BTC-USDT-SWAP","instType":"SWAP",
+            "mgnMode":"cross","posSide":"net","pos":"-3","avgPx":"64100.5","upl":"1.32",
+            "lever":"10","liqPx":"","markPx":"64012.1"}]}"#,
+            long_short_mode,
+        );
+        assert!(net.one_way);
+        assert_eq!((net.short.size, net.short.price), (-3.0, 64100.5));
+
+        let hedge = parse(
+            r#"{"code":"0","msg":"","data":[
+            {"instId":"BTC-USDT-SWAP","posSide":"long","pos":"2","avgPx":"63000"},
+            {"instId":"BTC-USDT-SWAP","posSide":"short","pos":"1","avgPx":"65000"}]}"#,
+            net_mode,
+        );
+        assert!(!hedge.one_way);
+        assert_eq!((hedge.long.size, hedge.long.price), (2.0, 63000.0));
+        assert_eq!((hedge.short.size, hedge.short.price), (-1.0, 65000.0));
+    }
 }
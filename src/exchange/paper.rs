@@ -0,0 +1,237 @@
+//! Paper trading: live market data from a real exchange, with orders, fills, balance and
+//! positions kept locally by a `SimulatedExchange` per symbol.
+
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use crate::types::{
+    Market, Ticker, Order, PositionPair, PositionCosts, OrderBook, ExchangeParams, TradeFill,
+    FundingPayment,
+};
+use super::simulated::SimulatedExchange;
+use super::{Exchange, ExchangeError};
+use crate::data::Candle;
+
+/// Wraps a real exchange for market data and simulates the account.
+///
+/// Orders rest on the simulated book of their symbol until an order book fetched from the
+/// real exchange reaches their price, and then fill in full at the order price. Every
+/// symbol starts from `starting_balance`; the balance reported is the starting balance
+/// plus the PnL and fees of all symbols. Positions pay no funding.
+#[derive(Clone)]
+pub struct PaperExchange {
+    inner: Box<dyn Exchange>,
+    starting_balance: f64,
+    maker_fee: f64,
+    taker_fee: f64,
+    accounts: Arc<Mutex<HashMap<String, SimulatedExchange>>>,
+}
+
+impl PaperExchange {
+    pub fn new(inner: Box<dyn Exchange>, starting_balance: f64) -> Self {
+        Self {
+            inner,
+            starting_balance,
+            maker_fee: 0.0,
+            taker_fee: 0.0,
+            accounts: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    pub fn with_fees(mut self, maker_fee: f64, taker_fee: f64) -> Self {
+        self.maker_fee = maker_fee;
+        self.taker_fee = taker_fee;
+        self
+    }
+
+    /// The simulated account of `symbol`, created on first use.
+    fn account(&self, symbol: &str) -> SimulatedExchange {
+        self.accounts
+            .lock()
+            .unwrap()
+            .entry(symbol.to_string())
+            .or_insert_with(|| {
+                let account = SimulatedExchange::new(self.starting_balance);
+                account.set_maker_fee(self.maker_fee);
+                account.set_taker_fee(self.taker_fee);
+                account
+            })
+            .clone()
+    }
+}
+
+#[async_trait]
+impl Exchange for PaperExchange {
+    fn clone_box(&self) -> Box<dyn Exchange> {
+        Box::new(self.clone())
+    }
+
+    fn quote(&self) -> &str {
+        self.inner.quote()
+    }
+
+    async fn load_markets(&self) -> Result<HashMap<String, Market>, ExchangeError> {
+        self.inner.load_markets().await
+    }
+
+    async fn fetch_tickers(
+        &self, symbols: &[String],
+    ) -> Result<HashMap<String, Ticker>, ExchangeError> {
+        self.inner.fetch_tickers(symbols).await
+    }
+
+    async fn fetch_ticker(&self, symbol: &str) -> Result<f64, ExchangeError> {
+        self.inner.fetch_ticker(symbol).await
+    }
+
+    async fn fetch_last_close(&self, symbol: &str) -> Result<Option<f64>, ExchangeError> {
+        self.inner.fetch_last_close(symbol).await
+    }
+
+    async fn fetch_ohlcv(
+        &self, symbol: &str, timeframe: &str, since_ms: Option<i64>, limit: usize,
+    ) -> Result<Vec<Candle>, ExchangeError> {
+        self.inner
+            .fetch_ohlcv(symbol, timeframe, since_ms, limit)
+            .await
+    }
+
+    async fn fetch_order_book(&self, symbol: &str) -> Result<OrderBook, ExchangeError> {
+        let order_book = self.inner.fetch_order_book(symbol).await?;
+        if let (Some(bid), Some(ask)) = (order_book.bids.first(), order_book.asks.first()) {
+            let account = self.account(symbol);
+            account.set_time_ms(self.inner.now_ms());
+            account.update_book(bid[0], ask[0]);
+        }
+        Ok(order_book)
+    }
+
+    async fn fetch_balance(&self) -> Result<f64, ExchangeError> {
+        let accounts = self.accounts.lock().unwrap();
+        let pnl: f64 = accounts
+            .values()
+            .map(|account| account.snapshot().balance - self.starting_balance)
+            .sum();
+        Ok(self.starting_balance + pnl)
+    }
+
+    async fn place_order(&mut self, order: &Order) -> Result<(), ExchangeError> {
+        self.account(&order.symbol).place_order(order).await
+    }
+
+    async fn cancel_order(&mut self, symbol: &str, order_id: &str) -> Result<(), ExchangeError> {
+        self.account(symbol).cancel_order(symbol, order_id).await
+    }
+
+    async fn fetch_open_orders(&self, symbol: &str) -> Result<Vec<Order>, ExchangeError> {
+        self.account(symbol).fetch_open_orders(symbol).await
+    }
+
+    async fn fetch_positions(&self, symbol: &str) -> Result<PositionPair, ExchangeError> {
+        self.account(symbol).fetch_positions(symbol).await
+    }
+
+    async fn fetch_position_costs(&self, symbol: &str) -> Result<PositionCosts, ExchangeError> {
+        self.account(symbol).fetch_position_costs(symbol).await
+    }
+
+    async fn fetch_exchange_params(&self, symbol: &str) -> Result<ExchangeParams, ExchangeError> {
+        let exchange_params = self.inner.fetch_exchange_params(symbol).await?;
+        self.account(symbol)
+            .set_exchange_params(exchange_params.clone());
+        Ok(exchange_params)
+    }
+
+    fn has_fill_history(&self) -> bool {
+        true
+    }
+
+    async fn fetch_fill_history(
+        &self, symbol: &str, start_ms: i64, end_ms: i64,
+    ) -> Result<Vec<TradeFill>, ExchangeError> {
+        let fills = self.account(symbol).snapshot().fills;
+        Ok(fills
+            .into_iter()
+            .enumerate()
+            .filter(|(_, fill)| fill.timestamp_ms >= start_ms && fill.timestamp_ms <= end_ms)
+            .map(|(i, fill)| TradeFill {
+                id: format!("paper-{}-{}", symbol, i),
+                order_id: fill.order_id,
+                symbol: symbol.to_string(),
+                timestamp_ms: fill.timestamp_ms,
+                side: fill.side,
+                position_side: String::new(),
+                qty: fill.qty,
+                price: fill.price,
+                fee: fill.fee,
+                maker: Some(!fill.taker),
+                order_price: fill.order_price,
+                realized_pnl: None,
+            })
+            .collect())
+    }
+
+    async fn fetch_funding_payments(
+        &self, _symbol: &str, _start_ms: i64, _end_ms: i64,
+    ) -> Result<Vec<FundingPayment>, ExchangeError> {
+        Ok(Vec::new())
+    }
+
+    fn now_ms(&self) -> i64 {
+        self.inner.now_ms()
+    }
+
+    fn is_precision_error(&self, error: &str) -> bool {
+        error.contains("not a multiple of") || error.contains("below min_")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_fills_follow_the_real_book() {
+        // stands in for the real exchange, with a zero spread book at its last price
+        let market = SimulatedExchange::new(0.0);
+        market.update_price(100.0, 100.0, 100.0);
+        let mut paper = PaperExchange::new(market.clone_box(), 1000.0).with_fees(0.001, 0.0);
+        paper.fetch_exchange_params("BTCUSDT").await.unwrap();
+        paper.fetch_order_book("BTCUSDT").await.unwrap();
+
+        let order = |side: &str, price: f64| Order {
+            symbol: "BTCUSDT".to_string(),
+            side: side.to_string(),
+            qty: 1.0,
+            price,
+            ..Default::default()
+        };
+        paper.place_order(&order("Buy", 99.0)).await.unwrap();
+        // the order rests while the market stays above it
+        paper.fetch_order_book("BTCUSDT").await.unwrap();
+        assert_eq!(paper.fetch_open_orders("BTCUSDT").await.unwrap().len(), 1);
+        assert_eq!(market.fetch_open_orders("BTCUSDT").await.unwrap().len(), 0);
+
+        market.update_price(99.0, 99.0, 99.0);
+        paper.fetch_order_book("BTCUSDT").await.unwrap();
+        assert!(paper.fetch_open_orders("BTCUSDT").await.unwrap().is_empty());
+        let position = paper.fetch_positions("BTCUSDT").await.unwrap().long;
+        assert_eq!((position.size, position.price), (1.0, 99.0));
+        let fills = paper.fetch_my_trades("BTCUSDT", 0).await.unwrap();
+        assert_eq!(fills.len(), 1);
+        assert_eq!(fills[0].order_id, "0");
+        assert!((fills[0].fee - 0.099).abs() < 1e-9);
+
+        paper.place_order(&order("Sell", 101.0)).await.unwrap();
+        market.update_price(101.0, 101.0, 101.0);
+        paper.fetch_order_book("BTCUSDT").await.unwrap();
+        assert_eq!(
+            paper.fetch_positions("BTCUSDT").await.unwrap().net().size,
+            0.0
+        );
+        // 2 of PnL less both maker fees, and an untouched account for another symbol
+        paper.fetch_positions("ETHUSDT").await.unwrap();
+        let balance = paper.fetch_balance().await.unwrap();
+        assert!((balance - (1000.0 + 2.0 - 0.099 - 0.101)).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,131 @@
+use crate::risk_governor::calc_unrealized_pnl;
+use crate::types::{ExchangeParams, Position, PositionPnl};
+
+/// Checks the unrealized PnL computed from `position` and `params` against the one the
+/// exchange reports, at the exchange's mark price. A difference above `max_divergence` of
+/// the position's value means the contract multiplier or inverse flag is wrong, or the
+/// entry price is stale; the error lists what the reported PnL implies for each.
+pub fn check_unrealized_pnl(
+    position: &Position, reported: &PositionPnl, params: &ExchangeParams, max_divergence: f64,
+) -> Result<(), String> {
+    let mark = reported.mark_price;
+    if position.size == 0.0 || !(mark.is_finite() && mark > 0.0) {
+        return Ok(());
+    }
+    let computed = calc_unrealized_pnl(position, mark, params);
+    let value = if params.inverse {
+        position.size.abs() * params.c_mult / mark
+    } else {
+        position.size.abs() * params.c_mult * mark
+    };
+    let divergence = (computed - reported.unrealized_pnl).abs() / value;
+    if divergence <= max_divergence {
+        return Ok(());
+    }
+    // the entry price and contract multiplier that would make the two agree
+    let per_contract = reported.unrealized_pnl / (position.size * params.c_mult);
+    let implied_entry = if params.inverse {
+        1.0 / (per_contract + 1.0 / mark)
+    } else {
+        mark - per_contract
+    };
+    let price_move = if params.inverse {
+        position.size * (1.0 / position.price - 1.0 / mark)
+    } else {
+        position.size * (mark - position.price)
+    };
+    let implied_c_mult = if price_move != 0.0 {
+        format!("{:.6}", reported.unrealized_pnl / price_move)
+    } else {
+        "n/a".to_string()
+    };
+    Err(format!(
+        "unrealized PnL {:.6} computed at mark {} differs from the exchange's {:.6} by {:.4}% \
+        of the position's value (size {} @ {}, c_mult {}, inverse {}); the exchange's PnL \
+        implies an entry price of {:.8} or a c_mult of {}",
+        computed,
+        mark,
+        reported.unrealized_pnl,
+        divergence * 100.0,
+        position.size,
+        position.price,
+        params.c_mult,
+        params.inverse,
+        implied_entry,
+        implied_c_mult
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_unrealized_pnl() {
+        let params = ExchangeParams {
+            c_mult: 1.0,
+            ..Default::default()
+        };
+        let position = Position {
+            size: 2.0,
+            price: 100.0,
+        };
+        let reported = |unrealized_pnl: f64| PositionPnl {
+            size: 2.0,
+            mark_price: 110.0,
+            unrealized_pnl,
+        };
+        assert!(check_unrealized_pnl(&position, &reported(20.0), &params, 0.001).is_ok());
+        assert!(check_unrealized_pnl(&position, &reported(20.1), &params, 0.001).is_ok());
+
+        // the exchange's PnL is ten times ours: a contract of 10 units
+        let err = check_unrealized_pnl(&position, &reported(200.0), &params, 0.001).unwrap_err();
+        assert!(err.contains("implies an entry price of 10.00000000 or a c_mult of 10.000000"));
+
+        // or the position was re-entered at 105 and our entry price is stale
+        let err = check_unrealized_pnl(&position, &reported(10.0), &params, 0.001).unwrap_err();
+        assert!(err.contains("an entry price of 105.00000000"));
+
+        let flat = Position::default();
+        assert!(check_unrealized_pnl(&flat, &reported(200.0), &params, 0.001).is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_reported_pnl_reaches_through_wrappers() {
+        use crate::exchange::market_cache::CachedMarketsExchange;
+        use crate::exchange::retry::RetryingExchange;
+        use crate::exchange::simulated::SimulatedExchange;
+        use crate::exchange::Exchange;
+        use crate::types::{Order, RetryConfig};
+
+        let simulated = SimulatedExchange::new(1000.0);
+        simulated.update_price(100.0, 100.0, 100.0);
+        // wrapped as live exchanges are by default
+        let path = std::env::temp_dir().join(format!("pnl_check_{}.json", std::process::id()));
+        let retrying = RetryingExchange::new(simulated.clone_box(), RetryConfig::default());
+        let mut exchange = CachedMarketsExchange::new(Box::new(retrying), path.clone(), 24.0);
+        assert_eq!(exchange.fetch_position_pnl("BTCUSDT").await.unwrap(), None);
+
+        let order = Order {
+            symbol: "BTCUSDT".to_string(),
+            side: "Buy".to_string(),
+            qty: 1.0,
+            price: 100.0,
+            ..Default::default()
+        };
+        exchange.place_order(&order).await.unwrap();
+        simulated.update_price(99.0, 99.0, 99.0);
+        simulated.update_price(110.0, 110.0, 110.0);
+        let position = exchange.fetch_positions("BTCUSDT").await.unwrap().net();
+        assert!(position.size > 0.0);
+        let reported = exchange
+            .fetch_position_pnl("BTCUSDT")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(reported.mark_price, 110.0);
+        let params = exchange.fetch_exchange_params("BTCUSDT").await.unwrap();
+        assert!(check_unrealized_pnl(&position, &reported, &params, 0.001).is_ok());
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,123 @@
+use super::quarantine::SymbolQuarantine;
+use super::{Exchange, ExchangeError};
+use crate::grid::utils::{calc_min_entry_qty, round_dn, round_up};
+use crate::types::{ExchangeParams, Order};
+use tracing::{error, warn};
+
+/// Rounds an order's price and qty to the symbol's steps and raises the qty to the
+/// minimum order size. Prices are rounded away from the market, buys down and sells up.
+/// Reduce-only orders are exempt from the minimum cost.
+pub fn requantize_order(order: &Order, params: &ExchangeParams) -> Order {
+    let mut requantized = order.clone();
+    requantized.price = if order.side == "Buy" {
+        round_dn(order.price, params.price_step)
+    } else {
+        round_up(order.price, params.price_step)
+    };
+    let min_qty = if order.reduce_only {
+        params.min_qty
+    } else {
+        calc_min_entry_qty(
+            requantized.price,
+            params.inverse,
+            params.c_mult,
+            params.qty_step,
+            params.min_qty,
+            params.min_cost,
+        )
+    };
+    requantized.qty = round_dn(order.qty, params.qty_step).max(min_qty);
+    requantized
+}
+
+/// Reacts to an order rejected for its price or qty precision or for being below the
+/// minimum size: re-quantizes it with freshly fetched exchange params and retries once.
+/// Rejections that cannot be corrected count towards the symbol's quarantine. Returns
+/// true if the re-quantized order was accepted.
+pub async fn handle_precision_rejection(
+    exchange: &mut Box<dyn Exchange>, order: &Order, error: &ExchangeError,
+    quarantine: &SymbolQuarantine,
+) -> bool {
+    let symbol = &order.symbol;
+    let params = match exchange.fetch_exchange_params(symbol).await {
+        Ok(params) => params,
+        Err(e) => {
+            error!("[{}] Failed to fetch exchange params: {}", symbol, e);
+            quarantine.record_error(symbol, error);
+            return false;
+        }
+    };
+    let requantized = requantize_order(order, &params);
+    if requantized.qty == order.qty && requantized.price == order.price {
+        error!(
+            "[{}] Order rejected for precision, but it already matches the exchange params: {}",
+            symbol, error
+        );
+        quarantine.record_error(symbol, error);
+        return false;
+    }
+    warn!(
+        "[{}] Order rejected for precision ({}); retrying as qty {} @ {} instead of qty {} @ {}",
+        symbol, error, requantized.qty, requantized.price, order.qty, order.price
+    );
+    match exchange.place_order(&requantized).await {
+        Ok(()) => {
+            quarantine.record_success(symbol);
+            true
+        }
+        Err(e) => {
+            error!("[{}] Retry after re-quantizing failed: {}", symbol, e);
+            quarantine.record_error(symbol, &e);
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::exchange::simulated::SimulatedExchange;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_precision_rejection_is_requantized() {
+        let simulated = SimulatedExchange::new(1000.0);
+        simulated.update_price(100.0, 100.0, 100.0);
+        let mut exchange = simulated.clone_box();
+        let quarantine = SymbolQuarantine::new(2, Duration::from_secs(60));
+        let mut order = Order {
+            id: String::new(),
+            symbol: "BTCUSDT".to_string(),
+            side: "Buy".to_string(),
+            position_side: "Long".to_string(),
+            qty: 0.0156789,
+            price: 99.987,
+            reduce_only: false,
+            custom_id: String::new(),
+            client_order_id: String::new(),
+            time_in_force: String::new(),
+        };
+        let err = exchange.place_order(&order).await.unwrap_err();
+        assert!(exchange.is_precision_error(&err.to_string()));
+
+        handle_precision_rejection(&mut exchange, &order, &err, &quarantine).await;
+        // fast-math rounding may be off by the last bit
+        let placed = &simulated.snapshot().orders[0];
+        assert!((placed.qty - 0.015).abs() < 1e-12);
+        assert!((placed.price - 99.98).abs() < 1e-12);
+
+        // below min cost, raised to the minimum entry qty
+        order.qty = 0.001;
+        order.price = 100.0;
+        let requantized = requantize_order(&order, &simulated.snapshot().exchange_params);
+        assert!((requantized.qty - 0.01).abs() < 1e-12);
+
+        // nothing to correct: repeated rejections quarantine the symbol
+        order.qty = 0.01;
+        let err = ExchangeError::InvalidOrder("qty invalid".to_string());
+        for _ in 0..2 {
+            handle_precision_rejection(&mut exchange, &order, &err, &quarantine).await;
+        }
+        assert!(quarantine.is_quarantined("BTCUSDT"));
+    }
+}
@@ -0,0 +1,54 @@
+/// Checks that independent price sources, e.g. the ticker's last price, the order book mid
+/// and the latest candle close, agree: every price must be positive and within
+/// `max_divergence` (relative) of their median. A feed that is stale or reports a
+/// different market shows up as an outlier.
+pub fn check_price_sources(sources: &[(&str, f64)], max_divergence: f64) -> Result<(), String> {
+    if let Some((name, price)) = sources.iter().find(|(_, p)| !(p.is_finite() && *p > 0.0)) {
+        return Err(format!("invalid {} price {}", name, price));
+    }
+    if sources.len() < 2 {
+        return Ok(());
+    }
+    let mut prices: Vec<f64> = sources.iter().map(|(_, p)| *p).collect();
+    prices.sort_by(|a, b| a.total_cmp(b));
+    let mid = prices.len() / 2;
+    let median = if prices.len().is_multiple_of(2) {
+        (prices[mid - 1] + prices[mid]) / 2.0
+    } else {
+        prices[mid]
+    };
+    let outliers: Vec<String> = sources
+        .iter()
+        .filter(|(_, p)| (p - median).abs() / median > max_divergence)
+        .map(|(name, p)| format!("{} {}", name, p))
+        .collect();
+    if outliers.is_empty() {
+        Ok(())
+    } else {
+        Err(format!(
+            "{} diverge from the median {} by more than {:.4}",
+            outliers.join(", "),
+            median,
+            max_divergence
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_price_sources() {
+        let sources = [("ticker", 100.0), ("book mid", 100.05), ("candle", 99.9)];
+        assert!(check_price_sources(&sources, 0.01).is_ok());
+
+        // a stale ticker stands out against the book and the candle
+        let sources = [("ticker", 90.0), ("book mid", 100.05), ("candle", 99.9)];
+        let err = check_price_sources(&sources, 0.01).unwrap_err();
+        assert!(err.starts_with("ticker 90 diverge"));
+
+        assert!(check_price_sources(&[("ticker", 0.0), ("book mid", 100.0)], 0.01).is_err());
+        assert!(check_price_sources(&[("book mid", 100.0)], 0.01).is_ok());
+    }
+}
@@ -0,0 +1,107 @@
+use super::ExchangeError;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::warn;
+
+#[derive(Debug, Default)]
+struct SymbolErrors {
+    consecutive_errors: u32,
+    quarantined_until: Option<Instant>,
+}
+
+/// Tracks order rejections per symbol and quarantines symbols the exchange keeps
+/// rejecting (delisted, reduce-only mode, leverage not allowed, ...) for a cooldown period.
+/// A quarantined symbol places no entries; its closes are still placed.
+#[derive(Clone)]
+pub struct SymbolQuarantine {
+    symbols: Arc<Mutex<HashMap<String, SymbolErrors>>>,
+    error_threshold: u32,
+    cooldown: Duration,
+}
+
+impl SymbolQuarantine {
+    pub fn new(error_threshold: u32, cooldown: Duration) -> Self {
+        Self {
+            symbols: Arc::new(Mutex::new(HashMap::new())),
+            error_threshold,
+            cooldown,
+        }
+    }
+
+    /// Counts a rejection of one of `symbol`'s orders. Failures of the connection, the
+    /// account or the exchange as a whole say nothing about the symbol and are not counted.
+    pub fn record_error(&self, symbol: &str, error: &ExchangeError) {
+        if !matches!(
+            error,
+            ExchangeError::InvalidOrder(_) | ExchangeError::Other(_)
+        ) {
+            return;
+        }
+        let mut symbols = self.symbols.lock().unwrap();
+        let entry = symbols.entry(symbol.to_string()).or_default();
+        entry.consecutive_errors += 1;
+        if self.error_threshold > 0 && entry.consecutive_errors >= self.error_threshold {
+            warn!(
+                "[{}] Quarantining symbol for {:?} after {} consecutive rejections. Last error: {}",
+                symbol, self.cooldown, entry.consecutive_errors, error
+            );
+            entry.consecutive_errors = 0;
+            entry.quarantined_until = Some(Instant::now() + self.cooldown);
+        }
+    }
+
+    pub fn record_success(&self, symbol: &str) {
+        let mut symbols = self.symbols.lock().unwrap();
+        if let Some(entry) = symbols.get_mut(symbol) {
+            entry.consecutive_errors = 0;
+        }
+    }
+
+    pub fn is_quarantined(&self, symbol: &str) -> bool {
+        let mut symbols = self.symbols.lock().unwrap();
+        match symbols.get_mut(symbol) {
+            Some(entry) => match entry.quarantined_until {
+                Some(until) if Instant::now() < until => true,
+                Some(_) => {
+                    warn!("[{}] Quarantine expired", symbol);
+                    entry.quarantined_until = None;
+                    false
+                }
+                None => false,
+            },
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quarantine_threshold_and_cooldown() {
+        let quarantine = SymbolQuarantine::new(2, Duration::from_millis(50));
+        let rejected = ExchangeError::InvalidOrder("symbol is delisted".to_string());
+
+        // only rejections of the symbol's orders count
+        quarantine.record_error("BTCUSDT", &ExchangeError::Network("timed out".to_string()));
+        quarantine.record_error(
+            "BTCUSDT",
+            &ExchangeError::InsufficientBalance("insufficient margin".to_string()),
+        );
+        quarantine.record_error("BTCUSDT", &rejected);
+        assert!(!quarantine.is_quarantined("BTCUSDT"));
+
+        // a success resets the count
+        quarantine.record_success("BTCUSDT");
+        quarantine.record_error("BTCUSDT", &rejected);
+        assert!(!quarantine.is_quarantined("BTCUSDT"));
+        quarantine.record_error("BTCUSDT", &rejected);
+        assert!(quarantine.is_quarantined("BTCUSDT"));
+        assert!(!quarantine.is_quarantined("ETHUSDT"));
+
+        std::thread::sleep(Duration::from_millis(60));
+        assert!(!quarantine.is_quarantined("BTCUSDT"));
+    }
+}
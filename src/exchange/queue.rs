@@ -0,0 +1,544 @@
+use super::intent_log::{self, IntentLog};
+use super::precision;
+use super::quarantine::SymbolQuarantine;
+use super::risk_limit::{self, RiskLimitCaps};
+use super::{Exchange, ExchangeError, SendSyncError};
+use crate::events::{BotEvent, EventBus};
+use crate::types::{CancelRequest, LiveConfig, Order};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::sync::mpsc;
+use tracing::{debug, error};
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum OrderRequest {
+    Place(Order),
+    Cancel(CancelRequest),
+}
+
+/// Most requests of one kind sent to the exchange together.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BatchLimits {
+    pub creations: usize,
+    pub cancellations: usize,
+}
+
+impl Default for BatchLimits {
+    /// One request at a time.
+    fn default() -> Self {
+        Self {
+            creations: 1,
+            cancellations: 1,
+        }
+    }
+}
+
+impl BatchLimits {
+    /// From `max_n_creations_per_batch` and `max_n_cancellations_per_batch`, where 0 sends
+    /// everything pending at once and leaves splitting to the exchange's batch endpoint.
+    pub fn from_config(live: &LiveConfig) -> Self {
+        let limit = |n: i32| if n > 0 { n as usize } else { usize::MAX };
+        Self {
+            creations: limit(live.max_n_creations_per_batch),
+            cancellations: limit(live.max_n_cancellations_per_batch),
+        }
+    }
+}
+
+/// Pending requests bucketed by priority: cancellations first, then closes
+/// (reduce-only orders), then new entries. Duplicate cancellations are coalesced.
+#[derive(Debug, Default)]
+struct PendingOrders {
+    cancels: VecDeque<CancelRequest>,
+    cancel_ids: HashSet<String>,
+    closes: VecDeque<Order>,
+    entries: VecDeque<Order>,
+}
+
+impl PendingOrders {
+    /// Adds a request to its bucket. Returns false if it was coalesced into a pending one.
+    fn push(&mut self, request: OrderRequest) -> bool {
+        match request {
+            OrderRequest::Cancel(cancel) => {
+                if !self.cancel_ids.insert(cancel.order_id.clone()) {
+                    return false;
+                }
+                self.cancels.push_back(cancel);
+            }
+            OrderRequest::Place(order) => {
+                if order.reduce_only {
+                    self.closes.push_back(order);
+                } else {
+                    self.entries.push_back(order);
+                }
+            }
+        }
+        true
+    }
+
+    /// Takes the next requests to send together: up to `limits.cancellations` cancels
+    /// while any are pending, otherwise up to `limits.creations` orders, closes first.
+    fn pop_batch(&mut self, limits: &BatchLimits) -> Vec<OrderRequest> {
+        let mut batch = Vec::new();
+        if !self.cancels.is_empty() {
+            while batch.len() < limits.cancellations {
+                let Some(cancel) = self.cancels.pop_front() else {
+                    break;
+                };
+                self.cancel_ids.remove(&cancel.order_id);
+                batch.push(OrderRequest::Cancel(cancel));
+            }
+            return batch;
+        }
+        while batch.len() < limits.creations {
+            let Some(order) = self.closes.pop_front().or_else(|| self.entries.pop_front()) else {
+                break;
+            };
+            batch.push(OrderRequest::Place(order));
+        }
+        batch
+    }
+
+    fn len(&self) -> usize {
+        self.cancels.len() + self.closes.len() + self.entries.len()
+    }
+}
+
+/// Outcomes of a symbol's order placements since its summary was last taken. Every order
+/// has an outcome of its own, so a rejection never fails the orders sent along with it.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct PlacementSummary {
+    pub accepted: usize,
+    /// Orders accepted after correcting a precision or risk limit rejection.
+    pub accepted_after_retry: usize,
+    /// Reasons of the orders that were dropped.
+    pub rejected: Vec<String>,
+}
+
+impl PlacementSummary {
+    pub fn is_empty(&self) -> bool {
+        self.accepted == 0 && self.rejected.is_empty()
+    }
+
+    /// E.g. "placed 5 of 7 orders (1 after a retry); rejected: qty invalid (x2)".
+    pub fn format(&self) -> String {
+        let mut out = format!(
+            "placed {} of {} orders",
+            self.accepted,
+            self.accepted + self.rejected.len()
+        );
+        if self.accepted_after_retry > 0 {
+            out.push_str(&format!(" ({} after a retry)", self.accepted_after_retry));
+        }
+        if !self.rejected.is_empty() {
+            let mut counts: BTreeMap<&str, usize> = BTreeMap::new();
+            for reason in &self.rejected {
+                *counts.entry(reason).or_default() += 1;
+            }
+            let reasons: Vec<String> = counts
+                .into_iter()
+                .map(|(reason, n)| format!("{} (x{})", reason, n))
+                .collect();
+            out.push_str(&format!("; rejected: {}", reasons.join(", ")));
+        }
+        out
+    }
+}
+
+#[derive(Clone, Default)]
+struct PlacementSummaries(Arc<Mutex<HashMap<String, PlacementSummary>>>);
+
+impl PlacementSummaries {
+    fn record(&self, symbol: &str, update: impl FnOnce(&mut PlacementSummary)) {
+        update(
+            self.0
+                .lock()
+                .unwrap()
+                .entry(symbol.to_string())
+                .or_default(),
+        );
+    }
+
+    fn accepted(&self, symbol: &str, after_retry: bool) {
+        self.record(symbol, |summary| {
+            summary.accepted += 1;
+            if after_retry {
+                summary.accepted_after_retry += 1;
+            }
+        });
+    }
+
+    fn rejected(&self, symbol: &str, reason: String) {
+        self.record(symbol, |summary| summary.rejected.push(reason));
+    }
+
+    fn take(&self, symbol: &str) -> PlacementSummary {
+        self.0.lock().unwrap().remove(symbol).unwrap_or_default()
+    }
+}
+
+/// Handle to a per-exchange order execution queue.
+///
+/// Requests are executed in batches of one kind, within `BatchLimits`, by a background
+/// task. New requests are drained into the queue between batches, so a close submitted
+/// behind a burst of entries is still sent before them.
+#[derive(Clone)]
+pub struct OrderQueue {
+    sender: mpsc::UnboundedSender<OrderRequest>,
+    depth: Arc<AtomicUsize>,
+    quarantine: SymbolQuarantine,
+    risk_limit_caps: RiskLimitCaps,
+    summaries: PlacementSummaries,
+}
+
+impl OrderQueue {
+    /// Spawns the worker. Requests left in flight by a previous run, according to
+    /// `intent_log`, are resolved before any new request is executed. Accepted orders and
+    /// failures are published on `events`.
+    pub fn spawn(
+        exchange: Box<dyn Exchange>, quarantine: SymbolQuarantine, intent_log: IntentLog,
+        events: EventBus, batch_limits: BatchLimits,
+    ) -> Self {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let depth = Arc::new(AtomicUsize::new(0));
+        let risk_limit_caps = RiskLimitCaps::default();
+        let summaries = PlacementSummaries::default();
+        tokio::spawn(run_queue(
+            exchange,
+            receiver,
+            depth.clone(),
+            quarantine.clone(),
+            risk_limit_caps.clone(),
+            intent_log,
+            events,
+            summaries.clone(),
+            batch_limits,
+        ));
+        Self {
+            sender,
+            depth,
+            quarantine,
+            risk_limit_caps,
+            summaries,
+        }
+    }
+
+    /// Takes the outcomes of `symbol`'s placements since the last call.
+    pub fn take_placement_summary(&self, symbol: &str) -> PlacementSummary {
+        self.summaries.take(symbol)
+    }
+
+    /// Maximum position value for `symbol` after a risk limit rejection that could not be
+    /// resolved by raising the risk limit.
+    pub fn risk_limit_cap(&self, symbol: &str) -> Option<f64> {
+        self.risk_limit_caps.get(symbol)
+    }
+
+    pub fn is_quarantined(&self, symbol: &str) -> bool {
+        self.quarantine.is_quarantined(symbol)
+    }
+
+    pub fn place(&self, order: Order) -> Result<(), SendSyncError> {
+        self.submit(OrderRequest::Place(order))
+    }
+
+    pub fn cancel(&self, symbol: &str, order_id: &str) -> Result<(), SendSyncError> {
+        self.submit(OrderRequest::Cancel(CancelRequest {
+            symbol: symbol.to_string(),
+            order_id: order_id.to_string(),
+        }))
+    }
+
+    pub fn submit(&self, request: OrderRequest) -> Result<(), SendSyncError> {
+        self.depth.fetch_add(1, Ordering::Relaxed);
+        self.sender.send(request).map_err(|_| {
+            self.depth.fetch_sub(1, Ordering::Relaxed);
+            "Order queue worker has stopped".into()
+        })
+    }
+
+    /// Number of submitted requests not yet completed by the exchange.
+    pub fn depth(&self) -> usize {
+        self.depth.load(Ordering::Relaxed)
+    }
+}
+
+async fn run_queue(
+    mut exchange: Box<dyn Exchange>, mut receiver: mpsc::UnboundedReceiver<OrderRequest>,
+    depth: Arc<AtomicUsize>, quarantine: SymbolQuarantine, risk_limit_caps: RiskLimitCaps,
+    mut intent_log: IntentLog, events: EventBus, summaries: PlacementSummaries,
+    batch_limits: BatchLimits,
+) {
+    intent_log::recover(&mut exchange, &mut intent_log).await;
+    let mut pending = PendingOrders::default();
+    loop {
+        if pending.len() == 0 {
+            match receiver.recv().await {
+                Some(request) => {
+                    if !pending.push(request) {
+                        depth.fetch_sub(1, Ordering::Relaxed);
+                    }
+                }
+                None => break,
+            }
+        }
+        while let Ok(request) = receiver.try_recv() {
+            if !pending.push(request) {
+                debug!("Coalesced duplicate cancel request");
+                depth.fetch_sub(1, Ordering::Relaxed);
+            }
+        }
+
+        let batch = pending.pop_batch(&batch_limits);
+        if batch.is_empty() {
+            continue;
+        }
+        debug!("Order queue depth: {}", depth.load(Ordering::Relaxed));
+        let n_requests = batch.len();
+        let mut intents = Vec::new();
+        let mut orders = Vec::new();
+        let mut cancels = Vec::new();
+        for mut request in batch {
+            if let OrderRequest::Place(order) = &request {
+                if !order.reduce_only && quarantine.is_quarantined(&order.symbol) {
+                    debug!("[{}] Dropping entry for quarantined symbol", order.symbol);
+                    summaries.rejected(&order.symbol, "symbol quarantined".to_string());
+                    continue;
+                }
+            }
+            // a failed write only loses crash safety, so the request is still sent
+            match intent_log.begin(&mut request) {
+                Ok(Some(seq)) => intents.push(seq),
+                Ok(None) => {}
+                Err(e) => error!("Failed to write intent log: {}", e),
+            }
+            match request {
+                OrderRequest::Place(order) => orders.push(order),
+                OrderRequest::Cancel(cancel) => cancels.push(cancel),
+            }
+        }
+
+        if !orders.is_empty() {
+            let results = exchange.place_orders(&orders).await;
+            for (order, result) in orders.iter().zip(results) {
+                handle_placement(
+                    &mut exchange,
+                    order,
+                    result,
+                    &quarantine,
+                    &risk_limit_caps,
+                    &events,
+                    &summaries,
+                )
+                .await;
+            }
+        }
+        if !cancels.is_empty() {
+            let results = exchange.cancel_orders(&cancels).await;
+            for (cancel, result) in cancels.iter().zip(results) {
+                if let Err(e) = result {
+                    error!(
+                        "[{}] Failed to cancel order {}: {}",
+                        cancel.symbol, cancel.order_id, e
+                    );
+                }
+            }
+        }
+        for seq in intents {
+            if let Err(e) = intent_log.complete(seq) {
+                error!("Failed to write intent log: {}", e);
+            }
+        }
+        depth.fetch_sub(n_requests, Ordering::Relaxed);
+    }
+}
+
+/// Records the outcome of placing `order`, retrying it once after a precision or risk
+/// limit rejection.
+async fn handle_placement(
+    exchange: &mut Box<dyn Exchange>, order: &Order, result: Result<(), ExchangeError>,
+    quarantine: &SymbolQuarantine, risk_limit_caps: &RiskLimitCaps, events: &EventBus,
+    summaries: &PlacementSummaries,
+) {
+    match result {
+        Ok(()) => {
+            quarantine.record_success(&order.symbol);
+            summaries.accepted(&order.symbol, false);
+            events.publish(BotEvent::OrderPlaced {
+                order: order.clone(),
+            });
+        }
+        Err(e) if exchange.is_risk_limit_error(&e.to_string()) => {
+            if risk_limit::handle_risk_limit_rejection(exchange, order, risk_limit_caps).await {
+                summaries.accepted(&order.symbol, true);
+            } else {
+                summaries.rejected(&order.symbol, e.to_string());
+            }
+        }
+        Err(e) if exchange.is_precision_error(&e.to_string()) => {
+            if precision::handle_precision_rejection(exchange, order, &e, quarantine).await {
+                summaries.accepted(&order.symbol, true);
+            } else {
+                summaries.rejected(&order.symbol, e.to_string());
+            }
+        }
+        Err(e) => {
+            error!("[{}] Failed to place order: {}", order.symbol, e);
+            quarantine.record_error(&order.symbol, &e);
+            summaries.rejected(&order.symbol, e.to_string());
+            events.publish(BotEvent::Error {
+                symbol: order.symbol.clone(),
+                message: format!("Failed to place order: {}", e),
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn order(id: &str, reduce_only: bool) -> Order {
+        Order {
+            id: id.to_string(),
+            symbol: "BTCUSDT".to_string(),
+            side: "Buy".to_string(),
+            position_side: "Long".to_string(),
+            qty: 1.0,
+            price: 100.0,
+            reduce_only,
+            custom_id: String::new(),
+            client_order_id: String::new(),
+            time_in_force: String::new(),
+        }
+    }
+
+    fn cancel(order_id: &str) -> OrderRequest {
+        OrderRequest::Cancel(CancelRequest {
+            symbol: "BTCUSDT".to_string(),
+            order_id: order_id.to_string(),
+        })
+    }
+
+    fn popped_ids(pending: &mut PendingOrders, limits: &BatchLimits) -> Vec<String> {
+        pending
+            .pop_batch(limits)
+            .into_iter()
+            .map(|request| match request {
+                OrderRequest::Place(order) => order.id,
+                OrderRequest::Cancel(cancel) => format!("cancel:{}", cancel.order_id),
+            })
+            .collect()
+    }
+
+    fn popped_id(pending: &mut PendingOrders) -> String {
+        popped_ids(pending, &BatchLimits::default())
+            .pop()
+            .unwrap_or_default()
+    }
+
+    #[test]
+    fn test_closes_have_priority_over_entries() {
+        let mut pending = PendingOrders::default();
+        pending.push(OrderRequest::Place(order("entry_0", false)));
+        pending.push(OrderRequest::Place(order("entry_1", false)));
+        pending.push(OrderRequest::Place(order("close_0", true)));
+        pending.push(cancel("a"));
+
+        assert_eq!(popped_id(&mut pending), "cancel:a");
+        assert_eq!(popped_id(&mut pending), "close_0");
+        assert_eq!(popped_id(&mut pending), "entry_0");
+        assert_eq!(popped_id(&mut pending), "entry_1");
+        assert!(pending.pop_batch(&BatchLimits::default()).is_empty());
+    }
+
+    #[test]
+    fn test_batches_hold_one_kind_within_limits() {
+        let mut pending = PendingOrders::default();
+        pending.push(OrderRequest::Place(order("entry_0", false)));
+        pending.push(OrderRequest::Place(order("entry_1", false)));
+        pending.push(OrderRequest::Place(order("close_0", true)));
+        pending.push(cancel("a"));
+        pending.push(cancel("b"));
+        let limits = BatchLimits {
+            creations: 2,
+            cancellations: 5,
+        };
+
+        assert_eq!(
+            popped_ids(&mut pending, &limits),
+            vec!["cancel:a", "cancel:b"]
+        );
+        assert_eq!(
+            popped_ids(&mut pending, &limits),
+            vec!["close_0", "entry_0"]
+        );
+        assert_eq!(popped_ids(&mut pending, &limits), vec!["entry_1"]);
+    }
+
+    #[test]
+    fn test_placement_summary() {
+        let summaries = PlacementSummaries::default();
+        summaries.accepted("BTCUSDT", false);
+        summaries.accepted("BTCUSDT", true);
+        summaries.rejected("BTCUSDT", "qty invalid".to_string());
+        summaries.rejected("BTCUSDT", "qty invalid".to_string());
+        summaries.rejected("ETHUSDT", "symbol quarantined".to_string());
+
+        let summary = summaries.take("BTCUSDT");
+        assert_eq!(
+            summary.format(),
+            "placed 2 of 4 orders (1 after a retry); rejected: qty invalid (x2)"
+        );
+        assert!(summaries.take("BTCUSDT").is_empty());
+        assert_eq!(summaries.take("ETHUSDT").rejected.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_quarantined_symbol_still_places_closes() {
+        let simulated = crate::exchange::simulated::SimulatedExchange::new(1000.0);
+        simulated.update_price(100.0, 100.0, 100.0);
+        let quarantine = SymbolQuarantine::new(1, std::time::Duration::from_secs(60));
+        quarantine.record_error(
+            "BTCUSDT",
+            &ExchangeError::InvalidOrder("symbol is in reduce-only mode".to_string()),
+        );
+        let queue = OrderQueue::spawn(
+            simulated.clone_box(),
+            quarantine,
+            IntentLog::open(None),
+            EventBus::default(),
+            BatchLimits::default(),
+        );
+        let mut close = order("close", true);
+        close.side = "Sell".to_string();
+        close.price = 110.0;
+        queue.place(order("entry", false)).unwrap();
+        queue.place(close).unwrap();
+        while queue.depth() > 0 {
+            tokio::task::yield_now().await;
+        }
+
+        let placed = simulated.snapshot().orders;
+        assert_eq!(placed.len(), 1);
+        assert!(placed[0].reduce_only);
+        assert_eq!(
+            queue.take_placement_summary("BTCUSDT").format(),
+            "placed 1 of 2 orders; rejected: symbol quarantined (x1)"
+        );
+    }
+
+    #[test]
+    fn test_duplicate_cancels_are_coalesced() {
+        let mut pending = PendingOrders::default();
+        assert!(pending.push(cancel("a")));
+        assert!(!pending.push(cancel("a")));
+        assert!(pending.push(cancel("b")));
+        assert_eq!(pending.len(), 2);
+
+        pending.pop_batch(&BatchLimits::default());
+        assert!(pending.push(cancel("a")));
+    }
+}
@@ -0,0 +1,306 @@
+//! Client-side request budget per exchange. Every REST request goes through a token
+//! bucket holding the exchange's request weight per window, shared by all accounts on the
+//! exchange in this process, so bots stay below the limits that get IPs banned.
+
+use reqwest::{RequestBuilder, Response, StatusCode};
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Duration;
+use tokio::time::Instant;
+use crate::types::RateLimitConfig;
+use super::ExchangeError;
+use tracing::{debug, warn};
+
+/// Published request limits of an exchange, used where `live.rate_limit` sets none.
+pub struct ExchangeLimits {
+    pub capacity: f64,
+    pub window_seconds: f64,
+    /// Weight of requests by URL path prefix; other requests weigh 1.
+    pub weights: &'static [(&'static str, f64)],
+    /// Response header reporting the weight used in the current window.
+    pub used_weight_header: Option<&'static str>,
+}
+
+pub fn exchange_limits(exchange_name: &str) -> ExchangeLimits {
+    let limits = |capacity, window_seconds, weights| ExchangeLimits {
+        capacity,
+        window_seconds,
+        weights,
+        used_weight_header: None,
+    };
+    match exchange_name {
+        "binance" => ExchangeLimits {
+            used_weight_header: Some("x-mbx-used-weight-1m"),
+            ..limits(
+                2400.0,
+                60.0,
+                &[
+                    ("/fapi/v1/ticker/24hr", 40.0),
+                    ("/fapi/v1/depth", 5.0),
+                    ("/fapi/v1/income", 30.0),
+                    ("/fapi/v1/userTrades", 5.0),
+                    ("/fapi/v1/batchOrders", 5.0),
+                    ("/fapi/v2/balance", 5.0),
+                    ("/fapi/v1/assetIndex", 10.0),
+                    ("/fapi/v2/positionRisk", 5.0),
+                ],
+            )
+        },
+        "bybit" => limits(600.0, 5.0, &[]),
+        "okx" => limits(40.0, 2.0, &[]),
+        "bitget" => limits(20.0, 1.0, &[]),
+        "gateio" => limits(200.0, 10.0, &[]),
+        // requests to /info pass their weight explicitly, see `SendRateLimited::send_weighted`
+        "hyperliquid" => limits(1200.0, 60.0, &[]),
+        _ => limits(10.0, 1.0, &[]),
+    }
+}
+
+struct Bucket {
+    tokens: f64,
+    refilled_at: Instant,
+    /// Requests wait until then after the exchange answered 429 or 418.
+    blocked_until: Option<Instant>,
+    consecutive_limits: u32,
+}
+
+/// A token bucket refilled continuously at `capacity` per window. Clones share the bucket.
+#[derive(Clone)]
+pub struct RateLimiter {
+    name: String,
+    capacity: f64,
+    refill_per_second: f64,
+    weights: Arc<Vec<(String, f64)>>,
+    used_weight_header: Option<&'static str>,
+    backoff: Duration,
+    max_backoff: Duration,
+    bucket: Arc<Mutex<Bucket>>,
+}
+
+impl RateLimiter {
+    pub fn new(exchange_name: &str, config: &RateLimitConfig) -> Self {
+        let limits = exchange_limits(exchange_name);
+        let or_default = |value: f64, default: f64| if value > 0.0 { value } else { default };
+        let capacity = or_default(config.capacity, limits.capacity);
+        let window_seconds = or_default(config.window_seconds, limits.window_seconds);
+        let mut weights: Vec<(String, f64)> = limits
+            .weights
+            .iter()
+            .filter(|(path, _)| !config.weights.contains_key(*path))
+            .map(|(path, weight)| (path.to_string(), *weight))
+            .chain(config.weights.clone())
+            .collect();
+        // longest prefix first, so the most specific path wins
+        weights.sort_by_key(|(path, _)| std::cmp::Reverse(path.len()));
+        Self {
+            name: exchange_name.to_string(),
+            capacity,
+            refill_per_second: capacity / window_seconds,
+            weights: Arc::new(weights),
+            used_weight_header: limits.used_weight_header,
+            backoff: Duration::from_secs_f64(config.backoff_seconds),
+            max_backoff: Duration::from_secs_f64(config.max_backoff_seconds),
+            bucket: Arc::new(Mutex::new(Bucket {
+                tokens: capacity,
+                refilled_at: Instant::now(),
+                blocked_until: None,
+                consecutive_limits: 0,
+            })),
+        }
+    }
+
+    /// The limiter of `exchange_name`, created on first use and shared from then on.
+    pub fn shared(exchange_name: &str, config: &RateLimitConfig) -> Self {
+        static LIMITERS: OnceLock<Mutex<HashMap<String, RateLimiter>>> = OnceLock::new();
+        LIMITERS
+            .get_or_init(Default::default)
+            .lock()
+            .unwrap()
+            .entry(exchange_name.to_string())
+            .or_insert_with(|| Self::new(exchange_name, config))
+            .clone()
+    }
+
+    pub fn weight(&self, path: &str) -> f64 {
+        self.weights
+            .iter()
+            .find(|(prefix, _)| path.starts_with(prefix.as_str()))
+            .map_or(1.0, |(_, weight)| *weight)
+    }
+
+    fn refill(&self, bucket: &mut Bucket, now: Instant) {
+        let elapsed = now
+            .saturating_duration_since(bucket.refilled_at)
+            .as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * self.refill_per_second).min(self.capacity);
+        bucket.refilled_at = now;
+    }
+
+    /// Waits until `weight` is available and takes it from the bucket. Weights above the
+    /// capacity wait for a full bucket.
+    pub async fn acquire(&self, weight: f64) {
+        let weight = weight.min(self.capacity);
+        loop {
+            let wait = {
+                let mut bucket = self.bucket.lock().unwrap();
+                let now = Instant::now();
+                self.refill(&mut bucket, now);
+                match bucket.blocked_until {
+                    Some(until) if until > now => until - now,
+                    _ if bucket.tokens >= weight - 1e-9 => {
+                        bucket.tokens -= weight;
+                        return;
+                    }
+                    _ => Duration::from_secs_f64((weight - bucket.tokens) / self.refill_per_second),
+                }
+            };
+            debug!("{} request budget exhausted, waiting {:?}", self.name, wait);
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    /// Updates the bucket from a response: syncs it with the weight the exchange reports
+    /// as used, and pauses all requests after a 429 (rate limited) or 418 (IP banned).
+    fn record_response(&self, status: StatusCode, headers: &reqwest::header::HeaderMap) {
+        let mut bucket = self.bucket.lock().unwrap();
+        let now = Instant::now();
+        self.refill(&mut bucket, now);
+        if let Some(used) = self
+            .used_weight_header
+            .and_then(|name| headers.get(name))
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.parse::<f64>().ok())
+        {
+            bucket.tokens = bucket.tokens.min(self.capacity - used);
+        }
+        if status == StatusCode::TOO_MANY_REQUESTS || status == StatusCode::IM_A_TEAPOT {
+            let retry_after = headers
+                .get(reqwest::header::RETRY_AFTER)
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.parse::<f64>().ok())
+                .map(Duration::from_secs_f64);
+            let backoff = retry_after.unwrap_or_else(|| {
+                self.backoff
+                    .saturating_mul(1 << bucket.consecutive_limits.min(16))
+                    .min(self.max_backoff)
+            });
+            bucket.consecutive_limits += 1;
+            bucket.blocked_until = Some(now + backoff);
+            bucket.tokens = 0.0;
+        } else {
+            bucket.consecutive_limits = 0;
+        }
+    }
+
+    /// Sends `request` once `weight` is available. A 429 or 418 answer pauses every
+    /// request to the exchange and is returned as an error, without retrying: signed
+    /// requests would have expired by the end of the pause.
+    pub async fn send_weighted(
+        &self, request: RequestBuilder, weight: f64,
+    ) -> Result<Response, ExchangeError> {
+        self.acquire(weight).await;
+        let response = request.send().await?;
+        let status = response.status();
+        self.record_response(status, response.headers());
+        if status == StatusCode::TOO_MANY_REQUESTS || status == StatusCode::IM_A_TEAPOT {
+            let blocked_for = self
+                .bucket
+                .lock()
+                .unwrap()
+                .blocked_until
+                .map_or(Duration::ZERO, |until| until - Instant::now());
+            warn!(
+                "{} rate limit hit (HTTP {}), pausing requests for {:.1}s",
+                self.name,
+                status.as_u16(),
+                blocked_for.as_secs_f64()
+            );
+            return Err(ExchangeError::RateLimited(format!(
+                "{} rate limit hit (HTTP {})",
+                self.name,
+                status.as_u16()
+            )));
+        }
+        Ok(response)
+    }
+
+    /// Sends `request` weighted by its URL path.
+    pub async fn send(&self, request: RequestBuilder) -> Result<Response, ExchangeError> {
+        let (client, request) = request.build_split();
+        let request = request?;
+        let weight = self.weight(request.url().path());
+        self.send_weighted(RequestBuilder::from_parts(client, request), weight)
+            .await
+    }
+}
+
+/// `send` through a rate limiter, in place of `RequestBuilder::send`.
+pub trait SendRateLimited {
+    fn send_limited(
+        self, limiter: &RateLimiter,
+    ) -> impl Future<Output = Result<Response, ExchangeError>> + Send;
+    fn send_weighted(
+        self, limiter: &RateLimiter, weight: f64,
+    ) -> impl Future<Output = Result<Response, ExchangeError>> + Send;
+}
+
+impl SendRateLimited for RequestBuilder {
+    fn send_limited(
+        self, limiter: &RateLimiter,
+    ) -> impl Future<Output = Result<Response, ExchangeError>> + Send {
+        limiter.send(self)
+    }
+
+    fn send_weighted(
+        self, limiter: &RateLimiter, weight: f64,
+    ) -> impl Future<Output = Result<Response, ExchangeError>> + Send {
+        limiter.send_weighted(self, weight)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test(start_paused = true)]
+    async fn test_bucket_waits_and_backs_off() {
+        let config = RateLimitConfig {
+            capacity: 10.0,
+            window_seconds: 1.0,
+            weights: HashMap::from([("/fapi/v1/depth".to_string(), 8.0)]),
+            ..RateLimitConfig::default()
+        };
+        let limiter = RateLimiter::new("binance", &config);
+        assert_eq!(limiter.weight("/fapi/v1/depth"), 8.0);
+        assert_eq!(limiter.weight("/fapi/v1/ticker/24hr"), 40.0);
+        assert_eq!(limiter.weight("/fapi/v1/order"), 1.0);
+
+        let start = Instant::now();
+        limiter.acquire(8.0).await;
+        limiter.acquire(2.0).await;
+        assert_eq!(start.elapsed(), Duration::ZERO);
+        // the bucket is empty and refills at 10 a second
+        limiter.acquire(5.0).await;
+        assert_eq!(start.elapsed(), Duration::from_millis(500));
+
+        let mut headers = reqwest::header::HeaderMap::new();
+        headers.insert("x-mbx-used-weight-1m", "9".parse().unwrap());
+        limiter.record_response(StatusCode::OK, &headers);
+        assert!(limiter.bucket.lock().unwrap().tokens <= 1.0);
+
+        headers.insert(reqwest::header::RETRY_AFTER, "3".parse().unwrap());
+        limiter.record_response(StatusCode::IM_A_TEAPOT, &headers);
+        let banned_at = Instant::now();
+        limiter.acquire(1.0).await;
+        assert_eq!(banned_at.elapsed(), Duration::from_secs(3));
+
+        // without Retry-After the pause doubles on every consecutive 429
+        limiter.record_response(
+            StatusCode::TOO_MANY_REQUESTS,
+            &reqwest::header::HeaderMap::new(),
+        );
+        let pause = limiter.bucket.lock().unwrap().blocked_until.unwrap() - Instant::now();
+        assert_eq!(pause, Duration::from_secs_f64(config.backoff_seconds * 2.0));
+    }
+}
@@ -0,0 +1,291 @@
+//! Retries of exchange requests that failed for reasons expected to pass: network errors,
+//! rate limits and maintenance. Rejections such as invalid orders fail at once.
+
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::future::Future;
+use std::time::Duration;
+use crate::types::{
+    Market, Ticker, Order, PositionPair, PositionCosts, PositionPnl, OrderBook, ExchangeParams,
+    TradeFill, FundingPayment, CancelRequest, CollateralAsset, MarginMode, RetryConfig, ApiKeyInfo,
+};
+use super::{Exchange, ExchangeError};
+use crate::data::Candle;
+use tracing::warn;
+
+/// Runs `request` until it succeeds, fails with an error that is not retryable, or has
+/// been attempted `config.max_attempts` times, pausing with exponential backoff between
+/// attempts. `label` names the request in logs.
+pub async fn retry<T, F, Fut>(
+    config: &RetryConfig, label: &str, mut request: F,
+) -> Result<T, ExchangeError>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, ExchangeError>>,
+{
+    let mut attempt = 1;
+    loop {
+        match request().await {
+            Err(e) if e.is_retryable() && attempt < config.max_attempts => {
+                let delay = (config.base_delay_seconds * 2f64.powi(attempt as i32 - 1))
+                    .min(config.max_delay_seconds);
+                warn!(
+                    "{} failed (attempt {} of {}): {}; retrying in {:.1}s",
+                    label, attempt, config.max_attempts, e, delay
+                );
+                tokio::time::sleep(Duration::from_secs_f64(delay)).await;
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Wraps an exchange and retries its reads with `retry`. Orders and cancellations are
+/// sent once: one that timed out may still have reached the exchange, and the next
+/// cycle compares open orders before sending it again.
+#[derive(Clone)]
+pub struct RetryingExchange {
+    inner: Box<dyn Exchange>,
+    config: RetryConfig,
+}
+
+impl RetryingExchange {
+    pub fn new(inner: Box<dyn Exchange>, config: RetryConfig) -> Self {
+        Self { inner, config }
+    }
+}
+
+#[async_trait]
+impl Exchange for RetryingExchange {
+    fn clone_box(&self) -> Box<dyn Exchange> {
+        Box::new(self.clone())
+    }
+
+    fn quote(&self) -> &str {
+        self.inner.quote()
+    }
+
+    async fn load_markets(&self) -> Result<HashMap<String, Market>, ExchangeError> {
+        retry(&self.config, "load_markets", || self.inner.load_markets()).await
+    }
+
+    async fn fetch_tickers(
+        &self, symbols: &[String],
+    ) -> Result<HashMap<String, Ticker>, ExchangeError> {
+        retry(&self.config, "fetch_tickers", || {
+            self.inner.fetch_tickers(symbols)
+        })
+        .await
+    }
+
+    async fn fetch_ticker(&self, symbol: &str) -> Result<f64, ExchangeError> {
+        retry(&self.config, "fetch_ticker", || {
+            self.inner.fetch_ticker(symbol)
+        })
+        .await
+    }
+
+    async fn fetch_order_book(&self, symbol: &str) -> Result<OrderBook, ExchangeError> {
+        retry(&self.config, "fetch_order_book", || {
+            self.inner.fetch_order_book(symbol)
+        })
+        .await
+    }
+
+    async fn fetch_balance(&self) -> Result<f64, ExchangeError> {
+        retry(&self.config, "fetch_balance", || self.inner.fetch_balance()).await
+    }
+
+    async fn fetch_collateral(&self) -> Result<Vec<CollateralAsset>, ExchangeError> {
+        retry(&self.config, "fetch_collateral", || {
+            self.inner.fetch_collateral()
+        })
+        .await
+    }
+
+    async fn place_order(&mut self, order: &Order) -> Result<(), ExchangeError> {
+        self.inner.place_order(order).await
+    }
+
+    async fn cancel_order(&mut self, symbol: &str, order_id: &str) -> Result<(), ExchangeError> {
+        self.inner.cancel_order(symbol, order_id).await
+    }
+
+    async fn place_orders(&mut self, orders: &[Order]) -> Vec<Result<(), ExchangeError>> {
+        self.inner.place_orders(orders).await
+    }
+
+    async fn cancel_orders(&mut self, cancels: &[CancelRequest]) -> Vec<Result<(), ExchangeError>> {
+        self.inner.cancel_orders(cancels).await
+    }
+
+    async fn fetch_open_orders(&self, symbol: &str) -> Result<Vec<Order>, ExchangeError> {
+        retry(&self.config, "fetch_open_orders", || {
+            self.inner.fetch_open_orders(symbol)
+        })
+        .await
+    }
+
+    async fn fetch_positions(&self, symbol: &str) -> Result<PositionPair, ExchangeError> {
+        retry(&self.config, "fetch_positions", || {
+            self.inner.fetch_positions(symbol)
+        })
+        .await
+    }
+
+    async fn fetch_position_costs(&self, symbol: &str) -> Result<PositionCosts, ExchangeError> {
+        retry(&self.config, "fetch_position_costs", || {
+            self.inner.fetch_position_costs(symbol)
+        })
+        .await
+    }
+
+    async fn fetch_position_pnl(&self, symbol: &str) -> Result<Option<PositionPnl>, ExchangeError> {
+        retry(&self.config, "fetch_position_pnl", || {
+            self.inner.fetch_position_pnl(symbol)
+        })
+        .await
+    }
+
+    async fn fetch_exchange_params(&self, symbol: &str) -> Result<ExchangeParams, ExchangeError> {
+        retry(&self.config, "fetch_exchange_params", || {
+            self.inner.fetch_exchange_params(symbol)
+        })
+        .await
+    }
+
+    async fn fetch_fill_history(
+        &self, symbol: &str, start_ms: i64, end_ms: i64,
+    ) -> Result<Vec<TradeFill>, ExchangeError> {
+        retry(&self.config, "fetch_fill_history", || {
+            self.inner.fetch_fill_history(symbol, start_ms, end_ms)
+        })
+        .await
+    }
+
+    async fn fetch_my_trades(
+        &self, symbol: &str, since_ms: i64,
+    ) -> Result<Vec<TradeFill>, ExchangeError> {
+        retry(&self.config, "fetch_my_trades", || {
+            self.inner.fetch_my_trades(symbol, since_ms)
+        })
+        .await
+    }
+
+    async fn fetch_funding_payments(
+        &self, symbol: &str, start_ms: i64, end_ms: i64,
+    ) -> Result<Vec<FundingPayment>, ExchangeError> {
+        retry(&self.config, "fetch_funding_payments", || {
+            self.inner.fetch_funding_payments(symbol, start_ms, end_ms)
+        })
+        .await
+    }
+
+    async fn fetch_last_close(&self, symbol: &str) -> Result<Option<f64>, ExchangeError> {
+        retry(&self.config, "fetch_last_close", || {
+            self.inner.fetch_last_close(symbol)
+        })
+        .await
+    }
+
+    async fn fetch_ohlcv(
+        &self, symbol: &str, timeframe: &str, since_ms: Option<i64>, limit: usize,
+    ) -> Result<Vec<Candle>, ExchangeError> {
+        retry(&self.config, "fetch_ohlcv", || {
+            self.inner.fetch_ohlcv(symbol, timeframe, since_ms, limit)
+        })
+        .await
+    }
+
+    async fn fetch_api_key_info(&self) -> Result<Option<ApiKeyInfo>, ExchangeError> {
+        retry(&self.config, "fetch_api_key_info", || {
+            self.inner.fetch_api_key_info()
+        })
+        .await
+    }
+
+    fn now_ms(&self) -> i64 {
+        self.inner.now_ms()
+    }
+
+    fn is_precision_error(&self, error: &str) -> bool {
+        self.inner.is_precision_error(error)
+    }
+
+    fn has_fill_history(&self) -> bool {
+        self.inner.has_fill_history()
+    }
+
+    fn is_risk_limit_error(&self, error: &str) -> bool {
+        self.inner.is_risk_limit_error(error)
+    }
+
+    async fn fetch_risk_limit(&self, symbol: &str) -> Result<Option<f64>, ExchangeError> {
+        retry(&self.config, "fetch_risk_limit", || {
+            self.inner.fetch_risk_limit(symbol)
+        })
+        .await
+    }
+
+    async fn raise_risk_limit(&mut self, symbol: &str) -> Result<Option<f64>, ExchangeError> {
+        self.inner.raise_risk_limit(symbol).await
+    }
+
+    async fn set_leverage(&mut self, symbol: &str, leverage: f64) -> Result<(), ExchangeError> {
+        self.inner.set_leverage(symbol, leverage).await
+    }
+
+    async fn set_margin_mode(
+        &mut self, symbol: &str, mode: MarginMode,
+    ) -> Result<(), ExchangeError> {
+        self.inner.set_margin_mode(symbol, mode).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::time::Instant;
+
+    #[tokio::test(start_paused = true)]
+    async fn test_retries_only_retryable_errors_with_backoff() {
+        let config = RetryConfig {
+            max_attempts: 4,
+            base_delay_seconds: 1.0,
+            max_delay_seconds: 3.0,
+        };
+        let start = Instant::now();
+        let mut attempts = 0;
+        let result = retry(&config, "test", || {
+            attempts += 1;
+            let result = if attempts < 3 {
+                Err(ExchangeError::Network("connection reset".to_string()))
+            } else {
+                Ok(attempts)
+            };
+            async move { result }
+        })
+        .await;
+        assert_eq!(result, Ok(3));
+        assert_eq!(start.elapsed(), Duration::from_secs(1 + 2));
+
+        // the delay is capped, and the last error returned once attempts run out
+        let start = Instant::now();
+        let result: Result<(), _> = retry(&config, "test", || async {
+            Err(ExchangeError::ExchangeMaintenance("down".to_string()))
+        })
+        .await;
+        assert!(matches!(result, Err(ExchangeError::ExchangeMaintenance(_))));
+        assert_eq!(start.elapsed(), Duration::from_secs(1 + 2 + 3));
+
+        let mut attempts = 0;
+        let result: Result<(), _> = retry(&config, "test", || {
+            attempts += 1;
+            async { Err(ExchangeError::InvalidOrder("Qty invalid".to_string())) }
+        })
+        .await;
+        assert!(result.is_err());
+        assert_eq!(attempts, 1);
+    }
+}
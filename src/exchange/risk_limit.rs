@@ -0,0 +1,158 @@
+use super::Exchange;
+use crate::types::{BotSideConfig, Order};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tracing::{error, warn};
+
+/// Maximum position values per symbol, learned from risk limit rejections that could not
+/// be resolved by raising the risk limit tier.
+#[derive(Clone, Default)]
+pub struct RiskLimitCaps {
+    caps: Arc<Mutex<HashMap<String, f64>>>,
+}
+
+impl RiskLimitCaps {
+    pub fn get(&self, symbol: &str) -> Option<f64> {
+        self.caps.lock().unwrap().get(symbol).copied()
+    }
+
+    pub fn set(&self, symbol: &str, max_position_value: f64) {
+        self.caps
+            .lock()
+            .unwrap()
+            .insert(symbol.to_string(), max_position_value);
+    }
+
+    pub fn remove(&self, symbol: &str) {
+        self.caps.lock().unwrap().remove(symbol);
+    }
+}
+
+/// Reacts to an order rejected for exceeding the risk limit: raises the risk limit tier
+/// where the exchange allows it and retries the order once, otherwise caps the symbol's
+/// grid at the current limit. Returns true if the retried order was accepted.
+pub async fn handle_risk_limit_rejection(
+    exchange: &mut Box<dyn Exchange>, order: &Order, caps: &RiskLimitCaps,
+) -> bool {
+    let symbol = &order.symbol;
+    match exchange.raise_risk_limit(symbol).await {
+        Ok(Some(max_position_value)) => {
+            warn!(
+                "[{}] Order rejected by risk limit; raised risk limit to max position value {}",
+                symbol, max_position_value
+            );
+            caps.remove(symbol);
+            return match exchange.place_order(order).await {
+                Ok(()) => true,
+                Err(e) => {
+                    error!("[{}] Retry after raising risk limit failed: {}", symbol, e);
+                    false
+                }
+            };
+        }
+        Ok(None) => {}
+        Err(e) => warn!("[{}] Failed to raise risk limit: {}", symbol, e),
+    }
+    match exchange.fetch_risk_limit(symbol).await {
+        Ok(Some(max_position_value)) => {
+            warn!(
+                "[{}] Order rejected by risk limit; capping grid at max position value {}",
+                symbol, max_position_value
+            );
+            caps.set(symbol, max_position_value);
+        }
+        Ok(None) => error!(
+            "[{}] Order rejected by risk limit, which can neither be raised nor read",
+            symbol
+        ),
+        Err(e) => error!("[{}] Failed to fetch risk limit: {}", symbol, e),
+    }
+    false
+}
+
+/// Lowers `total_wallet_exposure_limit`, and `close_wallet_exposure_limit` if set, so the
+/// position stays 5% below `max_position_value`.
+pub fn cap_exposure_limit(
+    bot_params: &BotSideConfig, max_position_value: Option<f64>, balance: f64,
+) -> BotSideConfig {
+    let mut capped = bot_params.clone();
+    if let Some(max_position_value) = max_position_value {
+        if balance > 0.0 {
+            capped.total_wallet_exposure_limit = capped
+                .total_wallet_exposure_limit
+                .min(max_position_value * 0.95 / balance);
+            if capped.close_wallet_exposure_limit > 0.0 {
+                capped.close_wallet_exposure_limit = capped
+                    .close_wallet_exposure_limit
+                    .min(max_position_value * 0.95 / balance);
+            }
+        }
+    }
+    capped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::exchange::simulated::SimulatedExchange;
+
+    #[tokio::test]
+    async fn test_risk_limit_rejection_caps_grid() {
+        let simulated = SimulatedExchange::new(1000.0);
+        simulated.update_price(100.0, 100.0, 100.0);
+        simulated.set_risk_limit(Some(500.0));
+        let mut exchange = simulated.clone_box();
+        let order = Order {
+            id: String::new(),
+            symbol: "BTCUSDT".to_string(),
+            side: "Buy".to_string(),
+            position_side: "Long".to_string(),
+            qty: 6.0,
+            price: 100.0,
+            reduce_only: false,
+            custom_id: String::new(),
+            client_order_id: String::new(),
+            time_in_force: String::new(),
+        };
+        let err = exchange.place_order(&order).await.unwrap_err();
+        assert!(exchange.is_risk_limit_error(&err.to_string()));
+
+        let caps = RiskLimitCaps::default();
+        handle_risk_limit_rejection(&mut exchange, &order, &caps).await;
+        assert_eq!(caps.get("BTCUSDT"), Some(500.0));
+
+        let bot_params = BotSideConfig {
+            total_wallet_exposure_limit: 1.0,
+            ..Default::default()
+        };
+        let capped = cap_exposure_limit(&bot_params, caps.get("BTCUSDT"), 1000.0);
+        assert_eq!(capped.total_wallet_exposure_limit, 0.475);
+        let uncapped = cap_exposure_limit(&bot_params, None, 1000.0);
+        assert_eq!(uncapped.total_wallet_exposure_limit, 1.0);
+    }
+
+    #[tokio::test]
+    async fn test_risk_limit_rejection_raises_tier() {
+        let simulated = SimulatedExchange::new(1000.0);
+        simulated.update_price(100.0, 100.0, 100.0);
+        simulated.set_risk_limit(Some(500.0));
+        simulated.set_risk_limit_tiers(vec![500.0, 1000.0, 2000.0]);
+        let mut exchange = simulated.clone_box();
+        let order = Order {
+            symbol: "BTCUSDT".to_string(),
+            side: "Buy".to_string(),
+            position_side: "Long".to_string(),
+            qty: 6.0,
+            price: 100.0,
+            ..Default::default()
+        };
+        let caps = RiskLimitCaps::default();
+        caps.set("BTCUSDT", 500.0);
+        let err = exchange.place_order(&order).await.unwrap_err();
+        assert!(exchange.is_risk_limit_error(&err.to_string()));
+
+        assert!(handle_risk_limit_rejection(&mut exchange, &order, &caps).await);
+        assert_eq!(simulated.snapshot().risk_limit, Some(1000.0));
+        assert_eq!(caps.get("BTCUSDT"), None);
+    }
+}
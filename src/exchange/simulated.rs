@@ -1,26 +1,595 @@
 use async_trait::async_trait;
-use std::collections::HashMap;
-use crate::types::{Market, Ticker, Order, Position, OrderBook, ExchangeParams};
-use super::{Exchange, SendSyncError};
-use tracing::info;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use crate::grid::utils::{calc_new_psize_pprice, calc_pnl_long, calc_pnl_short, qty_to_cost};
+use crate::types::{
+    Market, Ticker, Order, OrderType, Position, PositionPair, PositionCosts, PositionPnl,
+    OrderBook, ExchangeParams, FundingPayment, SlippageModel,
+};
+use crate::risk_governor::calc_unrealized_pnl;
+use crate::snapshot::SymbolSnapshot;
+use super::{timeframe_ms, Exchange, ExchangeError};
+use crate::data::Candle;
+use tracing::{info, warn};
 
-#[derive(Clone)]
-pub struct SimulatedExchange {
+/// An executed order, recorded in fill order.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Fill {
+    /// Number of price updates before the one that filled the order.
+    pub step: u64,
+    /// Simulated clock time of the price update that filled the order.
+    pub timestamp_ms: i64,
+    pub side: String,
+    pub qty: f64,
+    /// Execution price: the order price, moved against the order by `taker_slippage` and
+    /// the slippage model on taker fills.
+    pub price: f64,
+    pub order_price: f64,
+    pub order_id: String,
+    pub custom_id: String,
+    pub fee: f64,
+    /// Whether the order was marketable when placed and paid the taker fee.
+    pub taker: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct SimulatedState {
     pub balance: f64,
     pub position: Position,
+    /// Fees accrued by the open position; reset when it is closed or flipped.
+    pub position_costs: PositionCosts,
+    pub maker_fee: f64,
+    pub taker_fee: f64,
+    /// Adverse price move of taker fills, as a fraction of the order price.
+    pub taker_slippage: f64,
+    /// Slippage of taker fills on top of `taker_slippage`, depending on the candle.
+    pub slippage_model: SlippageModel,
+    /// Cap on the slippage model's fraction; 0 for none.
+    pub max_slippage: f64,
+    /// Whether unstuck closes are placed as market orders, see `set_market_unstuck_closes`.
+    pub market_unstuck_closes: bool,
+    /// Orders resting on the book until the next price update.
     pub orders: Vec<Order>,
+    /// Ids of resting orders priced through the last price when placed, which would have
+    /// filled immediately as takers.
+    taker_order_ids: HashSet<String>,
+    /// Ids of resting market orders, which fill on the next price update whatever its range.
+    market_order_ids: HashSet<String>,
+    /// Round trip until a placed order rests on the book, and until a cancel takes effect,
+    /// in ms of the simulated clock. See `set_latency`.
+    pub ack_delay_ms: i64,
+    pub cancel_delay_ms: i64,
+    /// Placed orders not yet acknowledged, with the time they reach the book.
+    pending_orders: Vec<(i64, Order)>,
+    /// Cancels in flight, as (time they take effect, order id).
+    pending_cancels: Vec<(i64, String)>,
+    /// Orders that filled while a cancel of theirs was in flight.
+    pub cancel_races: Vec<String>,
+    /// High, low and volume of the candle being applied, for the slippage model.
+    candle: (f64, f64, f64),
+    /// The last candle applied, stamped as the 1m candle closing at the time it was
+    /// applied, for `fetch_ohlcv`.
+    last_candle: Option<Candle>,
+    pub last_price: f64,
+    pub exchange_params: ExchangeParams,
+    pub rejected_orders: Vec<(Order, String)>,
+    /// Maximum position value accepted for new entries; `None` for no risk limit.
+    pub risk_limit: Option<f64>,
+    /// Maximum position values of the higher tiers `raise_risk_limit` can move to.
+    pub risk_limit_tiers: Vec<f64>,
+    /// Leverage last set through `set_leverage`; 0 until then.
+    pub leverage: f64,
+    pub fills: Vec<Fill>,
+    /// Funding settled on the position, see `settle_funding`.
+    pub funding_payments: Vec<FundingPayment>,
+    /// Number of price updates so far.
+    pub step: u64,
+    /// Simulated clock, in ms. Only moves when set or advanced.
+    pub now_ms: i64,
+    /// Candles to apply once the clock reaches their time, as (time, high, low, close),
+    /// in the order they were scheduled.
+    scheduled_candles: Vec<(i64, f64, f64, f64)>,
+    next_order_id: u64,
+}
+
+/// Clock step of `advance_candles`.
+pub const CANDLE_INTERVAL_MS: i64 = 60_000;
+
+/// An in-memory exchange for backtests and tests.
+///
+/// Placed orders rest on the book until the next call to `update_price`, which fills
+/// every order crossed by the candle's high/low at the order price and expires the rest,
+/// mirroring how the strategy recomputes its orders every step. The state is shared
+/// between clones, so a bot holding several `clone_box` handles sees one account.
+///
+/// The exchange runs on its own clock, reported through `Exchange::now_ms`, so tests can
+/// move time forward instantly instead of sleeping.
+#[derive(Clone)]
+pub struct SimulatedExchange {
+    state: Arc<Mutex<SimulatedState>>,
 }
 
 impl SimulatedExchange {
     pub fn new(starting_balance: f64) -> Self {
         Self {
-            balance: starting_balance,
-            position: Position {
-                size: 0.0,
-                price: 0.0,
-            },
-            orders: Vec::new(),
+            state: Arc::new(Mutex::new(SimulatedState {
+                balance: starting_balance,
+                position: Position {
+                    size: 0.0,
+                    price: 0.0,
+                },
+                position_costs: PositionCosts::default(),
+                maker_fee: 0.0,
+                taker_fee: 0.0,
+                taker_slippage: 0.0,
+                slippage_model: SlippageModel::None,
+                max_slippage: 0.0,
+                market_unstuck_closes: false,
+                orders: Vec::new(),
+                taker_order_ids: HashSet::new(),
+                market_order_ids: HashSet::new(),
+                ack_delay_ms: 0,
+                cancel_delay_ms: 0,
+                pending_orders: Vec::new(),
+                pending_cancels: Vec::new(),
+                cancel_races: Vec::new(),
+                candle: (0.0, 0.0, 0.0),
+                last_candle: None,
+                last_price: 0.0,
+                exchange_params: ExchangeParams {
+                    qty_step: 0.001,
+                    price_step: 0.01,
+                    min_qty: 0.001,
+                    min_cost: 1.0,
+                    c_mult: 1.0,
+                    inverse: false,
+                },
+                rejected_orders: Vec::new(),
+                risk_limit: None,
+                risk_limit_tiers: Vec::new(),
+                leverage: 0.0,
+                fills: Vec::new(),
+                funding_payments: Vec::new(),
+                step: 0,
+                now_ms: 0,
+                scheduled_candles: Vec::new(),
+                next_order_id: 0,
+            })),
+        }
+    }
+
+    /// Sets the fee charged, as a fraction of cost, on every fill.
+    pub fn set_maker_fee(&self, maker_fee: f64) {
+        self.state.lock().unwrap().maker_fee = maker_fee;
+    }
+
+    /// Sets the fee charged, as a fraction of cost, on fills of orders that were
+    /// marketable when placed.
+    pub fn set_taker_fee(&self, taker_fee: f64) {
+        self.state.lock().unwrap().taker_fee = taker_fee;
+    }
+
+    /// Sets how far taker fills execute from their order price, as a fraction of it:
+    /// buys above it and sells below it. Negative values model price improvement.
+    pub fn set_taker_slippage(&self, taker_slippage: f64) {
+        self.state.lock().unwrap().taker_slippage = taker_slippage;
+    }
+
+    /// Sets the slippage of taker fills that depends on the candle they fill in, added to
+    /// `taker_slippage` and capped at `max_slippage` (a fraction; 0 for no cap).
+    pub fn set_slippage_model(&self, slippage_model: SlippageModel, max_slippage: f64) {
+        let mut state = self.state.lock().unwrap();
+        state.slippage_model = slippage_model;
+        state.max_slippage = max_slippage;
+    }
+
+    /// Places unstuck closes as market orders: they fill on the next price update at the
+    /// last price when placed, plus slippage, as takers, whether or not the candle reaches
+    /// their limit price.
+    pub fn set_market_unstuck_closes(&self, market_unstuck_closes: bool) {
+        self.state.lock().unwrap().market_unstuck_closes = market_unstuck_closes;
+    }
+
+    /// Delays placed orders by `ack_delay_ms` before they rest on the book, are returned by
+    /// `fetch_open_orders` and can fill, and cancels by `cancel_delay_ms` before they take
+    /// effect, leaving the order free to fill meanwhile, as during live round trips.
+    /// Delays run on the simulated clock; 0 applies requests immediately.
+    pub fn set_latency(&self, ack_delay_ms: i64, cancel_delay_ms: i64) {
+        let mut state = self.state.lock().unwrap();
+        state.ack_delay_ms = ack_delay_ms.max(0);
+        state.cancel_delay_ms = cancel_delay_ms.max(0);
+    }
+
+    /// Sets the qty/price steps and minimums orders are validated against.
+    pub fn set_exchange_params(&self, exchange_params: ExchangeParams) {
+        self.state.lock().unwrap().exchange_params = exchange_params;
+    }
+
+    pub fn set_risk_limit(&self, max_position_value: Option<f64>) {
+        self.state.lock().unwrap().risk_limit = max_position_value;
+    }
+
+    pub fn set_risk_limit_tiers(&self, max_position_values: Vec<f64>) {
+        self.state.lock().unwrap().risk_limit_tiers = max_position_values;
+    }
+
+    /// The account balance, without copying the rest of the state.
+    pub fn balance(&self) -> f64 {
+        self.state.lock().unwrap().balance
+    }
+
+    /// Settles one funding interval at `rate` on the open position, valued at the last
+    /// price: longs pay positive rates and shorts receive them. Returns the amount paid,
+    /// negative when received.
+    pub fn settle_funding(&self, symbol: &str, rate: f64) -> f64 {
+        let mut state = self.state.lock().unwrap();
+        if state.position.size == 0.0 || rate == 0.0 {
+            return 0.0;
+        }
+        let params = &state.exchange_params;
+        let amount = state.position.size.signum()
+            * qty_to_cost(
+                state.position.size.abs(),
+                state.last_price,
+                params.inverse,
+                params.c_mult,
+            )
+            * rate;
+        state.balance -= amount;
+        state.position_costs.funding_paid += amount;
+        let timestamp_ms = state.now_ms;
+        state.funding_payments.push(FundingPayment {
+            symbol: symbol.to_string(),
+            timestamp_ms,
+            amount,
+        });
+        amount
+    }
+
+    /// Returns a copy of the current account state.
+    pub fn snapshot(&self) -> SimulatedState {
+        self.state.lock().unwrap().clone()
+    }
+
+    /// The fills after the first `n`, without copying the rest of the state.
+    pub fn fills_since(&self, n: usize) -> Vec<Fill> {
+        let state = self.state.lock().unwrap();
+        state.fills.get(n..).unwrap_or_default().to_vec()
+    }
+
+    /// Loads the state of `symbol` from an exported account snapshot, replacing the
+    /// position, market and resting orders. Orders keep their exchange ids.
+    pub fn load_snapshot(&self, symbol: &str, snapshot: &SymbolSnapshot) {
+        let mut state = self.state.lock().unwrap();
+        state.position = snapshot.position;
+        state.position_costs = snapshot.position_costs;
+        state.last_price = snapshot.last_price;
+        state.exchange_params = snapshot.exchange_params.clone();
+        state.orders.retain(|o| o.symbol != symbol);
+        state
+            .orders
+            .extend(snapshot.open_orders.iter().map(|order| Order {
+                symbol: symbol.to_string(),
+                ..order.clone()
+            }));
+    }
+
+    /// Advances the market by one candle: fills resting orders crossed by `high`/`low`
+    /// and expires the others. The clock is left unchanged.
+    pub fn update_price(&self, high: f64, low: f64, close: f64) {
+        self.update_candle(high, low, close, 0.0);
+    }
+
+    /// `update_price` with the candle's base volume, for the "volume" slippage model.
+    pub fn update_candle(&self, high: f64, low: f64, close: f64, volume: f64) {
+        self.state
+            .lock()
+            .unwrap()
+            .apply_candle(high, low, close, volume);
+    }
+
+    /// Applies an order book top from a live market: fills resting buys the best ask has
+    /// reached and sells the best bid has reached, at the order price, and keeps the
+    /// others resting, unlike `update_price`. The last price moves to the mid.
+    pub fn update_book(&self, best_bid: f64, best_ask: f64) {
+        let mut state = self.state.lock().unwrap();
+        state.apply_requests();
+        let orders = std::mem::take(&mut state.orders);
+        state.candle = (best_ask, best_bid, 0.0);
+        for order in orders {
+            let crossed = state.market_order_ids.contains(&order.id)
+                || if order.side == "Buy" {
+                    best_ask <= order.price
+                } else {
+                    best_bid >= order.price
+                };
+            if crossed {
+                state.fill(&order);
+            } else {
+                state.orders.push(order);
+            }
+        }
+        // orders still resting were not marketable after all and fill as makers later
+        state.taker_order_ids.clear();
+        state.market_order_ids.clear();
+        state.last_price = (best_bid + best_ask) / 2.0;
+        state.step += 1;
+    }
+
+    pub fn now_ms(&self) -> i64 {
+        self.state.lock().unwrap().now_ms
+    }
+
+    /// Moves the clock to `now_ms`, applying the candles scheduled up to then. The clock
+    /// never moves backwards.
+    pub fn set_time_ms(&self, now_ms: i64) {
+        let mut state = self.state.lock().unwrap();
+        loop {
+            let due = state
+                .scheduled_candles
+                .iter()
+                .enumerate()
+                .filter(|(_, candle)| candle.0 <= now_ms)
+                .min_by_key(|(i, candle)| (candle.0, *i))
+                .map(|(i, _)| i);
+            let Some(i) = due else {
+                break;
+            };
+            let (at_ms, high, low, close) = state.scheduled_candles.remove(i);
+            state.now_ms = state.now_ms.max(at_ms);
+            state.apply_candle(high, low, close, 0.0);
+        }
+        state.now_ms = state.now_ms.max(now_ms);
+        state.apply_requests();
+    }
+
+    pub fn advance_ms(&self, ms: i64) {
+        self.set_time_ms(self.now_ms() + ms);
+    }
+
+    pub fn advance_seconds(&self, seconds: f64) {
+        self.advance_ms((seconds * 1000.0) as i64);
+    }
+
+    /// Advances the clock by `n` candle intervals of `CANDLE_INTERVAL_MS`.
+    pub fn advance_candles(&self, n: u32) {
+        self.advance_ms(n as i64 * CANDLE_INTERVAL_MS);
+    }
+
+    /// Schedules a candle to be applied once the clock reaches `at_ms`. Candles with the
+    /// same time are applied in the order they were scheduled.
+    pub fn schedule_candle(&self, at_ms: i64, high: f64, low: f64, close: f64) {
+        self.state
+            .lock()
+            .unwrap()
+            .scheduled_candles
+            .push((at_ms, high, low, close));
+    }
+}
+
+impl SimulatedState {
+    /// Puts acknowledged orders on the book and applies the cancels that have taken
+    /// effect by the current time.
+    fn apply_requests(&mut self) {
+        let now_ms = self.now_ms;
+        let (due, in_flight): (Vec<_>, Vec<_>) = std::mem::take(&mut self.pending_cancels)
+            .into_iter()
+            .partition(|(at_ms, _)| *at_ms <= now_ms);
+        self.pending_cancels = in_flight;
+        for (_, order_id) in due {
+            self.orders.retain(|o| o.id != order_id);
+            self.pending_orders.retain(|(_, o)| o.id != order_id);
+        }
+        let (due, in_flight): (Vec<_>, Vec<_>) = std::mem::take(&mut self.pending_orders)
+            .into_iter()
+            .partition(|(at_ms, _)| *at_ms <= now_ms);
+        self.pending_orders = in_flight;
+        for (_, order) in due {
+            self.rest(order);
+        }
+    }
+
+    /// Puts an acknowledged order on the book, marking it a taker if it is priced through
+    /// the last price.
+    fn rest(&mut self, mut order: Order) {
+        let unstuck = matches!(
+            OrderType::from_str(&order.custom_id),
+            Some(OrderType::CloseUnstuckLong | OrderType::CloseUnstuckShort)
+        );
+        if self.market_unstuck_closes && unstuck && self.last_price > 0.0 {
+            order.price = self.last_price;
+            self.taker_order_ids.insert(order.id.clone());
+            self.market_order_ids.insert(order.id.clone());
+            self.orders.push(order);
+            return;
+        }
+        // the book is a zero spread at the last price, so only orders priced through it
+        // would have crossed the spread, e.g. trailing and unstuck closes chasing the market
+        let marketable = if order.side == "Buy" {
+            order.price > self.last_price
+        } else {
+            order.price < self.last_price
+        };
+        if self.last_price > 0.0 && marketable {
+            self.taker_order_ids.insert(order.id.clone());
+        }
+        self.orders.push(order);
+    }
+
+    fn apply_candle(&mut self, high: f64, low: f64, close: f64, volume: f64) {
+        self.apply_requests();
+        let orders = std::mem::take(&mut self.orders);
+        self.candle = (high, low, volume);
+        for order in orders {
+            let crossed = self.market_order_ids.contains(&order.id)
+                || if order.side == "Buy" {
+                    low < order.price
+                } else {
+                    high > order.price
+                };
+            if crossed {
+                self.fill(&order);
+            }
+        }
+        self.taker_order_ids.clear();
+        self.market_order_ids.clear();
+        self.last_candle = Some(Candle {
+            timestamp: (self.now_ms - CANDLE_INTERVAL_MS).max(0) as u64,
+            open: self.last_price,
+            high,
+            low,
+            close,
+            volume,
+        });
+        self.last_price = close;
+        self.step += 1;
+    }
+
+    fn fill(&mut self, order: &Order) {
+        let cancels_in_flight = self.pending_cancels.len();
+        self.pending_cancels
+            .retain(|(_, order_id)| *order_id != order.id);
+        if self.pending_cancels.len() < cancels_in_flight {
+            self.cancel_races.push(order.id.clone());
+        }
+        let qty = if order.side == "Buy" {
+            order.qty
+        } else {
+            -order.qty
+        };
+        let params = &self.exchange_params;
+        let taker = self.taker_order_ids.contains(&order.id);
+        let fee_rate = if taker {
+            self.taker_fee
+        } else {
+            self.maker_fee
+        };
+        let price = if taker {
+            let (high, low, volume) = self.candle;
+            let mut model_slippage =
+                self.slippage_model
+                    .slippage(order.qty, order.price, high, low, volume);
+            if self.max_slippage > 0.0 {
+                model_slippage = model_slippage.min(self.max_slippage);
+            }
+            order.price * (1.0 + (self.taker_slippage + model_slippage) * qty.signum())
+        } else {
+            order.price
+        };
+        let fee = qty_to_cost(order.qty, price, params.inverse, params.c_mult) * fee_rate;
+        self.balance -= fee;
+        let position = self.position;
+        if position.size != 0.0 && position.size.signum() != qty.signum() {
+            let closed_qty = qty.abs().min(position.size.abs());
+            let pnl = if position.size > 0.0 {
+                calc_pnl_long(
+                    position.price,
+                    price,
+                    closed_qty,
+                    params.inverse,
+                    params.c_mult,
+                )
+            } else {
+                calc_pnl_short(
+                    position.price,
+                    price,
+                    closed_qty,
+                    params.inverse,
+                    params.c_mult,
+                )
+            };
+            self.balance += pnl;
+            let remaining = position.size + qty;
+            self.position = if remaining.abs() < params.qty_step * 0.5 {
+                Position::default()
+            } else if remaining.signum() == position.size.signum() {
+                Position {
+                    size: remaining,
+                    price: position.price,
+                }
+            } else {
+                Position {
+                    size: remaining,
+                    price,
+                }
+            };
+            if self.position.size == 0.0 || self.position.size.signum() != position.size.signum() {
+                self.position_costs = PositionCosts::default();
+            }
+        } else {
+            let (size, position_price) =
+                calc_new_psize_pprice(position.size, position.price, qty, price, params.qty_step);
+            self.position = Position {
+                size,
+                price: position_price,
+            };
+        }
+        if self.position.size != 0.0 {
+            self.position_costs.fees_paid += fee;
+        }
+        self.fills.push(Fill {
+            step: self.step,
+            timestamp_ms: self.now_ms,
+            side: order.side.clone(),
+            qty: order.qty,
+            price,
+            order_price: order.price,
+            order_id: order.id.clone(),
+            custom_id: order.custom_id.clone(),
+            fee,
+            taker,
+        });
+    }
+
+    /// Checks an order against the exchange's qty/price steps and minimums.
+    fn validate(&self, order: &Order) -> Result<(), String> {
+        let params = &self.exchange_params;
+        let is_multiple = |value: f64, step: f64| {
+            let n = (value / step).round();
+            (n * step - value).abs() <= step * 1e-6
+        };
+        if !(order.qty > 0.0 && order.price > 0.0) {
+            return Err(format!(
+                "non-positive qty {} or price {}",
+                order.qty, order.price
+            ));
         }
+        if order.qty < params.min_qty * (1.0 - 1e-9) {
+            return Err(format!(
+                "qty {} below min_qty {}",
+                order.qty, params.min_qty
+            ));
+        }
+        if !order.reduce_only && order.qty * order.price * params.c_mult < params.min_cost * 0.999 {
+            return Err(format!(
+                "cost {} below min_cost {}",
+                order.qty * order.price * params.c_mult,
+                params.min_cost
+            ));
+        }
+        if !is_multiple(order.qty, params.qty_step) {
+            return Err(format!(
+                "qty {} not a multiple of {}",
+                order.qty, params.qty_step
+            ));
+        }
+        if !is_multiple(order.price, params.price_step) {
+            return Err(format!(
+                "price {} not a multiple of {}",
+                order.price, params.price_step
+            ));
+        }
+        if let Some(risk_limit) = self.risk_limit {
+            let position_value =
+                (self.position.size.abs() + order.qty) * order.price * params.c_mult;
+            if !order.reduce_only && position_value > risk_limit {
+                return Err(format!(
+                    "position value {} would exceed risk limit {}",
+                    position_value, risk_limit
+                ));
+            }
+        }
+        Ok(())
     }
 }
 
@@ -30,77 +599,453 @@ impl Exchange for SimulatedExchange {
         Box::new(self.clone())
     }
 
-    async fn load_markets(&self) -> Result<HashMap<String, Market>, SendSyncError> {
+    async fn load_markets(&self) -> Result<HashMap<String, Market>, ExchangeError> {
         unimplemented!()
     }
 
     async fn fetch_tickers(
         &self, _symbols: &[String],
-    ) -> Result<HashMap<String, Ticker>, SendSyncError> {
+    ) -> Result<HashMap<String, Ticker>, ExchangeError> {
         unimplemented!()
     }
 
-    async fn fetch_ticker(&self, _symbol: &str) -> Result<f64, SendSyncError> {
-        unimplemented!()
+    async fn fetch_ticker(&self, _symbol: &str) -> Result<f64, ExchangeError> {
+        Ok(self.state.lock().unwrap().last_price)
     }
 
-    async fn fetch_order_book(&self, _symbol: &str) -> Result<OrderBook, SendSyncError> {
-        unimplemented!()
+    async fn fetch_last_close(&self, _symbol: &str) -> Result<Option<f64>, ExchangeError> {
+        Ok(Some(self.state.lock().unwrap().last_price))
+    }
+
+    /// Only the last candle applied is kept.
+    async fn fetch_ohlcv(
+        &self, _symbol: &str, timeframe: &str, since_ms: Option<i64>, limit: usize,
+    ) -> Result<Vec<Candle>, ExchangeError> {
+        if timeframe_ms(timeframe)? != CANDLE_INTERVAL_MS {
+            return Err(format!("Simulated candles are 1m, not {}", timeframe).into());
+        }
+        let state = self.state.lock().unwrap();
+        Ok(state
+            .last_candle
+            .filter(|candle| since_ms.is_none_or(|since| candle.timestamp as i64 >= since))
+            .into_iter()
+            .take(limit)
+            .collect())
+    }
+
+    async fn fetch_order_book(&self, _symbol: &str) -> Result<OrderBook, ExchangeError> {
+        // zero spread book at the last close, as assumed by the backtester
+        let price = self.state.lock().unwrap().last_price;
+        Ok(OrderBook {
+            bids: vec![[price, 1.0]],
+            asks: vec![[price, 1.0]],
+        })
     }
 
-    async fn fetch_balance(&self) -> Result<f64, SendSyncError> {
-        Ok(self.balance)
+    async fn fetch_balance(&self) -> Result<f64, ExchangeError> {
+        Ok(self.state.lock().unwrap().balance)
     }
 
-    async fn place_order(&mut self, order: &Order) -> Result<(), SendSyncError> {
+    async fn place_order(&mut self, order: &Order) -> Result<(), ExchangeError> {
         info!("Placing order: {:?}", order);
-        let order_cost = order.qty * order.price;
-        if self.balance >= order_cost {
-            self.balance -= order_cost;
-            let mut new_order = order.clone();
-            new_order.id = self.orders.len().to_string();
-            self.orders.push(new_order);
-
-            let qty = if order.side == "Buy" {
-                order.qty
-            } else {
-                -order.qty
-            };
-            let new_size = self.position.size + qty;
-            if new_size == 0.0 {
-                self.position.price = 0.0;
-            } else {
-                self.position.price =
-                    (self.position.size * self.position.price + qty * order.price) / new_size;
-            }
-            self.position.size = new_size;
+        let mut state = self.state.lock().unwrap();
+        if let Err(reason) = state.validate(order) {
+            warn!("Rejecting order {:?}: {}", order, reason);
+            state.rejected_orders.push((order.clone(), reason.clone()));
+            return Err(reason.into());
+        }
+        let mut new_order = order.clone();
+        new_order.id = state.next_order_id.to_string();
+        state.next_order_id += 1;
+        if state.ack_delay_ms > 0 {
+            let at_ms = state.now_ms + state.ack_delay_ms;
+            state.pending_orders.push((at_ms, new_order));
+        } else {
+            state.rest(new_order);
         }
         Ok(())
     }
 
-    async fn cancel_order(&mut self, order_id: &str) -> Result<(), SendSyncError> {
-        info!("Canceling order: {}", order_id);
-        if let Some(index) = self.orders.iter().position(|o| o.id == order_id) {
-            let order = &self.orders[index];
-            let order_cost = order.qty * order.price;
-            self.balance += order_cost;
-            self.orders.remove(index);
+    async fn cancel_order(&mut self, symbol: &str, order_id: &str) -> Result<(), ExchangeError> {
+        info!("[{}] Canceling order: {}", symbol, order_id);
+        let mut state = self.state.lock().unwrap();
+        if state.cancel_delay_ms > 0 {
+            let at_ms = state.now_ms + state.cancel_delay_ms;
+            state.pending_cancels.push((at_ms, order_id.to_string()));
+            return Ok(());
         }
+        state
+            .orders
+            .retain(|o| o.symbol != symbol || o.id != order_id);
+        state
+            .pending_orders
+            .retain(|(_, o)| o.symbol != symbol || o.id != order_id);
         Ok(())
     }
 
-    async fn fetch_position(&self, _symbol: &str) -> Result<Position, SendSyncError> {
-        Ok(self.position.clone())
+    async fn fetch_open_orders(&self, symbol: &str) -> Result<Vec<Order>, ExchangeError> {
+        let state = self.state.lock().unwrap();
+        Ok(state
+            .orders
+            .iter()
+            .filter(|o| o.symbol == symbol)
+            .cloned()
+            .collect())
     }
 
-    async fn fetch_exchange_params(&self, _symbol: &str) -> Result<ExchangeParams, SendSyncError> {
-        Ok(ExchangeParams {
-            qty_step: 0.001,
-            price_step: 0.01,
-            min_qty: 0.001,
-            min_cost: 1.0,
-            c_mult: 1.0,
-            inverse: false,
-        })
+    async fn fetch_positions(&self, _symbol: &str) -> Result<PositionPair, ExchangeError> {
+        Ok(PositionPair::from_net(self.state.lock().unwrap().position))
+    }
+
+    async fn fetch_position_costs(&self, _symbol: &str) -> Result<PositionCosts, ExchangeError> {
+        Ok(self.state.lock().unwrap().position_costs)
+    }
+
+    async fn set_leverage(&mut self, _symbol: &str, leverage: f64) -> Result<(), ExchangeError> {
+        self.state.lock().unwrap().leverage = leverage;
+        Ok(())
+    }
+
+    /// The position's PnL at the last price, which stands in for the mark price.
+    async fn fetch_position_pnl(
+        &self, _symbol: &str,
+    ) -> Result<Option<PositionPnl>, ExchangeError> {
+        let state = self.state.lock().unwrap();
+        if state.position.size == 0.0 || state.last_price <= 0.0 {
+            return Ok(None);
+        }
+        Ok(Some(PositionPnl {
+            size: state.position.size,
+            mark_price: state.last_price,
+            unrealized_pnl: calc_unrealized_pnl(
+                &state.position,
+                state.last_price,
+                &state.exchange_params,
+            ),
+        }))
+    }
+
+    async fn fetch_funding_payments(
+        &self, symbol: &str, start_ms: i64, end_ms: i64,
+    ) -> Result<Vec<FundingPayment>, ExchangeError> {
+        Ok(self
+            .state
+            .lock()
+            .unwrap()
+            .funding_payments
+            .iter()
+            .filter(|p| {
+                p.symbol == symbol && p.timestamp_ms >= start_ms && p.timestamp_ms <= end_ms
+            })
+            .cloned()
+            .collect())
+    }
+
+    async fn fetch_exchange_params(&self, _symbol: &str) -> Result<ExchangeParams, ExchangeError> {
+        Ok(self.state.lock().unwrap().exchange_params.clone())
+    }
+
+    fn is_precision_error(&self, error: &str) -> bool {
+        error.contains("not a multiple of") || error.contains("below min_")
+    }
+
+    fn now_ms(&self) -> i64 {
+        self.state.lock().unwrap().now_ms
+    }
+
+    fn is_risk_limit_error(&self, error: &str) -> bool {
+        error.contains("exceed risk limit")
+    }
+
+    async fn fetch_risk_limit(&self, _symbol: &str) -> Result<Option<f64>, ExchangeError> {
+        Ok(self.state.lock().unwrap().risk_limit)
+    }
+
+    async fn raise_risk_limit(&mut self, _symbol: &str) -> Result<Option<f64>, ExchangeError> {
+        let mut state = self.state.lock().unwrap();
+        let Some(current) = state.risk_limit else {
+            return Ok(None);
+        };
+        let next_tier = state
+            .risk_limit_tiers
+            .iter()
+            .copied()
+            .filter(|value| *value > current)
+            .min_by(f64::total_cmp);
+        if next_tier.is_some() {
+            state.risk_limit = next_tier;
+        }
+        Ok(next_tier)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_clock_and_scheduled_candles() {
+        let simulated = SimulatedExchange::new(1000.0);
+        let mut exchange = simulated.clone_box();
+        simulated.set_time_ms(1_000_000);
+        simulated.update_price(100.0, 100.0, 100.0);
+        simulated.schedule_candle(1_000_000 + 2 * CANDLE_INTERVAL_MS, 101.0, 95.0, 96.0);
+        simulated.schedule_candle(1_000_000 + CANDLE_INTERVAL_MS, 100.0, 99.0, 99.5);
+        exchange
+            .place_order(&Order {
+                id: String::new(),
+                symbol: "BTCUSDT".to_string(),
+                side: "Buy".to_string(),
+                position_side: "Long".to_string(),
+                qty: 0.1,
+                price: 98.0,
+                reduce_only: false,
+                custom_id: String::new(),
+                client_order_id: String::new(),
+                time_in_force: String::new(),
+            })
+            .await
+            .unwrap();
+
+        simulated.advance_seconds(30.0);
+        assert_eq!(exchange.now_ms(), 1_030_000);
+        assert_eq!(simulated.snapshot().step, 1);
+
+        // the first scheduled candle expires the order, the second would have filled it
+        simulated.advance_candles(2);
+        let state = simulated.snapshot();
+        assert_eq!(state.now_ms, 1_030_000 + 2 * CANDLE_INTERVAL_MS);
+        assert_eq!(state.step, 3);
+        assert_eq!(state.last_price, 96.0);
+        assert!(state.fills.is_empty());
+
+        simulated.set_time_ms(0);
+        assert_eq!(simulated.now_ms(), state.now_ms);
+    }
+
+    #[tokio::test]
+    async fn test_fetch_ohlcv_returns_last_candle() {
+        let simulated = SimulatedExchange::new(1000.0);
+        assert!(simulated
+            .fetch_ohlcv("BTCUSDT", "1m", None, 10)
+            .await
+            .unwrap()
+            .is_empty());
+        simulated.set_time_ms(10 * CANDLE_INTERVAL_MS);
+        simulated.update_price(100.0, 100.0, 100.0);
+        simulated.update_candle(102.0, 99.0, 101.0, 5.0);
+        let candles = simulated
+            .fetch_ohlcv("BTCUSDT", "1m", None, 10)
+            .await
+            .unwrap();
+        assert_eq!(
+            candles,
+            vec![Candle {
+                timestamp: 9 * CANDLE_INTERVAL_MS as u64,
+                open: 100.0,
+                high: 102.0,
+                low: 99.0,
+                close: 101.0,
+                volume: 5.0,
+            }]
+        );
+        let since_ms = Some(10 * CANDLE_INTERVAL_MS);
+        assert!(simulated
+            .fetch_ohlcv("BTCUSDT", "1m", since_ms, 10)
+            .await
+            .unwrap()
+            .is_empty());
+        assert!(simulated
+            .fetch_ohlcv("BTCUSDT", "1h", None, 10)
+            .await
+            .is_err());
+
+        // kline rows of the exchanges, with numbers or strings
+        let row: Vec<serde_json::Value> =
+            serde_json::from_str(r#"[1700000000000, "100.5", "101", "99", "100", "12.5", 0]"#)
+                .unwrap();
+        let candle = crate::exchange::parse_candle_row(&row).unwrap();
+        assert_eq!(
+            (candle.timestamp, candle.high, candle.volume),
+            (1_700_000_000_000, 101.0, 12.5)
+        );
+        assert!(crate::exchange::parse_candle_row(&row[..3]).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_marketable_orders_pay_taker_fee() {
+        let simulated = SimulatedExchange::new(1000.0);
+        simulated.set_maker_fee(0.0002);
+        simulated.set_taker_fee(0.0005);
+        simulated.update_price(100.0, 100.0, 100.0);
+        let mut exchange = simulated.clone_box();
+        let order = |side: &str, price: f64| Order {
+            id: String::new(),
+            symbol: "BTCUSDT".to_string(),
+            side: side.to_string(),
+            position_side: "Long".to_string(),
+            qty: 1.0,
+            price,
+            reduce_only: false,
+            custom_id: String::new(),
+            client_order_id: String::new(),
+            time_in_force: String::new(),
+        };
+        exchange.place_order(&order("Buy", 99.0)).await.unwrap();
+        simulated.update_price(100.0, 98.0, 99.0);
+        // a close chasing the price through the book
+        exchange.place_order(&order("Sell", 98.0)).await.unwrap();
+        simulated.update_price(99.5, 98.5, 99.0);
+
+        let state = simulated.snapshot();
+        let takers: Vec<bool> = state.fills.iter().map(|f| f.taker).collect();
+        assert_eq!(takers, vec![false, true]);
+        let fees = 99.0 * 0.0002 + 98.0 * 0.0005;
+        assert!((state.balance - (1000.0 - 1.0 - fees)).abs() < 1e-9);
+    }
+
+    #[tokio::test]
+    async fn test_funding_settles_on_open_position() {
+        let simulated = SimulatedExchange::new(1000.0);
+        simulated.update_price(100.0, 100.0, 100.0);
+        assert_eq!(simulated.settle_funding("BTCUSDT", 0.0001), 0.0);
+        let mut exchange = simulated.clone_box();
+        let order = |side: &str| Order {
+            id: String::new(),
+            symbol: "BTCUSDT".to_string(),
+            side: side.to_string(),
+            position_side: "Long".to_string(),
+            qty: 2.0,
+            price: 100.0,
+            reduce_only: false,
+            custom_id: String::new(),
+            client_order_id: String::new(),
+            time_in_force: String::new(),
+        };
+        exchange.place_order(&order("Buy")).await.unwrap();
+        simulated.update_price(100.0, 99.0, 110.0);
+        simulated.set_time_ms(8 * 3_600_000);
+        // longs pay positive rates on the position's value at the last price
+        let paid = simulated.settle_funding("BTCUSDT", 0.0001);
+        assert!((paid - 2.0 * 110.0 * 0.0001).abs() < 1e-12);
+        let state = simulated.snapshot();
+        assert!((state.balance - (1000.0 - paid)).abs() < 1e-12);
+        assert_eq!(state.position_costs.funding_paid, paid);
+        let payments = exchange
+            .fetch_funding_payments("BTCUSDT", 0, 8 * 3_600_000)
+            .await
+            .unwrap();
+        assert_eq!(payments.len(), 1);
+        assert_eq!(payments[0].timestamp_ms, 8 * 3_600_000);
+
+        // and shorts receive them
+        exchange.place_order(&order("Sell")).await.unwrap();
+        exchange.place_order(&order("Sell")).await.unwrap();
+        simulated.update_price(101.0, 100.0, 100.0);
+        assert!((simulated.settle_funding("BTCUSDT", 0.0001) + 0.02).abs() < 1e-12);
+    }
+
+    #[tokio::test]
+    async fn test_market_unstuck_close_slips_with_the_candle() {
+        let simulated = SimulatedExchange::new(1000.0);
+        simulated.update_price(100.0, 100.0, 100.0);
+        let mut exchange = simulated.clone_box();
+        let order = |side: &str, price: f64, custom_id: &str| Order {
+            id: String::new(),
+            symbol: "BTCUSDT".to_string(),
+            side: side.to_string(),
+            position_side: "Long".to_string(),
+            qty: 1.0,
+            price,
+            reduce_only: side == "Sell",
+            custom_id: custom_id.to_string(),
+            client_order_id: String::new(),
+            time_in_force: String::new(),
+        };
+        exchange
+            .place_order(&order("Buy", 99.0, "entry_initial_normal_long"))
+            .await
+            .unwrap();
+        simulated.update_price(100.0, 98.0, 99.0);
+
+        // half the candle's range, capped at 100 bps
+        simulated.set_slippage_model(SlippageModel::Range(0.5), 0.01);
+        simulated.set_market_unstuck_closes(true);
+        // a limit close the candle never reaches, filled at market anyway
+        exchange
+            .place_order(&order("Sell", 105.0, "unstuck_close_long"))
+            .await
+            .unwrap();
+        simulated.update_candle(101.0, 97.0, 99.0, 10.0);
+
+        let state = simulated.snapshot();
+        assert_eq!(state.fills.len(), 2);
+        let close = &state.fills[1];
+        assert!(close.taker);
+        assert_eq!(close.order_price, 99.0);
+        assert!((close.price - 99.0 * 0.99).abs() < 1e-9);
+        assert_eq!(state.position.size, 0.0);
+
+        let model = SlippageModel::Volume(0.01);
+        assert_eq!(model.slippage(5.0, 100.0, 0.0, 0.0, 10.0), 0.005);
+        assert_eq!(model.slippage(50.0, 100.0, 0.0, 0.0, 10.0), 0.01);
+    }
+
+    #[tokio::test]
+    async fn test_orders_fill_during_the_cancel_round_trip() {
+        let simulated = SimulatedExchange::new(1000.0);
+        simulated.update_price(100.0, 100.0, 100.0);
+        simulated.set_latency(200, 500);
+        let mut exchange = simulated.clone_box();
+        let order = |price: f64| Order {
+            id: String::new(),
+            symbol: "BTCUSDT".to_string(),
+            side: "Buy".to_string(),
+            position_side: "Long".to_string(),
+            qty: 1.0,
+            price,
+            reduce_only: false,
+            custom_id: String::new(),
+            client_order_id: String::new(),
+            time_in_force: String::new(),
+        };
+        exchange.place_order(&order(99.0)).await.unwrap();
+        // not on the book until acknowledged
+        assert!(exchange
+            .fetch_open_orders("BTCUSDT")
+            .await
+            .unwrap()
+            .is_empty());
+        simulated.advance_ms(200);
+        let resting = exchange.fetch_open_orders("BTCUSDT").await.unwrap();
+        assert_eq!(resting.len(), 1);
+
+        // the price reaches the order before the cancel does
+        exchange
+            .cancel_order("BTCUSDT", &resting[0].id)
+            .await
+            .unwrap();
+        simulated.advance_ms(100);
+        simulated.update_price(100.0, 98.0, 98.5);
+        let state = simulated.snapshot();
+        assert_eq!(state.fills.len(), 1);
+        assert_eq!(state.cancel_races, vec![resting[0].id.clone()]);
+
+        // a cancel that arrives in time
+        exchange.place_order(&order(98.0)).await.unwrap();
+        simulated.advance_ms(200);
+        let resting = exchange.fetch_open_orders("BTCUSDT").await.unwrap();
+        exchange
+            .cancel_order("BTCUSDT", &resting[0].id)
+            .await
+            .unwrap();
+        simulated.advance_ms(500);
+        assert!(exchange
+            .fetch_open_orders("BTCUSDT")
+            .await
+            .unwrap()
+            .is_empty());
+        simulated.update_price(98.5, 97.0, 97.5);
+        assert_eq!(simulated.snapshot().fills.len(), 1);
     }
 }
@@ -0,0 +1,283 @@
+use super::{parse_decimal, MarketEvent, WsProtocol};
+use crate::config::UserConfig;
+use crate::exchange::SendSyncError;
+use crate::types::{OrderBook, Ticker, TradeFill};
+use async_trait::async_trait;
+use serde::Deserialize;
+
+const BINANCE_API_URL: &str = "https://fapi.binance.com";
+const BINANCE_WS_URL: &str = "wss://fstream.binance.com/ws";
+
+#[derive(Deserialize, Debug)]
+#[serde(tag = "e")]
+enum BinanceWsEvent {
+    #[serde(rename = "bookTicker")]
+    BookTicker {
+        s: String,
+        b: String,
+        #[serde(rename = "B")]
+        bid_qty: String,
+        a: String,
+        #[serde(rename = "A")]
+        ask_qty: String,
+    },
+    #[serde(rename = "24hrTicker")]
+    Ticker { s: String, c: String, q: String },
+    #[serde(rename = "ORDER_TRADE_UPDATE")]
+    OrderTradeUpdate { o: BinanceWsOrderUpdate },
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Deserialize, Debug)]
+struct BinanceWsOrderUpdate {
+    s: String,
+    #[serde(rename = "S")]
+    side: String,
+    /// Execution type; "TRADE" for fills.
+    x: String,
+    i: i64,
+    /// Last filled qty and price.
+    l: String,
+    #[serde(rename = "L")]
+    last_price: String,
+    #[serde(default)]
+    n: String,
+    #[serde(rename = "N", default)]
+    commission_asset: String,
+    #[serde(rename = "T")]
+    trade_time: i64,
+    t: i64,
+    ps: String,
+    /// Whether the fill added liquidity.
+    #[serde(default)]
+    m: Option<bool>,
+    /// Original order price; 0 for market orders.
+    #[serde(default)]
+    p: String,
+    /// PnL realized by the fill, before its commission.
+    #[serde(default)]
+    rp: String,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct BinanceListenKey {
+    listen_key: String,
+}
+
+fn method_message(method: &str, streams: Vec<String>) -> String {
+    serde_json::json!({ "method": method, "params": streams, "id": 1 }).to_string()
+}
+
+/// Book ticker and 24h ticker streams of USDⓈ-M futures.
+pub struct BinancePublic;
+
+impl BinancePublic {
+    fn streams(symbols: &[String]) -> Vec<String> {
+        symbols
+            .iter()
+            .map(|symbol| symbol.to_lowercase())
+            .flat_map(|symbol| {
+                [
+                    format!("{}@bookTicker", symbol),
+                    format!("{}@ticker", symbol),
+                ]
+            })
+            .collect()
+    }
+}
+
+#[async_trait]
+impl WsProtocol for BinancePublic {
+    fn name(&self) -> &'static str {
+        "binance"
+    }
+
+    async fn url(&self) -> Result<String, SendSyncError> {
+        Ok(BINANCE_WS_URL.to_string())
+    }
+
+    fn subscribe_messages(&self, symbols: &[String]) -> Vec<String> {
+        if symbols.is_empty() {
+            return Vec::new();
+        }
+        vec![method_message("SUBSCRIBE", Self::streams(symbols))]
+    }
+
+    fn unsubscribe_messages(&self, symbols: &[String]) -> Vec<String> {
+        vec![method_message("UNSUBSCRIBE", Self::streams(symbols))]
+    }
+
+    fn parse(&mut self, text: &str) -> Result<Vec<MarketEvent>, SendSyncError> {
+        // subscription replies are {"result":null,"id":1}
+        if !text.contains(r#""e":"#) {
+            return Ok(Vec::new());
+        }
+        match serde_json::from_str(text)? {
+            BinanceWsEvent::BookTicker {
+                s,
+                b,
+                bid_qty,
+                a,
+                ask_qty,
+            } => Ok(vec![MarketEvent::OrderBook {
+                symbol: s,
+                order_book: OrderBook {
+                    bids: vec![[b.parse()?, bid_qty.parse()?]],
+                    asks: vec![[a.parse()?, ask_qty.parse()?]],
+                },
+            }]),
+            BinanceWsEvent::Ticker { s, c, q } => Ok(vec![MarketEvent::Ticker(Ticker {
+                symbol: s,
+                last: parse_decimal(&c)?,
+                quote_volume: parse_decimal(&q)?,
+                ..Default::default()
+            })]),
+            _ => Ok(Vec::new()),
+        }
+    }
+}
+
+/// The user data stream's order updates, kept open with a listen key.
+pub struct BinancePrivate {
+    client: reqwest::Client,
+    api_key: String,
+    quote: String,
+}
+
+impl BinancePrivate {
+    pub fn new(user_config: &UserConfig) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            api_key: user_config.key.clone(),
+            quote: user_config.quote.clone(),
+        }
+    }
+}
+
+#[async_trait]
+impl WsProtocol for BinancePrivate {
+    fn name(&self) -> &'static str {
+        "binance private"
+    }
+
+    async fn url(&self) -> Result<String, SendSyncError> {
+        // returns the account's active listen key if there is one
+        let response = self
+            .client
+            .post(format!("{}/fapi/v1/listenKey", BINANCE_API_URL))
+            .header("X-MBX-APIKEY", &self.api_key)
+            .send()
+            .await?
+            .text()
+            .await?;
+        let listen_key: BinanceListenKey = serde_json::from_str(&response)
+            .map_err(|_| format!("Failed to create listen key: {}", response))?;
+        Ok(format!("{}/{}", BINANCE_WS_URL, listen_key.listen_key))
+    }
+
+    fn subscribe_messages(&self, _symbols: &[String]) -> Vec<String> {
+        Vec::new()
+    }
+
+    fn unsubscribe_messages(&self, _symbols: &[String]) -> Vec<String> {
+        Vec::new()
+    }
+
+    fn parse(&mut self, text: &str) -> Result<Vec<MarketEvent>, SendSyncError> {
+        let BinanceWsEvent::OrderTradeUpdate { o } = serde_json::from_str(text)? else {
+            return Ok(Vec::new());
+        };
+        if o.x != "TRADE" {
+            return Ok(Vec::new());
+        }
+        let side = if o.side == "BUY" { "Buy" } else { "Sell" };
+        let position_side = match o.ps.as_str() {
+            "LONG" => "Long",
+            "SHORT" => "Short",
+            _ => "",
+        };
+        // commissions paid in other assets (e.g. BNB) cannot be valued here
+        let fee = if o.commission_asset == self.quote {
+            parse_decimal(&o.n)?
+        } else {
+            0.0
+        };
+        Ok(vec![MarketEvent::Fill(TradeFill {
+            id: o.t.to_string(),
+            order_id: o.i.to_string(),
+            symbol: o.s,
+            timestamp_ms: o.trade_time,
+            side: side.to_string(),
+            position_side: position_side.to_string(),
+            qty: o.l.parse()?,
+            price: o.last_price.parse()?,
+            fee,
+            realized_pnl: o.rp.parse().ok(),
+            maker: o.m,
+            order_price: o.p.parse().unwrap_or(0.0),
+        })])
+    }
+
+    /// Extends the listen key, which otherwise expires an hour after the last renewal.
+    async fn keep_alive(&self) -> Result<(), SendSyncError> {
+        self.client
+            .put(format!("{}/fapi/v1/listenKey", BINANCE_API_URL))
+            .header("X-MBX-APIKEY", &self.api_key)
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_book_ticker_and_order_updates() {
+        let mut public = BinancePublic;
+        let events = public
+            .parse(
+                r#"{"e":"bookTicker","u":1,"E":1,"T":1,"s":"BTCUSDT","b":"100.0","B":"2",
+                "a":"100.1","A":"3"}"#,
+            )
+            .unwrap();
+        assert_eq!(
+            events,
+            vec![MarketEvent::OrderBook {
+                symbol: "BTCUSDT".to_string(),
+                order_book: OrderBook {
+                    bids: vec![[100.0, 2.0]],
+                    asks: vec![[100.1, 3.0]],
+                },
+            }]
+        );
+        assert!(public
+            .parse(r#"{"result":null,"id":1}"#)
+            .unwrap()
+            .is_empty());
+
+        let mut private = BinancePrivate::new(&UserConfig::public("binance"));
+        let events = private
+            .parse(
+                r#"{"e":"ORDER_TRADE_UPDATE","E":1,"T":1,"o":{"s":"BTCUSDT","c":"x",
+                "S":"BUY","o":"LIMIT","x":"TRADE","X":"PARTIALLY_FILLED","i":42,"l":"0.1",
+                "L":"100.0","n":"0.002","N":"USDT","T":1700000000000,"t":7,"ps":"LONG",
+                "rp":"0"}}"#,
+            )
+            .unwrap();
+        let [MarketEvent::Fill(fill)] = events.as_slice() else {
+            panic!("expected one fill, got {:?}", events);
+        };
+        assert_eq!((fill.id.as_str(), fill.order_id.as_str()), ("7", "42"));
+        assert_eq!(
+            (fill.side.as_str(), fill.position_side.as_str()),
+            ("Buy", "Long")
+        );
+        assert_eq!((fill.qty, fill.price, fill.fee), (0.1, 100.0, 0.002));
+        assert_eq!(fill.realized_pnl, Some(0.0));
+    }
+}
@@ -0,0 +1,337 @@
+use super::{parse_decimal, MarketEvent, WsProtocol};
+use crate::config::UserConfig;
+use crate::exchange::SendSyncError;
+use crate::types::{OrderBook, Ticker, TradeFill};
+use async_trait::async_trait;
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use serde::Deserialize;
+use sha2::Sha256;
+use std::collections::HashMap;
+use tokio_tungstenite::tungstenite::Message;
+
+const BYBIT_PUBLIC_WS_URL: &str = "wss://stream.bybit.com/v5/public/linear";
+const BYBIT_PRIVATE_WS_URL: &str = "wss://stream.bybit.com/v5/private";
+/// Bybit accepts at most this many topics per subscribe request.
+const MAX_TOPICS_PER_REQUEST: usize = 10;
+
+#[derive(Deserialize, Debug)]
+struct BybitWsMessage {
+    #[serde(default)]
+    topic: String,
+    #[serde(rename = "type", default)]
+    kind: String,
+    #[serde(default)]
+    data: serde_json::Value,
+}
+
+#[derive(Deserialize, Debug)]
+struct BybitWsBook {
+    s: String,
+    b: Vec<(String, String)>,
+    a: Vec<(String, String)>,
+}
+
+/// A ticker snapshot or delta; deltas leave out unchanged fields.
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct BybitWsTicker {
+    symbol: String,
+    #[serde(default)]
+    bid1_price: String,
+    #[serde(default)]
+    ask1_price: String,
+    #[serde(default)]
+    last_price: String,
+    #[serde(default)]
+    turnover24h: String,
+    #[serde(default)]
+    funding_rate: String,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct BybitWsExecution {
+    symbol: String,
+    exec_id: String,
+    order_id: String,
+    side: String,
+    exec_qty: String,
+    exec_price: String,
+    exec_fee: String,
+    exec_time: String,
+    exec_type: String,
+    #[serde(default)]
+    closed_size: String,
+    #[serde(default)]
+    is_maker: Option<bool>,
+    #[serde(default)]
+    order_price: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct BybitWsAuthReply {
+    #[serde(default)]
+    success: bool,
+    #[serde(default)]
+    ret_msg: String,
+}
+
+fn topic_messages(op: &str, topics: Vec<String>) -> Vec<String> {
+    topics
+        .chunks(MAX_TOPICS_PER_REQUEST)
+        .map(|args| serde_json::json!({ "op": op, "args": args }).to_string())
+        .collect()
+}
+
+/// Top-of-book and ticker streams of linear perpetuals.
+#[derive(Default)]
+pub struct BybitPublic {
+    /// Level 1 deltas only carry the side that changed.
+    books: HashMap<String, OrderBook>,
+}
+
+impl BybitPublic {
+    fn topics(symbols: &[String]) -> Vec<String> {
+        symbols
+            .iter()
+            .flat_map(|symbol| {
+                [
+                    format!("orderbook.1.{}", symbol),
+                    format!("tickers.{}", symbol),
+                ]
+            })
+            .collect()
+    }
+}
+
+#[async_trait]
+impl WsProtocol for BybitPublic {
+    fn name(&self) -> &'static str {
+        "bybit"
+    }
+
+    async fn url(&self) -> Result<String, SendSyncError> {
+        Ok(BYBIT_PUBLIC_WS_URL.to_string())
+    }
+
+    fn subscribe_messages(&self, symbols: &[String]) -> Vec<String> {
+        topic_messages("subscribe", Self::topics(symbols))
+    }
+
+    fn unsubscribe_messages(&self, symbols: &[String]) -> Vec<String> {
+        topic_messages("unsubscribe", Self::topics(symbols))
+    }
+
+    fn ping_message(&self) -> Message {
+        Message::text(r#"{"op":"ping"}"#)
+    }
+
+    fn parse(&mut self, text: &str) -> Result<Vec<MarketEvent>, SendSyncError> {
+        let message: BybitWsMessage = serde_json::from_str(text)?;
+        if message.topic.starts_with("orderbook.") {
+            let update: BybitWsBook = serde_json::from_value(message.data)?;
+            let levels = |entries: &[(String, String)]| -> Result<Vec<[f64; 2]>, SendSyncError> {
+                let mut levels = Vec::new();
+                for (price, qty) in entries {
+                    let qty: f64 = qty.parse()?;
+                    if qty > 0.0 {
+                        levels.push([price.parse()?, qty]);
+                    }
+                }
+                Ok(levels)
+            };
+            let book = self.books.entry(update.s.clone()).or_default();
+            if message.kind == "snapshot" {
+                book.bids = levels(&update.b)?;
+                book.asks = levels(&update.a)?;
+            } else {
+                // a level 1 delta replaces the top of the sides it lists
+                if !update.b.is_empty() {
+                    book.bids = levels(&update.b)?;
+                }
+                if !update.a.is_empty() {
+                    book.asks = levels(&update.a)?;
+                }
+            }
+            return Ok(vec![MarketEvent::OrderBook {
+                symbol: update.s,
+                order_book: book.clone(),
+            }]);
+        }
+        if message.topic.starts_with("tickers.") {
+            let ticker: BybitWsTicker = serde_json::from_value(message.data)?;
+            return Ok(vec![MarketEvent::Ticker(Ticker {
+                symbol: ticker.symbol,
+                bid: parse_decimal(&ticker.bid1_price)?,
+                ask: parse_decimal(&ticker.ask1_price)?,
+                last: parse_decimal(&ticker.last_price)?,
+                quote_volume: parse_decimal(&ticker.turnover24h)?,
+                funding_rate: parse_decimal(&ticker.funding_rate)?,
+            })]);
+        }
+        Ok(Vec::new())
+    }
+}
+
+/// The account's executions.
+pub struct BybitPrivate {
+    api_key: String,
+    api_secret: String,
+}
+
+impl BybitPrivate {
+    pub fn new(user_config: &UserConfig) -> Self {
+        Self {
+            api_key: user_config.key.clone(),
+            api_secret: user_config.secret.clone(),
+        }
+    }
+}
+
+#[async_trait]
+impl WsProtocol for BybitPrivate {
+    fn name(&self) -> &'static str {
+        "bybit private"
+    }
+
+    async fn url(&self) -> Result<String, SendSyncError> {
+        Ok(BYBIT_PRIVATE_WS_URL.to_string())
+    }
+
+    fn auth_message(&self) -> Option<String> {
+        let expires = Utc::now().timestamp_millis() + 10_000;
+        let signature = {
+            type HmacSha256 = Hmac<Sha256>;
+            let mut mac = HmacSha256::new_from_slice(self.api_secret.as_bytes()).unwrap();
+            mac.update(format!("GET/realtime{}", expires).as_bytes());
+            hex::encode(mac.finalize().into_bytes())
+        };
+        Some(
+            serde_json::json!({ "op": "auth", "args": [self.api_key, expires, signature] })
+                .to_string(),
+        )
+    }
+
+    fn check_auth(&self, reply: &str) -> Result<(), SendSyncError> {
+        let reply: BybitWsAuthReply = serde_json::from_str(reply)?;
+        if !reply.success {
+            return Err(format!("Authentication failed: {}", reply.ret_msg).into());
+        }
+        Ok(())
+    }
+
+    fn subscribe_messages(&self, _symbols: &[String]) -> Vec<String> {
+        topic_messages("subscribe", vec!["execution".to_string()])
+    }
+
+    fn unsubscribe_messages(&self, _symbols: &[String]) -> Vec<String> {
+        Vec::new()
+    }
+
+    fn ping_message(&self) -> Message {
+        Message::text(r#"{"op":"ping"}"#)
+    }
+
+    fn parse(&mut self, text: &str) -> Result<Vec<MarketEvent>, SendSyncError> {
+        let message: BybitWsMessage = serde_json::from_str(text)?;
+        if message.topic != "execution" {
+            return Ok(Vec::new());
+        }
+        let executions: Vec<BybitWsExecution> = serde_json::from_value(message.data)?;
+        let mut events = Vec::new();
+        for execution in executions {
+            if execution.exec_type != "Trade" {
+                continue;
+            }
+            // as in the REST execution list, a fill that closed something belongs to the
+            // opposite side
+            let closing = parse_decimal(&execution.closed_size)? > 0.0;
+            let position_side = match (execution.side.as_str(), closing) {
+                ("Buy", false) | ("Sell", true) => "Long",
+                _ => "Short",
+            };
+            events.push(MarketEvent::Fill(TradeFill {
+                id: execution.exec_id,
+                order_id: execution.order_id,
+                symbol: execution.symbol,
+                timestamp_ms: execution.exec_time.parse()?,
+                side: execution.side,
+                position_side: position_side.to_string(),
+                qty: execution.exec_qty.parse()?,
+                price: execution.exec_price.parse()?,
+                fee: execution.exec_fee.parse()?,
+                maker: execution.is_maker,
+                order_price: execution.order_price.parse().unwrap_or(0.0),
+                realized_pnl: None,
+            }));
+        }
+        Ok(events)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_level_one_deltas_update_one_side() {
+        let mut stream = BybitPublic::default();
+        stream
+            .parse(
+                r#"{"topic":"orderbook.1.BTCUSDT","type":"snapshot","data":{"s":"BTCUSDT",
+                "b":[["100.0","2"]],"a":[["100.5","3"]],"u":1,"seq":1}}"#,
+            )
+            .unwrap();
+        let events = stream
+            .parse(
+                r#"{"topic":"orderbook.1.BTCUSDT","type":"delta","data":{"s":"BTCUSDT",
+                "b":[],"a":[["100.4","1"]],"u":2,"seq":2}}"#,
+            )
+            .unwrap();
+
+        let expected = OrderBook {
+            bids: vec![[100.0, 2.0]],
+            asks: vec![[100.4, 1.0]],
+        };
+        assert_eq!(
+            events,
+            vec![MarketEvent::OrderBook {
+                symbol: "BTCUSDT".to_string(),
+                order_book: expected,
+            }]
+        );
+        // acks and pongs carry no events
+        assert!(stream
+            .parse(r#"{"success":true,"ret_msg":"pong","op":"ping"}"#)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_executions_become_fills() {
+        let mut stream = BybitPrivate {
+            api_key: String::new(),
+            api_secret: String::new(),
+        };
+        let events = stream
+            .parse(
+                r#"{"topic":"execution","data":[{"category":"linear","symbol":"BTCUSDT",
+                "execId":"e1","orderId":"o1","side":"Sell","execQty":"0.5","execPrice":"101",
+                "execFee":"0.01","execTime":"1700000000000","execType":"Trade",
+                "closedSize":"0.5"},{"symbol":"BTCUSDT","execId":"e2","orderId":"o2",
+                "side":"Sell","execQty":"0","execPrice":"0","execFee":"0.2",
+                "execTime":"1700000000000","execType":"Funding"}]}"#,
+            )
+            .unwrap();
+
+        let [MarketEvent::Fill(fill)] = events.as_slice() else {
+            panic!("expected one fill, got {:?}", events);
+        };
+        assert_eq!(
+            (fill.id.as_str(), fill.position_side.as_str()),
+            ("e1", "Long")
+        );
+        assert_eq!((fill.qty, fill.price, fill.fee), (0.5, 101.0, 0.01));
+    }
+}
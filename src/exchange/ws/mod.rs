@@ -0,0 +1,463 @@
+pub mod binance;
+pub mod bybit;
+pub mod okx;
+
+use crate::config::UserConfig;
+use crate::exchange::SendSyncError;
+use crate::types::{OrderBook, Ticker, TradeFill, WebSocketConfig};
+use async_trait::async_trait;
+use futures::{SinkExt, StreamExt};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::{broadcast, watch};
+use tokio::task::JoinHandle;
+use tokio_tungstenite::tungstenite::Message;
+use tracing::{info, warn};
+
+/// Streamed events past this many unread by a receiver are dropped for it.
+const FEED_CAPACITY: usize = 1024;
+const INITIAL_RECONNECT_DELAY: Duration = Duration::from_secs(1);
+const AUTH_TIMEOUT: Duration = Duration::from_secs(10);
+/// How often private streams renew their session, e.g. Binance's listen key.
+const KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(30 * 60);
+
+/// Market data or an account fill received over a WebSocket.
+#[derive(Debug, Clone, PartialEq)]
+pub enum MarketEvent {
+    OrderBook {
+        symbol: String,
+        order_book: OrderBook,
+    },
+    /// Fields the update left out are 0 and keep their previous value in the feed.
+    Ticker(Ticker),
+    Fill(TradeFill),
+}
+
+/// One exchange's WebSocket API: where to connect, how to subscribe and keep the
+/// connection alive, and how to read its messages.
+#[async_trait]
+pub trait WsProtocol: Send + Sync + 'static {
+    fn name(&self) -> &'static str;
+    /// Private streams may need a REST call for their URL.
+    async fn url(&self) -> Result<String, SendSyncError>;
+    /// Login sent right after connecting, before any subscription.
+    fn auth_message(&self) -> Option<String> {
+        None
+    }
+    /// Checks the reply to `auth_message`.
+    fn check_auth(&self, _reply: &str) -> Result<(), SendSyncError> {
+        Ok(())
+    }
+    /// Private streams subscribe to the account's fills whatever `symbols` holds.
+    fn subscribe_messages(&self, symbols: &[String]) -> Vec<String>;
+    fn unsubscribe_messages(&self, symbols: &[String]) -> Vec<String>;
+    fn ping_message(&self) -> Message {
+        Message::Ping(Default::default())
+    }
+    /// Events in a text message; acks, pongs and messages of other channels yield none.
+    fn parse(&mut self, text: &str) -> Result<Vec<MarketEvent>, SendSyncError>;
+    /// Renews the stream's session every `KEEP_ALIVE_INTERVAL`.
+    async fn keep_alive(&self) -> Result<(), SendSyncError> {
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+struct Latest {
+    order_book: Option<(OrderBook, Instant)>,
+    ticker: Option<(Ticker, Instant)>,
+}
+
+/// The latest streamed order book and ticker of every symbol, and a channel of all
+/// streamed events for tasks that react to them.
+#[derive(Clone)]
+pub struct MarketFeed {
+    latest: Arc<Mutex<HashMap<String, Latest>>>,
+    sender: broadcast::Sender<MarketEvent>,
+}
+
+impl Default for MarketFeed {
+    fn default() -> Self {
+        let (sender, _) = broadcast::channel(FEED_CAPACITY);
+        Self {
+            latest: Arc::default(),
+            sender,
+        }
+    }
+}
+
+impl MarketFeed {
+    pub fn publish(&self, event: MarketEvent) {
+        {
+            let mut latest = self.latest.lock().unwrap();
+            match &event {
+                MarketEvent::OrderBook { symbol, order_book } => {
+                    latest.entry(symbol.clone()).or_default().order_book =
+                        Some((order_book.clone(), Instant::now()));
+                }
+                MarketEvent::Ticker(update) => {
+                    let (ticker, received_at) = latest
+                        .entry(update.symbol.clone())
+                        .or_default()
+                        .ticker
+                        .get_or_insert_with(|| {
+                            let ticker = Ticker {
+                                symbol: update.symbol.clone(),
+                                ..Default::default()
+                            };
+                            (ticker, Instant::now())
+                        });
+                    merge_ticker(ticker, update);
+                    *received_at = Instant::now();
+                }
+                MarketEvent::Fill(_) => {}
+            }
+        }
+        // an error only means nobody is listening
+        let _ = self.sender.send(event);
+    }
+
+    /// The latest order book of `symbol` and when it arrived, unless it is older than
+    /// `max_age`.
+    pub fn order_book(&self, symbol: &str, max_age: Duration) -> Option<(OrderBook, Instant)> {
+        let latest = self.latest.lock().unwrap();
+        let (order_book, received_at) = latest.get(symbol)?.order_book.as_ref()?;
+        (received_at.elapsed() <= max_age).then(|| (order_book.clone(), *received_at))
+    }
+
+    /// The latest ticker of `symbol`, unless no update arrived within `max_age`.
+    pub fn ticker(&self, symbol: &str, max_age: Duration) -> Option<Ticker> {
+        let latest = self.latest.lock().unwrap();
+        let (ticker, received_at) = latest.get(symbol)?.ticker.as_ref()?;
+        (received_at.elapsed() <= max_age).then(|| ticker.clone())
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<MarketEvent> {
+        self.sender.subscribe()
+    }
+}
+
+fn merge_ticker(ticker: &mut Ticker, update: &Ticker) {
+    for (field, value) in [
+        (&mut ticker.bid, update.bid),
+        (&mut ticker.ask, update.ask),
+        (&mut ticker.last, update.last),
+        (&mut ticker.quote_volume, update.quote_volume),
+        (&mut ticker.funding_rate, update.funding_rate),
+    ] {
+        if value != 0.0 {
+            *field = value;
+        }
+    }
+}
+
+/// Reconnect delays, doubling from `INITIAL_RECONNECT_DELAY` up to `max`.
+struct Backoff {
+    next: Duration,
+    max: Duration,
+}
+
+impl Backoff {
+    fn new(max: Duration) -> Self {
+        Self {
+            next: INITIAL_RECONNECT_DELAY.min(max),
+            max,
+        }
+    }
+
+    fn next_delay(&mut self) -> Duration {
+        let delay = self.next;
+        self.next = (self.next * 2).min(self.max);
+        delay
+    }
+
+    fn reset(&mut self) {
+        self.next = INITIAL_RECONNECT_DELAY.min(self.max);
+    }
+}
+
+/// WebSocket connections of one account, streaming into a `MarketFeed`: a public stream
+/// of order books and tickers for the symbols being traded and, with API keys, a private
+/// stream of the account's fills. Connections are re-established with backoff when they
+/// fail or go quiet, until the stream is dropped.
+pub struct MarketStream {
+    feed: MarketFeed,
+    symbols: watch::Sender<Vec<String>>,
+    handles: Vec<JoinHandle<()>>,
+}
+
+impl MarketStream {
+    /// Connects to `user_config`'s exchange, or returns `None` if it has no WebSocket
+    /// client.
+    pub fn spawn(user_config: &UserConfig, config: &WebSocketConfig) -> Option<Self> {
+        let authenticated = !user_config.key.is_empty();
+        let (public, private): (Box<dyn WsProtocol>, Option<Box<dyn WsProtocol>>) =
+            match user_config.exchange.as_str() {
+                "bybit" => (
+                    Box::new(bybit::BybitPublic::default()),
+                    authenticated.then(|| {
+                        Box::new(bybit::BybitPrivate::new(user_config)) as Box<dyn WsProtocol>
+                    }),
+                ),
+                "binance" => (
+                    Box::new(binance::BinancePublic),
+                    authenticated.then(|| {
+                        Box::new(binance::BinancePrivate::new(user_config)) as Box<dyn WsProtocol>
+                    }),
+                ),
+                "okx" => (
+                    Box::new(okx::OkxPublic),
+                    authenticated.then(|| {
+                        Box::new(okx::OkxPrivate::new(user_config)) as Box<dyn WsProtocol>
+                    }),
+                ),
+                _ => return None,
+            };
+        let feed = MarketFeed::default();
+        let (symbols, symbols_rx) = watch::channel(Vec::new());
+        let mut handles = vec![tokio::spawn(run_stream(
+            public,
+            Some(symbols_rx),
+            feed.clone(),
+            config.clone(),
+        ))];
+        if let Some(private) = private {
+            handles.push(tokio::spawn(run_stream(
+                private,
+                None,
+                feed.clone(),
+                config.clone(),
+            )));
+        }
+        Some(Self {
+            feed,
+            symbols,
+            handles,
+        })
+    }
+
+    pub fn feed(&self) -> MarketFeed {
+        self.feed.clone()
+    }
+
+    /// Streams the order books and tickers of `symbols` from now on, and only those.
+    pub fn set_symbols(&self, symbols: &[String]) {
+        self.symbols.send_if_modified(|current| {
+            if current.as_slice() == symbols {
+                return false;
+            }
+            *current = symbols.to_vec();
+            true
+        });
+    }
+}
+
+impl Drop for MarketStream {
+    fn drop(&mut self) {
+        for handle in &self.handles {
+            handle.abort();
+        }
+    }
+}
+
+/// Keeps `protocol` connected until the symbol list it follows is dropped.
+async fn run_stream(
+    mut protocol: Box<dyn WsProtocol>, mut symbols: Option<watch::Receiver<Vec<String>>>,
+    feed: MarketFeed, config: WebSocketConfig,
+) {
+    let mut backoff = Backoff::new(Duration::from_secs_f64(config.max_reconnect_delay_seconds));
+    loop {
+        match stream_once(
+            protocol.as_mut(),
+            symbols.as_mut(),
+            &feed,
+            &config,
+            &mut backoff,
+        )
+        .await
+        {
+            Ok(()) => return,
+            Err(e) => {
+                let delay = backoff.next_delay();
+                warn!(
+                    "[ws {}] Connection lost: {}; reconnecting in {:.0}s",
+                    protocol.name(),
+                    e,
+                    delay.as_secs_f64()
+                );
+                tokio::time::sleep(delay).await;
+            }
+        }
+    }
+}
+
+/// Streams over one connection until it fails, returning `Ok` only once the symbol
+/// list is dropped.
+async fn stream_once(
+    protocol: &mut dyn WsProtocol, mut symbols: Option<&mut watch::Receiver<Vec<String>>>,
+    feed: &MarketFeed, config: &WebSocketConfig, backoff: &mut Backoff,
+) -> Result<(), SendSyncError> {
+    let url = protocol.url().await?;
+    let (mut socket, _) = tokio_tungstenite::connect_async(url.as_str()).await?;
+    if let Some(auth) = protocol.auth_message() {
+        socket.send(Message::text(auth)).await?;
+        let reply = tokio::time::timeout(AUTH_TIMEOUT, async {
+            while let Some(message) = socket.next().await {
+                if let Message::Text(text) = message? {
+                    return Ok(text.to_string());
+                }
+            }
+            Err::<_, SendSyncError>("Connection closed during authentication".into())
+        })
+        .await
+        .map_err(|_| "Timed out waiting for authentication")??;
+        protocol.check_auth(&reply)?;
+    }
+    let mut subscribed = match symbols.as_deref_mut() {
+        Some(symbols) => symbols.borrow_and_update().clone(),
+        None => Vec::new(),
+    };
+    for message in protocol.subscribe_messages(&subscribed) {
+        socket.send(Message::text(message)).await?;
+    }
+    info!("[ws {}] Connected", protocol.name());
+
+    let idle_timeout = Duration::from_secs_f64(config.idle_timeout_seconds);
+    let mut ping = tokio::time::interval(Duration::from_secs_f64(config.ping_interval_seconds));
+    let mut keep_alive = tokio::time::interval(KEEP_ALIVE_INTERVAL);
+    // both fire immediately; the connection is fresh
+    ping.tick().await;
+    keep_alive.tick().await;
+    loop {
+        let symbols_changed = async {
+            match symbols.as_deref_mut() {
+                Some(symbols) => symbols.changed().await.is_ok(),
+                None => std::future::pending().await,
+            }
+        };
+        tokio::select! {
+            message = tokio::time::timeout(idle_timeout, socket.next()) => {
+                let message = message
+                    .map_err(|_| format!("nothing received for {:?}", idle_timeout))?
+                    .ok_or("connection closed")??;
+                match message {
+                    Message::Text(text) => match protocol.parse(&text) {
+                        Ok(events) => {
+                            if !events.is_empty() {
+                                backoff.reset();
+                            }
+                            for event in events {
+                                feed.publish(event);
+                            }
+                        }
+                        Err(e) => warn!("[ws {}] Failed to parse message: {}", protocol.name(), e),
+                    },
+                    Message::Close(frame) => {
+                        return Err(format!("closed by the exchange: {:?}", frame).into());
+                    }
+                    // pings are answered by tungstenite
+                    _ => {}
+                }
+            }
+            _ = ping.tick() => socket.send(protocol.ping_message()).await?,
+            _ = keep_alive.tick() => protocol.keep_alive().await?,
+            changed = symbols_changed => {
+                if !changed {
+                    let _ = socket.close(None).await;
+                    return Ok(());
+                }
+                let wanted = symbols.as_deref_mut().map_or_else(Vec::new, |symbols| {
+                    symbols.borrow_and_update().clone()
+                });
+                let removed: Vec<String> = subscribed
+                    .iter()
+                    .filter(|s| !wanted.contains(s))
+                    .cloned()
+                    .collect();
+                let added: Vec<String> = wanted
+                    .iter()
+                    .filter(|s| !subscribed.contains(s))
+                    .cloned()
+                    .collect();
+                if !removed.is_empty() {
+                    for message in protocol.unsubscribe_messages(&removed) {
+                        socket.send(Message::text(message)).await?;
+                    }
+                }
+                if !added.is_empty() {
+                    for message in protocol.subscribe_messages(&added) {
+                        socket.send(Message::text(message)).await?;
+                    }
+                }
+                subscribed = wanted;
+            }
+        }
+    }
+}
+
+/// Parses a decimal string field, treating an empty string as 0.
+fn parse_decimal(value: &str) -> Result<f64, SendSyncError> {
+    if value.is_empty() {
+        return Ok(0.0);
+    }
+    Ok(value.parse()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff_doubles_up_to_max_and_resets() {
+        let mut backoff = Backoff::new(Duration::from_secs(5));
+        let delays: Vec<u64> = (0..5).map(|_| backoff.next_delay().as_secs()).collect();
+        assert_eq!(delays, vec![1, 2, 4, 5, 5]);
+        backoff.reset();
+        assert_eq!(backoff.next_delay(), Duration::from_secs(1));
+    }
+
+    #[test]
+    fn test_feed_merges_partial_ticker_updates() {
+        let feed = MarketFeed::default();
+        feed.publish(MarketEvent::Ticker(Ticker {
+            symbol: "BTCUSDT".to_string(),
+            bid: 99.0,
+            ask: 101.0,
+            last: 100.0,
+            quote_volume: 1e6,
+            funding_rate: 0.0001,
+        }));
+        feed.publish(MarketEvent::Ticker(Ticker {
+            symbol: "BTCUSDT".to_string(),
+            last: 100.5,
+            ..Default::default()
+        }));
+        let ticker = feed.ticker("BTCUSDT", Duration::from_secs(10)).unwrap();
+        assert_eq!((ticker.bid, ticker.ask, ticker.last), (99.0, 101.0, 100.5));
+        assert_eq!(ticker.quote_volume, 1e6);
+        assert!(feed.ticker("ETHUSDT", Duration::from_secs(10)).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_feed_keeps_latest_book_and_broadcasts() {
+        let feed = MarketFeed::default();
+        let mut events = feed.subscribe();
+        let order_book = OrderBook {
+            bids: vec![[99.0, 1.0]],
+            asks: vec![[101.0, 1.0]],
+        };
+        feed.publish(MarketEvent::OrderBook {
+            symbol: "BTCUSDT".to_string(),
+            order_book: order_book.clone(),
+        });
+
+        let (latest, _) = feed.order_book("BTCUSDT", Duration::from_secs(10)).unwrap();
+        assert_eq!(latest, order_book);
+        assert!(feed.order_book("BTCUSDT", Duration::ZERO).is_none());
+        assert!(matches!(
+            events.recv().await.unwrap(),
+            MarketEvent::OrderBook { symbol, .. } if symbol == "BTCUSDT"
+        ));
+    }
+}
@@ -0,0 +1,357 @@
+use super::{parse_decimal, MarketEvent, WsProtocol};
+use crate::config::UserConfig;
+use crate::exchange::SendSyncError;
+use crate::types::{OrderBook, Ticker, TradeFill};
+use async_trait::async_trait;
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use serde::Deserialize;
+use sha2::Sha256;
+use tokio_tungstenite::tungstenite::Message;
+
+const OKX_PUBLIC_WS_URL: &str = "wss://ws.okx.com:8443/ws/v5/public";
+const OKX_PRIVATE_WS_URL: &str = "wss://ws.okx.com:8443/ws/v5/private";
+
+#[derive(Deserialize, Debug)]
+struct OkxWsArg {
+    channel: String,
+    #[serde(rename = "instId", default)]
+    inst_id: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct OkxWsMessage {
+    arg: Option<OkxWsArg>,
+    #[serde(default)]
+    data: serde_json::Value,
+}
+
+#[derive(Deserialize, Debug)]
+struct OkxWsBook {
+    /// [price, size, deprecated, order count]
+    bids: Vec<Vec<String>>,
+    asks: Vec<Vec<String>>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct OkxWsTicker {
+    inst_id: String,
+    last: String,
+    bid_px: String,
+    ask_px: String,
+    /// In base currency for swaps.
+    vol_ccy24h: String,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct OkxWsOrder {
+    inst_id: String,
+    ord_id: String,
+    #[serde(default)]
+    trade_id: String,
+    #[serde(default)]
+    fill_sz: String,
+    #[serde(default)]
+    fill_px: String,
+    /// Negative when a fee was charged.
+    #[serde(default)]
+    fill_fee: String,
+    #[serde(default)]
+    fill_time: String,
+    /// "M" for maker fills, "T" for taker fills.
+    #[serde(default)]
+    exec_type: String,
+    /// Order price; empty for market orders.
+    #[serde(default)]
+    px: String,
+    side: String,
+    pos_side: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct OkxWsEvent {
+    event: String,
+    #[serde(default)]
+    msg: String,
+}
+
+/// The bot's symbols are instrument ids without the "-SWAP" suffix, as in `Okx`.
+fn symbol_of(inst_id: &str) -> String {
+    inst_id.replace("-SWAP", "")
+}
+
+fn channel_messages(op: &str, args: Vec<serde_json::Value>) -> Vec<String> {
+    if args.is_empty() {
+        return Vec::new();
+    }
+    vec![serde_json::json!({ "op": op, "args": args }).to_string()]
+}
+
+fn parse_levels(entries: &[Vec<String>]) -> Result<Vec<[f64; 2]>, SendSyncError> {
+    entries
+        .iter()
+        .map(|entry| match entry.as_slice() {
+            [price, size, ..] => Ok([price.parse()?, size.parse()?]),
+            _ => Err(format!("Malformed book level: {:?}", entry).into()),
+        })
+        .collect()
+}
+
+/// Best bid/offer and ticker channels of perpetual swaps.
+pub struct OkxPublic;
+
+impl OkxPublic {
+    fn args(symbols: &[String]) -> Vec<serde_json::Value> {
+        symbols
+            .iter()
+            .flat_map(|symbol| {
+                let inst_id = format!("{}-SWAP", symbol);
+                [
+                    serde_json::json!({ "channel": "bbo-tbt", "instId": inst_id }),
+                    serde_json::json!({ "channel": "tickers", "instId": inst_id }),
+                ]
+            })
+            .collect()
+    }
+}
+
+#[async_trait]
+impl WsProtocol for OkxPublic {
+    fn name(&self) -> &'static str {
+        "okx"
+    }
+
+    async fn url(&self) -> Result<String, SendSyncError> {
+        Ok(OKX_PUBLIC_WS_URL.to_string())
+    }
+
+    fn subscribe_messages(&self, symbols: &[String]) -> Vec<String> {
+        channel_messages("subscribe", Self::args(symbols))
+    }
+
+    fn unsubscribe_messages(&self, symbols: &[String]) -> Vec<String> {
+        channel_messages("unsubscribe", Self::args(symbols))
+    }
+
+    fn ping_message(&self) -> Message {
+        Message::text("ping")
+    }
+
+    fn parse(&mut self, text: &str) -> Result<Vec<MarketEvent>, SendSyncError> {
+        if text == "pong" {
+            return Ok(Vec::new());
+        }
+        let message: OkxWsMessage = serde_json::from_str(text)?;
+        let Some(arg) = message.arg else {
+            return Ok(Vec::new());
+        };
+        if message.data.is_null() {
+            // subscription acks echo the arg without data
+            return Ok(Vec::new());
+        }
+        match arg.channel.as_str() {
+            "bbo-tbt" => {
+                let books: Vec<OkxWsBook> = serde_json::from_value(message.data)?;
+                books
+                    .iter()
+                    .map(|book| {
+                        Ok(MarketEvent::OrderBook {
+                            symbol: symbol_of(&arg.inst_id),
+                            order_book: OrderBook {
+                                bids: parse_levels(&book.bids)?,
+                                asks: parse_levels(&book.asks)?,
+                            },
+                        })
+                    })
+                    .collect()
+            }
+            "tickers" => {
+                let tickers: Vec<OkxWsTicker> = serde_json::from_value(message.data)?;
+                tickers
+                    .into_iter()
+                    .map(|ticker| {
+                        let last = parse_decimal(&ticker.last)?;
+                        Ok(MarketEvent::Ticker(Ticker {
+                            symbol: symbol_of(&ticker.inst_id),
+                            bid: parse_decimal(&ticker.bid_px)?,
+                            ask: parse_decimal(&ticker.ask_px)?,
+                            last,
+                            quote_volume: parse_decimal(&ticker.vol_ccy24h)? * last,
+                            funding_rate: 0.0,
+                        }))
+                    })
+                    .collect()
+            }
+            _ => Ok(Vec::new()),
+        }
+    }
+}
+
+/// The account's swap order updates, of which those with a trade id are fills.
+pub struct OkxPrivate {
+    api_key: String,
+    api_secret: String,
+    passphrase: String,
+}
+
+impl OkxPrivate {
+    pub fn new(user_config: &UserConfig) -> Self {
+        Self {
+            api_key: user_config.key.clone(),
+            api_secret: user_config.secret.clone(),
+            passphrase: user_config.passphrase.clone(),
+        }
+    }
+}
+
+#[async_trait]
+impl WsProtocol for OkxPrivate {
+    fn name(&self) -> &'static str {
+        "okx private"
+    }
+
+    async fn url(&self) -> Result<String, SendSyncError> {
+        Ok(OKX_PRIVATE_WS_URL.to_string())
+    }
+
+    fn auth_message(&self) -> Option<String> {
+        let timestamp = Utc::now().timestamp().to_string();
+        let signature = {
+            type HmacSha256 = Hmac<Sha256>;
+            let mut mac = HmacSha256::new_from_slice(self.api_secret.as_bytes()).unwrap();
+            mac.update(format!("{}GET/users/self/verify", timestamp).as_bytes());
+            base64::encode(mac.finalize().into_bytes())
+        };
+        let args = serde_json::json!({
+            "apiKey": self.api_key,
+            "passphrase": self.passphrase,
+            "timestamp": timestamp,
+            "sign": signature,
+        });
+        Some(serde_json::json!({ "op": "login", "args": [args] }).to_string())
+    }
+
+    fn check_auth(&self, reply: &str) -> Result<(), SendSyncError> {
+        let reply: OkxWsEvent = serde_json::from_str(reply)?;
+        if reply.event != "login" {
+            return Err(format!("Login failed: {}", reply.msg).into());
+        }
+        Ok(())
+    }
+
+    fn subscribe_messages(&self, _symbols: &[String]) -> Vec<String> {
+        channel_messages(
+            "subscribe",
+            vec![serde_json::json!({ "channel": "orders", "instType": "SWAP" })],
+        )
+    }
+
+    fn unsubscribe_messages(&self, _symbols: &[String]) -> Vec<String> {
+        Vec::new()
+    }
+
+    fn ping_message(&self) -> Message {
+        Message::text("ping")
+    }
+
+    fn parse(&mut self, text: &str) -> Result<Vec<MarketEvent>, SendSyncError> {
+        if text == "pong" {
+            return Ok(Vec::new());
+        }
+        let message: OkxWsMessage = serde_json::from_str(text)?;
+        if message.arg.is_none_or(|arg| arg.channel != "orders") || message.data.is_null() {
+            return Ok(Vec::new());
+        }
+        let orders: Vec<OkxWsOrder> = serde_json::from_value(message.data)?;
+        let mut events = Vec::new();
+        for order in orders {
+            if order.trade_id.is_empty() || parse_decimal(&order.fill_sz)? == 0.0 {
+                continue;
+            }
+            let side = if order.side == "buy" { "Buy" } else { "Sell" };
+            let position_side = match order.pos_side.as_str() {
+                "long" => "Long",
+                "short" => "Short",
+                _ => "",
+            };
+            events.push(MarketEvent::Fill(TradeFill {
+                id: order.trade_id,
+                order_id: order.ord_id,
+                symbol: symbol_of(&order.inst_id),
+                timestamp_ms: order.fill_time.parse()?,
+                side: side.to_string(),
+                position_side: position_side.to_string(),
+                qty: order.fill_sz.parse()?,
+                price: order.fill_px.parse()?,
+                fee: -parse_decimal(&order.fill_fee)?,
+                maker: match order.exec_type.as_str() {
+                    "M" => Some(true),
+                    "T" => Some(false),
+                    _ => None,
+                },
+                order_price: order.px.parse().unwrap_or(0.0),
+                realized_pnl: None,
+            }));
+        }
+        Ok(events)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bbo_and_order_fills() {
+        let mut public = OkxPublic;
+        let events = public
+            .parse(
+                r#"{"arg":{"channel":"bbo-tbt","instId":"BTC-USDT-SWAP"},"data":[{
+                "asks":[["100.1","5","0","2"]],"bids":[["100.0","4","0","1"]],"ts":"1"}]}"#,
+            )
+            .unwrap();
+        assert_eq!(
+            events,
+            vec![MarketEvent::OrderBook {
+                symbol: "BTC-USDT".to_string(),
+                order_book: OrderBook {
+                    bids: vec![[100.0, 4.0]],
+                    asks: vec![[100.1, 5.0]],
+                },
+            }]
+        );
+        assert!(public.parse("pong").unwrap().is_empty());
+        assert!(public
+            .parse(r#"{"event":"subscribe","arg":{"channel":"tickers","instId":"BTC-USDT-SWAP"}}"#)
+            .unwrap()
+            .is_empty());
+
+        let mut private = OkxPrivate::new(&UserConfig::public("okx"));
+        assert!(private
+            .check_auth(r#"{"event":"login","code":"0","msg":""}"#)
+            .is_ok());
+        assert!(private
+            .check_auth(r#"{"event":"error","code":"60009","msg":"Login failed."}"#)
+            .is_err());
+        let events = private
+            .parse(
+                r#"{"arg":{"channel":"orders","instType":"SWAP"},"data":[{
+                "instId":"BTC-USDT-SWAP","ordId":"o1","tradeId":"t1","fillSz":"2",
+                "fillPx":"100","fillFee":"-0.04","fillTime":"1700000000000","side":"sell",
+                "posSide":"short","state":"partially_filled"},{"instId":"BTC-USDT-SWAP",
+                "ordId":"o2","tradeId":"","fillSz":"0","side":"buy","posSide":"long",
+                "state":"live"}]}"#,
+            )
+            .unwrap();
+        let [MarketEvent::Fill(fill)] = events.as_slice() else {
+            panic!("expected one fill, got {:?}", events);
+        };
+        assert_eq!(
+            (fill.symbol.as_str(), fill.side.as_str()),
+            ("BTC-USDT", "Sell")
+        );
+        assert_eq!((fill.qty, fill.price, fill.fee), (2.0, 100.0, 0.04));
+    }
+}
@@ -0,0 +1,220 @@
+//! Execution statistics of a backtest by order type: the share of placed orders that fill,
+//! how long they rest before filling and how far the price moves against them meanwhile,
+//! for weighing trailing orders against grid orders on evidence.
+//!
+//! The backtester recomputes its orders every step. An order placed again at the same
+//! price is taken to have kept resting, as the live bot keeps it, and one not placed again
+//! to have been cancelled.
+
+use crate::exchange::simulated::Fill;
+use crate::types::OrderType;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+
+/// An order by type, side (true for buys) and price bits.
+type OrderKey = (OrderType, bool, u64);
+
+#[derive(Serialize, Debug, Clone, Default, PartialEq)]
+pub struct OrderTypeStats {
+    pub n_placed: usize,
+    pub n_filled: usize,
+    /// Share of the placed orders that filled.
+    pub fill_rate: f64,
+    /// Mean time from placement to fill of the filled orders.
+    pub mean_minutes_to_fill: f64,
+    /// Mean move of the price from placement to fill, against the order and relative to
+    /// the price at placement: down for buys, up for sells.
+    pub mean_adverse_move: f64,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Placement {
+    timestamp_ms: i64,
+    price: f64,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Totals {
+    n_placed: usize,
+    n_filled: usize,
+    time_to_fill_ms: f64,
+    adverse_move: f64,
+}
+
+/// Follows the orders of one symbol from placement to fill or cancellation.
+#[derive(Debug, Clone, Default)]
+pub struct ExecutionTracker {
+    /// Orders resting since the last step, and those placed in the current one.
+    resting: HashMap<OrderKey, Placement>,
+    placed: HashMap<OrderKey, Placement>,
+    totals: HashMap<OrderType, Totals>,
+}
+
+impl ExecutionTracker {
+    /// Records the fills of the resting orders.
+    pub fn record_fills(&mut self, fills: &[Fill]) {
+        for fill in fills {
+            let Some(order_type) = OrderType::from_str(&fill.custom_id) else {
+                continue;
+            };
+            let buy = fill.side == "Buy";
+            let key = (order_type, buy, fill.order_price.to_bits());
+            let Some(placement) = self.resting.remove(&key) else {
+                continue;
+            };
+            let adverse_move = if buy {
+                placement.price - fill.price
+            } else {
+                fill.price - placement.price
+            };
+            let totals = self.totals.entry(order_type).or_default();
+            totals.n_filled += 1;
+            totals.time_to_fill_ms += (fill.timestamp_ms - placement.timestamp_ms) as f64;
+            totals.adverse_move += adverse_move / placement.price;
+        }
+    }
+
+    /// Records an order placed at `timestamp_ms` with the price at `market_price`; one
+    /// resting at the same price since the last step keeps its original placement.
+    pub fn record_placement(
+        &mut self, order_type: OrderType, buy: bool, price: f64, timestamp_ms: i64,
+        market_price: f64,
+    ) {
+        let key = (order_type, buy, price.to_bits());
+        let placement = self.resting.remove(&key).unwrap_or_else(|| {
+            self.totals.entry(order_type).or_default().n_placed += 1;
+            Placement {
+                timestamp_ms,
+                price: market_price,
+            }
+        });
+        self.placed.insert(key, placement);
+    }
+
+    /// Ends a step: resting orders not placed again are cancelled.
+    pub fn end_step(&mut self) {
+        std::mem::swap(&mut self.resting, &mut self.placed);
+        self.placed.clear();
+    }
+}
+
+/// The statistics of all `trackers` combined, by order type.
+pub fn summarize<'a>(
+    trackers: impl IntoIterator<Item = &'a ExecutionTracker>,
+) -> BTreeMap<String, OrderTypeStats> {
+    let mut combined: HashMap<OrderType, Totals> = HashMap::new();
+    for tracker in trackers {
+        for (order_type, totals) in &tracker.totals {
+            let sum = combined.entry(*order_type).or_default();
+            sum.n_placed += totals.n_placed;
+            sum.n_filled += totals.n_filled;
+            sum.time_to_fill_ms += totals.time_to_fill_ms;
+            sum.adverse_move += totals.adverse_move;
+        }
+    }
+    combined
+        .into_iter()
+        .map(|(order_type, totals)| {
+            let per_fill = |sum: f64| {
+                if totals.n_filled > 0 {
+                    sum / totals.n_filled as f64
+                } else {
+                    0.0
+                }
+            };
+            let stats = OrderTypeStats {
+                n_placed: totals.n_placed,
+                n_filled: totals.n_filled,
+                fill_rate: if totals.n_placed > 0 {
+                    totals.n_filled as f64 / totals.n_placed as f64
+                } else {
+                    0.0
+                },
+                mean_minutes_to_fill: per_fill(totals.time_to_fill_ms) / 60_000.0,
+                mean_adverse_move: per_fill(totals.adverse_move),
+            };
+            (order_type.to_string(), stats)
+        })
+        .collect()
+}
+
+pub fn format_execution_stats(stats: &BTreeMap<String, OrderTypeStats>) -> String {
+    let mut out = String::from("Execution by order type:\n");
+    if stats.is_empty() {
+        out.push_str("  no orders placed\n");
+    }
+    for (order_type, s) in stats {
+        let _ = writeln!(
+            out,
+            "  {:<28} placed {:>7}  filled {:>6} ({:>5.1}%)  to fill {:>8.1}m  adverse move {:.3}%",
+            order_type,
+            s.n_placed,
+            s.n_filled,
+            s.fill_rate * 100.0,
+            s.mean_minutes_to_fill,
+            s.mean_adverse_move * 100.0
+        );
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fill(order_type: OrderType, side: &str, order_price: f64, timestamp_ms: i64) -> Fill {
+        Fill {
+            step: 0,
+            timestamp_ms,
+            side: side.to_string(),
+            qty: 1.0,
+            price: order_price,
+            order_price,
+            order_id: String::new(),
+            custom_id: order_type.to_string(),
+            fee: 0.0,
+            taker: false,
+        }
+    }
+
+    #[test]
+    fn test_tracks_orders_from_placement_to_fill() {
+        let grid = OrderType::EntryGridNormalLong;
+        let trailing = OrderType::CloseTrailingLong;
+        let mut tracker = ExecutionTracker::default();
+        // step 0: a grid entry at 95 and a trailing close at 101, the price at 100
+        tracker.record_placement(grid, true, 95.0, 0, 100.0);
+        tracker.record_placement(trailing, false, 101.0, 0, 100.0);
+        tracker.end_step();
+        // step 1: the grid entry rests on, the trailing close moves up
+        tracker.record_placement(grid, true, 95.0, 60_000, 99.0);
+        tracker.record_placement(trailing, false, 102.0, 60_000, 99.0);
+        tracker.end_step();
+        // step 2: both fill
+        tracker.record_fills(&[
+            fill(grid, "Buy", 95.0, 120_000),
+            fill(trailing, "Sell", 102.0, 120_000),
+        ]);
+        tracker.end_step();
+
+        let stats = summarize([&tracker]);
+        assert_eq!(
+            stats[&grid.to_string()],
+            OrderTypeStats {
+                n_placed: 1,
+                n_filled: 1,
+                fill_rate: 1.0,
+                mean_minutes_to_fill: 2.0,
+                mean_adverse_move: 0.05,
+            }
+        );
+        let trailing_stats = &stats[&trailing.to_string()];
+        // the close at 101 was cancelled
+        assert_eq!((trailing_stats.n_placed, trailing_stats.n_filled), (2, 1));
+        assert_eq!(trailing_stats.fill_rate, 0.5);
+        assert_eq!(trailing_stats.mean_minutes_to_fill, 1.0);
+        assert!((trailing_stats.mean_adverse_move - 3.0 / 99.0).abs() < 1e-12);
+        assert!(format_execution_stats(&stats).contains("50.0%"));
+    }
+}
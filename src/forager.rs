@@ -1,77 +1,542 @@
-use log::info;
+use log::{info, warn};
 
+use crate::data::Candle;
+use crate::exchange::queue::OrderQueue;
+use crate::exchange::Exchange;
+use crate::indicators::MAX_LIVE_CANDLES;
+use crate::listings::{self, ListingRegistry};
 use crate::manager::Manager;
-use chrono::{DateTime, Utc};
+use crate::paths::Paths;
+use crate::types::{BotSideConfig, LiveConfig, Market, Ticker};
+use crate::venue::{self, Venue, VenueQuote};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
 
 // Forager:
 // - Periodically fetches all available markets from the exchange.
-// - Scores the markets based on volume, unilateralness, and noisiness.
-// - Compares the top-scoring markets with the currently running bots.
-// - Starts new bots for top-scoring markets that are not yet running.
-// - Stops bots that are running on markets that are no longer in the top list.
+// - Ranks the markets by relative volume and volatility over `filter_rolling_window`.
+// - Compares the top-ranked markets with the currently running bots.
+// - Starts new bots for top-ranked markets that are not yet running.
+// - Winds down bots on markets that are no longer in the top list.
+// - With several venues configured, picks the venue each coin is traded on.
+
+/// Candles used for ranking are refetched once they are this old.
+const RANKING_REFRESH_MS: i64 = 10 * 60 * 1000;
+
+/// Candles of a symbol for ranking, with the time they were fetched.
+type RankingCandles = (i64, Vec<Candle>);
+
+/// Volume and volatility of a symbol over a side's `filter_rolling_window`.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct SymbolActivity {
+    /// Traded value in quote currency.
+    pub quote_volume: f64,
+    /// Standard deviation of the log returns between consecutive closes.
+    pub volatility: f64,
+}
+
+impl SymbolActivity {
+    pub fn from_candles(candles: &[Candle]) -> Self {
+        let quote_volume = candles.iter().map(|c| c.close * c.volume).sum();
+        let returns: Vec<f64> = candles
+            .windows(2)
+            .filter(|w| w[0].close > 0.0 && w[1].close > 0.0)
+            .map(|w| (w[1].close / w[0].close).ln())
+            .collect();
+        let volatility = if returns.len() < 2 {
+            0.0
+        } else {
+            let mean = returns.iter().sum::<f64>() / returns.len() as f64;
+            let variance =
+                returns.iter().map(|r| (r - mean).powi(2)).sum::<f64>() / returns.len() as f64;
+            variance.sqrt()
+        };
+        Self {
+            quote_volume,
+            volatility,
+        }
+    }
+}
+
+/// The `n_positions` symbols to trade: those whose volume relative to the most traded
+/// symbol falls in the lowest `relative_volume_clip_pct` are dropped, and the most
+/// volatile of the rest are taken. Ties keep symbol order.
+pub fn rank_symbols(
+    activity: &HashMap<String, SymbolActivity>, relative_volume_clip_pct: f64, n_positions: usize,
+) -> Vec<String> {
+    let max_volume = activity
+        .values()
+        .map(|a| a.quote_volume)
+        .fold(0.0, f64::max);
+    let mut by_volume: Vec<(&String, f64, f64)> = activity
+        .iter()
+        .map(|(symbol, a)| {
+            let relative_volume = if max_volume > 0.0 {
+                a.quote_volume / max_volume
+            } else {
+                0.0
+            };
+            (symbol, relative_volume, a.volatility)
+        })
+        .collect();
+    by_volume.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+    let n_clipped = (by_volume.len() as f64 * relative_volume_clip_pct.clamp(0.0, 1.0)) as usize;
+    by_volume.truncate(by_volume.len() - n_clipped);
+    by_volume.sort_by(|a, b| b.2.total_cmp(&a.2).then_with(|| a.0.cmp(b.0)));
+    by_volume
+        .into_iter()
+        .take(n_positions)
+        .map(|(symbol, _, _)| symbol.clone())
+        .collect()
+}
 
 #[derive(Clone)]
 pub struct Forager {
     manager: Manager,
+    /// Listings known of each user's exchange, loaded on first use.
+    listings: Arc<Mutex<HashMap<String, ListingRegistry>>>,
+    /// Candles of the eligible symbols for ranking.
+    candles: Arc<Mutex<HashMap<String, RankingCandles>>>,
 }
 
 impl Forager {
     pub async fn new(manager: Manager) -> Self {
-        Self { manager }
+        Self {
+            manager,
+            listings: Arc::default(),
+            candles: Arc::default(),
+        }
     }
 
     pub async fn run(&self) -> Vec<String> {
         info!("Forager is running");
+        let exchange = self.manager.exchange.as_ref();
+        let (eligible_symbols, _) = self
+            .eligible_symbols(
+                exchange,
+                &self.manager.config.live.user,
+                &self.manager.order_queue,
+            )
+            .await;
+        self.rank(exchange, eligible_symbols).await
+    }
 
-        let markets = self
-            .manager
-            .exchange
-            .load_markets()
-            .await
-            .unwrap_or_default();
+    /// The eligible symbols each enabled side trades, see `rank_symbols`. With no more
+    /// eligible symbols than a side has positions, all of them are traded.
+    async fn rank(&self, exchange: &dyn Exchange, eligible_symbols: Vec<String>) -> Vec<String> {
+        let bot = &self.manager.config.bot;
+        let sides: Vec<&BotSideConfig> = [&bot.long, &bot.short]
+            .into_iter()
+            .filter(|side| side.enabled)
+            .collect();
+        let n_positions = |side: &BotSideConfig| side.n_positions.round().max(0.0) as usize;
+        if sides
+            .iter()
+            .all(|side| eligible_symbols.len() <= n_positions(side))
+        {
+            return eligible_symbols;
+        }
+        // one fetch serves both sides' windows; long windows are ranked on hourly candles
+        let window_minutes = sides
+            .iter()
+            .map(|side| side.filter_rolling_window.max(1.0))
+            .fold(1.0, f64::max);
+        let (timeframe, candle_minutes) = if window_minutes <= MAX_LIVE_CANDLES as f64 {
+            ("1m", 1.0)
+        } else {
+            ("1h", 60.0)
+        };
+        let limit = ((window_minutes / candle_minutes).ceil() as usize).min(MAX_LIVE_CANDLES);
+        let now_ms = exchange.now_ms();
+        let mut candles = std::mem::take(&mut *self.candles.lock().unwrap());
+        candles.retain(|symbol, _| eligible_symbols.contains(symbol));
+        for symbol in &eligible_symbols {
+            if candles
+                .get(symbol)
+                .is_some_and(|(fetched_ms, _)| now_ms - fetched_ms < RANKING_REFRESH_MS)
+            {
+                continue;
+            }
+            match exchange.fetch_ohlcv(symbol, timeframe, None, limit).await {
+                Ok(fetched) => {
+                    candles.insert(symbol.clone(), (now_ms, fetched));
+                }
+                Err(e) => warn!("[{}] Failed to fetch candles for ranking: {}", symbol, e),
+            }
+        }
+
+        let mut selected: Vec<String> = Vec::new();
+        for side in sides {
+            let n_candles =
+                ((side.filter_rolling_window.max(1.0) / candle_minutes).ceil() as usize).max(2);
+            let activity: HashMap<String, SymbolActivity> = candles
+                .iter()
+                .map(|(symbol, (_, symbol_candles))| {
+                    let start = symbol_candles.len().saturating_sub(n_candles);
+                    (
+                        symbol.clone(),
+                        SymbolActivity::from_candles(&symbol_candles[start..]),
+                    )
+                })
+                .collect();
+            for symbol in rank_symbols(
+                &activity,
+                side.filter_relative_volume_clip_pct,
+                n_positions(side),
+            ) {
+                if !selected.contains(&symbol) {
+                    selected.push(symbol);
+                }
+            }
+        }
+        *self.candles.lock().unwrap() = candles;
+        info!(
+            "Ranked {} eligible symbols, trading {}",
+            eligible_symbols.len(),
+            selected.join(",")
+        );
+        selected
+    }
+
+    /// Chooses, for every coin eligible on at least one venue, the venue to trade it on.
+    /// Returns (user, symbol) pairs. `held` maps coins with open positions to their venue's
+    /// user.
+    pub async fn run_venues(
+        &self, venues: &[Venue], held: &HashMap<String, String>,
+    ) -> Vec<(String, String)> {
+        info!("Forager is running across {} venues", venues.len());
+
+        let mut quotes = Vec::new();
+        for venue in venues {
+            let exchange = venue.account.exchange.as_ref();
+            let (symbols, tickers) = self
+                .eligible_symbols(exchange, &venue.account.user, &venue.order_queue)
+                .await;
+            quotes.extend(symbols.into_iter().map(|symbol| VenueQuote {
+                user: venue.account.user.clone(),
+                coin: venue::coin_of(&symbol, exchange.quote()),
+                maker_fee: venue.account.maker_fee,
+                funding_rate: tickers.get(&symbol).map_or(0.0, |t| t.funding_rate),
+                min_cost: 0.0,
+                depth: 0.0,
+                symbol,
+            }));
+        }
+
+        // minimum costs and depth only matter where venues compete for a coin
+        let mut venue_counts: HashMap<String, usize> = HashMap::new();
+        for quote in &quotes {
+            *venue_counts.entry(quote.coin.clone()).or_default() += 1;
+        }
+        for quote in quotes.iter_mut().filter(|q| venue_counts[&q.coin] > 1) {
+            let Some(venue) = venues.iter().find(|v| v.account.user == quote.user) else {
+                continue;
+            };
+            let exchange = &venue.account.exchange;
+            match exchange.fetch_exchange_params(&quote.symbol).await {
+                Ok(params) => quote.min_cost = params.min_cost,
+                Err(e) => warn!(
+                    "[{}] {}: failed to fetch exchange params: {}",
+                    quote.symbol, quote.user, e
+                ),
+            }
+            match exchange.fetch_order_book(&quote.symbol).await {
+                Ok(order_book) => quote.depth = venue::calc_depth(&order_book),
+                Err(e) => warn!(
+                    "[{}] {}: failed to fetch order book: {}",
+                    quote.symbol, quote.user, e
+                ),
+            }
+        }
+
+        venue::select_venues(quotes, held, &self.manager.config.live.venues)
+            .into_iter()
+            .map(|quote| (quote.user, quote.symbol))
+            .collect()
+    }
+
+    /// Symbols of `exchange` passing the approval, volume and age filters, with their tickers.
+    async fn eligible_symbols(
+        &self, exchange: &dyn Exchange, user: &str, order_queue: &OrderQueue,
+    ) -> (Vec<String>, HashMap<String, Ticker>) {
+        let markets = exchange.load_markets().await.unwrap_or_default();
         let symbols: Vec<String> = markets.keys().cloned().collect();
-        let tickers = self
-            .manager
-            .exchange
-            .fetch_tickers(&symbols)
-            .await
-            .unwrap_or_default();
-
-        let approved_coins = &self.manager.config.live.approved_coins;
-        let ignored_coins = &self.manager.config.live.ignored_coins;
-        let empty_means_all_approved = self.manager.config.live.empty_means_all_approved;
-        let min_vol = self.manager.config.live.min_vol_24h;
+        let tickers = exchange.fetch_tickers(&symbols).await.unwrap_or_default();
+
+        let eligible_symbols = eligible_markets(
+            &markets,
+            &tickers,
+            &self.manager.config.live,
+            exchange.quote(),
+            |symbol| order_queue.is_quarantined(symbol),
+        );
+        let eligible_symbols = self
+            .old_enough(exchange, user, &markets, eligible_symbols)
+            .await;
+
+        (eligible_symbols, tickers)
+    }
+
+    /// The `symbols` listed at least `live.minimum_coin_age_days` ago, by the listing time
+    /// the exchange reports or else the first daily candle it has. Symbols whose age
+    /// cannot be told are left out.
+    async fn old_enough(
+        &self, exchange: &dyn Exchange, user: &str, markets: &HashMap<String, Market>,
+        symbols: Vec<String>,
+    ) -> Vec<String> {
         let min_age_days = self.manager.config.live.minimum_coin_age_days;
-        let now = Utc::now();
+        if min_age_days <= 0.0 {
+            return symbols;
+        }
+        let path = Paths::from_config(&self.manager.config.live)
+            .for_user(user)
+            .listings();
+        // taken out while probing, so the lock is not held across requests
+        let known = self.listings.lock().unwrap().remove(user);
+        let mut registry = known.unwrap_or_else(|| {
+            path.as_deref()
+                .map(ListingRegistry::load)
+                .unwrap_or_default()
+        });
+        let now_ms = exchange.now_ms();
+        let mut changed = false;
+        for symbol in &symbols {
+            if let Some(market) = markets.get(symbol) {
+                changed |= registry.record_listing(symbol, market.created_at);
+            }
+            if registry.listed_by_ms(symbol).is_none() {
+                match listings::probe_first_candle(exchange, symbol, now_ms).await {
+                    Ok(Some(first_ms)) => changed |= registry.record_candle(symbol, first_ms),
+                    Ok(None) => {}
+                    Err(e) => warn!("[{}] Failed to fetch the first candle: {}", symbol, e),
+                }
+            }
+        }
+        let old_enough = filter_old_enough(&registry, symbols, min_age_days, now_ms);
+        if changed {
+            if let Some(path) = &path {
+                if let Err(e) = registry.save(path) {
+                    warn!("Failed to save listings {}: {}", path.display(), e);
+                }
+            }
+        }
+        self.listings
+            .lock()
+            .unwrap()
+            .insert(user.to_string(), registry);
+        old_enough
+    }
+}
 
-        let eligible_symbols: Vec<String> = markets
-            .iter()
-            .filter(|(symbol, market)| {
-                market.active
-                    && market.swap
-                    && market.linear
-                    && market.symbol.ends_with("USDT")
-                    && !ignored_coins.contains(symbol)
-                    && (empty_means_all_approved || approved_coins.contains(symbol))
-            })
-            .filter_map(|(symbol, market)| {
-                if let Some(ticker) = tickers.get(symbol) {
-                    if let Some(created_at) = DateTime::from_timestamp(market.created_at / 1000, 0)
-                    {
-                        let age = now - created_at;
-                        if ticker.quote_volume >= min_vol
-                            && age >= chrono::Duration::days(min_age_days as i64)
-                        {
-                            return Some(symbol.clone());
-                        }
-                    }
+/// The `markets` that are active linear swaps settled in `quote`, approved and not ignored in
+/// `live`, not quarantined, and traded for at least `live.min_vol_24h` in quote currency
+/// over the last 24h, sorted. Markets without a ticker are left out.
+pub fn eligible_markets(
+    markets: &HashMap<String, Market>, tickers: &HashMap<String, Ticker>, live: &LiveConfig,
+    quote: &str, is_quarantined: impl Fn(&str) -> bool,
+) -> Vec<String> {
+    let mut eligible: Vec<String> = markets
+        .iter()
+        .filter(|(symbol, market)| {
+            market.active
+                && market.swap
+                && market.linear
+                && market.settles_in(quote)
+                && !live.ignored_coins.contains(symbol)
+                && !is_quarantined(symbol)
+                && (live.empty_means_all_approved || live.approved_coins.contains(symbol))
+        })
+        .filter(|(symbol, _)| {
+            tickers
+                .get(*symbol)
+                .is_some_and(|ticker| ticker.quote_volume >= live.min_vol_24h)
+        })
+        .map(|(symbol, _)| symbol.clone())
+        .collect();
+    eligible.sort();
+    eligible
+}
+
+/// The `symbols` listed at least `min_age_days` before `now_ms` by `listings`; symbols
+/// whose listing is unknown are left out unless there is no minimum age.
+pub fn filter_old_enough(
+    listings: &ListingRegistry, symbols: Vec<String>, min_age_days: f64, now_ms: i64,
+) -> Vec<String> {
+    if min_age_days <= 0.0 {
+        return symbols;
+    }
+    symbols
+        .into_iter()
+        .filter(
+            |symbol| match listings.is_old_enough(symbol, min_age_days, now_ms) {
+                Some(old_enough) => old_enough,
+                None => {
+                    warn!("[{}] Listing time unknown, skipping", symbol);
+                    false
                 }
-                None
-            })
-            .collect();
+            },
+        )
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rank_symbols_clips_volume_then_takes_the_most_volatile() {
+        let candles = |closes: &[f64], volume: f64| -> Vec<Candle> {
+            closes
+                .iter()
+                .enumerate()
+                .map(|(i, &close)| Candle {
+                    timestamp: i as u64 * 60_000,
+                    open: close,
+                    high: close,
+                    low: close,
+                    close,
+                    volume,
+                })
+                .collect()
+        };
+        let calm = SymbolActivity::from_candles(&candles(&[100.0, 100.1, 100.0, 100.1], 1e6));
+        let wild = SymbolActivity::from_candles(&candles(&[100.0, 110.0, 95.0, 105.0], 1e6));
+        assert!((calm.quote_volume - 400.2e6).abs() < 1e-3);
+        assert!(wild.volatility > calm.volatility * 10.0);
+
+        let activity = HashMap::from([
+            ("BTCUSDT".to_string(), calm),
+            ("SOLUSDT".to_string(), wild),
+            (
+                "DOGEUSDT".to_string(),
+                SymbolActivity {
+                    volatility: wild.volatility / 2.0,
+                    ..calm
+                },
+            ),
+            // the most volatile, but too thinly traded
+            (
+                "THINUSDT".to_string(),
+                SymbolActivity {
+                    quote_volume: 1.0,
+                    volatility: 1.0,
+                },
+            ),
+        ]);
+        assert_eq!(
+            rank_symbols(&activity, 0.25, 2),
+            vec!["SOLUSDT".to_string(), "DOGEUSDT".to_string()]
+        );
+        assert_eq!(
+            rank_symbols(&activity, 0.0, 1),
+            vec!["THINUSDT".to_string()]
+        );
+        assert_eq!(rank_symbols(&activity, 0.25, 10).len(), 3);
+    }
+
+    #[test]
+    fn test_new_and_illiquid_coins_are_not_eligible() {
+        let mut live = crate::config::load_config("tests/fixtures/backtest_config.hjson")
+            .unwrap()
+            .live;
+        live.approved_coins = ["BTCUSDT", "NEWUSDT", "THINUSDT", "HALTEDUSDT", "BANNEDUSDT"]
+            .map(String::from)
+            .to_vec();
+        live.ignored_coins = vec!["BANNEDUSDT".to_string()];
+        live.empty_means_all_approved = false;
+        live.min_vol_24h = 1e6;
+        live.minimum_coin_age_days = 30.0;
+        let now_ms = 365 * listings::MS_PER_DAY;
+
+        let mut markets = HashMap::new();
+        let mut tickers = HashMap::new();
+        for (symbol, listed_days_ago, quote_volume) in [
+            ("BTCUSDT", 300, 5e9),
+            ("NEWUSDT", 3, 5e7),
+            ("THINUSDT", 300, 2e5),
+            ("HALTEDUSDT", 300, 2e6),
+            ("BANNEDUSDT", 300, 5e7),
+            ("OTHERUSDT", 300, 5e7),
+        ] {
+            markets.insert(
+                symbol.to_string(),
+                Market {
+                    symbol: symbol.to_string(),
+                    active: true,
+                    swap: true,
+                    linear: true,
+                    created_at: now_ms - listed_days_ago * listings::MS_PER_DAY,
+                    settle: "USDT".to_string(),
+                },
+            );
+            tickers.insert(
+                symbol.to_string(),
+                Ticker {
+                    symbol: symbol.to_string(),
+                    quote_volume,
+                    ..Default::default()
+                },
+            );
+        }
+        // no ticker: its volume is unknown
+        markets.insert(
+            "ETHUSDT".to_string(),
+            Market {
+                symbol: "ETHUSDT".to_string(),
+                active: true,
+                swap: true,
+                linear: true,
+                created_at: 0,
+                settle: "USDT".to_string(),
+            },
+        );
+        live.approved_coins.push("ETHUSDT".to_string());
+
+        let liquid = eligible_markets(&markets, &tickers, &live, "USDT", |s| s == "HALTEDUSDT");
+        assert_eq!(liquid, ["BTCUSDT", "NEWUSDT"]);
 
-        // TODO: implement scoring logic
+        let mut registry = ListingRegistry::default();
+        for market in markets.values() {
+            registry.record_listing(&market.symbol, market.created_at);
+        }
+        assert_eq!(
+            filter_old_enough(
+                &registry,
+                liquid.clone(),
+                live.minimum_coin_age_days,
+                now_ms
+            ),
+            ["BTCUSDT"]
+        );
+        assert_eq!(filter_old_enough(&registry, liquid, 0.0, now_ms).len(), 2);
+        // listing unknown
+        assert!(filter_old_enough(&registry, vec!["ETHUSDT".into()], 30.0, now_ms).is_empty());
 
-        eligible_symbols
+        // Hyperliquid names markets by their coin alone; they settle in USDC
+        live.empty_means_all_approved = true;
+        let markets = HashMap::from(["BTC", "ETH"].map(|coin| {
+            let market = Market {
+                symbol: coin.to_string(),
+                active: true,
+                swap: true,
+                linear: true,
+                created_at: 0,
+                settle: "USDC".to_string(),
+            };
+            (coin.to_string(), market)
+        }));
+        let tickers = HashMap::from(["BTC", "ETH"].map(|coin| {
+            let ticker = Ticker {
+                symbol: coin.to_string(),
+                quote_volume: 5e7,
+                ..Default::default()
+            };
+            (coin.to_string(), ticker)
+        }));
+        assert_eq!(
+            eligible_markets(&markets, &tickers, &live, "USDC", |_| false),
+            ["BTC", "ETH"]
+        );
+        assert!(eligible_markets(&markets, &tickers, &live, "USDT", |_| false).is_empty());
     }
 }
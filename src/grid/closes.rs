@@ -1,10 +1,12 @@
 use crate::types::{
     BotSideConfig, ExchangeParams, GridOrder, OrderType, Position, StateParams, TrailingPriceBundle,
 };
+use crate::constants::LONG;
 use tracing::warn;
 use super::utils::{
-    calc_close_grid_backwards_long, calc_close_grid_backwards_short,
-    calc_close_grid_frontwards_long, calc_close_grid_frontwards_short,
+    calc_close_grid_backwards_long, calc_close_grid_backwards_short, calc_auto_unstuck_allowance,
+    calc_close_grid_frontwards_long, calc_close_grid_frontwards_short, calc_pnl_long,
+    calc_pnl_short, round_dn, round_up,
 };
 
 /// Calculates a trailing close order for a long position.
@@ -32,7 +34,10 @@ fn calc_trailing_close_long(
         && state_params.order_book.best_bid() < retracement_price
     {
         // Trailing stop triggered
-        let close_qty = position.size * bot_params.close_trailing_qty_pct;
+        let close_qty = round_dn(
+            position.size * bot_params.close_trailing_qty_pct,
+            exchange_params.qty_step,
+        );
         let close_price = state_params.order_book.best_bid();
 
         let min_qty = super::entries::calc_min_entry_qty(close_price, exchange_params);
@@ -53,6 +58,9 @@ pub fn calc_closes_long(
     exchange_params: &ExchangeParams, state_params: &StateParams, bot_params: &BotSideConfig,
     position: &Position, trailing_price_bundle: &TrailingPriceBundle,
 ) -> Vec<GridOrder> {
+    if position.size <= 0.0 {
+        return vec![];
+    }
     // Basic router: if trailing is enabled, use it. Otherwise, use grid.
     // A more sophisticated router like in entries.rs could be implemented later.
     if bot_params.close_trailing_threshold_pct > 0.0
@@ -85,7 +93,7 @@ pub fn calc_closes_long(
             exchange_params.min_qty,
             exchange_params.min_cost,
             exchange_params.c_mult,
-            bot_params.total_wallet_exposure_limit,
+            bot_params.close_exposure_limit(),
             bot_params.close_grid_min_markup,
             bot_params.close_grid_markup_range,
             bot_params.n_close_orders,
@@ -109,7 +117,7 @@ pub fn calc_closes_long(
             exchange_params.min_qty,
             exchange_params.min_cost,
             exchange_params.c_mult,
-            bot_params.total_wallet_exposure_limit,
+            bot_params.close_exposure_limit(),
             bot_params.close_grid_min_markup,
             bot_params.close_grid_markup_range,
             bot_params.n_close_orders,
@@ -162,7 +170,10 @@ fn calc_trailing_close_short(
         && state_params.order_book.best_ask() > retracement_price
     {
         // Trailing stop triggered
-        let close_qty = position.size.abs() * bot_params.close_trailing_qty_pct;
+        let close_qty = round_dn(
+            position.size.abs() * bot_params.close_trailing_qty_pct,
+            exchange_params.qty_step,
+        );
         let close_price = state_params.order_book.best_ask();
 
         let min_qty = super::entries::calc_min_entry_qty(close_price, exchange_params);
@@ -183,6 +194,9 @@ pub fn calc_closes_short(
     exchange_params: &ExchangeParams, state_params: &StateParams, bot_params: &BotSideConfig,
     position: &Position, trailing_price_bundle: &TrailingPriceBundle,
 ) -> Vec<GridOrder> {
+    if position.size >= 0.0 {
+        return vec![];
+    }
     if bot_params.close_trailing_threshold_pct > 0.0
         && bot_params.close_trailing_retracement_pct > 0.0
     {
@@ -213,7 +227,7 @@ pub fn calc_closes_short(
             exchange_params.min_qty,
             exchange_params.min_cost,
             exchange_params.c_mult,
-            bot_params.total_wallet_exposure_limit,
+            bot_params.close_exposure_limit(),
             bot_params.close_grid_min_markup,
             bot_params.close_grid_markup_range,
             bot_params.n_close_orders,
@@ -237,7 +251,7 @@ pub fn calc_closes_short(
             exchange_params.min_qty,
             exchange_params.min_cost,
             exchange_params.c_mult,
-            bot_params.total_wallet_exposure_limit,
+            bot_params.close_exposure_limit(),
             bot_params.close_grid_min_markup,
             bot_params.close_grid_markup_range,
             bot_params.n_close_orders,
@@ -264,9 +278,203 @@ pub fn calc_closes_short(
         )
         .collect()
 }
+/// Calculates an unstuck close for a long position held longer than `max_position_age_days`.
+///
+/// Aged positions are unstuck regardless of `unstuck_threshold`. The close is priced like a
+/// regular unstuck close, `unstuck_ema_dist` above the upper EMA band, for
+/// `unstuck_close_pct` of the position, and its loss is capped by the unstuck loss
+/// allowance. Realized PnL history is not tracked, so the allowance is relative to the
+/// current balance.
+///
+/// # Returns
+///
+/// The unstuck close, or None if the position is not aged, an unstuck close is already
+/// among `closes`, or a regular close would fill first.
+pub fn calc_aged_unstuck_close_long(
+    exchange_params: &ExchangeParams, state_params: &StateParams, bot_params: &BotSideConfig,
+    position: &Position, position_age_days: f64, closes: &[GridOrder],
+) -> Option<GridOrder> {
+    if bot_params.max_position_age_days <= 0.0
+        || position.size <= 0.0
+        || position_age_days < bot_params.max_position_age_days
+        || closes
+            .iter()
+            .any(|o| o.order_type == OrderType::CloseUnstuckLong)
+    {
+        return None;
+    }
+    let price = f64::max(
+        state_params.order_book.best_ask(),
+        round_up(
+            state_params.ema_bands.upper * (1.0 + bot_params.unstuck_ema_dist),
+            exchange_params.price_step,
+        ),
+    );
+    let lowest_close_price = closes
+        .iter()
+        .filter(|o| o.qty < 0.0)
+        .map(|o| o.price)
+        .fold(f64::INFINITY, f64::min);
+    if price >= lowest_close_price {
+        return None;
+    }
+    let mut qty = position.size * bot_params.unstuck_close_pct;
+    let loss_per_qty = -calc_pnl_long(
+        position.price,
+        price,
+        1.0,
+        exchange_params.inverse,
+        exchange_params.c_mult,
+    );
+    if loss_per_qty > 0.0 {
+        let allowance = calc_auto_unstuck_allowance(
+            state_params.balance,
+            bot_params.unstuck_loss_allowance_pct,
+            state_params.pnl_cumsum_max,
+            state_params.pnl_cumsum_last,
+        );
+        qty = qty.min(allowance / loss_per_qty);
+    }
+    let qty = round_dn(qty.min(position.size), exchange_params.qty_step);
+    if qty < super::entries::calc_min_entry_qty(price, exchange_params) {
+        return None;
+    }
+    Some(GridOrder {
+        qty: -qty,
+        price,
+        order_type: OrderType::CloseUnstuckLong,
+    })
+}
+
+/// Calculates an unstuck close for a short position held longer than
+/// `max_position_age_days`. Counterpart to `calc_aged_unstuck_close_long`, priced
+/// `unstuck_ema_dist` below the lower EMA band.
+pub fn calc_aged_unstuck_close_short(
+    exchange_params: &ExchangeParams, state_params: &StateParams, bot_params: &BotSideConfig,
+    position: &Position, position_age_days: f64, closes: &[GridOrder],
+) -> Option<GridOrder> {
+    if bot_params.max_position_age_days <= 0.0
+        || position.size >= 0.0
+        || position_age_days < bot_params.max_position_age_days
+        || closes
+            .iter()
+            .any(|o| o.order_type == OrderType::CloseUnstuckShort)
+    {
+        return None;
+    }
+    let ema_price = round_dn(
+        state_params.ema_bands.lower * (1.0 - bot_params.unstuck_ema_dist),
+        exchange_params.price_step,
+    );
+    let price = if ema_price > 0.0 {
+        f64::min(state_params.order_book.best_bid(), ema_price)
+    } else {
+        state_params.order_book.best_bid()
+    };
+    let highest_close_price = closes
+        .iter()
+        .filter(|o| o.qty > 0.0)
+        .map(|o| o.price)
+        .fold(0.0, f64::max);
+    if price <= highest_close_price {
+        return None;
+    }
+    let position_size = position.size.abs();
+    let mut qty = position_size * bot_params.unstuck_close_pct;
+    let loss_per_qty = -calc_pnl_short(
+        position.price,
+        price,
+        1.0,
+        exchange_params.inverse,
+        exchange_params.c_mult,
+    );
+    if loss_per_qty > 0.0 {
+        let allowance = calc_auto_unstuck_allowance(
+            state_params.balance,
+            bot_params.unstuck_loss_allowance_pct,
+            state_params.pnl_cumsum_max,
+            state_params.pnl_cumsum_last,
+        );
+        qty = qty.min(allowance / loss_per_qty);
+    }
+    let qty = round_dn(qty.min(position_size), exchange_params.qty_step);
+    if qty < super::entries::calc_min_entry_qty(price, exchange_params) {
+        return None;
+    }
+    Some(GridOrder {
+        qty,
+        price,
+        order_type: OrderType::CloseUnstuckShort,
+    })
+}
+
+/// The take-profit close of `pside` nearest to the market: the lowest long close or the
+/// highest short close. Unstuck closes are not take-profits.
+pub fn nearest_take_profit(orders: &[GridOrder], pside: usize) -> Option<GridOrder> {
+    let take_profits = orders.iter().filter(|o| {
+        o.order_type.is_close()
+            && !matches!(
+                o.order_type,
+                OrderType::CloseUnstuckLong | OrderType::CloseUnstuckShort
+            )
+            && if pside == LONG {
+                o.qty < 0.0
+            } else {
+                o.qty > 0.0
+            }
+    });
+    if pside == LONG {
+        take_profits
+            .min_by(|a, b| a.price.total_cmp(&b.price))
+            .copied()
+    } else {
+        take_profits
+            .max_by(|a, b| a.price.total_cmp(&b.price))
+            .copied()
+    }
+}
+
+/// Moves the nearest take-profit close so it is not priced through the break-even price.
+///
+/// Long closes (negative qty) are raised to at least `break_even_price`, short closes
+/// (positive qty) lowered to at most it. Unstuck closes are left alone, since they are
+/// meant to realize a controlled loss.
+pub fn floor_nearest_close_at_break_even(
+    closes: &mut [GridOrder], break_even_price: f64, price_step: f64,
+) {
+    if !break_even_price.is_finite() || break_even_price <= 0.0 {
+        return;
+    }
+    let nearest = closes
+        .iter_mut()
+        .filter(|o| {
+            o.qty != 0.0
+                && !matches!(
+                    o.order_type,
+                    OrderType::CloseUnstuckLong | OrderType::CloseUnstuckShort
+                )
+        })
+        .min_by(|a, b| {
+            let (a_dist, b_dist) = if a.qty < 0.0 {
+                (a.price, b.price)
+            } else {
+                (-a.price, -b.price)
+            };
+            a_dist.total_cmp(&b_dist)
+        });
+    if let Some(order) = nearest {
+        if order.qty < 0.0 {
+            order.price = f64::max(order.price, round_up(break_even_price, price_step));
+        } else {
+            order.price = f64::min(order.price, round_dn(break_even_price, price_step));
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::constants::SHORT;
     use crate::types::{BotSideConfig, EMABands, ExchangeParams, OrderBook, Position, StateParams};
 
     fn setup_test_params() -> (ExchangeParams, StateParams, BotSideConfig, Position) {
@@ -289,6 +497,8 @@ mod tests {
                 upper: 105.0,
                 lower: 95.0,
             },
+            atr: 0.0,
+            ..Default::default()
         };
 
         let bot_params = BotSideConfig {
@@ -336,6 +546,37 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_close_wallet_exposure_limit_sizes_the_close_grid() {
+        let (exchange_params, state_params, mut bot_params, position) = setup_test_params();
+        bot_params.backwards_tp = true;
+        let trailing_bundle = TrailingPriceBundle::default();
+        let closes = |bot_params: &BotSideConfig| {
+            calc_closes_long(
+                &exchange_params,
+                &state_params,
+                bot_params,
+                &position,
+                &trailing_bundle,
+            )
+            .iter()
+            .map(|o| (o.qty, o.price))
+            .collect::<Vec<_>>()
+        };
+        let shared = closes(&bot_params);
+        bot_params.close_wallet_exposure_limit = bot_params.total_wallet_exposure_limit;
+        assert_eq!(closes(&bot_params), shared);
+
+        // a full position for the close grid, spread over all of it, however high the
+        // entry side's limit
+        bot_params.close_wallet_exposure_limit = 0.1;
+        let independent = closes(&bot_params);
+        bot_params.total_wallet_exposure_limit = 0.1;
+        bot_params.close_wallet_exposure_limit = 0.0;
+        assert_eq!(independent, closes(&bot_params));
+        assert_ne!(independent, shared);
+    }
+
     #[test]
     fn test_calc_closes_long_backwards() {
         let (exchange_params, state_params, mut bot_params, position) = setup_test_params();
@@ -357,4 +598,94 @@ mod tests {
         let total_qty: f64 = closes.iter().map(|o| o.qty.abs()).sum();
         assert!((total_qty - position.size).abs() < exchange_params.qty_step);
     }
+
+    #[test]
+    fn test_floor_nearest_close_at_break_even() {
+        let (exchange_params, state_params, bot_params, position) = setup_test_params();
+        let trailing_bundle = TrailingPriceBundle::default();
+        let mut closes = calc_closes_long(
+            &exchange_params,
+            &state_params,
+            &bot_params,
+            &position,
+            &trailing_bundle,
+        );
+        let prices: Vec<f64> = closes.iter().map(|o| o.price).collect();
+        let break_even = prices[0] + 0.005;
+
+        floor_nearest_close_at_break_even(&mut closes, break_even, exchange_params.price_step);
+        assert_eq!(
+            closes[0].price,
+            round_up(break_even, exchange_params.price_step)
+        );
+        for (order, price) in closes.iter().zip(&prices).skip(1) {
+            assert_eq!(order.price, *price);
+        }
+
+        let mut short_closes = vec![GridOrder {
+            qty: 1.0,
+            price: 95.0,
+            order_type: OrderType::CloseGridShort,
+        }];
+        floor_nearest_close_at_break_even(&mut short_closes, 94.999, 0.01);
+        // fast-math rounding may be off by the last bit
+        assert!((short_closes[0].price - 94.99).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_nearest_take_profit() {
+        let order = |qty: f64, price: f64, order_type: OrderType| GridOrder {
+            qty,
+            price,
+            order_type,
+        };
+        let orders = vec![
+            order(0.1, 90.0, OrderType::EntryGridNormalLong),
+            order(-0.1, 103.0, OrderType::CloseGridLong),
+            order(-0.1, 101.0, OrderType::CloseGridLong),
+            order(-0.1, 95.0, OrderType::CloseUnstuckLong),
+            order(0.1, 97.0, OrderType::CloseGridShort),
+        ];
+        assert_eq!(nearest_take_profit(&orders, LONG).unwrap().price, 101.0);
+        assert_eq!(nearest_take_profit(&orders, SHORT).unwrap().price, 97.0);
+        assert!(nearest_take_profit(&orders[..1], LONG).is_none());
+    }
+
+    #[test]
+    fn test_calc_aged_unstuck_close_long() {
+        let (exchange_params, mut state_params, mut bot_params, position) = setup_test_params();
+        bot_params.unstuck_close_pct = 0.5;
+        bot_params.unstuck_loss_allowance_pct = 0.01;
+        state_params.order_book.asks = vec![[90.0, 1.0]];
+        state_params.ema_bands.upper = 89.0;
+        let closes = vec![GridOrder {
+            qty: -1.0,
+            price: 101.0,
+            order_type: OrderType::CloseGridLong,
+        }];
+
+        let aged_close = |bot_params: &BotSideConfig, age: f64| {
+            calc_aged_unstuck_close_long(
+                &exchange_params,
+                &state_params,
+                bot_params,
+                &position,
+                age,
+                &closes,
+            )
+        };
+        assert!(aged_close(&bot_params, 100.0).is_none());
+
+        bot_params.max_position_age_days = 7.0;
+        assert!(aged_close(&bot_params, 6.9).is_none());
+        let order = aged_close(&bot_params, 7.0).unwrap();
+        assert_eq!(order.order_type, OrderType::CloseUnstuckLong);
+        // unstuck price: max(90.0, round_up(89.0 * 1.01, 0.01)) = 90.0
+        assert_eq!(order.price, 90.0);
+        // half the position; the 10.0 loss allowance at 10.0 loss per unit would allow 1.0
+        assert_eq!(order.qty, -0.5);
+
+        bot_params.unstuck_loss_allowance_pct = 0.002;
+        assert_eq!(aged_close(&bot_params, 7.0).unwrap().qty, -0.2);
+    }
 }
@@ -3,18 +3,15 @@ use crate::types::{
     TrailingPriceBundle,
 };
 use super::utils::{
-    calc_ema_price_ask, calc_ema_price_bid, calc_new_psize_pprice, calc_wallet_exposure,
-    calc_wallet_exposure_if_filled, cost_to_qty, interpolate, round_, round_dn, round_up,
+    self, calc_ema_price_ask, calc_ema_price_bid, calc_new_psize_pprice, calc_wallet_exposure,
+    calc_wallet_exposure_if_filled, cost_to_qty, interpolate, qty_to_cost, round_, round_dn,
+    round_up,
 };
 use crate::grid::utils::{calc_pnl_long, calc_pnl_short};
-use std::cmp::Ordering;
 
-/// Iteratively finds an entry order quantity that brings the wallet exposure
-/// to the target limit defined in `bot_params.total_wallet_exposure_limit`.
-///
-/// This function uses an iterative approach (up to 15 iterations) to find an
-/// order quantity. It starts with a guess and refines it using interpolation
-/// until the resulting wallet exposure is within 1% of the target limit.
+/// Finds an entry order quantity that brings the wallet exposure to the limit defined in
+/// `bot_params.total_wallet_exposure_limit`; see
+/// [`utils::find_entry_qty_bringing_wallet_exposure_to_target`](super::utils::find_entry_qty_bringing_wallet_exposure_to_target).
 ///
 /// # Arguments
 ///
@@ -26,122 +23,21 @@ use std::cmp::Ordering;
 ///
 /// # Returns
 ///
-/// The calculated quantity that best matches the target wallet exposure.
+/// The multiple of the qty step bringing the exposure closest to the limit.
 /// Returns 0.0 if the current wallet exposure is already near or above the limit.
 pub fn find_entry_qty_bringing_wallet_exposure_to_target(
     exchange_params: &ExchangeParams, bot_params: &BotSideConfig, state_params: &StateParams,
     position: &Position, entry_price: f64,
 ) -> f64 {
-    let wallet_exposure = calc_wallet_exposure(
-        exchange_params.c_mult,
+    utils::find_entry_qty_bringing_wallet_exposure_to_target(
         state_params.balance,
         position.size,
         position.price,
-        exchange_params.inverse,
-    );
-
-    if wallet_exposure >= bot_params.total_wallet_exposure_limit * 0.99 {
-        return 0.0;
-    }
-
-    let mut guesses = vec![];
-    let mut vals = vec![];
-    let mut evals = vec![];
-
-    guesses.push(round_(
-        position.size.abs() * bot_params.total_wallet_exposure_limit
-            / f64::max(0.01, wallet_exposure),
-        exchange_params.qty_step,
-    ));
-    vals.push(calc_wallet_exposure_if_filled(
-        state_params.balance,
-        position.size,
-        position.price,
-        guesses.last().unwrap().clone(),
+        bot_params.total_wallet_exposure_limit,
         entry_price,
         exchange_params.inverse,
-        &exchange_params,
-    ));
-    evals.push(
-        (vals.last().unwrap().clone() - bot_params.total_wallet_exposure_limit).abs()
-            / bot_params.total_wallet_exposure_limit,
-    );
-
-    guesses.push(f64::max(
-        0.0,
-        round_(
-            f64::max(
-                guesses.last().unwrap().clone() * 1.2,
-                guesses.last().unwrap().clone() + exchange_params.qty_step,
-            ),
-            exchange_params.qty_step,
-        ),
-    ));
-    vals.push(calc_wallet_exposure_if_filled(
-        state_params.balance,
-        position.size,
-        position.price,
-        guesses.last().unwrap().clone(),
-        entry_price,
-        exchange_params.inverse,
-        &exchange_params,
-    ));
-    evals.push(
-        (vals.last().unwrap().clone() - bot_params.total_wallet_exposure_limit).abs()
-            / bot_params.total_wallet_exposure_limit,
-    );
-
-    for _ in 0..15 {
-        if guesses.last().unwrap() == &guesses[guesses.len() - 2] {
-            guesses.push(
-                (guesses[guesses.len() - 2] * 1.1)
-                    .max(guesses[guesses.len() - 2] + exchange_params.qty_step)
-                    .abs(),
-            );
-            vals.push(calc_wallet_exposure_if_filled(
-                state_params.balance,
-                position.size,
-                position.price,
-                guesses.last().unwrap().clone(),
-                entry_price,
-                exchange_params.inverse,
-                &exchange_params,
-            ));
-        }
-        guesses.push(f64::max(
-            0.0,
-            round_(
-                interpolate(
-                    bot_params.total_wallet_exposure_limit,
-                    &vals[vals.len() - 2..],
-                    &guesses[guesses.len() - 2..],
-                ),
-                exchange_params.qty_step,
-            ),
-        ));
-        vals.push(calc_wallet_exposure_if_filled(
-            state_params.balance,
-            position.size,
-            position.price,
-            guesses.last().unwrap().clone(),
-            entry_price,
-            exchange_params.inverse,
-            &exchange_params,
-        ));
-        evals.push(
-            (vals.last().unwrap().clone() - bot_params.total_wallet_exposure_limit).abs()
-                / bot_params.total_wallet_exposure_limit,
-        );
-
-        if evals.last().unwrap() < &0.01 {
-            break;
-        }
-    }
-
-    let mut evals_guesses: Vec<_> = evals.iter().zip(guesses.iter()).collect();
-    evals_guesses.sort_by(|a, b| a.0.partial_cmp(b.0).unwrap_or(Ordering::Equal));
-
-    *evals_guesses[0].1
+        exchange_params,
+    )
 }
 
 /// Calculates an "auto unstuck" entry order for a long position.
@@ -268,6 +164,168 @@ pub fn calc_initial_entry_qty(
     )
 }
 
+/// Number of orders the initial entry is split into: `entry_initial_n_splits`, reduced so
+/// that every split still meets the exchange minimum.
+pub fn calc_initial_entry_n_splits(
+    initial_entry_qty: f64, min_entry_qty: f64, bot_params: &BotSideConfig,
+) -> usize {
+    let max_splits = (initial_entry_qty / min_entry_qty).floor().max(1.0);
+    bot_params
+        .entry_initial_n_splits
+        .round()
+        .clamp(1.0, max_splits) as usize
+}
+
+/// Calculates the next order of a long initial entry, or `None` once the initial entry
+/// is complete.
+///
+/// The initial entry may be split into `entry_initial_n_splits` equal orders spread
+/// `entry_initial_split_band_pct` below the initial entry price, the first at the entry
+/// price itself. The position counts as one aggregate initial entry, so each filled split
+/// moves the next order one step down the band until the whole quantity is filled.
+pub fn calc_initial_entry_long(
+    exchange_params: &ExchangeParams, bot_params: &BotSideConfig, initial_entry_price: f64,
+    initial_entry_qty: f64, position_size: f64,
+) -> Option<GridOrder> {
+    let min_entry_qty = calc_min_entry_qty(initial_entry_price, exchange_params);
+    let n_splits = calc_initial_entry_n_splits(initial_entry_qty, min_entry_qty, bot_params);
+    if n_splits == 1 && position_size == 0.0 {
+        return Some(GridOrder {
+            qty: initial_entry_qty,
+            price: initial_entry_price,
+            order_type: OrderType::EntryInitialNormalLong,
+        });
+    }
+    let split_qty = initial_entry_qty / n_splits as f64;
+    if position_size >= initial_entry_qty - split_qty * 0.2 {
+        return None;
+    }
+    let split_idx = ((position_size / split_qty).floor() as usize).min(n_splits - 1);
+    let target_qty = if split_idx + 1 == n_splits {
+        initial_entry_qty
+    } else {
+        split_qty * (split_idx + 1) as f64
+    };
+    let price = if split_idx == 0 {
+        initial_entry_price
+    } else {
+        round_dn(
+            initial_entry_price
+                * (1.0
+                    - bot_params.entry_initial_split_band_pct * split_idx as f64
+                        / (n_splits - 1) as f64),
+            exchange_params.price_step,
+        )
+    };
+    Some(GridOrder {
+        qty: f64::max(
+            calc_min_entry_qty(price, exchange_params),
+            round_dn(target_qty - position_size, exchange_params.qty_step),
+        ),
+        price,
+        order_type: if position_size == 0.0 {
+            OrderType::EntryInitialNormalLong
+        } else {
+            OrderType::EntryInitialPartialLong
+        },
+    })
+}
+
+/// Short-side counterpart of `calc_initial_entry_long`, with splits spread above the
+/// initial entry price. Takes the absolute position size and returns a negative qty.
+pub fn calc_initial_entry_short(
+    exchange_params: &ExchangeParams, bot_params: &BotSideConfig, initial_entry_price: f64,
+    initial_entry_qty: f64, position_size_abs: f64,
+) -> Option<GridOrder> {
+    let min_entry_qty = calc_min_entry_qty(initial_entry_price, exchange_params);
+    let n_splits = calc_initial_entry_n_splits(initial_entry_qty, min_entry_qty, bot_params);
+    if n_splits == 1 && position_size_abs == 0.0 {
+        return Some(GridOrder {
+            qty: -initial_entry_qty,
+            price: initial_entry_price,
+            order_type: OrderType::EntryInitialNormalShort,
+        });
+    }
+    let split_qty = initial_entry_qty / n_splits as f64;
+    if position_size_abs >= initial_entry_qty - split_qty * 0.2 {
+        return None;
+    }
+    let split_idx = ((position_size_abs / split_qty).floor() as usize).min(n_splits - 1);
+    let target_qty = if split_idx + 1 == n_splits {
+        initial_entry_qty
+    } else {
+        split_qty * (split_idx + 1) as f64
+    };
+    let price = if split_idx == 0 {
+        initial_entry_price
+    } else {
+        round_up(
+            initial_entry_price
+                * (1.0
+                    + bot_params.entry_initial_split_band_pct * split_idx as f64
+                        / (n_splits - 1) as f64),
+            exchange_params.price_step,
+        )
+    };
+    Some(GridOrder {
+        qty: -f64::max(
+            calc_min_entry_qty(price, exchange_params),
+            round_dn(target_qty - position_size_abs, exchange_params.qty_step),
+        ),
+        price,
+        order_type: if position_size_abs == 0.0 {
+            OrderType::EntryInitialNormalShort
+        } else {
+            OrderType::EntryInitialPartialShort
+        },
+    })
+}
+
+/// Entry parameters for an adopted position, i.e. one opened outside the bot.
+///
+/// A position smaller than the initial entry counts as a completed initial entry: the
+/// initial entry qty is lowered to the position size at `entry_price`, so the grid goes on
+/// with normal re-entries instead of topping the position up. Larger positions already
+/// map onto a grid level and keep the configured parameters.
+pub fn calc_adopted_params(
+    exchange_params: &ExchangeParams, bot_params: &BotSideConfig, balance: f64,
+    position_size_abs: f64, entry_price: f64,
+) -> BotSideConfig {
+    let mut adopted = bot_params.clone();
+    let max_cost = balance * bot_params.total_wallet_exposure_limit;
+    if max_cost <= 0.0 {
+        return adopted;
+    }
+    let position_cost = qty_to_cost(
+        position_size_abs,
+        entry_price,
+        exchange_params.inverse,
+        exchange_params.c_mult,
+    );
+    adopted.entry_initial_qty_pct = bot_params
+        .entry_initial_qty_pct
+        .min(position_cost / max_cost);
+    adopted
+}
+
+/// Converts ATR multiples in `entry_trailing_threshold_pct` and
+/// `entry_trailing_retracement_pct` into fractions of `price`.
+///
+/// While the ATR is unknown both become infinite, so trailing entries stay idle instead
+/// of firing on zero values.
+pub fn calc_atr_trailing_params(bot_params: &BotSideConfig, atr: f64, price: f64) -> BotSideConfig {
+    let mut atr_params = bot_params.clone();
+    if atr > 0.0 && price > 0.0 {
+        let atr_pct = atr / price;
+        atr_params.entry_trailing_threshold_pct *= atr_pct;
+        atr_params.entry_trailing_retracement_pct *= atr_pct;
+    } else {
+        atr_params.entry_trailing_threshold_pct = f64::INFINITY;
+        atr_params.entry_trailing_retracement_pct = f64::INFINITY;
+    }
+    atr_params
+}
+
 /// Determines the minimum allowed entry quantity.
 ///
 /// This is the greater of the exchange's absolute minimum quantity (`min_qty`)
@@ -359,7 +417,8 @@ pub fn calc_cropped_reentry_qty(
 /// Calculates the quantity for a standard reentry order.
 ///
 /// The reentry quantity is the larger of two values:
-/// 1. A multiple of the current position size (`entry_grid_double_down_factor`).
+/// 1. A multiple of the current position size, the double down factor scheduled for the
+///    position's grid level (see `calc_double_down_factor`).
 /// 2. A quantity based on the initial entry quantity percentage (`entry_initial_qty_pct`).
 ///
 /// This ensures that reentry orders scale with the position size while also having a
@@ -371,6 +430,7 @@ pub fn calc_cropped_reentry_qty(
 /// * `entry_price` - The price of the proposed reentry.
 /// * `balance` - Current account balance.
 /// * `position_size` - The absolute size of the current position.
+/// * `level` - The grid level of the current position.
 /// * `exchange_params` - General parameters for the exchange.
 /// * `bot_params` - Configuration for the bot's side.
 ///
@@ -378,14 +438,14 @@ pub fn calc_cropped_reentry_qty(
 ///
 /// The calculated quantity for the reentry order.
 pub fn calc_reentry_qty(
-    entry_price: f64, balance: f64, position_size: f64, exchange_params: &ExchangeParams,
-    bot_params: &BotSideConfig,
+    entry_price: f64, balance: f64, position_size: f64, level: usize,
+    exchange_params: &ExchangeParams, bot_params: &BotSideConfig,
 ) -> f64 {
     f64::max(
         calc_min_entry_qty(entry_price, &exchange_params),
         round_(
             f64::max(
-                position_size.abs() * bot_params.entry_grid_double_down_factor,
+                position_size.abs() * calc_double_down_factor(level, bot_params),
                 cost_to_qty(
                     balance,
                     entry_price,
@@ -399,18 +459,82 @@ pub fn calc_reentry_qty(
     )
 }
 
+/// Estimates the grid level of a position, i.e. how many reentries it has taken.
+///
+/// Each reentry at level `k` grows the position by roughly `1 + calc_double_down_factor(k)`,
+/// so the level is recovered by walking the double down schedule until the expected size
+/// passes the ratio of the position size to the initial entry qty (rounded in log space).
+///
+/// # Arguments
+///
+/// * `position_size` - The size of the current position.
+/// * `initial_entry_qty` - The quantity of the initial entry.
+/// * `bot_params` - Configuration for the bot's side.
+///
+/// # Returns
+///
+/// The grid level, 0 for a position holding only the initial entry.
+pub fn calc_grid_level(
+    position_size: f64, initial_entry_qty: f64, bot_params: &BotSideConfig,
+) -> usize {
+    const MAX_LEVEL: usize = 1000;
+    let ratio = position_size.abs() / initial_entry_qty;
+    if ratio.is_nan() {
+        return 0;
+    }
+    let mut level = 0;
+    let mut expected_ratio = 1.0;
+    while level < MAX_LEVEL {
+        let next_ratio = expected_ratio * (1.0 + calc_double_down_factor(level, bot_params));
+        if next_ratio <= expected_ratio || ratio < (expected_ratio * next_ratio).sqrt() {
+            break;
+        }
+        expected_ratio = next_ratio;
+        level += 1;
+    }
+    level
+}
+
+/// Returns the double down factor for a reentry at a given grid level.
+///
+/// Explicit `entry_grid_double_down_factors` take precedence, with the last value
+/// repeated for deeper levels. Otherwise the factor is `entry_grid_double_down_factor`
+/// scaled by `(1 + level)^entry_grid_double_down_exponent`.
+pub fn calc_double_down_factor(level: usize, bot_params: &BotSideConfig) -> f64 {
+    let factors = &bot_params.entry_grid_double_down_factors;
+    if let Some(&last) = factors.last() {
+        return factors.get(level).copied().unwrap_or(last);
+    }
+    bot_params.entry_grid_double_down_factor
+        * ((1 + level) as f64).powf(bot_params.entry_grid_double_down_exponent)
+}
+
+/// Returns the multiplier applied to `entry_grid_spacing_pct` at a given grid level.
+///
+/// Explicit `entry_grid_spacing_multipliers` take precedence, with the last value
+/// repeated for deeper levels. Otherwise the spacing grows as
+/// `(1 + level)^entry_grid_spacing_exponent`, which is constant for an exponent of 0.
+pub fn calc_grid_spacing_multiplier(level: usize, bot_params: &BotSideConfig) -> f64 {
+    let multipliers = &bot_params.entry_grid_spacing_multipliers;
+    if let Some(&last) = multipliers.last() {
+        return multipliers.get(level).copied().unwrap_or(last);
+    }
+    ((1 + level) as f64).powf(bot_params.entry_grid_spacing_exponent)
+}
+
 /// Calculates the next grid reentry price for a long position (bid side).
 ///
 /// The price is determined by stepping down from the current position price. The step size
-/// is based on `entry_grid_spacing_pct` and is weighted by how much of the wallet
-/// exposure limit has been used (`entry_grid_spacing_weight`). This creates a dynamic
-/// grid that spreads out as more capital is deployed. The final price is capped by the
-/// current best bid in the order book.
+/// is based on `entry_grid_spacing_pct`, scaled per grid level by the spacing curve and
+/// weighted by how much of the wallet exposure limit has been used
+/// (`entry_grid_spacing_weight`). This creates a dynamic grid that spreads out as more
+/// capital is deployed. The final price is capped by the current best bid in the order book.
 ///
 /// # Arguments
 ///
 /// * `position_price` - The average price of the current position.
 /// * `wallet_exposure` - The current wallet exposure.
+/// * `level` - The grid level of the current position.
 /// * `order_book_bid` - The best bid price from the order book.
 /// * `exchange_params` - General parameters for the exchange.
 /// * `bot_params` - Configuration for the bot's side.
@@ -419,14 +543,16 @@ pub fn calc_reentry_qty(
 ///
 /// The calculated reentry price for the bid side.
 fn calc_reentry_price_bid(
-    position_price: f64, wallet_exposure: f64, order_book_bid: f64,
+    position_price: f64, wallet_exposure: f64, level: usize, order_book_bid: f64,
     exchange_params: &ExchangeParams, bot_params: &BotSideConfig,
 ) -> f64 {
     let multiplier = (wallet_exposure / bot_params.total_wallet_exposure_limit)
         * bot_params.entry_grid_spacing_weight;
+    let spacing_pct =
+        bot_params.entry_grid_spacing_pct * calc_grid_spacing_multiplier(level, bot_params);
     let reentry_price = f64::min(
         round_dn(
-            position_price * (1.0 - bot_params.entry_grid_spacing_pct * (1.0 + multiplier)),
+            position_price * (1.0 - spacing_pct * (1.0 + multiplier)),
             exchange_params.price_step,
         ),
         order_book_bid,
@@ -441,13 +567,15 @@ fn calc_reentry_price_bid(
 /// Calculates the next grid reentry price for a short position (ask side).
 ///
 /// This is the counterpart to `calc_reentry_price_bid`. The price is determined by
-/// stepping up from the current position price, with a dynamic step size weighted by
-/// wallet exposure. The final price is capped by the current best ask in the order book.
+/// stepping up from the current position price, with a dynamic step size scaled by grid
+/// level and weighted by wallet exposure. The final price is capped by the current best
+/// ask in the order book.
 ///
 /// # Arguments
 ///
 /// * `position_price` - The average price of the current position.
 /// * `wallet_exposure` - The current wallet exposure.
+/// * `level` - The grid level of the current position.
 /// * `order_book_ask` - The best ask price from the order book.
 /// * `exchange_params` - General parameters for the exchange.
 /// * `bot_params` - Configuration for the bot's side.
@@ -456,14 +584,16 @@ fn calc_reentry_price_bid(
 ///
 /// The calculated reentry price for the ask side.
 fn calc_reentry_price_ask(
-    position_price: f64, wallet_exposure: f64, order_book_ask: f64,
+    position_price: f64, wallet_exposure: f64, level: usize, order_book_ask: f64,
     exchange_params: &ExchangeParams, bot_params: &BotSideConfig,
 ) -> f64 {
     let multiplier = (wallet_exposure / bot_params.total_wallet_exposure_limit)
         * bot_params.entry_grid_spacing_weight;
+    let spacing_pct =
+        bot_params.entry_grid_spacing_pct * calc_grid_spacing_multiplier(level, bot_params);
     let reentry_price = f64::max(
         round_up(
-            position_price * (1.0 + bot_params.entry_grid_spacing_pct * (1.0 + multiplier)),
+            position_price * (1.0 + spacing_pct * (1.0 + multiplier)),
             exchange_params.price_step,
         ),
         order_book_ask,
@@ -519,21 +649,14 @@ pub fn calc_grid_entry_long(
         state_params.balance,
         initial_entry_price,
     );
-    if position.size == 0.0 {
-        return GridOrder {
-            qty: initial_entry_qty,
-            price: initial_entry_price,
-            order_type: OrderType::EntryInitialNormalLong,
-        };
-    } else if position.size < initial_entry_qty * 0.8 {
-        return GridOrder {
-            qty: f64::max(
-                calc_min_entry_qty(initial_entry_price, &exchange_params),
-                round_dn(initial_entry_qty - position.size, exchange_params.qty_step),
-            ),
-            price: initial_entry_price,
-            order_type: OrderType::EntryInitialPartialLong,
-        };
+    if let Some(initial_entry) = calc_initial_entry_long(
+        exchange_params,
+        bot_params,
+        initial_entry_price,
+        initial_entry_qty,
+        position.size,
+    ) {
+        return initial_entry;
     }
     let wallet_exposure = calc_wallet_exposure(
         exchange_params.c_mult,
@@ -547,9 +670,11 @@ pub fn calc_grid_entry_long(
     }
 
     // normal re-entry
+    let level = calc_grid_level(position.size, initial_entry_qty, bot_params);
     let reentry_price = calc_reentry_price_bid(
         position.price,
         wallet_exposure,
+        level,
         state_params.order_book.best_bid(),
         exchange_params,
         bot_params,
@@ -562,6 +687,7 @@ pub fn calc_grid_entry_long(
             reentry_price,
             state_params.balance,
             position.size,
+            level,
             exchange_params,
             bot_params,
         ),
@@ -591,9 +717,11 @@ pub fn calc_grid_entry_long(
         reentry_price,
         exchange_params.qty_step,
     );
+    let next_level = calc_grid_level(psize_if_filled, initial_entry_qty, bot_params);
     let next_reentry_price = calc_reentry_price_bid(
         pprice_if_filled,
         wallet_exposure_if_filled,
+        next_level,
         state_params.order_book.best_bid(),
         exchange_params,
         bot_params,
@@ -603,6 +731,7 @@ pub fn calc_grid_entry_long(
             next_reentry_price,
             state_params.balance,
             psize_if_filled,
+            next_level,
             exchange_params,
             bot_params,
         ),
@@ -621,7 +750,7 @@ pub fn calc_grid_entry_long(
         next_reentry_price,
     );
     let effective_double_down_factor = next_reentry_qty_cropped / psize_if_filled;
-    if effective_double_down_factor < bot_params.entry_grid_double_down_factor * 0.25 {
+    if effective_double_down_factor < calc_double_down_factor(next_level, bot_params) * 0.25 {
         // next reentry too small. Inflate current reentry.
         let new_entry_qty = interpolate(
             bot_params.total_wallet_exposure_limit,
@@ -794,22 +923,14 @@ pub fn calc_trailing_entry_long(
         state_params.balance,
         initial_entry_price,
     );
-    if position.size == 0.0 {
-        // normal initial entry
-        return GridOrder {
-            qty: initial_entry_qty,
-            price: initial_entry_price,
-            order_type: OrderType::EntryInitialNormalLong,
-        };
-    } else if position.size < initial_entry_qty * 0.8 {
-        return GridOrder {
-            qty: f64::max(
-                calc_min_entry_qty(initial_entry_price, &exchange_params),
-                round_dn(initial_entry_qty - position.size, exchange_params.qty_step),
-            ),
-            price: initial_entry_price,
-            order_type: OrderType::EntryInitialPartialLong,
-        };
+    if let Some(initial_entry) = calc_initial_entry_long(
+        exchange_params,
+        bot_params,
+        initial_entry_price,
+        initial_entry_qty,
+        position.size,
+    ) {
+        return initial_entry;
     }
     let wallet_exposure = calc_wallet_exposure(
         exchange_params.c_mult,
@@ -878,6 +999,7 @@ pub fn calc_trailing_entry_long(
             reentry_price,
             state_params.balance,
             position.size,
+            calc_grid_level(position.size, initial_entry_qty, bot_params),
             &exchange_params,
             &bot_params,
         ),
@@ -947,24 +1069,14 @@ pub fn calc_grid_entry_short(
         initial_entry_price,
     );
     let position_size_abs = position.size.abs();
-    if position_size_abs == 0.0 {
-        return GridOrder {
-            qty: -initial_entry_qty,
-            price: initial_entry_price,
-            order_type: OrderType::EntryInitialNormalShort,
-        };
-    } else if position_size_abs < initial_entry_qty * 0.8 {
-        return GridOrder {
-            qty: -f64::max(
-                calc_min_entry_qty(initial_entry_price, &exchange_params),
-                round_dn(
-                    initial_entry_qty - position_size_abs,
-                    exchange_params.qty_step,
-                ),
-            ),
-            price: initial_entry_price,
-            order_type: OrderType::EntryInitialPartialShort,
-        };
+    if let Some(initial_entry) = calc_initial_entry_short(
+        exchange_params,
+        bot_params,
+        initial_entry_price,
+        initial_entry_qty,
+        position_size_abs,
+    ) {
+        return initial_entry;
     }
     let wallet_exposure = calc_wallet_exposure(
         exchange_params.c_mult,
@@ -978,9 +1090,11 @@ pub fn calc_grid_entry_short(
     }
 
     // normal re-entry
+    let level = calc_grid_level(position.size, initial_entry_qty, bot_params);
     let reentry_price = calc_reentry_price_ask(
         position.price,
         wallet_exposure,
+        level,
         state_params.order_book.best_ask(),
         exchange_params,
         bot_params,
@@ -993,6 +1107,7 @@ pub fn calc_grid_entry_short(
             reentry_price,
             state_params.balance,
             position_size_abs,
+            level,
             exchange_params,
             bot_params,
         ),
@@ -1022,9 +1137,11 @@ pub fn calc_grid_entry_short(
         reentry_price,
         exchange_params.qty_step,
     );
+    let next_level = calc_grid_level(psize_if_filled, initial_entry_qty, bot_params);
     let next_reentry_price = calc_reentry_price_ask(
         pprice_if_filled,
         wallet_exposure_if_filled,
+        next_level,
         state_params.order_book.best_ask(),
         exchange_params,
         bot_params,
@@ -1034,6 +1151,7 @@ pub fn calc_grid_entry_short(
             next_reentry_price,
             state_params.balance,
             psize_if_filled,
+            next_level,
             exchange_params,
             bot_params,
         ),
@@ -1052,7 +1170,7 @@ pub fn calc_grid_entry_short(
         next_reentry_price,
     );
     let effective_double_down_factor = next_reentry_qty_cropped / psize_if_filled;
-    if effective_double_down_factor < bot_params.entry_grid_double_down_factor * 0.25 {
+    if effective_double_down_factor < calc_double_down_factor(next_level, bot_params) * 0.25 {
         // next reentry too small. Inflate current reentry.
         let new_entry_qty = interpolate(
             bot_params.total_wallet_exposure_limit,
@@ -1111,25 +1229,14 @@ pub fn calc_trailing_entry_short(
         initial_entry_price,
     );
     let position_size_abs = position.size.abs();
-    if position_size_abs == 0.0 {
-        // normal initial entry
-        return GridOrder {
-            qty: -initial_entry_qty,
-            price: initial_entry_price,
-            order_type: OrderType::EntryInitialNormalShort,
-        };
-    } else if position_size_abs < initial_entry_qty * 0.8 {
-        return GridOrder {
-            qty: -f64::max(
-                calc_min_entry_qty(initial_entry_price, &exchange_params),
-                round_dn(
-                    initial_entry_qty - position_size_abs,
-                    exchange_params.qty_step,
-                ),
-            ),
-            price: initial_entry_price,
-            order_type: OrderType::EntryInitialPartialShort,
-        };
+    if let Some(initial_entry) = calc_initial_entry_short(
+        exchange_params,
+        bot_params,
+        initial_entry_price,
+        initial_entry_qty,
+        position_size_abs,
+    ) {
+        return initial_entry;
     }
     let wallet_exposure = calc_wallet_exposure(
         exchange_params.c_mult,
@@ -1198,6 +1305,7 @@ pub fn calc_trailing_entry_short(
             reentry_price,
             state_params.balance,
             position_size_abs,
+            calc_grid_level(position_size_abs, initial_entry_qty, bot_params),
             &exchange_params,
             &bot_params,
         ),
@@ -1362,6 +1470,17 @@ pub fn calc_entries_long(
     exchange_params: &ExchangeParams, state_params: &StateParams, bot_params: &BotSideConfig,
     position: &Position, trailing_price_bundle: &TrailingPriceBundle,
 ) -> Vec<GridOrder> {
+    let atr_params;
+    let bot_params = if bot_params.entry_trailing_atr_mode {
+        atr_params = calc_atr_trailing_params(
+            bot_params,
+            state_params.atr,
+            state_params.order_book.best_bid(),
+        );
+        &atr_params
+    } else {
+        bot_params
+    };
     let mut entries = Vec::<GridOrder>::new();
 
     let pos_pnl_pct = calc_pnl_long(
@@ -1395,6 +1514,9 @@ pub fn calc_entries_long(
         let state_params_mod = StateParams {
             balance: state_params.balance,
             ema_bands: state_params.ema_bands.clone(),
+            atr: state_params.atr,
+            pnl_cumsum_max: state_params.pnl_cumsum_max,
+            pnl_cumsum_last: state_params.pnl_cumsum_last,
             order_book: OrderBook {
                 asks: vec![],
                 bids: vec![[bid, 0.0]],
@@ -1454,6 +1576,17 @@ pub fn calc_entries_short(
     exchange_params: &ExchangeParams, state_params: &StateParams, bot_params: &BotSideConfig,
     position: &Position, trailing_price_bundle: &TrailingPriceBundle,
 ) -> Vec<GridOrder> {
+    let atr_params;
+    let bot_params = if bot_params.entry_trailing_atr_mode {
+        atr_params = calc_atr_trailing_params(
+            bot_params,
+            state_params.atr,
+            state_params.order_book.best_ask(),
+        );
+        &atr_params
+    } else {
+        bot_params
+    };
     let mut entries = Vec::<GridOrder>::new();
 
     let pos_pnl_pct = calc_pnl_short(
@@ -1487,6 +1620,9 @@ pub fn calc_entries_short(
         let state_params_mod = StateParams {
             balance: state_params.balance,
             ema_bands: state_params.ema_bands.clone(),
+            atr: state_params.atr,
+            pnl_cumsum_max: state_params.pnl_cumsum_max,
+            pnl_cumsum_last: state_params.pnl_cumsum_last,
             order_book: OrderBook {
                 asks: vec![[ask, 0.0]],
                 bids: vec![],
@@ -1558,6 +1694,8 @@ mod tests {
                 upper: 105.0,
                 lower: 95.0,
             },
+            atr: 0.0,
+            ..Default::default()
         };
 
         let bot_params = BotSideConfig {
@@ -1616,6 +1754,101 @@ mod tests {
         assert_eq!(initial_qty, 1.0);
     }
 
+    #[test]
+    fn test_initial_entry_splits() {
+        let (exchange_params, _, mut bot_params, _, _) = setup_test_params();
+        let next = |bot_params: &BotSideConfig, position_size: f64| {
+            calc_initial_entry_long(&exchange_params, bot_params, 100.0, 1.0, position_size)
+                .map(|o| (o.qty, o.price, o.order_type))
+        };
+        // unsplit: one order, completed by a partial after a partial fill
+        assert_eq!(
+            next(&bot_params, 0.0),
+            Some((1.0, 100.0, OrderType::EntryInitialNormalLong))
+        );
+        assert_eq!(
+            next(&bot_params, 0.5),
+            Some((0.5, 100.0, OrderType::EntryInitialPartialLong))
+        );
+        assert_eq!(next(&bot_params, 0.8), None);
+
+        // 4 splits of 0.25 spread over 3% below the entry price
+        bot_params.entry_initial_n_splits = 4.0;
+        bot_params.entry_initial_split_band_pct = 0.03;
+        assert_eq!(
+            next(&bot_params, 0.0),
+            Some((0.25, 100.0, OrderType::EntryInitialNormalLong))
+        );
+        assert_eq!(
+            next(&bot_params, 0.25),
+            Some((0.25, 99.0, OrderType::EntryInitialPartialLong))
+        );
+        assert_eq!(
+            next(&bot_params, 0.6),
+            Some((0.15, 98.0, OrderType::EntryInitialPartialLong))
+        );
+        assert_eq!(
+            next(&bot_params, 0.75),
+            Some((0.25, 97.0, OrderType::EntryInitialPartialLong))
+        );
+        assert_eq!(next(&bot_params, 0.96), None);
+    }
+
+    #[test]
+    fn test_calc_adopted_params() {
+        let (exchange_params, state_params, bot_params, _, _) = setup_test_params();
+        // a manual position at a third of the initial entry qty
+        let position = Position {
+            size: 0.3,
+            price: 100.0,
+        };
+        let entry = calc_grid_entry_long(&exchange_params, &state_params, &bot_params, &position);
+        assert_eq!(entry.order_type, OrderType::EntryInitialPartialLong);
+
+        let adopted_params = calc_adopted_params(
+            &exchange_params,
+            &bot_params,
+            state_params.balance,
+            position.size,
+            state_params.order_book.best_bid(),
+        );
+        let entry =
+            calc_grid_entry_long(&exchange_params, &state_params, &adopted_params, &position);
+        assert!(entry.qty > 0.0);
+        assert!(!matches!(
+            entry.order_type,
+            OrderType::EntryInitialNormalLong | OrderType::EntryInitialPartialLong
+        ));
+        assert!(entry.price < position.price);
+
+        // positions beyond the initial entry keep the configured parameters
+        let adopted_params = calc_adopted_params(
+            &exchange_params,
+            &bot_params,
+            state_params.balance,
+            3.0,
+            state_params.order_book.best_bid(),
+        );
+        assert_eq!(
+            adopted_params.entry_initial_qty_pct,
+            bot_params.entry_initial_qty_pct
+        );
+    }
+
+    #[test]
+    fn test_calc_atr_trailing_params() {
+        let (_, _, mut bot_params, _, _) = setup_test_params();
+        bot_params.entry_trailing_threshold_pct = 2.0;
+        bot_params.entry_trailing_retracement_pct = 0.5;
+        let atr_params = calc_atr_trailing_params(&bot_params, 1.0, 100.0);
+        assert_eq!(atr_params.entry_trailing_threshold_pct, 0.02);
+        assert_eq!(atr_params.entry_trailing_retracement_pct, 0.005);
+
+        let idle = calc_atr_trailing_params(&bot_params, 0.0, 100.0);
+        assert_eq!(idle.entry_trailing_threshold_pct, f64::INFINITY);
+        assert_eq!(idle.entry_trailing_retracement_pct, f64::INFINITY);
+    }
+
     #[test]
     fn test_calc_reentry_price_bid() {
         let (exchange_params, state_params, bot_params, position, _) = setup_test_params();
@@ -1623,6 +1856,7 @@ mod tests {
         let reentry_price = calc_reentry_price_bid(
             position.price,
             wallet_exposure,
+            0,
             state_params.order_book.best_bid(),
             &exchange_params,
             &bot_params,
@@ -1642,6 +1876,7 @@ mod tests {
         let reentry_price = calc_reentry_price_ask(
             position.price,
             wallet_exposure,
+            0,
             state_params.order_book.best_ask(),
             &exchange_params,
             &bot_params,
@@ -1654,6 +1889,62 @@ mod tests {
         assert_eq!(reentry_price, 101.5);
     }
 
+    #[test]
+    fn test_grid_spacing_curve() {
+        let (exchange_params, state_params, mut bot_params, position, _) = setup_test_params();
+        // initial qty 1.0 and double down factor 2.0: each level triples the position
+        assert_eq!(calc_grid_level(1.0, 1.0, &bot_params), 0);
+        assert_eq!(calc_grid_level(3.0, 1.0, &bot_params), 1);
+        assert_eq!(calc_grid_level(-9.0, 1.0, &bot_params), 2);
+        assert_eq!(calc_grid_spacing_multiplier(3, &bot_params), 1.0);
+
+        bot_params.entry_grid_spacing_exponent = 1.0;
+        assert_eq!(calc_grid_spacing_multiplier(2, &bot_params), 3.0);
+
+        bot_params.entry_grid_spacing_multipliers = vec![1.0, 1.3, 1.7, 2.2];
+        assert_eq!(calc_grid_spacing_multiplier(1, &bot_params), 1.3);
+        assert_eq!(calc_grid_spacing_multiplier(10, &bot_params), 2.2);
+
+        // level 2 spacing: 0.01 * 1.7 * (1 + 0.5) = 0.0255
+        let reentry_price = calc_reentry_price_bid(
+            position.price,
+            5.0,
+            2,
+            state_params.order_book.best_bid(),
+            &exchange_params,
+            &bot_params,
+        );
+        assert_eq!(reentry_price, 97.45);
+    }
+
+    #[test]
+    fn test_double_down_schedule() {
+        let (exchange_params, state_params, mut bot_params, position, _) = setup_test_params();
+        assert_eq!(calc_double_down_factor(4, &bot_params), 2.0);
+
+        bot_params.entry_grid_double_down_exponent = 1.0;
+        assert_eq!(calc_double_down_factor(1, &bot_params), 4.0);
+
+        // small early adds, aggressive later adds
+        bot_params.entry_grid_double_down_factors = vec![0.5, 1.0, 3.0];
+        assert_eq!(calc_double_down_factor(0, &bot_params), 0.5);
+        assert_eq!(calc_double_down_factor(7, &bot_params), 3.0);
+        // expected size ratios per level: 1, 1.5, 3, 12
+        assert_eq!(calc_grid_level(1.5, 1.0, &bot_params), 1);
+        assert_eq!(calc_grid_level(3.0, 1.0, &bot_params), 2);
+        assert_eq!(calc_grid_level(12.0, 1.0, &bot_params), 3);
+
+        let reentry_qty = calc_reentry_qty(
+            100.0,
+            state_params.balance,
+            position.size * 3.0,
+            2,
+            &exchange_params,
+            &bot_params,
+        );
+        assert_eq!(reentry_qty, 9.0);
+    }
+
     #[test]
     fn test_calc_reentry_qty() {
         let (exchange_params, state_params, bot_params, position, _) = setup_test_params();
@@ -1662,6 +1953,7 @@ mod tests {
             entry_price,
             state_params.balance,
             position.size,
+            0,
             &exchange_params,
             &bot_params,
         );
@@ -0,0 +1,159 @@
+use crate::grid::utils::{calc_min_entry_qty, cost_to_qty, qty_to_cost, round_, round_dn};
+use crate::types::{ExchangeParams, GridOrder, OrderBook, OrderJitterConfig};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+/// Mixes `value` into `hash` (splitmix64 finalizer), so each order gets its own stream.
+fn mix(hash: u64, value: u64) -> u64 {
+    let mut z = hash ^ value.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+/// Randomly shrinks order quantities and shifts prices by a tick, as set by `config`.
+///
+/// The jitter of an order is derived from `seed` and the order itself, so an unchanged
+/// grid order gets the same jitter every cycle and is not replaced needlessly. A jittered
+/// order never costs more than the original: when a price moves a tick against the
+/// account the qty is trimmed to match. Changes that would take an order below the
+/// exchange minimum, or a maker order across the book, are dropped.
+pub fn apply_order_jitter(
+    orders: &mut [GridOrder], order_book: &OrderBook, exchange_params: &ExchangeParams,
+    config: &OrderJitterConfig, seed: u64,
+) {
+    if !config.is_enabled() {
+        return;
+    }
+    let ExchangeParams {
+        qty_step,
+        price_step,
+        min_qty,
+        min_cost,
+        c_mult,
+        inverse,
+    } = *exchange_params;
+    for order in orders.iter_mut().filter(|o| o.qty != 0.0 && o.price > 0.0) {
+        let mut rng = StdRng::seed_from_u64(
+            [
+                order.order_type as u64,
+                order.price.to_bits(),
+                order.qty.to_bits(),
+            ]
+            .into_iter()
+            .fold(seed, mix),
+        );
+        let is_buy = order.qty > 0.0;
+        let original_cost = qty_to_cost(order.qty, order.price, inverse, c_mult);
+        let mut qty = order.qty.abs();
+        let mut price = order.price;
+
+        if config.qty > 0.0 {
+            let reduction = rng.gen_range(0.0..=config.qty.min(1.0));
+            qty = round_dn(qty * (1.0 - reduction), qty_step);
+        }
+        if config.price_tick {
+            let ticks = rng.gen_range(-1i32..=1) as f64;
+            let shifted = round_(price + ticks * price_step, price_step);
+            let crosses = !order_book.bids.is_empty()
+                && !order_book.asks.is_empty()
+                && if is_buy {
+                    shifted >= order_book.best_ask()
+                } else {
+                    shifted <= order_book.best_bid()
+                };
+            if shifted > 0.0 && !crosses {
+                price = shifted;
+            }
+        }
+        if qty_to_cost(qty, price, inverse, c_mult) > original_cost {
+            qty = qty.min(round_dn(
+                cost_to_qty(original_cost, price, inverse, c_mult),
+                qty_step,
+            ));
+        }
+
+        let min_entry_qty = calc_min_entry_qty(price, inverse, c_mult, qty_step, min_qty, min_cost);
+        if qty >= min_entry_qty {
+            order.qty = if is_buy { qty } else { -qty };
+            order.price = price;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::OrderType;
+
+    fn ladder() -> Vec<GridOrder> {
+        (0..20)
+            .map(|i| GridOrder {
+                qty: if i % 2 == 0 { 1.0 } else { -1.0 },
+                price: if i % 2 == 0 {
+                    99.0 - i as f64
+                } else {
+                    101.0 + i as f64
+                },
+                order_type: if i % 2 == 0 {
+                    OrderType::EntryGridNormalLong
+                } else {
+                    OrderType::CloseGridLong
+                },
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_jitter_is_bounded_and_repeatable() {
+        let order_book = OrderBook {
+            bids: vec![[99.9, 1.0]],
+            asks: vec![[100.1, 1.0]],
+        };
+        let exchange_params = ExchangeParams {
+            qty_step: 0.001,
+            price_step: 0.1,
+            min_qty: 0.001,
+            min_cost: 1.0,
+            c_mult: 1.0,
+            inverse: false,
+        };
+        let config = OrderJitterConfig {
+            qty: 0.05,
+            price_tick: true,
+            ..Default::default()
+        };
+        let original = ladder();
+        let mut jittered = original.clone();
+        apply_order_jitter(&mut jittered, &order_book, &exchange_params, &config, 7);
+
+        assert!(jittered
+            .iter()
+            .zip(&original)
+            .any(|(j, o)| j.qty != o.qty || j.price != o.price));
+        for (j, o) in jittered.iter().zip(&original) {
+            assert_eq!(j.qty.signum(), o.qty.signum());
+            assert!(j.qty.abs() >= 0.95 - 1e-9 && j.qty.abs() <= 1.0);
+            assert!((j.price - o.price).abs() <= 0.1 + 1e-9);
+            assert!(j.qty.abs() * j.price <= o.qty.abs() * o.price + 1e-9);
+        }
+
+        let mut again = original.clone();
+        apply_order_jitter(&mut again, &order_book, &exchange_params, &config, 7);
+        assert!(again
+            .iter()
+            .zip(&jittered)
+            .all(|(a, j)| a.qty == j.qty && a.price == j.price));
+
+        // a buy one tick below the ask cannot be shifted onto it
+        let mut at_touch = vec![GridOrder {
+            qty: 1.0,
+            price: 100.0,
+            order_type: OrderType::EntryGridNormalLong,
+        }];
+        for seed in 0..20 {
+            apply_order_jitter(&mut at_touch, &order_book, &exchange_params, &config, seed);
+            assert!(at_touch[0].price < 100.1);
+        }
+    }
+}
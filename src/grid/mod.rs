@@ -1,3 +1,6 @@
 pub mod closes;
 pub mod entries;
+pub mod fast_math;
+pub mod jitter;
+pub mod pricing;
 pub mod utils;
@@ -0,0 +1,98 @@
+use crate::grid::utils::round_;
+use crate::types::{BookPriceMode, GridOrder, OrderBook};
+
+/// Reprices grid orders at the top of the book according to `mode`. Buys are orders with
+/// a positive qty. Orders deeper in the book keep their grid level in every mode, and
+/// orders that cross the spread stay marketable.
+pub fn apply_book_price_mode(
+    orders: &mut [GridOrder], order_book: &OrderBook, price_step: f64, mode: BookPriceMode,
+) {
+    if mode == BookPriceMode::Grid || order_book.bids.is_empty() || order_book.asks.is_empty() {
+        return;
+    }
+    let (best_bid, best_ask) = (order_book.best_bid(), order_book.best_ask());
+    for order in orders.iter_mut().filter(|o| o.qty != 0.0) {
+        let is_buy = order.qty > 0.0;
+        order.price = match mode {
+            BookPriceMode::Join if is_buy && order.price > best_bid && order.price < best_ask => {
+                best_bid
+            }
+            BookPriceMode::Join if !is_buy && order.price < best_ask && order.price > best_bid => {
+                best_ask
+            }
+            BookPriceMode::Improve
+                if is_buy && order.price >= best_bid && order.price < best_ask =>
+            {
+                let improved = round_(best_bid + price_step, price_step);
+                if improved < best_ask {
+                    improved
+                } else {
+                    order.price
+                }
+            }
+            BookPriceMode::Improve
+                if !is_buy && order.price <= best_ask && order.price > best_bid =>
+            {
+                let improved = round_(best_ask - price_step, price_step);
+                if improved > best_bid {
+                    improved
+                } else {
+                    order.price
+                }
+            }
+            _ => order.price,
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::OrderType;
+
+    fn prices(mode: BookPriceMode, bid: f64, ask: f64) -> Vec<f64> {
+        let order = |qty: f64, price: f64| GridOrder {
+            qty,
+            price,
+            order_type: OrderType::Empty,
+        };
+        let mut orders = vec![
+            order(1.0, 100.0),
+            order(1.0, 99.0),
+            order(-1.0, 100.3),
+            order(-1.0, 101.0),
+        ];
+        let order_book = OrderBook {
+            bids: vec![[bid, 1.0]],
+            asks: vec![[ask, 1.0]],
+        };
+        apply_book_price_mode(&mut orders, &order_book, 0.1, mode);
+        orders.iter().map(|o| o.price).collect()
+    }
+
+    #[test]
+    fn test_book_price_modes() {
+        assert_eq!(
+            prices(BookPriceMode::Grid, 99.8, 100.5),
+            vec![100.0, 99.0, 100.3, 101.0]
+        );
+        assert_eq!(
+            prices(BookPriceMode::Join, 99.8, 100.5),
+            vec![99.8, 99.0, 100.5, 101.0]
+        );
+        assert_eq!(
+            prices(BookPriceMode::Improve, 99.8, 100.5),
+            vec![99.9, 99.0, 100.4, 101.0]
+        );
+        // a one tick spread leaves nothing to improve on
+        assert_eq!(
+            prices(BookPriceMode::Improve, 100.0, 100.1),
+            vec![100.0, 99.0, 100.3, 101.0]
+        );
+        // orders crossing the spread are left to take
+        assert_eq!(
+            prices(BookPriceMode::Improve, 99.5, 99.8),
+            vec![100.0, 99.0, 100.3, 101.0]
+        );
+    }
+}
@@ -1,13 +1,8 @@
 use crate::constants::{LONG, SHORT};
+use crate::grid::fast_math;
 use crate::types::ExchangeParams;
 use std::cmp::Ordering;
 
-/// Rounds a number to the specified number of decimal places.
-fn round_to_decimal_places(value: f64, decimal_places: usize) -> f64 {
-    let multiplier = 10f64.powi(decimal_places as i32);
-    (value * multiplier).round() / multiplier
-}
-
 /// Rounds a number up to the nearest multiple of a given step size.
 ///
 /// This is typically used for rounding prices up to the exchange's required price tick size.
@@ -22,7 +17,7 @@ fn round_to_decimal_places(value: f64, decimal_places: usize) -> f64 {
 /// The rounded-up number.
 pub fn round_up(n: f64, step: f64) -> f64 {
     let result = (n / step).ceil() * step;
-    round_to_decimal_places(result, 10)
+    fast_math::clean_decimals(result)
 }
 
 /// Rounds a number to the nearest multiple of a given step size.
@@ -39,7 +34,7 @@ pub fn round_up(n: f64, step: f64) -> f64 {
 /// The rounded number.
 pub fn round_(n: f64, step: f64) -> f64 {
     let result = (n / step).round() * step;
-    round_to_decimal_places(result, 10)
+    fast_math::clean_decimals(result)
 }
 
 /// Rounds a number down to the nearest multiple of a given step size.
@@ -56,7 +51,7 @@ pub fn round_(n: f64, step: f64) -> f64 {
 /// The rounded-down number.
 pub fn round_dn(n: f64, step: f64) -> f64 {
     let result = (n / step).floor() * step;
-    round_to_decimal_places(result, 10)
+    fast_math::clean_decimals(result)
 }
 
 /// Rounds a number to a dynamic number of significant digits.
@@ -76,7 +71,7 @@ pub fn round_dynamic(n: f64, d: i32) -> f64 {
     let shift = d - (n.abs().log10().floor() as i32) - 1;
     let multiplier = 10f64.powi(shift);
     let result = (n * multiplier).round() / multiplier;
-    round_to_decimal_places(result, 10)
+    fast_math::clean_decimals(result)
 }
 
 /// Rounds a number up to a dynamic number of significant digits.
@@ -96,7 +91,7 @@ pub fn round_dynamic_up(n: f64, d: i32) -> f64 {
     let shift = d - (n.abs().log10().floor() as i32) - 1;
     let multiplier = 10f64.powi(shift);
     let result = (n * multiplier).ceil() / multiplier;
-    round_to_decimal_places(result, 10)
+    fast_math::clean_decimals(result)
 }
 
 /// Rounds a number down to a dynamic number of significant digits.
@@ -116,7 +111,7 @@ pub fn round_dynamic_dn(n: f64, d: i32) -> f64 {
     let shift = d - (n.abs().log10().floor() as i32) - 1;
     let multiplier = 10f64.powi(shift);
     let result = (n * multiplier).floor() / multiplier;
-    round_to_decimal_places(result, 10)
+    fast_math::clean_decimals(result)
 }
 
 /// Calculates the absolute percentage difference between two numbers.
@@ -366,6 +361,68 @@ pub fn calc_pnl_short(
     }
 }
 
+/// Calculates the break-even price of a long position.
+///
+/// This is the price at which closing the whole position recovers the fees and funding
+/// accrued while it was open.
+///
+/// # Arguments
+///
+/// * `entry_price` - The average entry price.
+/// * `qty` - The quantity of the position.
+/// * `costs` - Fees and funding paid by the position, in quote currency.
+/// * `inverse` - `true` for inverse contracts.
+/// * `c_mult` - The contract multiplier.
+///
+/// # Returns
+///
+/// The break-even price, or `f64::INFINITY` if the costs cannot be recovered.
+pub fn calc_break_even_price_long(
+    entry_price: f64, qty: f64, costs: f64, inverse: bool, c_mult: f64,
+) -> f64 {
+    if qty == 0.0 || entry_price == 0.0 {
+        return entry_price;
+    }
+    let costs_per_contract = costs / (qty.abs() * c_mult);
+    if inverse {
+        let inverse_price = 1.0 / entry_price - costs_per_contract;
+        if inverse_price <= 0.0 {
+            f64::INFINITY
+        } else {
+            1.0 / inverse_price
+        }
+    } else {
+        entry_price + costs_per_contract
+    }
+}
+
+/// Calculates the break-even price of a short position.
+///
+/// # Arguments
+///
+/// * `entry_price` - The average entry price.
+/// * `qty` - The quantity of the position.
+/// * `costs` - Fees and funding paid by the position, in quote currency.
+/// * `inverse` - `true` for inverse contracts.
+/// * `c_mult` - The contract multiplier.
+///
+/// # Returns
+///
+/// The break-even price, floored at 0.0 if the costs exceed what the position can earn.
+pub fn calc_break_even_price_short(
+    entry_price: f64, qty: f64, costs: f64, inverse: bool, c_mult: f64,
+) -> f64 {
+    if qty == 0.0 || entry_price == 0.0 {
+        return entry_price;
+    }
+    let costs_per_contract = costs / (qty.abs() * c_mult);
+    if inverse {
+        1.0 / (1.0 / entry_price + costs_per_contract)
+    } else {
+        f64::max(entry_price - costs_per_contract, 0.0)
+    }
+}
+
 /// Calculates the percentage difference between the current price and the position's average price.
 ///
 /// # Arguments
@@ -478,8 +535,7 @@ pub fn calc_ema_price_ask(
 ///
 /// The new EMA value.
 pub fn calc_ema(prev_ema: f64, price: f64, span: f64) -> f64 {
-    let multiplier = 2.0 / (span + 1.0);
-    (price * multiplier) + (prev_ema * (1.0 - multiplier))
+    fast_math::ema_step(prev_ema, price, fast_math::ema_alpha(span))
 }
 
 /// Calculates the minimum entry quantity, considering both exchange minimums and cost minimums.
@@ -583,10 +639,100 @@ pub fn calc_initial_entry_qty(
     )
 }
 
+/// Finds the multiple of `qty_step` in `[0, max_qty]` whose wallet exposure is closest to
+/// `target`, for exposures monotonic in the qty: `increasing` for entries, decreasing for
+/// closes. `max_qty` itself is also a candidate, so closes may take a whole position that
+/// is not a multiple of the step.
+///
+/// Searches the step count from `guess`: doubling away from it until the target is
+/// bracketed, then bisecting down to adjacent steps, so it settles however coarse the
+/// step is. Returns `max_qty` if even that falls short of the target.
+fn solve_qty_for_wallet_exposure(
+    target: f64, guess: f64, qty_step: f64, max_qty: f64, increasing: bool,
+    wallet_exposure: impl Fn(f64) -> f64,
+) -> f64 {
+    // bounds the search on entries, which have no natural maximum
+    const MAX_STEPS: i64 = 1 << 52;
+    let max_n = if max_qty.is_finite() {
+        ((max_qty / qty_step).ceil() as i64).clamp(0, MAX_STEPS)
+    } else {
+        MAX_STEPS
+    };
+    let qty = |n: i64| {
+        if n >= max_n {
+            max_qty.min(fast_math::clean_decimals(n as f64 * qty_step))
+        } else {
+            fast_math::clean_decimals(n as f64 * qty_step)
+        }
+    };
+    // false below the qty reaching the target, true from it on
+    let reaches = |value: f64| {
+        if increasing {
+            value >= target
+        } else {
+            value <= target
+        }
+    };
+
+    let guess = if guess.is_finite() {
+        guess / qty_step
+    } else {
+        0.0
+    };
+    let start = (guess.round() as i64).clamp(0, max_n);
+    let start_value = wallet_exposure(qty(start));
+    let (mut lo, mut hi, mut lo_value, mut hi_value);
+    if reaches(start_value) {
+        (hi, hi_value) = (start, start_value);
+        let mut width = 1;
+        loop {
+            if hi == 0 {
+                return qty(0);
+            }
+            lo = (hi - width).max(0);
+            lo_value = wallet_exposure(qty(lo));
+            if !reaches(lo_value) {
+                break;
+            }
+            (hi, hi_value) = (lo, lo_value);
+            width *= 2;
+        }
+    } else {
+        (lo, lo_value) = (start, start_value);
+        let mut width = 1;
+        loop {
+            if lo == max_n {
+                return qty(max_n);
+            }
+            hi = (lo + width).min(max_n);
+            hi_value = wallet_exposure(qty(hi));
+            if reaches(hi_value) {
+                break;
+            }
+            (lo, lo_value) = (hi, hi_value);
+            width *= 2;
+        }
+    }
+    while hi - lo > 1 {
+        let mid = lo + (hi - lo) / 2;
+        let mid_value = wallet_exposure(qty(mid));
+        if reaches(mid_value) {
+            (hi, hi_value) = (mid, mid_value);
+        } else {
+            (lo, lo_value) = (mid, mid_value);
+        }
+    }
+    if (hi_value - target).abs() < (lo_value - target).abs() {
+        qty(hi)
+    } else {
+        qty(lo)
+    }
+}
+
 /// Finds the entry order quantity that will bring the wallet exposure to a target value.
 ///
-/// This function uses an iterative solver with interpolation to find the quantity.
-/// It starts with initial guesses and refines them to converge on the target exposure.
+/// Starts from the qty adding the missing exposure at `entry_price`, exact for linear
+/// contracts, and bisects on multiples of the qty step from there.
 ///
 /// # Arguments
 ///
@@ -600,8 +746,8 @@ pub fn calc_initial_entry_qty(
 ///
 /// # Returns
 ///
-/// The calculated entry quantity to reach the target exposure. Returns 0.0 if the
-/// current exposure is already near or above the target.
+/// The multiple of the qty step bringing the exposure closest to the target. Returns 0.0
+/// if the current exposure is already near or above the target.
 pub fn find_entry_qty_bringing_wallet_exposure_to_target(
     balance: f64, psize: f64, pprice: f64, wallet_exposure_target: f64, entry_price: f64,
     inverse: bool, exchange_params: &ExchangeParams,
@@ -614,89 +760,49 @@ pub fn find_entry_qty_bringing_wallet_exposure_to_target(
     if wallet_exposure >= wallet_exposure_target * 0.99 {
         return 0.0;
     }
-
-    let mut guesses = Vec::new();
-    let mut vals = Vec::new();
-    let mut evals = Vec::new();
-
-    guesses.push(round_(
-        psize.abs() * wallet_exposure_target / wallet_exposure.max(0.01),
-        exchange_params.qty_step,
-    ));
-    vals.push(calc_wallet_exposure_if_filled(
-        balance,
-        psize,
-        pprice,
-        guesses[0],
+    let guess = cost_to_qty(
+        balance * (wallet_exposure_target - wallet_exposure),
         entry_price,
         inverse,
-        exchange_params,
-    ));
-    evals.push((vals[0] - wallet_exposure_target).abs() / wallet_exposure_target);
-
-    guesses.push(
-        (guesses[0] * 1.2)
-            .max(guesses[0] + exchange_params.qty_step)
-            .max(0.0),
+        exchange_params.c_mult,
     );
-    vals.push(calc_wallet_exposure_if_filled(
-        balance,
-        psize,
-        pprice,
-        guesses[1],
-        entry_price,
-        inverse,
-        exchange_params,
-    ));
-    evals.push((vals[1] - wallet_exposure_target).abs() / wallet_exposure_target);
-
-    for _ in 0..15 {
-        if guesses.last() == guesses.get(guesses.len() - 2) {
-            let last_guess = guesses.last().unwrap().clone();
-            guesses.push((last_guess * 1.1).max(last_guess + exchange_params.qty_step));
-            vals.push(calc_wallet_exposure_if_filled(
+    solve_qty_for_wallet_exposure(
+        wallet_exposure_target,
+        guess,
+        exchange_params.qty_step,
+        f64::INFINITY,
+        true,
+        |qty| {
+            calc_wallet_exposure_if_filled(
                 balance,
                 psize,
                 pprice,
-                *guesses.last().unwrap(),
+                qty,
                 entry_price,
                 inverse,
                 exchange_params,
-            ));
-        }
-        let new_guess = interpolate(
-            wallet_exposure_target,
-            &vals[vals.len() - 2..],
-            &guesses[guesses.len() - 2..],
-        )
-        .max(0.0);
-        let new_guess = round_(new_guess, exchange_params.qty_step);
-        guesses.push(new_guess);
-        vals.push(calc_wallet_exposure_if_filled(
-            balance,
-            psize,
-            pprice,
-            new_guess,
-            entry_price,
-            inverse,
-            exchange_params,
-        ));
-        evals.push((vals.last().unwrap() - wallet_exposure_target).abs() / wallet_exposure_target);
+            )
+        },
+    )
+}
 
-        if *evals.last().unwrap() < 0.01 {
-            break;
-        }
+/// The close qty solving `cost(psize - qty) / (balance + pnl(qty)) = target` exactly, with
+/// `cost` and `pnl` both linear in the qty as given by one unit of each.
+fn close_qty_guess(
+    balance: f64, abs_psize: f64, wallet_exposure_target: f64, unit_cost: f64, unit_pnl: f64,
+) -> f64 {
+    let denominator = unit_cost + wallet_exposure_target * unit_pnl;
+    if denominator > 0.0 {
+        (unit_cost * abs_psize - wallet_exposure_target * balance) / denominator
+    } else {
+        abs_psize
     }
-
-    let mut evals_guesses: Vec<_> = evals.iter().zip(guesses.iter()).collect();
-    evals_guesses.sort_by(|a, b| a.0.partial_cmp(b.0).unwrap_or(Ordering::Equal));
-    *evals_guesses[0].1
 }
 
 /// Finds the close order quantity for a long position that will bring wallet exposure to a target.
 ///
-/// This function uses an iterative solver to find the portion of the position to close.
-/// It's used for partially closing a position to reduce risk (de-risking).
+/// Used for partially closing a position to reduce risk (de-risking). Starts from the
+/// closed-form qty, which only ignores rounding, and bisects on multiples of the qty step.
 ///
 /// # Arguments
 ///
@@ -710,18 +816,12 @@ pub fn find_entry_qty_bringing_wallet_exposure_to_target(
 ///
 /// # Returns
 ///
-/// The calculated close quantity. Returns 0.0 if exposure is already below the target,
-/// or the full position size if the target is 0.
+/// The calculated close quantity, at most `psize`. Returns 0.0 if exposure is already
+/// below the target, or the full position size if the target is 0.
 pub fn find_close_qty_long_bringing_wallet_exposure_to_target(
     balance: f64, psize: f64, pprice: f64, wallet_exposure_target: f64, close_price: f64,
     inverse: bool, exchange_params: &ExchangeParams,
 ) -> f64 {
-    let eval = |guess: f64| {
-        let pnl = calc_pnl_long(pprice, close_price, guess, inverse, exchange_params.c_mult);
-        let new_balance = balance + pnl;
-        qty_to_cost(psize - guess, pprice, inverse, exchange_params.c_mult) / new_balance
-    };
-
     if wallet_exposure_target == 0.0 {
         return psize;
     }
@@ -730,80 +830,31 @@ pub fn find_close_qty_long_bringing_wallet_exposure_to_target(
     if wallet_exposure <= wallet_exposure_target * 1.001 {
         return 0.0;
     }
-
-    let mut guesses = Vec::new();
-    let mut vals = Vec::new();
-    let mut evals = Vec::new();
-
-    guesses.push(
-        round_(
-            psize * (1.0 - wallet_exposure_target / wallet_exposure),
-            exchange_params.qty_step,
-        )
-        .max(0.0)
-        .min(psize),
+    let c_mult = exchange_params.c_mult;
+    let guess = close_qty_guess(
+        balance,
+        psize,
+        wallet_exposure_target,
+        qty_to_cost(1.0, pprice, inverse, c_mult),
+        calc_pnl_long(pprice, close_price, 1.0, inverse, c_mult),
     );
-    vals.push(eval(guesses[0]));
-    evals.push((vals[0] - wallet_exposure_target).abs() / wallet_exposure_target);
-
-    let mut next_guess = (guesses[0] * 1.2).max(guesses[0] + exchange_params.qty_step);
-    if next_guess == guesses[0] {
-        next_guess = (guesses[0] * 0.8).min(guesses[0] - exchange_params.qty_step);
-    }
-    guesses.push(next_guess.max(0.0).min(psize));
-    vals.push(eval(guesses[1]));
-    evals.push((vals[1] - wallet_exposure_target).abs() / wallet_exposure_target);
-
-    for _ in 0..15 {
-        let egv: Vec<_> = evals
-            .iter()
-            .zip(guesses.iter())
-            .zip(vals.iter())
-            .map(|((e, g), v)| (*e, *g, *v))
-            .collect();
-        let mut sorted_egv = egv;
-        sorted_egv.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(Ordering::Equal));
-
-        let new_guess = interpolate(
-            wallet_exposure_target,
-            &[sorted_egv[0].2, sorted_egv[1].2],
-            &[sorted_egv[0].1, sorted_egv[1].1],
-        );
-
-        let mut new_guess = round_(new_guess, exchange_params.qty_step)
-            .max(0.0)
-            .min(psize);
-
-        if guesses.contains(&new_guess) {
-            new_guess = (new_guess - exchange_params.qty_step).max(0.0).min(psize);
-            if guesses.contains(&new_guess) {
-                new_guess = (new_guess + 2.0 * exchange_params.qty_step)
-                    .max(0.0)
-                    .min(psize);
-                if guesses.contains(&new_guess) {
-                    break;
-                }
-            }
-        }
-
-        guesses.push(new_guess);
-        vals.push(eval(new_guess));
-        evals.push((vals.last().unwrap() - wallet_exposure_target).abs() / wallet_exposure_target);
-
-        if *evals.last().unwrap() < 0.01 {
-            break;
-        }
-    }
-
-    let mut evals_guesses: Vec<_> = evals.iter().zip(guesses.iter()).collect();
-    evals_guesses.sort_by(|a, b| a.0.partial_cmp(b.0).unwrap_or(Ordering::Equal));
-    *evals_guesses[0].1
+    solve_qty_for_wallet_exposure(
+        wallet_exposure_target,
+        guess,
+        exchange_params.qty_step,
+        psize,
+        false,
+        |qty| {
+            let pnl = calc_pnl_long(pprice, close_price, qty, inverse, c_mult);
+            qty_to_cost(psize - qty, pprice, inverse, c_mult) / (balance + pnl)
+        },
+    )
 }
 
 /// Finds the close order quantity for a short position that will bring wallet exposure to a target.
 ///
-/// This function uses an iterative solver to find the portion of the position to close.
-/// It's used for partially closing a position to reduce risk (de-risking).
+/// Used for partially closing a position to reduce risk (de-risking). Starts from the
+/// closed-form qty, which only ignores rounding, and bisects on multiples of the qty step.
 ///
 /// # Arguments
 ///
@@ -817,18 +868,13 @@ pub fn find_close_qty_long_bringing_wallet_exposure_to_target(
 ///
 /// # Returns
 ///
-/// The calculated close quantity (as a positive value). Returns 0.0 if exposure is
-/// already below the target, or the full position size if the target is 0.
+/// The calculated close quantity (as a positive value), at most the position size. Returns
+/// 0.0 if exposure is already below the target, or the full position size if the target
+/// is 0.
 pub fn find_close_qty_short_bringing_wallet_exposure_to_target(
     balance: f64, psize: f64, pprice: f64, wallet_exposure_target: f64, close_price: f64,
     inverse: bool, exchange_params: &ExchangeParams,
 ) -> f64 {
-    let eval = |guess: f64| {
-        let pnl = calc_pnl_short(pprice, close_price, guess, inverse, exchange_params.c_mult);
-        let new_balance = balance + pnl;
-        qty_to_cost(psize.abs() - guess, pprice, inverse, exchange_params.c_mult) / new_balance
-    };
-
     if wallet_exposure_target == 0.0 {
         return psize.abs();
     }
@@ -837,78 +883,26 @@ pub fn find_close_qty_short_bringing_wallet_exposure_to_target(
     if wallet_exposure <= wallet_exposure_target * 1.001 {
         return 0.0;
     }
-
-    let mut guesses = Vec::new();
-    let mut vals = Vec::new();
-    let mut evals = Vec::new();
-
+    let c_mult = exchange_params.c_mult;
     let abs_psize = psize.abs();
-
-    guesses.push(
-        round_(
-            abs_psize * (1.0 - wallet_exposure_target / wallet_exposure),
-            exchange_params.qty_step,
-        )
-        .max(0.0)
-        .min(abs_psize),
+    let guess = close_qty_guess(
+        balance,
+        abs_psize,
+        wallet_exposure_target,
+        qty_to_cost(1.0, pprice, inverse, c_mult),
+        calc_pnl_short(pprice, close_price, 1.0, inverse, c_mult),
     );
-    vals.push(eval(guesses[0]));
-    evals.push((vals[0] - wallet_exposure_target).abs() / wallet_exposure_target);
-
-    let mut next_guess = (guesses[0] * 1.2).max(guesses[0] + exchange_params.qty_step);
-    if next_guess == guesses[0] {
-        next_guess = (guesses[0] * 0.8).min(guesses[0] - exchange_params.qty_step);
-    }
-    guesses.push(next_guess.max(0.0).min(abs_psize));
-    vals.push(eval(guesses[1]));
-    evals.push((vals[1] - wallet_exposure_target).abs() / wallet_exposure_target);
-
-    for _ in 0..15 {
-        let egv: Vec<_> = evals
-            .iter()
-            .zip(guesses.iter())
-            .zip(vals.iter())
-            .map(|((e, g), v)| (*e, *g, *v))
-            .collect();
-        let mut sorted_egv = egv;
-        sorted_egv.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(Ordering::Equal));
-
-        let new_guess = interpolate(
-            wallet_exposure_target,
-            &[sorted_egv[0].2, sorted_egv[1].2],
-            &[sorted_egv[0].1, sorted_egv[1].1],
-        );
-
-        let mut new_guess = round_(new_guess, exchange_params.qty_step)
-            .max(0.0)
-            .min(abs_psize);
-
-        if guesses.contains(&new_guess) {
-            new_guess = (new_guess - exchange_params.qty_step)
-                .max(0.0)
-                .min(abs_psize);
-            if guesses.contains(&new_guess) {
-                new_guess = (new_guess + 2.0 * exchange_params.qty_step)
-                    .max(0.0)
-                    .min(abs_psize);
-                if guesses.contains(&new_guess) {
-                    break;
-                }
-            }
-        }
-
-        guesses.push(new_guess);
-        vals.push(eval(new_guess));
-        evals.push((vals.last().unwrap() - wallet_exposure_target).abs() / wallet_exposure_target);
-
-        if *evals.last().unwrap() < 0.01 {
-            break;
-        }
-    }
-
-    let mut evals_guesses: Vec<_> = evals.iter().zip(guesses.iter()).collect();
-    evals_guesses.sort_by(|a, b| a.0.partial_cmp(b.0).unwrap_or(Ordering::Equal));
-    *evals_guesses[0].1
+    solve_qty_for_wallet_exposure(
+        wallet_exposure_target,
+        guess,
+        exchange_params.qty_step,
+        abs_psize,
+        false,
+        |qty| {
+            let pnl = calc_pnl_short(pprice, close_price, qty, inverse, c_mult);
+            qty_to_cost(abs_psize - qty, pprice, inverse, c_mult) / (balance + pnl)
+        },
+    )
 }
 
 /// Calculates the theoretical bankruptcy price for the current combined positions.
@@ -1825,6 +1819,27 @@ pub fn calc_close_grid_backwards_short(
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_calc_break_even_price() {
+        assert_eq!(
+            calc_break_even_price_long(100.0, 2.0, 1.0, false, 1.0),
+            100.5
+        );
+        assert_eq!(
+            calc_break_even_price_short(100.0, -2.0, 1.0, false, 1.0),
+            99.5
+        );
+        assert_eq!(
+            calc_break_even_price_long(100.0, 2.0, 0.0, false, 1.0),
+            100.0
+        );
+        // inverse: pnl at break-even equals the costs
+        let be = calc_break_even_price_long(100.0, 1000.0, 0.1, true, 1.0);
+        assert!((calc_pnl_long(100.0, be, 1000.0, true, 1.0) - 0.1).abs() < 1e-9);
+        let be = calc_break_even_price_short(100.0, 1000.0, 0.1, true, 1.0);
+        assert!((calc_pnl_short(100.0, be, 1000.0, true, 1.0) - 0.1).abs() < 1e-9);
+    }
+
     #[test]
     fn test_round_() {
         assert_eq!(round_(1.2345, 0.01), 1.23);
@@ -1848,6 +1863,127 @@ mod tests {
         assert_eq!(round_dn(1.23, 0.01), 1.23);
         assert_eq!(round_dn(1.24, 0.05), 1.20);
     }
+
+    /// The solvers' results are exact multiples of the qty step (or the whole position for
+    /// closes) and no neighbouring qty gets closer to the target, for steps from far finer
+    /// to far coarser than the position.
+    #[test]
+    fn test_wallet_exposure_solvers_settle_on_the_closest_step() {
+        let err = |we: f64, target: f64| (we - target).abs();
+        let on_step = |qty: f64, step: f64| (round_(qty, step) - qty).abs() < 0.01 * step;
+        for &qty_step in &[1e-8, 1e-3, 0.1, 1.0, 10.0, 1000.0] {
+            for &inverse in &[false, true] {
+                let exchange_params = ExchangeParams {
+                    qty_step,
+                    c_mult: if inverse { 10.0 } else { 1.0 },
+                    inverse,
+                    ..Default::default()
+                };
+                let c_mult = exchange_params.c_mult;
+                let size_for = |we: f64, balance: f64, price: f64| {
+                    round_(cost_to_qty(we * balance, price, inverse, c_mult), qty_step)
+                };
+                for &balance in &[10.0, 1000.0, 1e6] {
+                    for &target in &[0.05, 0.5, 2.0, 5.0] {
+                        let pprice = 100.0;
+                        // multiples of the step past f64 precision
+                        if size_for(2.0 * target, balance, pprice) / qty_step > 1e12 {
+                            continue;
+                        }
+                        for &start_we in &[0.0, 0.3 * target] {
+                            let psize = size_for(start_we, balance, pprice);
+                            let entry_price = 90.0;
+                            let we = |qty: f64| {
+                                calc_wallet_exposure_if_filled(
+                                    balance,
+                                    psize,
+                                    pprice,
+                                    qty,
+                                    entry_price,
+                                    inverse,
+                                    &exchange_params,
+                                )
+                            };
+                            let qty = find_entry_qty_bringing_wallet_exposure_to_target(
+                                balance,
+                                psize,
+                                pprice,
+                                target,
+                                entry_price,
+                                inverse,
+                                &exchange_params,
+                            );
+                            let case = (qty_step, inverse, balance, target, psize, qty);
+                            assert!(qty >= 0.0 && on_step(qty, qty_step), "{:?}", case);
+                            assert!(err(we(qty), target) <= err(we(qty + qty_step), target));
+                            if qty >= qty_step {
+                                let below = round_(qty - qty_step, qty_step);
+                                assert!(
+                                    err(we(qty), target) <= err(we(below), target),
+                                    "{:?}",
+                                    case
+                                );
+                            }
+                        }
+
+                        let psize = size_for(2.0 * target, balance, pprice);
+                        if psize == 0.0 {
+                            continue;
+                        }
+                        for &close_price in &[95.0, 105.0] {
+                            let closes = [
+                                (
+                                    find_close_qty_long_bringing_wallet_exposure_to_target(
+                                        balance,
+                                        psize,
+                                        pprice,
+                                        target,
+                                        close_price,
+                                        inverse,
+                                        &exchange_params,
+                                    ),
+                                    calc_pnl_long(pprice, close_price, 1.0, inverse, c_mult),
+                                ),
+                                (
+                                    find_close_qty_short_bringing_wallet_exposure_to_target(
+                                        balance,
+                                        -psize,
+                                        pprice,
+                                        target,
+                                        close_price,
+                                        inverse,
+                                        &exchange_params,
+                                    ),
+                                    calc_pnl_short(pprice, close_price, 1.0, inverse, c_mult),
+                                ),
+                            ];
+                            for (qty, unit_pnl) in closes {
+                                let we = |qty: f64| {
+                                    qty_to_cost(psize - qty, pprice, inverse, c_mult)
+                                        / (balance + unit_pnl * qty)
+                                };
+                                let case = (qty_step, inverse, balance, target, close_price, qty);
+                                assert!((0.0..=psize).contains(&qty), "{:?}", case);
+                                assert!(on_step(qty, qty_step) || qty == psize, "{:?}", case);
+                                let above = (qty + qty_step).min(psize);
+                                let below = round_dn(qty - qty_step, qty_step).max(0.0);
+                                assert!(
+                                    err(we(qty), target) <= err(we(above), target),
+                                    "{:?}",
+                                    case
+                                );
+                                assert!(
+                                    err(we(qty), target) <= err(we(below), target),
+                                    "{:?}",
+                                    case
+                                );
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
 }
 #[test]
 fn test_calc_diff() {
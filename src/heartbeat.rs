@@ -0,0 +1,177 @@
+use crate::exchange::SendSyncError;
+use crate::paths::Paths;
+use chrono::Utc;
+use clap::Args;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tracing::warn;
+
+/// Outcome of one manager cycle.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct CycleStats {
+    pub symbol: String,
+    pub finished_at_ms: i64,
+    pub duration_ms: u64,
+    /// Grid orders the cycle sent to the order queue.
+    pub n_orders: usize,
+    pub error: Option<String>,
+}
+
+/// Contents of `<state_dir>/<user>/heartbeat.json`, rewritten after every cycle of every
+/// symbol.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct HeartbeatFile {
+    pub user: String,
+    pub version: String,
+    pub pid: u32,
+    pub started_at_ms: i64,
+    pub updated_at_ms: i64,
+    pub cycles: u64,
+    pub last_cycle: CycleStats,
+    /// The latest cycle of each symbol.
+    pub symbols: BTreeMap<String, CycleStats>,
+}
+
+/// Records cycles into the user's heartbeat file. Shared by all managers of a bot.
+#[derive(Clone)]
+pub struct Heartbeat {
+    path: PathBuf,
+    state: Arc<Mutex<HeartbeatFile>>,
+}
+
+impl Heartbeat {
+    /// `None` when `paths` has persistence disabled.
+    pub fn new(paths: &Paths, user: &str) -> Option<Self> {
+        let state = HeartbeatFile {
+            user: user.to_string(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            pid: std::process::id(),
+            started_at_ms: Utc::now().timestamp_millis(),
+            ..Default::default()
+        };
+        Some(Self {
+            path: paths.heartbeat()?,
+            state: Arc::new(Mutex::new(state)),
+        })
+    }
+
+    pub fn record(&self, stats: CycleStats) {
+        let mut state = self.state.lock().unwrap();
+        state.updated_at_ms = stats.finished_at_ms;
+        state.cycles += 1;
+        state.symbols.insert(stats.symbol.clone(), stats.clone());
+        state.last_cycle = stats;
+        if let Err(e) =
+            write_atomically(&self.path, &serde_json::to_string_pretty(&*state).unwrap())
+        {
+            warn!("Failed to write heartbeat {}: {}", self.path.display(), e);
+        }
+    }
+
+    pub fn remove_symbol(&self, symbol: &str) {
+        self.state.lock().unwrap().symbols.remove(symbol);
+    }
+}
+
+/// Writes to a temporary file first, so a probe never reads a half-written heartbeat.
+fn write_atomically(path: &Path, content: &str) -> std::io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let tmp = path.with_extension("json.tmp");
+    fs::write(&tmp, content)?;
+    fs::rename(&tmp, path)
+}
+
+#[derive(Args, Clone, Debug)]
+pub struct HealthcheckArgs {
+    #[clap(long)]
+    pub user: String,
+    /// Age after which the heartbeat counts as stale
+    #[clap(long, default_value_t = 300.0)]
+    pub max_age_seconds: f64,
+}
+
+/// Checks a heartbeat: it fails when the heartbeat is older than `max_age` at `now_ms`
+/// or the latest cycle failed.
+pub fn check(heartbeat: &HeartbeatFile, max_age: Duration, now_ms: i64) -> Result<(), String> {
+    let age_ms = now_ms - heartbeat.updated_at_ms;
+    if age_ms > max_age.as_millis() as i64 {
+        return Err(format!(
+            "Heartbeat of user {} is stale: last cycle {:.0}s ago",
+            heartbeat.user,
+            age_ms as f64 / 1000.0
+        ));
+    }
+    if let Some(error) = &heartbeat.last_cycle.error {
+        return Err(format!(
+            "Last cycle of user {} failed on {}: {}",
+            heartbeat.user, heartbeat.last_cycle.symbol, error
+        ));
+    }
+    Ok(())
+}
+
+/// Reads the user's heartbeat and checks it, for liveness probes: an error makes the
+/// process exit non-zero.
+pub fn run_healthcheck(
+    paths: &Paths, args: &HealthcheckArgs,
+) -> Result<HeartbeatFile, SendSyncError> {
+    let path = paths
+        .heartbeat()
+        .ok_or("live.state_dir is not set in config.hjson, so there is no heartbeat")?;
+    let content = fs::read_to_string(&path)
+        .map_err(|e| format!("Failed to read heartbeat {}: {}", path.display(), e))?;
+    let heartbeat: HeartbeatFile = serde_json::from_str(&content)?;
+    check(
+        &heartbeat,
+        Duration::from_secs_f64(args.max_age_seconds),
+        Utc::now().timestamp_millis(),
+    )?;
+    Ok(heartbeat)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_heartbeat_round_trip_and_check() {
+        let root = std::env::temp_dir().join(format!("heartbeat_test_{}", std::process::id()));
+        let paths = Paths::new(root.to_str().unwrap(), "alice");
+        let heartbeat = Heartbeat::new(&paths, "alice").unwrap();
+        let args = HealthcheckArgs {
+            user: "alice".to_string(),
+            max_age_seconds: 60.0,
+        };
+        // no cycle yet
+        assert!(run_healthcheck(&paths, &args).is_err());
+
+        let now_ms = Utc::now().timestamp_millis();
+        heartbeat.record(CycleStats {
+            symbol: "BTCUSDT".to_string(),
+            finished_at_ms: now_ms,
+            duration_ms: 120,
+            n_orders: 4,
+            error: None,
+        });
+        let file = run_healthcheck(&paths, &args).unwrap();
+        assert_eq!((file.cycles, file.last_cycle.n_orders), (1, 4));
+        assert!(check(&file, Duration::from_secs(60), now_ms + 61_000).is_err());
+
+        heartbeat.record(CycleStats {
+            symbol: "ETHUSDT".to_string(),
+            finished_at_ms: now_ms,
+            error: Some("timeout".to_string()),
+            ..Default::default()
+        });
+        let err = run_healthcheck(&paths, &args).unwrap_err().to_string();
+        assert!(err.contains("ETHUSDT") && err.contains("timeout"));
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
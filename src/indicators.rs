@@ -0,0 +1,346 @@
+use crate::backtest::{scale_indicator_spans, CandleIndicators};
+use crate::constants::LONG;
+use crate::data::{Candle, HlcvReader};
+use crate::exchange::{Exchange, SendSyncError};
+use crate::grid::utils::{calc_ema_price_ask, calc_ema_price_bid};
+use crate::schedule::CANDLE_MS;
+use crate::types::{BotConfig, BotSideConfig, EMABands, TrailingPriceBundle};
+use clap::Parser;
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::fmt::Write;
+
+/// Closed 1m candles an `IndicatorEngine` keeps, and fetches at startup to warm up: the
+/// most one request returns on most exchanges.
+pub const MAX_LIVE_CANDLES: usize = 1000;
+
+/// The live bot's indicators of one symbol. Keeps its latest closed 1m candles and feeds
+/// each one, as it closes, to the EMA bands and ATR of both sides over their own spans,
+/// and to the trailing prices, through the same `CandleIndicators` as backtests.
+#[derive(Debug, Clone, Default)]
+pub struct IndicatorEngine {
+    candles: VecDeque<Candle>,
+    /// Indicators over the long and the short side's spans.
+    long: CandleIndicators,
+    short: CandleIndicators,
+}
+
+impl IndicatorEngine {
+    /// Open time of the first candle not fed yet; `None` before the warm-up.
+    pub fn since_ms(&self) -> Option<i64> {
+        self.candles
+            .back()
+            .map(|candle| candle.timestamp as i64 + CANDLE_MS)
+    }
+
+    pub fn is_warm(&self) -> bool {
+        !self.candles.is_empty()
+    }
+
+    /// Feeds the candles, oldest first, that closed by `now_ms` and are newer than the
+    /// last one fed, and returns how many were fed.
+    pub fn update(
+        &mut self, candles: &[Candle], now_ms: i64, long: &BotSideConfig, short: &BotSideConfig,
+    ) -> usize {
+        let mut n_fed = 0;
+        for candle in candles {
+            let fed = self
+                .candles
+                .back()
+                .is_some_and(|last| candle.timestamp <= last.timestamp);
+            // the candle still forming waits for the next update
+            if fed || candle.timestamp as i64 + CANDLE_MS > now_ms {
+                continue;
+            }
+            self.long
+                .update(candle.high, candle.low, candle.close, long);
+            self.short
+                .update(candle.high, candle.low, candle.close, short);
+            if self.candles.len() == MAX_LIVE_CANDLES {
+                self.candles.pop_front();
+            }
+            self.candles.push_back(*candle);
+            n_fed += 1;
+        }
+        n_fed
+    }
+
+    pub fn candles(&self) -> &VecDeque<Candle> {
+        &self.candles
+    }
+
+    fn side(&self, pside: usize) -> &CandleIndicators {
+        if pside == LONG {
+            &self.long
+        } else {
+            &self.short
+        }
+    }
+
+    pub fn ema_bands(&self, pside: usize) -> EMABands {
+        self.side(pside).ema_bands()
+    }
+
+    pub fn atr(&self, pside: usize) -> f64 {
+        self.side(pside).atr()
+    }
+
+    /// Trailing prices follow the closes alone, so both sides share them.
+    pub fn trailing_price_bundle(&self) -> &TrailingPriceBundle {
+        &self.long.trailing_price_bundle
+    }
+}
+
+#[derive(Parser, Debug, Clone)]
+pub struct IndicatorsArgs {
+    /// Symbol to inspect
+    #[clap(long)]
+    pub symbol: String,
+
+    /// The two EMA spans in minutes, defaults to bot.long.ema_span_0 and ema_span_1
+    #[clap(long, value_delimiter = ',')]
+    pub spans: Vec<f64>,
+
+    /// ATR span in minutes, defaults to bot.long.entry_trailing_atr_span
+    #[clap(long)]
+    pub atr_span: Option<f64>,
+
+    /// First cached candle to read, e.g. 2024-01-01 or now-30d
+    #[clap(long, default_value = "now-30d")]
+    pub start: String,
+
+    /// User/account name defined in api-keys.json; prices the initial entries against
+    /// the live order book and price step of that account's exchange
+    #[clap(long)]
+    pub user: Option<String>,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct IndicatorReport {
+    pub symbol: String,
+    /// Timestamp of the last candle read.
+    pub timestamp_ms: u64,
+    pub close: f64,
+    pub ema_spans: [f64; 2],
+    pub ema_upper: f64,
+    pub ema_lower: f64,
+    pub atr_span: f64,
+    pub atr: f64,
+    /// ATR relative to the close.
+    pub volatility: f64,
+    /// Share of the candles read whose volatility was at or below the last one's.
+    pub volatility_percentile: f64,
+    /// Initial entry prices of both sides, `entry_initial_ema_dist` away from the bands.
+    /// Rounded to the price step and capped at the top of the book with `--user`.
+    pub initial_entry_long: f64,
+    pub initial_entry_short: f64,
+}
+
+/// The bot's long side params with the spans of `args`, so the indicators come out of
+/// the same `CandleIndicators` the backtester and parity tests feed.
+pub fn indicator_params(
+    config: &BotConfig, args: &IndicatorsArgs,
+) -> Result<BotSideConfig, SendSyncError> {
+    let mut params = config.bot.long.clone();
+    match args.spans.as_slice() {
+        [] => {}
+        &[span_0, span_1] => {
+            params.ema_span_0 = span_0;
+            params.ema_span_1 = span_1;
+        }
+        spans => return Err(format!("Expected two EMA spans, got {}", spans.len()).into()),
+    }
+    if let Some(atr_span) = args.atr_span {
+        params.entry_trailing_atr_span = atr_span;
+    }
+    Ok(params)
+}
+
+/// Runs `candles` of (timestamp, high, low, close) through the indicators and reports
+/// their values after the last one. `params` spans are in minutes and scaled to `step_ms`.
+pub fn calc_indicators(
+    symbol: &str, candles: impl IntoIterator<Item = (u64, f64, f64, f64)>, params: &BotSideConfig,
+    step_ms: u64, config: &BotConfig,
+) -> Option<IndicatorReport> {
+    let scaled = scale_indicator_spans(params, step_ms);
+    let mut indicators = CandleIndicators::default();
+    let mut volatilities = Vec::new();
+    let mut last = None;
+    for (timestamp, high, low, close) in candles {
+        indicators.update(high, low, close, &scaled);
+        if close > 0.0 {
+            volatilities.push(indicators.atr() / close);
+        }
+        last = Some((timestamp, close));
+    }
+    let (timestamp_ms, close) = last?;
+    let volatility = volatilities.last().copied().unwrap_or_default();
+    let volatility_percentile = if volatilities.is_empty() {
+        0.0
+    } else {
+        volatilities.iter().filter(|&&v| v <= volatility).count() as f64 / volatilities.len() as f64
+    };
+    let bands = indicators.ema_bands();
+    Some(IndicatorReport {
+        symbol: symbol.to_string(),
+        timestamp_ms,
+        close,
+        ema_spans: [params.ema_span_0, params.ema_span_1],
+        ema_upper: bands.upper,
+        ema_lower: bands.lower,
+        atr_span: params.entry_trailing_atr_span,
+        atr: indicators.atr(),
+        volatility,
+        volatility_percentile,
+        initial_entry_long: bands.lower * (1.0 - config.bot.long.entry_initial_ema_dist),
+        initial_entry_short: bands.upper * (1.0 + config.bot.short.entry_initial_ema_dist),
+    })
+}
+
+/// Computes the indicators over the cached candles at `backtest.resolution` from
+/// `args.start` on.
+pub fn build_report(
+    config: &BotConfig, args: &IndicatorsArgs,
+) -> Result<IndicatorReport, SendSyncError> {
+    let params = indicator_params(config, args)?;
+    let mut reader = HlcvReader::open(
+        config,
+        &args.symbol,
+        Some(&args.start),
+        None,
+        config.backtest.candle_chunk_size,
+    )?;
+    let mut candles = Vec::new();
+    while let Some((timestamps, hlcvs)) = reader.next_chunk()? {
+        for (row, &timestamp) in hlcvs.outer_iter().zip(&timestamps) {
+            candles.push((timestamp, row[0], row[1], row[4]));
+        }
+    }
+    calc_indicators(
+        &args.symbol,
+        candles,
+        &params,
+        config.backtest.step_ms(),
+        config,
+    )
+    .ok_or_else(|| format!("No cached candles for {} since {}", args.symbol, args.start).into())
+}
+
+/// Prices the initial entries the way the bot does: rounded to the price step and
+/// never crossing the current best bid or ask.
+pub async fn price_live_entries(
+    report: &mut IndicatorReport, exchange: &dyn Exchange, config: &BotConfig,
+) -> Result<(), SendSyncError> {
+    let params = exchange.fetch_exchange_params(&report.symbol).await?;
+    let book = exchange.fetch_order_book(&report.symbol).await?;
+    report.initial_entry_long = calc_ema_price_bid(
+        params.price_step,
+        book.best_bid(),
+        report.ema_lower,
+        config.bot.long.entry_initial_ema_dist,
+    );
+    report.initial_entry_short = calc_ema_price_ask(
+        params.price_step,
+        book.best_ask(),
+        report.ema_upper,
+        config.bot.short.entry_initial_ema_dist,
+    );
+    Ok(())
+}
+
+pub fn format_indicator_report(report: &IndicatorReport) -> String {
+    let mut out = format!("{} at {}\n", report.symbol, report.timestamp_ms);
+    let _ = writeln!(out, "close:               {}", report.close);
+    let _ = writeln!(
+        out,
+        "EMA bands ({}/{}m):  {} - {}",
+        report.ema_spans[0], report.ema_spans[1], report.ema_lower, report.ema_upper
+    );
+    let _ = writeln!(out, "ATR ({}m):           {}", report.atr_span, report.atr);
+    let _ = writeln!(
+        out,
+        "volatility:          {:.4}% (percentile {:.0})",
+        report.volatility * 100.0,
+        report.volatility_percentile * 100.0
+    );
+    let _ = writeln!(
+        out,
+        "initial entries:     long {}, short {}",
+        report.initial_entry_long, report.initial_entry_short
+    );
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config;
+    use crate::constants::SHORT;
+
+    #[test]
+    fn test_engine_feeds_closed_candles_once() {
+        let config = config::load_config("tests/fixtures/backtest_config.hjson").unwrap();
+        let mut long = config.bot.long.clone();
+        let mut short = config.bot.short.clone();
+        (long.ema_span_0, long.ema_span_1) = (1.0, 3.0);
+        (short.ema_span_0, short.ema_span_1) = (1.0, 1.0);
+        let candle = |i: u64, close: f64| Candle {
+            timestamp: i * CANDLE_MS as u64,
+            open: close,
+            high: close,
+            low: close,
+            close,
+            volume: 1.0,
+        };
+        let mut engine = IndicatorEngine::default();
+        assert_eq!(engine.since_ms(), None);
+        // the third candle is still forming at 2m30s
+        let candles = [candle(0, 100.0), candle(1, 110.0), candle(2, 120.0)];
+        assert_eq!(engine.update(&candles, 150_000, &long, &short), 2);
+        assert_eq!(engine.since_ms(), Some(2 * CANDLE_MS));
+        assert_eq!(engine.ema_bands(LONG).upper, 110.0);
+        assert_eq!(engine.ema_bands(LONG).lower, 105.0);
+        assert_eq!(engine.ema_bands(SHORT).lower, 110.0);
+
+        // fed candles are skipped when fetched again
+        assert_eq!(engine.update(&candles, 180_000, &long, &short), 1);
+        assert_eq!(engine.candles().len(), 3);
+        assert_eq!(engine.ema_bands(LONG).upper, 120.0);
+        assert_eq!(engine.trailing_price_bundle().max_since_open, 120.0);
+    }
+
+    #[test]
+    fn test_calc_indicators() {
+        let mut config = config::load_config("tests/fixtures/backtest_config.hjson").unwrap();
+        config.bot.long.entry_initial_ema_dist = 0.01;
+        config.bot.short.entry_initial_ema_dist = 0.01;
+        let args = IndicatorsArgs {
+            symbol: "BTCUSDT".to_string(),
+            spans: vec![1.0, 3.0],
+            atr_span: Some(2.0),
+            start: "now-30d".to_string(),
+            user: None,
+        };
+        let params = indicator_params(&config, &args).unwrap();
+
+        // flat candles with a range of 2, then a wide one
+        let mut candles: Vec<(u64, f64, f64, f64)> =
+            (0..10).map(|i| (i * 60_000, 101.0, 99.0, 100.0)).collect();
+        candles.push((600_000, 110.0, 100.0, 110.0));
+        let report = calc_indicators("BTCUSDT", candles, &params, 60_000, &config).unwrap();
+        assert_eq!(report.timestamp_ms, 600_000);
+        // a span of 1 follows the close, a span of 3 moves halfway
+        assert_eq!(report.ema_upper, 110.0);
+        assert_eq!(report.ema_lower, 105.0);
+        assert!((report.atr - 22.0 / 3.0).abs() < 1e-9);
+        assert_eq!(report.volatility_percentile, 1.0);
+        assert!((report.initial_entry_long - 103.95).abs() < 1e-9);
+        assert!((report.initial_entry_short - 111.1).abs() < 1e-9);
+
+        let three_spans = IndicatorsArgs {
+            spans: vec![1.0, 2.0, 3.0],
+            ..args
+        };
+        assert!(indicator_params(&config, &three_spans).is_err());
+    }
+}
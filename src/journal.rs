@@ -0,0 +1,597 @@
+use crate::analysis;
+use crate::control::{StatusBoard, SymbolStatus};
+use crate::events::{BotEvent, EventBus, EventSubscriber};
+use crate::exchange::{Exchange, SendSyncError};
+use crate::listings;
+use crate::paths::Paths;
+use crate::types::{PeriodAnalysis, TradeFill};
+use chrono::{DateTime, Utc};
+use clap::{Parser, Subcommand};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tracing::{info, warn};
+
+const MS_PER_DAY: f64 = 24.0 * 60.0 * 60.0 * 1000.0;
+
+/// Days of realized PnL reported by day, today included.
+pub const DAILY_PNL_DAYS: i64 = 7;
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum HistoryCommand {
+    /// Rebuilds the fill journal from the exchange's trade history
+    Backfill(BackfillArgs),
+    /// Prints monthly and quarterly performance of the fill journal
+    Report(ReportArgs),
+}
+
+#[derive(Parser, Debug, Clone)]
+pub struct BackfillArgs {
+    /// User/account name defined in api-keys.json
+    #[clap(long)]
+    pub user: String,
+
+    /// Number of days of history to fetch
+    #[clap(long, default_value_t = 30.0)]
+    pub days: f64,
+
+    /// Symbols to fetch, defaults to live.approved_coins
+    #[clap(long, value_delimiter = ',')]
+    pub symbols: Vec<String>,
+}
+
+#[derive(Parser, Debug, Clone)]
+pub struct ReportArgs {
+    /// User/account name defined in api-keys.json
+    #[clap(long)]
+    pub user: String,
+}
+
+/// A journaled fill with the PnL it realized; `cumulative_pnl` over the journal forms the
+/// account's cumulative PnL series.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct JournalEntry {
+    #[serde(flatten)]
+    pub fill: TradeFill,
+    /// PnL realized by this fill, net of its fee.
+    pub realized_pnl: f64,
+    pub cumulative_pnl: f64,
+}
+
+/// The account at one point in time, recorded whether or not anything filled.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct EquitySnapshot {
+    pub timestamp_ms: i64,
+    pub balance: f64,
+    /// Balance plus the unrealized PnL of all positions.
+    pub equity: f64,
+    /// Combined wallet exposure of all positions.
+    pub wallet_exposure: f64,
+    pub n_positions: usize,
+}
+
+impl EquitySnapshot {
+    /// The account at `balance` with the positions of the managers in `symbols`.
+    pub fn new(timestamp_ms: i64, balance: f64, symbols: &[SymbolStatus]) -> Self {
+        Self {
+            timestamp_ms,
+            balance,
+            equity: balance + symbols.iter().map(|s| s.unrealized_pnl).sum::<f64>(),
+            wallet_exposure: symbols.iter().map(|s| s.wallet_exposure).sum(),
+            n_positions: symbols.iter().filter(|s| s.position_size != 0.0).count(),
+        }
+    }
+}
+
+/// Realized PnL of one UTC day.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct DailyPnl {
+    pub day_start_ms: i64,
+    pub pnl: f64,
+    pub n_fills: usize,
+}
+
+/// The journal's realized PnL: the peak and latest cumulative PnL, which size the unstuck
+/// loss allowance, and the PnL of each of the last `DAILY_PNL_DAYS` days.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct RealizedPnl {
+    pub pnl_cumsum_max: f64,
+    pub pnl_cumsum_last: f64,
+    /// Oldest first, ending with the day the PnL was computed on.
+    pub daily: Vec<DailyPnl>,
+}
+
+/// All fills of one user, oldest first, stored at `Paths::journal`, along with the
+/// periodic snapshots of the account.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+pub struct FillJournal {
+    pub entries: Vec<JournalEntry>,
+    #[serde(default)]
+    pub equity_snapshots: Vec<EquitySnapshot>,
+}
+
+impl FillJournal {
+    /// Loads the journal from `path`, falling back to an empty journal if the file is
+    /// missing or unreadable.
+    pub fn load(path: &Path) -> Self {
+        match fs::read_to_string(path) {
+            Ok(content) => serde_json::from_str(&content).unwrap_or_else(|e| {
+                warn!("Ignoring unreadable journal {}: {}", path.display(), e);
+                Self::default()
+            }),
+            Err(_) => Self::default(),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), SendSyncError> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// Adds the fills not journaled yet and recomputes the PnL of every entry. Returns the
+    /// number of fills added.
+    pub fn merge(&mut self, fills: Vec<TradeFill>) -> usize {
+        let mut all: Vec<TradeFill> = self.entries.drain(..).map(|e| e.fill).collect();
+        let n_before = all.len();
+        for fill in fills {
+            if !all
+                .iter()
+                .any(|f| f.id == fill.id && f.symbol == fill.symbol)
+            {
+                all.push(fill);
+            }
+        }
+        let added = all.len() - n_before;
+        all.sort_by(|a, b| {
+            a.timestamp_ms
+                .cmp(&b.timestamp_ms)
+                .then_with(|| a.id.cmp(&b.id))
+        });
+        self.entries = calc_realized_pnls(all);
+        added
+    }
+
+    pub fn cumulative_pnl(&self) -> f64 {
+        self.entries.last().map_or(0.0, |e| e.cumulative_pnl)
+    }
+
+    /// Realized PnL of each of the `n_days` UTC days up to the one of `now_ms`, oldest
+    /// first, days without fills included.
+    pub fn daily_pnls(&self, now_ms: i64, n_days: i64) -> Vec<DailyPnl> {
+        let today_ms = now_ms - now_ms.rem_euclid(listings::MS_PER_DAY);
+        (0..n_days)
+            .rev()
+            .map(|days_ago| {
+                let day_start_ms = today_ms - days_ago * listings::MS_PER_DAY;
+                let day = day_start_ms..day_start_ms + listings::MS_PER_DAY;
+                let entries: Vec<&JournalEntry> = self
+                    .entries
+                    .iter()
+                    .filter(|e| day.contains(&e.fill.timestamp_ms))
+                    .collect();
+                DailyPnl {
+                    day_start_ms,
+                    pnl: entries.iter().map(|e| e.realized_pnl).sum(),
+                    n_fills: entries.len(),
+                }
+            })
+            .collect()
+    }
+
+    /// The realized PnL as of `now_ms`. The cumulative PnL peaks at 0 at the least, its
+    /// value before the first fill.
+    pub fn realized_pnl(&self, now_ms: i64) -> RealizedPnl {
+        RealizedPnl {
+            pnl_cumsum_max: self
+                .entries
+                .iter()
+                .map(|e| e.cumulative_pnl)
+                .fold(0.0, f64::max),
+            pnl_cumsum_last: self.cumulative_pnl(),
+            daily: self.daily_pnls(now_ms, DAILY_PNL_DAYS),
+        }
+    }
+
+    /// Adds a snapshot, keeping them in time order.
+    pub fn record_snapshot(&mut self, snapshot: EquitySnapshot) {
+        let at = self
+            .equity_snapshots
+            .partition_point(|s| s.timestamp_ms <= snapshot.timestamp_ms);
+        self.equity_snapshots.insert(at, snapshot);
+    }
+
+    /// The account's equity over time, as timestamps and values. Up to the first snapshot
+    /// it is the balance after each fill, found by walking the realized PnL back from the
+    /// balance of that snapshot, or from `current_balance` without snapshots. From then on
+    /// it is the equity of each snapshot.
+    pub fn equity_curve(&self, current_balance: f64) -> (Vec<i64>, Vec<f64>) {
+        let first_snapshot = self.equity_snapshots.first();
+        let n_fills = first_snapshot.map_or(self.entries.len(), |first| {
+            self.entries
+                .partition_point(|e| e.fill.timestamp_ms <= first.timestamp_ms)
+        });
+        let (anchor_balance, anchor_pnl) = match first_snapshot {
+            Some(first) => (
+                first.balance,
+                n_fills
+                    .checked_sub(1)
+                    .map_or(0.0, |i| self.entries[i].cumulative_pnl),
+            ),
+            None => (current_balance, self.cumulative_pnl()),
+        };
+        self.entries[..n_fills]
+            .iter()
+            .map(|e| {
+                (
+                    e.fill.timestamp_ms,
+                    anchor_balance - (anchor_pnl - e.cumulative_pnl),
+                )
+            })
+            .chain(
+                self.equity_snapshots
+                    .iter()
+                    .map(|s| (s.timestamp_ms, s.equity)),
+            )
+            .unzip()
+    }
+}
+
+/// Replays the fills per symbol and position side with average-cost accounting to find the
+/// PnL each fill realized. The PnL the exchange reports for a fill takes precedence over
+/// the replay's, and in one-way mode a fill reporting PnL while no position is known closes
+/// a position opened before the first journaled fill rather than opening the opposite
+/// side. Without a reported PnL, such closes have no known cost basis and realize only
+/// their fee.
+fn calc_realized_pnls(fills: Vec<TradeFill>) -> Vec<JournalEntry> {
+    // (symbol, position side) -> (signed size, average price)
+    let mut positions: HashMap<(String, String), (f64, f64)> = HashMap::new();
+    let mut cumulative_pnl = 0.0;
+    fills
+        .into_iter()
+        .map(|fill| {
+            let (size, price) = positions
+                .entry((fill.symbol.clone(), fill.position_side.clone()))
+                .or_insert((0.0, 0.0));
+            let qty = if fill.side == "Buy" {
+                fill.qty
+            } else {
+                -fill.qty
+            };
+            let direction = match fill.position_side.as_str() {
+                "Long" => 1.0,
+                "Short" => -1.0,
+                _ => 0.0,
+            };
+            let reported_close = fill.realized_pnl.is_some_and(|pnl| pnl != 0.0);
+            let opening = if *size == 0.0 {
+                !reported_close && (direction == 0.0 || qty.signum() == direction)
+            } else {
+                size.signum() == qty.signum()
+            };
+            let mut pnl = 0.0;
+            if opening {
+                *price = (*size * *price + qty * fill.price) / (*size + qty);
+                *size += qty;
+            } else {
+                let closed_qty = qty.abs().min(size.abs());
+                pnl = closed_qty * (fill.price - *price) * size.signum();
+                *size += closed_qty * qty.signum();
+                if size.abs() < 1e-12 {
+                    *size = 0.0;
+                    *price = 0.0;
+                }
+                // in one-way mode the rest of the fill opens the opposite side
+                let remaining_qty = qty.abs() - closed_qty;
+                if remaining_qty > 1e-12 && direction == 0.0 && closed_qty > 0.0 {
+                    *size = remaining_qty * qty.signum();
+                    *price = fill.price;
+                }
+            }
+            let realized_pnl = fill.realized_pnl.unwrap_or(pnl) - fill.fee;
+            cumulative_pnl += realized_pnl;
+            JournalEntry {
+                fill,
+                realized_pnl,
+                cumulative_pnl,
+            }
+        })
+        .collect()
+}
+
+/// One line per day of `daily`, e.g. `2024-05-01: +12.34 (8 fills)`.
+pub fn format_daily_pnls(daily: &[DailyPnl]) -> String {
+    daily
+        .iter()
+        .map(|day| {
+            let date = DateTime::from_timestamp_millis(day.day_start_ms)
+                .map_or_else(String::new, |d| d.format("%Y-%m-%d").to_string());
+            format!("{}: {:+.2} ({} fills)\n", date, day.pnl, day.n_fills)
+        })
+        .collect()
+}
+
+/// Journals fills as they are published on the event bus and publishes the journal's
+/// realized PnL to the status board, for the managers' unstuck allowance.
+pub struct JournalRecorder {
+    path: PathBuf,
+    journal: FillJournal,
+    status_board: StatusBoard,
+}
+
+impl JournalRecorder {
+    pub fn new(path: PathBuf, status_board: StatusBoard) -> Self {
+        let journal = FillJournal::load(&path);
+        status_board.set_realized_pnl(journal.realized_pnl(Utc::now().timestamp_millis()));
+        Self {
+            path,
+            journal,
+            status_board,
+        }
+    }
+}
+
+impl EventSubscriber for JournalRecorder {
+    fn on_event(&mut self, event: &BotEvent) {
+        match event {
+            BotEvent::OrderFilled { fill } => {
+                if self.journal.merge(vec![fill.clone()]) == 0 {
+                    return;
+                }
+            }
+            BotEvent::EquitySnapshot { snapshot } => {
+                self.journal.record_snapshot(snapshot.clone());
+            }
+            _ => return,
+        }
+        // snapshots also roll the daily PnL over to a new day
+        self.status_board
+            .set_realized_pnl(self.journal.realized_pnl(Utc::now().timestamp_millis()));
+        if let Err(e) = self.journal.save(&self.path) {
+            warn!("Failed to save journal {}: {}", self.path.display(), e);
+        }
+    }
+}
+
+/// Publishes a snapshot of the account every `interval`, from its balance on `exchange`
+/// and the positions of the managers on `status_board`, until the task is aborted.
+pub async fn take_equity_snapshots(
+    exchange: Box<dyn Exchange>, status_board: StatusBoard, events: EventBus, interval: Duration,
+) {
+    let mut ticks = tokio::time::interval_at(tokio::time::Instant::now() + interval, interval);
+    loop {
+        ticks.tick().await;
+        let balance = match exchange.fetch_balance().await {
+            Ok(balance) => balance,
+            Err(e) => {
+                warn!("Failed to fetch the balance for an equity snapshot: {}", e);
+                continue;
+            }
+        };
+        let symbols = status_board.snapshot("").symbols;
+        events.publish(BotEvent::EquitySnapshot {
+            snapshot: EquitySnapshot::new(exchange.now_ms(), balance, &symbols),
+        });
+    }
+}
+
+/// Fetches the last `days` of fills for `symbols` and merges them into the user's journal.
+pub async fn backfill(
+    exchange: &dyn Exchange, paths: &Paths, symbols: &[String], days: f64,
+) -> Result<(), SendSyncError> {
+    let path = paths
+        .journal()
+        .ok_or("live.state_dir is not set in config.hjson")?;
+    if symbols.is_empty() {
+        return Err("No symbols to backfill; pass --symbols or set live.approved_coins".into());
+    }
+    let end_ms = Utc::now().timestamp_millis();
+    let start_ms = end_ms - (days * MS_PER_DAY) as i64;
+    let mut fills = Vec::new();
+    for symbol in symbols {
+        let symbol_fills = exchange
+            .fetch_fill_history(symbol, start_ms, end_ms)
+            .await?;
+        info!("Fetched {} fills for {}", symbol_fills.len(), symbol);
+        fills.extend(symbol_fills);
+    }
+    let mut journal = FillJournal::load(&path);
+    let added = journal.merge(fills);
+    journal.save(&path)?;
+    println!(
+        "Added {} fills to {} ({} total), cumulative PnL {:.4}",
+        added,
+        path.display(),
+        journal.entries.len(),
+        journal.cumulative_pnl()
+    );
+    Ok(())
+}
+
+/// What `history report` prints.
+#[derive(Serialize, Debug, Clone, Default, PartialEq)]
+pub struct HistoryReport {
+    #[serde(flatten)]
+    pub periods: PeriodAnalysis,
+    /// Realized PnL of the last `DAILY_PNL_DAYS` days.
+    pub daily_pnl: Vec<DailyPnl>,
+}
+
+/// The journal's monthly and quarterly performance, based on the current balance, and its
+/// realized PnL of the last days.
+pub async fn report(
+    exchange: &dyn Exchange, paths: &Paths,
+) -> Result<HistoryReport, SendSyncError> {
+    let path = paths
+        .journal()
+        .ok_or("live.state_dir is not set in config.hjson")?;
+    let journal = FillJournal::load(&path);
+    if journal.entries.is_empty() && journal.equity_snapshots.is_empty() {
+        return Err(format!(
+            "No fills or equity snapshots journaled in {}; run history backfill first",
+            path.display()
+        )
+        .into());
+    }
+    let balance = exchange.fetch_balance().await?;
+    let (timestamps, equity_curve) = journal.equity_curve(balance);
+    Ok(HistoryReport {
+        periods: analysis::calculate_period_analysis(&timestamps, &equity_curve),
+        daily_pnl: journal.daily_pnls(Utc::now().timestamp_millis(), DAILY_PNL_DAYS),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fill(id: &str, ts: i64, side: &str, position_side: &str, qty: f64, price: f64) -> TradeFill {
+        TradeFill {
+            id: id.to_string(),
+            order_id: String::new(),
+            symbol: "BTCUSDT".to_string(),
+            timestamp_ms: ts,
+            side: side.to_string(),
+            position_side: position_side.to_string(),
+            qty,
+            price,
+            fee: 0.1,
+            maker: None,
+            order_price: 0.0,
+            realized_pnl: None,
+        }
+    }
+
+    #[test]
+    fn test_merge_and_realized_pnl() {
+        let mut journal = FillJournal::default();
+        let added = journal.merge(vec![
+            fill("2", 2, "Buy", "Long", 1.0, 110.0),
+            fill("1", 1, "Buy", "Long", 1.0, 100.0),
+            // close of a short opened before the journal started
+            fill("3", 3, "Buy", "Short", 1.0, 50.0),
+        ]);
+        assert_eq!(added, 3);
+        assert_eq!(
+            journal.merge(vec![fill("1", 1, "Buy", "Long", 1.0, 100.0)]),
+            0
+        );
+        assert_eq!(
+            journal.merge(vec![fill("4", 4, "Sell", "Long", 2.0, 120.0)]),
+            1
+        );
+
+        let pnls: Vec<f64> = journal.entries.iter().map(|e| e.realized_pnl).collect();
+        assert_eq!(pnls, vec![-0.1, -0.1, -0.1, 29.9]);
+        assert!((journal.cumulative_pnl() - 29.6).abs() < 1e-9);
+
+        // one-way mode flips through zero
+        let mut journal = FillJournal::default();
+        journal.merge(vec![
+            fill("1", 1, "Sell", "", 1.0, 100.0),
+            fill("2", 2, "Buy", "", 3.0, 90.0),
+            fill("3", 3, "Sell", "", 2.0, 95.0),
+        ]);
+        let pnls: Vec<f64> = journal.entries.iter().map(|e| e.realized_pnl).collect();
+        assert_eq!(pnls, vec![-0.1, 9.9, 9.9]);
+
+        // one-way close of a long opened before the journal, with the exchange's PnL: no
+        // short is left behind to realize PnL on the next buy
+        let reported = |fill: TradeFill, pnl: f64| TradeFill {
+            realized_pnl: Some(pnl),
+            ..fill
+        };
+        let mut journal = FillJournal::default();
+        journal.merge(vec![
+            reported(fill("1", 1, "Sell", "", 1.0, 100.0), 15.0),
+            reported(fill("2", 2, "Buy", "", 1.0, 90.0), 0.0),
+            reported(fill("3", 3, "Sell", "", 1.0, 95.0), 5.0),
+        ]);
+        let pnls: Vec<f64> = journal.entries.iter().map(|e| e.realized_pnl).collect();
+        assert_eq!(pnls, vec![14.9, -0.1, 4.9]);
+        // the replay agrees once it knows the cost basis
+        let mut journal = FillJournal::default();
+        journal.merge(vec![
+            reported(fill("1", 1, "Sell", "", 1.0, 100.0), 15.0),
+            fill("2", 2, "Buy", "", 1.0, 90.0),
+            fill("3", 3, "Sell", "", 1.0, 95.0),
+        ]);
+        let pnls: Vec<f64> = journal.entries.iter().map(|e| e.realized_pnl).collect();
+        assert_eq!(pnls, vec![14.9, -0.1, 4.9]);
+    }
+
+    #[test]
+    fn test_realized_pnl_by_day() {
+        let day = listings::MS_PER_DAY;
+        let mut journal = FillJournal::default();
+        journal.merge(vec![
+            fill("1", 10 * day + 1_000, "Buy", "Long", 1.0, 100.0),
+            fill("2", 10 * day + 2_000, "Sell", "Long", 1.0, 120.0),
+            fill("3", 12 * day + 1_000, "Buy", "Long", 1.0, 100.0),
+            fill("4", 12 * day + 2_000, "Sell", "Long", 1.0, 90.0),
+        ]);
+        let pnl = journal.realized_pnl(12 * day + 5_000);
+        assert!((pnl.pnl_cumsum_max - 19.8).abs() < 1e-9);
+        assert!((pnl.pnl_cumsum_last - 9.6).abs() < 1e-9);
+        assert_eq!(pnl.daily.len(), DAILY_PNL_DAYS as usize);
+        let last: Vec<(i64, usize)> = pnl.daily[4..]
+            .iter()
+            .map(|d| (d.day_start_ms, d.n_fills))
+            .collect();
+        assert_eq!(last, vec![(10 * day, 2), (11 * day, 0), (12 * day, 2)]);
+        assert!((pnl.daily[6].pnl + 10.2).abs() < 1e-9);
+        assert!(format_daily_pnls(&pnl.daily[6..]).contains("-10.20 (2 fills)"));
+        // a loss from the start never raises the peak above 0
+        assert_eq!(FillJournal::default().realized_pnl(0).pnl_cumsum_max, 0.0);
+    }
+
+    #[test]
+    fn test_equity_curve_continues_with_snapshots() {
+        let mut journal = FillJournal::default();
+        journal.merge(vec![
+            fill("1", 1_000, "Buy", "Long", 1.0, 100.0),
+            fill("2", 2_000, "Sell", "Long", 1.0, 110.0),
+            // after the first snapshot: left to the snapshots
+            fill("3", 5_000, "Buy", "Long", 1.0, 100.0),
+        ]);
+        let status =
+            |symbol: &str, position_size: f64, wallet_exposure: f64, upnl: f64| SymbolStatus {
+                symbol: symbol.to_string(),
+                position_size,
+                wallet_exposure,
+                unrealized_pnl: upnl,
+                ..Default::default()
+            };
+        let snapshot = EquitySnapshot::new(
+            3_000,
+            1000.0,
+            &[
+                status("BTCUSDT", 0.5, 0.2, 5.0),
+                status("ETHUSDT", 0.0, 0.0, 0.0),
+                status("SOLUSDT", -2.0, 0.3, -1.0),
+            ],
+        );
+        assert_eq!(snapshot.equity, 1004.0);
+        assert!((snapshot.wallet_exposure - 0.5).abs() < 1e-12);
+        assert_eq!(snapshot.n_positions, 2);
+        // recorded out of order, as a late subscriber might
+        journal.record_snapshot(EquitySnapshot {
+            timestamp_ms: 9_000,
+            equity: 1010.0,
+            ..snapshot.clone()
+        });
+        journal.record_snapshot(snapshot);
+
+        let (timestamps, equity) = journal.equity_curve(2000.0);
+        assert_eq!(timestamps, vec![1_000, 2_000, 3_000, 9_000]);
+        // fills are anchored at the first snapshot's balance, not the current one
+        let expected = [1000.0 - 9.9, 1000.0, 1004.0, 1010.0];
+        for (value, expected) in equity.iter().zip(expected) {
+            assert!((value - expected).abs() < 1e-9, "{:?}", equity);
+        }
+    }
+}
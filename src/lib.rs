@@ -0,0 +1,50 @@
+#![allow(dead_code)]
+#![allow(unused_variables)]
+
+pub mod allocation;
+pub mod analysis;
+pub mod backtest;
+pub mod bot;
+pub mod calibration;
+pub mod capital;
+pub mod chart;
+pub mod collateral;
+pub mod config;
+pub mod config_schema;
+pub mod constants;
+pub mod control;
+pub mod data;
+pub mod downloader;
+pub mod events;
+pub mod exchange;
+pub mod execution_stats;
+pub mod forager;
+pub mod grid;
+pub mod heartbeat;
+pub mod indicators;
+pub mod journal;
+pub mod listings;
+pub mod manager;
+pub mod margin;
+pub mod migration;
+pub mod notifications;
+pub mod optimizer;
+pub mod order_diff;
+pub mod pair_mode;
+#[cfg(test)]
+pub mod parity;
+pub mod paths;
+pub mod performance;
+pub mod profit_transfer;
+pub mod restarts;
+pub mod results;
+pub mod risk_governor;
+pub mod risk_report;
+pub mod schedule;
+pub mod snapshot;
+#[cfg(test)]
+pub mod soak;
+pub mod state;
+pub mod types;
+pub mod universe;
+pub mod venue;
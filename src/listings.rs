@@ -0,0 +1,158 @@
+//! When each symbol of an exchange was listed, backing `live.minimum_coin_age_days`.
+//! Exchanges report a listing time for some of their markets only, so it is combined
+//! with the earliest candle seen of each symbol: from downloaded data, or probed from the
+//! exchange's daily candles. Both bound the listing from above, so the earlier one wins.
+
+use crate::exchange::{Exchange, ExchangeError, SendSyncError};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::warn;
+
+pub const MS_PER_DAY: i64 = 24 * 60 * 60 * 1000;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+pub struct Listing {
+    /// Listing time reported by the exchange.
+    #[serde(default)]
+    pub listed_at_ms: Option<i64>,
+    /// Open time of the earliest candle seen, in data going back far enough that it is the
+    /// symbol's first or settles any age check.
+    #[serde(default)]
+    pub first_candle_ms: Option<i64>,
+}
+
+impl Listing {
+    /// The latest the symbol can have been listed, if anything is known about it.
+    pub fn listed_by_ms(&self) -> Option<i64> {
+        match (self.listed_at_ms, self.first_candle_ms) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        }
+    }
+}
+
+/// The listings known of one exchange's symbols.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct ListingRegistry {
+    pub symbols: BTreeMap<String, Listing>,
+}
+
+impl ListingRegistry {
+    /// Where the downloader keeps the listings of `exchange_name` for backtests.
+    pub fn path(exchange_name: &str) -> PathBuf {
+        PathBuf::from("historical_data/listings").join(format!("{}.json", exchange_name))
+    }
+
+    pub fn load(path: &Path) -> Self {
+        match fs::read_to_string(path) {
+            Ok(content) => serde_json::from_str(&content).unwrap_or_else(|e| {
+                warn!("Ignoring unreadable listings {}: {}", path.display(), e);
+                Self::default()
+            }),
+            Err(_) => Self::default(),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), SendSyncError> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// Records the listing time the exchange reports; adapters report 0 when they have
+    /// none. Returns true if it is news.
+    pub fn record_listing(&mut self, symbol: &str, listed_at_ms: i64) -> bool {
+        if listed_at_ms <= 0 {
+            return false;
+        }
+        let listing = self.symbols.entry(symbol.to_string()).or_default();
+        if listing.listed_at_ms == Some(listed_at_ms) {
+            return false;
+        }
+        listing.listed_at_ms = Some(listed_at_ms);
+        true
+    }
+
+    /// Records a candle of `symbol` opening at `timestamp_ms` with no candle before it in
+    /// the data it was found in. Returns true if it is earlier than any seen before.
+    pub fn record_candle(&mut self, symbol: &str, timestamp_ms: i64) -> bool {
+        let listing = self.symbols.entry(symbol.to_string()).or_default();
+        if listing
+            .first_candle_ms
+            .is_some_and(|first| first <= timestamp_ms)
+        {
+            return false;
+        }
+        listing.first_candle_ms = Some(timestamp_ms);
+        true
+    }
+
+    pub fn listed_by_ms(&self, symbol: &str) -> Option<i64> {
+        self.symbols.get(symbol).and_then(Listing::listed_by_ms)
+    }
+
+    /// Whether `symbol` was listed at least `min_age_days` before `at_ms`, or `None` if
+    /// nothing is known of its listing.
+    pub fn is_old_enough(&self, symbol: &str, min_age_days: f64, at_ms: i64) -> Option<bool> {
+        let threshold_ms = at_ms - (min_age_days * MS_PER_DAY as f64) as i64;
+        self.listed_by_ms(symbol)
+            .map(|listed_by_ms| listed_by_ms <= threshold_ms)
+    }
+}
+
+/// Days of daily candles fetched to find a symbol's first one.
+pub const PROBE_DAYS: i64 = 1000;
+
+/// Open time of the earliest daily candle of `symbol` in the `PROBE_DAYS` before `now_ms`,
+/// or `None` if there is none; symbols listed before that are reported at its start. The
+/// whole window is fetched, so the answer does not depend on whether the exchange returns
+/// the oldest or the newest candles of a range first.
+pub async fn probe_first_candle(
+    exchange: &dyn Exchange, symbol: &str, now_ms: i64,
+) -> Result<Option<i64>, ExchangeError> {
+    let candles = exchange
+        .fetch_ohlcv(
+            symbol,
+            "1d",
+            Some(now_ms - PROBE_DAYS * MS_PER_DAY),
+            PROBE_DAYS as usize + 1,
+        )
+        .await?;
+    Ok(candles.iter().map(|c| c.timestamp as i64).min())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_listing_ages_combine_exchange_dates_and_candles() {
+        let now_ms = 100 * MS_PER_DAY;
+        let mut listings = ListingRegistry::default();
+        // adapters without listing dates report 0
+        assert!(!listings.record_listing("GATEUSDT", 0));
+        assert_eq!(listings.is_old_enough("GATEUSDT", 30.0, now_ms), None);
+
+        assert!(listings.record_listing("BTCUSDT", 10 * MS_PER_DAY));
+        assert!(listings.record_listing("NEWUSDT", 90 * MS_PER_DAY));
+        assert_eq!(listings.is_old_enough("BTCUSDT", 30.0, now_ms), Some(true));
+        assert_eq!(listings.is_old_enough("NEWUSDT", 30.0, now_ms), Some(false));
+
+        assert!(listings.record_candle("GATEUSDT", 95 * MS_PER_DAY));
+        assert_eq!(
+            listings.is_old_enough("GATEUSDT", 30.0, now_ms),
+            Some(false)
+        );
+        assert!(listings.record_candle("GATEUSDT", 20 * MS_PER_DAY));
+        assert!(!listings.record_candle("GATEUSDT", 50 * MS_PER_DAY));
+        assert_eq!(listings.is_old_enough("GATEUSDT", 30.0, now_ms), Some(true));
+
+        // data older than the reported listing wins
+        listings.record_candle("NEWUSDT", 5 * MS_PER_DAY);
+        assert_eq!(listings.listed_by_ms("NEWUSDT"), Some(5 * MS_PER_DAY));
+    }
+}
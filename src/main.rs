@@ -1,31 +1,33 @@
-#![allow(dead_code)]
-#![allow(unused_variables)]
-
-pub mod analysis;
-mod backtest;
-mod bot;
-mod config;
-mod constants;
-mod data;
-mod downloader;
-mod exchange;
-mod forager;
-mod grid;
-mod manager;
-mod optimizer;
-pub mod profit_transfer;
-mod types;
-
-use crate::config::{load_api_keys, UserConfig};
-use crate::exchange::{Exchange, SendSyncError};
-use crate::types::LiveConfig;
-use clap::{Parser, Subcommand};
+use passivbot_rs::{
+    analysis, backtest, bot, calibration, config, config_schema, control, downloader, exchange,
+    heartbeat, indicators, journal, migration, optimizer, profit_transfer, risk_report, snapshot,
+    universe, venue,
+};
+use passivbot_rs::config::{load_api_keys, UserConfig};
+use passivbot_rs::exchange::{key_check, Exchange, SendSyncError};
+use passivbot_rs::paths::Paths;
+use passivbot_rs::types::LiveConfig;
+use clap::{CommandFactory, Parser, Subcommand};
+use clap_complete::Shell;
+use std::fs::{self, File, OpenOptions};
+use std::path::Path;
+use std::sync::Mutex;
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::fmt;
+use tracing_subscriber::prelude::*;
 
 #[derive(Parser)]
 #[clap(author, version, about, long_about = None)]
 struct Cli {
     #[clap(subcommand)]
     command: Commands,
+    /// Prints results as JSON on stdout, with logs on stderr
+    #[clap(long, global = true)]
+    json: bool,
+    /// Discards the cached markets and exchange params of all exchanges, so they are
+    /// fetched again
+    #[clap(long, global = true)]
+    refresh_markets: bool,
 }
 
 #[derive(Subcommand)]
@@ -34,20 +36,151 @@ enum Commands {
     Live {
         #[clap(long)]
         user: String,
+
+        /// Trades on paper: market data comes from the exchange, orders and fills are
+        /// simulated locally and nothing is sent to the account
+        #[clap(long)]
+        paper: bool,
+
+        /// Starting balance of a paper account, defaults to the account's real balance
+        #[clap(long, requires = "paper")]
+        paper_balance: Option<f64>,
+
+        /// Computes and publishes the orders the bot would place on the account without
+        /// placing or cancelling any; same as live.read_only
+        #[clap(long, conflicts_with = "paper")]
+        read_only: bool,
+    },
+    /// Exits non-zero if the bot's heartbeat is stale or its last cycle failed
+    Healthcheck(heartbeat::HealthcheckArgs),
+    /// Prints the status of a running bot via its control API
+    Status {
+        #[clap(long)]
+        user: String,
     },
     /// Runs a backtest
-    Backtest,
+    Backtest(backtest::BacktestArgs),
     /// Runs the optimizer
-    Optimize,
+    Optimize(optimizer::OptimizeArgs),
     /// Downloads historical data
     Download,
     /// Transfers profits from futures to spot
     ProfitTransfer(profit_transfer::ProfitTransferArgs),
+    /// Reports how open positions' grids change under a new config, and migrates them to it
+    Migrate(migration::MigrateArgs),
+    /// Measures fees and slippage from recent fills and writes them to the backtest config
+    Calibrate(calibration::CalibrateArgs),
+    /// Manages the fill journal
+    History {
+        #[clap(subcommand)]
+        command: journal::HistoryCommand,
+    },
+    /// Reports bankruptcy prices, stress PnL and worst-case exposure of live positions
+    Risk(risk_report::RiskArgs),
+    /// Prints the EMA bands, ATR and volatility the bot would use for a symbol
+    Indicators(indicators::IndicatorsArgs),
+    /// Exports account snapshots and replays them against the simulated exchange
+    Snapshot {
+        #[clap(subcommand)]
+        command: snapshot::SnapshotCommand,
+    },
+    /// Documents the config
+    Config {
+        #[clap(subcommand)]
+        command: config_schema::ConfigCommand,
+    },
+    /// Prints a shell completion script
+    Completions {
+        #[clap(value_enum)]
+        shell: Shell,
+    },
+}
+
+/// Name the live bot keeps its state and logs under; paper runs of a user are kept apart
+/// from its real account.
+fn state_user(user: &str, paper: bool) -> String {
+    if paper {
+        format!("{}-paper", user)
+    } else {
+        user.to_string()
+    }
+}
+
+fn open_log_file(dir: &Path) -> Result<File, SendSyncError> {
+    fs::create_dir_all(dir)?;
+    let path = dir.join("live.log");
+    Ok(OpenOptions::new().create(true).append(true).open(path)?)
+}
+
+/// Logs at info level to stdout, or to stderr with `--json` to keep stdout for the
+/// result, and to `log_file` if given.
+fn init_tracing(json: bool, log_file: Option<File>) {
+    let console = if json {
+        fmt::layer().with_writer(std::io::stderr).boxed()
+    } else {
+        fmt::layer().boxed()
+    };
+    let file = log_file.map(|file| fmt::layer().with_ansi(false).with_writer(Mutex::new(file)));
+    tracing_subscriber::registry()
+        .with(LevelFilter::INFO)
+        .with(console)
+        .with(file)
+        .init();
+}
+
+/// Prints `value` as pretty JSON on stdout.
+fn print_json<T: serde::Serialize>(value: &T) -> Result<(), SendSyncError> {
+    println!("{}", serde_json::to_string_pretty(value)?);
+    Ok(())
 }
 
 fn init_exchange(
     live_config: &LiveConfig, user_config: &UserConfig,
 ) -> Result<Box<dyn Exchange>, SendSyncError> {
+    let exchange = cache_markets(
+        init_base_exchange(live_config, user_config)?,
+        live_config,
+        &user_config.exchange,
+    );
+    let exchange: Box<dyn Exchange> = if live_config.fault_injection.is_enabled() {
+        tracing::warn!(
+            "Fault injection is enabled: {:?}",
+            live_config.fault_injection
+        );
+        Box::new(exchange::faulty::FaultInjectingExchange::new(
+            exchange,
+            live_config.fault_injection.clone(),
+        ))
+    } else {
+        exchange
+    };
+    if live_config.retry.max_attempts <= 1 {
+        return Ok(exchange);
+    }
+    Ok(Box::new(exchange::retry::RetryingExchange::new(
+        exchange,
+        live_config.retry.clone(),
+    )))
+}
+
+/// Wraps `exchange` in the disk cache of its markets and exchange params.
+fn cache_markets(
+    exchange: Box<dyn Exchange>, live_config: &LiveConfig, exchange_name: &str,
+) -> Box<dyn Exchange> {
+    if live_config.market_cache_ttl_hours <= 0.0 {
+        return exchange;
+    }
+    Box::new(exchange::market_cache::CachedMarketsExchange::new(
+        exchange,
+        exchange::market_cache::MarketInfo::path(exchange_name),
+        live_config.market_cache_ttl_hours,
+    ))
+}
+
+fn init_base_exchange(
+    live_config: &LiveConfig, user_config: &UserConfig,
+) -> Result<Box<dyn Exchange>, SendSyncError> {
+    user_config.validate()?;
     match user_config.exchange.as_str() {
         "bybit" => Ok(Box::new(exchange::bybit::Bybit::new(
             live_config,
@@ -79,36 +212,233 @@ fn init_exchange(
 
 #[tokio::main]
 async fn main() -> Result<(), SendSyncError> {
-    tracing_subscriber::fmt::init();
-
     let cli = Cli::parse();
 
-    let config = match config::load_config("config.hjson") {
+    if let Commands::Completions { shell } = &cli.command {
+        let mut command = Cli::command();
+        let name = command.get_name().to_string();
+        clap_complete::generate(*shell, &mut command, name, &mut std::io::stdout());
+        return Ok(());
+    }
+
+    let mut config = match config::load_config("config.hjson") {
         Ok(config) => config,
         Err(e) => return Err(e),
     };
+    // live runs also log to the user's log directory
+    let log_file = match &cli.command {
+        Commands::Live { user, paper, .. } => {
+            Paths::new(&config.live.state_dir, &state_user(user, *paper))
+                .log_dir()
+                .map(|dir| open_log_file(&dir))
+                .transpose()?
+        }
+        _ => None,
+    };
+    init_tracing(cli.json, log_file);
+    if cli.refresh_markets {
+        exchange::market_cache::clear()?;
+    }
+
+    if let Commands::Config {
+        command: config_schema::ConfigCommand::Schema,
+    } = &cli.command
+    {
+        let params = config_schema::build_schema(&config);
+        if cli.json {
+            print_json(&params)?;
+        } else {
+            print!("{}", config_schema::format_schema(&params));
+        }
+        return Ok(());
+    }
+
+    if let Commands::Healthcheck(args) = &cli.command {
+        let paths = Paths::new(&config.live.state_dir, &args.user);
+        let heartbeat = heartbeat::run_healthcheck(&paths, args)?;
+        if cli.json {
+            print_json(&heartbeat)?;
+        } else {
+            println!(
+                "ok: user {}, {} cycles, last cycle {} ({} ms)",
+                heartbeat.user,
+                heartbeat.cycles,
+                heartbeat.last_cycle.symbol,
+                heartbeat.last_cycle.duration_ms
+            );
+        }
+        return Ok(());
+    }
 
     let api_keys = load_api_keys()?;
 
     match &cli.command {
-        Commands::Live { user } => {
+        Commands::Live {
+            user,
+            paper,
+            paper_balance,
+            read_only,
+        } => {
+            if cfg!(feature = "fast-math") {
+                return Err(
+                    "This build has the fast-math feature, whose prices and quantities \
+                    can be off in the last digit; rebuild without it to trade live"
+                        .into(),
+                );
+            }
             let user_config = api_keys
                 .get(user)
                 .ok_or("User not found in api-keys.json")?;
-            let exchange = init_exchange(&config.live, user_config)?;
-            let mut bot = bot::Passivbot::new(config, exchange);
+            config.live.user = state_user(user, *paper);
+            let mut exchange = init_exchange(&config.live, user_config)?;
+            config.live.read_only |= *read_only;
+            if !*paper {
+                let key_check = key_check::preflight(exchange.as_ref(), user).await?;
+                if key_check.can_trade == Some(false) && !config.live.read_only {
+                    return Err(format!(
+                        "The API key of {} has no futures trade permission; grant it, or \
+                        pass --read-only to run without trading",
+                        user
+                    )
+                    .into());
+                }
+            }
+            if config.live.read_only {
+                tracing::info!("Read-only: orders are published but never placed");
+            }
+            if *paper {
+                if !config.live.venues.users.is_empty() {
+                    return Err("live.venues routes orders to real accounts and cannot be \
+                        used with --paper"
+                        .into());
+                }
+                let starting_balance = match paper_balance {
+                    Some(balance) => *balance,
+                    None => exchange.fetch_balance().await?,
+                };
+                tracing::info!(
+                    "Paper trading {} from a balance of {}, state in {}",
+                    user,
+                    starting_balance,
+                    config.live.user
+                );
+                // simulated fills are driven by fetched order books, not the stream
+                config.live.websocket.enabled = false;
+                exchange = Box::new(
+                    exchange::paper::PaperExchange::new(exchange, starting_balance)
+                        .with_fees(config.backtest.maker_fee, config.backtest.taker_fee),
+                );
+            }
+            if !exchange.has_fill_history() {
+                tracing::warn!(
+                    "{} has no trade history endpoint; fills are not detected over REST, \
+                    only from the private stream",
+                    user_config.exchange
+                );
+            }
+            let mut venues = Vec::new();
+            if !config.live.venues.users.is_empty() {
+                venues.push(venue::VenueAccount {
+                    user: user.clone(),
+                    exchange: exchange.clone_box(),
+                    maker_fee: user_config.maker_fee,
+                });
+                for venue_user in &config.live.venues.users {
+                    let venue_config = api_keys.get(venue_user).ok_or(format!(
+                        "Venue user {} not found in api-keys.json",
+                        venue_user
+                    ))?;
+                    let venue_exchange = init_exchange(&config.live, venue_config)?;
+                    let key_check =
+                        key_check::preflight(venue_exchange.as_ref(), venue_user).await?;
+                    if key_check.can_trade == Some(false) && !config.live.read_only {
+                        return Err(format!(
+                            "The API key of venue user {} has no futures trade permission",
+                            venue_user
+                        )
+                        .into());
+                    }
+                    venues.push(venue::VenueAccount {
+                        user: venue_user.clone(),
+                        exchange: venue_exchange,
+                        maker_fee: venue_config.maker_fee,
+                    });
+                }
+            }
+            let market_stream = if config.live.websocket.enabled {
+                let stream = exchange::ws::MarketStream::spawn(user_config, &config.live.websocket);
+                if stream.is_none() {
+                    tracing::warn!(
+                        "No WebSocket client for {}, polling market data over REST",
+                        user_config.exchange
+                    );
+                }
+                stream
+            } else {
+                None
+            };
+            let mut bot = bot::Passivbot::new(config, exchange).with_venues(venues);
+            if let Some(stream) = market_stream {
+                bot = bot.with_market_stream(stream);
+            }
             bot.start().await?;
         }
-        Commands::Backtest => {
+        Commands::Status { user } => {
+            if config.live.control_port == 0 {
+                return Err("live.control_port is not set in config.hjson".into());
+            }
+            let status = control::fetch_status(config.live.control_port).await?;
+            if &status.user != user {
+                return Err(format!(
+                    "Bot on port {} is running for user {}, not {}",
+                    config.live.control_port, status.user, user
+                )
+                .into());
+            }
+            if cli.json {
+                print_json(&status)?;
+            } else {
+                print!("{}", control::format_status(&status));
+            }
+        }
+        Commands::Backtest(args) => {
+            args.apply(&mut config);
             let mut backtester = backtest::Backtester::new(config);
-            backtester.start().await?;
+            let result = backtester.start().await?;
+            if cli.json {
+                print_json(&result)?;
+            }
         }
-        Commands::Optimize => {
+        Commands::Optimize(args) => {
+            args.backtest.apply(&mut config);
             let mut optimizer = optimizer::Optimizer::new(config);
+            if let Some(run_dir) = &args.refine {
+                optimizer = optimizer.with_refine_from(run_dir.clone());
+            }
             optimizer.start().await?;
         }
+        Commands::Completions { .. } => unreachable!("handled before loading the config"),
+        Commands::Config { .. } => unreachable!("handled before loading the API keys"),
+        Commands::Healthcheck(_) => unreachable!("handled before loading the API keys"),
         Commands::Download => {
-            let downloader = downloader::Downloader::new(config);
+            // presets are resolved against fresh public market data
+            let mut preset_exchanges: Vec<String> = config
+                .backtest
+                .symbols
+                .iter()
+                .filter(|(_, entries)| entries.iter().any(|entry| universe::is_preset(entry)))
+                .map(|(exchange_name, _)| exchange_name.clone())
+                .collect();
+            preset_exchanges.sort();
+            let mut downloader = downloader::Downloader::new(config.clone());
+            for exchange_name in preset_exchanges {
+                let exchange = cache_markets(
+                    init_base_exchange(&config.live, &config::UserConfig::public(&exchange_name))?,
+                    &config.live,
+                    &exchange_name,
+                );
+                downloader = downloader.with_market_source(&exchange_name, exchange);
+            }
             downloader.start().await?;
         }
         Commands::ProfitTransfer(args) => {
@@ -119,6 +449,185 @@ async fn main() -> Result<(), SendSyncError> {
             let mut transferer = profit_transfer::ProfitTransferer::new(exchange, args.clone());
             transferer.start().await?;
         }
+        Commands::Risk(args) => {
+            let user_config = api_keys
+                .get(&args.user)
+                .ok_or("User not found in api-keys.json")?;
+            let exchange = init_exchange(&config.live, user_config)?;
+            let symbols = if args.symbols.is_empty() {
+                &config.live.approved_coins
+            } else {
+                &args.symbols
+            };
+            let report = risk_report::build_report(
+                exchange.as_ref(),
+                &args.user,
+                symbols,
+                config.live.leverage,
+            )
+            .await?;
+            if cli.json {
+                print_json(&report)?;
+            } else {
+                print!("{}", risk_report::format_risk_report(&report));
+            }
+        }
+        Commands::Indicators(args) => {
+            let mut report = indicators::build_report(&config, args)?;
+            if let Some(user) = &args.user {
+                let user_config = api_keys
+                    .get(user)
+                    .ok_or("User not found in api-keys.json")?;
+                let exchange = init_exchange(&config.live, user_config)?;
+                indicators::price_live_entries(&mut report, exchange.as_ref(), &config).await?;
+            }
+            if cli.json {
+                print_json(&report)?;
+            } else {
+                print!("{}", indicators::format_indicator_report(&report));
+            }
+        }
+        Commands::Snapshot { command } => match command {
+            snapshot::SnapshotCommand::Export(args) => {
+                let user_config = api_keys
+                    .get(&args.user)
+                    .ok_or("User not found in api-keys.json")?;
+                let exchange = init_exchange(&config.live, user_config)?;
+                let symbols = if args.symbols.is_empty() {
+                    &config.live.approved_coins
+                } else {
+                    &args.symbols
+                };
+                let snapshot = snapshot::export(
+                    exchange.as_ref(),
+                    &args.user,
+                    &user_config.exchange,
+                    symbols,
+                )
+                .await?;
+                snapshot.save(&args.output)?;
+                tracing::info!(
+                    "Exported {} symbols of user {} to {}",
+                    snapshot.symbols.len(),
+                    args.user,
+                    args.output.display()
+                );
+            }
+            snapshot::SnapshotCommand::Replay(args) => {
+                let account = snapshot::AccountSnapshot::load(&args.path)?;
+                let symbols: Vec<String> = match &args.symbol {
+                    Some(symbol) => vec![symbol.clone()],
+                    None => account.symbols.keys().cloned().collect(),
+                };
+                let mut replays = Vec::new();
+                for symbol in &symbols {
+                    replays.push(snapshot::replay(&config, &account, symbol, args.cycles).await?);
+                }
+                if cli.json {
+                    print_json(&replays)?;
+                } else {
+                    print!("{}", snapshot::format_replays(&replays));
+                }
+            }
+        },
+        Commands::Migrate(args) => {
+            let user_config = api_keys
+                .get(&args.user)
+                .ok_or("User not found in api-keys.json")?;
+            let new_config = config::load_config(&args.config)?;
+            let mut exchange = init_exchange(&config.live, user_config)?;
+            let symbols = if args.symbols.is_empty() {
+                let mut symbols = config.live.approved_coins.clone();
+                symbols.extend(new_config.live.approved_coins.iter().cloned());
+                symbols.sort();
+                symbols.dedup();
+                symbols
+            } else {
+                args.symbols.clone()
+            };
+            let account = snapshot::export(
+                exchange.as_ref(),
+                &args.user,
+                &user_config.exchange,
+                &symbols,
+            )
+            .await?;
+            let plan = migration::plan(&config, &new_config, &account).await?;
+            if cli.json {
+                print_json(&plan)?;
+            } else {
+                print!("{}", migration::format_plan(&plan));
+            }
+            if args.confirm {
+                migration::apply(exchange.as_mut(), &plan).await?;
+                migration::install_config(Path::new("config.hjson"), &args.config)?;
+            } else {
+                tracing::info!("Nothing changed; pass --confirm to migrate");
+            }
+        }
+        Commands::Calibrate(args) => {
+            let user_config = api_keys
+                .get(&args.user)
+                .ok_or("User not found in api-keys.json")?;
+            // the other exchanges do not implement fetch_fill_history
+            if !matches!(user_config.exchange.as_str(), "bybit" | "binance") {
+                return Err(format!(
+                    "calibrate reads the account's fill history, which is only fetched on \
+                    Bybit and Binance, not on {}",
+                    user_config.exchange
+                )
+                .into());
+            }
+            let exchange = init_exchange(&config.live, user_config)?;
+            let symbols = if args.symbols.is_empty() {
+                &config.live.approved_coins
+            } else {
+                &args.symbols
+            };
+            let calibration =
+                calibration::calibrate_from_history(exchange.as_ref(), symbols, args.days).await?;
+            if cli.json {
+                print_json(&calibration)?;
+            } else {
+                print!("{}", calibration::format_calibration(&calibration));
+            }
+            if !args.dry_run {
+                calibration::write_to_config(Path::new("config.hjson"), &calibration)?;
+                tracing::info!("Wrote the calibrated costs to the backtest block of config.hjson");
+            }
+        }
+        Commands::History { command } => match command {
+            journal::HistoryCommand::Backfill(args) => {
+                let user_config = api_keys
+                    .get(&args.user)
+                    .ok_or("User not found in api-keys.json")?;
+                let exchange = init_exchange(&config.live, user_config)?;
+                let symbols = if args.symbols.is_empty() {
+                    &config.live.approved_coins
+                } else {
+                    &args.symbols
+                };
+                let paths = Paths::new(&config.live.state_dir, &args.user);
+                paths.migrate_legacy_layout();
+                journal::backfill(exchange.as_ref(), &paths, symbols, args.days).await?;
+            }
+            journal::HistoryCommand::Report(args) => {
+                let user_config = api_keys
+                    .get(&args.user)
+                    .ok_or("User not found in api-keys.json")?;
+                let exchange = init_exchange(&config.live, user_config)?;
+                let paths = Paths::new(&config.live.state_dir, &args.user);
+                paths.migrate_legacy_layout();
+                let report = journal::report(exchange.as_ref(), &paths).await?;
+                if cli.json {
+                    print_json(&report)?;
+                } else {
+                    print!("{}", analysis::format_period_analysis(&report.periods));
+                    println!("\nRealized PnL by day:");
+                    print!("{}", journal::format_daily_pnls(&report.daily_pnl));
+                }
+            }
+        },
     }
 
     Ok(())
@@ -1,153 +1,1312 @@
+use crate::allocation::{self, UserExposure};
 use crate::types::{
-    BotConfig, StateParams, GridOrder, TrailingPriceBundle, Order, Position, OrderBook,
-    ExchangeParams, EMABands,
+    BotConfig, BotSideConfig, StateParams, GridOrder, TrailingPriceBundle, Order, Position,
+    PositionCosts, OrderBook, ExchangeParams, EMABands, TradingMode, PositionAdoption,
+    BookPriceMode, MarginMode, PositionPair,
 };
-use crate::grid::{entries, closes};
+use crate::grid::{entries, closes, jitter, pricing, utils};
+use crate::constants::{LONG, SHORT};
+use crate::schedule;
+use crate::control::{StatusBoard, SymbolStatus};
+use crate::events::{BotEvent, EventBus};
+use crate::heartbeat::{CycleStats, Heartbeat};
+use crate::indicators::{IndicatorEngine, MAX_LIVE_CANDLES};
+use crate::margin;
+use crate::order_diff::{self, OrderDiff};
+use crate::paths::Paths;
+use crate::state::SymbolState;
+use chrono::{DateTime, Utc};
+use crate::exchange::queue::OrderQueue;
+use crate::exchange::fill_detection::FillDetector;
+use crate::exchange::{pnl_check, price_check, risk_limit};
+use crate::exchange::ws::{MarketEvent, MarketFeed};
+use crate::pair_mode;
+use crate::risk_governor;
 use crate::exchange::{Exchange, SendSyncError};
-use tracing::{info, error};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast::error::RecvError;
+
+/// How often funding payments of an open position are fetched.
+const FUNDING_CHECK_INTERVAL_MS: i64 = 10 * 60 * 1000;
+/// How often the open position's unrealized PnL is checked against the exchange's.
+const PNL_CHECK_INTERVAL_MS: i64 = 10 * 60 * 1000;
+use tracing::{debug, info, warn, error};
 
 #[derive(Clone)]
 pub struct Manager {
     pub symbol: String,
+    /// User of the `live.venues` account the symbol trades on; empty without venues.
+    venue: String,
     pub config: BotConfig,
     pub exchange: Box<dyn Exchange>,
+    pub order_queue: OrderQueue,
+    pub status_board: StatusBoard,
+    pub events: EventBus,
+    /// Streamed market data and fills, used instead of polling when present.
+    pub market_feed: Option<MarketFeed>,
+    /// Records every cycle for liveness probes.
+    pub heartbeat: Option<Heartbeat>,
+    /// Set while the forager winds the symbol down: new positions are no longer opened.
+    winding_down: Arc<AtomicBool>,
+    paths: Paths,
 
     // State
     position: Position,
+    /// Both legs of the symbol, with `position` holding their net.
+    positions: PositionPair,
+    position_costs: PositionCosts,
+    symbol_state: SymbolState,
     balance: f64,
     order_book: OrderBook,
+    order_book_fetched_at: Instant,
     exchange_params: ExchangeParams,
+    /// EMA bands and ATR supplied through `set_indicators`, for both sides.
     ema_bands: EMABands,
+    atr: f64,
     trailing_price_bundle: TrailingPriceBundle,
+    indicator_engine: IndicatorEngine,
+    /// Whether the indicators come from `set_indicators` rather than the exchange's candles.
+    indicators_supplied: bool,
+    /// Grid desired in the previous cycle.
+    last_orders: Vec<GridOrder>,
+    /// Last reconciliation of the grid against the orders resting on the exchange.
+    last_order_diff: OrderDiff,
+    /// Whether the position found at startup has been checked for adoption.
+    adoption_checked: bool,
+    /// Multiplier of the exposure limits set by the risk governor.
+    exposure_scale: f64,
+    next_funding_check_ms: i64,
+    next_pnl_check_ms: i64,
+    /// Fills up to this time have been published; 0 until the first cycle.
+    fills_checked_until_ms: i64,
+    fill_detector: FillDetector,
+    /// Orders resting on the exchange at the last poll; `None` if the poll failed.
+    open_orders: Option<Vec<Order>>,
+    last_modes: Option<(TradingMode, TradingMode)>,
+    /// Mid price the current closes were priced at; 0 until orders are first placed.
+    closes_priced_at_mid: f64,
+    /// `live.no_entry_windows` and the weekend pause, parsed once.
+    entry_schedule: schedule::EntrySchedule,
+    /// Seeds `jitter::apply_order_jitter`, drawn at startup unless configured.
+    jitter_seed: u64,
 }
 
 impl Manager {
-    pub fn new(symbol: String, config: BotConfig, exchange: Box<dyn Exchange>) -> Self {
+    pub fn new(
+        symbol: String, config: BotConfig, exchange: Box<dyn Exchange>, order_queue: OrderQueue,
+        status_board: StatusBoard,
+    ) -> Self {
+        let paths = Paths::from_config(&config.live);
+        let symbol_state = paths
+            .symbol_state(&symbol)
+            .map(|path| SymbolState::load(&path))
+            .unwrap_or_default();
+        // the config was validated when it was loaded
+        let entry_schedule =
+            schedule::EntrySchedule::from_config(&config.live).unwrap_or_else(|e| {
+                error!("[{}] Ignoring no-entry windows: {}", symbol, e);
+                schedule::EntrySchedule::default()
+            });
+        let jitter_seed = match config.live.order_jitter.seed {
+            0 => rand::random(),
+            seed => seed,
+        };
         Self {
             symbol,
+            venue: String::new(),
             config,
             exchange,
+            order_queue,
+            status_board,
+            events: EventBus::default(),
+            market_feed: None,
+            heartbeat: None,
+            winding_down: Arc::default(),
+            paths,
             position: Default::default(),
+            positions: Default::default(),
+            position_costs: Default::default(),
+            symbol_state,
             balance: 0.0,
             order_book: Default::default(),
+            order_book_fetched_at: Instant::now(),
             exchange_params: Default::default(),
             ema_bands: Default::default(),
+            atr: 0.0,
             trailing_price_bundle: Default::default(),
+            indicator_engine: IndicatorEngine::default(),
+            indicators_supplied: false,
+            last_orders: Vec::new(),
+            last_order_diff: OrderDiff::default(),
+            adoption_checked: false,
+            exposure_scale: 1.0,
+            next_funding_check_ms: 0,
+            next_pnl_check_ms: 0,
+            fills_checked_until_ms: 0,
+            fill_detector: FillDetector::default(),
+            open_orders: None,
+            last_modes: None,
+            closes_priced_at_mid: 0.0,
+            entry_schedule,
+            jitter_seed,
         }
     }
 
+    pub fn with_events(mut self, events: EventBus) -> Self {
+        self.events = events;
+        self
+    }
+
+    pub fn with_market_feed(mut self, market_feed: MarketFeed) -> Self {
+        self.market_feed = Some(market_feed);
+        self
+    }
+
+    pub fn with_heartbeat(mut self, heartbeat: Option<Heartbeat>) -> Self {
+        self.heartbeat = heartbeat;
+        self
+    }
+
+    pub fn with_winding_down(mut self, winding_down: Arc<AtomicBool>) -> Self {
+        self.winding_down = winding_down;
+        self
+    }
+
+    /// Reports the symbol as traded on the account of `user`, one of `live.venues`.
+    pub fn with_venue(mut self, user: &str) -> Self {
+        self.venue = user.to_string();
+        self
+    }
+
     pub async fn run(&mut self) {
         info!("[{}] Starting manager", self.symbol);
+        self.set_up_symbol().await;
         loop {
-            if self.update_state().await.is_err() {
+            let started = Instant::now();
+            let result = self.run_cycle().await;
+            if let Some(heartbeat) = &self.heartbeat {
+                heartbeat.record(CycleStats {
+                    symbol: self.symbol.clone(),
+                    finished_at_ms: Utc::now().timestamp_millis(),
+                    duration_ms: started.elapsed().as_millis() as u64,
+                    n_orders: self.last_orders.len(),
+                    error: result.as_ref().err().map(|e| e.to_string()),
+                });
+            }
+            if let Err(e) = result {
                 // error is already logged in update_state
+                self.events.publish(BotEvent::Error {
+                    symbol: self.symbol.clone(),
+                    message: e.to_string(),
+                });
                 tokio::time::sleep(tokio::time::Duration::from_secs(10)).await;
                 continue;
             }
 
-            self.execute_logic().await;
+            let delay = if self.config.live.align_to_candle_close {
+                let now_ms = self.exchange.now_ms();
+                let offset_ms = (self.config.live.candle_close_offset_seconds * 1000.0) as i64;
+                let next_ms = schedule::next_candle_close_ms(now_ms, offset_ms);
+                Duration::from_millis((next_ms - now_ms) as u64)
+            } else {
+                Duration::from_secs_f64(self.config.live.execution_delay_seconds)
+            };
+            self.sleep_refreshing_closes(delay).await;
+        }
+    }
+
+    /// Sets the symbol's margin mode and leverage on the exchange to `live.margin_mode` and
+    /// `live.leverage`, which the margin figures assume. Failures are logged, and the
+    /// symbol is traded at the account's settings.
+    async fn set_up_symbol(&mut self) {
+        if self.config.live.read_only {
+            return;
+        }
+        let margin_mode = &self.config.live.margin_mode;
+        if !margin_mode.is_empty() {
+            match MarginMode::from_str(margin_mode) {
+                Some(mode) => {
+                    if let Err(e) = self.exchange.set_margin_mode(&self.symbol, mode).await {
+                        warn!(
+                            "[{}] Failed to set {} margin: {}",
+                            self.symbol, margin_mode, e
+                        );
+                    }
+                }
+                None => warn!("Unknown margin mode: {}", margin_mode),
+            }
+        }
+        let leverage = self.config.live.leverage;
+        if leverage > 0.0 {
+            // a new leverage would change the margin of a position already open
+            match self.exchange.fetch_positions(&self.symbol).await {
+                Ok(positions) if positions.long.size != 0.0 || positions.short.size != 0.0 => {
+                    info!(
+                        "[{}] Keeping the leverage of the open position",
+                        self.symbol
+                    );
+                    return;
+                }
+                Ok(_) => {}
+                Err(e) => {
+                    warn!(
+                        "[{}] Not setting leverage, failed to fetch positions: {}",
+                        self.symbol, e
+                    );
+                    return;
+                }
+            }
+            if let Err(e) = self.exchange.set_leverage(&self.symbol, leverage).await {
+                warn!(
+                    "[{}] Failed to set leverage {}: {}",
+                    self.symbol, leverage, e
+                );
+            }
+        }
+    }
+
+    /// Sleeps for `duration`, checking the nearest take-profit close every
+    /// `close_refresh_interval_seconds` meanwhile.
+    async fn sleep_refreshing_closes(&mut self, duration: Duration) {
+        let deadline = tokio::time::Instant::now() + duration;
+        if let Some(feed) = self.market_feed.clone() {
+            self.sleep_streaming(&feed, deadline).await;
+            return;
+        }
+        let interval = self.config.live.close_refresh_interval_seconds;
+        if interval <= 0.0 {
+            tokio::time::sleep_until(deadline).await;
+            return;
+        }
+        loop {
+            let next_check = tokio::time::Instant::now() + Duration::from_secs_f64(interval);
+            if next_check >= deadline {
+                tokio::time::sleep_until(deadline).await;
+                return;
+            }
+            tokio::time::sleep_until(next_check).await;
+            if let Err(e) = self.refresh_nearest_close().await {
+                warn!("[{}] Failed to refresh nearest close: {}", self.symbol, e);
+            }
+        }
+    }
+
+    /// Sleeps until `deadline`, re-pricing the nearest take-profit close on every streamed
+    /// order book of the symbol unless `close_refresh_interval_seconds` is 0, and returning
+    /// early on a streamed fill so the next cycle reacts to it right away.
+    async fn sleep_streaming(&mut self, feed: &MarketFeed, deadline: tokio::time::Instant) {
+        let refresh_closes = self.config.live.close_refresh_interval_seconds > 0.0;
+        let mut events = feed.subscribe();
+        loop {
+            let event = tokio::select! {
+                _ = tokio::time::sleep_until(deadline) => return,
+                event = events.recv() => event,
+            };
+            match event {
+                Ok(MarketEvent::OrderBook { symbol, order_book })
+                    if refresh_closes && symbol == self.symbol =>
+                {
+                    if let Err(e) = self.reprice_nearest_close(order_book).await {
+                        warn!("[{}] Failed to refresh nearest close: {}", self.symbol, e);
+                    }
+                }
+                Ok(MarketEvent::Fill(fill)) if fill.symbol == self.symbol => {
+                    info!(
+                        "[{}] Streamed fill of order {}, running the next cycle now",
+                        self.symbol, fill.order_id
+                    );
+                    return;
+                }
+                // missed books are superseded by the next one
+                Ok(_) | Err(RecvError::Lagged(_)) => {}
+                Err(RecvError::Closed) => {
+                    tokio::time::sleep_until(deadline).await;
+                    return;
+                }
+            }
+        }
+    }
 
-            // Sleep for a configurable duration
-            let delay = self.config.live.execution_delay_seconds;
-            tokio::time::sleep(tokio::time::Duration::from_secs_f64(delay)).await;
+    /// The side whose nearest take-profit close may be re-priced between cycles, if any.
+    fn refreshable_close_side(&self) -> Option<usize> {
+        if self.position.size == 0.0
+            || self.holds_two_legs()
+            || self.closes_priced_at_mid <= 0.0
+            || self.symbol_state.adoption_pending
+            || self.config.live.read_only
+        {
+            return None;
+        }
+        let pside = if self.position.size > 0.0 {
+            LONG
+        } else {
+            SHORT
+        };
+        self.config
+            .side_mode(pside)
+            .allows_closes()
+            .then_some(pside)
+    }
+
+    async fn refresh_nearest_close(&mut self) -> Result<(), SendSyncError> {
+        if self.refreshable_close_side().is_none() {
+            return Ok(());
+        }
+        let order_book = self.exchange.fetch_order_book(&self.symbol).await?;
+        self.reprice_nearest_close(order_book).await
+    }
+
+    /// Re-prices only the nearest take-profit close when the mid price of `order_book` has
+    /// moved by `close_refresh_threshold` since closes were priced, so take-profits stay
+    /// tight without recomputing the whole grid. The next full cycle prices everything anew.
+    async fn reprice_nearest_close(&mut self, order_book: OrderBook) -> Result<(), SendSyncError> {
+        let Some(pside) = self.refreshable_close_side() else {
+            return Ok(());
+        };
+        if let Err(reason) = order_book.check_usable(self.config.live.max_book_spread) {
+            debug!(
+                "[{}] Order book unusable, not refreshing closes: {}",
+                self.symbol, reason
+            );
+            return Ok(());
+        }
+        self.order_book = order_book;
+        self.order_book_fetched_at = Instant::now();
+        let mid_price = (self.order_book.best_bid() + self.order_book.best_ask()) / 2.0;
+        let moved = (mid_price - self.closes_priced_at_mid).abs() / self.closes_priced_at_mid;
+        if moved < self.config.live.close_refresh_threshold {
+            return Ok(());
+        }
+
+        let (long_cfg, short_cfg) = self.side_configs();
+        let side_cfg = if pside == LONG { &long_cfg } else { &short_cfg };
+        let mut closes = self.calc_take_profit_closes(pside, side_cfg, &self.state_params(pside));
+        pricing::apply_book_price_mode(
+            &mut closes,
+            &self.order_book,
+            self.exchange_params.price_step,
+            self.book_price_mode(),
+        );
+        self.apply_order_jitter(&mut closes);
+        self.closes_priced_at_mid = mid_price;
+        let Some(nearest) = closes::nearest_take_profit(&closes, pside) else {
+            return Ok(());
+        };
+        let previous = closes::nearest_take_profit(&self.last_orders, pside);
+        if previous.is_some_and(|o| o.price == nearest.price && o.qty == nearest.qty) {
+            return Ok(());
+        }
+        info!(
+            "[{}] Mid price moved {:.2}% since closes were priced, re-pricing nearest close to {}",
+            self.symbol,
+            moved * 100.0,
+            nearest.price
+        );
+
+        if let Some(previous) = previous {
+            let side = if pside == LONG { "Sell" } else { "Buy" };
+            let price_tolerance = self.exchange_params.price_step / 2.0;
+            let qty_tolerance = self.exchange_params.qty_step / 2.0;
+            let open_orders = self.exchange.fetch_open_orders(&self.symbol).await?;
+            // matched like `order_diff::reconcile`: most exchanges report the bot's closes
+            // without a reduce-only flag
+            if let Some(order) = open_orders.iter().find(|o| {
+                o.side.eq_ignore_ascii_case(side)
+                    && (o.price - previous.price).abs() <= price_tolerance
+                    && (o.qty - previous.qty.abs()).abs() <= qty_tolerance
+            }) {
+                self.order_queue.cancel(&self.symbol, &order.id)?;
+            }
+            self.last_orders.retain(|o| {
+                !(o.order_type == previous.order_type
+                    && o.price == previous.price
+                    && o.qty == previous.qty)
+            });
         }
+        self.order_queue.place(self.to_order(&nearest))?;
+        self.last_orders.push(nearest);
+        Ok(())
+    }
+
+    /// Runs a single update/execute cycle of the live loop.
+    pub async fn run_cycle(&mut self) -> Result<(), SendSyncError> {
+        self.update_state().await?;
+        self.execute_logic().await;
+        Ok(())
     }
 
     async fn update_state(&mut self) -> Result<(), SendSyncError> {
         info!("[{}] Updating state", self.symbol);
 
-        let position_fut = self.exchange.fetch_position(&self.symbol);
+        let position_fut = async {
+            let positions = self.exchange.fetch_positions(&self.symbol).await?;
+            if self.config.live.pair_mode.enabled && positions.one_way {
+                return Err("Account is in one-way mode, pair mode needs hedge mode".into());
+            }
+            Ok(positions)
+        };
+        let position_costs_fut = self.exchange.fetch_position_costs(&self.symbol);
         let balance_fut = self.exchange.fetch_balance();
-        let order_book_fut = self.exchange.fetch_order_book(&self.symbol);
+        let streamed_order_book = self.market_feed.as_ref().and_then(|feed| {
+            let max_age = Duration::from_secs_f64(self.config.live.max_book_age_seconds);
+            feed.order_book(&self.symbol, max_age)
+        });
+        let order_book_fut = async {
+            match streamed_order_book {
+                Some(streamed) => Ok(streamed),
+                None => self
+                    .exchange
+                    .fetch_order_book(&self.symbol)
+                    .await
+                    .map(|order_book| (order_book, Instant::now())),
+            }
+        };
         let exchange_params_fut = self.exchange.fetch_exchange_params(&self.symbol);
 
-        let (position_res, balance_res, order_book_res, exchange_params_res) = tokio::join!(
+        let (position_res, position_costs_res, balance_res, order_book_res, exchange_params_res) = tokio::join!(
             position_fut,
+            position_costs_fut,
             balance_fut,
             order_book_fut,
             exchange_params_fut
         );
 
-        self.position = position_res.map_err(|e| -> SendSyncError {
+        let previous_position = self.position;
+        self.positions = position_res.map_err(|e: SendSyncError| {
             error!("[{}] Failed to fetch position: {}", self.symbol, e);
             e
         })?;
+        self.position = self.positions.net();
+        if self.position.size != previous_position.size
+            || self.position.price != previous_position.price
+        {
+            self.events.publish(BotEvent::PositionChanged {
+                symbol: self.symbol.clone(),
+                position: self.position,
+            });
+        }
+        let orders_changed = self.poll_open_orders().await;
+        if self.position.size != previous_position.size
+            || orders_changed
+            || self.fill_detector.has_pending()
+        {
+            self.publish_fills().await;
+        }
+        if self.fills_checked_until_ms == 0 {
+            self.fills_checked_until_ms = self.exchange.now_ms();
+        }
+        // a position the state has no record of was opened outside the bot
+        // adoption assumes a single position, so two legs are left as they are
+        let unknown_position = !self.adoption_checked
+            && !self.holds_two_legs()
+            && self.position.size != 0.0
+            && (self.symbol_state.position_side != self.position.size.signum()
+                || self.symbol_state.adoption_pending);
+        self.adoption_checked = true;
+        let mut state_changed = self
+            .symbol_state
+            .update_position(self.position.size, self.exchange.now_ms());
+        if unknown_position {
+            self.adopt_position();
+            state_changed = true;
+        }
+        if self.update_funding().await {
+            state_changed = true;
+        }
+        if state_changed {
+            self.save_state();
+        }
+        self.position_costs = position_costs_res.map_err(|e| -> SendSyncError {
+            error!("[{}] Failed to fetch position costs: {}", self.symbol, e);
+            e.into()
+        })?;
+        self.position_costs.funding_paid += self.symbol_state.funding_paid;
+        let previous_balance = self.balance;
         self.balance = balance_res.map_err(|e| -> SendSyncError {
             error!("[{}] Failed to fetch balance: {}", self.symbol, e);
-            e
-        })?;
-        self.order_book = order_book_res.map_err(|e| -> SendSyncError {
-            error!("[{}] Failed to fetch order book: {}", self.symbol, e);
-            e
+            e.into()
         })?;
+        if self.balance != previous_balance {
+            self.events.publish(BotEvent::BalanceUpdated {
+                balance: self.balance,
+            });
+        }
+        (self.order_book, self.order_book_fetched_at) =
+            order_book_res.map_err(|e| -> SendSyncError {
+                error!("[{}] Failed to fetch order book: {}", self.symbol, e);
+                e.into()
+            })?;
         self.exchange_params = exchange_params_res.map_err(|e| -> SendSyncError {
             error!("[{}] Failed to fetch exchange params: {}", self.symbol, e);
-            e
+            e.into()
         })?;
+        self.check_unrealized_pnl().await;
 
-        // TODO: Implement EMA calculations
-        self.ema_bands = Default::default();
-        // TODO: Implement trailing price logic
-        self.trailing_price_bundle = Default::default();
+        let equity = self.balance
+            + self.unrealized_pnl()
+            + self
+                .status_board
+                .unrealized_pnl_excluding(&self.venue, &self.symbol);
+        let peak_equity = self.symbol_state.risk_governor.peak_equity;
+        self.exposure_scale = self
+            .symbol_state
+            .risk_governor
+            .update(equity, &self.config.live.risk_governor);
+        if self.symbol_state.risk_governor.peak_equity != peak_equity {
+            self.save_state();
+        }
+
+        self.update_indicators().await;
 
         Ok(())
     }
 
+    /// Polls the open orders and returns true if one may have filled since the last poll,
+    /// so fills are noticed without a private stream.
+    async fn poll_open_orders(&mut self) -> bool {
+        match self.exchange.fetch_open_orders(&self.symbol).await {
+            Ok(open_orders) => {
+                self.open_orders = Some(open_orders.clone());
+                self.exchange.has_fill_history()
+                    && self
+                        .fill_detector
+                        .update_open_orders(open_orders, self.exchange.now_ms())
+            }
+            Err(e) => {
+                warn!("[{}] Failed to fetch open orders: {}", self.symbol, e);
+                self.open_orders = None;
+                false
+            }
+        }
+    }
+
+    /// Publishes the account's fills since the last check. While orders that left the book
+    /// await their trades, the same window is read again on the next cycle. Fills made
+    /// before the bot started are left to `history backfill`.
+    async fn publish_fills(&mut self) {
+        if self.fills_checked_until_ms == 0 || !self.exchange.has_fill_history() {
+            return;
+        }
+        let now_ms = self.exchange.now_ms();
+        match self
+            .exchange
+            .fetch_my_trades(&self.symbol, self.fills_checked_until_ms)
+            .await
+        {
+            Ok(fills) => {
+                for fill in self.fill_detector.confirm(fills, now_ms) {
+                    self.events.publish(BotEvent::OrderFilled { fill });
+                }
+                if !self.fill_detector.has_pending() {
+                    self.fills_checked_until_ms = now_ms;
+                }
+            }
+            Err(e) => debug!("[{}] Failed to fetch fills: {}", self.symbol, e),
+        }
+    }
+
+    /// Accrues the funding settled on the open position since the last check and warns
+    /// when the total crosses `live.funding_alert_threshold`. Returns true if the state
+    /// changed.
+    async fn update_funding(&mut self) -> bool {
+        let now_ms = self.exchange.now_ms();
+        if self.position.size == 0.0 || now_ms < self.next_funding_check_ms {
+            return false;
+        }
+        self.next_funding_check_ms = now_ms + FUNDING_CHECK_INTERVAL_MS;
+        let start_ms = self.symbol_state.funding_checked_until_ms;
+        let payments = match self
+            .exchange
+            .fetch_funding_payments(&self.symbol, start_ms, now_ms)
+            .await
+        {
+            Ok(payments) => payments,
+            Err(e) => {
+                debug!("[{}] Failed to fetch funding payments: {}", self.symbol, e);
+                return false;
+            }
+        };
+        let added = self.symbol_state.accrue_funding(&payments, now_ms);
+        if added != 0.0 {
+            info!(
+                "[{}] Funding settled: {:.4}, paid by the position so far: {:.4}",
+                self.symbol, added, self.symbol_state.funding_paid
+            );
+        }
+        let threshold = self.config.live.funding_alert_threshold;
+        if self.symbol_state.check_funding_alert(threshold) {
+            warn!(
+                "[{}] Position has paid {:.4} in funding, above the alert threshold {}",
+                self.symbol, self.symbol_state.funding_paid, threshold
+            );
+        }
+        true
+    }
+
+    /// Compares the unrealized PnL computed for the position with the one the exchange
+    /// reports, every `PNL_CHECK_INTERVAL_MS`, and warns when they differ by more than
+    /// `live.max_upnl_divergence`. Two legs are left out, as the position is their net and
+    /// the exchange reports them apart.
+    async fn check_unrealized_pnl(&mut self) {
+        let max_divergence = self.config.live.max_upnl_divergence;
+        let now_ms = self.exchange.now_ms();
+        if max_divergence <= 0.0
+            || self.holds_two_legs()
+            || self.position.size == 0.0
+            || now_ms < self.next_pnl_check_ms
+        {
+            return;
+        }
+        self.next_pnl_check_ms = now_ms + PNL_CHECK_INTERVAL_MS;
+        let reported = match self.exchange.fetch_position_pnl(&self.symbol).await {
+            Ok(Some(reported)) => reported,
+            Ok(None) => return,
+            Err(e) => {
+                debug!("[{}] Failed to fetch position PnL: {}", self.symbol, e);
+                return;
+            }
+        };
+        // the position moved between the two requests
+        if (reported.size - self.position.size).abs() > self.exchange_params.qty_step * 0.5 {
+            return;
+        }
+        if let Err(reason) = pnl_check::check_unrealized_pnl(
+            &self.position,
+            &reported,
+            &self.exchange_params,
+            max_divergence,
+        ) {
+            warn!("[{}] Position PnL mismatch: {}", self.symbol, reason);
+        }
+    }
+
+    fn save_state(&self) {
+        if let Some(path) = self.paths.symbol_state(&self.symbol) {
+            if let Err(e) = self.symbol_state.save(&path) {
+                warn!("[{}] Failed to save state: {}", self.symbol, e);
+            }
+        }
+    }
+
+    fn book_price_mode(&self) -> BookPriceMode {
+        let mode = &self.config.live.book_price_mode;
+        BookPriceMode::from_str(mode).unwrap_or_else(|| {
+            warn!("Unknown book price mode: {}", mode);
+            BookPriceMode::Grid
+        })
+    }
+
+    /// Takes over a position found at startup that the bot did not open, according to
+    /// `live.position_adoption`.
+    fn adopt_position(&mut self) {
+        let live = &self.config.live;
+        let mode = PositionAdoption::from_str(&live.position_adoption).unwrap_or_else(|| {
+            warn!("Unknown position adoption mode: {}", live.position_adoption);
+            PositionAdoption::Auto
+        });
+        if mode == PositionAdoption::Off {
+            return;
+        }
+        if mode == PositionAdoption::Confirm && !live.confirmed_adoptions.contains(&self.symbol) {
+            warn!(
+                "[{}] Found position size {} price {} not opened by the bot; add {} to live.confirmed_adoptions and restart to manage it",
+                self.symbol, self.position.size, self.position.price, self.symbol
+            );
+            self.symbol_state.adoption_pending = true;
+            return;
+        }
+        self.symbol_state.adopted = true;
+        self.symbol_state.adoption_pending = false;
+        info!(
+            "[{}] Adopting position size {} price {} not opened by the bot",
+            self.symbol, self.position.size, self.position.price
+        );
+    }
+
+    /// Feeds the 1m candles closed since the last update to the indicator engine. The
+    /// first update warms it up on the latest `MAX_LIVE_CANDLES`, so the bands are settled
+    /// from the first cycle; until then they stay unset.
+    async fn update_indicators(&mut self) {
+        if self.indicators_supplied {
+            return;
+        }
+        let since_ms = self.indicator_engine.since_ms();
+        let candles = match self
+            .exchange
+            .fetch_ohlcv(&self.symbol, "1m", since_ms, MAX_LIVE_CANDLES)
+            .await
+        {
+            Ok(candles) => candles,
+            Err(e) => {
+                warn!("[{}] Failed to fetch candles: {}", self.symbol, e);
+                return;
+            }
+        };
+        let n_fed = self.indicator_engine.update(
+            &candles,
+            self.exchange.now_ms(),
+            &self.config.bot.long,
+            &self.config.bot.short,
+        );
+        if n_fed == 0 {
+            return;
+        }
+        if since_ms.is_none() {
+            info!(
+                "[{}] Indicators warmed up on {} candles",
+                self.symbol, n_fed
+            );
+        }
+        self.trailing_price_bundle = self.indicator_engine.trailing_price_bundle().clone();
+    }
+
+    /// Supplies EMA bands, ATR and trailing prices computed outside the manager for both
+    /// sides, which then stops computing them from the exchange's candles.
+    pub fn set_indicators(
+        &mut self, ema_bands: EMABands, atr: f64, trailing_price_bundle: TrailingPriceBundle,
+    ) {
+        self.indicators_supplied = true;
+        self.ema_bands = ema_bands;
+        self.atr = atr;
+        self.trailing_price_bundle = trailing_price_bundle;
+    }
+
+    /// Reports the user's exposure to its allocation group and returns true if the group's
+    /// combined exposure has reached its budget.
+    fn allocation_group_full(&self, now_ms: i64) -> bool {
+        let Some(group) = allocation::find_group(&self.config.live) else {
+            return false;
+        };
+        let exposure = UserExposure {
+            user: self.config.live.user.clone(),
+            exposure: self
+                .status_board
+                .position_value_excluding(&self.venue, &self.symbol)
+                + utils::qty_to_cost(
+                    self.position.size.abs(),
+                    self.position.price,
+                    self.exchange_params.inverse,
+                    self.exchange_params.c_mult,
+                ),
+            updated_at_ms: now_ms,
+        };
+        if let Err(e) = allocation::publish_exposure(&self.paths, group, &exposure) {
+            warn!("[{}] Failed to publish group exposure: {}", self.symbol, e);
+        }
+        let group_exposure = if self.paths.is_enabled() {
+            allocation::load_group_exposure(&self.paths, group, now_ms)
+        } else {
+            exposure.exposure
+        };
+        let full = group.max_exposure > 0.0 && group_exposure >= group.max_exposure;
+        if full {
+            info!(
+                "[{}] Allocation group {} exposure {:.2} at budget {:.2}, pausing entries",
+                self.symbol, group.name, group_exposure, group.max_exposure
+            );
+        }
+        full
+    }
+
+    /// Price at which closing the position recovers its accrued fees and funding.
+    pub fn break_even_price(&self) -> f64 {
+        let (inverse, c_mult) = (self.exchange_params.inverse, self.exchange_params.c_mult);
+        let costs = self.position_costs.total();
+        if self.position.size > 0.0 {
+            utils::calc_break_even_price_long(
+                self.position.price,
+                self.position.size,
+                costs,
+                inverse,
+                c_mult,
+            )
+        } else {
+            utils::calc_break_even_price_short(
+                self.position.price,
+                self.position.size,
+                costs,
+                inverse,
+                c_mult,
+            )
+        }
+    }
+
+    /// Unrealized PnL of both legs, marked at the side of the book each would close into.
+    pub fn unrealized_pnl(&self) -> f64 {
+        self.position_pnl(&self.positions.long) + self.position_pnl(&self.positions.short)
+    }
+
+    fn position_pnl(&self, position: &Position) -> f64 {
+        let (inverse, c_mult) = (self.exchange_params.inverse, self.exchange_params.c_mult);
+        if position.size > 0.0 {
+            utils::calc_pnl_long(
+                position.price,
+                self.order_book.best_bid(),
+                position.size,
+                inverse,
+                c_mult,
+            )
+        } else if position.size < 0.0 {
+            utils::calc_pnl_short(
+                position.price,
+                self.order_book.best_ask(),
+                position.size,
+                inverse,
+                c_mult,
+            )
+        } else {
+            0.0
+        }
+    }
+
+    /// The position a side's grid works with: its own leg.
+    fn side_position(&self, pside: usize) -> Position {
+        self.positions.leg(pside)
+    }
+
+    /// Whether the symbol is held in two legs, in pair mode or by the exchange's hedge mode,
+    /// rather than as a single position.
+    fn holds_two_legs(&self) -> bool {
+        self.config.live.pair_mode.enabled
+            || (self.positions.long.size != 0.0 && self.positions.short.size != 0.0)
+    }
+
+    /// Cross-checks the ticker, the order book mid and the latest candle close against
+    /// `live.max_price_divergence`. Sources the exchange cannot provide are left out.
+    async fn check_price_sources(&self) -> Result<(), String> {
+        let max_divergence = self.config.live.max_price_divergence;
+        if max_divergence <= 0.0 {
+            return Ok(());
+        }
+        let streamed_last = self
+            .market_feed
+            .as_ref()
+            .and_then(|feed| {
+                let max_age = Duration::from_secs_f64(self.config.live.max_book_age_seconds);
+                feed.ticker(&self.symbol, max_age)
+            })
+            .map(|ticker| ticker.last)
+            .filter(|last| *last > 0.0);
+        let ticker_fut = async {
+            match streamed_last {
+                Some(last) => Ok(last),
+                None => self.exchange.fetch_ticker(&self.symbol).await,
+            }
+        };
+        let (ticker_res, close_res) =
+            tokio::join!(ticker_fut, self.exchange.fetch_last_close(&self.symbol));
+        let mid = (self.order_book.best_bid() + self.order_book.best_ask()) / 2.0;
+        let mut sources = vec![("book mid", mid)];
+        match ticker_res {
+            Ok(last) => sources.push(("ticker", last)),
+            Err(e) => warn!("[{}] Failed to fetch ticker: {}", self.symbol, e),
+        }
+        match close_res {
+            Ok(Some(close)) => sources.push(("candle close", close)),
+            Ok(None) => {}
+            Err(e) => warn!("[{}] Failed to fetch last candle: {}", self.symbol, e),
+        }
+        price_check::check_price_sources(&sources, max_divergence)
+    }
+
     async fn execute_logic(&mut self) {
         info!("[{}] Executing logic", self.symbol);
+        let placements = self.order_queue.take_placement_summary(&self.symbol);
+        if !placements.rejected.is_empty() {
+            warn!("[{}] Last cycle {}", self.symbol, placements.format());
+        } else if !placements.is_empty() {
+            info!("[{}] Last cycle {}", self.symbol, placements.format());
+        }
 
-        let state_params = StateParams {
-            balance: self.balance,
-            order_book: self.order_book.clone(),
-            ema_bands: self.ema_bands.clone(),
+        let book_age = self.order_book_fetched_at.elapsed().as_secs_f64();
+        let book_check = if book_age > self.config.live.max_book_age_seconds {
+            Err(format!("order book is {:.1}s old", book_age))
+        } else {
+            self.order_book
+                .check_usable(self.config.live.max_book_spread)
         };
+        if let Err(reason) = book_check {
+            warn!(
+                "[{}] Order book unusable, skipping order generation: {}",
+                self.symbol, reason
+            );
+            return;
+        }
+        if let Err(reason) = self.check_price_sources().await {
+            error!(
+                "[{}] Market data suspect, skipping order placement: {}",
+                self.symbol, reason
+            );
+            self.events.publish(BotEvent::Error {
+                symbol: self.symbol.clone(),
+                message: format!("Market data suspect: {}", reason),
+            });
+            return;
+        }
+
+        let (long_params, short_params) = (self.state_params(LONG), self.state_params(SHORT));
+
+        if self.symbol_state.adoption_pending {
+            info!(
+                "[{}] Position awaits adoption confirmation, placing no orders",
+                self.symbol
+            );
+            return;
+        }
 
-        let long_cfg = &self.config.bot.long;
-        let short_cfg = &self.config.bot.short;
+        if self.exposure_scale < 1.0 {
+            info!(
+                "[{}] Risk governor scaling exposure limits by {:.3}",
+                self.symbol, self.exposure_scale
+            );
+        }
+        let (long_cfg, short_cfg) = self.side_configs();
+        let (long_cfg, short_cfg) = (&long_cfg, &short_cfg);
+        let long_mode = self.side_mode(LONG);
+        let short_mode = self.side_mode(SHORT);
+        if self.last_modes != Some((long_mode, short_mode)) {
+            self.last_modes = Some((long_mode, short_mode));
+            self.events.publish(BotEvent::ModeChanged {
+                symbol: self.symbol.clone(),
+                long: long_mode,
+                short: short_mode,
+            });
+        }
+        let now = DateTime::from_timestamp_millis(self.exchange.now_ms()).unwrap_or_else(Utc::now);
+        let entries_paused = self.entry_schedule.entries_paused(now)
+            || self.allocation_group_full(now.timestamp_millis());
+        let position_age_days = self.symbol_state.position_age_days(now.timestamp_millis());
+        if entries_paused {
+            info!(
+                "[{}] Inside no-entry window, managing exits only",
+                self.symbol
+            );
+        }
+
+        if self.position.size != 0.0 {
+            info!(
+                "[{}] Position size {} price {} break-even {} upnl {:.4}",
+                self.symbol,
+                self.position.size,
+                self.position.price,
+                self.break_even_price(),
+                self.unrealized_pnl()
+            );
+        }
+
+        let (long_position, short_position) = (self.side_position(LONG), self.side_position(SHORT));
+        let (long_balanced, short_balanced) = if self.config.live.pair_mode.enabled {
+            pair_mode::entries_allowed(
+                &self.positions,
+                self.balance,
+                &self.exchange_params,
+                self.config.live.pair_mode.max_imbalance,
+            )
+        } else {
+            (true, true)
+        };
+        if !long_balanced || !short_balanced {
+            info!(
+                "[{}] Pair legs imbalanced (long {} short {}), pausing {} entries",
+                self.symbol,
+                long_position.size,
+                short_position.size,
+                if long_balanced { "short" } else { "long" }
+            );
+        }
 
         let mut all_orders = Vec::new();
-        all_orders.extend(entries::calc_entries_long(
-            &self.exchange_params,
-            &state_params,
-            long_cfg,
-            &self.position,
-            &self.trailing_price_bundle,
-        ));
-        all_orders.extend(entries::calc_entries_short(
-            &self.exchange_params,
-            &state_params,
-            short_cfg,
-            &self.position,
-            &self.trailing_price_bundle,
-        ));
-        all_orders.extend(closes::calc_closes_long(
-            &self.exchange_params,
-            &state_params,
-            long_cfg,
-            &self.position,
-            &self.trailing_price_bundle,
-        ));
-        all_orders.extend(closes::calc_closes_short(
-            &self.exchange_params,
-            &state_params,
-            short_cfg,
-            &self.position,
-            &self.trailing_price_bundle,
-        ));
+        if !entries_paused && long_balanced && long_mode.allows_entries(long_position.size.max(0.0))
+        {
+            all_orders.extend(entries::calc_entries_long(
+                &self.exchange_params,
+                &long_params,
+                long_cfg,
+                &long_position,
+                &self.trailing_price_bundle,
+            ));
+        }
+        if !entries_paused
+            && short_balanced
+            && short_mode.allows_entries(short_position.size.min(0.0))
+        {
+            all_orders.extend(entries::calc_entries_short(
+                &self.exchange_params,
+                &short_params,
+                short_cfg,
+                &short_position,
+                &self.trailing_price_bundle,
+            ));
+        }
+        if long_mode.allows_closes() {
+            let mut closes_long = self.calc_take_profit_closes(LONG, long_cfg, &long_params);
+            closes_long.extend(closes::calc_aged_unstuck_close_long(
+                &self.exchange_params,
+                &long_params,
+                long_cfg,
+                &long_position,
+                position_age_days,
+                &closes_long,
+            ));
+            all_orders.extend(closes_long);
+        }
+        if short_mode.allows_closes() {
+            let mut closes_short = self.calc_take_profit_closes(SHORT, short_cfg, &short_params);
+            closes_short.extend(closes::calc_aged_unstuck_close_short(
+                &self.exchange_params,
+                &short_params,
+                short_cfg,
+                &short_position,
+                position_age_days,
+                &closes_short,
+            ));
+            all_orders.extend(closes_short);
+        }
+        pricing::apply_book_price_mode(
+            &mut all_orders,
+            &self.order_book,
+            self.exchange_params.price_step,
+            self.book_price_mode(),
+        );
+        self.apply_order_jitter(&mut all_orders);
 
-        if let Err(e) = self.place_grid_orders(all_orders).await {
+        if let Err(e) = self.place_grid_orders(&all_orders).await {
             error!("[{}] Failed to place orders: {}", self.symbol, e);
         }
+
+        self.closes_priced_at_mid = (self.order_book.best_bid() + self.order_book.best_ask()) / 2.0;
+
+        self.publish_status(long_mode, short_mode, &all_orders);
+    }
+
+    /// The side's configured mode, gracefully stopping where it would trade normally while
+    /// the symbol is wound down.
+    fn side_mode(&self, pside: usize) -> TradingMode {
+        let mode = self.config.side_mode(pside);
+        if mode == TradingMode::Normal && self.winding_down.load(Ordering::Relaxed) {
+            TradingMode::GracefulStop
+        } else {
+            mode
+        }
+    }
+
+    /// The market state `pside`'s orders are computed on, with that side's EMA bands.
+    fn state_params(&self, pside: usize) -> StateParams {
+        let (ema_bands, atr) = if self.indicators_supplied {
+            (self.ema_bands.clone(), self.atr)
+        } else {
+            (
+                self.indicator_engine.ema_bands(pside),
+                self.indicator_engine.atr(pside),
+            )
+        };
+        let realized_pnl = self.status_board.realized_pnl().unwrap_or_default();
+        StateParams {
+            balance: self.balance,
+            order_book: self.order_book.clone(),
+            ema_bands,
+            atr,
+            pnl_cumsum_max: realized_pnl.pnl_cumsum_max,
+            pnl_cumsum_last: realized_pnl.pnl_cumsum_last,
+        }
+    }
+
+    /// Bot params of both sides with exposure limits scaled by the risk governor and capped
+    /// by the risk limit, adjusted for an adopted position.
+    fn side_configs(&self) -> (BotSideConfig, BotSideConfig) {
+        let risk_limit_cap = self.order_queue.risk_limit_cap(&self.symbol);
+        let long_cfg =
+            risk_governor::scale_exposure_limit(&self.config.bot.long, self.exposure_scale);
+        let short_cfg =
+            risk_governor::scale_exposure_limit(&self.config.bot.short, self.exposure_scale);
+        let mut long_cfg = risk_limit::cap_exposure_limit(&long_cfg, risk_limit_cap, self.balance);
+        let mut short_cfg =
+            risk_limit::cap_exposure_limit(&short_cfg, risk_limit_cap, self.balance);
+        if self.symbol_state.adopted {
+            if self.position.size > 0.0 {
+                long_cfg = entries::calc_adopted_params(
+                    &self.exchange_params,
+                    &long_cfg,
+                    self.balance,
+                    self.position.size,
+                    self.order_book.best_bid(),
+                );
+            } else if self.position.size < 0.0 {
+                short_cfg = entries::calc_adopted_params(
+                    &self.exchange_params,
+                    &short_cfg,
+                    self.balance,
+                    -self.position.size,
+                    self.order_book.best_ask(),
+                );
+            }
+        }
+        (long_cfg, short_cfg)
+    }
+
+    /// Take-profit closes of one side, the nearest floored at break-even if configured.
+    fn calc_take_profit_closes(
+        &self, pside: usize, side_cfg: &BotSideConfig, state_params: &StateParams,
+    ) -> Vec<GridOrder> {
+        let position = self.side_position(pside);
+        let mut closes = if pside == LONG {
+            closes::calc_closes_long(
+                &self.exchange_params,
+                state_params,
+                side_cfg,
+                &position,
+                &self.trailing_price_bundle,
+            )
+        } else {
+            closes::calc_closes_short(
+                &self.exchange_params,
+                state_params,
+                side_cfg,
+                &position,
+                &self.trailing_price_bundle,
+            )
+        };
+        let in_position = if pside == LONG {
+            position.size > 0.0
+        } else {
+            position.size < 0.0
+        };
+        if side_cfg.close_floor_at_break_even && in_position {
+            // accrued costs belong to the net position, so a leg breaks even at its entry
+            let break_even = if self.holds_two_legs() {
+                position.price
+            } else {
+                self.break_even_price()
+            };
+            closes::floor_nearest_close_at_break_even(
+                &mut closes,
+                break_even,
+                self.exchange_params.price_step,
+            );
+        }
+        closes
+    }
+
+    fn apply_order_jitter(&self, orders: &mut [GridOrder]) {
+        jitter::apply_order_jitter(
+            orders,
+            &self.order_book,
+            &self.exchange_params,
+            &self.config.live.order_jitter,
+            self.jitter_seed,
+        );
+    }
+
+    fn publish_status(
+        &self, long_mode: TradingMode, short_mode: TradingMode, orders: &[GridOrder],
+    ) {
+        let mid_price = (self.order_book.best_bid() + self.order_book.best_ask()) / 2.0;
+        let nearest_price = |is_close: bool| {
+            orders
+                .iter()
+                .filter(|o| o.qty != 0.0 && o.order_type.is_close() == is_close)
+                .map(|o| o.price)
+                .min_by(|a, b| (a - mid_price).abs().total_cmp(&(b - mid_price).abs()))
+        };
+        let realized_pnl = self.status_board.realized_pnl().unwrap_or_default();
+        let unstuck_allowance = |loss_allowance_pct: f64| {
+            utils::calc_auto_unstuck_allowance(
+                self.balance,
+                loss_allowance_pct,
+                realized_pnl.pnl_cumsum_max,
+                realized_pnl.pnl_cumsum_last,
+            )
+        };
+        let positions = if self.holds_two_legs() {
+            vec![self.positions.long, self.positions.short]
+        } else {
+            vec![self.position]
+        };
+        let entry_orders: Vec<(f64, f64)> = orders
+            .iter()
+            .filter(|o| o.qty != 0.0 && !o.order_type.is_close())
+            .map(|o| (o.qty, o.price))
+            .collect();
+        self.status_board.update(SymbolStatus {
+            symbol: self.symbol.clone(),
+            venue: self.venue.clone(),
+            mode_long: format!("{:?}", long_mode),
+            mode_short: format!("{:?}", short_mode),
+            position_size: self.position.size,
+            position_price: self.position.price,
+            wallet_exposure: utils::calc_wallet_exposure(
+                self.exchange_params.c_mult,
+                self.balance,
+                self.position.size,
+                self.position.price,
+                self.exchange_params.inverse,
+            ),
+            nearest_entry_price: nearest_price(false),
+            nearest_close_price: nearest_price(true),
+            unstuck_allowance_long: unstuck_allowance(
+                self.config.bot.long.unstuck_loss_allowance_pct,
+            ),
+            unstuck_allowance_short: unstuck_allowance(
+                self.config.bot.short.unstuck_loss_allowance_pct,
+            ),
+            unrealized_pnl: self.unrealized_pnl(),
+            funding_paid: self.symbol_state.funding_paid,
+            last_order_diff: self.last_order_diff.clone(),
+            order_queue_depth: self.order_queue.depth(),
+            balance: self.balance,
+            leverage: self.config.live.leverage,
+            margin: margin::symbol_margin(
+                &positions,
+                &entry_orders,
+                &self.exchange_params,
+                self.config.live.leverage,
+                self.config.live.maintenance_margin_rate,
+            ),
+            updated_at_ms: Utc::now().timestamp_millis(),
+        });
+    }
+
+    fn to_order(&self, grid_order: &GridOrder) -> Order {
+        Order {
+            id: "".to_string(),
+            symbol: self.symbol.clone(),
+            side: if grid_order.qty > 0.0 {
+                "Buy".to_string()
+            } else {
+                "Sell".to_string()
+            },
+            // closes belong to the side they close, which matters in hedge mode
+            position_side: if grid_order.order_type.is_long() {
+                "Long".to_string()
+            } else {
+                "Short".to_string()
+            },
+            qty: grid_order.qty.abs(),
+            price: grid_order.price,
+            reduce_only: grid_order.order_type.is_close(),
+            custom_id: grid_order.order_type.to_string(),
+            client_order_id: String::new(),
+            time_in_force: self.config.live.time_in_force.clone(),
+        }
     }
 
-    async fn place_grid_orders(
-        &mut self, grid_orders: Vec<GridOrder>,
-    ) -> Result<(), SendSyncError> {
+    async fn place_grid_orders(&mut self, grid_orders: &[GridOrder]) -> Result<(), SendSyncError> {
+        // closes still go out, so positions can be wound down
+        let quarantined = self.order_queue.is_quarantined(&self.symbol);
+        if quarantined {
+            info!("[{}] Symbol is quarantined, skipping entries", self.symbol);
+        }
+
         let price_dist_thresh = self.config.live.price_distance_threshold;
         let mid_price = (self.order_book.best_bid() + self.order_book.best_ask()) / 2.0;
 
         let mut orders_to_place = Vec::new();
         for grid_order in grid_orders {
+            if quarantined && !grid_order.order_type.is_close() {
+                continue;
+            }
             if price_dist_thresh > 0.0 {
                 let price_dist = (grid_order.price - mid_price).abs() / mid_price;
                 if price_dist > price_dist_thresh {
@@ -156,43 +1315,127 @@ impl Manager {
                     continue;
                 }
             }
-            orders_to_place.push(grid_order);
+            orders_to_place.push(*grid_order);
         }
 
-        let batch_size = self.config.live.max_n_creations_per_batch as usize;
-        for chunk in orders_to_place.chunks(batch_size) {
-            let orders: Vec<Order> = chunk
-                .iter()
-                .map(|grid_order| Order {
-                    id: "".to_string(),
+        let diff = order_diff::diff_orders(&self.last_orders, &orders_to_place);
+        let grid_changed = !diff.is_empty();
+        debug!(
+            "[{}] Order diff: {}",
+            self.symbol,
+            serde_json::to_string(&diff).unwrap_or_default()
+        );
+        self.last_orders = orders_to_place.clone();
+
+        // without the resting orders, placing the grid again could duplicate it
+        let Some(open_orders) = &self.open_orders else {
+            warn!(
+                "[{}] Open orders unknown, skipping order placement",
+                self.symbol
+            );
+            return Ok(());
+        };
+        let desired: Vec<Order> = orders_to_place
+            .iter()
+            .map(|grid_order| self.to_order(grid_order))
+            .collect();
+        let reconciliation = order_diff::reconcile(
+            open_orders,
+            &desired,
+            self.exchange_params.price_step,
+            self.exchange_params.qty_step,
+        );
+        self.last_order_diff = reconciliation.to_diff();
+        if self.config.live.read_only {
+            // nothing rests on the account, so only report a grid that changed
+            if grid_changed
+                && (!reconciliation.to_cancel.is_empty() || !reconciliation.to_create.is_empty())
+            {
+                self.events.publish(BotEvent::OrdersIntended {
                     symbol: self.symbol.clone(),
-                    side: if grid_order.qty > 0.0 {
-                        "Buy".to_string()
-                    } else {
-                        "Sell".to_string()
-                    },
-                    position_side: if grid_order.qty > 0.0 {
-                        "Long".to_string()
-                    } else {
-                        "Short".to_string()
-                    },
-                    qty: grid_order.qty.abs(),
-                    price: grid_order.price,
-                    reduce_only: false,
-                    custom_id: grid_order.order_type.to_string(),
-                    time_in_force: self.config.live.time_in_force.clone(),
-                })
-                .collect();
-
-            // In a real scenario, we'd use a batch order endpoint if available.
-            // For now, we place them sequentially as before.
-            for order in &orders {
-                if let Err(e) = self.exchange.place_order(order).await {
-                    error!("[{}] Failed to place order: {}", self.symbol, e);
-                }
+                    to_cancel: reconciliation.to_cancel,
+                    to_create: reconciliation.to_create,
+                });
             }
+            return Ok(());
         }
+        if !reconciliation.to_cancel.is_empty() || !reconciliation.to_create.is_empty() {
+            info!(
+                "[{}] Reconciling orders: {} kept, {} to cancel, {} to create",
+                self.symbol,
+                reconciliation.kept.len(),
+                reconciliation.to_cancel.len(),
+                reconciliation.to_create.len()
+            );
+        }
+        for order in &reconciliation.to_cancel {
+            self.order_queue.cancel(&self.symbol, &order.id)?;
+        }
+        for order in reconciliation.to_create {
+            self.order_queue.place(order)?;
+        }
+        info!(
+            "[{}] Order queue depth: {}",
+            self.symbol,
+            self.order_queue.depth()
+        );
 
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::exchange::intent_log::IntentLog;
+    use crate::exchange::quarantine::SymbolQuarantine;
+    use crate::exchange::queue::BatchLimits;
+    use crate::exchange::simulated::SimulatedExchange;
+    use crate::snapshot::SymbolSnapshot;
+    use crate::types::Position;
+
+    async fn set_up(simulated: &SimulatedExchange) {
+        let mut config =
+            crate::config::load_config("tests/fixtures/backtest_config.hjson").unwrap();
+        config.live.state_dir = String::new();
+        config.live.leverage = 5.0;
+        let order_queue = OrderQueue::spawn(
+            simulated.clone_box(),
+            SymbolQuarantine::new(0, Duration::ZERO),
+            IntentLog::default(),
+            EventBus::default(),
+            BatchLimits::default(),
+        );
+        let mut manager = Manager::new(
+            "BTCUSDT".to_string(),
+            config,
+            simulated.clone_box(),
+            order_queue,
+            StatusBoard::default(),
+        );
+        manager.set_up_symbol().await;
+    }
+
+    #[tokio::test]
+    async fn test_set_up_symbol_keeps_the_leverage_of_an_open_position() {
+        let flat = SimulatedExchange::new(1000.0);
+        set_up(&flat).await;
+        assert_eq!(flat.snapshot().leverage, 5.0);
+
+        let in_position = SimulatedExchange::new(1000.0);
+        in_position.load_snapshot(
+            "BTCUSDT",
+            &SymbolSnapshot {
+                position: Position {
+                    size: -0.5,
+                    price: 100.0,
+                },
+                last_price: 100.0,
+                exchange_params: in_position.snapshot().exchange_params,
+                ..Default::default()
+            },
+        );
+        set_up(&in_position).await;
+        assert_eq!(in_position.snapshot().leverage, 0.0);
+    }
+}
@@ -0,0 +1,137 @@
+//! Margin usage at the configured leverage, per symbol and for the account, so the status
+//! shows how close the bot is to the exchange refusing new entries or liquidating.
+
+use crate::control::SymbolStatus;
+use crate::grid::utils::qty_to_cost;
+use crate::types::{ExchangeParams, Position};
+use serde::{Deserialize, Serialize};
+
+/// Margin one symbol ties up, in quote currency.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+pub struct SymbolMargin {
+    /// Position value / leverage.
+    pub initial_margin: f64,
+    /// Position value × `live.maintenance_margin_rate`.
+    pub maintenance_margin: f64,
+    /// Initial margin the exchange reserves for resting entry orders.
+    pub order_margin: f64,
+}
+
+/// `positions` are the symbol's open positions, both legs in pair mode, and
+/// `entry_orders` the (qty, price) of its resting entries.
+pub fn symbol_margin(
+    positions: &[Position], entry_orders: &[(f64, f64)], params: &ExchangeParams, leverage: f64,
+    maintenance_margin_rate: f64,
+) -> SymbolMargin {
+    let leverage = leverage.max(1.0);
+    let cost = |qty: f64, price: f64| qty_to_cost(qty.abs(), price, params.inverse, params.c_mult);
+    let position_value: f64 = positions
+        .iter()
+        .filter(|p| p.size != 0.0)
+        .map(|p| cost(p.size, p.price))
+        .sum();
+    let order_value: f64 = entry_orders
+        .iter()
+        .map(|&(qty, price)| cost(qty, price))
+        .sum();
+    SymbolMargin {
+        initial_margin: position_value / leverage,
+        maintenance_margin: position_value * maintenance_margin_rate,
+        order_margin: order_value / leverage,
+    }
+}
+
+/// Margin of the whole account. Usages are fractions of equity: at 100% initial margin
+/// usage the exchange refuses new entries, at 100% maintenance margin usage it liquidates.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+pub struct AccountMargin {
+    /// Balance plus the unrealized PnL of all symbols.
+    pub equity: f64,
+    pub initial_margin: f64,
+    pub maintenance_margin: f64,
+    pub order_margin: f64,
+    /// (initial + order margin) / equity.
+    pub initial_margin_usage: f64,
+    pub maintenance_margin_usage: f64,
+    /// Margin left for new entries, and the position value it funds at the lowest
+    /// leverage of the symbols.
+    pub headroom: f64,
+    pub headroom_position_value: f64,
+}
+
+pub fn account_margin(balance: f64, symbols: &[SymbolStatus]) -> AccountMargin {
+    let mut margin = AccountMargin {
+        equity: balance + symbols.iter().map(|s| s.unrealized_pnl).sum::<f64>(),
+        ..Default::default()
+    };
+    for s in symbols {
+        margin.initial_margin += s.margin.initial_margin;
+        margin.maintenance_margin += s.margin.maintenance_margin;
+        margin.order_margin += s.margin.order_margin;
+    }
+    let used = margin.initial_margin + margin.order_margin;
+    if margin.equity > 0.0 {
+        margin.initial_margin_usage = used / margin.equity;
+        margin.maintenance_margin_usage = margin.maintenance_margin / margin.equity;
+    } else if used > 0.0 {
+        margin.initial_margin_usage = f64::INFINITY;
+        margin.maintenance_margin_usage = f64::INFINITY;
+    }
+    margin.headroom = (margin.equity - used).max(0.0);
+    let leverage = symbols
+        .iter()
+        .map(|s| s.leverage)
+        .filter(|&leverage| leverage > 0.0)
+        .fold(f64::INFINITY, f64::min);
+    if leverage.is_finite() {
+        margin.headroom_position_value = margin.headroom * leverage.max(1.0);
+    }
+    margin
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_symbol_and_account_margin() {
+        let params = ExchangeParams {
+            c_mult: 1.0,
+            ..Default::default()
+        };
+        let position = Position {
+            size: 2.0,
+            price: 100.0,
+        };
+        let margin = symbol_margin(
+            &[position],
+            &[(1.0, 95.0), (-0.5, 110.0)],
+            &params,
+            10.0,
+            0.005,
+        );
+        assert_eq!(margin.initial_margin, 20.0);
+        assert_eq!(margin.maintenance_margin, 1.0);
+        assert_eq!(margin.order_margin, 15.0);
+
+        let status = |symbol: &str, margin: SymbolMargin, unrealized_pnl: f64| SymbolStatus {
+            symbol: symbol.to_string(),
+            margin,
+            unrealized_pnl,
+            leverage: 10.0,
+            ..Default::default()
+        };
+        let account = account_margin(
+            100.0,
+            &[
+                status("BTCUSDT", margin, -5.0),
+                status("ETHUSDT", SymbolMargin::default(), 5.0),
+            ],
+        );
+        assert_eq!(account.equity, 100.0);
+        assert_eq!(account.initial_margin_usage, 0.35);
+        assert_eq!(account.maintenance_margin_usage, 0.01);
+        assert_eq!(account.headroom, 65.0);
+        assert_eq!(account.headroom_position_value, 650.0);
+    }
+}
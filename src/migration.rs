@@ -0,0 +1,308 @@
+//! Moving open positions to a new config, such as one produced by the optimizer. The grid
+//! each symbol would run under the old and the new params is computed by replaying the
+//! account's snapshot, the differences are reported, and only with `--confirm` are the
+//! resting orders replaced and the new config installed.
+
+use crate::config;
+use crate::constants::{LONG, SHORT};
+use crate::exchange::{Exchange, SendSyncError};
+use crate::grid::utils::{calc_wallet_exposure, qty_to_cost};
+use crate::order_diff;
+use crate::snapshot::{self, AccountSnapshot};
+use crate::types::{BotConfig, Order, Position};
+use clap::Parser;
+use serde::Serialize;
+use std::fmt::Write;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::info;
+
+#[derive(Parser, Debug, Clone)]
+pub struct MigrateArgs {
+    /// User/account name defined in api-keys.json
+    #[clap(long)]
+    pub user: String,
+
+    /// The config to migrate to
+    #[clap(long)]
+    pub config: PathBuf,
+
+    /// Symbols to migrate, defaults to live.approved_coins of both configs
+    #[clap(long, value_delimiter = ',')]
+    pub symbols: Vec<String>,
+
+    /// Replaces the orders and installs the new config as config.hjson; without it the
+    /// differences are only reported
+    #[clap(long)]
+    pub confirm: bool,
+}
+
+/// One symbol's grid under the old and the new config.
+#[derive(Serialize, Debug, Clone)]
+pub struct SymbolMigration {
+    pub symbol: String,
+    pub position: Position,
+    pub wallet_exposure: f64,
+    /// `total_wallet_exposure_limit` of the position's side, or of the long side if flat.
+    pub exposure_limit_before: f64,
+    pub exposure_limit_after: f64,
+    /// Position value the side may still add, in quote currency.
+    pub entry_budget_before: f64,
+    pub entry_budget_after: f64,
+    pub orders_before: Vec<Order>,
+    pub orders_after: Vec<Order>,
+    /// Resting orders the new grid does not keep, and orders it adds.
+    pub to_cancel: Vec<Order>,
+    pub to_create: Vec<Order>,
+    pub n_kept: usize,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct MigrationPlan {
+    pub user: String,
+    pub balance: f64,
+    pub symbols: Vec<SymbolMigration>,
+}
+
+/// Position value a side may still add before reaching its exposure limit.
+pub fn entry_budget(
+    total_wallet_exposure_limit: f64, balance: f64, position: &Position, c_mult: f64, inverse: bool,
+) -> f64 {
+    let position_cost = if position.size == 0.0 {
+        0.0
+    } else {
+        qty_to_cost(position.size.abs(), position.price, inverse, c_mult)
+    };
+    (balance * total_wallet_exposure_limit - position_cost).max(0.0)
+}
+
+/// Compares the grids `old` and `new` run on each symbol of `account` with a position or
+/// resting orders, and the orders it takes to get from the resting ones to the new grid.
+pub async fn plan(
+    old: &BotConfig, new: &BotConfig, account: &AccountSnapshot,
+) -> Result<MigrationPlan, SendSyncError> {
+    let mut symbols = Vec::new();
+    for (symbol, symbol_snapshot) in &account.symbols {
+        let position = symbol_snapshot.position;
+        if position.size == 0.0 && symbol_snapshot.open_orders.is_empty() {
+            continue;
+        }
+        let before = snapshot::replay(old, account, symbol, 1).await?;
+        let after = snapshot::replay(new, account, symbol, 1).await?;
+        let params = &symbol_snapshot.exchange_params;
+        let pside = if position.size < 0.0 { SHORT } else { LONG };
+        let exposure_limit = |config: &BotConfig| {
+            if pside == LONG {
+                config.bot.long.total_wallet_exposure_limit
+            } else {
+                config.bot.short.total_wallet_exposure_limit
+            }
+        };
+        let budget = |limit: f64| {
+            entry_budget(
+                limit,
+                account.balance,
+                &position,
+                params.c_mult,
+                params.inverse,
+            )
+        };
+        let reconciliation = order_diff::reconcile(
+            &symbol_snapshot.open_orders,
+            &after.orders_after,
+            params.price_step,
+            params.qty_step,
+        );
+        symbols.push(SymbolMigration {
+            symbol: symbol.clone(),
+            position,
+            wallet_exposure: calc_wallet_exposure(
+                params.c_mult,
+                account.balance,
+                position.size.abs(),
+                position.price,
+                params.inverse,
+            ),
+            exposure_limit_before: exposure_limit(old),
+            exposure_limit_after: exposure_limit(new),
+            entry_budget_before: budget(exposure_limit(old)),
+            entry_budget_after: budget(exposure_limit(new)),
+            orders_before: before.orders_after,
+            orders_after: after.orders_after,
+            to_cancel: reconciliation.to_cancel,
+            to_create: reconciliation.to_create,
+            n_kept: reconciliation.kept.len(),
+        });
+    }
+    Ok(MigrationPlan {
+        user: account.user.clone(),
+        balance: account.balance,
+        symbols,
+    })
+}
+
+/// Replaces the resting orders symbol by symbol: cancels what the new grid does not keep,
+/// then places its closes before its entries. Stops at the first failure, leaving the
+/// symbols before it migrated.
+pub async fn apply(exchange: &mut dyn Exchange, plan: &MigrationPlan) -> Result<(), SendSyncError> {
+    for migration in &plan.symbols {
+        for order in &migration.to_cancel {
+            exchange.cancel_order(&migration.symbol, &order.id).await?;
+        }
+        let mut to_create = migration.to_create.clone();
+        to_create.sort_by_key(|order| !order.reduce_only);
+        for mut order in to_create {
+            // ids of the replay's simulated exchange
+            order.id = String::new();
+            order.client_order_id = String::new();
+            exchange.place_order(&order).await?;
+        }
+        info!(
+            "[{}] Migrated: {} orders cancelled, {} placed, {} kept",
+            migration.symbol,
+            migration.to_cancel.len(),
+            migration.to_create.len(),
+            migration.n_kept
+        );
+    }
+    Ok(())
+}
+
+/// Keeps the current config as `<path>.bak` and writes `new_config` in its place.
+pub fn install_config(path: &Path, new_config: &Path) -> Result<(), SendSyncError> {
+    // parse first, so a broken config never replaces a working one
+    config::load_config(new_config)?;
+    let backup = path.with_extension("hjson.bak");
+    fs::copy(path, &backup)?;
+    fs::copy(new_config, path)?;
+    info!(
+        "Installed {} as {}, the previous config is kept as {}",
+        new_config.display(),
+        path.display(),
+        backup.display()
+    );
+    Ok(())
+}
+
+pub fn format_plan(plan: &MigrationPlan) -> String {
+    let summarize = |orders: &[Order], reduce_only: bool| {
+        let orders: Vec<&Order> = orders
+            .iter()
+            .filter(|o| o.reduce_only == reduce_only)
+            .collect();
+        if orders.is_empty() {
+            return "none".to_string();
+        }
+        let qty: f64 = orders.iter().map(|o| o.qty).sum();
+        let min = orders.iter().map(|o| o.price).fold(f64::INFINITY, f64::min);
+        let max = orders
+            .iter()
+            .map(|o| o.price)
+            .fold(f64::NEG_INFINITY, f64::max);
+        format!("{} orders, qty {:.6} @ {}..{}", orders.len(), qty, min, max)
+    };
+    let mut out = format!("user: {}  balance: {:.2}\n", plan.user, plan.balance);
+    if plan.symbols.is_empty() {
+        out.push_str("no positions or open orders to migrate\n");
+    }
+    for m in &plan.symbols {
+        let _ = writeln!(
+            out,
+            "{}: position {} @ {}  WE {:.4}  limit {} -> {}  entry budget {:.2} -> {:.2}",
+            m.symbol,
+            m.position.size,
+            m.position.price,
+            m.wallet_exposure,
+            m.exposure_limit_before,
+            m.exposure_limit_after,
+            m.entry_budget_before,
+            m.entry_budget_after
+        );
+        let _ = writeln!(
+            out,
+            "  closes:  {}  ->  {}",
+            summarize(&m.orders_before, true),
+            summarize(&m.orders_after, true)
+        );
+        let _ = writeln!(
+            out,
+            "  entries: {}  ->  {}",
+            summarize(&m.orders_before, false),
+            summarize(&m.orders_after, false)
+        );
+        let _ = writeln!(
+            out,
+            "  orders: {} kept, {} to cancel, {} to create",
+            m.n_kept,
+            m.to_cancel.len(),
+            m.to_create.len()
+        );
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::snapshot::SymbolSnapshot;
+    use crate::types::ExchangeParams;
+    use std::collections::BTreeMap;
+
+    #[tokio::test]
+    async fn test_plan_compares_grids_under_both_configs() {
+        let mut old = config::load_config("tests/fixtures/backtest_config.hjson").unwrap();
+        old.bot.short.enabled = false;
+        let mut new = old.clone();
+        new.bot.long.total_wallet_exposure_limit = 0.5;
+        new.bot.long.close_grid_markup_range *= 2.0;
+        let position = Position {
+            size: 2.0,
+            price: 100.0,
+        };
+        let account = AccountSnapshot {
+            user: "alice".to_string(),
+            balance: 1000.0,
+            symbols: BTreeMap::from([
+                (
+                    "BTCUSDT".to_string(),
+                    SymbolSnapshot {
+                        position,
+                        last_price: 100.0,
+                        exchange_params: ExchangeParams {
+                            qty_step: 0.001,
+                            price_step: 0.01,
+                            min_qty: 0.001,
+                            min_cost: 1.0,
+                            c_mult: 1.0,
+                            inverse: false,
+                        },
+                        ..Default::default()
+                    },
+                ),
+                ("ETHUSDT".to_string(), SymbolSnapshot::default()),
+            ]),
+            ..Default::default()
+        };
+
+        let plan = plan(&old, &new, &account).await.unwrap();
+        // flat symbols without orders are left alone
+        assert_eq!(plan.symbols.len(), 1);
+        let m = &plan.symbols[0];
+        assert!((m.wallet_exposure - 0.2).abs() < 1e-9);
+        assert!((m.entry_budget_before - 800.0).abs() < 1e-9);
+        assert!((m.entry_budget_after - 300.0).abs() < 1e-9);
+        assert!(m.orders_before.iter().any(|o| o.reduce_only));
+        let closes = |orders: &[Order]| -> Vec<(f64, f64)> {
+            orders
+                .iter()
+                .filter(|o| o.reduce_only)
+                .map(|o| (o.qty, o.price))
+                .collect()
+        };
+        assert_ne!(closes(&m.orders_before), closes(&m.orders_after));
+        // nothing rests on the exchange yet, so the whole new grid is created
+        assert!(m.to_cancel.is_empty());
+        assert_eq!(m.to_create.len(), m.orders_after.len());
+        assert!(format_plan(&plan).contains("entry budget 800.00 -> 300.00"));
+    }
+}
@@ -0,0 +1,304 @@
+//! Notification messages rendered from templates, so operators can reword them and
+//! translate them for the groups they forward them to.
+//!
+//! Each message has a built-in English template. A language file,
+//! `<live.notifications.templates_dir>/<language>.hjson`, replaces any of them, and
+//! `live.notifications.templates` replaces those again. Templates name the message's
+//! variables in braces, e.g. `[{symbol}] Filled {side} {qty} @ {price}`; an empty
+//! template silences the message. Unknown messages or variables are rejected at startup.
+
+use crate::events::BotEvent;
+use crate::exchange::SendSyncError;
+use crate::types::{NotificationConfig, Order};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+/// Every message with its variables and built-in template; empty ones are not sent unless
+/// given a template.
+const MESSAGES: &[(&str, &[&str], &str)] = &[
+    (
+        "OrderPlaced",
+        &["symbol", "side", "qty", "price", "custom_id"],
+        "",
+    ),
+    (
+        "WouldCancel",
+        &["symbol", "side", "qty", "price", "custom_id"],
+        "[{symbol}] Read-only, would cancel {side} {qty} @ {price} ({custom_id})",
+    ),
+    (
+        "WouldPlace",
+        &["symbol", "side", "qty", "price", "custom_id"],
+        "[{symbol}] Read-only, would place {side} {qty} @ {price} ({custom_id})",
+    ),
+    (
+        "OrderFilled",
+        &["symbol", "side", "qty", "price", "fee"],
+        "[{symbol}] Filled {side} {qty} @ {price} (fee {fee})",
+    ),
+    ("PositionChanged", &["symbol", "size", "price"], ""),
+    ("BalanceUpdated", &["balance"], ""),
+    (
+        "ModeChanged",
+        &["symbol", "long", "short"],
+        "[{symbol}] Mode changed: long {long}, short {short}",
+    ),
+    ("Error", &["symbol", "message"], ""),
+    (
+        "EquitySnapshot",
+        &["balance", "equity", "wallet_exposure", "n_positions"],
+        "",
+    ),
+    (
+        "SafeMode",
+        &["n_restarts"],
+        "Safe mode after {n_restarts} restarts in a day: gracefully stopping all symbols",
+    ),
+];
+
+/// A message to send: its name in `MESSAGES` and the values of its variables.
+pub type Message = (&'static str, Vec<(&'static str, String)>);
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MessageTemplates {
+    templates: HashMap<String, String>,
+}
+
+impl Default for MessageTemplates {
+    /// The built-in English templates.
+    fn default() -> Self {
+        Self {
+            templates: MESSAGES
+                .iter()
+                .map(|(name, _, template)| (name.to_string(), template.to_string()))
+                .collect(),
+        }
+    }
+}
+
+impl MessageTemplates {
+    /// The templates of `config`: the built-in ones, replaced by those of its language
+    /// file and then by its own.
+    pub fn load(config: &NotificationConfig) -> Result<Self, SendSyncError> {
+        let mut templates = Self::default();
+        if !config.language.is_empty() {
+            let path = Path::new(&config.templates_dir).join(format!("{}.hjson", config.language));
+            let content = fs::read_to_string(&path)
+                .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+            let translated: HashMap<String, String> = serde_hjson::from_str(&content)
+                .map_err(|e| format!("Failed to parse {}: {}", path.display(), e))?;
+            templates
+                .extend(translated)
+                .map_err(|e| format!("{}: {}", path.display(), e))?;
+        }
+        templates
+            .extend(config.templates.clone())
+            .map_err(|e| format!("live.notifications.templates: {}", e))?;
+        Ok(templates)
+    }
+
+    fn extend(&mut self, templates: HashMap<String, String>) -> Result<(), String> {
+        for (name, template) in templates {
+            let Some((_, variables, _)) = MESSAGES.iter().find(|(n, _, _)| *n == name) else {
+                return Err(format!("unknown message {}", name));
+            };
+            if let Some(unknown) = placeholders(&template).find(|v| !variables.contains(v)) {
+                return Err(format!(
+                    "unknown variable {{{}}} in {}; it has {}",
+                    unknown,
+                    name,
+                    variables.join(", ")
+                ));
+            }
+            self.templates.insert(name, template);
+        }
+        Ok(())
+    }
+
+    /// The text of `message`, or `None` if its template is empty.
+    pub fn render(&self, message: &Message) -> Option<String> {
+        let (name, values) = message;
+        let template = self.templates.get(*name).filter(|t| !t.is_empty())?;
+        // one pass, so braces in the values are left alone
+        let mut text = String::with_capacity(template.len());
+        let mut rest = template.as_str();
+        while let Some(start) = rest.find('{') {
+            text.push_str(&rest[..start]);
+            let after = &rest[start + 1..];
+            let value = after.find('}').and_then(|end| {
+                let (_, value) = values.iter().find(|(v, _)| *v == &after[..end])?;
+                Some((end, value))
+            });
+            match value {
+                Some((end, value)) => {
+                    text.push_str(value);
+                    rest = &after[end + 1..];
+                }
+                None => {
+                    text.push('{');
+                    rest = after;
+                }
+            }
+        }
+        text.push_str(rest);
+        Some(text)
+    }
+}
+
+/// Names in braces in `template`, such as `symbol` in `[{symbol}]`.
+fn placeholders(template: &str) -> impl Iterator<Item = &str> {
+    template.split('{').skip(1).filter_map(|rest| {
+        let name = &rest[..rest.find('}')?];
+        let is_name =
+            !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+        is_name.then_some(name)
+    })
+}
+
+fn order_variables(symbol: &str, order: &Order) -> Vec<(&'static str, String)> {
+    vec![
+        ("symbol", symbol.to_string()),
+        ("side", order.side.clone()),
+        ("qty", order.qty.to_string()),
+        ("price", order.price.to_string()),
+        ("custom_id", order.custom_id.clone()),
+    ]
+}
+
+/// The messages `event` is reported with.
+pub fn messages(event: &BotEvent) -> Vec<Message> {
+    match event {
+        BotEvent::OrderPlaced { order } => {
+            vec![("OrderPlaced", order_variables(&order.symbol, order))]
+        }
+        BotEvent::OrdersIntended {
+            symbol,
+            to_cancel,
+            to_create,
+        } => to_cancel
+            .iter()
+            .map(|order| ("WouldCancel", order_variables(symbol, order)))
+            .chain(
+                to_create
+                    .iter()
+                    .map(|order| ("WouldPlace", order_variables(symbol, order))),
+            )
+            .collect(),
+        BotEvent::OrderFilled { fill } => vec![(
+            "OrderFilled",
+            vec![
+                ("symbol", fill.symbol.clone()),
+                ("side", fill.side.clone()),
+                ("qty", fill.qty.to_string()),
+                ("price", fill.price.to_string()),
+                ("fee", fill.fee.to_string()),
+            ],
+        )],
+        BotEvent::PositionChanged { symbol, position } => vec![(
+            "PositionChanged",
+            vec![
+                ("symbol", symbol.clone()),
+                ("size", position.size.to_string()),
+                ("price", position.price.to_string()),
+            ],
+        )],
+        BotEvent::BalanceUpdated { balance } => {
+            vec![("BalanceUpdated", vec![("balance", balance.to_string())])]
+        }
+        BotEvent::ModeChanged {
+            symbol,
+            long,
+            short,
+        } => vec![(
+            "ModeChanged",
+            vec![
+                ("symbol", symbol.clone()),
+                ("long", format!("{:?}", long)),
+                ("short", format!("{:?}", short)),
+            ],
+        )],
+        BotEvent::Error { symbol, message } => vec![(
+            "Error",
+            vec![("symbol", symbol.clone()), ("message", message.clone())],
+        )],
+        BotEvent::EquitySnapshot { snapshot } => vec![(
+            "EquitySnapshot",
+            vec![
+                ("balance", snapshot.balance.to_string()),
+                ("equity", snapshot.equity.to_string()),
+                ("wallet_exposure", snapshot.wallet_exposure.to_string()),
+                ("n_positions", snapshot.n_positions.to_string()),
+            ],
+        )],
+        BotEvent::SafeMode { n_restarts } => {
+            vec![("SafeMode", vec![("n_restarts", n_restarts.to_string())])]
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{Position, TradingMode};
+
+    #[test]
+    fn test_templates_are_translated_and_overridden() {
+        let mode_changed = BotEvent::ModeChanged {
+            symbol: "BTCUSDT".to_string(),
+            long: TradingMode::GracefulStop,
+            short: TradingMode::Normal,
+        };
+        let english = MessageTemplates::default();
+        assert_eq!(
+            english.render(&messages(&mode_changed)[0]).unwrap(),
+            "[BTCUSDT] Mode changed: long GracefulStop, short Normal"
+        );
+        // silent unless given a template
+        let position_changed = BotEvent::PositionChanged {
+            symbol: "BTCUSDT".to_string(),
+            position: Position {
+                size: 0.5,
+                price: 60000.0,
+            },
+        };
+        assert_eq!(english.render(&messages(&position_changed)[0]), None);
+
+        let mut config = NotificationConfig {
+            language: "de".to_string(),
+            templates_dir: "templates".to_string(),
+            templates: HashMap::new(),
+        };
+        config.templates.insert(
+            "PositionChanged".to_string(),
+            "{symbol}: {size} @ {price}".to_string(),
+        );
+        let german = MessageTemplates::load(&config).unwrap();
+        assert!(german
+            .render(&messages(&mode_changed)[0])
+            .unwrap()
+            .starts_with("[BTCUSDT] Modus"));
+        assert_eq!(
+            german.render(&messages(&position_changed)[0]).unwrap(),
+            "BTCUSDT: 0.5 @ 60000"
+        );
+
+        // every shipped translation only uses known messages and variables
+        for entry in fs::read_dir("templates").unwrap() {
+            let path = entry.unwrap().path();
+            config.language = path.file_stem().unwrap().to_string_lossy().into_owned();
+            MessageTemplates::load(&config).unwrap();
+        }
+
+        config
+            .templates
+            .insert("OrderFilled".to_string(), "{symbol} {pnl}".to_string());
+        let err = MessageTemplates::load(&config).unwrap_err().to_string();
+        assert!(err.contains("{pnl}"), "{}", err);
+        config.templates.clear();
+        config
+            .templates
+            .insert("Liquidated".to_string(), String::new());
+        assert!(MessageTemplates::load(&config).is_err());
+    }
+}
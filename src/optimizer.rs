@@ -1,9 +1,14 @@
-use crate::backtest;
+use crate::backtest::{self, BacktestArgs};
 use crate::types::{Analysis, BotConfig, BotSideConfig};
+use clap::Parser;
 use rand::prelude::*;
 use rayon::prelude::*;
 use std::cmp::Ordering;
 use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use crate::results::RunDir;
+use crate::universe;
 use std::sync::Arc;
 use tokio::runtime::Runtime;
 use tracing::info;
@@ -15,6 +20,10 @@ use crate::exchange::SendSyncError;
 pub struct Individual {
     pub variables: Vec<f64>,
     pub fitness: Vec<f64>,
+    /// Total relative violation of `optimizer.limits`; 0 for feasible individuals.
+    pub constraint_violation: f64,
+    /// Analysis of the individual's backtest; None until evaluated or if it failed.
+    pub analysis: Option<Analysis>,
     pub rank: i32,
     pub crowding_distance: f64,
 }
@@ -24,12 +33,20 @@ impl Individual {
         Self {
             variables,
             fitness: Vec::new(),
+            constraint_violation: 0.0,
+            analysis: None,
             rank: i32::MAX,
             crowding_distance: 0.0,
         }
     }
 
+    /// Constrained dominance: a feasible individual dominates every infeasible one, an
+    /// infeasible one those violating the limits more, and otherwise Pareto dominance
+    /// applies.
     fn dominates(&self, other: &Self) -> bool {
+        if self.constraint_violation != other.constraint_violation {
+            return self.constraint_violation < other.constraint_violation;
+        }
         let mut self_is_better = false;
         for i in 0..self.fitness.len() {
             if self.fitness[i] > other.fitness[i] {
@@ -47,7 +64,7 @@ impl Individual {
 
 fn evaluate_population(
     population: &mut [Individual], base_config: &BotConfig, param_keys: &[String],
-    tokio_runtime: &Arc<Runtime>, n_objectives: usize,
+    tokio_runtime: &Arc<Runtime>, objectives: &[Objective], limits: &[Limit],
 ) {
     let rt = tokio_runtime.clone();
     population.par_iter_mut().for_each(|ind| {
@@ -55,11 +72,15 @@ fn evaluate_population(
         let backtest_result = rt.block_on(backtest::run_single(&config));
         match backtest_result {
             Ok(result) => {
-                ind.fitness = calculate_fitness(&result.analysis);
+                ind.fitness = calculate_fitness(&result.analysis, objectives);
+                ind.constraint_violation = constraint_violation(&result.analysis, limits);
+                ind.analysis = Some(result.analysis);
             }
             Err(e) => {
                 eprintln!("Backtest failed for individual. Error: {}", e);
-                ind.fitness = vec![f64::MAX; n_objectives];
+                ind.fitness = vec![f64::MAX; objectives.len()];
+                ind.constraint_violation = f64::INFINITY;
+                ind.analysis = None;
             }
         }
     });
@@ -113,7 +134,32 @@ fn fast_non_dominated_sort(population: &mut [Individual]) -> Vec<Vec<Individual>
     fronts
 }
 
-fn crowding_distance_assignment(front: &mut [Individual], n_objectives: usize) {
+/// Keys and bounds of the optimized parameters, as "<side>.<name>". Disabled sides are
+/// not simulated, so their parameters are left out.
+fn search_space(config: &BotConfig) -> (Vec<String>, Vec<(f64, f64)>) {
+    let mut param_keys = Vec::new();
+    let mut param_bounds = Vec::new();
+    for (side, enabled, ranges) in [
+        ("long", config.bot.long.enabled, &config.optimizer.long),
+        ("short", config.bot.short.enabled, &config.optimizer.short),
+    ] {
+        if !enabled {
+            continue;
+        }
+        for (key, range) in ranges.iter() {
+            param_keys.push(format!("{}.{}", side, key));
+            param_bounds.push((range.start, range.end));
+        }
+    }
+    (param_keys, param_bounds)
+}
+
+/// Assigns crowding distances in objective space and, weighted by `diversity_weight`,
+/// in decision space (variables normalized by their bounds). Sorts the front by
+/// descending crowding distance.
+fn crowding_distance_assignment(
+    front: &mut [Individual], n_objectives: usize, bounds: &[(f64, f64)], diversity_weight: f64,
+) {
     if front.is_empty() {
         return;
     }
@@ -147,6 +193,26 @@ fn crowding_distance_assignment(front: &mut [Individual], n_objectives: usize) {
             }
         }
     }
+
+    if diversity_weight > 0.0 && len > 2 {
+        for (i, (low, high)) in bounds.iter().enumerate() {
+            let range = high - low;
+            if range.abs() <= 1e-9 {
+                continue;
+            }
+            front.sort_by(|a, b| {
+                a.variables[i]
+                    .partial_cmp(&b.variables[i])
+                    .unwrap_or(Ordering::Equal)
+            });
+            for j in 1..(len - 1) {
+                front[j].crowding_distance += diversity_weight
+                    * (front[j + 1].variables[i] - front[j - 1].variables[i])
+                    / range;
+            }
+        }
+    }
+
     front.sort_by(|a, b| {
         b.crowding_distance
             .partial_cmp(&a.crowding_distance)
@@ -154,6 +220,35 @@ fn crowding_distance_assignment(front: &mut [Individual], n_objectives: usize) {
     });
 }
 
+/// Mean and minimum standard deviation of each variable across the population,
+/// normalized by the variable's bounds.
+fn decision_space_spread(population: &[Individual], bounds: &[(f64, f64)]) -> (f64, f64) {
+    if population.len() < 2 || bounds.is_empty() {
+        return (0.0, 0.0);
+    }
+    let n = population.len() as f64;
+    let spreads: Vec<f64> = bounds
+        .iter()
+        .enumerate()
+        .map(|(i, (low, high))| {
+            let range = high - low;
+            if range.abs() <= 1e-9 {
+                return 0.0;
+            }
+            let mean = population.iter().map(|ind| ind.variables[i]).sum::<f64>() / n;
+            let variance = population
+                .iter()
+                .map(|ind| (ind.variables[i] - mean).powi(2))
+                .sum::<f64>()
+                / n;
+            variance.sqrt() / range
+        })
+        .collect();
+    let mean_spread = spreads.iter().sum::<f64>() / spreads.len() as f64;
+    let min_spread = spreads.iter().copied().fold(f64::INFINITY, f64::min);
+    (mean_spread, min_spread)
+}
+
 fn tournament_selection<'a>(population: &'a [Individual], rng: &mut impl Rng) -> &'a Individual {
     let i1 = rng.gen_range(0..population.len());
     let i2 = rng.gen_range(0..population.len());
@@ -228,8 +323,232 @@ fn polynomial_mutation(
     }
 }
 
-fn calculate_fitness(analysis: &Analysis) -> Vec<f64> {
-    vec![-analysis.sharpe_ratio, analysis.drawdown_worst]
+/// Mutation probability for a generation: constant, or with `adaptive` decaying linearly
+/// from `base` in the first generation to a tenth of it in the last.
+fn calc_mutation_probability(
+    base: f64, generation_idx: usize, n_generations: usize, adaptive: bool,
+) -> f64 {
+    if !adaptive || n_generations <= 1 {
+        return base;
+    }
+    let progress = generation_idx as f64 / (n_generations - 1) as f64;
+    base * (1.0 - 0.9 * progress.min(1.0))
+}
+
+/// Computes the hypervolume dominated by `points` (all objectives minimized) and bounded
+/// by `reference`, by slicing along the last objective and recursing on the rest.
+fn hypervolume(points: &[Vec<f64>], reference: &[f64]) -> f64 {
+    let n_dims = reference.len();
+    let mut points: Vec<&Vec<f64>> = points
+        .iter()
+        .filter(|p| p.iter().zip(reference).all(|(v, r)| v < r))
+        .collect();
+    if points.is_empty() || n_dims == 0 {
+        return 0.0;
+    }
+    if n_dims == 1 {
+        let best = points.iter().map(|p| p[0]).fold(f64::INFINITY, f64::min);
+        return reference[0] - best;
+    }
+
+    let last = n_dims - 1;
+    points.sort_by(|a, b| a[last].partial_cmp(&b[last]).unwrap_or(Ordering::Equal));
+    let mut volume = 0.0;
+    for i in 0..points.len() {
+        let upper = if i + 1 < points.len() {
+            points[i + 1][last]
+        } else {
+            reference[last]
+        };
+        let depth = upper - points[i][last];
+        if depth <= 0.0 {
+            continue;
+        }
+        let projected: Vec<Vec<f64>> = points[..=i].iter().map(|p| p[..last].to_vec()).collect();
+        volume += hypervolume(&projected, &reference[..last]) * depth;
+    }
+    volume
+}
+
+/// Reference point for hypervolume: slightly worse than the worst finite value of each objective.
+fn hypervolume_reference(population: &[Individual], n_objectives: usize) -> Vec<f64> {
+    (0..n_objectives)
+        .map(|i| {
+            let worst = population
+                .iter()
+                .map(|ind| ind.fitness[i])
+                .filter(|v| *v < f64::MAX)
+                .fold(f64::NEG_INFINITY, f64::max);
+            if worst.is_finite() {
+                worst + worst.abs() * 0.1 + 1e-9
+            } else {
+                0.0
+            }
+        })
+        .collect()
+}
+
+/// Objectives when `optimizer.scoring` is empty.
+pub const DEFAULT_SCORING: [&str; 2] = ["sharpe_ratio", "drawdown_worst"];
+
+/// One `Analysis` metric the optimizer minimizes, entered in `optimizer.scoring` as its
+/// name, optionally followed by `:weight`, e.g. "adg:2".
+#[derive(Debug, Clone)]
+pub struct Objective {
+    pub name: String,
+    /// -1 for metrics to maximize, which are negated, 1 for those to minimize.
+    pub sign: f64,
+    pub weight: f64,
+    metric: fn(&Analysis) -> f64,
+}
+
+impl Objective {
+    pub fn parse(entry: &str) -> Option<Self> {
+        let (name, weight) = match entry.split_once(':') {
+            Some((name, weight)) => (name.trim(), weight.trim().parse().ok()?),
+            None => (entry.trim(), 1.0),
+        };
+        if !(weight > 0.0 && f64::is_finite(weight)) {
+            return None;
+        }
+        let (sign, metric): (f64, fn(&Analysis) -> f64) = match name {
+            "adg" => (-1.0, |a| a.adg),
+            "mdg" => (-1.0, |a| a.mdg),
+            "sharpe_ratio" => (-1.0, |a| a.sharpe_ratio),
+            "sortino_ratio" => (-1.0, |a| a.sortino_ratio),
+            "omega_ratio" => (-1.0, |a| a.omega_ratio),
+            "calmar_ratio" => (-1.0, |a| a.calmar_ratio),
+            "sterling_ratio" => (-1.0, |a| a.sterling_ratio),
+            "expected_shortfall_1pct" => (1.0, |a| a.expected_shortfall_1pct),
+            "drawdown_worst" => (1.0, |a| a.drawdown_worst),
+            "drawdown_worst_mean_1pct" => (1.0, |a| a.drawdown_worst_mean_1pct),
+            "equity_balance_diff_mean" => (1.0, |a| a.equity_balance_diff_mean),
+            "equity_balance_diff_max" => (1.0, |a| a.equity_balance_diff_max),
+            "loss_profit_ratio" => (1.0, |a| a.loss_profit_ratio),
+            _ => return None,
+        };
+        Some(Self {
+            name: name.to_string(),
+            sign,
+            weight,
+            metric,
+        })
+    }
+
+    pub fn fitness(&self, analysis: &Analysis) -> f64 {
+        self.sign * self.weight * (self.metric)(analysis)
+    }
+
+    /// The metric's value behind a fitness.
+    pub fn metric_value(&self, fitness: f64) -> f64 {
+        fitness / (self.sign * self.weight)
+    }
+}
+
+/// The objectives of `scoring`, or of `DEFAULT_SCORING` if it is empty.
+pub fn parse_scoring(scoring: &[String]) -> Result<Vec<Objective>, String> {
+    let entries: Vec<&str> = if scoring.is_empty() {
+        DEFAULT_SCORING.to_vec()
+    } else {
+        scoring.iter().map(String::as_str).collect()
+    };
+    if !(2..=4).contains(&entries.len()) {
+        return Err(format!(
+            "optimizer.scoring must list 2 to 4 objectives, got {}",
+            entries.len()
+        ));
+    }
+    let objectives = entries
+        .iter()
+        .map(|entry| {
+            Objective::parse(entry).ok_or_else(|| {
+                format!(
+                    "Unknown optimizer.scoring objective {:?}, expected a metric of the \
+                    backtest analysis such as \"adg\" or \"drawdown_worst\", optionally \
+                    followed by a positive \":weight\"",
+                    entry
+                )
+            })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    for (i, objective) in objectives.iter().enumerate() {
+        if objectives[..i].iter().any(|o| o.name == objective.name) {
+            return Err(format!(
+                "optimizer.scoring lists {} more than once",
+                objective.name
+            ));
+        }
+    }
+    Ok(objectives)
+}
+
+/// A bound on a metric from `optimizer.limits`: an upper bound on metrics the optimizer
+/// minimizes, e.g. `drawdown_worst: 0.5`, and a lower bound on those it maximizes, e.g.
+/// `adg: 0.0005`.
+#[derive(Debug, Clone)]
+pub struct Limit {
+    pub objective: Objective,
+    pub bound: f64,
+}
+
+impl Limit {
+    /// How far `analysis` is past the bound, relative to it; 0 within it.
+    pub fn violation(&self, analysis: &Analysis) -> f64 {
+        let excess = self.objective.sign * ((self.objective.metric)(analysis) - self.bound);
+        if excess <= 0.0 {
+            0.0
+        } else if self.bound != 0.0 {
+            excess / self.bound.abs()
+        } else {
+            excess
+        }
+    }
+}
+
+pub fn parse_limits(limits: &HashMap<String, f64>) -> Result<Vec<Limit>, String> {
+    let mut parsed: Vec<Limit> = limits
+        .iter()
+        .map(|(name, &bound)| {
+            let objective = Objective::parse(name)
+                .filter(|_| !name.contains(':'))
+                .ok_or_else(|| {
+                    format!(
+                        "Unknown optimizer.limits metric {:?}, expected a metric of the \
+                        backtest analysis such as \"drawdown_worst\"",
+                        name
+                    )
+                })?;
+            Ok(Limit { objective, bound })
+        })
+        .collect::<Result<_, String>>()?;
+    parsed.sort_by(|a, b| a.objective.name.cmp(&b.objective.name));
+    Ok(parsed)
+}
+
+fn constraint_violation(analysis: &Analysis, limits: &[Limit]) -> f64 {
+    limits.iter().map(|limit| limit.violation(analysis)).sum()
+}
+
+fn calculate_fitness(analysis: &Analysis, objectives: &[Objective]) -> Vec<f64> {
+    objectives
+        .iter()
+        .map(|objective| objective.fitness(analysis))
+        .collect()
+}
+
+fn format_fitness(fitness: &[f64], objectives: &[Objective]) -> String {
+    objectives
+        .iter()
+        .zip(fitness)
+        .map(|(objective, &fitness)| {
+            format!(
+                "{} = {:.4}",
+                objective.name,
+                objective.metric_value(fitness)
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
 }
 
 fn individual_to_config(
@@ -262,23 +581,30 @@ fn individual_to_config(
             };
         }
         set_param!(total_wallet_exposure_limit);
+        set_param!(close_wallet_exposure_limit);
         set_param!(n_positions);
         set_param!(unstuck_loss_allowance_pct);
         set_param!(unstuck_close_pct);
         set_param!(unstuck_ema_dist);
         set_param!(unstuck_threshold);
+        set_param!(max_position_age_days);
         set_param!(filter_rolling_window);
         set_param!(filter_relative_volume_clip_pct);
         set_param!(ema_span_0);
         set_param!(ema_span_1);
         set_param!(entry_initial_qty_pct);
         set_param!(entry_initial_ema_dist);
+        set_param!(entry_initial_n_splits);
+        set_param!(entry_initial_split_band_pct);
         set_param!(entry_grid_spacing_pct);
         set_param!(entry_grid_spacing_weight);
+        set_param!(entry_grid_spacing_exponent);
         set_param!(entry_grid_double_down_factor);
+        set_param!(entry_grid_double_down_exponent);
         set_param!(entry_trailing_threshold_pct);
         set_param!(entry_trailing_retracement_pct);
         set_param!(entry_trailing_grid_ratio);
+        set_param!(entry_trailing_atr_span);
         set_param!(close_grid_min_markup);
         set_param!(close_grid_markup_range);
         set_param!(close_grid_qty_pct);
@@ -293,54 +619,196 @@ fn individual_to_config(
     config
 }
 
+/// Loads the parameters of the Pareto front solutions from a run directory's `results.json`.
+fn load_pareto_front(run_dir: &Path) -> Result<Vec<HashMap<String, f64>>, SendSyncError> {
+    let path = run_dir.join("results.json");
+    let content = fs::read_to_string(&path)
+        .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    let results: serde_json::Value = serde_json::from_str(&content)?;
+    let solutions = results["pareto_front"]
+        .as_array()
+        .ok_or_else(|| format!("{} has no pareto_front", path.display()))?;
+    Ok(solutions
+        .iter()
+        .filter_map(|solution| serde_json::from_value(solution["params"].clone()).ok())
+        .collect())
+}
+
+/// Value below which `fraction` of the sorted `values` fall, interpolating linearly.
+fn percentile(sorted: &[f64], fraction: f64) -> f64 {
+    let position = fraction * (sorted.len() - 1) as f64;
+    let (below, above) = (position.floor() as usize, position.ceil() as usize);
+    sorted[below] + (sorted[above] - sorted[below]) * (position - below as f64)
+}
+
+/// Narrows each parameter's bounds to the 5th-95th percentile of its values on a previous
+/// Pareto front, widened by `margin` times the original width and kept within the original
+/// bounds. Parameters the front does not cover keep their bounds.
+fn tighten_bounds(
+    param_keys: &[String], bounds: &[(f64, f64)], front: &[HashMap<String, f64>], margin: f64,
+) -> Vec<(f64, f64)> {
+    param_keys
+        .iter()
+        .zip(bounds)
+        .map(|(key, &(low, high))| {
+            let mut values: Vec<f64> = front
+                .iter()
+                .filter_map(|params| params.get(key))
+                .copied()
+                .collect();
+            if values.is_empty() {
+                return (low, high);
+            }
+            values.sort_by(f64::total_cmp);
+            let padding = margin * (high - low);
+            (
+                (percentile(&values, 0.05) - padding).max(low),
+                (percentile(&values, 0.95) + padding).min(high),
+            )
+        })
+        .collect()
+}
+
+#[derive(Parser, Debug, Clone)]
+pub struct OptimizeArgs {
+    #[clap(flatten)]
+    pub backtest: BacktestArgs,
+
+    /// Results directory of a completed run to refine: bounds are tightened around its
+    /// Pareto front, which seeds a smaller population
+    #[clap(long)]
+    pub refine: Option<PathBuf>,
+}
+
 // --- Main Optimizer Struct to be called from outside ---
 pub struct Optimizer {
     pub config: BotConfig,
+    /// Run directory whose Pareto front a refine run starts from.
+    pub refine_from: Option<PathBuf>,
 }
 
 impl Optimizer {
     pub fn new(config: BotConfig) -> Self {
-        Optimizer { config }
+        Optimizer {
+            config,
+            refine_from: None,
+        }
+    }
+
+    pub fn with_refine_from(mut self, run_dir: PathBuf) -> Self {
+        self.refine_from = Some(run_dir);
+        self
     }
 
     pub async fn start(&mut self) -> Result<(), SendSyncError> {
         info!("Starting custom NSGA-II optimizer...");
-
-        let mut param_keys = Vec::new();
-        let mut param_bounds = Vec::new();
+        universe::resolve_backtest_symbols(
+            &mut self.config.backtest,
+            self.config.live.minimum_coin_age_days,
+        )?;
+        let run_dir = RunDir::create("optimize", &self.config)?;
 
         let optimizer_config = &self.config.optimizer;
-        for (key, range) in optimizer_config.long.iter() {
-            param_keys.push(format!("long.{}", key));
-            param_bounds.push((range.start, range.end));
-        }
-        for (key, range) in optimizer_config.short.iter() {
-            param_keys.push(format!("short.{}", key));
-            param_bounds.push((range.start, range.end));
-        }
+        optimizer_config.validate()?;
+        let (param_keys, mut param_bounds) = search_space(&self.config);
 
-        let population_size = optimizer_config.population_size as usize;
+        let mut population_size = optimizer_config.population_size as usize;
+        let mut seeds = Vec::new();
+        if let Some(refine_from) = &self.refine_from {
+            let front = load_pareto_front(refine_from)?;
+            if front.is_empty() {
+                return Err(
+                    format!("No Pareto front solutions in {}", refine_from.display()).into(),
+                );
+            }
+            param_bounds = tighten_bounds(
+                &param_keys,
+                &param_bounds,
+                &front,
+                optimizer_config.refine_margin,
+            );
+            population_size = ((population_size as f64 * optimizer_config.refine_population_scale)
+                .round() as usize)
+                .max(2);
+            for (key, (low, high)) in param_keys.iter().zip(&param_bounds) {
+                info!("Refined bounds of {}: [{}, {}]", key, low, high);
+            }
+            info!(
+                "Refining {} with a population of {}, seeded with its {} Pareto front solutions",
+                refine_from.display(),
+                population_size,
+                front.len()
+            );
+            seeds = front
+                .iter()
+                .map(|params| {
+                    let variables = param_keys
+                        .iter()
+                        .zip(&param_bounds)
+                        .map(|(key, &(low, high))| {
+                            params
+                                .get(key)
+                                .map_or((low + high) / 2.0, |v| v.clamp(low, high))
+                        })
+                        .collect();
+                    Individual::new(variables)
+                })
+                .collect();
+            seeds.truncate(population_size);
+        }
         let n_generations = optimizer_config.n_generations as usize;
         let n_variables = param_bounds.len();
-        let mutation_prob = 1.0 / n_variables as f64;
-        let crossover_prob = 0.9;
+        let base_mutation_prob = if optimizer_config.mutation_probability > 0.0 {
+            optimizer_config.mutation_probability
+        } else {
+            1.0 / n_variables as f64
+        };
+        let crossover_prob = optimizer_config.crossover_probability;
         let eta_mutation = 20.0;
         let eta_crossover = 20.0;
-        let n_objectives = 2;
+        let objectives = parse_scoring(&optimizer_config.scoring)?;
+        let limits = parse_limits(&optimizer_config.limits)?;
+        for limit in &limits {
+            info!(
+                "Limit: {} {} {}",
+                limit.objective.name,
+                if limit.objective.sign > 0.0 {
+                    "<="
+                } else {
+                    ">="
+                },
+                limit.bound
+            );
+        }
+        let n_objectives = objectives.len();
+        info!(
+            "Objectives: {}",
+            objectives
+                .iter()
+                .map(|o| o.name.as_str())
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
 
         let tokio_runtime = Arc::new(Runtime::new().map_err(|e| Box::new(e) as SendSyncError)?);
-        let mut rng = thread_rng();
-
-        // 1. Initialize Population
-        let mut population: Vec<Individual> = (0..population_size)
-            .map(|_| {
-                let variables = param_bounds
-                    .iter()
-                    .map(|(low, high)| rng.gen_range(*low..=*high))
-                    .collect();
-                Individual::new(variables)
-            })
-            .collect();
+        let seed = if optimizer_config.seed == 0 {
+            thread_rng().gen_range(1..=u64::MAX)
+        } else {
+            optimizer_config.seed
+        };
+        info!("Seed: {}", seed);
+        let mut rng = StdRng::seed_from_u64(seed);
+
+        // 1. Initialize Population, after the seeds of a refine run
+        let n_random = population_size - seeds.len();
+        let mut population: Vec<Individual> = seeds;
+        population.extend((0..n_random).map(|_| {
+            let variables = param_bounds
+                .iter()
+                .map(|(low, high)| rng.gen_range(*low..=*high))
+                .collect();
+            Individual::new(variables)
+        }));
 
         // 2. Evaluate initial population
         evaluate_population(
@@ -348,12 +816,29 @@ impl Optimizer {
             &self.config,
             &param_keys,
             &tokio_runtime,
-            n_objectives,
+            &objectives,
+            &limits,
         );
 
+        let hv_reference = hypervolume_reference(&population, n_objectives);
+        let early_stopping_generations =
+            optimizer_config.early_stopping_generations.max(0) as usize;
+        let early_stopping_epsilon = optimizer_config.early_stopping_epsilon;
+        let mut hypervolume_history = Vec::new();
+        let diversity_weight = optimizer_config.diversity_weight;
+        let mut spread_history = Vec::new();
+        let mut best_hypervolume = f64::NEG_INFINITY;
+        let mut n_stale_generations = 0;
+
         // 3. Main generational loop
         for generation_idx in 0..n_generations {
             info!("Running generation {}...", generation_idx + 1);
+            let mutation_prob = calc_mutation_probability(
+                base_mutation_prob,
+                generation_idx,
+                n_generations,
+                optimizer_config.adaptive_mutation,
+            );
 
             // 4. Create offspring
             let mut offspring = Vec::with_capacity(population_size);
@@ -396,7 +881,8 @@ impl Optimizer {
                 &self.config,
                 &param_keys,
                 &tokio_runtime,
-                n_objectives,
+                &objectives,
+                &limits,
             );
 
             // 6. Combine and select next generation
@@ -408,7 +894,12 @@ impl Optimizer {
             let mut next_pop = Vec::new();
             for front in fronts.iter_mut() {
                 if next_pop.len() + front.len() > population_size {
-                    crowding_distance_assignment(front, n_objectives);
+                    crowding_distance_assignment(
+                        front,
+                        n_objectives,
+                        &param_bounds,
+                        diversity_weight,
+                    );
                     let remaining = population_size - next_pop.len();
                     next_pop.extend_from_slice(&front[0..remaining]);
                     break;
@@ -418,13 +909,51 @@ impl Optimizer {
             population = next_pop;
 
             if let Some(best_ind) = population.get(0) {
-                let best_fitness = best_ind.fitness.clone();
                 info!(
-                    "Generation {} Best Fitness (Negated Sharpe, Drawdown): {:?}",
+                    "Generation {} best fitness: {}",
                     generation_idx + 1,
-                    best_fitness
+                    format_fitness(&best_ind.fitness, &objectives)
                 );
             }
+
+            let front_fitness: Vec<Vec<f64>> = population
+                .iter()
+                .filter(|ind| ind.rank == 1)
+                .map(|ind| ind.fitness.clone())
+                .collect();
+            let generation_hypervolume = hypervolume(&front_fitness, &hv_reference);
+            hypervolume_history.push(generation_hypervolume);
+
+            let (mean_spread, min_spread) = decision_space_spread(&population, &param_bounds);
+            spread_history.push(serde_json::json!({
+                "mean": mean_spread,
+                "min": min_spread,
+            }));
+            info!(
+                "Generation {} decision space spread: mean {:.4}, min {:.4}",
+                generation_idx + 1,
+                mean_spread,
+                min_spread
+            );
+            info!(
+                "Generation {} Pareto front hypervolume: {:.6}",
+                generation_idx + 1,
+                generation_hypervolume
+            );
+
+            if generation_hypervolume > best_hypervolume + early_stopping_epsilon {
+                best_hypervolume = generation_hypervolume;
+                n_stale_generations = 0;
+            } else {
+                n_stale_generations += 1;
+            }
+            if early_stopping_generations > 0 && n_stale_generations >= early_stopping_generations {
+                info!(
+                    "Hypervolume has not improved by more than {} for {} generations, stopping early.",
+                    early_stopping_epsilon, n_stale_generations
+                );
+                break;
+            }
         }
 
         // 7. Get final Pareto front
@@ -439,14 +968,34 @@ impl Optimizer {
             pareto_front.len()
         );
 
+        write_results(
+            &run_dir,
+            &pareto_front,
+            &param_keys,
+            &param_bounds,
+            &objectives,
+            &hypervolume_history,
+            &spread_history,
+        )?;
+        write_pareto(
+            &run_dir,
+            &pareto_front,
+            &self.config,
+            &param_keys,
+            &objectives,
+            seed,
+        )?;
+
         for (i, individual) in pareto_front.iter().enumerate() {
-            let sharpe = -individual.fitness[0]; // Negate back
-            let drawdown = individual.fitness[1];
             info!(
-                "Solution {}: Sharpe Ratio = {:.4}, Worst Drawdown = {:.4}%",
+                "Solution {}: {}{}",
                 i + 1,
-                sharpe,
-                drawdown * 100.0
+                format_fitness(&individual.fitness, &objectives),
+                if individual.constraint_violation > 0.0 {
+                    " (violates optimizer.limits)"
+                } else {
+                    ""
+                }
             );
             if i < 5 {
                 // Print top 5 configs
@@ -457,3 +1006,389 @@ impl Optimizer {
         Ok(())
     }
 }
+
+fn write_results(
+    run_dir: &RunDir, pareto_front: &[Individual], param_keys: &[String],
+    param_bounds: &[(f64, f64)], objectives: &[Objective], hypervolume_history: &[f64],
+    spread_history: &[serde_json::Value],
+) -> Result<(), SendSyncError> {
+    let bounds: HashMap<&String, [f64; 2]> = param_keys
+        .iter()
+        .zip(param_bounds)
+        .map(|(key, &(low, high))| (key, [low, high]))
+        .collect();
+    let solutions: Vec<serde_json::Value> = pareto_front
+        .iter()
+        .map(|individual| {
+            let params: HashMap<&String, f64> = param_keys
+                .iter()
+                .zip(individual.variables.iter().copied())
+                .collect();
+            serde_json::json!({
+                "fitness": individual.fitness,
+                "constraint_violation": individual.constraint_violation,
+                "params": params,
+            })
+        })
+        .collect();
+    let objective_names: Vec<&str> = objectives.iter().map(|o| o.name.as_str()).collect();
+    let results = serde_json::json!({
+        "objectives": objective_names,
+        "param_bounds": bounds,
+        "hypervolume_history": hypervolume_history,
+        "decision_space_spread_history": spread_history,
+        "pareto_front": solutions,
+    });
+
+    run_dir.write_json("results.json", &results)?;
+    info!(
+        "Results written to {}",
+        run_dir.path.join("results.json").display()
+    );
+    Ok(())
+}
+
+/// Writes the Pareto front as `pareto.json`, with each solution's params and full backtest
+/// analysis, and each solution's complete config as `solutions/solution_NNN.hjson`, ready
+/// to pass to a backtest or live run.
+fn write_pareto(
+    run_dir: &RunDir, pareto_front: &[Individual], base_config: &BotConfig, param_keys: &[String],
+    objectives: &[Objective], seed: u64,
+) -> Result<(), SendSyncError> {
+    fs::create_dir_all(run_dir.path.join("solutions"))?;
+    let mut solutions = Vec::new();
+    for (i, individual) in pareto_front.iter().enumerate() {
+        let config_file = format!("solutions/solution_{:03}.hjson", i + 1);
+        // JSON is valid hjson, so the configs load as they are
+        run_dir.write_json(
+            &config_file,
+            &individual_to_config(individual, base_config, param_keys),
+        )?;
+        let params: HashMap<&String, f64> = param_keys
+            .iter()
+            .zip(individual.variables.iter().copied())
+            .collect();
+        solutions.push(serde_json::json!({
+            "id": i + 1,
+            "config": config_file,
+            "fitness": individual.fitness,
+            "constraint_violation": individual.constraint_violation,
+            "params": params,
+            "analysis": individual.analysis,
+        }));
+    }
+    let objective_names: Vec<&str> = objectives.iter().map(|o| o.name.as_str()).collect();
+    run_dir.write_json(
+        "pareto.json",
+        &serde_json::json!({
+            "seed": seed,
+            "objectives": objective_names,
+            "solutions": solutions,
+        }),
+    )?;
+    info!(
+        "Pareto front written to {}, solution configs to {}",
+        run_dir.path.join("pareto.json").display(),
+        run_dir.path.join("solutions").display()
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hypervolume_two_objectives() {
+        let points = vec![vec![1.0, 3.0], vec![2.0, 2.0], vec![3.0, 1.0]];
+        let reference = vec![4.0, 4.0];
+        // Union of rectangles [1,4]x[3,4], [2,4]x[2,4], [3,4]x[1,4] = 3 + 2 + 1
+        assert_eq!(hypervolume(&points, &reference), 6.0);
+    }
+
+    #[test]
+    fn test_hypervolume_ignores_points_outside_reference() {
+        let points = vec![vec![1.0, 1.0, 1.0], vec![5.0, 0.0, 0.0]];
+        let reference = vec![2.0, 2.0, 2.0];
+        assert_eq!(hypervolume(&points, &reference), 1.0);
+    }
+
+    #[test]
+    fn test_tighten_bounds_around_pareto_front() {
+        let keys = vec!["long.a".to_string(), "long.b".to_string()];
+        let bounds = vec![(0.0, 10.0), (0.0, 1.0)];
+        let front: Vec<HashMap<String, f64>> = (0..=20)
+            .map(|i| HashMap::from([("long.a".to_string(), 4.0 + i as f64 * 0.1)]))
+            .collect();
+        let tightened = tighten_bounds(&keys, &bounds, &front, 0.05);
+        // 5th-95th percentile of 4.0..=6.0 is 4.1..=5.9, padded by 0.5
+        assert!((tightened[0].0 - 3.6).abs() < 1e-9);
+        assert!((tightened[0].1 - 6.4).abs() < 1e-9);
+        // not on the front
+        assert_eq!(tightened[1], (0.0, 1.0));
+        // padding stays within the original bounds
+        assert_eq!(tighten_bounds(&keys, &bounds, &front, 1.0)[0], (0.0, 10.0));
+    }
+
+    #[test]
+    fn test_crowding_distance_adds_weighted_decision_space_distance() {
+        let individual = |variables: Vec<f64>, objective: f64| Individual {
+            fitness: vec![objective],
+            ..Individual::new(variables)
+        };
+        // the second variable's bound has no width, so its values must not count
+        let bounds = [(0.0, 10.0), (5.0, 5.0)];
+        // crowding distances by first variable, once checked to be sorted descending
+        let distances = |front: &[Individual]| -> Vec<(f64, f64)> {
+            assert!(front
+                .windows(2)
+                .all(|pair| pair[0].crowding_distance >= pair[1].crowding_distance));
+            let mut distances: Vec<(f64, f64)> = front
+                .iter()
+                .map(|ind| (ind.variables[0], ind.crowding_distance))
+                .collect();
+            distances.sort_by(|a, b| a.0.total_cmp(&b.0));
+            distances
+        };
+
+        // interior in objective space only: the objective boundaries at 4 and 6 are kept
+        // at an infinite distance, the individuals at 10 and 0 get (3 - 0) / 4 and
+        // (4 - 1) / 4
+        let front = || {
+            vec![
+                individual(vec![4.0, 1.0], 0.0),
+                individual(vec![10.0, 2.0], 1.0),
+                individual(vec![0.0, 3.0], 3.0),
+                individual(vec![6.0, 4.0], 4.0),
+            ]
+        };
+        let expected = [
+            (0.0, 0.75),
+            (4.0, f64::INFINITY),
+            (6.0, f64::INFINITY),
+            (10.0, 0.75),
+        ];
+        let mut objective_only = front();
+        crowding_distance_assignment(&mut objective_only, 1, &bounds, 0.0);
+        assert_eq!(distances(&objective_only), expected);
+        // 0 and 10 are also the boundaries in decision space, which add nothing to them
+        let mut weighted = front();
+        crowding_distance_assignment(&mut weighted, 1, &bounds, 0.5);
+        assert_eq!(distances(&weighted), expected);
+
+        // interior in both spaces: 0.75 + 0.5 * (6 - 0) / 10 and 0.75 + 0.5 * (10 - 4) / 10
+        let mut interior = vec![
+            individual(vec![0.0, 1.0], 0.0),
+            individual(vec![4.0, 2.0], 1.0),
+            individual(vec![6.0, 3.0], 3.0),
+            individual(vec![10.0, 4.0], 4.0),
+        ];
+        crowding_distance_assignment(&mut interior, 1, &bounds, 0.5);
+        let interior = distances(&interior);
+        assert_eq!(
+            (interior[0].1, interior[3].1),
+            (f64::INFINITY, f64::INFINITY)
+        );
+        assert!((interior[1].1 - 1.05).abs() < 1e-12);
+        assert!((interior[2].1 - 1.05).abs() < 1e-12);
+
+        // too few individuals to have an interior
+        let mut pair = vec![
+            individual(vec![0.0, 0.0], 0.0),
+            individual(vec![10.0, 0.0], 1.0),
+        ];
+        crowding_distance_assignment(&mut pair, 1, &bounds, 0.5);
+        assert!(pair
+            .iter()
+            .all(|ind| ind.crowding_distance == f64::INFINITY));
+    }
+
+    #[test]
+    fn test_decision_space_spread_normalizes_by_bounds() {
+        let population: Vec<Individual> = [
+            [0.0, 1.0, 7.0],
+            [0.0, 1.0, 8.0],
+            [10.0, 3.0, 9.0],
+            [10.0, 3.0, 7.0],
+        ]
+        .iter()
+        .map(|variables| Individual::new(variables.to_vec()))
+        .collect();
+        // standard deviations 5 of a width of 10 and 1 of a width of 4
+        assert_eq!(
+            decision_space_spread(&population, &[(0.0, 10.0), (0.0, 4.0)]),
+            (0.375, 0.25)
+        );
+        // a bound without width has no spread, whatever the values
+        assert_eq!(
+            decision_space_spread(&population, &[(0.0, 10.0), (0.0, 4.0), (2.0, 2.0)]),
+            (0.25, 0.0)
+        );
+        assert_eq!(
+            decision_space_spread(&population[..1], &[(0.0, 10.0)]),
+            (0.0, 0.0)
+        );
+        assert_eq!(decision_space_spread(&population, &[]), (0.0, 0.0));
+    }
+
+    #[test]
+    fn test_search_space_leaves_out_disabled_sides() {
+        let mut config =
+            crate::config::load_config("tests/fixtures/backtest_config.hjson").unwrap();
+        let range = |start: f64, end: f64| crate::types::OptimizeInRange { start, end };
+        config.optimizer.long = HashMap::from([("ema_span_0".to_string(), range(10.0, 20.0))]);
+        config.optimizer.short = HashMap::from([("ema_span_1".to_string(), range(30.0, 40.0))]);
+
+        // the fixture trades long only
+        assert_eq!(
+            search_space(&config),
+            (vec!["long.ema_span_0".to_string()], vec![(10.0, 20.0)])
+        );
+        config.bot.short.enabled = true;
+        assert_eq!(
+            search_space(&config),
+            (
+                vec![
+                    "long.ema_span_0".to_string(),
+                    "short.ema_span_1".to_string()
+                ],
+                vec![(10.0, 20.0), (30.0, 40.0)]
+            )
+        );
+        config.bot.long.enabled = false;
+        assert_eq!(
+            search_space(&config),
+            (vec!["short.ema_span_1".to_string()], vec![(30.0, 40.0)])
+        );
+    }
+
+    #[test]
+    fn test_adaptive_mutation_probability() {
+        assert_eq!(calc_mutation_probability(0.2, 5, 11, false), 0.2);
+        assert_eq!(calc_mutation_probability(0.2, 0, 11, true), 0.2);
+        assert!((calc_mutation_probability(0.2, 5, 11, true) - 0.11).abs() < 1e-12);
+        assert!((calc_mutation_probability(0.2, 10, 11, true) - 0.02).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_scoring_builds_signed_weighted_objectives() {
+        let analysis = Analysis {
+            adg: 0.002,
+            sharpe_ratio: 1.5,
+            drawdown_worst: 0.25,
+            ..Default::default()
+        };
+        let default = parse_scoring(&[]).unwrap();
+        assert_eq!(calculate_fitness(&analysis, &default), vec![-1.5, 0.25]);
+
+        let scoring: Vec<String> = ["adg:2", "drawdown_worst", "loss_profit_ratio"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let objectives = parse_scoring(&scoring).unwrap();
+        let fitness = calculate_fitness(&analysis, &objectives);
+        assert_eq!(fitness, vec![-0.004, 0.25, 1.0]);
+        assert_eq!(
+            format_fitness(&fitness, &objectives),
+            "adg = 0.0020, drawdown_worst = 0.2500, loss_profit_ratio = 1.0000"
+        );
+
+        for scoring in [
+            vec!["adg"],
+            vec![
+                "adg",
+                "sharpe_ratio",
+                "calmar_ratio",
+                "sortino_ratio",
+                "mdg",
+            ],
+            vec!["adg", "profit"],
+            vec!["adg", "adg:2"],
+            vec!["adg:0", "drawdown_worst"],
+        ] {
+            let scoring: Vec<String> = scoring.iter().map(|s| s.to_string()).collect();
+            assert!(parse_scoring(&scoring).is_err(), "{:?}", scoring);
+        }
+    }
+
+    #[test]
+    fn test_limits_rank_infeasible_individuals_last() {
+        let limits = parse_limits(&HashMap::from([
+            ("drawdown_worst".to_string(), 0.5),
+            ("adg".to_string(), 0.001),
+        ]))
+        .unwrap();
+        let analysis = |adg: f64, drawdown_worst: f64| Analysis {
+            adg,
+            drawdown_worst,
+            ..Default::default()
+        };
+        assert_eq!(constraint_violation(&analysis(0.002, 0.4), &limits), 0.0);
+        // 20% over the drawdown bound and half short of the adg bound
+        let violation = constraint_violation(&analysis(0.0005, 0.6), &limits);
+        assert!((violation - 0.7).abs() < 1e-9);
+
+        let individual = |fitness: Vec<f64>, constraint_violation: f64| Individual {
+            fitness,
+            constraint_violation,
+            ..Individual::new(Vec::new())
+        };
+        let mut population = vec![
+            // the best objectives, but past a limit
+            individual(vec![-3.0, 0.1], 0.2),
+            individual(vec![-1.0, 0.3], 0.0),
+            individual(vec![-2.0, 0.4], 0.0),
+            individual(vec![-3.0, 0.1], 0.5),
+        ];
+        let fronts = fast_non_dominated_sort(&mut population);
+        let violations: Vec<Vec<f64>> = fronts
+            .iter()
+            .map(|front| front.iter().map(|ind| ind.constraint_violation).collect())
+            .collect();
+        assert_eq!(violations, vec![vec![0.0, 0.0], vec![0.2], vec![0.5]]);
+
+        assert!(parse_limits(&HashMap::from([("profit".to_string(), 1.0)])).is_err());
+    }
+
+    #[test]
+    fn test_pareto_export_writes_loadable_configs() {
+        let base_config =
+            crate::config::load_config("tests/fixtures/backtest_config.hjson").unwrap();
+        let run_dir = RunDir {
+            path: std::env::temp_dir().join(format!("pareto_test_{}", std::process::id())),
+        };
+        let param_keys = vec!["long.total_wallet_exposure_limit".to_string()];
+        let objectives = parse_scoring(&[]).unwrap();
+        let solution = Individual {
+            fitness: vec![-1.5, 0.25],
+            analysis: Some(Analysis {
+                sharpe_ratio: 1.5,
+                drawdown_worst: 0.25,
+                ..Default::default()
+            }),
+            ..Individual::new(vec![0.75])
+        };
+        write_pareto(
+            &run_dir,
+            &[solution],
+            &base_config,
+            &param_keys,
+            &objectives,
+            42,
+        )
+        .unwrap();
+
+        let pareto: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(run_dir.path.join("pareto.json")).unwrap())
+                .unwrap();
+        assert_eq!(pareto["seed"], 42);
+        let solution = &pareto["solutions"][0];
+        assert_eq!(solution["params"]["long.total_wallet_exposure_limit"], 0.75);
+        assert_eq!(solution["analysis"]["sharpe_ratio"], 1.5);
+        let config_path = run_dir.path.join(solution["config"].as_str().unwrap());
+        let config = crate::config::load_config(&config_path).unwrap();
+        assert_eq!(config.bot.long.total_wallet_exposure_limit, 0.75);
+        assert_eq!(config.backtest.symbols, base_config.backtest.symbols);
+        fs::remove_dir_all(&run_dir.path).unwrap();
+    }
+}
@@ -0,0 +1,240 @@
+use crate::types::{GridOrder, Order};
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct OrderDiffEntry {
+    pub order_type: String,
+    pub qty: f64,
+    pub price: f64,
+    pub reason: String,
+}
+
+/// How one cycle's orders relate to the previous cycle's: which are kept unchanged, which
+/// are cancelled and which are created, each with the reason.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct OrderDiff {
+    pub kept: Vec<OrderDiffEntry>,
+    pub to_cancel: Vec<OrderDiffEntry>,
+    pub to_create: Vec<OrderDiffEntry>,
+}
+
+impl OrderDiff {
+    pub fn is_empty(&self) -> bool {
+        self.to_cancel.is_empty() && self.to_create.is_empty()
+    }
+}
+
+fn entry(order: &GridOrder, reason: String) -> OrderDiffEntry {
+    OrderDiffEntry {
+        order_type: order.order_type.to_string(),
+        qty: order.qty,
+        price: order.price,
+        reason,
+    }
+}
+
+fn change_reason(from: &GridOrder, to: &GridOrder) -> String {
+    match (from.price != to.price, from.qty != to.qty) {
+        (true, true) => format!(
+            "price {} -> {}, qty {} -> {}",
+            from.price, to.price, from.qty, to.qty
+        ),
+        (true, false) => format!("price {} -> {}", from.price, to.price),
+        _ => format!("qty {} -> {}", from.qty, to.qty),
+    }
+}
+
+/// Diffs the previously placed orders against the desired ones.
+///
+/// Identical orders are kept. A changed order of the same type is reported as a
+/// cancellation plus a creation, both giving the price/qty change as the reason.
+pub fn diff_orders(previous: &[GridOrder], desired: &[GridOrder]) -> OrderDiff {
+    let mut diff = OrderDiff::default();
+    let mut unmatched: Vec<&GridOrder> = previous.iter().collect();
+    let mut new_orders = Vec::new();
+    for order in desired {
+        match unmatched.iter().position(|o| {
+            o.order_type == order.order_type && o.qty == order.qty && o.price == order.price
+        }) {
+            Some(i) => {
+                unmatched.remove(i);
+                diff.kept.push(entry(order, "unchanged".to_string()));
+            }
+            None => new_orders.push(order),
+        }
+    }
+    for order in new_orders {
+        match unmatched
+            .iter()
+            .position(|o| o.order_type == order.order_type)
+        {
+            Some(i) => {
+                let replaced = unmatched.remove(i);
+                let reason = change_reason(replaced, order);
+                diff.to_cancel
+                    .push(entry(replaced, format!("replaced: {}", reason)));
+                diff.to_create
+                    .push(entry(order, format!("replaces: {}", reason)));
+            }
+            None => diff.to_create.push(entry(order, "new order".to_string())),
+        }
+    }
+    for order in unmatched {
+        diff.to_cancel
+            .push(entry(order, "no longer wanted".to_string()));
+    }
+    diff
+}
+
+/// What it takes to turn the orders resting on the exchange into the desired ones.
+#[derive(Debug, Clone, Default)]
+pub struct Reconciliation {
+    pub to_cancel: Vec<Order>,
+    pub to_create: Vec<Order>,
+    /// Desired orders already resting on the exchange.
+    pub kept: Vec<Order>,
+}
+
+fn order_entry(order: &Order, reason: &str) -> OrderDiffEntry {
+    OrderDiffEntry {
+        order_type: if order.custom_id.is_empty() {
+            order.side.clone()
+        } else {
+            order.custom_id.clone()
+        },
+        qty: order.qty,
+        price: order.price,
+        reason: reason.to_string(),
+    }
+}
+
+impl Reconciliation {
+    /// The reconciliation as an `OrderDiff`, for the status.
+    pub fn to_diff(&self) -> OrderDiff {
+        let entries = |orders: &[Order], reason: &str| {
+            orders.iter().map(|o| order_entry(o, reason)).collect()
+        };
+        OrderDiff {
+            kept: entries(&self.kept, "resting"),
+            to_cancel: entries(&self.to_cancel, "not in the grid"),
+            to_create: entries(&self.to_create, "not resting"),
+        }
+    }
+}
+
+/// Matches the `open` orders against the `desired` ones. An open order matches a desired
+/// order on the same side whose price and qty are within half a `price_step` and
+/// `qty_step` of it. Position sides and reduce-only flags are not compared: exchanges in
+/// one-way mode report no position side, and orders placed without a reduce-only flag are
+/// reported without one although the bot meant them as closes. Unmatched open orders are
+/// cancelled and unmatched desired orders created.
+pub fn reconcile(
+    open: &[Order], desired: &[Order], price_step: f64, qty_step: f64,
+) -> Reconciliation {
+    let (price_tolerance, qty_tolerance) = (price_step / 2.0, qty_step / 2.0);
+    let mut unmatched: Vec<&Order> = open.iter().collect();
+    let mut reconciliation = Reconciliation::default();
+    for order in desired {
+        match unmatched.iter().position(|o| {
+            o.side.eq_ignore_ascii_case(&order.side)
+                && (o.price - order.price).abs() <= price_tolerance
+                && (o.qty - order.qty).abs() <= qty_tolerance
+        }) {
+            Some(i) => {
+                unmatched.remove(i);
+                reconciliation.kept.push(order.clone());
+            }
+            None => reconciliation.to_create.push(order.clone()),
+        }
+    }
+    reconciliation.to_cancel = unmatched.into_iter().cloned().collect();
+    reconciliation
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::OrderType;
+
+    fn order(qty: f64, price: f64, order_type: OrderType) -> GridOrder {
+        GridOrder {
+            qty,
+            price,
+            order_type,
+        }
+    }
+
+    #[test]
+    fn test_diff_orders() {
+        let previous = vec![
+            order(1.0, 99.0, OrderType::EntryGridNormalLong),
+            order(-0.5, 101.0, OrderType::CloseGridLong),
+            order(1.0, 95.0, OrderType::EntryInitialPartialLong),
+        ];
+        let desired = vec![
+            order(1.0, 99.0, OrderType::EntryGridNormalLong),
+            order(-0.5, 101.5, OrderType::CloseGridLong),
+            order(-0.2, 102.0, OrderType::CloseTrailingLong),
+        ];
+        let diff = diff_orders(&previous, &desired);
+
+        assert_eq!(diff.kept.len(), 1);
+        assert_eq!(diff.to_create.len(), 2);
+        assert_eq!(diff.to_create[0].reason, "replaces: price 101 -> 101.5");
+        assert_eq!(diff.to_create[1].reason, "new order");
+        assert_eq!(diff.to_cancel.len(), 2);
+        assert_eq!(diff.to_cancel[0].reason, "replaced: price 101 -> 101.5");
+        assert_eq!(diff.to_cancel[1].reason, "no longer wanted");
+        assert!(diff_orders(&desired, &desired).is_empty());
+    }
+
+    #[test]
+    fn test_reconcile_with_open_orders() {
+        let open_order = |id: &str, side: &str, qty: f64, price: f64, reduce_only: bool| Order {
+            id: id.to_string(),
+            symbol: "BTCUSDT".to_string(),
+            side: side.to_string(),
+            qty,
+            price,
+            reduce_only,
+            ..Default::default()
+        };
+        let open = vec![
+            open_order("1", "Buy", 1.0, 99.0, false),
+            // reported with float noise
+            open_order("2", "sell", 0.5, 101.00000001, true),
+            open_order("3", "Buy", 1.0, 95.0, false),
+        ];
+        let desired = vec![
+            open_order("", "Buy", 1.0, 99.0, false),
+            open_order("", "Sell", 0.5, 101.0, true),
+            open_order("", "Buy", 2.0, 95.0, false),
+        ];
+        let reconciliation = reconcile(&open, &desired, 0.1, 0.001);
+
+        assert_eq!(reconciliation.kept.len(), 2);
+        assert_eq!(reconciliation.to_cancel.len(), 1);
+        assert_eq!(reconciliation.to_cancel[0].id, "3");
+        assert_eq!(reconciliation.to_create.len(), 1);
+        assert_eq!(reconciliation.to_create[0].qty, 2.0);
+        assert_eq!(reconcile(&open, &open, 0.1, 0.001).kept.len(), 3);
+    }
+
+    #[test]
+    fn test_reconcile_keeps_closes_reported_without_reduce_only() {
+        let close = |id: &str, reduce_only: bool| Order {
+            id: id.to_string(),
+            symbol: "BTCUSDT".to_string(),
+            side: "Sell".to_string(),
+            qty: 0.5,
+            price: 101.0,
+            reduce_only,
+            ..Default::default()
+        };
+        // placed as a close, reported by the exchange as a plain order
+        let reconciliation = reconcile(&[close("1", false)], &[close("", true)], 0.1, 0.001);
+        assert_eq!(reconciliation.kept.len(), 1);
+        assert!(reconciliation.to_cancel.is_empty());
+        assert!(reconciliation.to_create.is_empty());
+    }
+}
@@ -0,0 +1,72 @@
+use crate::grid::utils::calc_wallet_exposure;
+use crate::types::{ExchangeParams, PositionPair, Position};
+
+/// Whether the long and the short leg may add to their position: the leg whose wallet
+/// exposure exceeds the other's by `max_imbalance` or more may not, so the pair drifts
+/// back towards delta-neutral as the heavier leg's closes fill.
+pub fn entries_allowed(
+    legs: &PositionPair, balance: f64, exchange_params: &ExchangeParams, max_imbalance: f64,
+) -> (bool, bool) {
+    let exposure = |position: &Position| {
+        calc_wallet_exposure(
+            exchange_params.c_mult,
+            balance,
+            position.size.abs(),
+            position.price,
+            exchange_params.inverse,
+        )
+    };
+    let imbalance = exposure(&legs.long) - exposure(&legs.short);
+    (imbalance < max_imbalance, -imbalance < max_imbalance)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants::SHORT;
+
+    #[test]
+    fn test_heavier_leg_pauses_entries() {
+        let exchange_params = ExchangeParams::default();
+        let legs = |long: f64, short: f64| PositionPair {
+            long: Position {
+                size: long,
+                price: 100.0,
+            },
+            short: Position {
+                size: -short,
+                price: 110.0,
+            },
+            one_way: false,
+        };
+        // exposures 0.3 and 0.25 at a balance of 1000
+        assert_eq!(
+            entries_allowed(&legs(3.0, 2.5 / 1.1), 1000.0, &exchange_params, 0.1),
+            (true, true)
+        );
+        // long at 0.4 against 0.25
+        assert_eq!(
+            entries_allowed(&legs(4.0, 2.5 / 1.1), 1000.0, &exchange_params, 0.1),
+            (false, true)
+        );
+        assert_eq!(
+            entries_allowed(&legs(0.0, 2.5 / 1.1), 1000.0, &exchange_params, 0.1),
+            (true, false)
+        );
+
+        let net = legs(3.0, 1.0).net();
+        assert_eq!((net.size, net.price), (2.0, 100.0));
+        let net = legs(1.0, 3.0).net();
+        assert_eq!((net.size, net.price), (-2.0, 110.0));
+        assert_eq!(legs(1.0, 1.0).net().price, 0.0);
+        // a one-way position sits on the leg of its sign
+        let short = Position {
+            size: -2.0,
+            price: 90.0,
+        };
+        let pair = PositionPair::from_net(short);
+        assert_eq!((pair.long.size, pair.leg(SHORT).size), (0.0, -2.0));
+        assert!(pair.one_way && PositionPair::from_net(Position::default()).one_way);
+        assert_eq!(pair.net().price, 90.0);
+    }
+}
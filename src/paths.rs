@@ -0,0 +1,180 @@
+use crate::types::LiveConfig;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::{info, warn};
+
+/// Locations of the files a bot keeps across restarts, all under `live.state_dir`:
+///
+/// - `<state_dir>/<user>/state/<symbol>.json`: per-symbol state
+/// - `<state_dir>/<user>/journal.json`: fill journal
+/// - `<state_dir>/<user>/intents.jsonl`: order intent log
+/// - `<state_dir>/<user>/heartbeat.json`: liveness heartbeat, see `heartbeat`
+/// - `<state_dir>/<user>/restarts.json`: startups of the last day, see `restarts`
+/// - `<state_dir>/<user>/logs/`: logs of live runs
+/// - `<state_dir>/groups/<group>/`: allocation group reports, shared by all users
+///
+/// Each user has a directory of its own, so bots of several users on one machine never
+/// write to the same files. An empty `state_dir` disables persistence, and every path is
+/// then `None`.
+#[derive(Debug, Clone, Default)]
+pub struct Paths {
+    root: Option<PathBuf>,
+    user: String,
+}
+
+impl Paths {
+    pub fn new(state_dir: &str, user: &str) -> Self {
+        Self {
+            root: (!state_dir.is_empty()).then(|| expand_home(state_dir)),
+            user: user.to_string(),
+        }
+    }
+
+    pub fn from_config(live_config: &LiveConfig) -> Self {
+        Self::new(&live_config.state_dir, &live_config.user)
+    }
+
+    /// The paths of another user under the same `state_dir`.
+    pub fn for_user(&self, user: &str) -> Self {
+        Self {
+            root: self.root.clone(),
+            user: user.to_string(),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.root.is_some()
+    }
+
+    pub fn user_dir(&self) -> Option<PathBuf> {
+        self.root.as_ref().map(|root| root.join(&self.user))
+    }
+
+    pub fn symbol_state(&self, symbol: &str) -> Option<PathBuf> {
+        self.user_dir()
+            .map(|dir| dir.join("state").join(format!("{}.json", symbol)))
+    }
+
+    pub fn journal(&self) -> Option<PathBuf> {
+        self.user_dir().map(|dir| dir.join("journal.json"))
+    }
+
+    pub fn intent_log(&self) -> Option<PathBuf> {
+        self.user_dir().map(|dir| dir.join("intents.jsonl"))
+    }
+
+    /// Listing times of the symbols of the user's exchange, see `ListingRegistry`.
+    pub fn listings(&self) -> Option<PathBuf> {
+        self.user_dir().map(|dir| dir.join("listings.json"))
+    }
+
+    pub fn heartbeat(&self) -> Option<PathBuf> {
+        self.user_dir().map(|dir| dir.join("heartbeat.json"))
+    }
+
+    pub fn restarts(&self) -> Option<PathBuf> {
+        self.user_dir().map(|dir| dir.join("restarts.json"))
+    }
+
+    pub fn log_dir(&self) -> Option<PathBuf> {
+        self.user_dir().map(|dir| dir.join("logs"))
+    }
+
+    pub fn group_dir(&self, group: &str) -> Option<PathBuf> {
+        self.root
+            .as_ref()
+            .map(|root| root.join("groups").join(group))
+    }
+
+    /// Moves the user's files from the layout without user directories, where symbol
+    /// states sat directly in `state_dir` and journals and intent logs were named after
+    /// the user, into the user's directory. Files already present there are kept.
+    pub fn migrate_legacy_layout(&self) {
+        let (Some(root), Some(user_dir)) = (&self.root, self.user_dir()) else {
+            return;
+        };
+        let mut moves = vec![
+            (
+                root.join("journal").join(format!("{}.json", self.user)),
+                user_dir.join("journal.json"),
+            ),
+            (
+                root.join("intents").join(format!("{}.jsonl", self.user)),
+                user_dir.join("intents.jsonl"),
+            ),
+        ];
+        if let Ok(entries) = fs::read_dir(root) {
+            for path in entries.filter_map(|e| e.ok()).map(|e| e.path()) {
+                if path.is_file() && path.extension().is_some_and(|ext| ext == "json") {
+                    if let Some(name) = path.file_name() {
+                        let target = user_dir.join("state").join(name);
+                        moves.push((path, target));
+                    }
+                }
+            }
+        }
+        for (from, to) in moves {
+            if from.is_file() && !to.exists() {
+                if let Err(e) = move_file(&from, &to) {
+                    warn!(
+                        "Failed to move {} to {}: {}",
+                        from.display(),
+                        to.display(),
+                        e
+                    );
+                } else {
+                    info!("Moved {} to {}", from.display(), to.display());
+                }
+            }
+        }
+    }
+}
+
+fn move_file(from: &Path, to: &Path) -> std::io::Result<()> {
+    if let Some(dir) = to.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::rename(from, to)
+}
+
+/// Expands a leading `~` to the home directory.
+fn expand_home(dir: &str) -> PathBuf {
+    match (dir.strip_prefix('~'), std::env::var_os("HOME")) {
+        (Some(rest), Some(home)) => PathBuf::from(home).join(rest.trim_start_matches('/')),
+        _ => PathBuf::from(dir),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_user_paths_and_migration() {
+        let root = std::env::temp_dir().join(format!("paths_test_{}", std::process::id()));
+        let paths = Paths::new(root.to_str().unwrap(), "alice");
+        assert_eq!(
+            paths.symbol_state("BTCUSDT"),
+            Some(root.join("alice/state/BTCUSDT.json"))
+        );
+        assert_eq!(
+            paths.for_user("bob").journal(),
+            Some(root.join("bob/journal.json"))
+        );
+        assert_eq!(paths.group_dir("main"), Some(root.join("groups/main")));
+        assert!(Paths::new("", "alice").journal().is_none());
+
+        fs::create_dir_all(root.join("journal")).unwrap();
+        fs::write(root.join("BTCUSDT.json"), "{}").unwrap();
+        fs::write(root.join("journal/alice.json"), "{}").unwrap();
+        fs::write(root.join("journal/bob.json"), "{}").unwrap();
+        paths.migrate_legacy_layout();
+        assert!(root.join("alice/state/BTCUSDT.json").is_file());
+        assert!(root.join("alice/journal.json").is_file());
+        assert!(!root.join("BTCUSDT.json").exists());
+        // other users' files stay for them to migrate
+        assert!(root.join("journal/bob.json").is_file());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,238 @@
+//! Rolling live performance: ADG and Sharpe ratio over the last 7 and 30 days of the fill
+//! journal's equity curve, compared against the backtest baseline in
+//! `live.performance_alerts`.
+
+use crate::analysis;
+use crate::control::StatusBoard;
+use crate::events::{BotEvent, EventSubscriber};
+use crate::journal::FillJournal;
+use crate::types::PerformanceAlertConfig;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use tracing::{info, warn};
+
+const MS_PER_DAY: i64 = 24 * 60 * 60 * 1000;
+
+/// Windows the metrics are rolled over, in days.
+pub const WINDOWS_DAYS: [u32; 2] = [7, 30];
+
+/// Balance updates arrive every cycle of every symbol; metrics are recomputed at most this
+/// often unless a fill comes in.
+const RECOMPUTE_INTERVAL_MS: i64 = 5 * 60 * 1000;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct RollingMetrics {
+    pub window_days: u32,
+    pub adg: f64,
+    /// Sharpe ratio of the daily returns, annualized as in backtests.
+    pub sharpe: f64,
+    pub n_fills: usize,
+    /// Metrics below their band, e.g. `["adg"]`.
+    #[serde(default)]
+    pub degraded: Vec<String>,
+}
+
+/// Metrics over the `window_days` before `now_ms`, from the journal's equity curve ending
+/// at `balance`. `None` while the journal does not reach back to the window's start.
+pub fn rolling_metrics(
+    journal: &FillJournal, balance: f64, now_ms: i64, window_days: u32,
+) -> Option<RollingMetrics> {
+    let start_ms = now_ms - window_days as i64 * MS_PER_DAY;
+    let (timestamps, equity) = journal.equity_curve(balance);
+    if timestamps.first().is_none_or(|&first| first > start_ms) {
+        return None;
+    }
+    // equity at the end of each day of the window, starting with the window's start
+    let equity_at = |ms: i64| {
+        let n = timestamps.partition_point(|&t| t <= ms);
+        if n == 0 {
+            equity[0]
+        } else {
+            equity[n - 1]
+        }
+    };
+    let daily: Vec<f64> = (0..=window_days as i64)
+        .map(|day| equity_at(start_ms + day * MS_PER_DAY))
+        .collect();
+    let (start, end) = (daily[0], daily[daily.len() - 1]);
+    if start <= 0.0 || end <= 0.0 {
+        return None;
+    }
+    Some(RollingMetrics {
+        window_days,
+        adg: (end / start).powf(1.0 / window_days as f64) - 1.0,
+        sharpe: analysis::calculate_sharpe_ratio(&analysis::calculate_returns(&daily)),
+        n_fills: journal
+            .entries
+            .iter()
+            .filter(|e| e.fill.timestamp_ms > start_ms)
+            .count(),
+        degraded: Vec::new(),
+    })
+}
+
+/// Names of the metrics more than `tolerance` of their baseline below it. Metrics with a
+/// zero baseline are not checked.
+pub fn degraded_metrics(metrics: &RollingMetrics, config: &PerformanceAlertConfig) -> Vec<String> {
+    let below = |live: f64, baseline: f64| {
+        baseline != 0.0 && live < baseline - config.tolerance * baseline.abs()
+    };
+    let mut degraded = Vec::new();
+    if below(metrics.adg, config.baseline_adg) {
+        degraded.push("adg".to_string());
+    }
+    if below(metrics.sharpe, config.baseline_sharpe) {
+        degraded.push("sharpe".to_string());
+    }
+    degraded
+}
+
+/// Keeps its own copy of the fill journal and publishes the rolling metrics to the status
+/// board, warning once when a metric falls below its band and again after it recovers.
+pub struct PerformanceTracker {
+    config: PerformanceAlertConfig,
+    journal: FillJournal,
+    balance: f64,
+    computed_at_ms: i64,
+    status_board: StatusBoard,
+    /// (window, metric) pairs alerted on and not recovered since.
+    alerted: Vec<(u32, String)>,
+}
+
+impl PerformanceTracker {
+    /// Starts from the journal at `journal_path`, or from an empty one.
+    pub fn new(
+        config: PerformanceAlertConfig, journal_path: Option<PathBuf>, status_board: StatusBoard,
+    ) -> Self {
+        Self {
+            config,
+            journal: journal_path
+                .map(|path| FillJournal::load(&path))
+                .unwrap_or_default(),
+            balance: 0.0,
+            computed_at_ms: 0,
+            status_board,
+            alerted: Vec::new(),
+        }
+    }
+
+    fn update(&mut self, now_ms: i64) {
+        self.computed_at_ms = now_ms;
+        let mut all_metrics = Vec::new();
+        for window_days in WINDOWS_DAYS {
+            let Some(mut metrics) =
+                rolling_metrics(&self.journal, self.balance, now_ms, window_days)
+            else {
+                continue;
+            };
+            metrics.degraded = degraded_metrics(&metrics, &self.config);
+            for metric in ["adg", "sharpe"] {
+                let key = (window_days, metric.to_string());
+                let is_degraded = metrics.degraded.iter().any(|m| m == metric);
+                let was_alerted = self.alerted.contains(&key);
+                let (live, baseline) = if metric == "adg" {
+                    (metrics.adg, self.config.baseline_adg)
+                } else {
+                    (metrics.sharpe, self.config.baseline_sharpe)
+                };
+                if is_degraded && !was_alerted {
+                    warn!(
+                        "Live {}-day {} of {:.6} is below its band around the backtest baseline {:.6}",
+                        window_days, metric, live, baseline
+                    );
+                    self.alerted.push(key);
+                } else if !is_degraded && was_alerted {
+                    info!(
+                        "Live {}-day {} of {:.6} is back within its band",
+                        window_days, metric, live
+                    );
+                    self.alerted.retain(|k| k != &key);
+                }
+            }
+            all_metrics.push(metrics);
+        }
+        self.status_board.set_performance(all_metrics);
+    }
+}
+
+impl EventSubscriber for PerformanceTracker {
+    fn on_event(&mut self, event: &BotEvent) {
+        let now_ms = Utc::now().timestamp_millis();
+        match event {
+            BotEvent::OrderFilled { fill } => {
+                let added = self.journal.merge(vec![fill.clone()]);
+                if added > 0 && self.balance > 0.0 {
+                    self.update(now_ms);
+                }
+            }
+            BotEvent::EquitySnapshot { snapshot } => {
+                self.journal.record_snapshot(snapshot.clone());
+            }
+            BotEvent::BalanceUpdated { balance } => {
+                self.balance = *balance;
+                if now_ms - self.computed_at_ms >= RECOMPUTE_INTERVAL_MS {
+                    self.update(now_ms);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::TradeFill;
+
+    /// A long bought at 100 and sold at 101 every day, for `days` days up to `now_ms`.
+    fn daily_round_trips(days: i64, now_ms: i64) -> FillJournal {
+        let mut fills = Vec::new();
+        for day in 0..days {
+            let ts = now_ms - (days - day) * MS_PER_DAY + 1000;
+            for (i, (side, price)) in [("Buy", 100.0), ("Sell", 101.0)].into_iter().enumerate() {
+                fills.push(TradeFill {
+                    id: format!("{}-{}", day, i),
+                    order_id: String::new(),
+                    symbol: "BTCUSDT".to_string(),
+                    timestamp_ms: ts + i as i64,
+                    side: side.to_string(),
+                    position_side: "Long".to_string(),
+                    qty: 1.0,
+                    price,
+                    fee: 0.0,
+                    maker: None,
+                    order_price: 0.0,
+                    realized_pnl: None,
+                });
+            }
+        }
+        let mut journal = FillJournal::default();
+        journal.merge(fills);
+        journal
+    }
+
+    #[test]
+    fn test_rolling_metrics_and_bands() {
+        let now_ms = 100 * MS_PER_DAY;
+        let journal = daily_round_trips(10, now_ms);
+        // 1 of PnL a day on a balance ending at 1000
+        let week = rolling_metrics(&journal, 1000.0, now_ms, 7).unwrap();
+        assert_eq!(week.n_fills, 14);
+        assert!((week.adg - 1.0 / 993.0).abs() < 1e-5);
+        // not enough history for the 30 day window
+        assert!(rolling_metrics(&journal, 1000.0, now_ms, 30).is_none());
+
+        let config = PerformanceAlertConfig {
+            baseline_adg: 0.001,
+            baseline_sharpe: 0.0,
+            tolerance: 0.5,
+        };
+        assert!(degraded_metrics(&week, &config).is_empty());
+        let config = PerformanceAlertConfig {
+            baseline_adg: 0.003,
+            ..config
+        };
+        assert_eq!(degraded_metrics(&week, &config), vec!["adg".to_string()]);
+    }
+}
@@ -0,0 +1,99 @@
+//! Startups of the bot over the last day, for `live.max_n_restarts_per_day`. A bot that
+//! keeps crashing and being restarted starts in safe mode: every side that would trade
+//! normally gracefully stops, so open positions keep being closed out while nothing new
+//! is opened.
+
+use crate::constants::{LONG, SHORT};
+use crate::exchange::SendSyncError;
+use crate::listings::MS_PER_DAY;
+use crate::types::{BotConfig, TradingMode};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+use tracing::warn;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct RestartLog {
+    /// Startup times within the last day, oldest first.
+    pub started_at_ms: Vec<i64>,
+}
+
+impl RestartLog {
+    pub fn load(path: &Path) -> Self {
+        match fs::read_to_string(path) {
+            Ok(content) => serde_json::from_str(&content).unwrap_or_else(|e| {
+                warn!("Ignoring unreadable restart log {}: {}", path.display(), e);
+                Self::default()
+            }),
+            Err(_) => Self::default(),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), SendSyncError> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// Records a startup at `now_ms` and forgets those more than a day before it. Returns
+    /// the number of restarts in the last day: the startups before this one, each of whose
+    /// runs has ended since.
+    pub fn record_start(&mut self, now_ms: i64) -> usize {
+        self.started_at_ms.retain(|&t| t > now_ms - MS_PER_DAY);
+        let n_restarts = self.started_at_ms.len();
+        self.started_at_ms.push(now_ms);
+        n_restarts
+    }
+}
+
+/// Whether `n_restarts` in the last day exceed `max_n_restarts_per_day`; 0 allows any.
+pub fn exceeds_limit(n_restarts: usize, max_n_restarts_per_day: i32) -> bool {
+    max_n_restarts_per_day > 0 && n_restarts > max_n_restarts_per_day as usize
+}
+
+/// Puts `config` in safe mode: sides that would trade normally are forced to gracefully
+/// stop, and those forced to a stricter mode or taking profit only stay as they are.
+pub fn enter_safe_mode(config: &mut BotConfig) {
+    for pside in [LONG, SHORT] {
+        if config.side_mode(pside) != TradingMode::Normal {
+            continue;
+        }
+        let forced_mode = if pside == LONG {
+            &mut config.live.forced_mode_long
+        } else {
+            &mut config.live.forced_mode_short
+        };
+        *forced_mode = "graceful_stop".to_string();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config;
+
+    #[test]
+    fn test_repeated_restarts_enter_safe_mode() {
+        let mut log = RestartLog::default();
+        let start_ms = 10 * MS_PER_DAY;
+        for i in 0..4 {
+            assert_eq!(log.record_start(start_ms + i * 60_000), i as usize);
+        }
+        assert!(exceeds_limit(3, 2));
+        assert!(!exceeds_limit(2, 2));
+        assert!(!exceeds_limit(100, 0));
+        // startups more than a day ago no longer count
+        assert_eq!(log.record_start(start_ms + MS_PER_DAY + 90_000), 2);
+        assert_eq!(log.started_at_ms.len(), 3);
+
+        let mut config = config::load_config("tests/fixtures/backtest_config.hjson").unwrap();
+        config.live.forced_mode_long = String::new();
+        config.live.forced_mode_short = "manual".to_string();
+        assert!(config.bot.long.enabled);
+        enter_safe_mode(&mut config);
+        assert_eq!(config.side_mode(LONG), TradingMode::GracefulStop);
+        assert_eq!(config.side_mode(SHORT), TradingMode::Manual);
+    }
+}
@@ -0,0 +1,73 @@
+use crate::data;
+use crate::exchange::SendSyncError;
+use crate::types::BotConfig;
+use chrono::Utc;
+use serde::Serialize;
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+use tracing::info;
+
+/// A timestamped directory holding everything needed to reproduce a backtest or
+/// optimization run: the resolved config, run metadata and all outputs.
+///
+/// Layout: `results/<kind>/<timestamp>/{config.json, metadata.json, ...}`
+pub struct RunDir {
+    pub path: PathBuf,
+}
+
+impl RunDir {
+    pub fn create(kind: &str, config: &BotConfig) -> Result<Self, SendSyncError> {
+        let created_at = Utc::now();
+        let path = PathBuf::from("results")
+            .join(kind)
+            .join(created_at.format("%Y-%m-%dT%H%M%S").to_string());
+        fs::create_dir_all(&path)?;
+
+        let run_dir = Self { path };
+        run_dir.write_json("config.json", config)?;
+        run_dir.write_json(
+            "metadata.json",
+            &serde_json::json!({
+                "kind": kind,
+                "created_at": created_at.to_rfc3339(),
+                "git_commit": git_commit_hash(),
+                "start_date": config.backtest.start_date,
+                "end_date": config.backtest.end_date,
+                // relative dates resolved at the time of the run
+                "start_ts": data::parse_date_ms(&config.backtest.start_date),
+                "end_ts": data::parse_date_ms(&config.backtest.end_date),
+                "symbols": config.backtest.symbols,
+            }),
+        )?;
+        info!("Writing run results to {}", run_dir.path.display());
+        Ok(run_dir)
+    }
+
+    pub fn write_json<T: Serialize + ?Sized>(
+        &self, file_name: &str, value: &T,
+    ) -> Result<(), SendSyncError> {
+        let content = serde_json::to_string_pretty(value)?;
+        fs::write(self.path.join(file_name), content)?;
+        Ok(())
+    }
+
+    /// Like `write_json`, without whitespace, for large outputs.
+    pub fn write_compact_json<T: Serialize + ?Sized>(
+        &self, file_name: &str, value: &T,
+    ) -> Result<(), SendSyncError> {
+        fs::write(self.path.join(file_name), serde_json::to_string(value)?)?;
+        Ok(())
+    }
+}
+
+fn git_commit_hash() -> String {
+    Command::new("git")
+        .args(["rev-parse", "HEAD"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .map(|hash| hash.trim().to_string())
+        .unwrap_or_else(|| "unknown".to_string())
+}
@@ -0,0 +1,81 @@
+use crate::grid::utils::calc_equity;
+use crate::types::{BotSideConfig, ExchangeParams, Position, RiskGovernorConfig};
+use serde::{Deserialize, Serialize};
+
+/// Tracks the equity peak to scale exposure limits by the current drawdown, see
+/// `RiskGovernorConfig`. Backtests and live bots both feed it equity each step.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+pub struct RiskGovernor {
+    pub peak_equity: f64,
+}
+
+impl RiskGovernor {
+    /// Records the latest equity and returns the exposure scale for its drawdown.
+    pub fn update(&mut self, equity: f64, config: &RiskGovernorConfig) -> f64 {
+        self.peak_equity = self.peak_equity.max(equity);
+        if !config.enabled || self.peak_equity <= 0.0 {
+            return 1.0;
+        }
+        calc_exposure_scale(1.0 - equity / self.peak_equity, config)
+    }
+}
+
+/// Exposure scale for a drawdown, as a fraction of the equity peak.
+pub fn calc_exposure_scale(drawdown: f64, config: &RiskGovernorConfig) -> f64 {
+    if config.max_drawdown <= 0.0 {
+        return 1.0;
+    }
+    let progress = (drawdown / config.max_drawdown).clamp(0.0, 1.0);
+    1.0 - progress * (1.0 - config.min_exposure_scale.clamp(0.0, 1.0))
+}
+
+/// Unrealized PnL of a signed position at `last_price`.
+pub fn calc_unrealized_pnl(position: &Position, last_price: f64, params: &ExchangeParams) -> f64 {
+    let (psize_long, pprice_long, psize_short, pprice_short) = if position.size > 0.0 {
+        (position.size, position.price, 0.0, 0.0)
+    } else {
+        (0.0, 0.0, position.size, position.price)
+    };
+    calc_equity(
+        0.0,
+        psize_long,
+        pprice_long,
+        psize_short,
+        pprice_short,
+        last_price,
+        params.inverse,
+        params.c_mult,
+    )
+}
+
+pub fn scale_exposure_limit(bot_params: &BotSideConfig, scale: f64) -> BotSideConfig {
+    let mut scaled = bot_params.clone();
+    scaled.total_wallet_exposure_limit *= scale;
+    scaled.close_wallet_exposure_limit *= scale;
+    scaled
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exposure_scales_with_drawdown() {
+        let config = RiskGovernorConfig {
+            enabled: true,
+            max_drawdown: 0.2,
+            min_exposure_scale: 0.5,
+        };
+        let mut governor = RiskGovernor::default();
+        assert_eq!(governor.update(1000.0, &config), 1.0);
+        assert!((governor.update(900.0, &config) - 0.75).abs() < 1e-12);
+        assert_eq!(governor.update(700.0, &config), 0.5);
+        // recovery restores exposure without moving the peak
+        assert!((governor.update(950.0, &config) - 0.875).abs() < 1e-12);
+        assert_eq!(governor.update(1100.0, &config), 1.0);
+        assert_eq!(governor.peak_equity, 1100.0);
+
+        let disabled = RiskGovernorConfig::default();
+        assert_eq!(governor.update(500.0, &disabled), 1.0);
+    }
+}
@@ -0,0 +1,309 @@
+use crate::exchange::{Exchange, SendSyncError};
+use crate::grid::utils::{calc_bankruptcy_price, calc_new_psize_pprice, calc_wallet_exposure};
+use crate::risk_governor::calc_unrealized_pnl;
+use crate::types::{ExchangeParams, Order, Position};
+use clap::Parser;
+use serde::Serialize;
+use std::fmt::Write;
+use tracing::warn;
+
+/// Price moves the positions are stressed with, as fractions of the last price.
+pub const PRICE_SHOCKS: [f64; 6] = [-0.3, -0.2, -0.1, 0.1, 0.2, 0.3];
+
+#[derive(Parser, Debug, Clone)]
+pub struct RiskArgs {
+    /// User/account name defined in api-keys.json
+    #[clap(long)]
+    pub user: String,
+
+    /// Symbols to check, defaults to live.approved_coins
+    #[clap(long, value_delimiter = ',')]
+    pub symbols: Vec<String>,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct SymbolRisk {
+    pub symbol: String,
+    pub position_size: f64,
+    pub position_price: f64,
+    pub last_price: f64,
+    /// Price at which the whole balance would be lost on this position alone; 0 if flat.
+    pub bankruptcy_price: f64,
+    /// Relative distance from the last price to the bankruptcy price, if there is one.
+    pub bankruptcy_distance: Option<f64>,
+    /// Margin the position ties up at `live.leverage`, in quote currency.
+    pub margin: f64,
+    /// PnL of each of `PRICE_SHOCKS`, relative to the last price.
+    pub shock_pnls: Vec<f64>,
+    /// Wallet exposure of the long and short side if every resting entry filled.
+    pub worst_case_exposure_long: f64,
+    pub worst_case_exposure_short: f64,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct RiskReport {
+    pub user: String,
+    pub balance: f64,
+    /// Combined margin of all positions as a fraction of the balance.
+    pub margin_usage: f64,
+    pub shocks: Vec<f64>,
+    /// Combined PnL of all positions under each shock.
+    pub total_shock_pnls: Vec<f64>,
+    /// Combined wallet exposure if every resting entry of every symbol filled.
+    pub worst_case_exposure: f64,
+    pub symbols: Vec<SymbolRisk>,
+}
+
+/// Resting entry orders (not reduce-only) on the side of `side`, as (qty, price).
+fn resting_entries<'a>(
+    open_orders: &'a [Order], side: &'a str,
+) -> impl Iterator<Item = (f64, f64)> + 'a {
+    open_orders
+        .iter()
+        .filter(move |o| !o.reduce_only && o.side == side)
+        .map(|o| (o.qty, o.price))
+}
+
+/// Wallet exposure of one side after `position_size` (absolute) grows by every entry.
+fn calc_worst_case_exposure(
+    balance: f64, position_size: f64, position_price: f64,
+    entries: impl Iterator<Item = (f64, f64)>, params: &ExchangeParams,
+) -> f64 {
+    let (psize, pprice) = entries.fold(
+        (position_size, position_price),
+        |(psize, pprice), (qty, price)| {
+            calc_new_psize_pprice(psize, pprice, qty, price, params.qty_step)
+        },
+    );
+    calc_wallet_exposure(params.c_mult, balance, psize, pprice, params.inverse)
+}
+
+pub fn calc_symbol_risk(
+    symbol: &str, position: &Position, last_price: f64, balance: f64, open_orders: &[Order],
+    params: &ExchangeParams, leverage: f64,
+) -> SymbolRisk {
+    let (psize_long, pprice_long, psize_short, pprice_short) = if position.size > 0.0 {
+        (position.size, position.price, 0.0, 0.0)
+    } else {
+        (0.0, 0.0, position.size, position.price)
+    };
+    let bankruptcy_price = if position.size == 0.0 {
+        0.0
+    } else {
+        calc_bankruptcy_price(
+            balance,
+            psize_long,
+            pprice_long,
+            psize_short,
+            pprice_short,
+            params.inverse,
+            params.c_mult,
+        )
+    };
+    let bankruptcy_distance = (bankruptcy_price > 0.0 && last_price > 0.0)
+        .then(|| (last_price - bankruptcy_price).abs() / last_price);
+    let exposure = calc_wallet_exposure(
+        params.c_mult,
+        balance,
+        position.size.abs(),
+        position.price,
+        params.inverse,
+    );
+    let upnl = calc_unrealized_pnl(position, last_price, params);
+    let shock_pnls = PRICE_SHOCKS
+        .iter()
+        .map(|shock| calc_unrealized_pnl(position, last_price * (1.0 + shock), params) - upnl)
+        .collect();
+    SymbolRisk {
+        symbol: symbol.to_string(),
+        position_size: position.size,
+        position_price: position.price,
+        last_price,
+        bankruptcy_price,
+        bankruptcy_distance,
+        margin: exposure * balance / leverage.max(1.0),
+        shock_pnls,
+        worst_case_exposure_long: calc_worst_case_exposure(
+            balance,
+            psize_long,
+            pprice_long,
+            resting_entries(open_orders, "Buy"),
+            params,
+        ),
+        worst_case_exposure_short: calc_worst_case_exposure(
+            balance,
+            psize_short.abs(),
+            pprice_short,
+            resting_entries(open_orders, "Sell"),
+            params,
+        ),
+    }
+}
+
+/// Builds the report from the account's live positions, balance and resting orders.
+/// Symbols without a position or resting orders are left out.
+pub async fn build_report(
+    exchange: &dyn Exchange, user: &str, symbols: &[String], leverage: f64,
+) -> Result<RiskReport, SendSyncError> {
+    let balance = exchange.fetch_balance().await?;
+    let mut report = RiskReport {
+        user: user.to_string(),
+        balance,
+        margin_usage: 0.0,
+        shocks: PRICE_SHOCKS.to_vec(),
+        total_shock_pnls: vec![0.0; PRICE_SHOCKS.len()],
+        worst_case_exposure: 0.0,
+        symbols: Vec::new(),
+    };
+    for symbol in symbols {
+        let position = exchange.fetch_positions(symbol).await?.net();
+        let open_orders = exchange
+            .fetch_open_orders(symbol)
+            .await
+            .unwrap_or_else(|e| {
+                warn!("[{}] Failed to fetch open orders: {}", symbol, e);
+                Vec::new()
+            });
+        if position.size == 0.0 && open_orders.is_empty() {
+            continue;
+        }
+        let last_price = exchange.fetch_ticker(symbol).await?;
+        let params = exchange.fetch_exchange_params(symbol).await?;
+        let risk = calc_symbol_risk(
+            symbol,
+            &position,
+            last_price,
+            balance,
+            &open_orders,
+            &params,
+            leverage,
+        );
+        if balance > 0.0 {
+            report.margin_usage += risk.margin / balance;
+        }
+        for (total, pnl) in report.total_shock_pnls.iter_mut().zip(&risk.shock_pnls) {
+            *total += pnl;
+        }
+        report.worst_case_exposure +=
+            risk.worst_case_exposure_long + risk.worst_case_exposure_short;
+        report.symbols.push(risk);
+    }
+    Ok(report)
+}
+
+pub fn format_risk_report(report: &RiskReport) -> String {
+    let fmt_shocks = |pnls: &[f64]| {
+        pnls.iter()
+            .map(|pnl| format!("{:>10.2}", pnl))
+            .collect::<String>()
+    };
+    let mut out = format!(
+        "user: {}  balance: {:.2}  margin usage: {:.1}%  worst-case WE: {:.4}\n",
+        report.user,
+        report.balance,
+        report.margin_usage * 100.0,
+        report.worst_case_exposure
+    );
+    let _ = writeln!(
+        out,
+        "{:<14} {:>12} {:>12} {:>12} {:>12} {:>10} {:>16}{}",
+        "symbol",
+        "pos size",
+        "last price",
+        "bankruptcy",
+        "distance",
+        "margin",
+        "WE l/s if filled",
+        report
+            .shocks
+            .iter()
+            .map(|shock| format!("{:>10}", format!("{:+.0}%", shock * 100.0)))
+            .collect::<String>()
+    );
+    for s in &report.symbols {
+        let _ = writeln!(
+            out,
+            "{:<14} {:>12} {:>12} {:>12} {:>12} {:>10.2} {:>16}{}",
+            s.symbol,
+            s.position_size,
+            s.last_price,
+            if s.bankruptcy_price > 0.0 {
+                s.bankruptcy_price.to_string()
+            } else {
+                "-".to_string()
+            },
+            s.bankruptcy_distance
+                .map_or("-".to_string(), |d| format!("{:.1}%", d * 100.0)),
+            s.margin,
+            format!(
+                "{:.2}/{:.2}",
+                s.worst_case_exposure_long, s.worst_case_exposure_short
+            ),
+            fmt_shocks(&s.shock_pnls)
+        );
+    }
+    let _ = writeln!(
+        out,
+        "{:<14} {:>79}{}",
+        "total",
+        "",
+        fmt_shocks(&report.total_shock_pnls)
+    );
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_symbol_risk() {
+        let params = ExchangeParams {
+            qty_step: 0.001,
+            price_step: 0.01,
+            min_qty: 0.001,
+            min_cost: 1.0,
+            c_mult: 1.0,
+            inverse: false,
+        };
+        let position = Position {
+            size: 1.0,
+            price: 100.0,
+        };
+        let entry = Order {
+            id: "1".to_string(),
+            symbol: "BTCUSDT".to_string(),
+            side: "Buy".to_string(),
+            position_side: "Long".to_string(),
+            qty: 1.0,
+            price: 80.0,
+            reduce_only: false,
+            custom_id: String::new(),
+            client_order_id: String::new(),
+            time_in_force: String::new(),
+        };
+        let risk = calc_symbol_risk("BTCUSDT", &position, 100.0, 50.0, &[entry], &params, 10.0);
+        // a 1 BTC long from 100 with 50 of balance is wiped out at 50
+        assert_eq!(risk.bankruptcy_price, 50.0);
+        assert_eq!(risk.bankruptcy_distance, Some(0.5));
+        assert_eq!(risk.margin, 10.0);
+        assert_eq!(risk.shock_pnls.len(), PRICE_SHOCKS.len());
+        assert!((risk.shock_pnls[0] + 30.0).abs() < 1e-9);
+        assert!((risk.shock_pnls[5] - 30.0).abs() < 1e-9);
+        // filling the entry doubles the position to 2 @ 90
+        assert!((risk.worst_case_exposure_long - 3.6).abs() < 1e-9);
+        assert_eq!(risk.worst_case_exposure_short, 0.0);
+
+        let flat = calc_symbol_risk(
+            "BTCUSDT",
+            &Position::default(),
+            100.0,
+            50.0,
+            &[],
+            &params,
+            10.0,
+        );
+        assert_eq!(flat.bankruptcy_distance, None);
+        assert!(flat.shock_pnls.iter().all(|&pnl| pnl == 0.0));
+    }
+}
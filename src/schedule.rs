@@ -0,0 +1,248 @@
+use crate::types::{LiveConfig, TradingWindow};
+use chrono::{DateTime, Datelike, Timelike, Utc, Weekday};
+
+/// Length of the candles aligned cycles follow.
+pub const CANDLE_MS: i64 = 60_000;
+
+/// The first 1m candle close after `now_ms`, shifted by `offset_ms`.
+pub fn next_candle_close_ms(now_ms: i64, offset_ms: i64) -> i64 {
+    let offset_ms = offset_ms.rem_euclid(CANDLE_MS);
+    now_ms - (now_ms - offset_ms).rem_euclid(CANDLE_MS) + CANDLE_MS
+}
+
+/// A validated `TradingWindow`.
+#[derive(Debug, Clone, PartialEq)]
+enum Window {
+    /// A daily window in UTC hours, wrapping past midnight if `start_hour > end_hour`.
+    /// With `days`, only windows starting on those weekdays count.
+    Recurring {
+        days: Vec<Weekday>,
+        start_hour: f64,
+        end_hour: f64,
+    },
+    Event {
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    },
+}
+
+impl Window {
+    fn parse(window: &TradingWindow) -> Result<Self, String> {
+        if !window.start.is_empty() || !window.end.is_empty() {
+            let parse = |name: &str, value: &str| {
+                DateTime::parse_from_rfc3339(value)
+                    .map(|dt| dt.with_timezone(&Utc))
+                    .map_err(|e| {
+                        format!("{} \"{}\" is not an RFC 3339 timestamp: {}", name, value, e)
+                    })
+            };
+            if window.start.is_empty() || window.end.is_empty() {
+                return Err("an event window needs both start and end".to_string());
+            }
+            if !window.days.is_empty() {
+                return Err("an event window cannot have days".to_string());
+            }
+            let (start, end) = (parse("start", &window.start)?, parse("end", &window.end)?);
+            if end <= start {
+                return Err(format!(
+                    "end {} is not after start {}",
+                    window.end, window.start
+                ));
+            }
+            return Ok(Window::Event { start, end });
+        }
+        for (name, hour) in [
+            ("start_hour", window.start_hour),
+            ("end_hour", window.end_hour),
+        ] {
+            if !(0.0..=24.0).contains(&hour) {
+                return Err(format!("{} must be between 0 and 24, got {}", name, hour));
+            }
+        }
+        let days = window
+            .days
+            .iter()
+            .map(|day| {
+                day.parse::<Weekday>()
+                    .map_err(|_| format!("unknown day \"{}\"", day))
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Window::Recurring {
+            days,
+            start_hour: window.start_hour,
+            end_hour: window.end_hour,
+        })
+    }
+
+    fn contains(&self, now: DateTime<Utc>) -> bool {
+        match self {
+            Window::Event { start, end } => now >= *start && now < *end,
+            Window::Recurring {
+                days,
+                start_hour,
+                end_hour,
+            } => {
+                let hour = now.hour() as f64 + now.minute() as f64 / 60.0;
+                // the weekday the window containing `now` started on
+                let started_on = if start_hour <= end_hour {
+                    if hour < *start_hour || hour >= *end_hour {
+                        return false;
+                    }
+                    now.weekday()
+                } else if hour >= *start_hour {
+                    now.weekday()
+                } else if hour < *end_hour {
+                    now.weekday().pred()
+                } else {
+                    return false;
+                };
+                days.is_empty() || days.contains(&started_on)
+            }
+        }
+    }
+}
+
+/// The no-entry windows of a config, parsed once.
+#[derive(Debug, Clone, Default)]
+pub struct EntrySchedule {
+    pause_on_weekends: bool,
+    windows: Vec<Window>,
+}
+
+impl EntrySchedule {
+    pub fn from_config(live_config: &LiveConfig) -> Result<Self, String> {
+        let windows = live_config
+            .no_entry_windows
+            .iter()
+            .enumerate()
+            .map(|(i, window)| {
+                Window::parse(window).map_err(|e| format!("live.no_entry_windows[{}]: {}", i, e))
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self {
+            pause_on_weekends: live_config.pause_entries_on_weekends,
+            windows,
+        })
+    }
+
+    /// Returns true if new entries are paused at `now` by any configured window.
+    pub fn entries_paused(&self, now: DateTime<Utc>) -> bool {
+        (self.pause_on_weekends && matches!(now.weekday(), Weekday::Sat | Weekday::Sun))
+            || self.windows.iter().any(|window| window.contains(now))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_next_candle_close() {
+        assert_eq!(next_candle_close_ms(125_000, 2_000), 182_000);
+        // a cycle that starts on time waits for the next candle
+        assert_eq!(next_candle_close_ms(122_000, 2_000), 182_000);
+        assert_eq!(next_candle_close_ms(121_999, 2_000), 122_000);
+        assert_eq!(next_candle_close_ms(120_000, 0), 180_000);
+    }
+
+    fn schedule(windows: serde_json::Value, pause_on_weekends: bool) -> EntrySchedule {
+        let mut live_config = crate::config::load_config("tests/fixtures/backtest_config.hjson")
+            .unwrap()
+            .live;
+        live_config.no_entry_windows = serde_json::from_value(windows).unwrap();
+        live_config.pause_entries_on_weekends = pause_on_weekends;
+        EntrySchedule::from_config(&live_config).unwrap()
+    }
+
+    fn at(time: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(time)
+            .unwrap()
+            .with_timezone(&Utc)
+    }
+
+    #[test]
+    fn test_plain_window() {
+        let schedule = schedule(
+            serde_json::json!([{ "start_hour": 8, "end_hour": 9.5 }]),
+            false,
+        );
+        // 2024-03-06 is a Wednesday
+        assert!(!schedule.entries_paused(at("2024-03-06T07:59:00Z")));
+        assert!(schedule.entries_paused(at("2024-03-06T08:00:00Z")));
+        assert!(schedule.entries_paused(at("2024-03-06T09:29:00Z")));
+        assert!(!schedule.entries_paused(at("2024-03-06T09:30:00Z")));
+    }
+
+    #[test]
+    fn test_window_wrapping_past_midnight() {
+        let schedule = schedule(
+            serde_json::json!([{ "start_hour": 22, "end_hour": 2 }]),
+            false,
+        );
+        assert!(!schedule.entries_paused(at("2024-03-06T21:59:00Z")));
+        assert!(schedule.entries_paused(at("2024-03-06T23:00:00Z")));
+        assert!(schedule.entries_paused(at("2024-03-07T01:59:00Z")));
+        assert!(!schedule.entries_paused(at("2024-03-07T02:00:00Z")));
+    }
+
+    #[test]
+    fn test_days_apply_to_the_day_the_window_started() {
+        let schedule = schedule(
+            serde_json::json!([{ "days": ["Fri"], "start_hour": 22, "end_hour": 2 }]),
+            false,
+        );
+        // Friday 2024-03-08 22:00 to Saturday 02:00
+        assert!(!schedule.entries_paused(at("2024-03-08T01:00:00Z")));
+        assert!(schedule.entries_paused(at("2024-03-08T22:30:00Z")));
+        assert!(schedule.entries_paused(at("2024-03-09T01:00:00Z")));
+        assert!(!schedule.entries_paused(at("2024-03-09T22:30:00Z")));
+        assert!(!schedule.entries_paused(at("2024-03-07T23:00:00Z")));
+    }
+
+    #[test]
+    fn test_weekend_pause() {
+        let schedule = schedule(serde_json::json!([]), true);
+        assert!(!schedule.entries_paused(at("2024-03-08T23:59:00Z")));
+        assert!(schedule.entries_paused(at("2024-03-09T00:00:00Z")));
+        assert!(schedule.entries_paused(at("2024-03-10T23:59:00Z")));
+        assert!(!schedule.entries_paused(at("2024-03-11T00:00:00Z")));
+    }
+
+    #[test]
+    fn test_event_window() {
+        let schedule = schedule(
+            serde_json::json!([{
+                "start": "2024-03-20T17:30:00Z",
+                "end": "2024-03-20T19:00:00+00:00"
+            }]),
+            false,
+        );
+        assert!(!schedule.entries_paused(at("2024-03-20T17:29:00Z")));
+        assert!(schedule.entries_paused(at("2024-03-20T17:30:00Z")));
+        assert!(!schedule.entries_paused(at("2024-03-20T19:00:00Z")));
+        // the same hours on other days are not affected
+        assert!(!schedule.entries_paused(at("2024-03-21T18:00:00Z")));
+    }
+
+    #[test]
+    fn test_invalid_windows_are_rejected() {
+        let mut live_config = crate::config::load_config("tests/fixtures/backtest_config.hjson")
+            .unwrap()
+            .live;
+        for windows in [
+            serde_json::json!([{ "start": "2024-03-20T17:30:00Z" }]),
+            serde_json::json!([{ "end": "2024-03-20T17:30:00Z" }]),
+            serde_json::json!([{ "start": "2024-03-20", "end": "2024-03-21" }]),
+            serde_json::json!([{ "start": "2024-03-21T00:00:00Z", "end": "2024-03-20T00:00:00Z" }]),
+            serde_json::json!([{ "days": ["Fryday"], "start_hour": 1, "end_hour": 2 }]),
+            serde_json::json!([{ "start_hour": 25 }]),
+        ] {
+            live_config.no_entry_windows = serde_json::from_value(windows.clone()).unwrap();
+            assert!(
+                EntrySchedule::from_config(&live_config).is_err(),
+                "{} was accepted",
+                windows
+            );
+        }
+    }
+}
@@ -0,0 +1,270 @@
+//! Account snapshots: the state of a live account exported to JSON, and replayed against
+//! the simulated exchange to reproduce locally what the bot does with that exact state.
+
+use crate::control::StatusBoard;
+use crate::events::EventBus;
+use crate::exchange::intent_log::IntentLog;
+use crate::exchange::quarantine::SymbolQuarantine;
+use crate::exchange::queue::{BatchLimits, OrderQueue};
+use crate::exchange::simulated::SimulatedExchange;
+use crate::exchange::{Exchange, SendSyncError};
+use crate::manager::Manager;
+use crate::types::{BotConfig, ExchangeParams, Order, Position, PositionCosts};
+use clap::{Parser, Subcommand};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tracing::{info, warn};
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum SnapshotCommand {
+    /// Writes the balance, positions and open orders of an account to a JSON file
+    Export(ExportArgs),
+    /// Loads a snapshot into the simulated exchange and runs the bot against it
+    Replay(ReplayArgs),
+}
+
+#[derive(Parser, Debug, Clone)]
+pub struct ExportArgs {
+    /// User/account name defined in api-keys.json
+    #[clap(long)]
+    pub user: String,
+
+    /// Symbols to export, defaults to live.approved_coins
+    #[clap(long, value_delimiter = ',')]
+    pub symbols: Vec<String>,
+
+    /// File to write the snapshot to
+    #[clap(long)]
+    pub output: PathBuf,
+}
+
+#[derive(Parser, Debug, Clone)]
+pub struct ReplayArgs {
+    /// Snapshot file written by `snapshot export`
+    #[clap(long)]
+    pub path: PathBuf,
+
+    /// Symbol to replay, defaults to every symbol in the snapshot
+    #[clap(long)]
+    pub symbol: Option<String>,
+
+    /// Number of bot cycles to run
+    #[clap(long, default_value_t = 1)]
+    pub cycles: usize,
+}
+
+/// State of one symbol of the account.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct SymbolSnapshot {
+    pub position: Position,
+    pub position_costs: PositionCosts,
+    pub last_price: f64,
+    pub exchange_params: ExchangeParams,
+    pub open_orders: Vec<Order>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct AccountSnapshot {
+    pub user: String,
+    pub exchange: String,
+    pub taken_at_ms: i64,
+    pub balance: f64,
+    pub symbols: BTreeMap<String, SymbolSnapshot>,
+}
+
+impl AccountSnapshot {
+    pub fn save(&self, path: &Path) -> Result<(), SendSyncError> {
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    pub fn load(path: &Path) -> Result<Self, SendSyncError> {
+        let content = fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read snapshot {}: {}", path.display(), e))?;
+        Ok(serde_json::from_str(&content)?)
+    }
+}
+
+/// Reads the account state of `symbols` from `exchange`.
+pub async fn export(
+    exchange: &dyn Exchange, user: &str, exchange_name: &str, symbols: &[String],
+) -> Result<AccountSnapshot, SendSyncError> {
+    let mut snapshot = AccountSnapshot {
+        user: user.to_string(),
+        exchange: exchange_name.to_string(),
+        taken_at_ms: exchange.now_ms(),
+        balance: exchange.fetch_balance().await?,
+        symbols: BTreeMap::new(),
+    };
+    for symbol in symbols {
+        info!("[{}] Exporting account state", symbol);
+        let symbol_snapshot = SymbolSnapshot {
+            position: exchange.fetch_positions(symbol).await?.net(),
+            position_costs: exchange.fetch_position_costs(symbol).await?,
+            last_price: exchange.fetch_ticker(symbol).await?,
+            exchange_params: exchange.fetch_exchange_params(symbol).await?,
+            open_orders: exchange.fetch_open_orders(symbol).await?,
+        };
+        snapshot.symbols.insert(symbol.clone(), symbol_snapshot);
+    }
+    Ok(snapshot)
+}
+
+/// Open orders of a symbol before and after replaying bot cycles on its snapshot.
+#[derive(Serialize, Debug, Clone)]
+pub struct SymbolReplay {
+    pub symbol: String,
+    pub position: Position,
+    pub orders_before: Vec<Order>,
+    pub orders_after: Vec<Order>,
+}
+
+/// Runs `cycles` bot cycles for `symbol` against a simulated exchange holding the
+/// snapshot's state. The price stays at the snapshot's last price, so nothing fills and
+/// the result shows which orders the bot would cancel and place.
+pub async fn replay(
+    config: &BotConfig, snapshot: &AccountSnapshot, symbol: &str, cycles: usize,
+) -> Result<SymbolReplay, SendSyncError> {
+    let symbol_snapshot = snapshot
+        .symbols
+        .get(symbol)
+        .ok_or_else(|| format!("Symbol {} is not in the snapshot", symbol))?;
+    let mut config = config.clone();
+    // a replay must not touch the live bot's state files
+    config.live.state_dir = String::new();
+    if config.live.pair_mode.enabled {
+        warn!("live.pair_mode is not simulated: replaying with one net position");
+        config.live.pair_mode.enabled = false;
+    }
+
+    let simulated = SimulatedExchange::new(snapshot.balance);
+    simulated.load_snapshot(symbol, symbol_snapshot);
+    let order_queue = OrderQueue::spawn(
+        simulated.clone_box(),
+        SymbolQuarantine::new(0, Duration::ZERO),
+        IntentLog::default(),
+        EventBus::default(),
+        BatchLimits::default(),
+    );
+    let mut manager = Manager::new(
+        symbol.to_string(),
+        config,
+        simulated.clone_box(),
+        order_queue.clone(),
+        StatusBoard::default(),
+    );
+    for _ in 0..cycles {
+        manager.run_cycle().await?;
+        while order_queue.depth() > 0 {
+            tokio::task::yield_now().await;
+        }
+    }
+
+    Ok(SymbolReplay {
+        symbol: symbol.to_string(),
+        position: symbol_snapshot.position,
+        orders_before: symbol_snapshot.open_orders.clone(),
+        orders_after: simulated.fetch_open_orders(symbol).await?,
+    })
+}
+
+pub fn format_replays(replays: &[SymbolReplay]) -> String {
+    let format_order = |order: &Order| {
+        format!(
+            "{:>4} {} @ {}{}",
+            order.side,
+            order.qty,
+            order.price,
+            if order.reduce_only {
+                " reduce-only"
+            } else {
+                ""
+            }
+        )
+    };
+    let mut out = String::new();
+    for replay in replays {
+        let _ = writeln!(
+            out,
+            "{}: position {} @ {}",
+            replay.symbol, replay.position.size, replay.position.price
+        );
+        let _ = writeln!(out, "  open orders in the snapshot:");
+        for order in &replay.orders_before {
+            let _ = writeln!(out, "    {}", format_order(order));
+        }
+        let _ = writeln!(out, "  open orders after the replay:");
+        for order in &replay.orders_after {
+            let _ = writeln!(out, "    {}", format_order(order));
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config;
+
+    #[tokio::test]
+    async fn test_export_round_trip_and_replay() {
+        let mut config = config::load_config("tests/fixtures/backtest_config.hjson").unwrap();
+        config.bot.long.enabled = false;
+        let account = SimulatedExchange::new(1000.0);
+        account.update_price(100.0, 100.0, 100.0);
+        account.load_snapshot(
+            "BTCUSDT",
+            &SymbolSnapshot {
+                position: Position {
+                    size: -0.5,
+                    price: 98.0,
+                },
+                last_price: 100.0,
+                exchange_params: ExchangeParams {
+                    qty_step: 0.001,
+                    price_step: 0.01,
+                    min_qty: 0.001,
+                    min_cost: 1.0,
+                    c_mult: 1.0,
+                    inverse: false,
+                },
+                open_orders: vec![Order {
+                    id: "stale".to_string(),
+                    symbol: "BTCUSDT".to_string(),
+                    side: "Sell".to_string(),
+                    qty: 0.1,
+                    price: 150.0,
+                    ..Default::default()
+                }],
+                ..Default::default()
+            },
+        );
+
+        let symbols = vec!["BTCUSDT".to_string()];
+        let exported = export(&account, "alice", "bybit", &symbols).await.unwrap();
+        let path = std::env::temp_dir().join(format!("snapshot_test_{}.json", std::process::id()));
+        exported.save(&path).unwrap();
+        let snapshot = AccountSnapshot::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(snapshot.balance, 1000.0);
+        assert_eq!(snapshot.symbols["BTCUSDT"].position.size, -0.5);
+        assert_eq!(snapshot.symbols["BTCUSDT"].open_orders.len(), 1);
+
+        let replay = replay(&config, &snapshot, "BTCUSDT", 1).await.unwrap();
+        assert_eq!(replay.orders_before[0].id, "stale");
+        // the far-off sell is not part of the grid, and the short gets its closes
+        assert!(replay.orders_after.iter().all(|o| o.id != "stale"));
+        assert!(replay
+            .orders_after
+            .iter()
+            .any(|o| o.side == "Buy" && o.reduce_only));
+        assert!(format_replays(&[replay]).contains("BTCUSDT: position -0.5 @ 98"));
+    }
+}
@@ -0,0 +1,243 @@
+//! Soak tests running the live manager loop against the simulated exchange over
+//! randomized price paths, checking account invariants after every cycle.
+
+use crate::config;
+use crate::control::StatusBoard;
+use crate::constants::{LONG, SHORT};
+use crate::events::{BotEvent, EventBus, EventSubscriber};
+use crate::exchange::intent_log::IntentLog;
+use crate::exchange::quarantine::SymbolQuarantine;
+use crate::exchange::queue::{BatchLimits, OrderQueue};
+use crate::exchange::simulated::{SimulatedExchange, CANDLE_INTERVAL_MS};
+use crate::exchange::faulty::FaultInjectingExchange;
+use crate::exchange::Exchange;
+use crate::grid::utils::round_;
+use crate::manager::Manager;
+use crate::types::{EMABands, FaultInjectionConfig, Order, TrailingPriceBundle};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// How much trading a soak run saw, so tests can tell its invariants were exercised.
+#[derive(Debug, Default)]
+struct SoakStats {
+    cycles_in_position: usize,
+    /// Times the position was closed back to flat.
+    n_round_trips: usize,
+}
+
+/// `latency` is the simulated exchange's (ack, cancel) delay in ms, see `set_latency`.
+async fn run_soak(
+    pside: usize, n_cycles: usize, seed: u64, faults: FaultInjectionConfig, latency: (i64, i64),
+) -> SoakStats {
+    let mut config = config::load_config("tests/fixtures/backtest_config.hjson").unwrap();
+    config.bot.long.enabled = pside == LONG;
+    config.bot.short.enabled = pside != LONG;
+    config.live.state_dir = String::new();
+    let side_config = if pside == LONG {
+        config.bot.long.clone()
+    } else {
+        config.bot.short.clone()
+    };
+
+    let simulated = SimulatedExchange::new(config.backtest.starting_balance);
+    let mut price = 100.0;
+    simulated.update_price(price, price, price);
+    simulated.set_latency(latency.0, latency.1);
+    // the bot sees the exchange through the fault injector; invariants are checked on
+    // the simulated exchange itself
+    let faults_enabled = faults.is_enabled();
+    let exchange: Box<dyn Exchange> = if faults_enabled {
+        Box::new(FaultInjectingExchange::new(simulated.clone_box(), faults))
+    } else {
+        simulated.clone_box()
+    };
+    let order_queue = OrderQueue::spawn(
+        exchange.clone_box(),
+        SymbolQuarantine::new(0, Duration::ZERO),
+        IntentLog::default(),
+        EventBus::default(),
+        BatchLimits::default(),
+    );
+    let mut manager = Manager::new(
+        "BTCUSDT".to_string(),
+        config,
+        exchange,
+        order_queue.clone(),
+        StatusBoard::default(),
+    );
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut stats = SoakStats::default();
+    let mut was_in_position = false;
+
+    for cycle in 0..n_cycles {
+        if let Err(e) = manager.run_cycle().await {
+            assert!(faults_enabled, "cycle {}: {}", cycle, e);
+        }
+        while order_queue.depth() > 0 {
+            tokio::task::yield_now().await;
+        }
+
+        let close = round_(price * (1.0 + rng.gen_range(-0.005..0.005)), 0.01);
+        let high = f64::max(price, close) * (1.0 + rng.gen_range(0.0..0.002));
+        let low = f64::min(price, close) * (1.0 - rng.gen_range(0.0..0.002));
+        simulated.advance_candles(1);
+        simulated.update_price(high, low, close);
+        price = close;
+
+        let state = simulated.snapshot();
+        let in_position = state.position.size != 0.0;
+        if in_position {
+            stats.cycles_in_position += 1;
+        } else if was_in_position {
+            stats.n_round_trips += 1;
+        }
+        was_in_position = in_position;
+        assert!(
+            state.balance > 0.0,
+            "cycle {}: balance {}",
+            cycle,
+            state.balance
+        );
+        let wallet_exposure = state.position.size.abs() * state.position.price / state.balance;
+        assert!(
+            wallet_exposure <= side_config.total_wallet_exposure_limit * 1.01,
+            "cycle {}: wallet exposure {} exceeds limit {}",
+            cycle,
+            wallet_exposure,
+            side_config.total_wallet_exposure_limit
+        );
+        assert!(
+            state.rejected_orders.is_empty(),
+            "cycle {}: invalid orders {:?}",
+            cycle,
+            state.rejected_orders
+        );
+    }
+    stats
+}
+
+#[tokio::test]
+async fn test_soak_long() {
+    let mut stats = SoakStats::default();
+    for seed in 1..=4 {
+        let run = run_soak(LONG, 300, seed, FaultInjectionConfig::default(), (0, 0)).await;
+        stats.cycles_in_position += run.cycles_in_position;
+        stats.n_round_trips += run.n_round_trips;
+    }
+    // the invariants held while positions were opened and closed
+    assert!(stats.cycles_in_position > 0, "{:?}", stats);
+    assert!(stats.n_round_trips > 0, "{:?}", stats);
+}
+
+#[tokio::test]
+async fn test_soak_short() {
+    run_soak(SHORT, 300, 2, FaultInjectionConfig::default(), (0, 0)).await;
+}
+
+#[tokio::test]
+async fn test_soak_short_with_faults() {
+    let faults = FaultInjectionConfig {
+        timeout_prob: 0.02,
+        rate_limit_prob: 0.02,
+        stale_prob: 0.05,
+        partial_prob: 0.02,
+        timeout_seconds: 0.0,
+        seed: 3,
+    };
+    run_soak(SHORT, 300, 3, faults, (0, 0)).await;
+}
+
+// Orders and cancels only reach the book after the bot's next cycle, which reconciles
+// against a book that does not show its previous requests yet.
+#[tokio::test]
+async fn test_soak_short_with_latency() {
+    run_soak(
+        SHORT,
+        300,
+        4,
+        FaultInjectionConfig::default(),
+        (CANDLE_INTERVAL_MS + 1_000, CANDLE_INTERVAL_MS + 1_000),
+    )
+    .await;
+}
+
+/// Thousands of cycles over several seeds; run with `cargo test -- --ignored`.
+#[tokio::test]
+#[ignore]
+async fn test_soak_extended() {
+    for seed in 0..5 {
+        run_soak(LONG, 5000, seed, FaultInjectionConfig::default(), (0, 0)).await;
+        run_soak(SHORT, 5000, seed, FaultInjectionConfig::default(), (0, 0)).await;
+    }
+}
+
+struct IntendedRecorder(Arc<Mutex<Vec<Order>>>);
+
+impl EventSubscriber for IntendedRecorder {
+    fn on_event(&mut self, event: &BotEvent) {
+        if let BotEvent::OrdersIntended { to_create, .. } = event {
+            self.0.lock().unwrap().extend(to_create.iter().cloned());
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_read_only_publishes_orders_without_placing_them() {
+    let mut config = config::load_config("tests/fixtures/backtest_config.hjson").unwrap();
+    config.bot.long.enabled = false;
+    config.bot.short.enabled = true;
+    config.live.state_dir = String::new();
+    config.live.read_only = true;
+    let simulated = SimulatedExchange::new(config.backtest.starting_balance);
+    simulated.update_price(100.0, 100.0, 100.0);
+    let events = EventBus::default();
+    let intended = Arc::default();
+    events.subscribe(IntendedRecorder(Arc::clone(&intended)));
+    let order_queue = OrderQueue::spawn(
+        simulated.clone_box(),
+        SymbolQuarantine::new(0, Duration::ZERO),
+        IntentLog::default(),
+        events.clone(),
+        BatchLimits::default(),
+    );
+    let mut manager = Manager::new(
+        "BTCUSDT".to_string(),
+        config,
+        simulated.clone_box(),
+        order_queue.clone(),
+        StatusBoard::default(),
+    )
+    .with_events(events);
+    let bands = EMABands {
+        upper: 100.0,
+        lower: 100.0,
+    };
+    manager.set_indicators(bands, 0.0, TrailingPriceBundle::default());
+
+    for _ in 0..5 {
+        manager.run_cycle().await.unwrap();
+        simulated.advance_candles(1);
+        simulated.update_price(100.0, 100.0, 100.0);
+    }
+    for _ in 0..10 {
+        tokio::task::yield_now().await;
+    }
+
+    let state = simulated.snapshot();
+    assert!(state.orders.is_empty());
+    assert_eq!(state.position.size, 0.0);
+    // the grid is published once, not again every cycle it stays the same
+    let intended = intended.lock().unwrap();
+    assert!(!intended.is_empty());
+    assert!(intended.iter().all(|o| o.side == "Sell"));
+    assert_eq!(
+        intended.len(),
+        intended
+            .iter()
+            .map(|o| (o.price.to_bits(), o.qty.to_bits()))
+            .collect::<std::collections::HashSet<_>>()
+            .len()
+    );
+}
@@ -0,0 +1,162 @@
+use crate::risk_governor::RiskGovernor;
+use crate::types::FundingPayment;
+use crate::exchange::SendSyncError;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+use tracing::warn;
+
+const MS_PER_DAY: f64 = 24.0 * 60.0 * 60.0 * 1000.0;
+
+/// Per-symbol bot state that has to survive restarts, stored at
+/// `Paths::symbol_state`.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+pub struct SymbolState {
+    /// When the current position was opened (ms), 0 when flat.
+    pub position_opened_at_ms: i64,
+    /// Side the open timestamp refers to: 1.0 for long, -1.0 for short.
+    pub position_side: f64,
+    /// The current position was opened outside the bot and has been adopted.
+    #[serde(default)]
+    pub adopted: bool,
+    /// The current position was opened outside the bot and awaits confirmation before
+    /// the bot manages it.
+    #[serde(default)]
+    pub adoption_pending: bool,
+    /// Equity peak the risk governor measures drawdown from.
+    #[serde(default)]
+    pub risk_governor: RiskGovernor,
+    /// Funding paid by the current position since it was opened, in quote currency.
+    #[serde(default)]
+    pub funding_paid: f64,
+    /// Funding payments up to this time (ms) are included in `funding_paid`.
+    #[serde(default)]
+    pub funding_checked_until_ms: i64,
+    /// The funding alert for the current position has been raised.
+    #[serde(default)]
+    pub funding_alerted: bool,
+}
+
+impl SymbolState {
+    /// Loads the state from `path`, falling back to an empty state if the file is
+    /// missing or unreadable.
+    pub fn load(path: &Path) -> Self {
+        match fs::read_to_string(path) {
+            Ok(content) => serde_json::from_str(&content).unwrap_or_else(|e| {
+                warn!("Ignoring unreadable state file {}: {}", path.display(), e);
+                Self::default()
+            }),
+            Err(_) => Self::default(),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), SendSyncError> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// Updates the open timestamp from the latest position size: a position appearing or
+    /// flipping sides starts a new age, a flat position clears it. Returns true if the
+    /// state changed.
+    pub fn update_position(&mut self, position_size: f64, now_ms: i64) -> bool {
+        let side = if position_size > 0.0 {
+            1.0
+        } else if position_size < 0.0 {
+            -1.0
+        } else {
+            0.0
+        };
+        if side == self.position_side && (side == 0.0 || self.position_opened_at_ms != 0) {
+            return false;
+        }
+        self.position_side = side;
+        self.position_opened_at_ms = if side == 0.0 { 0 } else { now_ms };
+        self.adopted = false;
+        self.adoption_pending = false;
+        self.funding_paid = 0.0;
+        self.funding_checked_until_ms = self.position_opened_at_ms;
+        self.funding_alerted = false;
+        true
+    }
+
+    /// Adds the payments settled after the last check and up to `until_ms` to the
+    /// position's funding. Returns the amount added.
+    pub fn accrue_funding(&mut self, payments: &[FundingPayment], until_ms: i64) -> f64 {
+        let added: f64 = payments
+            .iter()
+            .filter(|p| {
+                p.timestamp_ms > self.funding_checked_until_ms && p.timestamp_ms <= until_ms
+            })
+            .map(|p| p.amount)
+            .sum();
+        self.funding_paid += added;
+        self.funding_checked_until_ms = self.funding_checked_until_ms.max(until_ms);
+        added
+    }
+
+    /// Returns true the first time the position's funding paid reaches `threshold`.
+    pub fn check_funding_alert(&mut self, threshold: f64) -> bool {
+        if threshold <= 0.0 || self.funding_alerted || self.funding_paid < threshold {
+            return false;
+        }
+        self.funding_alerted = true;
+        true
+    }
+
+    pub fn position_age_days(&self, now_ms: i64) -> f64 {
+        if self.position_opened_at_ms == 0 {
+            return 0.0;
+        }
+        (now_ms - self.position_opened_at_ms) as f64 / MS_PER_DAY
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_position_open_time_tracking() {
+        let mut state = SymbolState::default();
+        assert!(!state.update_position(0.0, 1_000));
+        assert!(state.update_position(1.0, 2_000));
+        assert!(!state.update_position(2.5, 3_000));
+        assert_eq!(state.position_opened_at_ms, 2_000);
+        assert_eq!(state.position_age_days(2_000 + MS_PER_DAY as i64), 1.0);
+
+        // flipping sides starts a new position
+        assert!(state.update_position(-1.0, 4_000));
+        assert_eq!(state.position_opened_at_ms, 4_000);
+
+        assert!(state.update_position(0.0, 5_000));
+        assert_eq!(state.position_age_days(6_000), 0.0);
+    }
+
+    #[test]
+    fn test_funding_accrual() {
+        let payment = |timestamp_ms: i64, amount: f64| FundingPayment {
+            symbol: "BTCUSDT".to_string(),
+            timestamp_ms,
+            amount,
+        };
+        let mut state = SymbolState::default();
+        state.update_position(1.0, 1_000);
+        let payments = [payment(500, 9.0), payment(2_000, 3.0), payment(3_000, -1.0)];
+        assert_eq!(state.accrue_funding(&payments, 2_500), 3.0);
+        // already counted payments are skipped when the next check overlaps
+        assert_eq!(state.accrue_funding(&payments, 4_000), -1.0);
+        assert_eq!(state.funding_paid, 2.0);
+        assert!(!state.check_funding_alert(5.0));
+        state.accrue_funding(&[payment(5_000, 4.0)], 5_000);
+        assert!(state.check_funding_alert(5.0));
+        assert!(!state.check_funding_alert(5.0));
+
+        // a new position starts from zero
+        state.update_position(-1.0, 6_000);
+        assert_eq!(state.funding_paid, 0.0);
+        assert_eq!(state.funding_checked_until_ms, 6_000);
+    }
+}
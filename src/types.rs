@@ -1,8 +1,9 @@
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::fmt;
+use crate::constants::LONG;
 
-#[derive(Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct BotConfig {
     pub live: LiveConfig,
     pub bot: SideConfigs,
@@ -10,7 +11,30 @@ pub struct BotConfig {
     pub backtest: BacktestConfig,
 }
 
-#[derive(Deserialize, Debug, Clone)]
+impl BotConfig {
+    /// Resolves the effective trading mode for a side (`LONG` or `SHORT`).
+    ///
+    /// A `forced_mode_*` in the live config, checked by `LiveConfig::validate`, takes
+    /// precedence. Otherwise a disabled side falls back to `TakeProfitOnly`, so existing
+    /// positions are still closed.
+    pub fn side_mode(&self, pside: usize) -> TradingMode {
+        let (side_config, forced_mode) = if pside == LONG {
+            (&self.bot.long, &self.live.forced_mode_long)
+        } else {
+            (&self.bot.short, &self.live.forced_mode_short)
+        };
+        if let Some(mode) = TradingMode::from_str(forced_mode) {
+            return mode;
+        }
+        if side_config.enabled {
+            TradingMode::Normal
+        } else {
+            TradingMode::TakeProfitOnly
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct LiveConfig {
     pub exchange: String,
     #[serde(default)]
@@ -22,6 +46,22 @@ pub struct LiveConfig {
     #[serde(default)]
     pub empty_means_all_approved: bool,
     pub execution_delay_seconds: f64,
+    /// Runs each cycle just after a 1m candle closes instead of `execution_delay_seconds`
+    /// after the previous one, so live cycles line up with backtest steps.
+    #[serde(default)]
+    pub align_to_candle_close: bool,
+    /// Seconds after the candle close the aligned cycle starts, leaving the exchange time
+    /// to publish the candle.
+    #[serde(default = "default_candle_close_offset_seconds")]
+    pub candle_close_offset_seconds: f64,
+    /// Seconds between checks of the nearest take-profit close in between full cycles;
+    /// 0 disables them.
+    #[serde(default)]
+    pub close_refresh_interval_seconds: f64,
+    /// Relative move of the mid price, since closes were last priced, at which the nearest
+    /// take-profit close is re-priced in between full cycles.
+    #[serde(default = "default_close_refresh_threshold")]
+    pub close_refresh_threshold: f64,
     #[serde(default)]
     pub filter_by_min_effective_cost: bool,
     #[serde(default)]
@@ -31,10 +71,21 @@ pub struct LiveConfig {
     #[serde(default)]
     pub ignored_coins: Vec<String>,
     pub leverage: f64,
+    /// How traded symbols are margined, set on the exchange with `leverage` when their
+    /// manager starts: "cross" or "isolated". Empty, the default, leaves the account's
+    /// setting.
+    #[serde(default)]
+    pub margin_mode: String,
+    /// Share of a position's value the exchange requires as maintenance margin, for the
+    /// margin usage in the status; the base tier of most exchanges.
+    #[serde(default = "default_maintenance_margin_rate")]
+    pub maintenance_margin_rate: f64,
     #[serde(default)]
     pub max_n_cancellations_per_batch: i32,
     #[serde(default)]
     pub max_n_creations_per_batch: i32,
+    /// Restarts within a day past which the bot starts in safe mode, gracefully stopping
+    /// every side that would trade normally; 0 allows any number.
     #[serde(default)]
     pub max_n_restarts_per_day: i32,
     pub minimum_coin_age_days: f64,
@@ -48,21 +99,543 @@ pub struct LiveConfig {
     pub price_distance_threshold: f64,
     #[serde(default)]
     pub time_in_force: String,
+    #[serde(default)]
+    pub no_entry_windows: Vec<TradingWindow>,
+    #[serde(default)]
+    pub pause_entries_on_weekends: bool,
+    #[serde(default = "default_quarantine_error_threshold")]
+    pub quarantine_error_threshold: u32,
+    #[serde(default = "default_quarantine_minutes")]
+    pub quarantine_minutes: f64,
+    /// Maximum (ask - bid) / mid before the order book is considered unusable.
+    #[serde(default = "default_max_book_spread")]
+    pub max_book_spread: f64,
+    #[serde(default = "default_max_book_age_seconds")]
+    pub max_book_age_seconds: f64,
+    /// Maximum relative divergence between the ticker's last price, the order book mid and
+    /// the latest candle close before the market data is considered suspect; 0 disables
+    /// the check.
+    #[serde(default)]
+    pub max_price_divergence: f64,
+    /// Directory for the state kept across restarts, with one subdirectory per user, see
+    /// `Paths`; empty disables persistence.
+    #[serde(default = "default_state_dir")]
+    pub state_dir: String,
+    /// Local port of the control API used by `status`; 0 disables it.
+    #[serde(default)]
+    pub control_port: u16,
+    /// Set from `--user` when running live.
+    #[serde(default)]
+    pub user: String,
+    /// Markets and exchange params are cached on disk for this long, see
+    /// `exchange::market_cache`; 0 disables the cache.
+    #[serde(default = "default_market_cache_ttl_hours")]
+    pub market_cache_ttl_hours: f64,
+    /// Sizes positions on the usable value of all collateral assets, after the exchange's
+    /// haircuts, instead of the quote balance alone; for unified or multi-asset accounts.
+    #[serde(default)]
+    pub cross_collateral: bool,
+    /// What happens at startup when the balance cannot fund `n_positions` grids of
+    /// minimum-size entries: "refuse", "reduce" or "off". See `capital::preflight`.
+    #[serde(default = "default_capital_check")]
+    pub capital_check: String,
+    /// Randomly injected exchange failures, for resilience testing only.
+    #[serde(default)]
+    pub fault_injection: FaultInjectionConfig,
+    #[serde(default)]
+    pub allocation_groups: Vec<AllocationGroup>,
+    /// How positions found at startup that the bot did not open are handled: "auto",
+    /// "confirm" or "off". See `PositionAdoption`.
+    #[serde(default = "default_position_adoption")]
+    pub position_adoption: String,
+    /// How grid orders at the top of the book are priced: "grid", "join" or "improve".
+    /// Live only; backtests have no spread.
+    #[serde(default = "default_book_price_mode")]
+    pub book_price_mode: String,
+    /// Symbols whose unknown positions may be adopted when `position_adoption` is "confirm".
+    #[serde(default)]
+    pub confirmed_adoptions: Vec<String>,
+    #[serde(default)]
+    pub risk_governor: RiskGovernorConfig,
+    /// Warns once a position has paid this much funding, in quote currency; 0 disables.
+    #[serde(default)]
+    pub funding_alert_threshold: f64,
+    /// Maximum difference between the unrealized PnL the bot computes for a position and
+    /// the one the exchange reports, as a fraction of the position's value, before a
+    /// warning; 0 disables the check.
+    #[serde(default)]
+    pub max_upnl_divergence: f64,
+    #[serde(default)]
+    pub venues: VenueConfig,
+    #[serde(default)]
+    pub websocket: WebSocketConfig,
+    #[serde(default)]
+    pub order_jitter: OrderJitterConfig,
+    #[serde(default)]
+    pub pair_mode: PairModeConfig,
+    #[serde(default)]
+    pub performance_alerts: PerformanceAlertConfig,
+    #[serde(default)]
+    pub rate_limit: RateLimitConfig,
+    #[serde(default)]
+    pub retry: RetryConfig,
+    /// Computes and publishes the orders the bot would place without ever placing or
+    /// cancelling one.
+    #[serde(default)]
+    pub read_only: bool,
+    #[serde(default)]
+    pub notifications: NotificationConfig,
+    /// Minutes between snapshots of the account's balance, equity and exposure in the fill
+    /// journal, which keep its equity curve going while nothing fills; 0 disables them.
+    #[serde(default = "default_equity_snapshot_interval_minutes")]
+    pub equity_snapshot_interval_minutes: f64,
+}
+
+/// Wording and language of the bot's notification messages. See `notifications`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct NotificationConfig {
+    /// Loads `<templates_dir>/<language>.hjson` over the built-in English templates, e.g.
+    /// "de"; empty keeps English.
+    #[serde(default)]
+    pub language: String,
+    #[serde(default = "default_templates_dir")]
+    pub templates_dir: String,
+    /// Templates by message name, taking precedence over the language file's.
+    #[serde(default)]
+    pub templates: HashMap<String, String>,
+}
+
+impl Default for NotificationConfig {
+    fn default() -> Self {
+        Self {
+            language: String::new(),
+            templates_dir: default_templates_dir(),
+            templates: HashMap::new(),
+        }
+    }
+}
+
+/// Users whose bots, possibly on different exchanges, share one exposure budget.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AllocationGroup {
+    pub name: String,
+    pub users: Vec<String>,
+    /// Maximum combined position value of all users, in quote currency. New entries are
+    /// paused while the group is at or above it.
+    pub max_exposure: f64,
+    /// Exposure reports older than this are ignored.
+    #[serde(default = "default_max_report_age_seconds")]
+    pub max_report_age_seconds: f64,
+}
+
+/// Drawdown-aware scaling of every side's `total_wallet_exposure_limit`. The scale falls
+/// linearly from 1 at the equity peak to `min_exposure_scale` at `max_drawdown`, and
+/// rises again as equity recovers.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RiskGovernorConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Drawdown from the equity peak, as a fraction, at which scaling bottoms out.
+    #[serde(default = "default_governor_max_drawdown")]
+    pub max_drawdown: f64,
+    #[serde(default = "default_min_exposure_scale")]
+    pub min_exposure_scale: f64,
+}
+
+/// Trading each coin on whichever of several exchange accounts offers the best conditions.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct VenueConfig {
+    /// Users from api-keys.json whose exchanges compete with `live.user`'s for each coin.
+    /// Empty trades on `live.user`'s exchange only.
+    #[serde(default)]
+    pub users: Vec<String>,
+    /// Maximum combined position value across all venues, in quote currency; 0 for no cap.
+    #[serde(default)]
+    pub max_exposure: f64,
+    /// Order book value, in quote currency, within 1% of the mid price below which a venue
+    /// is passed over while another venue offers more.
+    #[serde(default)]
+    pub min_depth: f64,
+    /// Funding intervals a position is expected to be held, weighing funding against fees.
+    #[serde(default = "default_venue_funding_periods")]
+    pub funding_periods: f64,
+}
+
+/// Streaming order books, tickers and fills over WebSocket instead of polling REST, for
+/// the exchanges `exchange::ws` has a client for.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct WebSocketConfig {
+    /// With streaming, the nearest take-profit close is re-priced on every order book
+    /// update rather than every `close_refresh_interval_seconds`, unless that is 0.
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default = "default_ws_ping_interval_seconds")]
+    pub ping_interval_seconds: f64,
+    /// A connection that receives nothing, not even a pong, for this long is reconnected.
+    #[serde(default = "default_ws_idle_timeout_seconds")]
+    pub idle_timeout_seconds: f64,
+    /// Reconnect delays double from 1s up to this.
+    #[serde(default = "default_ws_max_reconnect_delay_seconds")]
+    pub max_reconnect_delay_seconds: f64,
+}
+
+fn default_ws_ping_interval_seconds() -> f64 {
+    20.0
+}
+
+fn default_ws_idle_timeout_seconds() -> f64 {
+    60.0
+}
+
+fn default_ws_max_reconnect_delay_seconds() -> f64 {
+    60.0
+}
+
+impl Default for WebSocketConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            ping_interval_seconds: default_ws_ping_interval_seconds(),
+            idle_timeout_seconds: default_ws_idle_timeout_seconds(),
+            max_reconnect_delay_seconds: default_ws_max_reconnect_delay_seconds(),
+        }
+    }
+}
+
+/// Small random variations of grid order sizes and prices, so ladders on the book look
+/// less machine-regular. See `grid::jitter`.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct OrderJitterConfig {
+    /// Each order's qty is reduced by a random fraction of up to this, e.g. 0.03 for up to
+    /// 3%. Quantities never grow, so entries stay within the wallet exposure limit and
+    /// closes within the position; 0 disables.
+    #[serde(default)]
+    pub qty: f64,
+    /// Moves each order one tick up or down at random, unless it would cross the book.
+    #[serde(default)]
+    pub price_tick: bool,
+    /// Backtests ignore the jitter unless this is set.
+    #[serde(default)]
+    pub in_backtests: bool,
+    /// 0 draws a random seed at startup; backtests use the seed as is.
+    #[serde(default)]
+    pub seed: u64,
+}
+
+impl OrderJitterConfig {
+    pub fn is_enabled(&self) -> bool {
+        self.qty > 0.0 || self.price_tick
+    }
+}
+
+/// Baseline the live ADG and Sharpe ratio, rolled over 7 and 30 days from the fill
+/// journal, are compared against. See `performance`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PerformanceAlertConfig {
+    /// Average daily gain of the config's backtest; 0 disables the ADG alert.
+    #[serde(default)]
+    pub baseline_adg: f64,
+    /// Sharpe ratio of the config's backtest; 0 disables the Sharpe alert.
+    #[serde(default)]
+    pub baseline_sharpe: f64,
+    /// Alerts once a live metric falls this fraction of the baseline below it, e.g. 0.5
+    /// when it is less than half the baseline.
+    #[serde(default = "default_performance_tolerance")]
+    pub tolerance: f64,
+}
+
+fn default_performance_tolerance() -> f64 {
+    0.5
+}
+
+impl Default for PerformanceAlertConfig {
+    fn default() -> Self {
+        PerformanceAlertConfig {
+            baseline_adg: 0.0,
+            baseline_sharpe: 0.0,
+            tolerance: default_performance_tolerance(),
+        }
+    }
+}
+
+/// Hedged long and short grids on every symbol at once, in the exchange's hedge mode, so
+/// grid profits are harvested on both sides while the net position stays small.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PairModeConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Entries of the side whose wallet exposure exceeds the other side's by this much
+    /// pause until the imbalance shrinks.
+    #[serde(default = "default_pair_max_imbalance")]
+    pub max_imbalance: f64,
+}
+
+fn default_pair_max_imbalance() -> f64 {
+    0.1
+}
+
+impl Default for PairModeConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_imbalance: default_pair_max_imbalance(),
+        }
+    }
+}
+
+fn default_venue_funding_periods() -> f64 {
+    3.0
+}
+
+impl Default for VenueConfig {
+    fn default() -> Self {
+        Self {
+            users: Vec::new(),
+            max_exposure: 0.0,
+            min_depth: 0.0,
+            funding_periods: default_venue_funding_periods(),
+        }
+    }
 }
 
-#[derive(Deserialize, Debug, Clone)]
+impl Default for RiskGovernorConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_drawdown: default_governor_max_drawdown(),
+            min_exposure_scale: default_min_exposure_scale(),
+        }
+    }
+}
+
+/// Client-side request budget of each exchange, see `exchange::rate_limit`. Zero capacity
+/// or window uses the exchange's published limit.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RateLimitConfig {
+    /// Request weight allowed per `window_seconds`.
+    #[serde(default)]
+    pub capacity: f64,
+    #[serde(default)]
+    pub window_seconds: f64,
+    /// Weight of requests by URL path prefix, e.g. `{"/fapi/v1/depth": 10}`, replacing the
+    /// exchange's default for that path. Other requests weigh 1.
+    #[serde(default)]
+    pub weights: HashMap<String, f64>,
+    /// Pause of all requests after a 429 or 418 without a Retry-After header, doubled on
+    /// every consecutive one up to `max_backoff_seconds`.
+    #[serde(default = "default_rate_limit_backoff_seconds")]
+    pub backoff_seconds: f64,
+    #[serde(default = "default_rate_limit_max_backoff_seconds")]
+    pub max_backoff_seconds: f64,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self {
+            capacity: 0.0,
+            window_seconds: 0.0,
+            weights: HashMap::new(),
+            backoff_seconds: default_rate_limit_backoff_seconds(),
+            max_backoff_seconds: default_rate_limit_max_backoff_seconds(),
+        }
+    }
+}
+
+/// Retries of exchange reads failing with a network, rate limit or maintenance error, see
+/// `exchange::retry`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RetryConfig {
+    /// Attempts per request, the first included; 1 disables retries.
+    #[serde(default = "default_retry_max_attempts")]
+    pub max_attempts: u32,
+    /// Pause before the first retry, doubled on every further one up to
+    /// `max_delay_seconds`.
+    #[serde(default = "default_retry_base_delay_seconds")]
+    pub base_delay_seconds: f64,
+    #[serde(default = "default_retry_max_delay_seconds")]
+    pub max_delay_seconds: f64,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        Self {
+            max_attempts: default_retry_max_attempts(),
+            base_delay_seconds: default_retry_base_delay_seconds(),
+            max_delay_seconds: default_retry_max_delay_seconds(),
+        }
+    }
+}
+
+/// Probabilities, per request, of injected exchange failures.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct FaultInjectionConfig {
+    #[serde(default)]
+    pub timeout_prob: f64,
+    #[serde(default)]
+    pub rate_limit_prob: f64,
+    #[serde(default)]
+    pub stale_prob: f64,
+    #[serde(default)]
+    pub partial_prob: f64,
+    #[serde(default)]
+    pub timeout_seconds: f64,
+    #[serde(default)]
+    pub seed: u64,
+}
+
+impl FaultInjectionConfig {
+    pub fn is_enabled(&self) -> bool {
+        self.timeout_prob > 0.0
+            || self.rate_limit_prob > 0.0
+            || self.stale_prob > 0.0
+            || self.partial_prob > 0.0
+    }
+}
+
+fn default_quarantine_error_threshold() -> u32 {
+    3
+}
+
+fn default_quarantine_minutes() -> f64 {
+    60.0
+}
+
+fn default_max_book_spread() -> f64 {
+    0.02
+}
+
+fn default_close_refresh_threshold() -> f64 {
+    0.002
+}
+
+fn default_max_book_age_seconds() -> f64 {
+    10.0
+}
+
+fn default_position_adoption() -> String {
+    "auto".to_string()
+}
+
+fn default_capital_check() -> String {
+    "refuse".to_string()
+}
+
+fn default_maintenance_margin_rate() -> f64 {
+    0.005
+}
+
+fn default_candle_close_offset_seconds() -> f64 {
+    2.0
+}
+
+fn default_book_price_mode() -> String {
+    "grid".to_string()
+}
+
+pub fn default_max_report_age_seconds() -> f64 {
+    300.0
+}
+
+fn default_governor_max_drawdown() -> f64 {
+    0.2
+}
+
+fn default_min_exposure_scale() -> f64 {
+    0.5
+}
+
+fn default_equity_snapshot_interval_minutes() -> f64 {
+    60.0
+}
+
+fn default_templates_dir() -> String {
+    "templates".to_string()
+}
+
+fn default_state_dir() -> String {
+    "~/.passivbot-rs".to_string()
+}
+
+fn default_market_cache_ttl_hours() -> f64 {
+    24.0
+}
+
+fn default_rate_limit_backoff_seconds() -> f64 {
+    5.0
+}
+
+fn default_rate_limit_max_backoff_seconds() -> f64 {
+    300.0
+}
+
+fn default_retry_max_attempts() -> u32 {
+    3
+}
+
+fn default_retry_base_delay_seconds() -> f64 {
+    1.0
+}
+
+fn default_retry_max_delay_seconds() -> f64 {
+    30.0
+}
+
+fn default_end_hour() -> f64 {
+    24.0
+}
+
+/// A period during which no new entries are made; exits are still managed.
+///
+/// Either a recurring window (`days` + `start_hour`/`end_hour` in UTC, wrapping past
+/// midnight if `start_hour > end_hour`) or a one-off event window (`start` and `end` as
+/// RFC 3339 timestamps). `days` are the weekdays a recurring window starts on, so a
+/// Friday 22 to 2 window ends on Saturday.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TradingWindow {
+    #[serde(default)]
+    pub days: Vec<String>,
+    #[serde(default)]
+    pub start_hour: f64,
+    #[serde(default = "default_end_hour")]
+    pub end_hour: f64,
+    #[serde(default)]
+    pub start: String,
+    #[serde(default)]
+    pub end: String,
+}
+
+impl LiveConfig {
+    /// Checks the settings parsed from strings, so a bad value stops the bot at startup.
+    pub fn validate(&self) -> Result<(), String> {
+        crate::schedule::EntrySchedule::from_config(self)?;
+        for (name, value) in [
+            ("forced_mode_long", &self.forced_mode_long),
+            ("forced_mode_short", &self.forced_mode_short),
+        ] {
+            if !value.is_empty() && TradingMode::from_str(value).is_none() {
+                return Err(format!(
+                    "live.{} must be \"normal\", \"manual\", \"graceful_stop\" or \"tp_only\", got \"{}\"",
+                    name, value
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct SideConfigs {
     pub long: BotSideConfig,
     pub short: BotSideConfig,
 }
 
-#[derive(Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct OptimizeInRange {
     pub start: f64,
     pub end: f64,
 }
 
-#[derive(Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct OptimizerConfig {
     pub n_generations: i32,
     pub population_size: i32,
@@ -74,23 +647,83 @@ pub struct OptimizerConfig {
     pub short: HashMap<String, OptimizeInRange>,
     #[serde(default)]
     pub compress_results_file: bool,
-    #[serde(default)]
+    /// Probability that a pair of parents is recombined.
+    #[serde(default = "default_crossover_probability")]
     pub crossover_probability: f64,
+    /// Bounds the backtest analysis must respect, by metric: upper bounds on metrics to
+    /// minimize, e.g. `drawdown_worst: 0.5`, lower bounds on those to maximize. Violating
+    /// individuals rank below every feasible one. See `optimizer::Limit`.
     #[serde(default)]
     pub limits: HashMap<String, f64>,
+    /// Per-parameter mutation probability; 0 uses 1 / number of optimized parameters.
     #[serde(default)]
     pub mutation_probability: f64,
+    /// Decays the mutation probability linearly over the generations, down to a tenth of
+    /// its starting value, trading exploration early for refinement late.
+    #[serde(default)]
+    pub adaptive_mutation: bool,
+    /// 2 to 4 metrics of the backtest analysis to optimize, e.g. "adg" or
+    /// "drawdown_worst", each optionally weighted as "adg:2"; empty for sharpe_ratio and
+    /// drawdown_worst. See `optimizer::Objective`.
     #[serde(default)]
     pub scoring: Vec<String>,
+    #[serde(default)]
+    pub early_stopping_generations: i32,
+    #[serde(default)]
+    pub early_stopping_epsilon: f64,
+    #[serde(default)]
+    pub diversity_weight: f64,
+    /// Margin added on both sides of the Pareto front's 5th-95th percentile range when a
+    /// refine run tightens the bounds, as a fraction of the original bounds' width.
+    #[serde(default = "default_refine_margin")]
+    pub refine_margin: f64,
+    /// Population of a refine run, as a fraction of `population_size`.
+    #[serde(default = "default_refine_population_scale")]
+    pub refine_population_scale: f64,
+    /// Seed of the optimizer's random generator, so a run can be repeated; 0 draws one,
+    /// which is logged and written to `pareto.json`.
+    #[serde(default)]
+    pub seed: u64,
+}
+
+fn default_refine_margin() -> f64 {
+    0.05
+}
+
+fn default_refine_population_scale() -> f64 {
+    0.5
 }
 
-#[derive(Deserialize, Debug, Clone)]
+impl OptimizerConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        for (name, value) in [
+            ("crossover_probability", self.crossover_probability),
+            ("mutation_probability", self.mutation_probability),
+        ] {
+            if !(0.0..=1.0).contains(&value) {
+                return Err(format!(
+                    "optimizer.{} must be between 0 and 1, got {}",
+                    name, value
+                ));
+            }
+        }
+        crate::optimizer::parse_scoring(&self.scoring)?;
+        crate::optimizer::parse_limits(&self.limits)?;
+        Ok(())
+    }
+}
+
+fn default_crossover_probability() -> f64 {
+    0.9
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct ExchangeConfig {
     #[serde(default)]
     pub spot: bool,
 }
 
-#[derive(Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct BacktestConfig {
     pub symbols: HashMap<String, Vec<String>>,
     #[serde(default)]
@@ -105,32 +738,250 @@ pub struct BacktestConfig {
     pub start_date: String,
     #[serde(default)]
     pub starting_balance: f64,
+    #[serde(default)]
+    pub candle_chunk_size: usize,
+    #[serde(default)]
+    pub maker_fee: f64,
+    /// Fee charged on fills of orders priced through the market when placed, which would
+    /// have filled as takers live.
+    #[serde(default)]
+    pub taker_fee: f64,
+    /// Adverse price move of those taker fills, as a fraction of the order price; see the
+    /// `calibrate` command.
+    #[serde(default)]
+    pub taker_slippage: f64,
+    /// Slippage of taker fills that depends on the candle they fill in, on top of
+    /// `taker_slippage`.
+    #[serde(default)]
+    pub slippage: SlippageConfig,
+    /// Fills unstuck closes as market orders: at the price when placed, as takers, on the
+    /// next step whether or not the candle reaches their limit price.
+    #[serde(default)]
+    pub market_unstuck_closes: bool,
+    /// Candle resolution the simulation steps through, "1m" (the default) or finer such as
+    /// "1s" or "5s", read from `<base_dir>/<symbol>_<resolution>.csv`. Finer steps evaluate
+    /// trailing settings more accurately at the cost of speed.
+    #[serde(default = "default_backtest_resolution")]
+    pub resolution: String,
+    /// Settles historical funding on open positions at each funding time, read from
+    /// `<base_dir>/<symbol>_funding.csv` as fetched by the downloader.
+    #[serde(default)]
+    pub include_funding: bool,
+    /// Writes `chart.json` with per-step balance, equity and positions to the run
+    /// directory. Off by default since it grows with every step of the run.
+    #[serde(default)]
+    pub export_chart: bool,
+    /// Candle files the downloader fetches at once, across all symbols.
+    #[serde(default = "default_download_concurrency")]
+    pub download_concurrency: usize,
+}
+
+/// See `SlippageModel`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SlippageConfig {
+    /// "none", "fixed", "volume" or "range".
+    #[serde(default = "default_slippage_model")]
+    pub model: String,
+    /// Slippage of the "fixed" model, in basis points.
+    #[serde(default)]
+    pub bps: f64,
+    /// Slippage of the "volume" model per unit of the fill's share of the candle's volume.
+    #[serde(default)]
+    pub volume_impact: f64,
+    /// Share of the candle's high-low range the "range" model slips by.
+    #[serde(default)]
+    pub range_fraction: f64,
+    /// Cap on the slippage of any model, in basis points; 0 for none.
+    #[serde(default)]
+    pub max_bps: f64,
+}
+
+impl Default for SlippageConfig {
+    fn default() -> Self {
+        Self {
+            model: default_slippage_model(),
+            bps: 0.0,
+            volume_impact: 0.0,
+            range_fraction: 0.0,
+            max_bps: 0.0,
+        }
+    }
+}
+
+fn default_slippage_model() -> String {
+    "none".to_string()
+}
+
+/// How far a simulated taker fill executes from its order price, as a fraction of it.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum SlippageModel {
+    None,
+    /// A constant fraction.
+    Fixed(f64),
+    /// `impact` × fill qty / candle volume: large fills on thin candles slip most.
+    Volume(f64),
+    /// This share of the candle's (high - low) / order price: volatile candles slip most.
+    Range(f64),
+}
+
+impl SlippageModel {
+    pub fn from_config(config: &SlippageConfig) -> Option<Self> {
+        match config.model.as_str() {
+            "none" => Some(SlippageModel::None),
+            "fixed" => Some(SlippageModel::Fixed(config.bps / 10_000.0)),
+            "volume" => Some(SlippageModel::Volume(config.volume_impact)),
+            "range" => Some(SlippageModel::Range(config.range_fraction)),
+            _ => None,
+        }
+    }
+
+    /// Slippage of a fill of `qty` at `price` in a candle with `high`, `low` and base
+    /// `volume`. Candles without volume count the fill as the whole candle.
+    pub fn slippage(&self, qty: f64, price: f64, high: f64, low: f64, volume: f64) -> f64 {
+        match *self {
+            SlippageModel::None => 0.0,
+            SlippageModel::Fixed(fraction) => fraction,
+            SlippageModel::Volume(impact) => {
+                if volume > 0.0 {
+                    impact * (qty / volume).min(1.0)
+                } else {
+                    impact
+                }
+            }
+            SlippageModel::Range(fraction) => {
+                if price > 0.0 {
+                    fraction * (high - low).max(0.0) / price
+                } else {
+                    0.0
+                }
+            }
+        }
+    }
+}
+
+fn default_backtest_resolution() -> String {
+    "1m".to_string()
+}
+
+fn default_download_concurrency() -> usize {
+    8
+}
+
+impl BacktestConfig {
+    /// Directory holding the candle and funding files.
+    pub fn data_dir(&self) -> &str {
+        if self.base_dir.is_empty() {
+            "data"
+        } else {
+            &self.base_dir
+        }
+    }
+
+    /// Length of one simulation step in ms.
+    pub fn step_ms(&self) -> u64 {
+        crate::data::parse_timeframe_ms(&self.resolution).unwrap_or(60_000)
+    }
+
+    /// Checks that the start and end dates parse; empty dates leave the range open.
+    pub fn validate(&self) -> Result<(), String> {
+        for (name, value) in [
+            ("start_date", &self.start_date),
+            ("end_date", &self.end_date),
+        ] {
+            if !value.is_empty() && crate::data::parse_date_ms(value).is_none() {
+                return Err(format!(
+                    "backtest.{} must be YYYY-MM-DD, \"now\" or \"now-<n>d\", got \"{}\"",
+                    name, value
+                ));
+            }
+        }
+        if SlippageModel::from_config(&self.slippage).is_none() {
+            return Err(format!(
+                "backtest.slippage.model must be \"none\", \"fixed\", \"volume\" or \"range\", got \"{}\"",
+                self.slippage.model
+            ));
+        }
+        match crate::data::parse_timeframe_ms(&self.resolution) {
+            Some(ms) if ms <= 60_000 && 60_000 % ms == 0 => Ok(()),
+            _ => Err(format!(
+                "backtest.resolution must divide a minute, such as \"1m\", \"5s\" or \"1s\", got \"{}\"",
+                self.resolution
+            )),
+        }
+    }
 }
 
 fn default_n_close_orders() -> f64 {
     5.0
 }
 
-#[derive(Deserialize, Debug, Clone, Default)]
+fn default_atr_span() -> f64 {
+    14.0
+}
+
+fn default_true() -> bool {
+    true
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
 pub struct BotSideConfig {
+    #[serde(default = "default_true")]
+    pub enabled: bool,
     pub total_wallet_exposure_limit: f64,
+    /// Wallet exposure the close grid takes a full position to be at, e.g. to keep closing
+    /// toward the old full size after `total_wallet_exposure_limit` was raised mid-position.
+    /// 0 uses `total_wallet_exposure_limit`.
+    #[serde(default)]
+    pub close_wallet_exposure_limit: f64,
     pub n_positions: f64,
     pub unstuck_loss_allowance_pct: f64,
     pub unstuck_close_pct: f64,
     pub unstuck_ema_dist: f64,
     pub unstuck_threshold: f64,
+    /// Positions held longer than this become eligible for unstuck closes regardless of
+    /// `unstuck_threshold`. 0 disables.
+    #[serde(default)]
+    pub max_position_age_days: f64,
     pub filter_rolling_window: f64,
     pub filter_relative_volume_clip_pct: f64,
     pub ema_span_0: f64,
     pub ema_span_1: f64,
     pub entry_initial_qty_pct: f64,
     pub entry_initial_ema_dist: f64,
+    /// Splits the initial entry into this many equal orders; 0 or 1 places it as one.
+    #[serde(default)]
+    pub entry_initial_n_splits: f64,
+    /// Width of the price band, below (long) or above (short) the initial entry price,
+    /// that split initial entries are spread across.
+    #[serde(default)]
+    pub entry_initial_split_band_pct: f64,
     pub entry_grid_spacing_pct: f64,
     pub entry_grid_spacing_weight: f64,
+    /// Spacing grows per grid level as `(1 + level)^exponent`; 0 keeps it constant.
+    #[serde(default)]
+    pub entry_grid_spacing_exponent: f64,
+    /// Explicit per-level spacing multipliers, e.g. [1, 1.3, 1.7, 2.2]. The last value
+    /// applies to all deeper levels. Takes precedence over the exponent when non-empty.
+    #[serde(default)]
+    pub entry_grid_spacing_multipliers: Vec<f64>,
     pub entry_grid_double_down_factor: f64,
+    /// The double down factor grows per grid level as `(1 + level)^exponent`; 0 keeps it
+    /// constant.
+    #[serde(default)]
+    pub entry_grid_double_down_exponent: f64,
+    /// Explicit per-level double down factors, e.g. [0.5, 1, 2, 3]. The last value applies
+    /// to all deeper levels. Takes precedence over the exponent when non-empty.
+    #[serde(default)]
+    pub entry_grid_double_down_factors: Vec<f64>,
     pub entry_trailing_threshold_pct: f64,
     pub entry_trailing_retracement_pct: f64,
     pub entry_trailing_grid_ratio: f64,
+    /// Interpret `entry_trailing_threshold_pct` and `entry_trailing_retracement_pct` as
+    /// multiples of ATR instead of fractions of price.
+    #[serde(default)]
+    pub entry_trailing_atr_mode: bool,
+    #[serde(default = "default_atr_span")]
+    pub entry_trailing_atr_span: f64,
     pub close_grid_min_markup: f64,
     pub close_grid_markup_range: f64,
     pub close_grid_qty_pct: f64,
@@ -142,15 +993,166 @@ pub struct BotSideConfig {
     pub close_trailing_grid_ratio: f64,
     #[serde(default)]
     pub backwards_tp: bool,
+    /// Never place the nearest take-profit close below (long) or above (short) the
+    /// position's break-even price.
+    #[serde(default)]
+    pub close_floor_at_break_even: bool,
+}
+
+impl BotSideConfig {
+    /// The exposure limit the close grid sizes a full position by.
+    pub fn close_exposure_limit(&self) -> f64 {
+        if self.close_wallet_exposure_limit > 0.0 {
+            self.close_wallet_exposure_limit
+        } else {
+            self.total_wallet_exposure_limit
+        }
+    }
 }
 
-#[derive(Deserialize, Debug, Default, Clone, Copy)]
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy)]
 pub struct Position {
     pub size: f64,
     pub price: f64,
 }
 
-#[derive(Deserialize, Debug, Clone)]
+/// A symbol's long and short position; the short leg has a negative size. In hedge mode
+/// these are the two legs the exchange keeps apart, in one-way mode the single position
+/// sits on the leg of its sign.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct PositionPair {
+    pub long: Position,
+    pub short: Position,
+    /// Whether the account holds the symbol as a single one-way position, flat or not.
+    pub one_way: bool,
+}
+
+impl PositionPair {
+    /// The pair of a one-way account holding `position`, which may be flat.
+    pub fn from_net(position: Position) -> Self {
+        let mut pair = Self {
+            one_way: true,
+            ..Self::default()
+        };
+        if position.size > 0.0 {
+            pair.long = position;
+        } else if position.size < 0.0 {
+            pair.short = position;
+        }
+        pair
+    }
+
+    /// The leg `pside`'s grid trades.
+    pub fn leg(&self, pside: usize) -> Position {
+        if pside == LONG {
+            self.long
+        } else {
+            self.short
+        }
+    }
+
+    /// The net of both legs, priced at the entry of the larger leg. Stands in for the single
+    /// position of one-way mode in status reports, group exposure and funding accrual.
+    pub fn net(&self) -> Position {
+        let size = self.long.size + self.short.size;
+        let price = if size > 0.0 {
+            self.long.price
+        } else if size < 0.0 {
+            self.short.price
+        } else {
+            0.0
+        };
+        Position { size, price }
+    }
+}
+
+/// Fees and funding accrued since a position was opened, in quote currency.
+/// Positive values are costs to the position.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy)]
+pub struct PositionCosts {
+    pub fees_paid: f64,
+    pub funding_paid: f64,
+}
+
+impl PositionCosts {
+    pub fn total(&self) -> f64 {
+        self.fees_paid + self.funding_paid
+    }
+}
+
+/// A funding settlement of an open position.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+pub struct FundingPayment {
+    pub symbol: String,
+    pub timestamp_ms: i64,
+    /// Funding paid by the position, in quote currency; negative when it was received.
+    pub amount: f64,
+}
+
+/// The permissions and restrictions of an API key, as reported by the exchange.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct ApiKeyInfo {
+    /// Whether the key may place and cancel futures orders.
+    pub can_trade: bool,
+    pub can_withdraw: bool,
+    /// Whether the key only works from whitelisted IP addresses.
+    pub ip_restricted: bool,
+    /// When the key, or its trade permission, expires.
+    pub expires_at_ms: Option<i64>,
+}
+
+/// A position's unrealized PnL as reported by the exchange, with the size and mark price
+/// it was computed from.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq)]
+pub struct PositionPnl {
+    /// Signed, like `Position::size`.
+    pub size: f64,
+    pub mark_price: f64,
+    pub unrealized_pnl: f64,
+}
+
+/// One asset of the account's margin collateral, as reported by the exchange.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+pub struct CollateralAsset {
+    pub asset: String,
+    pub quantity: f64,
+    /// Value in quote currency; negative for borrowed assets.
+    pub value: f64,
+    /// Share of the value the exchange discounts before counting it as margin, 0 to 1.
+    pub haircut: f64,
+}
+
+/// A historical fill of the account, as reported by the exchange's trade history.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+pub struct TradeFill {
+    /// Exchange execution id, unique per fill.
+    pub id: String,
+    /// Exchange id of the filled order; empty in journals written before it was recorded.
+    #[serde(default)]
+    pub order_id: String,
+    pub symbol: String,
+    pub timestamp_ms: i64,
+    /// "Buy" or "Sell".
+    pub side: String,
+    /// "Long" or "Short" in hedge mode, empty in one-way mode.
+    pub position_side: String,
+    pub qty: f64,
+    pub price: f64,
+    /// Fee in quote currency; negative for rebates.
+    pub fee: f64,
+    /// PnL the exchange reports the fill realized, before its fee; None where the exchange
+    /// does not report it.
+    #[serde(default)]
+    pub realized_pnl: Option<f64>,
+    /// Whether the fill added liquidity; None where the exchange does not report it.
+    #[serde(default)]
+    pub maker: Option<bool>,
+    /// Limit price of the filled order; 0 where unknown.
+    #[serde(default)]
+    pub order_price: f64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
 pub struct Order {
     pub id: String,
     pub symbol: String,
@@ -160,11 +1162,22 @@ pub struct Order {
     pub price: f64,
     pub reduce_only: bool,
     pub custom_id: String,
+    /// Unique id sent to the exchange with the order, so it can be found again after a
+    /// restart. Empty when the exchange should not be sent one.
+    #[serde(default)]
+    pub client_order_id: String,
     #[serde(default)]
     pub time_in_force: String,
 }
 
-#[derive(Deserialize, Debug, Clone)]
+/// A resting order to cancel, with the symbol batch cancel endpoints group requests by.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CancelRequest {
+    pub symbol: String,
+    pub order_id: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Market {
     pub symbol: String,
     pub active: bool,
@@ -172,9 +1185,25 @@ pub struct Market {
     pub linear: bool,
     #[serde(rename = "createdTime")]
     pub created_at: i64,
+    /// Currency the contract is margined and settled in; empty if unknown.
+    #[serde(default)]
+    pub settle: String,
+}
+
+impl Market {
+    /// Whether the contract settles in `quote`. Symbols don't always carry the quote
+    /// currency, e.g. Hyperliquid's bare coin names, so only markets without settlement
+    /// metadata fall back to the symbol's suffix.
+    pub fn settles_in(&self, quote: &str) -> bool {
+        if self.settle.is_empty() {
+            self.symbol.ends_with(quote)
+        } else {
+            self.settle == quote
+        }
+    }
 }
 
-#[derive(Deserialize, Debug, Clone)]
+#[derive(Deserialize, Debug, Default, Clone, PartialEq)]
 pub struct Ticker {
     pub symbol: String,
     pub bid: f64,
@@ -182,9 +1211,12 @@ pub struct Ticker {
     pub last: f64,
     #[serde(rename = "volume24h")]
     pub quote_volume: f64,
+    /// Current funding rate per interval; 0 where the exchange does not report it.
+    #[serde(default)]
+    pub funding_rate: f64,
 }
 
-#[derive(Deserialize, Debug, Default, Clone)]
+#[derive(Deserialize, Debug, Default, Clone, PartialEq)]
 pub struct OrderBook {
     pub bids: Vec<[f64; 2]>,
     pub asks: Vec<[f64; 2]>,
@@ -198,6 +1230,135 @@ impl OrderBook {
     pub fn best_bid(&self) -> f64 {
         self.bids.get(0).map_or(0.0, |b| b[0])
     }
+
+    /// Checks that both sides are present, positive and not crossed, and that the
+    /// spread relative to the mid price is at most `max_spread`. Orders priced off an
+    /// empty side would otherwise use `best_ask() == f64::MAX` or `best_bid() == 0.0`.
+    pub fn check_usable(&self, max_spread: f64) -> Result<(), String> {
+        if self.bids.is_empty() || self.asks.is_empty() {
+            return Err(format!(
+                "one-sided order book ({} bids, {} asks)",
+                self.bids.len(),
+                self.asks.len()
+            ));
+        }
+        let (bid, ask) = (self.best_bid(), self.best_ask());
+        if !(bid > 0.0 && ask.is_finite()) {
+            return Err(format!("invalid best bid {} / ask {}", bid, ask));
+        }
+        if bid > ask {
+            return Err(format!("crossed order book: bid {} > ask {}", bid, ask));
+        }
+        let spread = (ask - bid) / ((ask + bid) / 2.0);
+        if spread > max_spread {
+            return Err(format!(
+                "spread {:.4} exceeds max {:.4}",
+                spread, max_spread
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum TradingMode {
+    Normal,
+    Manual,
+    GracefulStop,
+    TakeProfitOnly,
+}
+
+impl TradingMode {
+    pub fn from_str(s: &str) -> Option<Self> {
+        match s {
+            "normal" => Some(TradingMode::Normal),
+            "manual" => Some(TradingMode::Manual),
+            "graceful_stop" => Some(TradingMode::GracefulStop),
+            "tp_only" => Some(TradingMode::TakeProfitOnly),
+            _ => None,
+        }
+    }
+
+    /// Whether entry orders may be placed given the current position size.
+    /// `GracefulStop` keeps adding to an open position but never opens a new one.
+    pub fn allows_entries(&self, position_size: f64) -> bool {
+        match self {
+            TradingMode::Normal => true,
+            TradingMode::GracefulStop => position_size != 0.0,
+            TradingMode::Manual | TradingMode::TakeProfitOnly => false,
+        }
+    }
+
+    pub fn allows_closes(&self) -> bool {
+        *self != TradingMode::Manual
+    }
+}
+
+/// Pricing of grid orders relative to the best bid and ask, trading queue position
+/// against fill probability.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum BookPriceMode {
+    /// Orders rest at their computed grid level.
+    Grid,
+    /// Orders priced inside the spread join the best price of their side.
+    Join,
+    /// Orders at or inside the best price of their side improve on it by one tick,
+    /// as long as they stay passive.
+    Improve,
+}
+
+/// How a symbol's positions are margined.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum MarginMode {
+    /// Positions share the account's balance as margin.
+    Cross,
+    /// Each position has margin of its own, the most it can lose.
+    Isolated,
+}
+
+impl MarginMode {
+    pub fn from_str(s: &str) -> Option<Self> {
+        match s {
+            "cross" => Some(MarginMode::Cross),
+            "isolated" => Some(MarginMode::Isolated),
+            _ => None,
+        }
+    }
+}
+
+impl BookPriceMode {
+    pub fn from_str(s: &str) -> Option<Self> {
+        match s {
+            "grid" => Some(BookPriceMode::Grid),
+            "join" => Some(BookPriceMode::Join),
+            "improve" => Some(BookPriceMode::Improve),
+            _ => None,
+        }
+    }
+}
+
+/// Handling of a position that exists at startup but was not opened by the bot.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum PositionAdoption {
+    /// The position is taken over as a completed initial entry, with closes placed right
+    /// away.
+    Auto,
+    /// Like `Auto` once the symbol is listed in `confirmed_adoptions`; until then no orders
+    /// are placed for the symbol.
+    Confirm,
+    /// The position is treated like one the bot opened itself.
+    Off,
+}
+
+impl PositionAdoption {
+    pub fn from_str(s: &str) -> Option<Self> {
+        match s {
+            "auto" => Some(PositionAdoption::Auto),
+            "confirm" => Some(PositionAdoption::Confirm),
+            "off" => Some(PositionAdoption::Off),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Debug, Default, Clone, Copy)]
@@ -207,11 +1368,12 @@ pub struct GridOrder {
     pub order_type: OrderType,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct ExchangeParams {
     pub qty_step: f64,
     pub price_step: f64,
     pub min_qty: f64,
+    /// Minimum order cost, in the account's quote currency.
     pub min_cost: f64,
     pub c_mult: f64,
     pub inverse: bool,
@@ -254,6 +1416,12 @@ pub struct StateParams {
     pub balance: f64,
     pub order_book: OrderBook,
     pub ema_bands: EMABands,
+    /// Average true range of recent candles, in price units; 0 when unknown.
+    pub atr: f64,
+    /// Peak and latest cumulative realized PnL of the account, which let the unstuck loss
+    /// allowance grow with the drop from the balance's peak; 0 when unknown.
+    pub pnl_cumsum_max: f64,
+    pub pnl_cumsum_last: f64,
 }
 
 impl Default for StateParams {
@@ -262,6 +1430,9 @@ impl Default for StateParams {
             balance: 0.0,
             order_book: OrderBook::default(),
             ema_bands: EMABands::default(),
+            atr: 0.0,
+            pnl_cumsum_max: 0.0,
+            pnl_cumsum_last: 0.0,
         }
     }
 }
@@ -290,7 +1461,7 @@ impl Default for TrailingPriceBundle {
     }
 }
 
-#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
 pub enum OrderType {
     EntryInitialNormalLong,
     EntryInitialPartialLong,
@@ -324,6 +1495,39 @@ pub enum OrderType {
 }
 
 impl OrderType {
+    /// Whether the order belongs to the long side, as an entry or a close.
+    pub fn is_long(&self) -> bool {
+        matches!(
+            self,
+            OrderType::EntryInitialNormalLong
+                | OrderType::EntryInitialPartialLong
+                | OrderType::EntryTrailingNormalLong
+                | OrderType::EntryTrailingCroppedLong
+                | OrderType::EntryGridNormalLong
+                | OrderType::EntryGridCroppedLong
+                | OrderType::EntryGridInflatedLong
+                | OrderType::EntryUnstuckLong
+                | OrderType::CloseGridLong
+                | OrderType::CloseTrailingLong
+                | OrderType::CloseNormalLong
+                | OrderType::CloseUnstuckLong
+        )
+    }
+
+    pub fn is_close(&self) -> bool {
+        matches!(
+            self,
+            OrderType::CloseGridLong
+                | OrderType::CloseTrailingLong
+                | OrderType::CloseNormalLong
+                | OrderType::CloseUnstuckLong
+                | OrderType::CloseGridShort
+                | OrderType::CloseTrailingShort
+                | OrderType::CloseNormalShort
+                | OrderType::CloseUnstuckShort
+        )
+    }
+
     pub fn from_str(s: &str) -> Option<Self> {
         match s {
             "entry_initial_normal_long" => Some(OrderType::EntryInitialNormalLong),
@@ -409,7 +1613,7 @@ pub struct Fill {
     pub order_type: OrderType,
 }
 
-#[derive(Debug)]
+#[derive(Serialize, Debug, Clone)]
 pub struct Analysis {
     pub adg: f64,
     pub mdg: f64,
@@ -445,8 +1649,98 @@ impl Default for Analysis {
         }
     }
 }
+
+/// Performance over one calendar month or quarter.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct PeriodMetrics {
+    /// "2023-01" for months, "2023-Q1" for quarters.
+    pub period: String,
+    pub start_equity: f64,
+    pub end_equity: f64,
+    /// end_equity / start_equity - 1
+    pub gain: f64,
+    /// Average daily gain, compounded.
+    pub adg: f64,
+    pub drawdown_worst: f64,
+}
+
+/// Period-bucketed performance, showing seasonality that whole-run ratios hide.
+#[derive(Serialize, Debug, Clone, Default, PartialEq)]
+pub struct PeriodAnalysis {
+    pub monthly: Vec<PeriodMetrics>,
+    pub quarterly: Vec<PeriodMetrics>,
+    /// Share of months with a positive gain.
+    pub win_months_ratio: f64,
+    pub win_quarters_ratio: f64,
+}
+
 #[derive(Debug, Clone)]
 pub struct Individual {
     pub config: BotConfig,
     pub fitness: f64,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants::SHORT;
+
+    fn book(bids: &[f64], asks: &[f64]) -> OrderBook {
+        OrderBook {
+            bids: bids.iter().map(|&p| [p, 1.0]).collect(),
+            asks: asks.iter().map(|&p| [p, 1.0]).collect(),
+        }
+    }
+
+    #[test]
+    fn test_order_book_usability() {
+        assert!(book(&[100.0], &[100.1]).check_usable(0.02).is_ok());
+        // Illiquid symbols: empty or one-sided books
+        assert!(book(&[], &[]).check_usable(0.02).is_err());
+        assert!(book(&[100.0], &[]).check_usable(0.02).is_err());
+        assert!(book(&[], &[100.1]).check_usable(0.02).is_err());
+        // Crossed, zero-priced and overly wide books
+        assert!(book(&[100.2], &[100.1]).check_usable(0.02).is_err());
+        assert!(book(&[0.0], &[100.1]).check_usable(0.02).is_err());
+        assert!(book(&[90.0], &[110.0]).check_usable(0.02).is_err());
+        assert!(book(&[90.0], &[110.0]).check_usable(0.25).is_ok());
+    }
+
+    #[test]
+    fn test_side_mode_and_what_it_allows() {
+        let mut config =
+            crate::config::load_config("tests/fixtures/backtest_config.hjson").unwrap();
+        assert!(config.bot.long.enabled && !config.bot.short.enabled);
+        assert_eq!(config.side_mode(LONG), TradingMode::Normal);
+        // a disabled side still closes what it holds
+        assert_eq!(config.side_mode(SHORT), TradingMode::TakeProfitOnly);
+
+        config.live.forced_mode_long = "manual".to_string();
+        config.live.forced_mode_short = "graceful_stop".to_string();
+        assert!(config.live.validate().is_ok());
+        assert_eq!(config.side_mode(LONG), TradingMode::Manual);
+        assert_eq!(config.side_mode(SHORT), TradingMode::GracefulStop);
+
+        config.live.forced_mode_short = "tp-only".to_string();
+        let error = config.live.validate().unwrap_err();
+        assert!(error.contains("live.forced_mode_short"), "{}", error);
+
+        assert!(TradingMode::Normal.allows_entries(0.0));
+        assert!(TradingMode::Normal.allows_entries(-1.0));
+        // graceful stop adds to open positions of either side but opens none
+        assert!(!TradingMode::GracefulStop.allows_entries(0.0));
+        assert!(TradingMode::GracefulStop.allows_entries(1.0));
+        assert!(TradingMode::GracefulStop.allows_entries(-1.0));
+        for mode in [TradingMode::Manual, TradingMode::TakeProfitOnly] {
+            assert!(!mode.allows_entries(0.0) && !mode.allows_entries(1.0));
+        }
+        assert!(!TradingMode::Manual.allows_closes());
+        for mode in [
+            TradingMode::Normal,
+            TradingMode::GracefulStop,
+            TradingMode::TakeProfitOnly,
+        ] {
+            assert!(mode.allows_closes());
+        }
+    }
+}
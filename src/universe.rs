@@ -0,0 +1,252 @@
+use crate::data;
+use crate::exchange::{Exchange, SendSyncError};
+use crate::listings::ListingRegistry;
+use crate::types::BacktestConfig;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+use tracing::info;
+
+/// 24h volume and listing date of a symbol, cached to resolve symbol presets.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SymbolMetadata {
+    pub symbol: String,
+    /// 24h volume in quote currency when the snapshot was taken.
+    pub quote_volume: f64,
+    /// Listing time reported by the exchange, 0 if it reports none.
+    pub created_at_ms: i64,
+}
+
+/// The active linear perpetuals of an exchange, as of `fetched_at_ms`.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct MarketSnapshot {
+    pub fetched_at_ms: i64,
+    pub symbols: Vec<SymbolMetadata>,
+}
+
+impl MarketSnapshot {
+    pub fn path(exchange_name: &str) -> PathBuf {
+        PathBuf::from("historical_data/markets").join(format!("{}.json", exchange_name))
+    }
+
+    pub fn load(exchange_name: &str) -> Result<Self, SendSyncError> {
+        let path = Self::path(exchange_name);
+        let content = fs::read_to_string(&path).map_err(|e| {
+            format!(
+                "No market snapshot for {} at {} ({}); run download first",
+                exchange_name,
+                path.display(),
+                e
+            )
+        })?;
+        Ok(serde_json::from_str(&content)?)
+    }
+
+    pub fn save(&self, exchange_name: &str) -> Result<(), SendSyncError> {
+        let path = Self::path(exchange_name);
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(&path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    pub async fn fetch(exchange: &dyn Exchange) -> Result<Self, SendSyncError> {
+        let markets = exchange.load_markets().await?;
+        let tickers = exchange.fetch_tickers(&[]).await?;
+        let mut symbols: Vec<SymbolMetadata> = markets
+            .into_values()
+            .filter(|m| m.active && m.swap && m.linear && m.settles_in(exchange.quote()))
+            .map(|m| SymbolMetadata {
+                quote_volume: tickers.get(&m.symbol).map_or(0.0, |t| t.quote_volume),
+                symbol: m.symbol,
+                created_at_ms: m.created_at,
+            })
+            .collect();
+        symbols.sort_by(|a, b| a.symbol.cmp(&b.symbol));
+        Ok(Self {
+            fetched_at_ms: Utc::now().timestamp_millis(),
+            symbols,
+        })
+    }
+}
+
+/// Whether a `backtest.symbols` entry is a preset or pattern rather than a symbol.
+pub fn is_preset(entry: &str) -> bool {
+    entry.contains('*') || parse_top_by_volume(entry).is_some()
+}
+
+/// The `n` of a `top<n>_by_volume` preset.
+fn parse_top_by_volume(entry: &str) -> Option<usize> {
+    entry
+        .strip_prefix("top")?
+        .strip_suffix("_by_volume")?
+        .parse()
+        .ok()
+}
+
+/// Matches `symbol` against a pattern where `*` stands for any run of characters.
+fn matches_pattern(pattern: &str, symbol: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = symbol.strip_prefix(first) else {
+        return false;
+    };
+    let parts: Vec<&str> = parts.collect();
+    let Some((last, middle)) = parts.split_last() else {
+        return rest.is_empty();
+    };
+    for part in middle {
+        match rest.find(part) {
+            Some(i) => rest = &rest[i + part.len()..],
+            None => return false,
+        }
+    }
+    rest.len() >= last.len() && rest.ends_with(last)
+}
+
+/// Expands presets (`top<n>_by_volume`) and patterns (`*USDT`) against a snapshot and keeps
+/// plain symbols as they are. Expanded symbols must be known from `listings` to have been
+/// listed `min_age_days` before `start_ms`, so their data covers the run. The result is
+/// deduplicated, in entry order.
+pub fn expand_symbols(
+    entries: &[String], snapshot: &MarketSnapshot, listings: &ListingRegistry, min_age_days: f64,
+    start_ms: i64,
+) -> Vec<String> {
+    let eligible: Vec<&SymbolMetadata> = snapshot
+        .symbols
+        .iter()
+        .filter(|m| {
+            min_age_days <= 0.0
+                || listings.is_old_enough(&m.symbol, min_age_days, start_ms) == Some(true)
+        })
+        .collect();
+    let mut symbols: Vec<String> = Vec::new();
+    for entry in entries {
+        let expanded: Vec<String> = if let Some(n) = parse_top_by_volume(entry) {
+            let mut by_volume = eligible.clone();
+            by_volume.sort_by(|a, b| b.quote_volume.total_cmp(&a.quote_volume));
+            by_volume
+                .into_iter()
+                .take(n)
+                .map(|m| m.symbol.clone())
+                .collect()
+        } else if entry.contains('*') {
+            eligible
+                .iter()
+                .filter(|m| matches_pattern(entry, &m.symbol))
+                .map(|m| m.symbol.clone())
+                .collect()
+        } else {
+            vec![entry.clone()]
+        };
+        for symbol in expanded {
+            if !symbols.contains(&symbol) {
+                symbols.push(symbol);
+            }
+        }
+    }
+    symbols
+}
+
+/// Replaces presets and patterns in `backtest.symbols` with the symbols they stand for,
+/// using the cached market snapshot and listings of each exchange. Lists without presets
+/// are left as they are and need no snapshot.
+pub fn resolve_backtest_symbols(
+    backtest: &mut BacktestConfig, min_age_days: f64,
+) -> Result<(), SendSyncError> {
+    let start_ms = data::parse_date_ms(&backtest.start_date)
+        .map_or(Utc::now().timestamp_millis(), |ms| ms as i64);
+    for (exchange_name, entries) in backtest.symbols.iter_mut() {
+        if !entries.iter().any(|entry| is_preset(entry)) {
+            continue;
+        }
+        let snapshot = MarketSnapshot::load(exchange_name)?;
+        let mut listings = ListingRegistry::load(&ListingRegistry::path(exchange_name));
+        for m in &snapshot.symbols {
+            listings.record_listing(&m.symbol, m.created_at_ms);
+        }
+        let symbols = expand_symbols(entries, &snapshot, &listings, min_age_days, start_ms);
+        info!(
+            "Resolved {} symbols of {} for {}: {}",
+            symbols.len(),
+            exchange_name,
+            entries.join(", "),
+            symbols.join(",")
+        );
+        *entries = symbols;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::listings::MS_PER_DAY;
+
+    #[test]
+    fn test_expand_symbols() {
+        let metadata = |symbol: &str, quote_volume: f64, created_at_ms: i64| SymbolMetadata {
+            symbol: symbol.to_string(),
+            quote_volume,
+            created_at_ms,
+        };
+        let snapshot = MarketSnapshot {
+            fetched_at_ms: 0,
+            symbols: vec![
+                metadata("BTCUSDT", 1e10, 1),
+                metadata("ETHUSDT", 5e9, 1),
+                metadata("SOLUSDT", 2e9, 0),
+                metadata("XYZUSDT", 3e9, 0),
+                metadata("NEWUSDT", 9e10, 90 * MS_PER_DAY),
+                metadata("ETHBTC", 1e8, 1),
+            ],
+        };
+        let mut listings = ListingRegistry::default();
+        for m in &snapshot.symbols {
+            listings.record_listing(&m.symbol, m.created_at_ms);
+        }
+        // no listing date from the exchange, but downloaded data going back far enough
+        listings.record_candle("SOLUSDT", 0);
+        let entries =
+            |list: &[&str]| -> Vec<String> { list.iter().map(|s| s.to_string()).collect() };
+        let start_ms = 100 * MS_PER_DAY;
+
+        // too recently listed for a 30 day minimum age
+        assert_eq!(
+            expand_symbols(
+                &entries(&["top2_by_volume"]),
+                &snapshot,
+                &listings,
+                30.0,
+                start_ms
+            ),
+            entries(&["BTCUSDT", "ETHUSDT"])
+        );
+        // XYZUSDT's age is unknown
+        assert_eq!(
+            expand_symbols(
+                &entries(&["top3_by_volume"]),
+                &snapshot,
+                &listings,
+                30.0,
+                start_ms
+            ),
+            entries(&["BTCUSDT", "ETHUSDT", "SOLUSDT"])
+        );
+        assert_eq!(
+            expand_symbols(
+                &entries(&["XRPUSDT", "*USDT", "BTC*"]),
+                &snapshot,
+                &listings,
+                0.0,
+                start_ms
+            ),
+            entries(&["XRPUSDT", "BTCUSDT", "ETHUSDT", "SOLUSDT", "XYZUSDT", "NEWUSDT"])
+        );
+        assert!(matches_pattern("E*U*T", "ETHUSDT"));
+        assert!(!matches_pattern("*USDT", "ETHBTC"));
+        assert!(is_preset("top20_by_volume") && !is_preset("BTCUSDT"));
+    }
+}
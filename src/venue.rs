@@ -0,0 +1,149 @@
+use crate::exchange::queue::OrderQueue;
+use crate::exchange::Exchange;
+use crate::types::{OrderBook, VenueConfig};
+use std::collections::{BTreeMap, HashMap};
+
+/// An exchange account coins may be traded on, see `VenueConfig`.
+#[derive(Clone)]
+pub struct VenueAccount {
+    pub user: String,
+    pub exchange: Box<dyn Exchange>,
+    pub maker_fee: f64,
+}
+
+/// A venue with the order queue its managers submit to.
+#[derive(Clone)]
+pub struct Venue {
+    pub account: VenueAccount,
+    pub order_queue: OrderQueue,
+}
+
+/// Trading conditions of one coin on one venue.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VenueQuote {
+    pub user: String,
+    pub symbol: String,
+    pub coin: String,
+    pub maker_fee: f64,
+    pub funding_rate: f64,
+    /// Minimum order cost, in quote currency.
+    pub min_cost: f64,
+    /// Order book value within 1% of the mid price, in quote currency.
+    pub depth: f64,
+}
+
+/// The coin a symbol trades, e.g. BTC for BTCUSDT, used to match symbols across venues.
+pub fn coin_of(symbol: &str, quote: &str) -> String {
+    symbol.strip_suffix(quote).unwrap_or(symbol).to_string()
+}
+
+/// Combined value of the order book's levels within 1% of the mid price.
+pub fn calc_depth(order_book: &OrderBook) -> f64 {
+    if order_book.bids.is_empty() || order_book.asks.is_empty() {
+        return 0.0;
+    }
+    let mid = (order_book.best_bid() + order_book.best_ask()) / 2.0;
+    let bids = order_book
+        .bids
+        .iter()
+        .filter(|[price, _]| *price >= mid * 0.99);
+    let asks = order_book
+        .asks
+        .iter()
+        .filter(|[price, _]| *price <= mid * 1.01);
+    bids.chain(asks).map(|[price, qty]| price * qty).sum()
+}
+
+/// Expected cost of trading a coin on a venue, as a fraction of the traded value: maker
+/// fees for a round trip plus funding over `funding_periods` intervals.
+pub fn calc_venue_cost(quote: &VenueQuote, config: &VenueConfig) -> f64 {
+    2.0 * quote.maker_fee + quote.funding_rate.abs() * config.funding_periods
+}
+
+/// Picks one venue per coin. Coins in `held` (coin -> user) with an open position stay on
+/// their venue while it still lists them. Otherwise venues below `min_depth` are passed over
+/// unless none meets it, and the lowest cost wins, then the deepest book, then the lowest
+/// minimum order cost.
+pub fn select_venues(
+    quotes: Vec<VenueQuote>, held: &HashMap<String, String>, config: &VenueConfig,
+) -> Vec<VenueQuote> {
+    let mut by_coin: BTreeMap<String, Vec<VenueQuote>> = BTreeMap::new();
+    for quote in quotes {
+        by_coin.entry(quote.coin.clone()).or_default().push(quote);
+    }
+    by_coin
+        .into_iter()
+        .filter_map(|(coin, mut candidates)| {
+            if let Some(user) = held.get(&coin) {
+                if let Some(i) = candidates.iter().position(|q| &q.user == user) {
+                    return Some(candidates.swap_remove(i));
+                }
+            }
+            if candidates.iter().any(|q| q.depth >= config.min_depth) {
+                candidates.retain(|q| q.depth >= config.min_depth);
+            }
+            candidates.into_iter().min_by(|a, b| {
+                calc_venue_cost(a, config)
+                    .total_cmp(&calc_venue_cost(b, config))
+                    .then(b.depth.total_cmp(&a.depth))
+                    .then(a.min_cost.total_cmp(&b.min_cost))
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_select_venues() {
+        let quote =
+            |user: &str, coin: &str, maker_fee: f64, funding_rate: f64, depth: f64| VenueQuote {
+                user: user.to_string(),
+                symbol: format!("{}USDT", coin),
+                coin: coin.to_string(),
+                maker_fee,
+                funding_rate,
+                min_cost: 5.0,
+                depth,
+            };
+        let config = VenueConfig {
+            min_depth: 10_000.0,
+            ..Default::default()
+        };
+        let quotes = vec![
+            // equal funding, lower fees win
+            quote("bybit", "BTC", 0.0002, 0.0001, 1e6),
+            quote("binance", "BTC", 0.0001, 0.0001, 1e6),
+            // the cheaper venue is too thin
+            quote("bybit", "ETH", 0.0002, 0.0, 1e6),
+            quote("binance", "ETH", 0.0001, 0.0, 1_000.0),
+            // funding dominates
+            quote("bybit", "SOL", 0.0001, -0.001, 1e6),
+            quote("binance", "SOL", 0.0002, 0.0001, 1e6),
+            quote("bybit", "XRP", 0.0002, 0.0, 0.0),
+        ];
+        let selected = |held: &HashMap<String, String>| -> Vec<(String, String)> {
+            select_venues(quotes.clone(), held, &config)
+                .into_iter()
+                .map(|q| (q.coin, q.user))
+                .collect()
+        };
+        let pair = |coin: &str, user: &str| (coin.to_string(), user.to_string());
+        assert_eq!(
+            selected(&HashMap::new()),
+            vec![
+                pair("BTC", "binance"),
+                pair("ETH", "bybit"),
+                pair("SOL", "binance"),
+                pair("XRP", "bybit"),
+            ]
+        );
+
+        // an open position keeps its venue
+        let held = HashMap::from([("BTC".to_string(), "bybit".to_string())]);
+        assert_eq!(selected(&held)[0], pair("BTC", "bybit"));
+        assert_eq!(coin_of("BTCUSDT", "USDT"), "BTC");
+    }
+}